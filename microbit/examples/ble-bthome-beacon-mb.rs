@@ -0,0 +1,141 @@
+//! Broadcast the temperature as a BLE advertisement in the BTHome format,
+//! so that phones and home automation systems can read it without
+//! connecting.
+//!
+//! The temperature is measured with the TEMP peripheral inside the nRF51
+//! every second and sent as a non-connectable advertisement on the 3
+//! advertising channels. The data is built with the `bthome` module of this
+//! crate. On a phone, look for the device "microbit" in a BLE scanner app
+//! like nRF Connect. Home Assistant finds it with the BTHome integration.
+//!
+//! There is no SoftDevice, the packets are sent directly with the RADIO
+//! peripheral. Add a humidity sensor like the HDC2080 on the edge connector
+//! and send its values with `humidity()` as well.
+//!
+//! Install cargo-embed with:
+//! `cargo install cargo-embed`
+//!
+//! Run with:
+//! `cargo embed --example ble-bthome-beacon-mb`
+//!
+#![no_main]
+#![no_std]
+
+use core::sync::atomic::{compiler_fence, Ordering};
+use cortex_m_rt::entry;
+use driver_examples_microbit::bthome::{AdvData, MAX_LEN};
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+
+/// Advertising channels and their frequencies in MHz above 2400
+const CHANNELS: [(u8, u8); 3] = [(37, 2), (38, 26), (39, 80)];
+const ACCESS_ADDRESS: u32 = 0x8E89_BED6;
+const CRC_POLY: u32 = 0x0006_5B;
+const CRC_INIT: u32 = 0x0055_5555;
+/// ADV_NONCONN_IND from a random address
+const ADV_NONCONN_IND: u8 = 0x42;
+/// Cycles of the 16MHz core per advertising interval
+const INTERVAL_CYCLES: u32 = 16_000_000;
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    rprintln!("BLE BTHome beacon example");
+    let p = microbit::Peripherals::take().unwrap();
+
+    // The radio needs the crystal oscillator.
+    p.CLOCK.events_hfclkstarted.write(|w| unsafe { w.bits(0) });
+    p.CLOCK.tasks_hfclkstart.write(|w| unsafe { w.bits(1) });
+    while p.CLOCK.events_hfclkstarted.read().bits() == 0 {}
+
+    let radio = &p.RADIO;
+    // Some nRF51 need trim values from the factory for the BLE mode.
+    // Bit 3 of OVERRIDEEN is cleared on those.
+    if p.FICR.overrideen.read().bits() & (1 << 3) == 0 {
+        let trim = |i: usize| p.FICR.ble_1mbit[i].read().bits();
+        radio.override0.write(|w| unsafe { w.bits(trim(0)) });
+        radio.override1.write(|w| unsafe { w.bits(trim(1)) });
+        radio.override2.write(|w| unsafe { w.bits(trim(2)) });
+        radio.override3.write(|w| unsafe { w.bits(trim(3)) });
+        // Bit 31 enables the override.
+        radio
+            .override4
+            .write(|w| unsafe { w.bits(trim(4) | 1 << 31) });
+    }
+    radio.mode.write(|w| w.mode().ble_1mbit());
+    // 0dBm
+    radio.txpower.write(|w| unsafe { w.bits(0) });
+    // 1 byte header, 8-bit length
+    radio.pcnf0.write(|w| unsafe { w.bits(8 | 1 << 8) });
+    // Up to 37 bytes, 3 bytes base address, little endian, whitening
+    radio
+        .pcnf1
+        .write(|w| unsafe { w.bits(37 | 3 << 16 | 1 << 25) });
+    radio
+        .base0
+        .write(|w| unsafe { w.bits(ACCESS_ADDRESS << 8) });
+    radio
+        .prefix0
+        .write(|w| unsafe { w.bits(ACCESS_ADDRESS >> 24) });
+    radio.txaddress.write(|w| unsafe { w.bits(0) });
+    // 3 byte CRC, without the address
+    radio.crccnf.write(|w| unsafe { w.bits(3 | 1 << 8) });
+    radio.crcpoly.write(|w| unsafe { w.bits(CRC_POLY) });
+    radio.crcinit.write(|w| unsafe { w.bits(CRC_INIT) });
+    // READY -> START and END -> DISABLE
+    radio.shorts.write(|w| unsafe { w.bits(0b11) });
+
+    // Random static address from the unique device address, with the two
+    // highest bits set.
+    let device_address = [
+        p.FICR.deviceaddr[0].read().bits().to_le_bytes(),
+        p.FICR.deviceaddr[1].read().bits().to_le_bytes(),
+    ];
+    let mut address = [0; 6];
+    address[..4].copy_from_slice(&device_address[0]);
+    address[4..].copy_from_slice(&device_address[1][..2]);
+    address[5] |= 0xC0;
+
+    let mut packet = [0u8; 2 + 6 + MAX_LEN];
+    loop {
+        // The temperature is in 0.25°C.
+        p.TEMP.events_datardy.write(|w| unsafe { w.bits(0) });
+        p.TEMP.tasks_start.write(|w| unsafe { w.bits(1) });
+        while p.TEMP.events_datardy.read().bits() == 0 {}
+        let centi_celsius = p.TEMP.temp.read().bits() as i32 * 25;
+        rprintln!(
+            "Temperature: {}.{:02}°C",
+            centi_celsius / 100,
+            (centi_celsius % 100).abs()
+        );
+
+        let data = AdvData::new()
+            .temperature(centi_celsius as i16)
+            .name("microbit")
+            .unwrap();
+        let data = data.as_bytes();
+        packet[0] = ADV_NONCONN_IND;
+        packet[1] = (6 + data.len()) as u8;
+        packet[2..8].copy_from_slice(&address);
+        packet[8..8 + data.len()].copy_from_slice(data);
+
+        for (channel, frequency) in CHANNELS.iter() {
+            radio
+                .frequency
+                .write(|w| unsafe { w.bits(u32::from(*frequency)) });
+            radio
+                .datawhiteiv
+                .write(|w| unsafe { w.bits(u32::from(*channel)) });
+            radio
+                .packetptr
+                .write(|w| unsafe { w.bits(packet.as_ptr() as u32) });
+            radio.events_disabled.write(|w| unsafe { w.bits(0) });
+            // The radio reads the packet from RAM by itself.
+            compiler_fence(Ordering::SeqCst);
+            radio.tasks_txen.write(|w| unsafe { w.bits(1) });
+            while radio.events_disabled.read().bits() == 0 {}
+        }
+
+        cortex_m::asm::delay(INTERVAL_CYCLES);
+    }
+}
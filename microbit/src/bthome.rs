@@ -0,0 +1,110 @@
+//! BLE advertising data in the BTHome v2 format.
+//!
+//! BTHome puts sensor values into the service data of an advertisement, so
+//! phones and home automation systems like Home Assistant read them without
+//! connecting. Each value is an object id followed by a little endian
+//! number with a fixed factor, e.g. 0.01°C for the temperature.
+//!
+//! ```ignore
+//! let data = AdvData::new()
+//!     .temperature(2150)
+//!     .humidity(4520)
+//!     .name("microbit")
+//!     .unwrap();
+//! // Advertise data.as_bytes(), e.g. with the RADIO peripheral or a
+//! // SoftDevice.
+//! ```
+//!
+//! The objects have to be added in the order of their ids, as in the
+//! methods below. The data is not encrypted.
+
+/// Longest advertising data of a legacy advertisement
+pub const MAX_LEN: usize = 31;
+const AD_FLAGS: u8 = 0x01;
+const AD_COMPLETE_NAME: u8 = 0x09;
+const AD_SERVICE_DATA: u8 = 0x16;
+/// LE General Discoverable Mode, BR/EDR Not Supported
+const FLAGS: u8 = 0x06;
+const BTHOME_UUID: u16 = 0xFCD2;
+/// Version 2, not encrypted, sent regularly
+const DEVICE_INFO: u8 = 0x40;
+const ID_BATTERY: u8 = 0x01;
+const ID_TEMPERATURE: u8 = 0x02;
+const ID_HUMIDITY: u8 = 0x03;
+
+/// Advertising data being built
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdvData {
+    bytes: [u8; MAX_LEN],
+    len: usize,
+    /// Position of the length of the service data
+    service_data: usize,
+}
+
+impl AdvData {
+    /// Start with the flags and an empty BTHome service data.
+    pub fn new() -> Self {
+        let mut data = AdvData {
+            bytes: [0; MAX_LEN],
+            len: 0,
+            service_data: 3,
+        };
+        let [uuid_low, uuid_high] = BTHOME_UUID.to_le_bytes();
+        data.push(&[2, AD_FLAGS, FLAGS]);
+        data.push(&[4, AD_SERVICE_DATA, uuid_low, uuid_high, DEVICE_INFO]);
+        data
+    }
+
+    /// Battery level in %
+    pub fn battery(self, percent: u8) -> Self {
+        self.object(ID_BATTERY, &[percent])
+    }
+
+    /// Temperature in 0.01°C
+    pub fn temperature(self, centi_celsius: i16) -> Self {
+        self.object(ID_TEMPERATURE, &centi_celsius.to_le_bytes())
+    }
+
+    /// Relative humidity in 0.01%
+    pub fn humidity(self, centi_percent: u16) -> Self {
+        self.object(ID_HUMIDITY, &centi_percent.to_le_bytes())
+    }
+
+    /// Add the name of the device after the service data. `None` when it
+    /// does not fit.
+    pub fn name(mut self, name: &str) -> Option<Self> {
+        if self.len + 2 + name.len() > MAX_LEN {
+            return None;
+        }
+        self.push(&[name.len() as u8 + 1, AD_COMPLETE_NAME]);
+        self.push(name.as_bytes());
+        Some(self)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Append an object to the service data. Objects which do not fit or
+    /// come after the name are left out.
+    fn object(mut self, id: u8, value: &[u8]) -> Self {
+        let service_data_end = self.service_data + 1 + usize::from(self.bytes[self.service_data]);
+        if service_data_end == self.len && self.len + 1 + value.len() <= MAX_LEN {
+            self.push(&[id]);
+            self.push(value);
+            self.bytes[self.service_data] += 1 + value.len() as u8;
+        }
+        self
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.bytes[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+}
+
+impl Default for AdvData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
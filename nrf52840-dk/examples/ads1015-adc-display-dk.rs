@@ -0,0 +1,123 @@
+//! Measure the voltages with an ADS1015 analog/digital
+//! converter and print them to an SSD1306 OLED display.
+//!
+//! You can see further explanations about this device and how this example
+//! works here:
+//!
+//! https://blog.eldruin.com/ads1x1x-analog-to-digital-converter-driver-in-rust/
+//!
+//! This example is runs on the nRF52840-DK board using TWIM0.
+//!
+//! ```
+//! DK    <-> ADS1015 <-> Display
+//! GND   <-> GND     <-> GND
+//! VDD   <-> VDD     <-> VDD
+//! P0.27 <-> SCL     <-> SCL
+//! P0.26 <-> SDA     <-> SDA
+//! ```
+//!
+//! The pins of the nRF52840 are not 5V tolerant, so the ADS1015 runs on the
+//! VDD of the board (3V) and its inputs must stay below that.
+//! For example you can create a simple voltage divider with 3 resistors of
+//! the same value between VDD and GND and connect the inputs of the ADC
+//! as follows:
+//!
+//! ```
+//!       ADS1015
+//! VDD <-> A0
+//!  |
+//!  R3
+//!  |  <-> A1
+//!  R2
+//!  |  <-> A2
+//!  R1
+//!  |
+//! GND <-> A3
+//! ```
+//!
+//! With this setup we should get the reading for VDD on channel A0,
+//! the reading for GND on channel A3 and A1 and A2 equally spaced in between
+//! (within resistence tolerances).
+//!
+//! Run with:
+//! `cargo embed --example ads1015-adc-display-dk`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use ads1x1x::{channel as AdcChannel, Ads1x1x, FullScaleRange, SlaveAddr};
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_nrf52840::{setup, Board};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use nb::block;
+use nrf52840_hal::{prelude::*, twim::Frequency};
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    rprintln!("ADS1015 example");
+    let Board {
+        i2c,
+        mut led,
+        mut delay,
+    } = setup(Frequency::K100);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut adc = Ads1x1x::new_ads1015(manager.acquire(), SlaveAddr::default());
+    // need to be able to measure [0-3V]
+    adc.set_full_scale_range(FullScaleRange::Within4_096V)
+        .unwrap();
+
+    loop {
+        // Blink LED 1 to check that everything is actually running.
+        // If the LED 1 is off, something went wrong.
+        led.on();
+        delay.delay_ms(50_u32);
+        led.off();
+        delay.delay_ms(50_u32);
+
+        // Read voltage in all channels
+        let values = [
+            block!(adc.read(&mut AdcChannel::SingleA0)).unwrap_or(-1),
+            block!(adc.read(&mut AdcChannel::SingleA1)).unwrap_or(-1),
+            block!(adc.read(&mut AdcChannel::SingleA2)).unwrap_or(-1),
+            block!(adc.read(&mut AdcChannel::SingleA3)).unwrap_or(-1),
+        ];
+
+        let mut lines: [heapless::String<32>; 4] = [
+            heapless::String::new(),
+            heapless::String::new(),
+            heapless::String::new(),
+            heapless::String::new(),
+        ];
+
+        disp.clear();
+        for (i, (line, value)) in lines.iter_mut().zip(values.iter()).enumerate() {
+            write!(line, "Channel {}: {}", i, value).unwrap();
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+//! Continuously measure the eCO2 and eTVOC in the air
+//! and print it to an SSD1306 OLED display.
+//!
+//! Introductory blog post with some pictures here:
+//! https://blog.eldruin.com/ccs811-indoor-air-quality-sensor-driver-in-rust/
+//!
+//! This example is runs on the nRF52840-DK board using TWIM0.
+//!
+//! ```
+//! DK    <-> CCS811 <-> Display
+//! GND   <-> GND    <-> GND
+//! VDD   <-> VCC    <-> VDD
+//! P0.27 <-> SCL    <-> SCL
+//! P0.26 <-> SDA    <-> SDA
+//! GND   <-> nWAKE
+//! VDD   <-> RST
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example ccs811-gas-voc-display-dk`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_nrf52840::{setup, Board};
+use embedded_ccs811::{prelude::*, AlgorithmResult, Ccs811Awake, MeasurementMode, SlaveAddr};
+use embedded_graphics::{
+    fonts::{Font6x12, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use nb::block;
+use nrf52840_hal::{prelude::*, twim::Frequency};
+use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    rprintln!("CCS811 example");
+    let Board {
+        i2c,
+        mut led,
+        mut delay,
+    } = setup(Frequency::K100);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x12)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let address = SlaveAddr::default();
+    let mut sensor = Ccs811Awake::new(manager.acquire(), address);
+    sensor.software_reset().unwrap();
+    delay.delay_ms(3_u32);
+    let mut sensor = sensor.start_application().ok().unwrap();
+    delay.delay_ms(2_u32);
+    let temperature_c = 25.0;
+    let relative_humidity_perc = 60.0;
+    sensor
+        .set_environment(temperature_c, relative_humidity_perc)
+        .unwrap();
+    sensor.set_mode(MeasurementMode::ConstantPower1s).unwrap();
+
+    let default = AlgorithmResult {
+        eco2: 9999,
+        etvoc: 9999,
+        raw_current: 255,
+        raw_voltage: 9999,
+    };
+    let mut lines: [heapless::String<32>; 2] = [heapless::String::new(), heapless::String::new()];
+    loop {
+        // Blink LED 1 to check that everything is actually running.
+        // If the LED 1 is off, something went wrong.
+        led.on();
+        delay.delay_ms(50_u32);
+        led.off();
+        delay.delay_ms(50_u32);
+
+        let data = block!(sensor.data()).unwrap_or(default);
+
+        lines[0].clear();
+        lines[1].clear();
+        write!(lines[0], "eCO2: {}", data.eco2).unwrap();
+        write!(lines[1], "eTVOC: {}", data.etvoc).unwrap();
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! Control a servo connected to channel 0 and one connected to channel 1.
+//!
+//! Make the servo at channel 0 turn clockwise, then counter-clockwise and
+//! the servo at channel 1 does the opposite.
+//!
+//! You can see a video of this device running here:
+//! https://blog.eldruin.com/pca9685-pwm-led-servo-controller-driver-in-rust/
+//!
+//! This example is runs on the nRF52840-DK board using TWIM0.
+//!
+//! ```
+//! DK    <-> PCA9685
+//! GND   <-> GND
+//! VDD   <-> VCC
+//! P0.27 <-> SCL
+//! P0.26 <-> SDA
+//! GND   <-> OE
+//!           V+      <-> +5V
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example pca9685-servos-dk`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use driver_examples_nrf52840::{setup, Board};
+use nrf52840_hal::{prelude::*, twim::Frequency};
+use panic_rtt_target as _;
+use pwm_pca9685::{Address, Channel, Pca9685};
+use rtt_target::{rprintln, rtt_init_print};
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    rprintln!("PCA9685 example");
+    let Board {
+        i2c,
+        mut led,
+        mut delay,
+    } = setup(Frequency::K400);
+
+    let mut pwm = Pca9685::new(i2c, Address::default()).unwrap();
+    // This results in about 60 Hz, which is the frequency at which servos operate.
+    pwm.set_prescale(100).unwrap();
+    pwm.enable().unwrap();
+    // Turn all channels on at time "0".
+    pwm.set_channel_on(Channel::All, 0).unwrap();
+
+    // You need to tweak these min/max values for your servos as these may vary.
+    let servo_min = 130; // minimum pulse length (out of 4096)
+    let servo_max = 610; // maximum pulse length (out of 4096)
+    let mut current = servo_min;
+    let mut factor: i16 = 1;
+    loop {
+        // Blink LED 1 (really fast, it will seem to be on the whole time)
+        // to check that everything is actually running.
+        // If the LED 1 is off, something went wrong.
+        led.on();
+        delay.delay_ms(1_u32);
+        led.off();
+        delay.delay_ms(1_u32);
+
+        pwm.set_channel_off(Channel::C0, current).unwrap();
+        pwm.set_channel_off(Channel::C1, servo_min + (servo_max - current))
+            .unwrap();
+
+        if current == servo_max {
+            factor = -1;
+        } else if current == servo_min {
+            factor = 1;
+        }
+        current = (current as i16 + factor) as u16;
+    }
+}
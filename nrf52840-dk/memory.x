@@ -0,0 +1,6 @@
+/* nRF52840: 1M flash, 256K RAM */
+MEMORY
+{
+  FLASH : ORIGIN = 0x00000000, LENGTH = 1024K
+  RAM : ORIGIN = 0x20000000, LENGTH = 256K
+}
@@ -0,0 +1,56 @@
+//! Board setup shared by the examples. Please have a look at the examples.
+//!
+//! `setup()` takes the peripherals and returns the I2C bus on the Arduino
+//! header (TWIM0: P0.27 SCL, P0.26 SDA), LED 1 and a delay on TIMER0:
+//!
+//! ```ignore
+//! let Board { i2c, mut led, mut delay } = setup(Frequency::K100);
+//! led.on();
+//! delay.delay_ms(50_u32);
+//! ```
+#![no_std]
+
+use nrf52840_hal::{
+    gpio::{p0, Level, Output, Pin, PushPull},
+    pac,
+    prelude::*,
+    twim::{self, Frequency, Twim},
+    Timer,
+};
+
+/// LED on the board. It lights up when the pin is low.
+pub struct Led(Pin<Output<PushPull>>);
+
+impl Led {
+    /// Turn the LED on.
+    pub fn on(&mut self) {
+        self.0.set_low().ok();
+    }
+
+    /// Turn the LED off.
+    pub fn off(&mut self) {
+        self.0.set_high().ok();
+    }
+}
+
+/// Peripherals used by the examples
+pub struct Board {
+    pub i2c: Twim<pac::TWIM0>,
+    pub led: Led,
+    pub delay: Timer<pac::TIMER0>,
+}
+
+/// Set up the I2C bus at the given frequency, LED 1 and the delay.
+pub fn setup(frequency: Frequency) -> Board {
+    let p = pac::Peripherals::take().unwrap();
+    let port0 = p0::Parts::new(p.P0);
+
+    let scl = port0.p0_27.into_floating_input().degrade();
+    let sda = port0.p0_26.into_floating_input().degrade();
+    let i2c = Twim::new(p.TWIM0, twim::Pins { scl, sda }, frequency);
+
+    let led = Led(port0.p0_13.into_push_pull_output(Level::High).degrade());
+    let delay = Timer::new(p.TIMER0);
+
+    Board { i2c, led, delay }
+}
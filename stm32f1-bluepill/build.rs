@@ -0,0 +1,34 @@
+//! Put the appropriate `memory.x` linker script where the linker can find it.
+//!
+//! With the `usb-bootloader` feature, the flash memory of the application
+//! starts after the 8K used by the USB bootloader.
+//! With the `upper-half` feature, the application is placed in the upper half
+//! of the flash memory so that it can be loaded by the
+//! `xmodem-firmware-update-bp` example.
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+fn main() {
+    let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let usb_bootloader = env::var_os("CARGO_FEATURE_USB_BOOTLOADER").is_some();
+    let upper_half = env::var_os("CARGO_FEATURE_UPPER_HALF").is_some();
+    let memory: &[u8] = match (usb_bootloader, upper_half) {
+        (false, false) => include_bytes!("memory-default.x"),
+        (true, false) => include_bytes!("memory-usb-bootloader.x"),
+        (false, true) => include_bytes!("memory-upper-half.x"),
+        (true, true) => panic!("The usb-bootloader and upper-half features cannot be combined"),
+    };
+    File::create(out.join("memory.x"))
+        .unwrap()
+        .write_all(memory)
+        .unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=memory-default.x");
+    println!("cargo:rerun-if-changed=memory-usb-bootloader.x");
+    println!("cargo:rerun-if-changed=memory-upper-half.x");
+}
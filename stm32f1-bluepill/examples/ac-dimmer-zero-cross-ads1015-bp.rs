@@ -0,0 +1,321 @@
+//! Dim an incandescent lamp on the AC mains with a triac by phase control,
+//! setting the power with a potentiometer read through an ADS1015 ADC and
+//! showing it on an SSD1306 OLED display.
+//!
+//! The triac is fired once every half wave, some time after the zero-cross
+//! of the mains voltage: the later, the less power goes to the lamp. This
+//! must happen at exactly the same time after every zero-cross, otherwise
+//! the lamp flickers, so it cannot be done with PWM or in the main loop:
+//! - The zero-cross detector triggers the EXTI1 interrupt, which has the
+//!   highest priority and only restarts TIM3 with the current delay.
+//! - TIM3 runs in one-pulse mode with 1us resolution. Its channel 1 output
+//!   goes high when the counter reaches the delay (PWM mode 2) and low again
+//!   `GATE_PULSE_US` later, when the counter overflows and stops. The gate
+//!   pulse is completely generated in hardware.
+//!
+//! The idle task reads the potentiometer, measures the mains frequency by
+//! counting the zero-crosses (50Hz and 60Hz both work) and calculates the
+//! delay for the requested power. The power is not linear with the delay,
+//! the delay is found by solving the power equation of a phase-controlled
+//! sine wave. If no zero-cross is detected the triac is not fired at all.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> Dimmer module <-> ADS1015 <-> Display <-> Potentiometer
+//! GND  <-> GND           <-> GND     <-> GND     <-> GND
+//! 3.3V <-> VCC           <-> VDD     <-> VDD     <-> +
+//! PA1  <-> Z-C
+//! PA6  <-> PWM (gate)
+//! PB8                    <-> SCL     <-> SCL
+//! PB9                    <-> SDA     <-> SDA
+//!                            A0                  <-> Wiper
+//! ```
+//!
+//! WARNING: Mains voltage can kill you. Only use a dimmer module with an
+//! optocoupler for the zero-cross detection and an opto-triac (e.g. MOC3021)
+//! driving the triac, so that the board is isolated from the mains, and do
+//! not touch the module while it is connected.
+//!
+//! The zero-cross output should produce a rising edge at every zero-cross.
+//! Edges closer together than `MIN_HALF_PERIOD_US` are ignored as noise.
+//!
+//! Run with:
+//! `cargo embed --example ac-dimmer-zero-cross-ads1015-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use ads1x1x::{channel as AdcChannel, Ads1x1x, FullScaleRange, SlaveAddr};
+use core::{f32::consts::PI, fmt::Write};
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    exti::setup_exti_pin,
+    i2c_speed, log_error, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use nb::block;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::PA1,
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Input, OpenDrain, Output, PullUp, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const GATE_PULSE_US: u32 = 100;
+/// Shortest delay after the zero-cross. Before that the current through
+/// the triac is too low for it to stay on.
+const MIN_DELAY_US: u32 = 300;
+const MIN_HALF_PERIOD_US: u32 = 7000;
+/// Time over which the zero-crosses are counted
+const FREQUENCY_WINDOW_US: u32 = 200_000;
+/// ADC reading with the potentiometer at 3.3V (2mV per LSB)
+const POT_FULL_SCALE: i16 = 1650;
+
+/// Delay after the zero-cross for the given power or `None` if the triac
+/// should not be fired.
+fn firing_delay_us(power_percent: u32, half_period_us: u32) -> Option<u32> {
+    if power_percent == 0 || half_period_us <= GATE_PULSE_US + 2 * MIN_DELAY_US {
+        return None;
+    }
+    // Power delivered to a resistive load when firing at angle `a`:
+    // P(a) = 1 - a/pi + sin(2a)/(2pi), which decreases from 1 to 0 over
+    // [0, pi]. Find `a` by bisection.
+    let target = power_percent.min(100) as f32 / 100.0;
+    let (mut low, mut high) = (0.0, PI);
+    for _ in 0..16 {
+        let angle = (low + high) / 2.0;
+        let power = 1.0 - angle / PI + libm::sinf(2.0 * angle) / (2.0 * PI);
+        if power > target {
+            low = angle;
+        } else {
+            high = angle;
+        }
+    }
+    let delay_us = ((low + high) / 2.0 / PI * half_period_us as f32) as u32;
+    Some(delay_us.clamp(MIN_DELAY_US, half_period_us - GATE_PULSE_US - MIN_DELAY_US))
+}
+
+/// Start the gate pulse `delay_us` from now.
+fn fire(timer: &pac::TIM3, delay_us: u32) {
+    timer.cr1.modify(|_, w| w.cen().disabled());
+    timer.cnt.write(|w| w.cnt().bits(0));
+    timer.ccr1.write(|w| w.ccr().bits(delay_us as u16));
+    timer
+        .arr
+        .write(|w| w.arr().bits((delay_us + GATE_PULSE_US) as u16));
+    timer.cr1.modify(|_, w| w.cen().enabled());
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        zero_cross: PA1<Input<PullUp>>,
+        gate_timer: pac::TIM3,
+        last_zero_cross: u32,
+        zero_crosses: u32,
+        firing_delay_us: Option<u32>,
+        // Taken by the idle task, which creates the drivers.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("AC dimmer example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the TIM3 clock before handing the RCC over to the HAL.
+        device.RCC.apb1enr.modify(|_, w| w.tim3en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // TIM3 channel 1 output
+        let _gate = gpioa.pa6.into_alternate_push_pull(&mut gpioa.crl);
+
+        // The TIM3 clock is twice PCLK1, so 72MHz. Count microseconds.
+        let gate_timer = device.TIM3;
+        gate_timer
+            .psc
+            .write(|w| w.psc().bits((SYSCLK_MHZ - 1) as u16));
+        gate_timer.egr.write(|w| w.ug().set_bit());
+        gate_timer
+            .ccmr1_output()
+            .modify(|_, w| w.oc1m().pwm_mode2());
+        gate_timer.ccer.modify(|_, w| w.cc1e().set_bit());
+        gate_timer.cr1.modify(|_, w| w.opm().enabled());
+
+        let mut zero_cross = gpioa.pa1.into_pull_up_input(&mut gpioa.crl);
+        setup_exti_pin(&mut zero_cross, &mut afio, &device.EXTI, Edge::RISING);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // ADS1015 ADC and display
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x48, 0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            zero_cross,
+            gate_timer,
+            last_zero_cross: DWT::get_cycle_count(),
+            zero_crosses: 0,
+            firing_delay_us: None,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = EXTI1, priority = 2, resources = [zero_cross, gate_timer, last_zero_cross, zero_crosses, firing_delay_us])]
+    fn on_zero_cross(cx: on_zero_cross::Context) {
+        let now = DWT::get_cycle_count();
+        cx.resources.zero_cross.clear_interrupt_pending_bit();
+        let elapsed_us = now.wrapping_sub(*cx.resources.last_zero_cross) / SYSCLK_MHZ;
+        if elapsed_us < MIN_HALF_PERIOD_US {
+            return;
+        }
+        *cx.resources.last_zero_cross = now;
+        *cx.resources.zero_crosses = cx.resources.zero_crosses.wrapping_add(1);
+        if let Some(delay_us) = *cx.resources.firing_delay_us {
+            fire(cx.resources.gate_timer, delay_us);
+        }
+    }
+
+    #[idle(resources = [zero_crosses, firing_delay_us, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let i2c = cx.resources.i2c.take().unwrap();
+        let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+        let interface = I2CDIBuilder::new().init(manager.acquire());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut adc = Ads1x1x::new_ads1015(manager.acquire(), SlaveAddr::default());
+        adc.set_full_scale_range(FullScaleRange::Within4_096V)
+            .unwrap();
+
+        let mut lines: [String<32>; 3] = Default::default();
+        let mut half_period_us = None;
+        let mut last_count = 0;
+        let mut last_check = DWT::get_cycle_count();
+        let mut led_on = false;
+        loop {
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            led_on = !led_on;
+            if led_on {
+                cx.resources.led.set_high().unwrap();
+            } else {
+                cx.resources.led.set_low().unwrap();
+            }
+
+            let elapsed = DWT::get_cycle_count().wrapping_sub(last_check);
+            if elapsed >= FREQUENCY_WINDOW_US * SYSCLK_MHZ {
+                let count = cx.resources.zero_crosses.lock(|count| *count);
+                let crossings = count.wrapping_sub(last_count);
+                half_period_us = if crossings > 0 {
+                    Some(elapsed / SYSCLK_MHZ / crossings)
+                } else {
+                    None
+                };
+                last_count = count;
+                last_check = last_check.wrapping_add(elapsed);
+            }
+
+            let power_percent = match block!(adc.read(&mut AdcChannel::SingleA0)) {
+                Ok(value) => value.clamp(0, POT_FULL_SCALE) as u32 * 100 / POT_FULL_SCALE as u32,
+                Err(e) => {
+                    log_error!("ADC error: {:?}", e);
+                    0
+                }
+            };
+            let delay_us =
+                half_period_us.and_then(|half_period| firing_delay_us(power_percent, half_period));
+            cx.resources.firing_delay_us.lock(|d| *d = delay_us);
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            write!(lines[0], "Power: {}%", power_percent).unwrap();
+            match half_period_us {
+                Some(half_period) => {
+                    let frequency_x10 = 5_000_000 / half_period;
+                    write!(
+                        lines[1],
+                        "Mains: {}.{}Hz",
+                        frequency_x10 / 10,
+                        frequency_x10 % 10
+                    )
+                    .unwrap()
+                }
+                None => write!(lines[1], "Mains: no zero-cross").unwrap(),
+            }
+            match delay_us {
+                Some(delay) => write!(lines[2], "Delay: {}us", delay).unwrap(),
+                None => write!(lines[2], "Delay: off").unwrap(),
+            }
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 16))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+        }
+    }
+};
@@ -27,16 +27,16 @@
 
 use ad983x::{Ad983x, FrequencyRegister, MODE};
 use cortex_m_rt::entry;
+use driver_examples_bluepill::{log_info, panic_display as _};
 use embedded_hal::digital::v2::OutputPin;
 use libm;
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use stm32f1xx_hal::{delay::Delay, pac, prelude::*, spi::Spi};
 
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("AD9833 example");
+    log_info!("AD9833 example");
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
 
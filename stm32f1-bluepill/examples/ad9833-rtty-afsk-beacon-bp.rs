@@ -0,0 +1,227 @@
+//! Transmit a status beacon as RTTY or Bell 202 AFSK with an AD9833
+//! waveform generator / direct digital synthesizer.
+//!
+//! The mark tone is set in the frequency register F0 and the space tone in
+//! F1 of the AD9833. Every `BEACON_INTERVAL_S` the status text is loaded
+//! into the `FskEncoder` from this crate. TIM2 then interrupts twice per
+//! bit and selects the frequency register of the next half bit until the
+//! text has been sent. Switching the register keeps the phase continuous.
+//!
+//! `MODE` selects the modulation:
+//! - `fsk::RTTY`: 45.45 baud Baudot. Decode it with fldigi in RTTY mode.
+//! - `fsk::BELL_202`: 1200 baud ASCII. Decode it with
+//!   `minimodem --rx 1200`.
+//!
+//! Connect the output to the sound card input of the computer running the
+//! decoder through a capacitor and a voltage divider or to an amplifier
+//! like the PAM8403 with a speaker and hold a microphone next to it. Only
+//! transmit on the air with a license and your own call sign in `CALLSIGN`.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1.
+//!
+//! ```
+//! BP   <-> AD9833
+//! GND  <-> VSS
+//! 3.3V <-> VDD
+//! PA4  <-> FSYNC
+//! PA5  <-> CLK
+//! PA7  <-> DAT
+//!          OUT <-> 10uF <-> sound card / amplifier input
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example ad9833-rtty-afsk-beacon-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use ad983x::{ic::Ad9833, Ad983x, FrequencyRegister, MODE as SPI_MODE};
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    fsk::{self, FskEncoder, Mode},
+    log_error, log_info, panic_display as _,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::{PA4, PA5, PA6, PA7},
+        gpioc::PC13,
+        Alternate, Floating, Input, Output, PushPull, State,
+    },
+    pac,
+    prelude::*,
+    spi::{Spi, Spi1NoRemap},
+    timer::{CountDownTimer, Event, Timer},
+};
+
+type Synth = Ad983x<
+    Spi<
+        pac::SPI1,
+        Spi1NoRemap,
+        (
+            PA5<Alternate<PushPull>>,
+            PA6<Input<Floating>>,
+            PA7<Alternate<PushPull>>,
+        ),
+    >,
+    PA4<Output<PushPull>>,
+    Ad9833,
+>;
+
+const SYSCLK_MHZ: u32 = 72;
+const MODE: Mode = fsk::RTTY;
+const CALLSIGN: &str = "N0CALL";
+const BEACON_INTERVAL_S: u32 = 30;
+/// Characters of the beacon, including Baudot shift codes
+const BEACON_LEN: usize = 96;
+const MCLK_HZ: f32 = 25_000_000.0;
+
+/// Value of a frequency register
+fn frequency_word(frequency_hz: f32) -> u32 {
+    (frequency_hz * (1 << 28) as f32 / MCLK_HZ + 0.5) as u32
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        synth: Synth,
+        timer: CountDownTimer<pac::TIM2>,
+        encoder: FskEncoder<BEACON_LEN>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("AD9833 RTTY/AFSK beacon example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+
+        // SPI1
+        let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+        let miso = gpioa.pa6;
+        let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+        let cs = gpioa
+            .pa4
+            .into_push_pull_output_with_state(&mut gpioa.crl, State::High);
+
+        let spi = Spi::spi1(
+            device.SPI1,
+            (sck, miso, mosi),
+            &mut afio.mapr,
+            SPI_MODE,
+            1_u32.mhz(),
+            clocks,
+            &mut rcc.apb2,
+        );
+
+        let mut synth = Ad983x::new_ad9833(spi, cs);
+        synth.reset().unwrap();
+        synth
+            .set_frequency(FrequencyRegister::F0, frequency_word(MODE.mark_hz))
+            .unwrap();
+        synth
+            .set_frequency(FrequencyRegister::F1, frequency_word(MODE.space_hz))
+            .unwrap();
+        synth.select_frequency(FrequencyRegister::F0).unwrap();
+
+        let timer = Timer::tim2(device.TIM2, &clocks, &mut rcc.apb1)
+            .start_count_down(MODE.half_bit_hz().hz());
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            synth,
+            timer,
+            encoder: FskEncoder::new(MODE),
+            led,
+        }
+    }
+
+    #[task(binds = TIM2, priority = 2, resources = [synth, timer, encoder])]
+    fn half_bit(cx: half_bit::Context) {
+        let synth = cx.resources.synth;
+        cx.resources.timer.clear_update_interrupt_flag();
+        match cx.resources.encoder.half_bit() {
+            Some(true) => synth.select_frequency(FrequencyRegister::F0).unwrap(),
+            Some(false) => synth.select_frequency(FrequencyRegister::F1).unwrap(),
+            None => {
+                // Silence between the beacons
+                synth.reset().unwrap();
+                cx.resources.timer.unlisten(Event::Update);
+            }
+        }
+    }
+
+    #[idle(resources = [synth, timer, encoder, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let cycles_per_s = SYSCLK_MHZ * 1_000_000;
+        let mut last_beacon = DWT::get_cycle_count().wrapping_sub(BEACON_INTERVAL_S * cycles_per_s);
+        let mut count: u32 = 0;
+        let mut text: String<BEACON_LEN> = String::new();
+        loop {
+            let now = DWT::get_cycle_count();
+            // Busy while sending
+            if !cx.resources.encoder.lock(|encoder| encoder.is_done()) {
+                continue;
+            }
+            cx.resources.led.set_high().unwrap();
+            if now.wrapping_sub(last_beacon) < BEACON_INTERVAL_S * cycles_per_s {
+                continue;
+            }
+            last_beacon = last_beacon.wrapping_add(BEACON_INTERVAL_S * cycles_per_s);
+            // The cycle counter wraps around in less than a minute, so count
+            // the beacons for the uptime.
+            let uptime_s = count * BEACON_INTERVAL_S;
+            count += 1;
+
+            text.clear();
+            write!(
+                text,
+                "\r\nDE {} {} BEACON {} UPTIME {}S\r\n",
+                CALLSIGN, CALLSIGN, count, uptime_s
+            )
+            .unwrap();
+            log_info!("Sending: {}", text.trim());
+            let result = cx.resources.encoder.lock(|encoder| encoder.load(&text));
+            if let Err(e) = result {
+                log_error!("Cannot send the beacon: {:?}", e);
+                continue;
+            }
+            cx.resources.led.set_low().unwrap();
+            cx.resources.synth.lock(|synth| {
+                synth.select_frequency(FrequencyRegister::F0).unwrap();
+                synth.enable().unwrap();
+            });
+            cx.resources.timer.lock(|timer| {
+                timer.start(MODE.half_bit_hz().hz());
+                timer.listen(Event::Update);
+            });
+        }
+    }
+};
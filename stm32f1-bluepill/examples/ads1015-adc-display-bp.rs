@@ -57,6 +57,10 @@
 //!
 //! As you can see, the voltage was divided equally by all resistors.
 //!
+//! The conversions are read with a timeout so that the example does not hang
+//! when the ADC stops answering. Channels that could not be read show
+//! "timeout" or "error".
+//!
 //! Run with:
 //! `cargo embed --example ads1015-adc-display-bp`,
 
@@ -66,7 +70,13 @@
 
 use ads1x1x::{channel as AdcChannel, Ads1x1x, FullScaleRange, SlaveAddr};
 use core::fmt::Write;
-use cortex_m_rt::entry;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::monotonic::{self, with_timeout, TimeoutError};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    clock, log_info,
+    panic_display::{self, Bus},
+};
 use embedded_graphics::{
     fonts::{Font6x8, Text},
     pixelcolor::BinaryColor,
@@ -74,28 +84,34 @@ use embedded_graphics::{
     style::TextStyleBuilder,
 };
 use embedded_hal::digital::v2::OutputPin;
-use nb::block;
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use ssd1306::{prelude::*, Builder, I2CDIBuilder};
 use stm32f1xx_hal::{
-    delay::Delay,
     i2c::{BlockingI2c, DutyCycle, Mode},
     pac,
     prelude::*,
 };
 
+const READ_TIMEOUT_MS: u32 = 50;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("ADS1015 example");
-    let cp = cortex_m::Peripherals::take().unwrap();
+    log_info!("ADS1015 example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
 
     let mut flash = dp.FLASH.constrain();
     let mut rcc = dp.RCC.constrain();
 
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
+    clock::log_clocks(&clocks);
 
     let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
 
@@ -122,12 +138,13 @@ fn main() -> ! {
 
     let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
     let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
-    let mut delay = Delay::new(cp.SYST, clocks);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
 
     let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
     let interface = I2CDIBuilder::new().init(manager.acquire());
     let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
     disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
 
     let text_style = TextStyleBuilder::new(Font6x8)
         .text_color(BinaryColor::On)
@@ -142,16 +159,16 @@ fn main() -> ! {
         // Blink LED 0 to check that everything is actually running.
         // If the LED 0 is off, something went wrong.
         led.set_high().unwrap();
-        delay.delay_ms(50_u16);
+        monotonic::wait_ms(50);
         led.set_low().unwrap();
-        delay.delay_ms(50_u16);
+        monotonic::wait_ms(50);
 
         // Read voltage in all channels
         let values = [
-            block!(adc.read(&mut AdcChannel::SingleA0)).unwrap_or(8091),
-            block!(adc.read(&mut AdcChannel::SingleA1)).unwrap_or(8091),
-            block!(adc.read(&mut AdcChannel::SingleA2)).unwrap_or(8091),
-            block!(adc.read(&mut AdcChannel::SingleA3)).unwrap_or(8091),
+            with_timeout(READ_TIMEOUT_MS, || adc.read(&mut AdcChannel::SingleA0)),
+            with_timeout(READ_TIMEOUT_MS, || adc.read(&mut AdcChannel::SingleA1)),
+            with_timeout(READ_TIMEOUT_MS, || adc.read(&mut AdcChannel::SingleA2)),
+            with_timeout(READ_TIMEOUT_MS, || adc.read(&mut AdcChannel::SingleA3)),
         ];
 
         let mut lines: [heapless::String<32>; 4] = [
@@ -162,9 +179,13 @@ fn main() -> ! {
         ];
 
         disp.clear();
-        for i in 0..values.len() {
-            write!(lines[i], "Channel {}: {}", i, values[i]).unwrap();
-            Text::new(&lines[i], Point::new(0, i as i32 * 16))
+        for (i, (line, value)) in lines.iter_mut().zip(values.iter()).enumerate() {
+            match value {
+                Ok(value) => write!(line, "Channel {}: {}", i, value).unwrap(),
+                Err(TimeoutError::Timeout) => write!(line, "Channel {}: timeout", i).unwrap(),
+                Err(TimeoutError::Other(_)) => write!(line, "Channel {}: error", i).unwrap(),
+            }
+            Text::new(line, Point::new(0, i as i32 * 16))
                 .into_styled(text_style)
                 .draw(&mut disp)
                 .unwrap();
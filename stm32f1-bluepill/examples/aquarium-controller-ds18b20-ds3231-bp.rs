@@ -0,0 +1,606 @@
+//! Aquarium controller: keep the water warm with a heater, switch the light
+//! on and off at fixed times and feed the fish with a servo-driven feeder.
+//! All the settings can be changed over a serial console and are kept in the
+//! flash memory.
+//!
+//! - The water temperature is measured every second with a DS18B20 sensor
+//!   through the `onewire` module of this crate. The heater relay switches
+//!   on below the set temperature minus half of `HEATER_HYSTERESIS` and off
+//!   above it plus the other half. If the sensor fails, the heater stays off.
+//! - The light relay is on between the on and off times of the DS3231 RTC.
+//!   The period may go over midnight.
+//! - At each of the two feeding times the feeder servo opens for the portion
+//!   time and closes again.
+//!
+//! Connect with a serial terminal at 9600 baud and send one command per
+//! line:
+//! - `STATUS`: print the time, the temperature, the outputs and the settings.
+//! - `TIME 2021-05-02 10:21:34`: set the RTC.
+//! - `LIGHT 08:00 20:00`: set the light on and off times.
+//! - `FEED 1 09:00`, `FEED 2 OFF`: set or disable one of the feeding times.
+//! - `FEED NOW`: feed at once.
+//! - `PORTION 800`: open the feeder for 800ms at every feeding.
+//! - `HEATER 25.5`: set the water temperature in degrees Celsius.
+//!
+//! Every command is answered with a line starting with `OK` or `ERR`.
+//! Changed settings are stored at once in the second-to-last page of the
+//! flash memory together with a CRC-32 calculated with the hardware CRC
+//! unit, like in the qtr-8a-line-follower-mcp3008-bp example.
+//!
+//! The 1-Wire time slots run with interrupts disabled for up to 0.5ms, so
+//! the console uses a low baud rate to not lose any characters.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and USART1.
+//!
+//! ```
+//! BP   <-> DS18B20 <-> DS3231 <-> Relays    <-> Servo <-> USB-serial adapter
+//! GND  <-> GND     <-> GND    <-> GND       <-> GND   <-> GND
+//! 3.3V <-> VDD     <-> VCC    <-> VCC
+//! 5V                                        <-> +
+//! PB12 <-> DQ
+//! PB8              <-> SCL
+//! PB9              <-> SDA
+//! PB0                         <-> IN1 (light)
+//! PB1                         <-> IN2 (heater)
+//! PA6                                       <-> Signal
+//! PA9                                                 <-> RX
+//! PA10                                                <-> TX
+//! ```
+//!
+//! Connect a 4.7K pull-up resistor from DQ to 3.3V. The relay modules must
+//! switch on with a high input. Never connect the heater or the light
+//! directly to the board and keep the mains wiring away from the water.
+//!
+//! Run with:
+//! `cargo embed --example aquarium-controller-ds18b20-ds3231-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    crc::Crc32,
+    i2c_speed, log_error, log_info, log_warn,
+    onewire::{self, OneWire, SKIP_ROM},
+    panic_display as _,
+    uart::{LineBuffer, RxBuffer, RxReader, TxBuffer, TxWriter, UartRx, UartTx},
+};
+use ds323x::{Ds323x, NaiveDateTime, Rtcc, Timelike};
+use embedded_hal::{
+    digital::v2::{InputPin, OutputPin},
+    Pwm,
+};
+use heapless::String;
+use rtic::app;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    crc::{Crc, CrcExt},
+    flash::{self, FlashSize, SectorSize},
+    gpio::{
+        gpioa::PA6,
+        gpiob::{PB0, PB1, PB12, PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    pwm::{Channel, Pwm as TimerPwm, C1},
+    serial::{Config, Rx, Serial, Tx},
+    timer::{Tim3NoRemap, Timer},
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+type ServoPwm = TimerPwm<pac::TIM3, Tim3NoRemap, C1, PA6<Alternate<PushPull>>>;
+
+const SYSCLK_MHZ: u32 = 72;
+const BAUD_RATE: u32 = 9600;
+const RX_BUFFER: usize = 64;
+const TX_BUFFER: usize = 256;
+const LINE_LEN: usize = 40;
+const POLL_MS: u32 = 10;
+/// Difference between switching the heater on and off in degrees Celsius
+const HEATER_HYSTERESIS: f32 = 0.4;
+const MIN_WATER_C: f32 = 15.0;
+const MAX_WATER_C: f32 = 32.0;
+const FEEDER_CLOSED_DEG: u32 = 0;
+const FEEDER_OPEN_DEG: u32 = 90;
+const MAX_PORTION_MS: u32 = 5000;
+
+// Second-to-last 1K page of the flash memory. The last one is reserved for
+// the xmodem-firmware-update-bp example.
+const SETTINGS_OFFSET: u32 = 0xF800;
+const PAGE_SIZE: usize = 1024;
+const SETTINGS_MAGIC: u32 = 0x3151_4141; // "AAQ1"
+const SETTINGS_SIZE: usize = 24;
+/// Stored instead of a disabled feeding time
+const NO_TIME: u16 = 0xFFFF;
+
+// DS18B20 commands
+const CONVERT_T: u8 = 0x44;
+const READ_SCRATCHPAD: u8 = 0xBE;
+
+/// Times are minutes since midnight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Settings {
+    light_on: u16,
+    light_off: u16,
+    feeding: [Option<u16>; 2],
+    portion_ms: u16,
+    water_c: f32,
+}
+
+impl Settings {
+    const fn new() -> Self {
+        Settings {
+            light_on: 8 * 60,
+            light_off: 20 * 60,
+            feeding: [Some(9 * 60), Some(18 * 60)],
+            portion_ms: 800,
+            water_c: 25.0,
+        }
+    }
+
+    fn light_is_on(&self, minute: u16) -> bool {
+        if self.light_on <= self.light_off {
+            (self.light_on..self.light_off).contains(&minute)
+        } else {
+            // Over midnight
+            minute >= self.light_on || minute < self.light_off
+        }
+    }
+
+    fn to_bytes<C: Crc32>(&self, crc: &mut C) -> [u8; SETTINGS_SIZE] {
+        let mut bytes = [0; SETTINGS_SIZE];
+        bytes[0..4].copy_from_slice(&SETTINGS_MAGIC.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.light_on.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.light_off.to_le_bytes());
+        for (i, time) in self.feeding.iter().enumerate() {
+            let time = time.unwrap_or(NO_TIME);
+            bytes[8 + i * 2..10 + i * 2].copy_from_slice(&time.to_le_bytes());
+        }
+        bytes[12..14].copy_from_slice(&self.portion_ms.to_le_bytes());
+        // bytes 14..16 are reserved
+        bytes[16..20].copy_from_slice(&self.water_c.to_bits().to_le_bytes());
+        let checksum = crc.checksum(&bytes[..SETTINGS_SIZE - 4]);
+        bytes[SETTINGS_SIZE - 4..].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes<C: Crc32>(bytes: &[u8], crc: &mut C) -> Option<Self> {
+        let half = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let word =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        if bytes.len() < SETTINGS_SIZE
+            || word(0) != SETTINGS_MAGIC
+            || word(SETTINGS_SIZE - 4) != crc.checksum(&bytes[..SETTINGS_SIZE - 4])
+        {
+            return None;
+        }
+        let feeding = |i: usize| match half(8 + i * 2) {
+            NO_TIME => None,
+            time => Some(time),
+        };
+        Some(Settings {
+            light_on: half(4),
+            light_off: half(6),
+            feeding: [feeding(0), feeding(1)],
+            portion_ms: half(12),
+            water_c: f32::from_bits(word(16)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Status,
+    Time(NaiveDateTime),
+    Light(u16, u16),
+    /// Set a feeding time (index into `Settings::feeding`)
+    Feed(usize, Option<u16>),
+    FeedNow,
+    Portion(u16),
+    Heater(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CommandError {
+    UnknownCommand,
+    MissingArgument,
+    InvalidTime,
+    InvalidNumber,
+    OutOfRange,
+}
+
+/// Parse a time like `08:30` into minutes since midnight.
+fn parse_time(text: &str) -> Result<u16, CommandError> {
+    let mut parts = text.split(':');
+    let mut part = |max: u16| match parts.next().map(str::parse::<u16>) {
+        Some(Ok(value)) if value <= max => Ok(value),
+        _ => Err(CommandError::InvalidTime),
+    };
+    let hours = part(23)?;
+    let minutes = part(59)?;
+    Ok(hours * 60 + minutes)
+}
+
+fn parse(line: &str) -> Result<Command, CommandError> {
+    let mut words = line.split_whitespace();
+    let command = words.next().ok_or(CommandError::UnknownCommand)?;
+    if command.eq_ignore_ascii_case("STATUS") {
+        return Ok(Command::Status);
+    }
+    let argument = words.next().ok_or(CommandError::MissingArgument)?;
+    if command.eq_ignore_ascii_case("TIME") {
+        let time = words.next().ok_or(CommandError::MissingArgument)?;
+        let mut text: String<LINE_LEN> = String::new();
+        write!(text, "{} {}", argument, time).map_err(|_| CommandError::InvalidTime)?;
+        return NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S")
+            .map(Command::Time)
+            .map_err(|_| CommandError::InvalidTime);
+    }
+    if command.eq_ignore_ascii_case("LIGHT") {
+        let off = words.next().ok_or(CommandError::MissingArgument)?;
+        return Ok(Command::Light(parse_time(argument)?, parse_time(off)?));
+    }
+    if command.eq_ignore_ascii_case("FEED") {
+        if argument.eq_ignore_ascii_case("NOW") {
+            return Ok(Command::FeedNow);
+        }
+        let index = match argument.parse::<usize>() {
+            Ok(number) if (1..=2).contains(&number) => number - 1,
+            _ => return Err(CommandError::InvalidNumber),
+        };
+        let time = words.next().ok_or(CommandError::MissingArgument)?;
+        if time.eq_ignore_ascii_case("OFF") {
+            return Ok(Command::Feed(index, None));
+        }
+        return Ok(Command::Feed(index, Some(parse_time(time)?)));
+    }
+    if command.eq_ignore_ascii_case("PORTION") {
+        return match argument.parse::<u32>() {
+            Ok(ms) if (1..=MAX_PORTION_MS).contains(&ms) => Ok(Command::Portion(ms as u16)),
+            Ok(_) => Err(CommandError::OutOfRange),
+            Err(_) => Err(CommandError::InvalidNumber),
+        };
+    }
+    if command.eq_ignore_ascii_case("HEATER") {
+        return match argument.parse::<f32>() {
+            Ok(c) if (MIN_WATER_C..=MAX_WATER_C).contains(&c) => Ok(Command::Heater(c)),
+            Ok(_) => Err(CommandError::OutOfRange),
+            Err(_) => Err(CommandError::InvalidNumber),
+        };
+    }
+    Err(CommandError::UnknownCommand)
+}
+
+/// Start a temperature conversion on the DS18B20. It takes up to 750ms.
+fn start_conversion<P: OutputPin + InputPin>(bus: &mut OneWire<P>) -> Result<(), onewire::Error> {
+    bus.reset()?;
+    bus.write_byte(SKIP_ROM);
+    bus.write_byte(CONVERT_T);
+    Ok(())
+}
+
+/// Read the result of the last conversion in degrees Celsius.
+fn read_temperature<P: OutputPin + InputPin>(bus: &mut OneWire<P>) -> Result<f32, onewire::Error> {
+    bus.reset()?;
+    bus.write_byte(SKIP_ROM);
+    bus.write_byte(READ_SCRATCHPAD);
+    let mut scratchpad = [0; 9];
+    bus.read_checked(&mut scratchpad)?;
+    // 1/16 degrees at the default 12-bit resolution
+    Ok(f32::from(i16::from_le_bytes([scratchpad[0], scratchpad[1]])) / 16.0)
+}
+
+fn write_time<W: Write>(w: &mut W, minute: Option<u16>) -> core::fmt::Result {
+    match minute {
+        Some(m) => write!(w, "{:02}:{:02}", m / 60, m % 60),
+        None => write!(w, "OFF"),
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        uart_rx: UartRx<Rx<pac::USART1>, RX_BUFFER>,
+        uart_tx: UartTx<Tx<pac::USART1>, TX_BUFFER>,
+        reader: RxReader<RX_BUFFER>,
+        writer: TxWriter<TX_BUFFER>,
+        flash: flash::Parts,
+        crc: Crc,
+        water_sensor: OneWire<PB12<Output<OpenDrain>>>,
+        light_relay: PB0<Output<PushPull>>,
+        heater_relay: PB1<Output<PushPull>>,
+        feeder: ServoPwm,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Aquarium controller example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+        let rx = gpioa.pa10;
+        let serial = Serial::usart1(
+            device.USART1,
+            (tx, rx),
+            &mut afio.mapr,
+            Config::default().baudrate(BAUD_RATE.bps()),
+            clocks,
+            &mut rcc.apb2,
+        );
+        let (tx, mut rx) = serial.split();
+        rx.listen();
+        let (uart_rx, reader) = singleton!(: RxBuffer<RX_BUFFER> = RxBuffer::new())
+            .unwrap()
+            .split(rx);
+        let (uart_tx, writer) = singleton!(: TxBuffer<TX_BUFFER> = TxBuffer::new())
+            .unwrap()
+            .split(tx, || rtic::pend(pac::Interrupt::USART1));
+
+        let water_pin = gpiob.pb12.into_open_drain_output(&mut gpiob.crh);
+        let water_sensor = OneWire::new(water_pin, clocks.sysclk().0);
+        let light_relay = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+        let heater_relay = gpiob.pb1.into_push_pull_output(&mut gpiob.crl);
+
+        let feeder_pin = gpioa.pa6.into_alternate_push_pull(&mut gpioa.crl);
+        let mut feeder = Timer::tim3(device.TIM3, &clocks, &mut rcc.apb1)
+            .pwm::<Tim3NoRemap, _, _, _>(feeder_pin, &mut afio.mapr, 50.hz());
+        feeder.enable(Channel::C1);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // DS3231 RTC
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x68]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            uart_rx,
+            uart_tx,
+            reader,
+            writer,
+            flash,
+            crc: device.CRC.new(&mut rcc.ahb),
+            water_sensor,
+            light_relay,
+            heater_relay,
+            feeder,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    /// Move the received bytes into the buffer and send the waiting ones.
+    #[task(binds = USART1, priority = 2, resources = [uart_rx, uart_tx])]
+    fn usart1(cx: usart1::Context) {
+        cx.resources.uart_rx.on_interrupt();
+        let uart_tx = cx.resources.uart_tx;
+        if uart_tx.on_interrupt() {
+            uart_tx.tx().listen();
+        } else {
+            uart_tx.tx().unlisten();
+        }
+    }
+
+    #[idle(resources = [reader, writer, flash, crc, water_sensor, light_relay, heater_relay, feeder, i2c, led])]
+    fn idle(cx: idle::Context) -> ! {
+        let reader = cx.resources.reader;
+        let tx = cx.resources.writer;
+        let mut line_buffer: LineBuffer<LINE_LEN> = LineBuffer::new();
+        let flash = cx.resources.flash;
+        let crc = cx.resources.crc;
+        let water_sensor = cx.resources.water_sensor;
+        let light_relay = cx.resources.light_relay;
+        let heater_relay = cx.resources.heater_relay;
+        let feeder = cx.resources.feeder;
+        let led = cx.resources.led;
+        let mut rtc = Ds323x::new_ds3231(cx.resources.i2c.take().unwrap());
+
+        // 1ms to 2ms pulses for 0 to 180 degrees in a 20ms period
+        let max_duty = feeder.get_max_duty();
+        let feeder_duty = |deg: u32| (u32::from(max_duty) * (180 + deg) / (20 * 180)) as u16;
+        feeder.set_duty(Channel::C1, feeder_duty(FEEDER_CLOSED_DEG));
+
+        let stored = {
+            let writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+            writer
+                .read(SETTINGS_OFFSET, SETTINGS_SIZE)
+                .ok()
+                .and_then(|bytes| Settings::from_bytes(bytes, crc))
+        };
+        let mut settings = stored.unwrap_or_else(|| {
+            log_warn!("No valid settings stored. Using the defaults.");
+            Settings::new()
+        });
+        log_info!("Settings: {:?}", settings);
+
+        if let Err(e) = start_conversion(water_sensor) {
+            log_error!("DS18B20 error: {:?}", e);
+        }
+        let mut water_c = None;
+        let mut light_on = false;
+        let mut heater_on = false;
+        let mut last_minute = None;
+        // Time in cycles when the feeder opened and how long it stays open
+        let mut feeding: Option<(u32, u32)> = None;
+        writeln!(tx, "OK ready").unwrap();
+
+        let mut last_poll = DWT::get_cycle_count();
+        let mut polls = 0_u32;
+        loop {
+            if DWT::get_cycle_count().wrapping_sub(last_poll) < POLL_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_poll = last_poll.wrapping_add(POLL_MS * 1000 * SYSCLK_MHZ);
+            polls += 1;
+
+            let mut feed = false;
+            for line in reader.lines(&mut line_buffer) {
+                log_info!("Command: {}", line);
+                let previous = settings;
+                match parse(&line) {
+                    Ok(Command::Status) => {
+                        write!(tx, "Time: ").unwrap();
+                        match rtc.get_datetime() {
+                            Ok(datetime) => writeln!(tx, "{}", datetime),
+                            Err(_) => writeln!(tx, "RTC error"),
+                        }
+                        .unwrap();
+                        match water_c {
+                            Some(c) => writeln!(tx, "Water: {:.1}C", c),
+                            None => writeln!(tx, "Water: sensor error"),
+                        }
+                        .unwrap();
+                        writeln!(tx, "Heater: {} (set {:.1}C)", heater_on, settings.water_c)
+                            .unwrap();
+                        write!(tx, "Light: {} (", light_on).unwrap();
+                        write_time(tx, Some(settings.light_on)).unwrap();
+                        write!(tx, " - ").unwrap();
+                        write_time(tx, Some(settings.light_off)).unwrap();
+                        writeln!(tx, ")").unwrap();
+                        for (i, time) in settings.feeding.iter().enumerate() {
+                            write!(tx, "Feed {}: ", i + 1).unwrap();
+                            write_time(tx, *time).unwrap();
+                            writeln!(tx).unwrap();
+                        }
+                        writeln!(tx, "OK STATUS portion {}ms", settings.portion_ms).unwrap();
+                    }
+                    Ok(Command::Time(datetime)) => match rtc.set_datetime(&datetime) {
+                        Ok(()) => writeln!(tx, "OK TIME {}", datetime).unwrap(),
+                        Err(_) => writeln!(tx, "ERR RTC").unwrap(),
+                    },
+                    Ok(Command::Light(on, off)) => {
+                        settings.light_on = on;
+                        settings.light_off = off;
+                        writeln!(tx, "OK LIGHT").unwrap();
+                    }
+                    Ok(Command::Feed(index, time)) => {
+                        settings.feeding[index] = time;
+                        writeln!(tx, "OK FEED {}", index + 1).unwrap();
+                    }
+                    Ok(Command::FeedNow) => {
+                        feed = true;
+                        writeln!(tx, "OK FEED NOW").unwrap();
+                    }
+                    Ok(Command::Portion(ms)) => {
+                        settings.portion_ms = ms;
+                        writeln!(tx, "OK PORTION {}", ms).unwrap();
+                    }
+                    Ok(Command::Heater(c)) => {
+                        settings.water_c = c;
+                        writeln!(tx, "OK HEATER {:.1}", c).unwrap();
+                    }
+                    Err(e) => writeln!(tx, "ERR {:?}", e).unwrap(),
+                }
+                if settings != previous {
+                    let mut writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+                    let stored = writer
+                        .erase(SETTINGS_OFFSET, PAGE_SIZE)
+                        .and_then(|_| writer.write(SETTINGS_OFFSET, &settings.to_bytes(crc)));
+                    match stored {
+                        Ok(()) => log_info!("Settings stored: {:?}", settings),
+                        Err(e) => log_error!("Flash error: {:?}", e),
+                    }
+                    // Apply the new schedule at once.
+                    last_minute = None;
+                }
+            }
+
+            // Once per second
+            if polls % (1000 / POLL_MS) == 0 {
+                // The conversion started a second ago is done.
+                water_c = read_temperature(water_sensor).ok();
+                if let Err(e) = start_conversion(water_sensor) {
+                    log_error!("DS18B20 error: {:?}", e);
+                }
+                heater_on = match water_c {
+                    Some(c) if c < settings.water_c - HEATER_HYSTERESIS / 2.0 => true,
+                    Some(c) if c > settings.water_c + HEATER_HYSTERESIS / 2.0 => false,
+                    Some(_) => heater_on,
+                    None => false,
+                };
+                if heater_on {
+                    heater_relay.set_high().unwrap();
+                } else {
+                    heater_relay.set_low().unwrap();
+                }
+
+                match rtc.get_datetime() {
+                    Ok(datetime) => {
+                        let minute = (datetime.hour() * 60 + datetime.minute()) as u16;
+                        light_on = settings.light_is_on(minute);
+                        // Feed once when the minute of a feeding time starts.
+                        if last_minute.is_some() && last_minute != Some(minute) {
+                            feed |= settings.feeding.contains(&Some(minute));
+                        }
+                        last_minute = Some(minute);
+                    }
+                    Err(_) => log_error!("RTC error"),
+                }
+                if light_on {
+                    light_relay.set_high().unwrap();
+                } else {
+                    light_relay.set_low().unwrap();
+                }
+
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                if polls % (2000 / POLL_MS) == 0 {
+                    led.set_low().unwrap();
+                } else {
+                    led.set_high().unwrap();
+                }
+            }
+
+            if feed && feeding.is_none() {
+                log_info!("Feeding");
+                feeder.set_duty(Channel::C1, feeder_duty(FEEDER_OPEN_DEG));
+                feeding = Some((DWT::get_cycle_count(), u32::from(settings.portion_ms)));
+            }
+            if let Some((since, portion_ms)) = feeding {
+                if DWT::get_cycle_count().wrapping_sub(since) >= portion_ms * 1000 * SYSCLK_MHZ {
+                    feeder.set_duty(Channel::C1, feeder_duty(FEEDER_CLOSED_DEG));
+                    feeding = None;
+                }
+            }
+        }
+    }
+};
@@ -0,0 +1,268 @@
+//! Log temperature records protected by a CRC-32 checksum on an AT24C256
+//! EEPROM and show which records are corrupted on an SSD1306 OLED display.
+//!
+//! Every record contains a sequence number, the temperature measured with a
+//! TMP102 and a CRC-32 checksum calculated with the STM32 hardware CRC unit.
+//! The records are stored in a ring buffer in the EEPROM. A new record is
+//! added every 5 seconds and then all records are validated.
+//! The display shows how many records are valid, empty and corrupted as well
+//! as the position of the corrupted records.
+//!
+//! Press the button to corrupt the last record written and see how it is
+//! detected.
+//!
+//! The software CRC implementation calculates the same checksum as the hardware
+//! unit so the records can also be validated on a device without CRC unit.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> AT24C256 <-> TMP102 <-> Display <-> Button
+//! GND  <-> GND      <-> GND    <-> GND
+//! 3.3V <-> VCC      <-> VCC    <-> VDD     <-> +
+//! 3.3V <-> A0, A1, A2
+//! GND  <-> WP
+//! PB8  <-> SCL      <-> SCL    <-> SCL
+//! PB9  <-> SDA      <-> SDA    <-> SDA
+//! PB12                                     <-> -
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example at24c256-crc-log-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    crc::Crc32,
+    log_error, log_info,
+    panic_display::{self, Bus},
+};
+use eeprom24x::{Eeprom24x, SlaveAddr as EepromAddr};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::{String, Vec};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    crc::CrcExt,
+    delay::Delay,
+    i2c::{BlockingI2c, DutyCycle, Mode},
+    pac,
+    prelude::*,
+};
+use tmp1x2::{SlaveAddr as Tmp1x2Addr, Tmp1x2};
+
+const RECORD_SIZE: u32 = 16;
+const RECORD_COUNT: usize = 32;
+
+/// Log record as stored in the EEPROM
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    sequence: u32,
+    temperature_mc: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    Empty,
+    Valid(u32),
+    Corrupted,
+}
+
+impl Record {
+    fn to_bytes<C: Crc32>(&self, crc: &mut C) -> [u8; RECORD_SIZE as usize] {
+        let mut bytes = [0; RECORD_SIZE as usize];
+        bytes[0..4].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.temperature_mc.to_le_bytes());
+        // bytes 8..12 are reserved
+        let checksum = crc.checksum(&bytes[..12]);
+        bytes[12..16].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    fn validate<C: Crc32>(bytes: &[u8; RECORD_SIZE as usize], crc: &mut C) -> Slot {
+        if bytes.iter().all(|b| *b == 0xFF) {
+            return Slot::Empty;
+        }
+        let checksum = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+        if crc.checksum(&bytes[..12]) == checksum {
+            Slot::Valid(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        } else {
+            Slot::Corrupted
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("AT24C256 CRC log example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let button = gpiob.pb12.into_pull_down_input(&mut gpiob.crh);
+
+    let i2c = BlockingI2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        Mode::Fast {
+            frequency: 100_000.hz(),
+            duty_cycle: DutyCycle::Ratio2to1,
+        },
+        clocks,
+        &mut rcc.apb1,
+        1000,
+        10,
+        1000,
+        1000,
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut crc = dp.CRC.new(&mut rcc.ahb);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut eeprom =
+        Eeprom24x::new_24x256(manager.acquire(), EepromAddr::Alternative(true, true, true));
+    let mut tmp102 = Tmp1x2::new(manager.acquire(), Tmp1x2Addr::default());
+
+    // Continue after the newest valid record.
+    let mut slots = [Slot::Empty; RECORD_COUNT];
+    scan(
+        |address, data| eeprom.read_data(address, data).is_ok(),
+        &mut crc,
+        &mut slots,
+    );
+    let newest = slots
+        .iter()
+        .enumerate()
+        .filter_map(|(i, slot)| match slot {
+            Slot::Valid(sequence) => Some((i, *sequence)),
+            _ => None,
+        })
+        .max_by_key(|(_, sequence)| *sequence);
+    let (mut next_index, mut sequence) = match newest {
+        Some((i, sequence)) => ((i + 1) % RECORD_COUNT, sequence + 1),
+        None => (0, 0),
+    };
+    log_info!("Next record {} at position {}", sequence, next_index);
+
+    let mut lines: [String<32>; 4] = [String::new(), String::new(), String::new(), String::new()];
+    let mut last_written = None;
+    loop {
+        if button.is_high().unwrap() {
+            if let Some(index) = last_written {
+                // Flip the bits of the temperature to simulate data corruption.
+                let address = index as u32 * RECORD_SIZE + 4;
+                let byte = eeprom.read_byte(address).unwrap_or(0);
+                eeprom.write_byte(address, !byte).unwrap();
+                delay.delay_ms(5_u16);
+                log_error!("Corrupted record at position {}", index);
+            }
+        } else {
+            let temperature = tmp102.read_temperature().unwrap_or(-273.0);
+            let record = Record {
+                sequence,
+                temperature_mc: (temperature * 1000.0) as i32,
+            };
+            let address = next_index as u32 * RECORD_SIZE;
+            eeprom
+                .write_page(address, &record.to_bytes(&mut crc))
+                .unwrap();
+            // wait maximum time necessary for write
+            delay.delay_ms(5_u16);
+            last_written = Some(next_index);
+            next_index = (next_index + 1) % RECORD_COUNT;
+            sequence += 1;
+        }
+
+        scan(
+            |address, data| eeprom.read_data(address, data).is_ok(),
+            &mut crc,
+            &mut slots,
+        );
+        let valid = slots.iter().filter(|s| matches!(s, Slot::Valid(_))).count();
+        let empty = slots.iter().filter(|s| **s == Slot::Empty).count();
+        let corrupted: Vec<usize, RECORD_COUNT> = slots
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| **s == Slot::Corrupted)
+            .map(|(i, _)| i)
+            .collect();
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        write!(lines[0], "Valid: {} Empty: {}", valid, empty).unwrap();
+        write!(lines[1], "Corrupted: {}", corrupted.len()).unwrap();
+        for (i, index) in corrupted.iter().enumerate() {
+            // Show as many positions as fit in two lines.
+            let line = if i < 7 { 2 } else { 3 };
+            write!(lines[line], "{} ", index).ok();
+        }
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+        delay.delay_ms(4950_u16);
+    }
+}
+
+/// Read all records with `read` and validate them.
+fn scan<F, C>(mut read: F, crc: &mut C, slots: &mut [Slot; RECORD_COUNT])
+where
+    F: FnMut(u32, &mut [u8]) -> bool,
+    C: Crc32,
+{
+    for (i, slot) in slots.iter_mut().enumerate() {
+        let mut bytes = [0; RECORD_SIZE as usize];
+        *slot = if read(i as u32 * RECORD_SIZE, &mut bytes) {
+            Record::validate(&bytes, crc)
+        } else {
+            Slot::Corrupted
+        };
+    }
+}
@@ -22,10 +22,10 @@
 #![no_main]
 
 use cortex_m_rt::entry;
+use driver_examples_bluepill::{log_info, panic_display as _};
 use eeprom24x::{Eeprom24x, SlaveAddr};
 use embedded_hal::digital::v2::OutputPin;
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use stm32f1xx_hal::{
     delay::Delay,
     i2c::{BlockingI2c, DutyCycle, Mode},
@@ -36,7 +36,7 @@ use stm32f1xx_hal::{
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("AT24C256 example");
+    log_info!("AT24C256 example");
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
 
@@ -0,0 +1,482 @@
+//! Self-balancing two-wheel robot with an MPU6050 gyroscope/accelerometer
+//! and a DRV8833 dual motor driver.
+//!
+//! A TIM3 interrupt runs the control loop at `CONTROL_HZ` (200Hz):
+//! - Read the MPU6050 (minimal driver below).
+//! - Estimate the tilt angle with the `ComplementaryFilter` from this crate,
+//!   combining the gyroscope rate and the accelerometer angle.
+//! - The `PidController` from this crate calculates the motor speed which
+//!   brings the robot back upright.
+//! - Drive both motors with the DRV8833 with the `motor` module of this
+//!   crate.
+//!
+//! When the robot tilts more than `FALL_ANGLE_DEG` it has fallen over: the
+//! motors stop until it is put upright again.
+//!
+//! The gains and the balance point can be tuned over a serial console at
+//! 115200 baud, one command per line:
+//! - `KP 0.08`, `KI 0.4`, `KD 0.003`: set a gain of the PID controller,
+//!   whose output is the motor speed from -1 to 1 and whose input is the
+//!   angle in degrees
+//! - `TRIM 1.5`: angle in degrees at which the robot is balanced
+//! - `STATUS`: print the gains, the angle and the motor speed
+//!
+//! Tuning: start with KI and KD at 0 and raise KP until the robot
+//! oscillates around the balance point, then raise KD until the
+//! oscillation is damped. A little KI helps to hold the position. Adjust
+//! TRIM so that the robot does not drift away.
+//!
+//! Mount the MPU6050 with its X axis pointing forward and its Z axis up.
+//! When tilting the robot forward, the angle in `STATUS` must increase and
+//! the wheels must turn forward. Otherwise change `REVERSE_ANGLE` or
+//! `REVERSE_MOTORS`. The robot must stand still while starting up so that
+//! the gyroscope offset can be measured.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and USART1.
+//!
+//! ```
+//! BP   <-> MPU6050 <-> DRV8833      <-> USB-serial adapter
+//! GND  <-> GND     <-> GND          <-> GND
+//! 3.3V <-> VCC
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! PA0              <-> AIN1
+//! PA1              <-> AIN2
+//! PA2              <-> BIN1
+//! PA3              <-> BIN2
+//! PA9                               <-> RX
+//! PA10                              <-> TX
+//!                      VM <-> Battery +
+//!                      AOUT1/2 <-> Left motor
+//!                      BOUT1/2 <-> Right motor
+//! ```
+//!
+//! Many DRV8833 boards pull nSLEEP up. If yours does not, connect it to VCC.
+//!
+//! Run with:
+//! `cargo embed --example balance-bot-mpu6050-drv8833-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::singleton;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    complementary::ComplementaryFilter,
+    i2c_speed, log_info, log_warn, motor, panic_display as _,
+    pid::PidController,
+    uart::{LineBuffer, RxBuffer, RxReader, TxBuffer, TxWriter, UartRx, UartTx},
+};
+use embedded_hal::{blocking::i2c, digital::v2::OutputPin, Pwm};
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::{PA0, PA1, PA2, PA3},
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    pwm::{Channel, Pwm as TimerPwm, C1, C2, C3, C4},
+    serial::{Config, Rx, Serial, Tx},
+    timer::{CountDownTimer, Event, Tim2NoRemap, Timer},
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+type MotorPwm = TimerPwm<
+    pac::TIM2,
+    Tim2NoRemap,
+    (C1, C2, C3, C4),
+    (
+        PA0<Alternate<PushPull>>,
+        PA1<Alternate<PushPull>>,
+        PA2<Alternate<PushPull>>,
+        PA3<Alternate<PushPull>>,
+    ),
+>;
+
+const SYSCLK_MHZ: u32 = 72;
+const BAUD_RATE: u32 = 115_200;
+const CONTROL_HZ: u32 = 200;
+const DT_S: f32 = 1.0 / CONTROL_HZ as f32;
+/// Weight of the gyroscope in the complementary filter
+const FILTER_ALPHA: f32 = 0.98;
+const FALL_ANGLE_DEG: f32 = 45.0;
+/// After a fall, the robot balances again once it is held this upright.
+const UPRIGHT_ANGLE_DEG: f32 = 5.0;
+/// Motor speed needed to overcome the friction of the gears
+const MOTOR_DEADBAND: f32 = 0.05;
+const REVERSE_ANGLE: bool = false;
+const REVERSE_MOTORS: bool = false;
+const GYRO_CALIBRATION_SAMPLES: i32 = 200;
+const RX_BUFFER: usize = 64;
+const TX_BUFFER: usize = 256;
+const LINE_LEN: usize = 32;
+
+const MPU6050_ADDRESS: u8 = 0x68;
+/// Gyroscope sensitivity at ±500°/s
+const GYRO_LSB_PER_DPS: f32 = 65.5;
+
+/// Minimal MPU6050 driver
+pub struct Mpu6050<I2C> {
+    i2c: I2C,
+}
+
+/// Raw accelerometer and gyroscope measurement: x, y, z
+#[derive(Debug, Clone, Copy)]
+struct Measurement {
+    accel: [i16; 3],
+    gyro: [i16; 3],
+}
+
+impl<I2C, E> Mpu6050<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    fn new(i2c: I2C) -> Self {
+        Mpu6050 { i2c }
+    }
+
+    /// Wake up with the gyroscope as clock, a 44Hz low-pass filter,
+    /// ±500°/s and ±2g. Returns the WHO_AM_I register, which should be 0x68.
+    fn init(&mut self) -> Result<u8, E> {
+        self.write_register(0x6B, 0x01)?;
+        self.write_register(0x1A, 0x03)?;
+        self.write_register(0x1B, 0x08)?;
+        self.write_register(0x1C, 0x00)?;
+        let mut who_am_i = [0];
+        self.i2c
+            .write_read(MPU6050_ADDRESS, &[0x75], &mut who_am_i)?;
+        Ok(who_am_i[0])
+    }
+
+    fn read(&mut self) -> Result<Measurement, E> {
+        // Accelerometer, temperature and gyroscope registers
+        let mut data = [0; 14];
+        self.i2c.write_read(MPU6050_ADDRESS, &[0x3B], &mut data)?;
+        let value = |i: usize| i16::from_be_bytes([data[i], data[i + 1]]);
+        Ok(Measurement {
+            accel: [value(0), value(2), value(4)],
+            gyro: [value(8), value(10), value(12)],
+        })
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), E> {
+        self.i2c.write(MPU6050_ADDRESS, &[register, value])
+    }
+}
+
+fn set_motors(pwm: &mut MotorPwm, speed: f32) {
+    let speed = if REVERSE_MOTORS { -speed } else { speed };
+    motor::set_speed(pwm, Channel::C1, Channel::C2, speed);
+    motor::set_speed(pwm, Channel::C3, Channel::C4, speed);
+}
+
+/// Tuning command received over the console
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Kp(f32),
+    Ki(f32),
+    Kd(f32),
+    Trim(f32),
+    Status,
+}
+
+fn parse(line: &str) -> Option<Command> {
+    let mut words = line.split_whitespace();
+    let command = words.next()?;
+    if command.eq_ignore_ascii_case("STATUS") {
+        return Some(Command::Status);
+    }
+    let value: f32 = words.next()?.parse().ok()?;
+    if command.eq_ignore_ascii_case("KP") {
+        Some(Command::Kp(value))
+    } else if command.eq_ignore_ascii_case("KI") {
+        Some(Command::Ki(value))
+    } else if command.eq_ignore_ascii_case("KD") {
+        Some(Command::Kd(value))
+    } else if command.eq_ignore_ascii_case("TRIM") {
+        Some(Command::Trim(value))
+    } else {
+        None
+    }
+}
+
+/// State of the control loop, shared with the console
+pub struct Balance {
+    filter: ComplementaryFilter,
+    pid: PidController,
+    trim_deg: f32,
+    gyro_offset: f32,
+    angle: f32,
+    speed: f32,
+    fallen: bool,
+    imu_errors: u32,
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        imu: Mpu6050<I2cBus>,
+        motors: MotorPwm,
+        timer: CountDownTimer<pac::TIM3>,
+        balance: Balance,
+        uart_rx: UartRx<Rx<pac::USART1>, RX_BUFFER>,
+        uart_tx: UartTx<Tx<pac::USART1>, TX_BUFFER>,
+        reader: RxReader<RX_BUFFER>,
+        writer: TxWriter<TX_BUFFER>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Balance bot example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let motor_pins = (
+            gpioa.pa0.into_alternate_push_pull(&mut gpioa.crl),
+            gpioa.pa1.into_alternate_push_pull(&mut gpioa.crl),
+            gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl),
+            gpioa.pa3.into_alternate_push_pull(&mut gpioa.crl),
+        );
+        let mut motors = Timer::tim2(device.TIM2, &clocks, &mut rcc.apb1)
+            .pwm::<Tim2NoRemap, _, _, _>(motor_pins, &mut afio.mapr, 20.khz());
+        set_motors(&mut motors, 0.0);
+        for channel in [Channel::C1, Channel::C2, Channel::C3, Channel::C4].iter() {
+            motors.enable(*channel);
+        }
+
+        let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+        let rx = gpioa.pa10;
+        let serial = Serial::usart1(
+            device.USART1,
+            (tx, rx),
+            &mut afio.mapr,
+            Config::default().baudrate(BAUD_RATE.bps()),
+            clocks,
+            &mut rcc.apb2,
+        );
+        let (tx, mut rx) = serial.split();
+        rx.listen();
+        let (uart_rx, reader) = singleton!(: RxBuffer<RX_BUFFER> = RxBuffer::new())
+            .unwrap()
+            .split(rx);
+        let (uart_tx, writer) = singleton!(: TxBuffer<TX_BUFFER> = TxBuffer::new())
+            .unwrap()
+            .split(tx, || rtic::pend(pac::Interrupt::USART1));
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[MPU6050_ADDRESS]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        let mut imu = Mpu6050::new(i2c);
+        let who_am_i = imu.init().unwrap();
+        if who_am_i != MPU6050_ADDRESS {
+            log_warn!("Unexpected WHO_AM_I: {:#04x}", who_am_i);
+        }
+
+        // Average the gyroscope while the robot stands still.
+        let mut sum = 0_i32;
+        for _ in 0..GYRO_CALIBRATION_SAMPLES {
+            cortex_m::asm::delay(SYSCLK_MHZ * 5000);
+            sum += i32::from(imu.read().unwrap().gyro[1]);
+        }
+        let gyro_offset = sum as f32 / GYRO_CALIBRATION_SAMPLES as f32;
+        log_info!("Gyroscope offset: {}", gyro_offset);
+
+        let mut timer =
+            Timer::tim3(device.TIM3, &clocks, &mut rcc.apb1).start_count_down(CONTROL_HZ.hz());
+        timer.listen(Event::Update);
+
+        init::LateResources {
+            imu,
+            motors,
+            timer,
+            balance: Balance {
+                filter: ComplementaryFilter::new(FILTER_ALPHA),
+                pid: PidController::new(0.08, 0.4, 0.003).limits(-1.0, 1.0),
+                trim_deg: 0.0,
+                gyro_offset,
+                angle: 0.0,
+                speed: 0.0,
+                fallen: true,
+                imu_errors: 0,
+            },
+            uart_rx,
+            uart_tx,
+            reader,
+            writer,
+            led,
+        }
+    }
+
+    #[task(binds = TIM3, priority = 2, resources = [imu, motors, timer, balance])]
+    fn control(cx: control::Context) {
+        cx.resources.timer.clear_update_interrupt_flag();
+        let balance = cx.resources.balance;
+        let motors = cx.resources.motors;
+
+        let measurement = match cx.resources.imu.read() {
+            Ok(m) => m,
+            Err(_) => {
+                // Never keep driving blind.
+                balance.imu_errors += 1;
+                balance.speed = 0.0;
+                set_motors(motors, 0.0);
+                return;
+            }
+        };
+        // Tilt around the Y axis
+        let [ax, _, az] = measurement.accel;
+        let accel_angle = libm::atan2f(f32::from(ax), f32::from(az)).to_degrees();
+        let rate = -(f32::from(measurement.gyro[1]) - balance.gyro_offset) / GYRO_LSB_PER_DPS;
+        let (accel_angle, rate) = if REVERSE_ANGLE {
+            (-accel_angle, -rate)
+        } else {
+            (accel_angle, rate)
+        };
+        balance.angle = balance.filter.update(rate, accel_angle, DT_S);
+
+        let tilt = libm::fabsf(balance.angle - balance.trim_deg);
+        if balance.fallen && tilt < UPRIGHT_ANGLE_DEG {
+            balance.fallen = false;
+            balance.pid.reset();
+        } else if !balance.fallen && tilt > FALL_ANGLE_DEG {
+            balance.fallen = true;
+        }
+
+        balance.speed = if balance.fallen {
+            0.0
+        } else {
+            // Drive the wheels under the robot: forward when it leans
+            // forward.
+            let output = -balance.pid.update(balance.trim_deg, balance.angle, DT_S);
+            if libm::fabsf(output) < 0.01 {
+                0.0
+            } else {
+                libm::copysignf(
+                    MOTOR_DEADBAND + (1.0 - MOTOR_DEADBAND) * libm::fabsf(output),
+                    output,
+                )
+            }
+        };
+        set_motors(motors, balance.speed);
+    }
+
+    /// Move the received bytes into the buffer and send the waiting ones.
+    /// This has the highest priority so that no characters are lost while
+    /// the control loop runs.
+    #[task(binds = USART1, priority = 3, resources = [uart_rx, uart_tx])]
+    fn usart1(cx: usart1::Context) {
+        cx.resources.uart_rx.on_interrupt();
+        let uart_tx = cx.resources.uart_tx;
+        if uart_tx.on_interrupt() {
+            uart_tx.tx().listen();
+        } else {
+            uart_tx.tx().unlisten();
+        }
+    }
+
+    #[idle(resources = [balance, reader, writer, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let reader = cx.resources.reader;
+        let tx = cx.resources.writer;
+        let mut line_buffer: LineBuffer<LINE_LEN> = LineBuffer::new();
+        writeln!(tx, "OK ready").unwrap();
+        let mut loops = 0_u32;
+        loop {
+            for line in reader.lines(&mut line_buffer) {
+                let command = parse(&line);
+                // Only copy the state while locked: writing to the console
+                // takes long enough to hold up the control loop.
+                let (kp, ki, kd, trim_deg, angle, speed, fallen, imu_errors) =
+                    cx.resources.balance.lock(|balance| {
+                        let (kp, ki, kd) = balance.pid.gains();
+                        match command {
+                            Some(Command::Kp(kp)) => balance.pid.set_gains(kp, ki, kd),
+                            Some(Command::Ki(ki)) => {
+                                balance.pid.set_gains(kp, ki, kd);
+                                balance.pid.reset();
+                            }
+                            Some(Command::Kd(kd)) => balance.pid.set_gains(kp, ki, kd),
+                            Some(Command::Trim(trim)) => balance.trim_deg = trim,
+                            Some(Command::Status) | None => (),
+                        }
+                        let (kp, ki, kd) = balance.pid.gains();
+                        (
+                            kp,
+                            ki,
+                            kd,
+                            balance.trim_deg,
+                            balance.angle,
+                            balance.speed,
+                            balance.fallen,
+                            balance.imu_errors,
+                        )
+                    });
+                match command {
+                    Some(_) => writeln!(
+                        tx,
+                        "OK KP {} KI {} KD {} TRIM {} angle: {:.1} speed: {:.2}{} IMU errors: {}",
+                        kp,
+                        ki,
+                        kd,
+                        trim_deg,
+                        angle,
+                        speed,
+                        if fallen { " (fallen)" } else { "" },
+                        imu_errors
+                    ),
+                    None => writeln!(tx, "ERR unknown command"),
+                }
+                .unwrap();
+            }
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            loops = loops.wrapping_add(1);
+            if loops % 1_000_000 == 0 {
+                cx.resources.led.set_low().unwrap();
+            } else if loops % 1_000_000 == 100_000 {
+                cx.resources.led.set_high().unwrap();
+            }
+        }
+    }
+};
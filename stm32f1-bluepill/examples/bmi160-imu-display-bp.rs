@@ -24,6 +24,10 @@ use bmi160::{
 };
 use core::fmt::Write;
 use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    clock, log_info,
+    panic_display::{self, Bus},
+};
 use embedded_graphics::{
     fonts::{Font6x8, Text},
     pixelcolor::BinaryColor,
@@ -31,8 +35,7 @@ use embedded_graphics::{
     style::TextStyleBuilder,
 };
 use embedded_hal::digital::v2::OutputPin;
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use ssd1306::{prelude::*, Builder, I2CDIBuilder};
 use stm32f1xx_hal::{
     delay::Delay,
@@ -44,13 +47,14 @@ use stm32f1xx_hal::{
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("BMI160 example");
+    log_info!("BMI160 example");
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
 
     let mut flash = dp.FLASH.constrain();
     let mut rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
+    clock::log_clocks(&clocks);
     let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
     let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
 
@@ -81,6 +85,7 @@ fn main() -> ! {
     let interface = I2CDIBuilder::new().init(manager.acquire());
     let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
     disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
     disp.flush().unwrap();
 
     let text_style = TextStyleBuilder::new(Font6x8)
@@ -0,0 +1,158 @@
+//! Broadcast the temperature measured with a TMP112 sensor on a CAN bus
+//! and change the sampling interval live with configuration frames.
+//!
+//! Every `interval` milliseconds the example sends a frame with id
+//! `SENSOR_ID` and 3 data bytes: the temperature in 0.01°C as a little
+//! endian i16 and a sequence number, so receivers notice lost frames.
+//! A frame with id `CONFIG_ID` and the new interval in milliseconds as a
+//! little endian u16 changes the interval, which is acknowledged with a
+//! frame with id `CONFIG_ACK_ID` and the interval now used. Only the
+//! configuration frames pass the filter of the peripheral, so the bus
+//! traffic of other nodes does not load the core.
+//!
+//! The bus runs at 500kbit/s with the `can` module of this crate. The
+//! bxCAN peripheral of the STM32F103 only supports classic CAN, not CAN FD
+//! with a faster data phase.
+//!
+//! For example with a USB CAN adapter on Linux:
+//! ```
+//! ip link set can0 up type can bitrate 500000
+//! candump can0
+//! cansend can0 200#E803
+//! ```
+//! The last command sets the interval to 1000ms.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and CAN1.
+//!
+//! ```
+//! BP   <-> TMP112 <-> SN65HVD230
+//! GND  <-> GND    <-> GND
+//! 3.3V <-> VCC    <-> 3.3V
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! PA11            <-> CRX
+//! PA12            <-> CTX
+//!                     CANH, CANL <-> bus
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example can-sensor-broadcast-tmp112-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    can::{self, Can, Frame, MAX_STD_ID},
+    i2c_speed, log_info, log_warn, monotonic, panic_display as _,
+};
+use embedded_hal::digital::v2::OutputPin;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{pac, prelude::*};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+const BITRATE: u32 = 500_000;
+const SENSOR_ID: u16 = 0x100;
+const CONFIG_ID: u16 = 0x200;
+const CONFIG_ACK_ID: u16 = 0x280;
+const DEFAULT_INTERVAL_MS: u16 = 1000;
+const MIN_INTERVAL_MS: u16 = 10;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("CAN sensor broadcast example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    // Enable the CAN clock before handing the RCC over to the HAL.
+    dp.RCC.apb1enr.modify(|_, w| w.canen().set_bit());
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // TMP112 sensor
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x48]),
+    );
+    let mut sensor = Tmp1x2::new(i2c, SlaveAddr::default());
+
+    // CAN1 on its default pins
+    let _can_rx = gpioa.pa11.into_floating_input(&mut gpioa.crh);
+    let _can_tx = gpioa.pa12.into_alternate_push_pull(&mut gpioa.crh);
+    let timing = can::bit_timing(clocks.pclk1().0, BITRATE).unwrap();
+    let mut can = Can::new(dp.CAN1, timing).unwrap();
+    can.set_filter(0, CONFIG_ID, MAX_STD_ID);
+    log_info!("Joined the bus");
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut interval_ms = DEFAULT_INTERVAL_MS;
+    let mut sequence = 0_u8;
+    let mut last_sample = monotonic::millis();
+    loop {
+        if let Some(frame) = can.receive() {
+            match frame.data() {
+                &[low, high] => {
+                    interval_ms = u16::from_le_bytes([low, high]).max(MIN_INTERVAL_MS);
+                    log_info!("Interval set to {}ms", interval_ms);
+                    let ack = Frame::new(CONFIG_ACK_ID, &interval_ms.to_le_bytes()).unwrap();
+                    nb::block!(can.transmit(&ack)).unwrap();
+                }
+                _ => log_warn!("Invalid configuration frame: {:?}", frame.data()),
+            }
+        }
+
+        if monotonic::millis().wrapping_sub(last_sample) < u32::from(interval_ms) {
+            continue;
+        }
+        last_sample = monotonic::millis();
+        match sensor.read_temperature() {
+            Ok(temperature) => {
+                let centi_celsius = (temperature * 100.0) as i16;
+                let [low, high] = centi_celsius.to_le_bytes();
+                let frame = Frame::new(SENSOR_ID, &[low, high, sequence]).unwrap();
+                // Skip the sample if the last one is still waiting, e.g.
+                // when no other node acknowledges the frames.
+                if can.transmit(&frame).is_err() {
+                    log_warn!("Bus busy or no other node");
+                }
+                sequence = sequence.wrapping_add(1);
+            }
+            Err(e) => log_warn!("Sensor error: {:?}", e),
+        }
+        if can.bus_off() {
+            log_warn!("Bus off");
+            led.set_high().unwrap();
+        } else {
+            // LED 0 is on while the example is on the bus.
+            led.set_low().unwrap();
+        }
+    }
+}
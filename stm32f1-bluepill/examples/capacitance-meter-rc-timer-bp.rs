@@ -0,0 +1,363 @@
+//! Measure capacitors from a few pF to thousands of µF by timing how long
+//! they take to charge through a known resistor, and show the value on an
+//! SSD1306 OLED display. Only two resistors are needed.
+//!
+//! The capacitor is charged through the resistor from 3.3V. Its voltage
+//! rises as `V(t) = 3.3V * (1 - e^(-t / RC))`, so the time until it reaches
+//! the input threshold of the pin is proportional to the capacitance:
+//! `C = t / (R * CHARGE_FACTOR)` with `CHARGE_FACTOR = -ln(1 - Vth / 3.3V)`.
+//!
+//! The time is captured by TIM2 channel 1 in hardware when the input goes
+//! high, so it is exact to one timer tick. The measurement ranges differ in
+//! the resistor and the timer prescaler. The meter starts with the most
+//! sensitive one and switches to the next one when the timer overflows
+//! before the capacitor is charged, or back when the time is very short:
+//! - 1M, 72MHz: up to about 1nF
+//! - 1K, 72MHz: up to about 1µF
+//! - 1K, 1MHz: up to about 80µF
+//! - 1K, 10kHz: up to about 8000µF
+//!
+//! The resistor which is not used must not be connected at all, so its pin
+//! is switched to a floating input. The HAL can not change the mode of a pin
+//! at run time so GPIOA is configured through its registers. Between the
+//! measurements the capacitor is discharged through the 1K resistor.
+//!
+//! The wires and the board add some pF. Start the example without a
+//! capacitor connected: this stray capacitance is measured and subtracted.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> Capacitor <-> Resistors <-> Display
+//! GND  <-> -                       <-> GND
+//! 3.3V                             <-> VDD
+//! PA0  <-> +         <-> 1M, 1K
+//! PA1                <-> 1M
+//! PA2                <-> 1K
+//! PB8                              <-> SCL
+//! PB9                              <-> SDA
+//! ```
+//!
+//! The input threshold varies from chip to chip. Measure a capacitor you
+//! know well (e.g. a 1% film capacitor of 100nF) and correct
+//! `CHARGE_FACTOR` by the ratio of the displayed and the real value.
+//! Electrolytic capacitors must be connected with the right polarity.
+//!
+//! Run with:
+//! `cargo embed --example capacitance-meter-rc-timer-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_info, monotonic,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{pac, prelude::*};
+
+const SYSCLK_MHZ: u32 = 72;
+/// -ln(1 - Vth / VDD) for an input threshold of about 1.8V
+const CHARGE_FACTOR: f32 = 0.79;
+/// Switch to the more sensitive range when it would capture in fewer ticks.
+/// Half of the timer keeps the ranges from switching back and forth.
+const DOWN_TICKS: f32 = 32768.0;
+const ZERO_SAMPLES: u32 = 16;
+const MEASURE_INTERVAL_MS: u32 = 500;
+
+/// Pin with the reference resistor
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resistor {
+    /// PA1
+    High,
+    /// PA2
+    Low,
+}
+
+/// State of a resistor pin
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Drive {
+    Floating,
+    Low,
+    High,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Range {
+    resistor: Resistor,
+    ohms: f32,
+    prescaler: u16,
+    name: &'static str,
+}
+
+const RANGES: [Range; 4] = [
+    Range {
+        resistor: Resistor::High,
+        ohms: 1_000_000.0,
+        prescaler: 1,
+        name: "1M 72MHz",
+    },
+    Range {
+        resistor: Resistor::Low,
+        ohms: 1_000.0,
+        prescaler: 1,
+        name: "1K 72MHz",
+    },
+    Range {
+        resistor: Resistor::Low,
+        ohms: 1_000.0,
+        prescaler: 72,
+        name: "1K 1MHz",
+    },
+    Range {
+        resistor: Resistor::Low,
+        ohms: 1_000.0,
+        prescaler: 7200,
+        name: "1K 10kHz",
+    },
+];
+
+/// Set the mode and level of the resistor pins. `modify` keeps the other
+/// pins of GPIOA as they are. Input mode with CNF 01 is a floating input.
+fn drive(gpioa: &pac::GPIOA, high: Drive, low: Drive) {
+    match high {
+        Drive::Floating => gpioa
+            .crl
+            .modify(|_, w| w.mode1().input().cnf1().open_drain()),
+        Drive::Low | Drive::High => {
+            if high == Drive::High {
+                gpioa.bsrr.write(|w| w.bs1().set_bit());
+            } else {
+                gpioa.bsrr.write(|w| w.br1().set_bit());
+            }
+            gpioa
+                .crl
+                .modify(|_, w| w.mode1().output().cnf1().push_pull());
+        }
+    }
+    match low {
+        Drive::Floating => gpioa
+            .crl
+            .modify(|_, w| w.mode2().input().cnf2().open_drain()),
+        Drive::Low | Drive::High => {
+            if low == Drive::High {
+                gpioa.bsrr.write(|w| w.bs2().set_bit());
+            } else {
+                gpioa.bsrr.write(|w| w.br2().set_bit());
+            }
+            gpioa
+                .crl
+                .modify(|_, w| w.mode2().output().cnf2().push_pull());
+        }
+    }
+}
+
+/// Discharge the capacitor through the 1K resistor. `charge_us` is the
+/// time of the last charge: 5 times that more leaves less than 1%.
+fn discharge(gpioa: &pac::GPIOA, charge_us: u32) {
+    drive(gpioa, Drive::Floating, Drive::Low);
+    while gpioa.idr.read().idr0().bit_is_set() {}
+    monotonic::wait_ms((charge_us * 5 / 1000).max(1));
+}
+
+/// Charge the capacitor in the given range and return the number of timer
+/// ticks until the input went high, `None` if the timer overflowed.
+fn measure(timer: &pac::TIM2, gpioa: &pac::GPIOA, range: &Range) -> Option<u32> {
+    timer.psc.write(|w| w.psc().bits(range.prescaler - 1));
+    // Load the prescaler and clear the counter, then clear the flags set
+    // by that.
+    timer.egr.write(|w| w.ug().set_bit());
+    timer
+        .sr
+        .modify(|_, w| w.uif().clear_bit().cc1if().clear_bit().cc1of().clear_bit());
+    timer.cr1.modify(|_, w| w.cen().enabled());
+    match range.resistor {
+        Resistor::High => drive(gpioa, Drive::High, Drive::Floating),
+        Resistor::Low => drive(gpioa, Drive::Floating, Drive::High),
+    }
+    let ticks = loop {
+        let status = timer.sr.read();
+        if status.cc1if().bit_is_set() {
+            break Some(timer.ccr1.read().bits() & 0xFFFF);
+        }
+        if status.uif().bit_is_set() {
+            break None;
+        }
+    };
+    timer.cr1.modify(|_, w| w.cen().disabled());
+    ticks
+}
+
+/// Timer ticks needed to charge 1pF in the given range
+fn ticks_per_pf(range: &Range) -> f32 {
+    range.ohms * CHARGE_FACTOR * SYSCLK_MHZ as f32 * 1e-6 / f32::from(range.prescaler)
+}
+
+fn capacitance_pf(ticks: u32, range: &Range) -> f32 {
+    ticks as f32 / ticks_per_pf(range)
+}
+
+fn write_capacitance<W: Write>(w: &mut W, pf: f32) -> core::fmt::Result {
+    if pf < 1000.0 {
+        write!(w, "{:.1} pF", pf)
+    } else if pf < 1e6 {
+        write!(w, "{:.2} nF", pf / 1e3)
+    } else {
+        write!(w, "{:.2} uF", pf / 1e6)
+    }
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Capacitance meter example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    // Enable the GPIOA and TIM2 clocks before handing the RCC over to the
+    // HAL. GPIOA is not split but configured through its registers.
+    dp.RCC.apb2enr.modify(|_, w| w.iopaen().set_bit());
+    dp.RCC.apb1enr.modify(|_, w| w.tim2en().set_bit());
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(SYSCLK_MHZ.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    // PA0 stays a floating input as after reset: TIM2 channel 1. Capture
+    // the rising edge of TI1 without a filter. The TIM2 clock is twice
+    // PCLK1, so 72MHz.
+    let gpioa = dp.GPIOA;
+    let timer = dp.TIM2;
+    timer.arr.write(|w| w.arr().bits(0xFFFF));
+    timer.ccmr1_input().modify(|_, w| w.cc1s().ti1());
+    timer.ccer.modify(|_, w| w.cc1e().set_bit());
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    // Stray capacitance without a capacitor connected
+    discharge(&gpioa, 0);
+    let mut stray_pf = 0.0;
+    for _ in 0..ZERO_SAMPLES {
+        if let Some(ticks) = measure(&timer, &gpioa, &RANGES[0]) {
+            stray_pf += capacitance_pf(ticks, &RANGES[0]) / ZERO_SAMPLES as f32;
+        }
+        discharge(&gpioa, 0);
+    }
+    log_info!("Stray capacitance: {:.1} pF", stray_pf);
+
+    let mut range = 0;
+    let mut lines: [String<32>; 3] = Default::default();
+    let mut led_on = false;
+    loop {
+        let result = measure(&timer, &gpioa, &RANGES[range]);
+        let charge_us = match result {
+            Some(ticks) => ticks * u32::from(RANGES[range].prescaler) / SYSCLK_MHZ,
+            None => 0xFFFF * u32::from(RANGES[range].prescaler) / SYSCLK_MHZ,
+        };
+        discharge(&gpioa, charge_us);
+
+        // Change the range and measure again at once if needed.
+        match result {
+            None if range + 1 < RANGES.len() => {
+                range += 1;
+                continue;
+            }
+            Some(ticks)
+                if range > 0
+                    && capacitance_pf(ticks, &RANGES[range]) * ticks_per_pf(&RANGES[range - 1])
+                        < DOWN_TICKS =>
+            {
+                range -= 1;
+                continue;
+            }
+            _ => (),
+        }
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        match result {
+            Some(ticks) => {
+                let pf = (capacitance_pf(ticks, &RANGES[range]) - stray_pf).max(0.0);
+                write!(lines[0], "C = ").unwrap();
+                write_capacitance(&mut lines[0], pf).unwrap();
+                write!(lines[2], "Charge: {} us", charge_us).unwrap();
+                log_info!("{} ({} ticks, {})", lines[0], ticks, RANGES[range].name);
+            }
+            None => write!(lines[0], "C = too large").unwrap(),
+        }
+        write!(lines[1], "Range: {}", RANGES[range].name).unwrap();
+
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led_on = !led_on;
+        if led_on {
+            led.set_high().unwrap();
+        } else {
+            led.set_low().unwrap();
+        }
+        monotonic::wait_ms(MEASURE_INTERVAL_MS);
+    }
+}
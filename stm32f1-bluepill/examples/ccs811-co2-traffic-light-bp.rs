@@ -0,0 +1,205 @@
+//! Continuously measure the eCO2 in the air with a CCS811 and show the air
+//! quality on a red/yellow/green "traffic light" made of three LEDs.
+//! While the air quality is bad (red), a buzzer chirps periodically as a
+//! reminder to open the windows.
+//!
+//! In order to avoid the lights flickering when the eCO2 value is close to a
+//! threshold, the thresholds have some hysteresis: the level only goes down
+//! again after the eCO2 value has dropped `HYSTERESIS` ppm below the threshold.
+//!
+//! Introductory blog post with some pictures here:
+//! https://blog.eldruin.com/ccs811-indoor-air-quality-sensor-driver-in-rust/
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> CCS811 <-> Green LED <-> Yellow LED <-> Red LED <-> Buzzer
+//! GND  <-> GND    <-> GND       <-> GND        <-> GND     <-> GND
+//! 3.3V <-> VCC
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! GND  <-> nWAKE
+//! 3.3V <-> RST
+//! PA0                 <-> +
+//! PA1                               <-> +
+//! PA2                                              <-> +
+//! PA3                                                          <-> +
+//! ```
+//!
+//! The LEDs need a series resistor (e.g. 220 Ohm) and the buzzer must be
+//! an active buzzer (it beeps when supplied with a constant voltage).
+//!
+//! Run with:
+//! `cargo embed --example ccs811-co2-traffic-light-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{log_error, log_info, panic_display as _};
+use embedded_ccs811::{prelude::*, Ccs811Awake, MeasurementMode, SlaveAddr};
+use embedded_hal::digital::v2::OutputPin;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    delay::Delay,
+    i2c::{BlockingI2c, DutyCycle, Mode},
+    pac,
+    prelude::*,
+};
+
+// eCO2 thresholds in ppm. Adjust to your needs.
+const YELLOW_THRESHOLD: u16 = 1000;
+const RED_THRESHOLD: u16 = 1500;
+const HYSTERESIS: u16 = 100;
+
+// The main loop runs roughly every 100ms.
+// Chirp every 30 seconds while the level is red.
+const CHIRP_PERIOD_LOOPS: u32 = 300;
+const CHIRP_DURATION_MS: u16 = 50;
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("CCS811 CO2 traffic light example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let i2c = BlockingI2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        Mode::Fast {
+            frequency: 100_000.hz(),
+            duty_cycle: DutyCycle::Ratio2to1,
+        },
+        clocks,
+        &mut rcc.apb1,
+        1000,
+        10,
+        1000,
+        1000,
+    );
+
+    let mut green = gpioa.pa0.into_push_pull_output(&mut gpioa.crl);
+    let mut yellow = gpioa.pa1.into_push_pull_output(&mut gpioa.crl);
+    let mut red = gpioa.pa2.into_push_pull_output(&mut gpioa.crl);
+    let mut buzzer = gpioa.pa3.into_push_pull_output(&mut gpioa.crl);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut ccs811 = Ccs811Awake::new(i2c, SlaveAddr::default());
+    ccs811.software_reset().unwrap();
+    delay.delay_ms(10_u16);
+
+    let mut ccs811 = ccs811.start_application().ok().unwrap();
+    ccs811.set_mode(MeasurementMode::ConstantPower1s).unwrap();
+
+    let mut traffic_light = TrafficLight::new(YELLOW_THRESHOLD, RED_THRESHOLD, HYSTERESIS);
+    let mut loop_counter: u32 = 0;
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+        delay.delay_ms(50_u16);
+
+        // The CCS811 only has new data once per second so do not block here.
+        match ccs811.data() {
+            Ok(data) => {
+                let previous = traffic_light.level();
+                let level = traffic_light.update(data.eco2);
+                if level != previous {
+                    log_info!("eCO2: {} ppm -> {:?}", data.eco2, level);
+                    // Chirp immediately when reaching the red level.
+                    loop_counter = 0;
+                }
+            }
+            Err(nb::Error::WouldBlock) => {}
+            Err(nb::Error::Other(_)) => log_error!(every = 20, "Error reading data"),
+        }
+
+        let level = traffic_light.level();
+        set_light(&mut green, level == Level::Green);
+        set_light(&mut yellow, level == Level::Yellow);
+        set_light(&mut red, level == Level::Red);
+
+        if level == Level::Red && loop_counter % CHIRP_PERIOD_LOOPS == 0 {
+            buzzer.set_high().unwrap();
+            delay.delay_ms(CHIRP_DURATION_MS);
+            buzzer.set_low().unwrap();
+        }
+        loop_counter = loop_counter.wrapping_add(1);
+    }
+}
+
+fn set_light<P: OutputPin>(pin: &mut P, on: bool) {
+    if on {
+        pin.set_high().ok();
+    } else {
+        pin.set_low().ok();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Green,
+    Yellow,
+    Red,
+}
+
+/// Air quality level with hysteresis on the thresholds
+struct TrafficLight {
+    yellow_threshold: u16,
+    red_threshold: u16,
+    hysteresis: u16,
+    level: Level,
+}
+
+impl TrafficLight {
+    fn new(yellow_threshold: u16, red_threshold: u16, hysteresis: u16) -> Self {
+        TrafficLight {
+            yellow_threshold,
+            red_threshold,
+            hysteresis,
+            level: Level::Green,
+        }
+    }
+
+    fn level(&self) -> Level {
+        self.level
+    }
+
+    fn update(&mut self, eco2: u16) -> Level {
+        // Going up happens as soon as a threshold is reached, going down only
+        // after the value has fallen `hysteresis` below the threshold.
+        let yellow_down = self.yellow_threshold.saturating_sub(self.hysteresis);
+        let red_down = self.red_threshold.saturating_sub(self.hysteresis);
+        self.level = match self.level {
+            Level::Green if eco2 >= self.red_threshold => Level::Red,
+            Level::Green if eco2 >= self.yellow_threshold => Level::Yellow,
+            Level::Yellow if eco2 >= self.red_threshold => Level::Red,
+            Level::Yellow if eco2 < yellow_down => Level::Green,
+            Level::Red if eco2 < yellow_down => Level::Green,
+            Level::Red if eco2 < red_down => Level::Yellow,
+            level => level,
+        };
+        self.level
+    }
+}
@@ -1,6 +1,9 @@
 //! Continuously measure the eCO2 and eTVOC in the air and print it to an
 //! SSD1306 OLED display.
 //!
+//! The LED blinks, the sensor is polled and the profiling report is printed
+//! at their own rates using the cooperative `scheduler` of this crate.
+//!
 //! Introductory blog post with some pictures here:
 //! https://blog.eldruin.com/ccs811-indoor-air-quality-sensor-driver-in-rust/
 //!
@@ -18,13 +21,23 @@
 //!
 //! Run with:
 //! `cargo embed --example ccs811-gas-voc-display-bp`,
+//!
+//! To see how long the sensor reads and display updates take, run with:
+//! `cargo embed --example ccs811-gas-voc-display-bp --features profile`,
 
 #![deny(unsafe_code)]
 #![no_std]
 #![no_main]
 
 use core::fmt::Write;
-use cortex_m_rt::entry;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    clock, log_info, monotonic,
+    panic_display::{self, Bus},
+    profile::{self, Probe},
+    scheduler::Scheduler,
+};
 use embedded_ccs811::{prelude::*, AlgorithmResult, Ccs811Awake, MeasurementMode, SlaveAddr};
 use embedded_graphics::{
     fonts::{Font6x8, Text},
@@ -34,28 +47,37 @@ use embedded_graphics::{
 };
 use embedded_hal::digital::v2::OutputPin;
 use heapless::String;
-use nb::block;
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use ssd1306::{prelude::*, Builder, I2CDIBuilder};
 use stm32f1xx_hal::{
-    delay::Delay,
     i2c::{BlockingI2c, DutyCycle, Mode},
     pac,
     prelude::*,
 };
 
+// Tasks in order of priority
+const BLINK: usize = 0;
+const MEASURE: usize = 1;
+const REPORT: usize = 2;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("CCS811 example");
-    let cp = cortex_m::Peripherals::take().unwrap();
+    log_info!("CCS811 example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
 
     let mut flash = dp.FLASH.constrain();
     let mut rcc = dp.RCC.constrain();
 
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
+    clock::log_clocks(&clocks);
 
     let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
 
@@ -84,12 +106,14 @@ fn main() -> ! {
 
     let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
     let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
-    let mut delay = Delay::new(cp.SYST, clocks);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+    profile::enable(&mut cp.DCB, &mut cp.DWT);
 
     let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
     let interface = I2CDIBuilder::new().init(manager.acquire());
     let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
     disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
     disp.flush().unwrap();
 
     let text_style = TextStyleBuilder::new(Font6x8)
@@ -98,7 +122,7 @@ fn main() -> ! {
 
     let mut ccs811 = Ccs811Awake::new(manager.acquire(), SlaveAddr::default());
     ccs811.software_reset().unwrap();
-    delay.delay_ms(10_u16);
+    monotonic::wait_ms(10);
     let mut lines: [String<32>; 2] = [String::new(), String::new()];
 
     let mut ccs811 = ccs811.start_application().ok().unwrap();
@@ -115,28 +139,52 @@ fn main() -> ! {
         raw_current: 255,
         raw_voltage: 9999,
     };
+    let mut sensor_probe = Probe::new("ccs811");
+    let mut draw_probe = Probe::new("draw");
+    let mut flush_probe = Probe::new("flush");
+    let mut scheduler = Scheduler::new([100, 250, 5000]);
+    let mut led_on = false;
     loop {
-        // Blink LED 0 to check that everything is actually running.
-        // If the LED 0 is off, something went wrong.
-        led.set_high().unwrap();
-        delay.delay_ms(100_u16);
-        led.set_low().unwrap();
-        delay.delay_ms(100_u16);
-
-        let data = block!(ccs811.data()).unwrap_or(default);
-
-        for line in lines.iter_mut() {
-            line.clear();
-        }
-        write!(lines[0], "eCO2: {}", data.eco2).unwrap();
-        write!(lines[1], "eTVOC: {}", data.etvoc).unwrap();
-        disp.clear();
-        for (i, line) in lines.iter().enumerate() {
-            Text::new(line, Point::new(0, i as i32 * 16))
-                .into_styled(text_style)
-                .draw(&mut disp)
-                .unwrap();
+        match scheduler.poll() {
+            Some(BLINK) => {
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                led_on = !led_on;
+                if led_on {
+                    led.set_high().unwrap();
+                } else {
+                    led.set_low().unwrap();
+                }
+            }
+            Some(MEASURE) => {
+                // New data is available every second.
+                let data = match sensor_probe.measure(|| ccs811.data()) {
+                    Ok(data) => data,
+                    Err(nb::Error::WouldBlock) => continue,
+                    Err(nb::Error::Other(_)) => default,
+                };
+
+                for line in lines.iter_mut() {
+                    line.clear();
+                }
+                write!(lines[0], "eCO2: {}", data.eco2).unwrap();
+                write!(lines[1], "eTVOC: {}", data.etvoc).unwrap();
+                draw_probe.measure(|| {
+                    disp.clear();
+                    for (i, line) in lines.iter().enumerate() {
+                        Text::new(line, Point::new(0, i as i32 * 16))
+                            .into_styled(text_style)
+                            .draw(&mut disp)
+                            .unwrap();
+                    }
+                });
+                flush_probe.measure(|| disp.flush()).unwrap();
+            }
+            Some(REPORT) => profile::report(
+                &mut [&mut sensor_probe, &mut draw_probe, &mut flush_probe],
+                clocks.sysclk().0,
+            ),
+            _ => (),
         }
-        disp.flush().unwrap();
     }
 }
@@ -3,6 +3,16 @@
 //! In order to compensate for the ambient temperature and humidity, an HDC2080
 //! sensor is used.
 //!
+//! The LED blinks, the sensors are read and the profiling report is printed
+//! at their own rates using the cooperative `scheduler` of this crate.
+//!
+//! The sensors can be unplugged and plugged again while the example runs.
+//! A sensor that fails to answer (the HDC2080 is read with a timeout) or
+//! that stops delivering data is marked offline. Offline sensors are probed
+//! every few seconds and initialized again when they answer. The header of
+//! the display shows a filled circle for each sensor that is online and an
+//! empty one for each sensor that is offline.
+//!
 //! Introductory blog post with some pictures here:
 //! https://blog.eldruin.com/ccs811-indoor-air-quality-sensor-driver-in-rust/
 //!
@@ -19,14 +29,25 @@
 //! ```
 //!
 //! Run with:
-//! `cargo embed --example ccs811-gas-voc-display-bp`,
+//! `cargo embed --example ccs811-gas-voc-hdc2080-display-bp`,
+//!
+//! To see how long the sensor reads and display updates take, run with:
+//! `cargo embed --example ccs811-gas-voc-hdc2080-display-bp --features profile`,
 
 #![deny(unsafe_code)]
 #![no_std]
 #![no_main]
 
 use core::fmt::Write;
-use cortex_m_rt::entry;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    log_error, log_info, log_warn,
+    monotonic::{self, with_timeout, TimeoutError},
+    panic_display::{self, Bus},
+    profile::{self, Probe},
+    scheduler::Scheduler,
+};
 use embedded_ccs811::{
     prelude::*, AlgorithmResult, Ccs811Awake, MeasurementMode, SlaveAddr as Ccs811SlaveAddr,
 };
@@ -34,28 +55,43 @@ use embedded_graphics::{
     fonts::{Font6x8, Text},
     pixelcolor::BinaryColor,
     prelude::*,
-    style::TextStyleBuilder,
+    primitives::Circle,
+    style::{PrimitiveStyle, TextStyleBuilder},
 };
 use embedded_hal::digital::v2::OutputPin;
 use hdc20xx::{Hdc20xx, SlaveAddr as Hdc20xxSlaveAddr};
 use heapless::String;
-use nb::block;
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use ssd1306::{prelude::*, Builder, I2CDIBuilder};
 use stm32f1xx_hal::{
-    delay::Delay,
     i2c::{BlockingI2c, DutyCycle, Mode},
     pac,
     prelude::*,
 };
 
+// Tasks in order of priority
+const BLINK: usize = 0;
+const MEASURE: usize = 1;
+const ENVIRONMENT: usize = 2;
+const PROBE: usize = 3;
+const REPORT: usize = 4;
+
+const READ_TIMEOUT_MS: u32 = 100;
+/// The CCS811 delivers data every second.
+const CCS811_DATA_TIMEOUT_MS: u32 = 3000;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("CCS811/HDC2080 example");
-    let cp = cortex_m::Peripherals::take().unwrap();
+    log_info!("CCS811/HDC2080 example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
 
     let mut flash = dp.FLASH.constrain();
     let mut rcc = dp.RCC.constrain();
@@ -89,30 +125,44 @@ fn main() -> ! {
 
     let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
     let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
-    let mut delay = Delay::new(cp.SYST, clocks);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+    profile::enable(&mut cp.DCB, &mut cp.DWT);
 
     let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
     let interface = I2CDIBuilder::new().init(manager.acquire());
     let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
     disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
     disp.flush().unwrap();
 
     let text_style = TextStyleBuilder::new(Font6x8)
         .text_color(BinaryColor::On)
         .build();
 
+    // Until the HDC2080 is read
+    let mut temperature = 25.0;
+    let mut humidity = 60.0;
+
+    // Reset the CCS811 and start the measurements. `None` if it does not
+    // answer.
+    let start_ccs811 = |temperature: f32, humidity: f32| {
+        let mut ccs811 = Ccs811Awake::new(manager.acquire(), Ccs811SlaveAddr::default());
+        ccs811.software_reset().ok()?;
+        monotonic::wait_ms(10);
+        let mut ccs811 = ccs811.start_application().ok()?;
+        ccs811.set_environment(temperature, humidity).ok()?;
+        ccs811.set_mode(MeasurementMode::ConstantPower1s).ok()?;
+        Some(ccs811)
+    };
+
     let mut hdc2080 = Hdc20xx::new(manager.acquire(), Hdc20xxSlaveAddr::default());
-    let mut ccs811 = Ccs811Awake::new(manager.acquire(), Ccs811SlaveAddr::default());
-    ccs811.software_reset().unwrap();
-    delay.delay_ms(10_u16);
-    let mut lines: [String<32>; 4] = [String::new(), String::new(), String::new(), String::new()];
-
-    let mut ccs811 = ccs811.start_application().ok().unwrap();
-    let mut env = block!(hdc2080.read()).unwrap();
-    ccs811
-        .set_environment(env.temperature, env.humidity.unwrap_or(0.0))
-        .unwrap();
-    ccs811.set_mode(MeasurementMode::ConstantPower1s).unwrap();
+    let mut hdc2080_online = true;
+    let mut ccs811 = start_ccs811(temperature, humidity);
+    if ccs811.is_none() {
+        log_warn!("CCS811 offline");
+    }
+    let mut last_data_ms = monotonic::millis();
+    let mut lines: [String<32>; 4] = Default::default();
 
     let default = AlgorithmResult {
         eco2: 9999,
@@ -121,41 +171,147 @@ fn main() -> ! {
         raw_voltage: 9999,
     };
 
-    let mut counter = 0;
+    let mut ccs811_probe = Probe::new("ccs811");
+    let mut hdc2080_probe = Probe::new("hdc2080");
+    let mut draw_probe = Probe::new("draw");
+    let mut flush_probe = Probe::new("flush");
+    let mut scheduler = Scheduler::new([500, 250, 10_000, 2000, 10_000]);
+    let mut led_on = false;
+    let mut data = default;
     loop {
-        // Blink LED 0 to check that everything is actually running.
-        // If the LED 0 is off, something went wrong.
-        led.set_high().unwrap();
-        delay.delay_ms(500_u16);
-        led.set_low().unwrap();
-        delay.delay_ms(500_u16);
-
-        let data = block!(ccs811.data()).unwrap_or(default);
-
-        counter += 1;
-        if counter > 10 {
-            counter = 0;
-
-            env = block!(hdc2080.read()).unwrap();
-            ccs811
-                .set_environment(env.temperature, env.humidity.unwrap_or(0.0))
-                .unwrap();
+        match scheduler.poll() {
+            Some(BLINK) => {
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                led_on = !led_on;
+                if led_on {
+                    led.set_high().unwrap();
+                } else {
+                    led.set_low().unwrap();
+                }
+                continue;
+            }
+            Some(MEASURE) => {
+                let sensor = match ccs811.as_mut() {
+                    Some(sensor) => sensor,
+                    None => continue,
+                };
+                match ccs811_probe.measure(|| sensor.data()) {
+                    Ok(new_data) => {
+                        data = new_data;
+                        last_data_ms = monotonic::millis();
+                    }
+                    // A CCS811 that was replugged is back in boot mode and
+                    // never has data.
+                    Err(nb::Error::WouldBlock)
+                        if monotonic::millis().wrapping_sub(last_data_ms)
+                            < CCS811_DATA_TIMEOUT_MS =>
+                    {
+                        continue
+                    }
+                    Err(_) => {
+                        log_warn!("CCS811 offline");
+                        ccs811 = None;
+                    }
+                }
+            }
+            Some(ENVIRONMENT) => {
+                if !hdc2080_online {
+                    continue;
+                }
+                match hdc2080_probe.measure(|| with_timeout(READ_TIMEOUT_MS, || hdc2080.read())) {
+                    Ok(env) => {
+                        temperature = env.temperature;
+                        humidity = env.humidity.unwrap_or(0.0);
+                        if let Some(sensor) = ccs811.as_mut() {
+                            sensor.set_environment(temperature, humidity).ok();
+                        }
+                    }
+                    Err(e) => {
+                        if let TimeoutError::Other(e) = e {
+                            log_error!("HDC2080 error: {:?}", e);
+                        }
+                        log_warn!("HDC2080 offline");
+                        hdc2080_online = false;
+                    }
+                }
+            }
+            Some(PROBE) => {
+                if !hdc2080_online {
+                    hdc2080 = Hdc20xx::new(manager.acquire(), Hdc20xxSlaveAddr::default());
+                    if let Ok(env) = with_timeout(READ_TIMEOUT_MS, || hdc2080.read()) {
+                        log_info!("HDC2080 online");
+                        temperature = env.temperature;
+                        humidity = env.humidity.unwrap_or(0.0);
+                        hdc2080_online = true;
+                    }
+                }
+                if ccs811.is_none() {
+                    ccs811 = start_ccs811(temperature, humidity);
+                    if ccs811.is_some() {
+                        log_info!("CCS811 online");
+                        last_data_ms = monotonic::millis();
+                    }
+                }
+            }
+            Some(REPORT) => {
+                profile::report(
+                    &mut [
+                        &mut ccs811_probe,
+                        &mut hdc2080_probe,
+                        &mut draw_probe,
+                        &mut flush_probe,
+                    ],
+                    clocks.sysclk().0,
+                );
+                continue;
+            }
+            _ => continue,
         }
 
-        for i in 0..4 {
-            lines[i].clear();
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        if ccs811.is_some() {
+            write!(lines[0], "eCO2: {}", data.eco2).unwrap();
+            write!(lines[1], "eTVOC: {}", data.etvoc).unwrap();
+        } else {
+            write!(lines[0], "CCS811 offline").unwrap();
         }
-        write!(lines[0], "eCO2: {}", data.eco2).unwrap();
-        write!(lines[1], "eTVOC: {}", data.etvoc).unwrap();
-        write!(lines[2], "Temp: {:.2}ºC", env.temperature).unwrap();
-        write!(lines[3], "Humidity: {:.2}%", env.humidity.unwrap_or(0.0)).unwrap();
-        disp.clear();
-        for (i, line) in lines.iter().enumerate() {
-            Text::new(line, Point::new(0, i as i32 * 16))
-                .into_styled(text_style)
-                .draw(&mut disp)
-                .unwrap();
+        if hdc2080_online {
+            write!(lines[2], "Temp: {:.2}ºC", temperature).unwrap();
+            write!(lines[3], "Humidity: {:.2}%", humidity).unwrap();
+        } else {
+            write!(lines[2], "HDC2080 offline").unwrap();
         }
-        disp.flush().unwrap();
+        let status = [
+            ("CCS811", 0, ccs811.is_some()),
+            ("HDC2080", 64, hdc2080_online),
+        ];
+        draw_probe.measure(|| {
+            disp.clear();
+            for (name, x, online) in status.iter() {
+                Text::new(name, Point::new(*x, 0))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+                let style = if *online {
+                    PrimitiveStyle::with_fill(BinaryColor::On)
+                } else {
+                    PrimitiveStyle::with_stroke(BinaryColor::On, 1)
+                };
+                Circle::new(Point::new(x + 50, 3), 3)
+                    .into_styled(style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, 16 + i as i32 * 12))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+        });
+        flush_probe.measure(|| disp.flush()).unwrap();
     }
 }
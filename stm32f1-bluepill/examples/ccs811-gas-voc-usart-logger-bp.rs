@@ -22,6 +22,11 @@
 //! 3.3V <-> RST
 //! ```
 //!
+//! Each line has the index, eCO2, eTVOC, raw current, raw voltage,
+//! temperature and humidity, separated by commas. With the `telemetry-json`
+//! feature each line is a JSON object with these fields instead, and the
+//! "start" lines before each batch are left out.
+//!
 //! Run with:
 //! `cargo embed --example ccs811-gas-voc-usart-logger-bp`,
 
@@ -29,7 +34,11 @@
 #![no_std]
 #![no_main]
 
+#[cfg(not(feature = "telemetry-json"))]
 use core::fmt::Write;
+#[cfg(feature = "telemetry-json")]
+use driver_examples_bluepill::telemetry;
+use driver_examples_bluepill::{bootloader::relocate_vector_table, log_info, panic_display as _};
 use embedded_ccs811::{
     mode as Ccs811Mode, prelude::*, AlgorithmResult, Ccs811Awake, MeasurementMode,
     SlaveAddr as Ccs811SlaveAddr,
@@ -37,10 +46,9 @@ use embedded_ccs811::{
 use embedded_hal::digital::v2::OutputPin;
 use hdc20xx::{mode as Hdc20xxMode, Hdc20xx, SlaveAddr as Hdc20xxSlaveAddr};
 use nb::block;
-use panic_rtt_target as _;
 use rtic::app;
 use rtic::cyccnt::U32Ext;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use shared_bus_rtic::SharedBus;
 use stm32f1xx_hal::{
     delay::Delay,
@@ -67,6 +75,18 @@ type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<O
  * prevent one driver from interrupting another while they are using the same underlying bus.
  */
 
+/// One line sent through the serial interface
+#[cfg_attr(feature = "telemetry-json", derive(serde::Serialize))]
+pub struct Sample {
+    index: usize,
+    eco2: u16,
+    etvoc: u16,
+    raw_current: u8,
+    raw_voltage: u16,
+    temperature: f32,
+    humidity: f32,
+}
+
 pub struct I2cDevs {
     ccs811: Ccs811Awake<SharedBus<I2cBus>, Ccs811Mode::App>,
     hdc2080: Hdc20xx<SharedBus<I2cBus>, Hdc20xxMode::OneShot>,
@@ -83,8 +103,9 @@ const APP: () = {
     #[init(schedule = [measure])]
     fn init(cx: init::Context) -> init::LateResources {
         rtt_init_print!();
-        rprintln!("CCS811/HDC2080 example");
+        log_info!("CCS811/HDC2080 example");
         let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
         core.DWT.enable_cycle_counter();
 
         let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
@@ -153,6 +174,7 @@ const APP: () = {
         cx.schedule.measure(cx.start + PERIOD.cycles()).unwrap();
 
         let (mut tx, _rx) = serial.split();
+        #[cfg(not(feature = "telemetry-json"))]
         writeln!(tx, "start\r",).unwrap();
         init::LateResources {
             led,
@@ -197,14 +219,33 @@ const APP: () = {
                 .set_environment(temp, humidity)
                 .unwrap();
         }
+        #[cfg(not(feature = "telemetry-json"))]
         writeln!(cx.resources.tx, "\rstart\r",).unwrap();
         for i in 0..*INDEX {
             let data = MEASUREMENTS[i];
             let env = if i == 0 { (0.0, 0.0) } else { ENV[i - 1] };
+            let sample = Sample {
+                index: i,
+                eco2: data.eco2,
+                etvoc: data.etvoc,
+                raw_current: data.raw_current,
+                raw_voltage: data.raw_voltage,
+                temperature: env.0,
+                humidity: env.1,
+            };
+            #[cfg(feature = "telemetry-json")]
+            telemetry::write_line::<_, _, 160>(cx.resources.tx, &sample).unwrap();
+            #[cfg(not(feature = "telemetry-json"))]
             writeln!(
                 cx.resources.tx,
                 "{},{},{},{},{},{:.2},{:.2}\r",
-                i, data.eco2, data.etvoc, data.raw_current, data.raw_voltage, env.0, env.1
+                sample.index,
+                sample.eco2,
+                sample.etvoc,
+                sample.raw_current,
+                sample.raw_voltage,
+                sample.temperature,
+                sample.humidity
             )
             .unwrap();
         }
@@ -0,0 +1,254 @@
+//! Print a receipt with the temperature measured with a TMP102 sensor on a
+//! CSN-A2 serial thermal printer when pressing a button.
+//!
+//! The temperature is read every second and the receipt includes the
+//! last value as well as the minimum and maximum since startup. It starts
+//! with a small logo printed as a bitmap. The printer is driven with the
+//! ESC/POS command builder in the `escpos` module of this crate, which works
+//! with other ESC/POS printers as well.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and USART1.
+//!
+//! ```
+//! BP   <-> TMP102 <-> Printer <-> Button
+//! GND  <-> GND    <-> GND
+//! 3.3V <-> VCC                <-> +
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! PB6             <-> RX
+//! PB12                        <-> -
+//! ```
+//!
+//! The printer needs its own 5-9V power supply able to deliver 2A. Its
+//! RX input works with 3.3V.
+//!
+//! The baud rate of the printer is printed on the test page, which is
+//! printed when turning it on while holding the feed button.
+//!
+//! Run with:
+//! `cargo embed --example csn-a2-thermal-printer-receipt-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    escpos::{Align, Printer},
+    i2c_speed, log_error, log_info, monotonic, panic_display as _,
+    scheduler::Scheduler,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    pac,
+    prelude::*,
+    serial::{Config, Serial},
+};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+const BAUD_RATE: u32 = 19200;
+
+// Tasks in order of priority
+const BUTTON: usize = 0;
+const MEASURE: usize = 1;
+const BLINK: usize = 2;
+
+/// 32x32 thermometer logo
+const LOGO: [u8; 4 * 32] = [
+    0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x07, 0xE0, 0x00, //
+    0x00, 0x0F, 0xF0, 0x00, //
+    0x00, 0x1C, 0x38, 0x00, //
+    0x00, 0x1C, 0x38, 0x00, //
+    0x00, 0x18, 0x1B, 0x80, //
+    0x00, 0x18, 0x18, 0x00, //
+    0x00, 0x18, 0x18, 0x00, //
+    0x00, 0x18, 0x18, 0x00, //
+    0x00, 0x18, 0x1B, 0x80, //
+    0x00, 0x18, 0x18, 0x00, //
+    0x00, 0x1F, 0xF8, 0x00, //
+    0x00, 0x1F, 0xF8, 0x00, //
+    0x00, 0x1F, 0xFB, 0x80, //
+    0x00, 0x1F, 0xF8, 0x00, //
+    0x00, 0x1F, 0xF8, 0x00, //
+    0x00, 0x1F, 0xF8, 0x00, //
+    0x00, 0x1F, 0xFB, 0x80, //
+    0x00, 0x1F, 0xF8, 0x00, //
+    0x00, 0x1F, 0xF8, 0x00, //
+    0x00, 0x1F, 0xF8, 0x00, //
+    0x00, 0x3F, 0xFC, 0x00, //
+    0x00, 0x3F, 0xFC, 0x00, //
+    0x00, 0x3F, 0xFC, 0x00, //
+    0x00, 0x7F, 0xFE, 0x00, //
+    0x00, 0x3F, 0xFC, 0x00, //
+    0x00, 0x3F, 0xFC, 0x00, //
+    0x00, 0x3F, 0xFC, 0x00, //
+    0x00, 0x1F, 0xF8, 0x00, //
+    0x00, 0x0F, 0xF0, 0x00, //
+    0x00, 0x07, 0xE0, 0x00, //
+];
+
+/// Temperature statistics since startup
+struct Readings {
+    current: f32,
+    min: f32,
+    max: f32,
+}
+
+impl Readings {
+    fn update(&mut self, temperature: f32) {
+        self.current = temperature;
+        self.min = self.min.min(temperature);
+        self.max = self.max.max(temperature);
+    }
+}
+
+fn print_receipt<P: Write>(
+    printer: &mut P,
+    number: u32,
+    readings: Option<&Readings>,
+    uptime_s: u32,
+) -> core::fmt::Result {
+    writeln!(printer, "Receipt #{}", number)?;
+    writeln!(
+        printer,
+        "Uptime: {}h {:02}m {:02}s",
+        uptime_s / 3600,
+        uptime_s / 60 % 60,
+        uptime_s % 60
+    )?;
+    writeln!(printer, "--------------------------------")?;
+    match readings {
+        Some(r) => {
+            writeln!(printer, "Temperature:          {:>6.1}C", r.current)?;
+            writeln!(printer, "Minimum:              {:>6.1}C", r.min)?;
+            writeln!(printer, "Maximum:              {:>6.1}C", r.max)
+        }
+        None => writeln!(printer, "Temperature:     sensor error"),
+    }
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("CSN-A2 thermal printer example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let tx = gpiob.pb6.into_alternate_push_pull(&mut gpiob.crl);
+    let rx = gpiob.pb7;
+    let serial = Serial::usart1(
+        dp.USART1,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(BAUD_RATE.bps()),
+        clocks,
+        &mut rcc.apb2,
+    );
+    let (tx, _rx) = serial.split();
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // TMP112 sensor
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x48]),
+    );
+
+    let button = gpiob.pb12.into_pull_down_input(&mut gpiob.crh);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let mut sensor = Tmp1x2::new(i2c, SlaveAddr::default());
+
+    // The printer needs some time to start up.
+    monotonic::wait_ms(500);
+    let mut printer = Printer::new(tx);
+    printer.init().unwrap();
+
+    let mut readings: Option<Readings> = None;
+    let mut receipts = 0;
+    let mut button_history = 0_u8;
+    let mut led_on = false;
+    let mut scheduler = Scheduler::new([5, 1000, 500]);
+    loop {
+        match scheduler.poll() {
+            Some(BUTTON) => {
+                // Print once the button has been pressed for 8 samples.
+                let previous = button_history;
+                button_history = (button_history << 1) | u8::from(button.is_high().unwrap());
+                if button_history != 0xFF || previous == 0xFF {
+                    continue;
+                }
+                receipts += 1;
+                log_info!("Printing receipt {}", receipts);
+                led.set_low().unwrap();
+                printer.align(Align::Center).unwrap();
+                printer.bitmap(4, 32, &LOGO).unwrap();
+                printer.size(2, 2).unwrap();
+                printer.bold(true).unwrap();
+                writeln!(printer, "Temperature").unwrap();
+                printer.size(1, 1).unwrap();
+                printer.bold(false).unwrap();
+                printer.align(Align::Left).unwrap();
+                let uptime_s = monotonic::millis() / 1000;
+                print_receipt(&mut printer, receipts, readings.as_ref(), uptime_s).unwrap();
+                printer.align(Align::Center).unwrap();
+                printer.inverse(true).unwrap();
+                writeln!(printer, " Thank you! ").unwrap();
+                printer.inverse(false).unwrap();
+                printer.feed(3).unwrap();
+            }
+            Some(MEASURE) => match sensor.read_temperature() {
+                Ok(temperature) => match readings.as_mut() {
+                    Some(r) => r.update(temperature),
+                    None => {
+                        readings = Some(Readings {
+                            current: temperature,
+                            min: temperature,
+                            max: temperature,
+                        })
+                    }
+                },
+                Err(_) => log_error!("Sensor error"),
+            },
+            Some(BLINK) => {
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                led_on = !led_on;
+                if led_on {
+                    led.set_high().unwrap();
+                } else {
+                    led.set_low().unwrap();
+                }
+            }
+            _ => (),
+        }
+    }
+}
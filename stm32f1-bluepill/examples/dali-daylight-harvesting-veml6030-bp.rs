@@ -0,0 +1,303 @@
+//! DALI lighting bus master keeping the illuminance of a room constant
+//! (daylight harvesting): the more daylight a VEML6030 ambient light
+//! sensor measures, the more a DALI LED driver is dimmed.
+//!
+//! Every `CONTROL_INTERVAL_MS` a PI controller calculates the light output
+//! needed for `TARGET_LUX` and, if the level changed, it is sent as a
+//! direct arc power forward frame to all control gear. Every
+//! `STATUS_INTERVAL_MS` the status of the control gear is queried and the
+//! answer is printed: lamp on, lamp failure, etc. The frames are encoded
+//! and decoded with the `dali` module of this crate:
+//! - TIM3 interrupts at 2400Hz and sets the output to the level of the next
+//!   half bit of the forward frame.
+//! - The time between the edges of the input is measured with the cycle
+//!   counter in an interrupt triggered on both edges and fed into the
+//!   `BackwardDecoder`, like in the nec-ir-remote-display-servo-bp example.
+//!
+//! The query is broadcast, so only connect one LED driver, otherwise the
+//! answers collide. The DALI bus needs a power supply (16V, at most 250mA)
+//! and an interface circuit like the MikroE DALI click: a high output pulls
+//! the bus low and the input is low while the bus is high.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> DALI interface <-> VEML6030
+//! GND  <-> GND            <-> GND
+//! 3.3V <-> VCC            <-> VCC
+//! PA0  <-> TX
+//! PA1  <-> RX
+//! PB8                     <-> SCL
+//! PB9                     <-> SDA
+//!          DA+, DA- <-> bus with power supply and LED driver
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example dali-daylight-harvesting-veml6030-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    dali::{
+        self, BackwardDecoder, BROADCAST_ARC_POWER, BROADCAST_COMMAND, FORWARD_HALF_BITS,
+        QUERY_STATUS, STATUS_GEAR_FAILURE, STATUS_LAMP_FAILURE, STATUS_LAMP_ON,
+        STATUS_POWER_FAILURE,
+    },
+    exti::setup_exti_pin,
+    i2c_speed, log_info, log_warn, panic_display as _,
+    pi::PiController,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::spsc::{Consumer, Producer, Queue};
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::{PA0, PA1},
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Floating, Input, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    timer::{CountDownTimer, Event, Timer},
+};
+use veml6030::{SlaveAddr, Veml6030};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const CONTROL_INTERVAL_MS: u32 = 200;
+const STATUS_INTERVAL_MS: u32 = 2000;
+const TARGET_LUX: f32 = 500.0;
+/// Light output at the start, in %
+const START_PERCENT: f32 = 50.0;
+
+/// Forward frame being transmitted
+pub struct Transmission {
+    levels: [bool; FORWARD_HALF_BITS],
+    index: usize,
+}
+
+impl Transmission {
+    fn is_done(&self) -> bool {
+        self.index >= FORWARD_HALF_BITS
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        tx: PA0<Output<PushPull>>,
+        rx: PA1<Input<Floating>>,
+        timer: CountDownTimer<pac::TIM3>,
+        transmission: Transmission,
+        decoder: BackwardDecoder,
+        last_edge: u32,
+        producer: Producer<'static, u8, 4>,
+        consumer: Consumer<'static, u8, 4>,
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        static mut QUEUE: Queue<u8, 4> = Queue::new();
+
+        rtt_init_print!();
+        log_info!("DALI daylight harvesting example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // A high output pulls the bus low, so low is idle.
+        let tx = gpioa
+            .pa0
+            .into_push_pull_output_with_state(&mut gpioa.crl, State::Low);
+        let mut rx = gpioa.pa1.into_floating_input(&mut gpioa.crl);
+        setup_exti_pin(&mut rx, &mut afio, &device.EXTI, Edge::RISING_FALLING);
+
+        let timer = Timer::tim3(device.TIM3, &clocks, &mut rcc.apb1).start_count_down(2400.hz());
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // VEML6030 sensor
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x10]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        let (producer, consumer) = QUEUE.split();
+        init::LateResources {
+            tx,
+            rx,
+            timer,
+            transmission: Transmission {
+                levels: [true; FORWARD_HALF_BITS],
+                index: FORWARD_HALF_BITS,
+            },
+            decoder: BackwardDecoder::new(),
+            last_edge: DWT::get_cycle_count(),
+            producer,
+            consumer,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = TIM3, priority = 2, resources = [tx, timer, transmission])]
+    fn half_bit(cx: half_bit::Context) {
+        let transmission = cx.resources.transmission;
+        cx.resources.timer.clear_update_interrupt_flag();
+        transmission.index += 1;
+        if transmission.is_done() {
+            cx.resources.timer.unlisten(Event::Update);
+        } else {
+            set_level(cx.resources.tx, transmission.levels[transmission.index]);
+        }
+    }
+
+    #[task(binds = EXTI1, priority = 2, resources = [rx, decoder, last_edge, producer])]
+    fn rx_edge(cx: rx_edge::Context) {
+        let now = DWT::get_cycle_count();
+        let rx = cx.resources.rx;
+        rx.clear_interrupt_pending_bit();
+        let duration_us = now.wrapping_sub(*cx.resources.last_edge) / SYSCLK_MHZ;
+        *cx.resources.last_edge = now;
+        // The input is low while the bus is high, so if it is high now, the
+        // bus was high during the period that just ended.
+        let bus_high = rx.is_high().unwrap();
+        if let Some(answer) = cx.resources.decoder.pulse(bus_high, duration_us) {
+            cx.resources.producer.enqueue(answer).ok();
+        }
+    }
+
+    #[idle(resources = [tx, timer, transmission, consumer, i2c, led])]
+    fn idle(cx: idle::Context) -> ! {
+        let i2c = cx.resources.i2c.take().unwrap();
+        let mut sensor = Veml6030::new(i2c, SlaveAddr::default());
+        sensor.enable().unwrap();
+        let consumer = cx.resources.consumer;
+        let led = cx.resources.led;
+
+        // Send a forward frame and wait until it has been sent.
+        let mut tx = cx.resources.tx;
+        let mut timer = cx.resources.timer;
+        let mut transmission = cx.resources.transmission;
+        let mut send = |address, data| {
+            tx.lock(|tx| {
+                timer.lock(|timer| {
+                    transmission.lock(|transmission| {
+                        transmission.levels = dali::forward(address, data);
+                        transmission.index = 0;
+                        set_level(tx, transmission.levels[0]);
+                        timer.start(2400.hz());
+                        timer.listen(Event::Update);
+                    })
+                })
+            });
+            while !transmission.lock(|transmission| transmission.is_done()) {}
+        };
+
+        let mut pi = PiController::new(0.02, 0.01).limits(-START_PERCENT, 100.0 - START_PERCENT);
+        let mut last_level = None;
+        let cycles_per_ms = SYSCLK_MHZ * 1000;
+        let mut last_control = DWT::get_cycle_count();
+        let mut last_status = last_control;
+        loop {
+            let now = DWT::get_cycle_count();
+            if now.wrapping_sub(last_control) >= CONTROL_INTERVAL_MS * cycles_per_ms {
+                last_control = now;
+                match sensor.read_lux() {
+                    Ok(lux) => {
+                        let dt_s = CONTROL_INTERVAL_MS as f32 / 1000.0;
+                        let percent = START_PERCENT + pi.update(TARGET_LUX, lux, dt_s);
+                        let level = dali::arc_level(percent);
+                        if last_level != Some(level) {
+                            log_info!("{:.0} lux: level {} ({:.1}%)", lux, level, percent);
+                            send(BROADCAST_ARC_POWER, level);
+                            last_level = Some(level);
+                        }
+                    }
+                    Err(e) => log_warn!("Sensor error: {:?}", e),
+                }
+            }
+
+            if now.wrapping_sub(last_status) >= STATUS_INTERVAL_MS * cycles_per_ms {
+                last_status = now;
+                send(BROADCAST_COMMAND, QUERY_STATUS);
+                // The input sees the forward frames too, which the decoder
+                // takes for answers. Drop them.
+                while consumer.dequeue().is_some() {}
+                let sent = DWT::get_cycle_count();
+                let answer = loop {
+                    if let Some(answer) = consumer.dequeue() {
+                        break Some(answer);
+                    }
+                    if DWT::get_cycle_count().wrapping_sub(sent)
+                        >= dali::ANSWER_TIMEOUT_US * SYSCLK_MHZ
+                    {
+                        break None;
+                    }
+                };
+                match answer {
+                    Some(status) => {
+                        led.set_low().unwrap();
+                        log_info!(
+                            "Status {:08b}: lamp {}, gear failure: {}, lamp failure: {}, power failure: {}",
+                            status,
+                            if status & STATUS_LAMP_ON != 0 { "on" } else { "off" },
+                            status & STATUS_GEAR_FAILURE != 0,
+                            status & STATUS_LAMP_FAILURE != 0,
+                            status & STATUS_POWER_FAILURE != 0
+                        );
+                    }
+                    None => {
+                        led.set_high().unwrap();
+                        log_warn!("No control gear answered");
+                    }
+                }
+            }
+        }
+    }
+};
+
+/// Set the bus level through the interface, which inverts it.
+fn set_level(tx: &mut PA0<Output<PushPull>>, high: bool) {
+    if high {
+        tx.set_low().unwrap();
+    } else {
+        tx.set_high().unwrap();
+    }
+}
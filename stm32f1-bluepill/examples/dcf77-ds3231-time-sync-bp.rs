@@ -0,0 +1,325 @@
+//! Decode the DCF77 radio time signal with a receiver module and use it to
+//! set a DS3231 real-time clock. The time of the RTC, the sync status and the
+//! last successful sync are shown on an SSD1306 OLED display.
+//!
+//! The receiver output marks the 100ms/200ms carrier reductions sent at the
+//! start of every second. The time between its edges is measured with the
+//! cycle counter in an interrupt triggered on both edges, since this version
+//! of the HAL does not support timer input capture. The durations are fed
+//! into the `Dcf77Decoder` from this crate, which assembles the bits of a
+//! minute and checks the parity bits.
+//!
+//! Since the parity bits do not catch every reception error, the RTC is
+//! only set when two consecutive minutes decode one minute apart. The RTC is
+//! set right at the start of the minute. Writing the seconds also restarts
+//! the internal second of the DS3231. The offset of the RTC found at every
+//! sync is printed so you can see how much it drifts.
+//!
+//! The RTC is set to the German local time (CET/CEST) as transmitted.
+//! Only DCF77 is decoded. WWVB uses a different time code.
+//!
+//! Most receiver modules output high during the carrier reductions. If
+//! yours is inverted, set `INVERTED_OUTPUT` to `true`. Keep the antenna away
+//! from the display and the board, and be patient: a sync takes at least
+//! two full minutes of clean reception.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> DCF77 module <-> DS3231 <-> Display
+//! GND  <-> GND          <-> GND    <-> GND
+//! 3.3V <-> VCC          <-> VCC    <-> VDD
+//! PA1  <-> TCO
+//! PB8                   <-> SCL    <-> SCL
+//! PB9                   <-> SDA    <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example dcf77-ds3231-time-sync-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    dcf77::{Dcf77Decoder, Dcf77Time, Error},
+    exti::setup_exti_pin,
+    i2c_speed, log_error, log_info,
+    panic_display::{self, Bus},
+};
+use ds323x::{Datelike, Ds323x, NaiveDate, NaiveDateTime, Rtcc, Timelike};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::{
+    spsc::{Consumer, Producer, Queue},
+    String,
+};
+use rtic::app;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::PA1,
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Floating, Input, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const INVERTED_OUTPUT: bool = false;
+
+/// Edges of the carrier reductions
+#[derive(Debug, Clone, Copy)]
+enum Pulse {
+    /// Start of a reduction with the time since the start of the previous one
+    Start { period_ms: u32 },
+    /// End of a reduction with its duration
+    End { duration_ms: u32 },
+}
+
+enum SyncStatus {
+    Receiving,
+    Failed(Error),
+    Unconfirmed,
+    Synced,
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        signal: PA1<Input<Floating>>,
+        #[init(0)]
+        last_start: u32,
+        #[init(0)]
+        last_edge: u32,
+        producer: Producer<'static, Pulse, 8>,
+        consumer: Consumer<'static, Pulse, 8>,
+        // Taken by the idle task, which creates the drivers.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        static mut QUEUE: Queue<Pulse, 8> = Queue::new();
+
+        rtt_init_print!();
+        log_info!("DCF77 DS3231 time sync example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let mut signal = gpioa.pa1.into_floating_input(&mut gpioa.crl);
+        setup_exti_pin(&mut signal, &mut afio, &device.EXTI, Edge::RISING_FALLING);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // DS3231 RTC and display
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x68, 0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        let (producer, consumer) = QUEUE.split();
+        init::LateResources {
+            signal,
+            producer,
+            consumer,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = EXTI1, priority = 2, resources = [signal, last_start, last_edge, producer])]
+    fn signal_edge(cx: signal_edge::Context) {
+        let now = DWT::get_cycle_count();
+        let signal = cx.resources.signal;
+        signal.clear_interrupt_pending_bit();
+        let reduction = signal.is_high().unwrap() != INVERTED_OUTPUT;
+        let pulse = if reduction {
+            let period_ms = now.wrapping_sub(*cx.resources.last_start) / (SYSCLK_MHZ * 1000);
+            *cx.resources.last_start = now;
+            Pulse::Start { period_ms }
+        } else {
+            let duration_ms = now.wrapping_sub(*cx.resources.last_edge) / (SYSCLK_MHZ * 1000);
+            Pulse::End { duration_ms }
+        };
+        *cx.resources.last_edge = now;
+        cx.resources.producer.enqueue(pulse).ok();
+    }
+
+    #[idle(resources = [consumer, i2c, led])]
+    fn idle(cx: idle::Context) -> ! {
+        let i2c = cx.resources.i2c.take().unwrap();
+        let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+        let interface = I2CDIBuilder::new().init(manager.acquire());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut rtc = Ds323x::new_ds3231(manager.acquire());
+
+        let mut decoder = Dcf77Decoder::new();
+        let mut status = SyncStatus::Receiving;
+        let mut previous: Option<NaiveDateTime> = None;
+        let mut last_sync: Option<(Dcf77Time, i64)> = None;
+        let mut lines: [String<32>; 5] = Default::default();
+        let mut last_redraw = DWT::get_cycle_count();
+        loop {
+            if let Some(pulse) = cx.resources.consumer.dequeue() {
+                match pulse {
+                    Pulse::Start { period_ms } => {
+                        cx.resources.led.set_low().unwrap();
+                        if let Some(result) = decoder.pulse_start(period_ms) {
+                            status = match result
+                                .and_then(|time| to_datetime(&time).map(|dt| (time, dt)))
+                            {
+                                Ok((time, datetime)) => {
+                                    log_info!("Received {}", datetime);
+                                    let confirmed = previous
+                                        .map(|p| {
+                                            datetime.signed_duration_since(p).num_seconds() == 60
+                                        })
+                                        .unwrap_or(false);
+                                    previous = Some(datetime);
+                                    if confirmed {
+                                        let offset = rtc
+                                            .get_datetime()
+                                            .map(|now| {
+                                                now.signed_duration_since(datetime).num_seconds()
+                                            })
+                                            .unwrap_or(0);
+                                        rtc.set_datetime(&datetime).unwrap();
+                                        log_info!("RTC set, it was off by {}s", offset);
+                                        last_sync = Some((time, offset));
+                                        SyncStatus::Synced
+                                    } else {
+                                        SyncStatus::Unconfirmed
+                                    }
+                                }
+                                Err(e) => {
+                                    log_error!("Error: {:?}", e);
+                                    previous = None;
+                                    SyncStatus::Failed(e)
+                                }
+                            };
+                        }
+                    }
+                    Pulse::End { duration_ms } => {
+                        cx.resources.led.set_high().unwrap();
+                        decoder.pulse_end(duration_ms);
+                    }
+                }
+            }
+            // Update the display twice a second.
+            if DWT::get_cycle_count().wrapping_sub(last_redraw) < SYSCLK_MHZ * 500_000 {
+                continue;
+            }
+            last_redraw = DWT::get_cycle_count();
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            match rtc.get_datetime() {
+                Ok(now) => write!(
+                    lines[0],
+                    "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+                    now.year(),
+                    now.month(),
+                    now.day(),
+                    now.hour(),
+                    now.minute(),
+                    now.second()
+                )
+                .unwrap(),
+                Err(_) => write!(lines[0], "RTC error").unwrap(),
+            }
+            match status {
+                SyncStatus::Receiving => write!(lines[1], "Receiving").unwrap(),
+                SyncStatus::Failed(e) => write!(lines[1], "Failed: {:?}", e).unwrap(),
+                SyncStatus::Unconfirmed => write!(lines[1], "Received, confirming").unwrap(),
+                SyncStatus::Synced => write!(lines[1], "Synced").unwrap(),
+            }
+            write!(lines[2], "Bits: {}/59", decoder.bits_received()).unwrap();
+            match last_sync {
+                Some((time, offset)) => {
+                    write!(
+                        lines[3],
+                        "Last: {:02}-{:02} {:02}:{:02} {}",
+                        time.month,
+                        time.day,
+                        time.hour,
+                        time.minute,
+                        if time.summer_time { "CEST" } else { "CET" }
+                    )
+                    .unwrap();
+                    write!(lines[4], "RTC was off {}s", offset).unwrap();
+                }
+                None => write!(lines[3], "Last: never").unwrap(),
+            }
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 12))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+        }
+    }
+};
+
+fn to_datetime(time: &Dcf77Time) -> Result<NaiveDateTime, Error> {
+    NaiveDate::from_ymd_opt(
+        i32::from(time.year),
+        u32::from(time.month),
+        u32::from(time.day),
+    )
+    .and_then(|date| date.and_hms_opt(u32::from(time.hour), u32::from(time.minute), 0))
+    .ok_or(Error::InvalidValue)
+}
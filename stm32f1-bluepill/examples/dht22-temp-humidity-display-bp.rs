@@ -0,0 +1,264 @@
+//! Measure the temperature and relative humidity with a DHT22 (AM2302) or
+//! DHT11 sensor and print them on an SSD1306 OLED display.
+//!
+//! These sensors use their own single-wire protocol:
+//! - The MCU pulls the line low for at least 1ms (18ms for the DHT11) and
+//!   releases it.
+//! - The sensor answers pulling the line low for 80µs and releasing it
+//!   for 80µs.
+//! - Then the sensor sends 40 bits. Each bit starts with the line low for
+//!   50µs followed by the line high for 26-28µs for a 0 or 70µs for a 1.
+//!
+//! The duration of the high pulses is measured with the DWT cycle counter,
+//! which also times the delays through `DwtDelay`.
+//! Interrupts are disabled during the 5ms of the transfer so that the
+//! timing is not disturbed.
+//! The last byte is a checksum of the other four. If it does not match or
+//! the sensor does not answer, the measurement is retried up to `RETRIES`
+//! times. The sensors must not be read more often than every 2 seconds
+//! (every second for the DHT11).
+//!
+//! Change `SENSOR` to `Sensor::Dht11` if you have a DHT11.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> DHT22 <-> Display
+//! GND  <-> GND   <-> GND
+//! 3.3V <-> VCC   <-> VDD
+//! PA8  <-> DATA
+//! PB8            <-> SCL
+//! PB9            <-> SDA
+//! ```
+//!
+//! The DATA line needs a 4.7K pull-up resistor to 3.3V. Many modules
+//! already have one.
+//!
+//! Run with:
+//! `cargo embed --example dht22-temp-humidity-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    clock,
+    delay::DwtDelay,
+    i2c_speed, log_info, log_warn,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::{
+    blocking::delay::DelayMs,
+    digital::v2::{InputPin, OutputPin},
+};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{pac, prelude::*};
+
+const SENSOR: Sensor = Sensor::Dht22;
+const RETRIES: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sensor {
+    Dht11,
+    Dht22,
+}
+
+#[derive(Debug)]
+enum Error {
+    /// The sensor did not answer or stopped sending
+    Timeout,
+    /// The checksum does not match
+    Checksum,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Measurement {
+    temperature: f32,
+    humidity: f32,
+}
+
+/// Wait while the line has the given level and return how many cycles that took.
+fn wait_while<P: InputPin>(pin: &P, high: bool, timeout_cycles: u32) -> Result<u32, Error> {
+    let start = DWT::get_cycle_count();
+    loop {
+        let elapsed = DWT::get_cycle_count().wrapping_sub(start);
+        if pin.is_high().unwrap_or(!high) != high {
+            return Ok(elapsed);
+        }
+        if elapsed > timeout_cycles {
+            return Err(Error::Timeout);
+        }
+    }
+}
+
+/// Do a complete transfer and return the 5 bytes received.
+fn read_raw<P>(pin: &mut P, delay: &mut DwtDelay, sensor: Sensor) -> Result<[u8; 5], Error>
+where
+    P: InputPin + OutputPin,
+{
+    // Start signal
+    pin.set_low().ok();
+    delay.delay_ms(if sensor == Sensor::Dht11 { 20_u8 } else { 2 });
+
+    let timeout = delay.us_to_cycles(100);
+    // A 1 is longer than 48µs, a 0 shorter.
+    let threshold = delay.us_to_cycles(48);
+    cortex_m::interrupt::free(|_| {
+        pin.set_high().ok();
+        // Wait for the answer of the sensor: high (released), low, high.
+        wait_while(pin, true, timeout)?;
+        wait_while(pin, false, timeout)?;
+        wait_while(pin, true, timeout)?;
+
+        let mut data = [0; 5];
+        for byte in data.iter_mut() {
+            for _ in 0..8 {
+                wait_while(pin, false, timeout)?;
+                let high = wait_while(pin, true, timeout)?;
+                *byte = (*byte << 1) | if high > threshold { 1 } else { 0 };
+            }
+        }
+        Ok(data)
+    })
+}
+
+fn decode(data: [u8; 5], sensor: Sensor) -> Result<Measurement, Error> {
+    let sum = data[..4].iter().fold(0_u8, |sum, b| sum.wrapping_add(*b));
+    if sum != data[4] {
+        return Err(Error::Checksum);
+    }
+    let measurement = match sensor {
+        Sensor::Dht11 => Measurement {
+            humidity: f32::from(data[0]) + f32::from(data[1]) / 10.0,
+            temperature: f32::from(data[2] & 0x7F) + f32::from(data[3]) / 10.0,
+        },
+        Sensor::Dht22 => {
+            let humidity = u16::from_be_bytes([data[0], data[1]]);
+            let temperature = u16::from_be_bytes([data[2] & 0x7F, data[3]]);
+            let sign = if data[2] & 0x80 != 0 { -1.0 } else { 1.0 };
+            Measurement {
+                humidity: f32::from(humidity) / 10.0,
+                temperature: sign * f32::from(temperature) / 10.0,
+            }
+        }
+    };
+    Ok(measurement)
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("DHT22 example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
+    clock::log_clocks(&clocks);
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+    let mut delay = DwtDelay::new(clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let mut dht = gpioa.pa8.into_open_drain_output(&mut gpioa.crh);
+    dht.set_high().unwrap();
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut lines: [heapless::String<32>; 3] = [
+        heapless::String::new(),
+        heapless::String::new(),
+        heapless::String::new(),
+    ];
+    let mut failures: u32 = 0;
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+
+        let mut result = Err(Error::Timeout);
+        for attempt in 0..RETRIES {
+            if attempt > 0 {
+                // Give the sensor time to recover before retrying.
+                delay.delay_ms(2000_u16);
+            }
+            result = read_raw(&mut dht, &mut delay, SENSOR).and_then(|data| decode(data, SENSOR));
+            dht.set_high().unwrap();
+            match &result {
+                Ok(_) => break,
+                Err(e) => {
+                    failures += 1;
+                    log_warn!("Attempt {} failed: {:?}", attempt + 1, e);
+                }
+            }
+        }
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        match result {
+            Ok(m) => {
+                log_info!(
+                    "Temperature: {:.1}C, humidity: {:.1}%",
+                    m.temperature,
+                    m.humidity
+                );
+                write!(lines[0], "Temperature: {:.1}C", m.temperature).unwrap();
+                write!(lines[1], "Humidity: {:.1}%", m.humidity).unwrap();
+            }
+            Err(e) => write!(lines[0], "Error: {:?}", e).unwrap(),
+        }
+        write!(lines[2], "Failed reads: {}", failures).unwrap();
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        delay.delay_ms(2000_u16);
+    }
+}
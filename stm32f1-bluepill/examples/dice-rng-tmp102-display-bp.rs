@@ -0,0 +1,198 @@
+//! Roll a dice when pressing a button and show it on an SSD1306 OLED display
+//! together with the temperature measured by a TMP102.
+//!
+//! The STM32F103 has no hardware random number generator so a software
+//! xorshift generator is used. It is seeded from the noise of the ADC reading
+//! a floating pin so that the results are different after each reset.
+//!
+//! The random numbers are also used to add some jitter to the moment the
+//! temperature is measured. When several devices share a bus or a radio
+//! channel and measure at exactly the same rate, their transmissions can
+//! collide over and over. Some random jitter avoids these patterns.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> TMP102 <-> Display <-> Button
+//! GND  <-> GND    <-> GND
+//! 3.3V <-> VCC    <-> VDD     <-> +
+//! PB8  <-> SCL    <-> SCL
+//! PB9  <-> SDA    <-> SDA
+//! PB12                        <-> -
+//! PA1 (leave unconnected)
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example dice-rng-tmp102-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+    rng::XorShift32,
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Circle, Rectangle},
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{adc, delay::Delay, pac, prelude::*};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+const MEASUREMENT_PERIOD_MS: u32 = 1000;
+const MAX_JITTER_MS: u32 = 200;
+const LOOP_PERIOD_MS: u32 = 10;
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Dice RNG example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.adcclk(2.mhz()).freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let button = gpiob.pb12.into_pull_down_input(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // TMP102 sensor and display
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x48, 0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    // Seed the random number generator with ADC noise.
+    let mut adc1 = adc::Adc::adc1(dp.ADC1, &mut rcc.apb2, clocks);
+    let mut floating = gpioa.pa1.into_analog(&mut gpioa.crl);
+    let noise = (0..32).map(|_| {
+        let sample: u16 = adc1.read(&mut floating).unwrap_or(0);
+        sample
+    });
+    let mut rng = XorShift32::from_noise(noise);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut tmp102 = Tmp1x2::new(manager.acquire(), SlaveAddr::default());
+
+    let mut dice = 1 + rng.below(6);
+    let mut temperature = tmp102.read_temperature().unwrap_or(-273.0);
+    let mut next_measurement_ms = next_measurement(0, &mut rng);
+    let mut now_ms: u32 = 0;
+    let mut was_pressed = false;
+    let mut buffer: heapless::String<32> = heapless::String::new();
+    loop {
+        let pressed = button.is_high().unwrap();
+        if pressed && !was_pressed {
+            dice = 1 + rng.below(6);
+            log_info!("Rolled a {}", dice);
+        }
+        was_pressed = pressed;
+
+        if now_ms >= next_measurement_ms {
+            temperature = tmp102.read_temperature().unwrap_or(-273.0);
+            next_measurement_ms = next_measurement(now_ms, &mut rng);
+            // Blink LED 0 on every measurement to check that everything is
+            // actually running. If the LED 0 does not blink, something went wrong.
+            led.set_low().unwrap();
+        } else {
+            led.set_high().unwrap();
+        }
+
+        buffer.clear();
+        write!(buffer, "Temp: {:.1}C", temperature).unwrap();
+        disp.clear();
+        Text::new(&buffer, Point::zero())
+            .into_styled(text_style)
+            .draw(&mut disp)
+            .unwrap();
+        draw_dice(&mut disp, dice, Point::new(44, 20)).unwrap();
+        disp.flush().unwrap();
+
+        delay.delay_ms(LOOP_PERIOD_MS);
+        now_ms = now_ms.wrapping_add(LOOP_PERIOD_MS);
+    }
+}
+
+/// Time of the next measurement including some random jitter.
+fn next_measurement(now_ms: u32, rng: &mut XorShift32) -> u32 {
+    now_ms + MEASUREMENT_PERIOD_MS - MAX_JITTER_MS / 2 + rng.below(MAX_JITTER_MS)
+}
+
+/// Draw a dice face with its top left corner at `top_left`.
+fn draw_dice<D: DrawTarget<BinaryColor>>(
+    display: &mut D,
+    value: u32,
+    top_left: Point,
+) -> Result<(), D::Error> {
+    const SIZE: i32 = 40;
+    const PIP_RADIUS: u32 = 4;
+    Rectangle::new(top_left, top_left + Point::new(SIZE, SIZE))
+        .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+        .draw(display)?;
+
+    // Pip positions in a 3x3 grid
+    let (left, center, right) = (10, 20, 30);
+    let pips: &[(i32, i32)] = match value {
+        1 => &[(center, center)],
+        2 => &[(left, left), (right, right)],
+        3 => &[(left, left), (center, center), (right, right)],
+        4 => &[(left, left), (right, left), (left, right), (right, right)],
+        5 => &[
+            (left, left),
+            (right, left),
+            (center, center),
+            (left, right),
+            (right, right),
+        ],
+        _ => &[
+            (left, left),
+            (right, left),
+            (left, center),
+            (right, center),
+            (left, right),
+            (right, right),
+        ],
+    };
+    for (x, y) in pips {
+        Circle::new(top_left + Point::new(*x, *y), PIP_RADIUS)
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(display)?;
+    }
+    Ok(())
+}
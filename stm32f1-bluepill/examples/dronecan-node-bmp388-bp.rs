@@ -0,0 +1,201 @@
+//! Minimal DroneCAN node broadcasting the static pressure and temperature
+//! measured with a BMP388 sensor, so the board can join the CAN bus of a
+//! drone or robot, e.g. with an ArduPilot or PX4 flight controller.
+//!
+//! The node uses the fixed node id `NODE_ID` and broadcasts:
+//! - `uavcan.protocol.NodeStatus` once a second, with its uptime and the
+//!   warning health while the sensor can't be read.
+//! - `uavcan.equipment.air_data.StaticPressure` and `StaticTemperature`
+//!   every `AIR_DATA_INTERVAL_MS` milliseconds.
+//!
+//! The messages are encoded with the `dronecan` module of this crate and
+//! sent with the `can` module at 1Mbit/s, the usual bit rate of DroneCAN
+//! networks. The node only sends, so no filter is set. It does not answer
+//! `GetNodeInfo` requests, so the DroneCAN GUI shows it without a name.
+//!
+//! For example with a USB CAN adapter on Linux:
+//! ```
+//! ip link set can0 up type can bitrate 1000000
+//! candump can0
+//! ```
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and CAN1.
+//!
+//! ```
+//! BP   <-> BMP388 <-> SN65HVD230
+//! GND  <-> GND    <-> GND
+//! 3.3V <-> VCC    <-> 3.3V
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! PA11            <-> CRX
+//! PA12            <-> CTX
+//!                     CANH, CANL <-> bus
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example dronecan-node-bmp388-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use bmp388::{PowerControl, BMP388};
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    can::{self, Can, Frame},
+    delay::DwtDelay,
+    dronecan::{
+        Health, Mode, Node, NodeStatus, StaticPressure, StaticTemperature, NODE_STATUS_ID,
+        PRIORITY_LOW, PRIORITY_MEDIUM, STATIC_PRESSURE_ID, STATIC_TEMPERATURE_ID,
+    },
+    i2c_speed, log_info, log_warn, monotonic, panic_display as _,
+};
+use embedded_hal::digital::v2::OutputPin;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{pac, prelude::*};
+
+const BITRATE: u32 = 1_000_000;
+const NODE_ID: u8 = 42;
+const BMP388_ADDRESS: u8 = 0x77;
+const STATUS_INTERVAL_MS: u32 = 1000;
+const AIR_DATA_INTERVAL_MS: u32 = 100;
+const SEND_TIMEOUT_MS: u32 = 2;
+/// Absolute accuracy of the BMP388 of ±50Pa, squared
+const PRESSURE_VARIANCE: f32 = 2500.0;
+/// Absolute accuracy of the BMP388 of ±0.5K, squared
+const TEMPERATURE_VARIANCE: f32 = 0.25;
+const CELSIUS_TO_KELVIN: f32 = 273.15;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+/// Send a frame once the previous one has left, or drop it after
+/// `SEND_TIMEOUT_MS`, e.g. when no other node acknowledges the frames.
+fn send(can: &mut Can, frame: Option<Frame>) {
+    let frame = match frame {
+        Some(frame) => frame,
+        None => return,
+    };
+    let start = monotonic::millis();
+    while can.transmit(&frame).is_err() {
+        if monotonic::millis().wrapping_sub(start) >= SEND_TIMEOUT_MS {
+            log_warn!("Bus busy or no other node");
+            return;
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("DroneCAN node example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    // Enable the CAN clock before handing the RCC over to the HAL.
+    dp.RCC.apb1enr.modify(|_, w| w.canen().set_bit());
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+    let mut delay = DwtDelay::new(clocks.sysclk().0);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[BMP388_ADDRESS]),
+    );
+    let mut sensor = BMP388::new(i2c, BMP388_ADDRESS, &mut delay).unwrap();
+    sensor.set_power_control(PowerControl::normal()).unwrap();
+
+    // CAN1 on its default pins
+    let _can_rx = gpioa.pa11.into_floating_input(&mut gpioa.crh);
+    let _can_tx = gpioa.pa12.into_alternate_push_pull(&mut gpioa.crh);
+    let timing = can::bit_timing(clocks.pclk1().0, BITRATE).unwrap();
+    let mut can = Can::new(dp.CAN1, timing).unwrap();
+    let mut node = Node::new(NODE_ID).unwrap();
+    log_info!("Node {} joined the bus", node.id());
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut health = Health::Ok;
+    let mut last_status = monotonic::millis().wrapping_sub(STATUS_INTERVAL_MS);
+    let mut last_air_data = monotonic::millis();
+    loop {
+        let now = monotonic::millis();
+        if now.wrapping_sub(last_status) >= STATUS_INTERVAL_MS {
+            last_status = last_status.wrapping_add(STATUS_INTERVAL_MS);
+            let status = NodeStatus {
+                uptime_s: now / 1000,
+                health,
+                mode: Mode::Operational,
+                vendor_status: 0,
+            };
+            send(
+                &mut can,
+                node.broadcast(PRIORITY_LOW, NODE_STATUS_ID, &status.encode()),
+            );
+        }
+
+        if now.wrapping_sub(last_air_data) >= AIR_DATA_INTERVAL_MS {
+            last_air_data = last_air_data.wrapping_add(AIR_DATA_INTERVAL_MS);
+            match sensor.sensor_values() {
+                Ok(values) => {
+                    health = Health::Ok;
+                    let pressure = StaticPressure {
+                        pressure: values.pressure as f32,
+                        variance: PRESSURE_VARIANCE,
+                    };
+                    let temperature = StaticTemperature {
+                        temperature: values.temperature as f32 + CELSIUS_TO_KELVIN,
+                        variance: TEMPERATURE_VARIANCE,
+                    };
+                    send(
+                        &mut can,
+                        node.broadcast(PRIORITY_MEDIUM, STATIC_PRESSURE_ID, &pressure.encode()),
+                    );
+                    send(
+                        &mut can,
+                        node.broadcast(
+                            PRIORITY_MEDIUM,
+                            STATIC_TEMPERATURE_ID,
+                            &temperature.encode(),
+                        ),
+                    );
+                }
+                Err(e) => {
+                    health = Health::Warning;
+                    log_warn!("Sensor error: {:?}", e);
+                }
+            }
+        }
+
+        if can.bus_off() {
+            led.set_high().unwrap();
+        } else {
+            // LED 0 is on while the node is on the bus.
+            led.set_low().unwrap();
+        }
+    }
+}
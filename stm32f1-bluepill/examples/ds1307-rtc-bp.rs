@@ -1,6 +1,9 @@
 //! Stores the date and time on a DS1307 real-time clock (RTC).
 //! Then reads the date and time repeatedly blink LED 0 for 30 seconds.
 //!
+//! The date and time are fixed in the code. To set an RTC to the actual
+//! time, have a look at the sntp-w5500-ds3231-time-sync-bp example.
+//!
 //! Introductory blog post here:
 //! https://blog.eldruin.com/ds1307-real-time-clock-rtc-driver-in-rust/
 //!
@@ -22,10 +25,10 @@
 #![no_main]
 
 use cortex_m_rt::entry;
+use driver_examples_bluepill::{log_info, panic_display as _};
 use ds1307::{Ds1307, NaiveDate, Rtcc};
 use embedded_hal::digital::v2::OutputPin;
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use stm32f1xx_hal::{
     delay::Delay,
     i2c::{BlockingI2c, DutyCycle, Mode},
@@ -36,7 +39,7 @@ use stm32f1xx_hal::{
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("DS1307 example");
+    log_info!("DS1307 example");
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
 
@@ -0,0 +1,504 @@
+//! Signal between two Bluepills over an audio link with DTMF (touch tone)
+//! keys and a CTCSS (sub-audible) tone. Both boards run this example, the
+//! output of one is connected to the input of the other, e.g. through a
+//! pair of radios.
+//!
+//! Transmit:
+//! - The Bluepill has no DAC, so the DTMF tones are generated with PWM:
+//!   TIM4 runs at 281kHz with 8 bits of duty cycle and TIM2 interrupts at
+//!   `SAMPLE_RATE_HZ` to set the duty cycle to the sum of the two sines of
+//!   the key, looked up in a table. An RC low-pass filter removes the PWM
+//!   frequency.
+//! - The AD9833 generates the CTCSS tone of `CTCSS_HZ`, which is mixed
+//!   into the output at a lower level.
+//!
+//! Receive:
+//! - ADC1 samples the input continuously with the `adc_stream` module. The
+//!   DMA interrupt averages 6 samples each, which gives about 7.9kHz, and
+//!   removes the DC offset.
+//! - The `DtmfDecoder` from this crate finds the keys with the fixed point
+//!   `Goertzel` filters of the `goertzel` module.
+//! - Another `Goertzel` filter measures the CTCSS tone in blocks of half a
+//!   second on the samples averaged again 16 times.
+//!
+//! Connect with a serial terminal at 115200 baud and send one command per
+//! line:
+//! - `SEND 123#`: send DTMF keys (`0` - `9`, `A` - `D`, `*`, `#`).
+//! - `CTCSS ON`, `CTCSS OFF`: switch the CTCSS tone on or off.
+//!
+//! Every command is answered with a line starting with `OK` or `ERR`. Keys
+//! received are printed as `RX 5` and changes of the CTCSS tone as
+//! `RX CTCSS ON` and `RX CTCSS OFF`.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1 and USART1.
+//!
+//! ```
+//! BP   <-> AD9833 <-> USB-serial adapter
+//! GND  <-> VSS    <-> GND
+//! 3.3V <-> VDD
+//! PA4  <-> FSYNC
+//! PA5  <-> CLK
+//! PA7  <-> DAT
+//! PA9             <-> RX
+//! PA10            <-> TX
+//! PB6  <-> 1K <-> 47nF to GND <-> 10K <-> audio out
+//!          OUT <-> 100K <-> audio out
+//! PA0  <-> audio in, biased to 1.65V with 2 x 10K and coupled with 1uF
+//! ```
+//!
+//! For a quick test, connect the audio output of one board to the audio
+//! input of the other through a 1uF capacitor.
+//!
+//! Run with:
+//! `cargo embed --example dtmf-ctcss-pwm-ad9833-adc-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use ad983x::{ic::Ad9833, Ad983x, FrequencyRegister, MODE as SPI_MODE};
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    adc_stream::{sample_rate_hz, AdcStream},
+    bootloader::relocate_vector_table,
+    dtmf::{self, DtmfDecoder},
+    goertzel::Goertzel,
+    log_info, panic_display as _,
+    uart::{LineBuffer, RxBuffer, RxReader, TxBuffer, TxWriter, UartRx, UartTx},
+};
+use heapless::{
+    spsc::{Consumer, Producer, Queue},
+    Vec,
+};
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    adc::SampleTime,
+    gpio::{
+        gpioa::{PA4, PA5, PA6, PA7},
+        gpiob::PB6,
+        Alternate, Floating, Input, Output, PushPull, State,
+    },
+    pac,
+    prelude::*,
+    pwm::{Channel, Pwm as TimerPwm, C1},
+    serial::{Config, Rx, Serial, Tx},
+    spi::{Spi, Spi1NoRemap},
+    timer::{CountDownTimer, Event, Tim4NoRemap, Timer},
+};
+
+type Synth = Ad983x<
+    Spi<
+        pac::SPI1,
+        Spi1NoRemap,
+        (
+            PA5<Alternate<PushPull>>,
+            PA6<Input<Floating>>,
+            PA7<Alternate<PushPull>>,
+        ),
+    >,
+    PA4<Output<PushPull>>,
+    Ad9833,
+>;
+type AudioPwm = TimerPwm<pac::TIM4, Tim4NoRemap, C1, PB6<Alternate<PushPull>>>;
+
+const SYSCLK_MHZ: u32 = 72;
+const ADC_MHZ: u32 = 12;
+const BAUD_RATE: u32 = 115_200;
+const RX_BUFFER: usize = 64;
+const TX_BUFFER: usize = 256;
+const LINE_LEN: usize = 40;
+const BUFFER: usize = 1024;
+/// Rate of the PWM output samples
+const SAMPLE_RATE_HZ: u32 = 16_000;
+const TONE_MS: u32 = 100;
+const PAUSE_MS: u32 = 100;
+/// ADC samples averaged for the DTMF decoder
+const DECIMATION: u32 = 6;
+/// DTMF samples averaged for the CTCSS filter
+const CTCSS_DECIMATION: u32 = 16;
+const CTCSS_HZ: f32 = 100.0;
+/// Lowest amplitude of the CTCSS tone, in ADC steps
+const CTCSS_MIN_AMPLITUDE: u64 = 20;
+const MCLK_HZ: f32 = 25_000_000.0;
+
+/// Value of a frequency register
+fn frequency_word(frequency_hz: f32) -> u32 {
+    (frequency_hz * (1 << 28) as f32 / MCLK_HZ + 0.5) as u32
+}
+
+/// Phase increment per output sample of a frequency
+fn phase_step(frequency_hz: f32) -> u32 {
+    (frequency_hz / SAMPLE_RATE_HZ as f32 * 4_294_967_296.0) as u32
+}
+
+/// DTMF output through PWM
+pub struct ToneGenerator {
+    pwm: AudioPwm,
+    /// One period of a sine with a quarter of the PWM range as amplitude
+    sine: [i16; 256],
+    phases: [u32; 2],
+    steps: [u32; 2],
+    tone_samples: u32,
+    pause_samples: u32,
+}
+
+impl ToneGenerator {
+    fn is_idle(&self) -> bool {
+        self.tone_samples == 0 && self.pause_samples == 0
+    }
+
+    fn start(&mut self, tones: (f32, f32)) {
+        self.phases = [0, 0];
+        self.steps = [phase_step(tones.0), phase_step(tones.1)];
+        self.tone_samples = TONE_MS * SAMPLE_RATE_HZ / 1000;
+        self.pause_samples = PAUSE_MS * SAMPLE_RATE_HZ / 1000;
+    }
+
+    /// Output the next sample.
+    fn next_sample(&mut self) {
+        let middle = (self.pwm.get_max_duty() / 2) as i16;
+        let value = if self.tone_samples > 0 {
+            self.tone_samples -= 1;
+            let mut value = middle;
+            for (phase, step) in self.phases.iter_mut().zip(self.steps.iter()) {
+                value += self.sine[(*phase >> 24) as usize];
+                *phase = phase.wrapping_add(*step);
+            }
+            value
+        } else {
+            self.pause_samples = self.pause_samples.saturating_sub(1);
+            middle
+        };
+        self.pwm.set_duty(Channel::C1, value as u16);
+    }
+}
+
+/// Decoding of the ADC samples
+pub struct Receiver {
+    dtmf: DtmfDecoder,
+    ctcss: Goertzel,
+    ctcss_len: u32,
+    /// Sums and counts of the samples being averaged
+    sum: u32,
+    count: u32,
+    ctcss_sum: i32,
+    ctcss_count: u32,
+    ctcss_samples: u32,
+    /// Average of the samples in 1/16 ADC steps
+    offset: i32,
+}
+
+/// What the receiver found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Received {
+    Key(u8),
+    Ctcss(bool),
+}
+
+impl Receiver {
+    fn add(&mut self, samples: &[u16], producer: &mut Producer<'static, Received, 16>) {
+        for sample in samples {
+            self.sum += u32::from(*sample);
+            self.count += 1;
+            if self.count < DECIMATION {
+                continue;
+            }
+            let average = (self.sum * 16 / DECIMATION) as i32;
+            self.sum = 0;
+            self.count = 0;
+            // Follow the DC offset slowly.
+            self.offset += (average - self.offset) / 256;
+            let sample = ((average - self.offset) / 16) as i16;
+            if let Some(key) = self.dtmf.push(sample) {
+                producer.enqueue(Received::Key(key)).ok();
+            }
+
+            self.ctcss_sum += i32::from(sample);
+            self.ctcss_count += 1;
+            if self.ctcss_count < CTCSS_DECIMATION {
+                continue;
+            }
+            self.ctcss
+                .push((self.ctcss_sum / CTCSS_DECIMATION as i32) as i16);
+            self.ctcss_sum = 0;
+            self.ctcss_count = 0;
+            self.ctcss_samples += 1;
+            if self.ctcss_samples == self.ctcss_len {
+                // The power of a sine of amplitude A is about (A * n / 2)².
+                let min = CTCSS_MIN_AMPLITUDE * u64::from(self.ctcss_len) / 2;
+                let present = self.ctcss.power() >= min * min;
+                producer.enqueue(Received::Ctcss(present)).ok();
+                self.ctcss.reset();
+                self.ctcss_samples = 0;
+            }
+        }
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        synth: Synth,
+        generator: ToneGenerator,
+        sample_timer: CountDownTimer<pac::TIM2>,
+        stream: AdcStream<BUFFER>,
+        receiver: Receiver,
+        producer: Producer<'static, Received, 16>,
+        consumer: Consumer<'static, Received, 16>,
+        uart_rx: UartRx<Rx<pac::USART1>, RX_BUFFER>,
+        uart_tx: UartTx<Tx<pac::USART1>, TX_BUFFER>,
+        reader: RxReader<RX_BUFFER>,
+        writer: TxWriter<TX_BUFFER>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        static mut QUEUE: Queue<Received, 16> = Queue::new();
+
+        rtt_init_print!();
+        log_info!("DTMF/CTCSS example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the ADC1 clock before handing the RCC over to the HAL.
+        device.RCC.apb2enr.modify(|_, w| w.adc1en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .adcclk(ADC_MHZ.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // SPI1
+        let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+        let miso = gpioa.pa6;
+        let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+        let cs = gpioa
+            .pa4
+            .into_push_pull_output_with_state(&mut gpioa.crl, State::High);
+
+        let spi = Spi::spi1(
+            device.SPI1,
+            (sck, miso, mosi),
+            &mut afio.mapr,
+            SPI_MODE,
+            1_u32.mhz(),
+            clocks,
+            &mut rcc.apb2,
+        );
+
+        // Held in reset until the CTCSS tone is switched on
+        let mut synth = Ad983x::new_ad9833(spi, cs);
+        synth.reset().unwrap();
+        synth
+            .set_frequency(FrequencyRegister::F0, frequency_word(CTCSS_HZ))
+            .unwrap();
+        synth.select_frequency(FrequencyRegister::F0).unwrap();
+
+        // 72MHz / 256
+        let audio_pin = gpiob.pb6.into_alternate_push_pull(&mut gpiob.crl);
+        let mut pwm = Timer::tim4(device.TIM4, &clocks, &mut rcc.apb1).pwm::<Tim4NoRemap, _, _, _>(
+            audio_pin,
+            &mut afio.mapr,
+            281_250.hz(),
+        );
+        pwm.set_duty(Channel::C1, pwm.get_max_duty() / 2);
+        pwm.enable(Channel::C1);
+        let amplitude = f32::from(pwm.get_max_duty()) / 4.0;
+        let mut sine = [0; 256];
+        for (i, value) in sine.iter_mut().enumerate() {
+            let angle = i as f32 * 2.0 * core::f32::consts::PI / 256.0;
+            *value = (libm::sinf(angle) * amplitude) as i16;
+        }
+        let mut sample_timer =
+            Timer::tim2(device.TIM2, &clocks, &mut rcc.apb1).start_count_down(SAMPLE_RATE_HZ.hz());
+        sample_timer.listen(Event::Update);
+
+        let _audio_in = gpioa.pa0.into_analog(&mut gpioa.crl);
+        let dma_ch1 = device.DMA1.split(&mut rcc.ahb).1;
+        let buffer = singleton!(: [[u16; BUFFER]; 2] = [[0; BUFFER]; 2]).unwrap();
+        let mut stream = AdcStream::start(device.ADC1, dma_ch1, &[0], SampleTime::T_239, buffer);
+        stream.listen();
+        let rate_hz = sample_rate_hz(clocks.adcclk().0, SampleTime::T_239, 1) / DECIMATION as f32;
+        log_info!("{:.0} samples/s", rate_hz);
+        let ctcss_rate_hz = rate_hz / CTCSS_DECIMATION as f32;
+        // Half a second
+        let ctcss_len = (ctcss_rate_hz / 2.0) as u32;
+
+        let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+        let rx = gpioa.pa10;
+        let serial = Serial::usart1(
+            device.USART1,
+            (tx, rx),
+            &mut afio.mapr,
+            Config::default().baudrate(BAUD_RATE.bps()),
+            clocks,
+            &mut rcc.apb2,
+        );
+        let (tx, mut rx) = serial.split();
+        rx.listen();
+        let (uart_rx, reader) = singleton!(: RxBuffer<RX_BUFFER> = RxBuffer::new())
+            .unwrap()
+            .split(rx);
+        let (uart_tx, writer) = singleton!(: TxBuffer<TX_BUFFER> = TxBuffer::new())
+            .unwrap()
+            .split(tx, || rtic::pend(pac::Interrupt::USART1));
+
+        let (producer, consumer) = QUEUE.split();
+        init::LateResources {
+            synth,
+            generator: ToneGenerator {
+                pwm,
+                sine,
+                phases: [0, 0],
+                steps: [0, 0],
+                tone_samples: 0,
+                pause_samples: 0,
+            },
+            sample_timer,
+            stream,
+            receiver: Receiver {
+                dtmf: DtmfDecoder::new(rate_hz),
+                ctcss: Goertzel::new(CTCSS_HZ, ctcss_rate_hz, ctcss_len),
+                ctcss_len,
+                sum: 0,
+                count: 0,
+                ctcss_sum: 0,
+                ctcss_count: 0,
+                ctcss_samples: 0,
+                offset: 2048 * 16,
+            },
+            producer,
+            consumer,
+            uart_rx,
+            uart_tx,
+            reader,
+            writer,
+        }
+    }
+
+    /// Output the next DTMF sample. Highest priority, so that the samples
+    /// come at a steady rate.
+    #[task(binds = TIM2, priority = 3, resources = [generator, sample_timer])]
+    fn tim2(cx: tim2::Context) {
+        cx.resources.sample_timer.clear_update_interrupt_flag();
+        cx.resources.generator.next_sample();
+    }
+
+    #[task(binds = DMA1_CHANNEL1, priority = 2, resources = [stream, receiver, producer])]
+    fn dma1_channel1(cx: dma1_channel1::Context) {
+        let receiver = cx.resources.receiver;
+        let producer = cx.resources.producer;
+        cx.resources
+            .stream
+            .poll(|samples| receiver.add(samples, producer));
+    }
+
+    /// Move the received bytes into the buffer and send the waiting ones.
+    #[task(binds = USART1, priority = 2, resources = [uart_rx, uart_tx])]
+    fn usart1(cx: usart1::Context) {
+        cx.resources.uart_rx.on_interrupt();
+        let uart_tx = cx.resources.uart_tx;
+        if uart_tx.on_interrupt() {
+            uart_tx.tx().listen();
+        } else {
+            uart_tx.tx().unlisten();
+        }
+    }
+
+    #[idle(resources = [synth, generator, stream, consumer, reader, writer])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let mut line_buffer: LineBuffer<LINE_LEN> = LineBuffer::new();
+        let mut keys: Vec<u8, LINE_LEN> = Vec::new();
+        let mut next_key = 0;
+        let mut ctcss_present = false;
+        let mut last_report = DWT::get_cycle_count();
+        writeln!(cx.resources.writer, "OK ready").unwrap();
+        loop {
+            for line in cx.resources.reader.lines(&mut line_buffer) {
+                log_info!("Command: {}", line);
+                let tx = &mut cx.resources.writer;
+                let mut words = line.split_whitespace();
+                let command = words.next().unwrap_or("");
+                let argument = words.next();
+                if command.eq_ignore_ascii_case("SEND") {
+                    match argument {
+                        Some(text) if text.bytes().all(|key| dtmf::tones(key).is_some()) => {
+                            keys.clear();
+                            keys.extend_from_slice(text.as_bytes()).ok();
+                            next_key = 0;
+                            writeln!(tx, "OK").unwrap();
+                        }
+                        Some(_) => writeln!(tx, "ERR InvalidKey").unwrap(),
+                        None => writeln!(tx, "ERR MissingArgument").unwrap(),
+                    }
+                } else if command.eq_ignore_ascii_case("CTCSS") {
+                    match argument {
+                        Some(on) if on.eq_ignore_ascii_case("ON") => {
+                            cx.resources.synth.lock(|synth| synth.enable().unwrap());
+                            writeln!(tx, "OK").unwrap();
+                        }
+                        Some(off) if off.eq_ignore_ascii_case("OFF") => {
+                            cx.resources.synth.lock(|synth| synth.reset().unwrap());
+                            writeln!(tx, "OK").unwrap();
+                        }
+                        _ => writeln!(tx, "ERR MissingArgument").unwrap(),
+                    }
+                } else {
+                    writeln!(tx, "ERR UnknownCommand").unwrap();
+                }
+            }
+
+            // Send the next key once the last one and its pause are done.
+            if let Some(key) = keys.get(next_key) {
+                let started = cx.resources.generator.lock(|generator| {
+                    if generator.is_idle() {
+                        generator.start(dtmf::tones(*key).unwrap());
+                        true
+                    } else {
+                        false
+                    }
+                });
+                if started {
+                    next_key += 1;
+                }
+            }
+
+            while let Some(received) = cx.resources.consumer.dequeue() {
+                log_info!("{:?}", received);
+                match received {
+                    Received::Key(key) => {
+                        writeln!(cx.resources.writer, "RX {}", char::from(key)).unwrap()
+                    }
+                    Received::Ctcss(present) => {
+                        // Only report the changes.
+                        let state = if present { "ON" } else { "OFF" };
+                        if core::mem::replace(&mut ctcss_present, present) != present {
+                            writeln!(cx.resources.writer, "RX CTCSS {}", state).unwrap();
+                        }
+                    }
+                }
+            }
+
+            if DWT::get_cycle_count().wrapping_sub(last_report) >= SYSCLK_MHZ * 10_000_000 {
+                last_report = DWT::get_cycle_count();
+                let overruns = cx.resources.stream.lock(|stream| stream.overruns());
+                log_info!("{} overruns", overruns);
+            }
+        }
+    }
+};
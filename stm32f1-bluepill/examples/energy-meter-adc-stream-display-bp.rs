@@ -0,0 +1,285 @@
+//! Measure the mains voltage and the current of a load with a ZMPT101B
+//! voltage transformer module and an SCT-013-030 current clamp, and show the
+//! RMS voltage and current, the real and apparent power, the power factor
+//! and the energy on an SSD1306 OLED display.
+//!
+//! ADC1 converts PA0 (voltage) and PA1 (current) one after the other,
+//! continuously, with the `adc_stream` module: about 17.8k samples per
+//! second of each. The DMA interrupt adds up each half of the buffer, so
+//! no samples are lost while the display is updated. Once per second:
+//! - the RMS values are the standard deviations of the samples, which
+//!   removes the DC bias of the inputs,
+//! - the real power is the mean of voltage times current minus the product
+//!   of the means, so a phase shift between them is taken into account,
+//! - the apparent power is the RMS voltage times the RMS current.
+//!
+//! The two channels are sampled 28us apart, about 0.5 degrees at 50Hz,
+//! which is neglected.
+//!
+//! DANGER: The ZMPT101B is connected to the mains. Do not touch anything
+//! while it is connected and let someone qualified do the mains wiring.
+//! The current clamp goes around one of the wires of the load only and
+//! needs no connection to the mains.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> ZMPT101B <-> SCT-013-030 <-> Display
+//! GND  <-> GND                      <-> GND
+//! 3.3V <-> VCC                      <-> VDD
+//! PA0  <-> OUT
+//! PA1               <-> Tip
+//!                       Sleeve: 1.65V bias
+//! PB8                               <-> SCL
+//! PB9                               <-> SDA
+//! ```
+//!
+//! The SCT-013-030 has a built-in burden resistor and gives 1V RMS at 30A.
+//! Its sleeve goes to the middle of two 10K resistors between 3.3V and GND,
+//! with a 10uF capacitor to GND, so that PA1 swings around 1.65V. Set the
+//! trimmer of the ZMPT101B so that the output stays within 0-3.3V, then
+//! adjust `VOLTS_PER_STEP` until the voltage matches a multimeter.
+//!
+//! Run with:
+//! `cargo embed --example energy-meter-adc-stream-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    adc_stream::{sample_rate_hz, AdcStream},
+    bootloader::relocate_vector_table,
+    convert::{ADC_MAX, VREF_MV},
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    adc::SampleTime,
+    gpio::{
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const ADC_MHZ: u32 = 9;
+/// Samples of both channels in each half of the buffer
+const BUFFER: usize = 512;
+const DISPLAY_MS: u32 = 1000;
+
+/// Mains volts per ADC step, depends on the trimmer of the ZMPT101B
+const VOLTS_PER_STEP: f64 = 0.36;
+/// Amperes per ADC step: 30A per volt
+const AMPS_PER_STEP: f64 = 30.0 * VREF_MV as f64 / 1000.0 / ADC_MAX as f64;
+
+/// Sums of the samples since the last display update
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sums {
+    count: u32,
+    v: i64,
+    i: i64,
+    vv: i64,
+    ii: i64,
+    vi: i64,
+}
+
+impl Sums {
+    fn add(&mut self, samples: &[u16]) {
+        for pair in samples.chunks_exact(2) {
+            let (v, i) = (i64::from(pair[0]), i64::from(pair[1]));
+            self.count += 1;
+            self.v += v;
+            self.i += i;
+            self.vv += v * v;
+            self.ii += i * i;
+            self.vi += v * i;
+        }
+    }
+
+    /// RMS voltage, RMS current and real power, in ADC steps
+    fn evaluate(&self) -> (f64, f64, f64) {
+        let n = f64::from(self.count.max(1));
+        let (v, i) = (self.v as f64 / n, self.i as f64 / n);
+        let v_rms = libm::sqrt((self.vv as f64 / n - v * v).max(0.0));
+        let i_rms = libm::sqrt((self.ii as f64 / n - i * i).max(0.0));
+        (v_rms, i_rms, self.vi as f64 / n - v * i)
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        stream: AdcStream<BUFFER>,
+        sums: Sums,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Energy meter example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the ADC1 clock before handing the RCC over to the HAL.
+        device.RCC.apb2enr.modify(|_, w| w.adc1en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .adcclk(ADC_MHZ.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let _voltage = gpioa.pa0.into_analog(&mut gpioa.crl);
+        let _current = gpioa.pa1.into_analog(&mut gpioa.crl);
+        let dma_ch1 = device.DMA1.split(&mut rcc.ahb).1;
+        let buffer = singleton!(: [[u16; BUFFER]; 2] = [[0; BUFFER]; 2]).unwrap();
+        let mut stream = AdcStream::start(device.ADC1, dma_ch1, &[0, 1], SampleTime::T_239, buffer);
+        stream.listen();
+        log_info!(
+            "{:.0} samples/s per channel",
+            sample_rate_hz(clocks.adcclk().0, SampleTime::T_239, 2)
+        );
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            stream,
+            sums: Sums::default(),
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = DMA1_CHANNEL1, priority = 2, resources = [stream, sums])]
+    fn dma1_channel1(cx: dma1_channel1::Context) {
+        let sums = cx.resources.sums;
+        cx.resources.stream.poll(|samples| sums.add(samples));
+    }
+
+    #[idle(resources = [stream, sums, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let led = cx.resources.led;
+        let interface = I2CDIBuilder::new().init(cx.resources.i2c.take().unwrap());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut last_update = DWT::get_cycle_count();
+        let mut energy_wh = 0.0;
+        let mut lines: [String<32>; 5] = Default::default();
+        let mut led_on = false;
+        loop {
+            if DWT::get_cycle_count().wrapping_sub(last_update) < DISPLAY_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_update = last_update.wrapping_add(DISPLAY_MS * 1000 * SYSCLK_MHZ);
+
+            let sums = cx.resources.sums.lock(core::mem::take);
+            let overruns = cx.resources.stream.lock(|stream| stream.overruns());
+            let (v_rms, i_rms, power) = sums.evaluate();
+            let voltage = v_rms * VOLTS_PER_STEP;
+            let current = i_rms * AMPS_PER_STEP;
+            let real_power = power * VOLTS_PER_STEP * AMPS_PER_STEP;
+            let apparent_power = voltage * current;
+            let power_factor = if apparent_power > 0.0 {
+                real_power / apparent_power
+            } else {
+                0.0
+            };
+            energy_wh += real_power * f64::from(DISPLAY_MS) / 3_600_000.0;
+            log_info!(
+                "{:.1}V {:.3}A {:.1}W {:.1}VA PF {:.2} {:.3}Wh, {} samples, {} overruns",
+                voltage,
+                current,
+                real_power,
+                apparent_power,
+                power_factor,
+                energy_wh,
+                sums.count,
+                overruns
+            );
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            write!(lines[0], "{:.1}V  {:.3}A", voltage, current).unwrap();
+            write!(lines[1], "Power: {:.1}W", real_power).unwrap();
+            write!(lines[2], "Apparent: {:.1}VA", apparent_power).unwrap();
+            write!(lines[3], "Power factor: {:.2}", power_factor).unwrap();
+            write!(lines[4], "Energy: {:.3}Wh", energy_wh).unwrap();
+
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 12))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            led_on = !led_on;
+            if led_on {
+                led.set_low().unwrap();
+            } else {
+                led.set_high().unwrap();
+            }
+        }
+    }
+};
@@ -0,0 +1,284 @@
+//! Show the live spectrum of a microphone or vibration sensor as bars on
+//! an SSD1306 OLED display.
+//!
+//! ADC1 samples the input continuously at about 47.6kHz with the
+//! `adc_stream` module. The DMA interrupt averages 4 samples each, which
+//! gives about 11.9kHz, and collects blocks of `N` samples. The idle task
+//! removes the DC offset of the block, shifts it up to the Q15 range,
+//! applies a Hann window and calculates a 256-point FFT with the fixed
+//! point `fft` module of this crate. The 128 bins up to half the sampling
+//! rate, about 46Hz wide each, are shown as bars with a height in decibels,
+//! the strongest bin in the first line.
+//!
+//! Use a microphone amplifier like the MAX4466 for audio or an analog
+//! accelerometer like the ADXL335 for vibrations.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> MAX4466 <-> Display
+//! GND  <-> GND     <-> GND
+//! 3.3V <-> VCC     <-> VDD
+//! PA0  <-> OUT
+//! PB8              <-> SCL
+//! PB9              <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example fft-spectrum-adc-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    adc_stream::{sample_rate_hz, AdcStream},
+    bootloader::relocate_vector_table,
+    fft::{magnitude, Fft},
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Line,
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    adc::SampleTime,
+    gpio::{
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const ADC_MHZ: u32 = 12;
+const BUFFER: usize = 512;
+/// FFT points
+const N: usize = 256;
+/// ADC samples averaged for each FFT sample
+const DECIMATION: u32 = 4;
+
+// Bar graph range and position
+/// Level of a full scale sine with the Hann window: 20 * log10(16384 * 0.5)
+const MAX_DB: f32 = 78.0;
+const MIN_DB: f32 = 18.0;
+const BAR_TOP: i32 = 10;
+const BAR_BOTTOM: i32 = 63;
+
+/// Block of samples for the FFT
+pub struct Capture {
+    samples: [u16; N],
+    len: usize,
+    sum: u32,
+    count: u32,
+}
+
+impl Capture {
+    fn add(&mut self, samples: &[u16]) {
+        for sample in samples {
+            if self.len == N {
+                // Full until the idle task takes it
+                return;
+            }
+            self.sum += u32::from(*sample);
+            self.count += 1;
+            if self.count == DECIMATION {
+                self.samples[self.len] = (self.sum / DECIMATION) as u16;
+                self.len += 1;
+                self.sum = 0;
+                self.count = 0;
+            }
+        }
+    }
+}
+
+/// Height of a bar for a magnitude
+fn bar_height(magnitude: u16) -> i32 {
+    let db = 20.0 * libm::log10f(f32::from(magnitude.max(1)));
+    let height = (db - MIN_DB) / (MAX_DB - MIN_DB) * (BAR_BOTTOM - BAR_TOP) as f32;
+    (height as i32).clamp(0, BAR_BOTTOM - BAR_TOP)
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        stream: AdcStream<BUFFER>,
+        capture: Capture,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("FFT spectrum example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the ADC1 clock before handing the RCC over to the HAL.
+        device.RCC.apb2enr.modify(|_, w| w.adc1en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .adcclk(ADC_MHZ.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let _input = gpioa.pa0.into_analog(&mut gpioa.crl);
+        let dma_ch1 = device.DMA1.split(&mut rcc.ahb).1;
+        let buffer = singleton!(: [[u16; BUFFER]; 2] = [[0; BUFFER]; 2]).unwrap();
+        let mut stream = AdcStream::start(device.ADC1, dma_ch1, &[0], SampleTime::T_239, buffer);
+        stream.listen();
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            stream,
+            capture: Capture {
+                samples: [0; N],
+                len: 0,
+                sum: 0,
+                count: 0,
+            },
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = DMA1_CHANNEL1, priority = 2, resources = [stream, capture])]
+    fn dma1_channel1(cx: dma1_channel1::Context) {
+        let capture = cx.resources.capture;
+        cx.resources.stream.poll(|samples| capture.add(samples));
+    }
+
+    #[idle(resources = [stream, capture, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let led = cx.resources.led;
+        let interface = I2CDIBuilder::new().init(cx.resources.i2c.take().unwrap());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+        let bar_style = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+
+        let rate_hz = sample_rate_hz(ADC_MHZ * 1_000_000, SampleTime::T_239, 1) / DECIMATION as f32;
+        log_info!("{:.0} samples/s", rate_hz);
+        let fft: Fft<N> = Fft::new();
+        let mut re = [0_i16; N];
+        let mut im = [0_i16; N];
+        let mut line: String<32> = String::new();
+        let mut frames = 0_u32;
+        let mut led_on = false;
+        loop {
+            let captured = cx.resources.capture.lock(|capture| {
+                if capture.len < N {
+                    return false;
+                }
+                let mean = capture.samples.iter().map(|s| u32::from(*s)).sum::<u32>() / N as u32;
+                for (value, sample) in re.iter_mut().zip(capture.samples.iter()) {
+                    // 12 bits to the Q15 range
+                    *value = ((i32::from(*sample) - mean as i32) << 3) as i16;
+                }
+                capture.len = 0;
+                true
+            });
+            if !captured {
+                continue;
+            }
+
+            let start = DWT::get_cycle_count();
+            im = [0; N];
+            fft.window(&mut re);
+            fft.transform(&mut re, &mut im);
+            let fft_us = DWT::get_cycle_count().wrapping_sub(start) / SYSCLK_MHZ;
+
+            disp.clear();
+            let mut peak = (0, 0);
+            // Bin 0 is the DC offset, skip it.
+            for bin in 1..N / 2 {
+                let level = magnitude(re[bin], im[bin]);
+                if level > peak.1 {
+                    peak = (bin, level);
+                }
+                let height = bar_height(level);
+                if height > 0 {
+                    let x = bin as i32;
+                    Line::new(
+                        Point::new(x, BAR_BOTTOM),
+                        Point::new(x, BAR_BOTTOM - height),
+                    )
+                    .into_styled(bar_style)
+                    .draw(&mut disp)
+                    .unwrap();
+                }
+            }
+            line.clear();
+            write!(line, "Peak: {:.0} Hz", peak.0 as f32 * rate_hz / N as f32).unwrap();
+            Text::new(&line, Point::zero())
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+            disp.flush().unwrap();
+
+            frames += 1;
+            if frames % 50 == 0 {
+                let overruns = cx.resources.stream.lock(|stream| stream.overruns());
+                log_info!("FFT: {}us, {} overruns", fft_us, overruns);
+            }
+            led_on = !led_on;
+            if led_on {
+                led.set_low().unwrap();
+            } else {
+                led.set_high().unwrap();
+            }
+        }
+    }
+};
@@ -0,0 +1,306 @@
+//! Show the temperature measured with a TMP112 on an analog-style gauge on a
+//! 240x240 round GC9A01 color LCD.
+//!
+//! The gauge is the reusable `Gauge` widget from this crate, which works on
+//! any embedded-graphics display. After drawing it once, only the needle and
+//! the numeric readout are redrawn when the temperature changes.
+//!
+//! A minimal GC9A01 driver is included in this example. It draws pixel by
+//! pixel, which is slow but good enough to move a needle.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1 for the
+//! display and I2C1 for the sensor.
+//!
+//! ```
+//! BP   <-> GC9A01 <-> TMP112
+//! GND  <-> GND    <-> GND
+//! 3.3V <-> VCC    <-> VCC
+//! 3.3V <-> BLK
+//! PA5  <-> SCL
+//! PA7  <-> SDA
+//! PB0  <-> CS
+//! PB1  <-> DC
+//! PB10 <-> RST
+//! PB8             <-> SCL
+//! PB9             <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example gc9a01-gauge-tmp112-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{gauge::Gauge, i2c_speed, log_info, panic_display as _};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+use embedded_hal::{
+    blocking::{delay::DelayMs, spi::Write},
+    digital::v2::OutputPin,
+    spi::MODE_0,
+};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*, spi::Spi};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+const SIZE: u16 = 240;
+
+/// Initialization sequence: command, parameters
+const INIT_SEQUENCE: &[(u8, &[u8])] = &[
+    (0xEF, &[]),
+    (0xEB, &[0x14]),
+    (0xFE, &[]),
+    (0xEF, &[]),
+    (0xEB, &[0x14]),
+    (0x84, &[0x40]),
+    (0x85, &[0xFF]),
+    (0x86, &[0xFF]),
+    (0x87, &[0xFF]),
+    (0x88, &[0x0A]),
+    (0x89, &[0x21]),
+    (0x8A, &[0x00]),
+    (0x8B, &[0x80]),
+    (0x8C, &[0x01]),
+    (0x8D, &[0x01]),
+    (0x8E, &[0xFF]),
+    (0x8F, &[0xFF]),
+    (0xB6, &[0x00, 0x20]),
+    (0x36, &[0x08]), // Memory access control: BGR order
+    (0x3A, &[0x05]), // Pixel format: 16 bits
+    (0x90, &[0x08, 0x08, 0x08, 0x08]),
+    (0xBD, &[0x06]),
+    (0xBC, &[0x00]),
+    (0xFF, &[0x60, 0x01, 0x04]),
+    (0xC3, &[0x13]),
+    (0xC4, &[0x13]),
+    (0xC9, &[0x22]),
+    (0xBE, &[0x11]),
+    (0xE1, &[0x10, 0x0E]),
+    (0xDF, &[0x21, 0x0C, 0x02]),
+    (0xF0, &[0x45, 0x09, 0x08, 0x08, 0x26, 0x2A]),
+    (0xF1, &[0x43, 0x70, 0x72, 0x36, 0x37, 0x6F]),
+    (0xF2, &[0x45, 0x09, 0x08, 0x08, 0x26, 0x2A]),
+    (0xF3, &[0x43, 0x70, 0x72, 0x36, 0x37, 0x6F]),
+    (0xED, &[0x1B, 0x0B]),
+    (0xAE, &[0x77]),
+    (0xCD, &[0x63]),
+    (
+        0x70,
+        &[0x07, 0x07, 0x04, 0x0E, 0x0F, 0x09, 0x07, 0x08, 0x03],
+    ),
+    (0xE8, &[0x34]),
+    (
+        0x62,
+        &[
+            0x18, 0x0D, 0x71, 0xED, 0x70, 0x70, 0x18, 0x0F, 0x71, 0xEF, 0x70, 0x70,
+        ],
+    ),
+    (
+        0x63,
+        &[
+            0x18, 0x11, 0x71, 0xF1, 0x70, 0x70, 0x18, 0x13, 0x71, 0xF3, 0x70, 0x70,
+        ],
+    ),
+    (0x64, &[0x28, 0x29, 0xF1, 0x01, 0xF1, 0x00, 0x07]),
+    (
+        0x66,
+        &[0x3C, 0x00, 0xCD, 0x67, 0x45, 0x45, 0x10, 0x00, 0x00, 0x00],
+    ),
+    (
+        0x67,
+        &[0x00, 0x3C, 0x00, 0x00, 0x00, 0x01, 0x54, 0x10, 0x32, 0x98],
+    ),
+    (0x74, &[0x10, 0x85, 0x80, 0x00, 0x00, 0x4E, 0x00]),
+    (0x98, &[0x3E, 0x07]),
+    (0x35, &[]), // Tearing effect line on
+    (0x21, &[]), // Display inversion on
+];
+
+/// Minimal GC9A01 driver
+struct Gc9a01<SPI, DC, CS> {
+    spi: SPI,
+    dc: DC,
+    cs: CS,
+}
+
+impl<SPI, DC, CS> Gc9a01<SPI, DC, CS>
+where
+    SPI: Write<u8>,
+    DC: OutputPin,
+    CS: OutputPin,
+{
+    fn new(spi: SPI, dc: DC, mut cs: CS) -> Self {
+        cs.set_high().ok();
+        Gc9a01 { spi, dc, cs }
+    }
+
+    fn init<RST, D>(&mut self, rst: &mut RST, delay: &mut D) -> Result<(), ()>
+    where
+        RST: OutputPin,
+        D: DelayMs<u8>,
+    {
+        rst.set_low().map_err(|_| ())?;
+        delay.delay_ms(10);
+        rst.set_high().map_err(|_| ())?;
+        delay.delay_ms(120);
+        for (command, parameters) in INIT_SEQUENCE {
+            self.command(*command, parameters)?;
+        }
+        // Sleep out
+        self.command(0x11, &[])?;
+        delay.delay_ms(120);
+        // Display on
+        self.command(0x29, &[])?;
+        delay.delay_ms(20);
+        Ok(())
+    }
+
+    fn command(&mut self, command: u8, parameters: &[u8]) -> Result<(), ()> {
+        self.cs.set_low().map_err(|_| ())?;
+        self.dc.set_low().map_err(|_| ())?;
+        let result = self.spi.write(&[command]).map_err(|_| ());
+        self.dc.set_high().map_err(|_| ())?;
+        let result = result.and_then(|_| self.spi.write(parameters).map_err(|_| ()));
+        self.cs.set_high().map_err(|_| ())?;
+        result
+    }
+
+    /// Select the area of the display memory which is written next.
+    fn set_window(&mut self, x0: u16, y0: u16, x1: u16, y1: u16) -> Result<(), ()> {
+        let [x0h, x0l] = x0.to_be_bytes();
+        let [x1h, x1l] = x1.to_be_bytes();
+        let [y0h, y0l] = y0.to_be_bytes();
+        let [y1h, y1l] = y1.to_be_bytes();
+        self.command(0x2A, &[x0h, x0l, x1h, x1l])?;
+        self.command(0x2B, &[y0h, y0l, y1h, y1l])
+    }
+
+    /// Fill the selected window with `count` pixels of the same color.
+    fn fill(&mut self, color: Rgb565, count: u32) -> Result<(), ()> {
+        let raw = (u16::from(color.r()) << 11 | u16::from(color.g()) << 5 | u16::from(color.b()))
+            .to_be_bytes();
+        self.command(0x2C, &[])?;
+        self.cs.set_low().map_err(|_| ())?;
+        self.dc.set_high().map_err(|_| ())?;
+        let mut result = Ok(());
+        for _ in 0..count {
+            result = self.spi.write(&raw).map_err(|_| ());
+            if result.is_err() {
+                break;
+            }
+        }
+        self.cs.set_high().map_err(|_| ())?;
+        result
+    }
+}
+
+impl<SPI, DC, CS> DrawTarget<Rgb565> for Gc9a01<SPI, DC, CS>
+where
+    SPI: Write<u8>,
+    DC: OutputPin,
+    CS: OutputPin,
+{
+    type Error = ();
+
+    fn draw_pixel(&mut self, pixel: Pixel<Rgb565>) -> Result<(), Self::Error> {
+        let Pixel(point, color) = pixel;
+        let size = i32::from(SIZE);
+        if point.x < 0 || point.y < 0 || point.x >= size || point.y >= size {
+            return Ok(());
+        }
+        let (x, y) = (point.x as u16, point.y as u16);
+        self.set_window(x, y, x, y)?;
+        self.fill(color, 1)
+    }
+
+    fn size(&self) -> Size {
+        Size::new(u32::from(SIZE), u32::from(SIZE))
+    }
+
+    fn clear(&mut self, color: Rgb565) -> Result<(), Self::Error> {
+        self.set_window(0, 0, SIZE - 1, SIZE - 1)?;
+        self.fill(color, u32::from(SIZE) * u32::from(SIZE))
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("GC9A01 gauge example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    // SPI1
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+
+    let cs = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+    let dc = gpiob.pb1.into_push_pull_output(&mut gpiob.crl);
+    let mut rst = gpiob.pb10.into_push_pull_output(&mut gpiob.crh);
+
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        MODE_0,
+        8_u32.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // TMP112 sensor
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x48]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let mut lcd = Gc9a01::new(spi, dc, cs);
+    lcd.init(&mut rst, &mut delay).unwrap();
+    lcd.clear(Rgb565::BLACK).unwrap();
+
+    let mut sensor = Tmp1x2::new(i2c, SlaveAddr::default());
+
+    let center = i32::from(SIZE) / 2;
+    let mut gauge = Gauge::new(Point::new(center, center), 115, -10.0, 40.0)
+        .ticks(10)
+        .colors(Rgb565::WHITE, Rgb565::RED, Rgb565::BLACK);
+    gauge.draw(&mut lcd).unwrap();
+
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+        delay.delay_ms(450_u16);
+
+        let temperature = sensor.read_temperature().unwrap_or(-273.0);
+        log_info!("Temperature: {:.2}C", temperature);
+        // Avoid flickering when the value did not change noticeably.
+        if libm::fabsf(temperature - gauge.value()) >= 0.05 {
+            gauge.update(&mut lcd, temperature).unwrap();
+        }
+    }
+}
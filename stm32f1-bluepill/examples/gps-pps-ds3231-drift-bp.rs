@@ -0,0 +1,389 @@
+//! Measure the frequency error (drift) of a DS3231 real-time clock against
+//! the PPS (pulse per second) output of a GPS receiver and correct it with
+//! the aging offset register of the DS3231.
+//!
+//! TIM2 is clocked by the 32768Hz output of the DS3231 on its channel 1 input
+//! (external clock mode 1) and captures its counter on the rising edge of
+//! the PPS on channel 2. The HAL does not support these modes so the timer
+//! registers are configured directly. The difference between two captures
+//! is the number of 32kHz periods in one GPS second. The counts are summed
+//! over `MEASUREMENT_S` seconds, which gives a resolution of
+//! 1 / (32768 * `MEASUREMENT_S`), i.e. about 0.05ppm for 10 minutes.
+//!
+//! The drift in ppm is then converted into an aging offset. One step of the
+//! aging offset changes the frequency by about 0.1ppm and a positive value
+//! slows the oscillator down. With `APPLY_CORRECTION` set, the new offset is
+//! written, a temperature conversion is forced so that it takes effect and
+//! a new measurement is started. Otherwise the suggestion is only shown.
+//! The aging offset is not kept when the DS3231 loses all power.
+//!
+//! The GPS receiver needs a fix for the PPS output to be active. PPS pulses
+//! that do not come one second apart (measured with the cycle counter)
+//! restart the measurement. The NMEA sentences of the receiver are received
+//! on USART2 with the `uart` module and the fix and the number of satellites
+//! are shown, so it is clear why no pulses come.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and USART2.
+//!
+//! ```
+//! BP   <-> GPS <-> DS3231 <-> Display
+//! GND  <-> GND <-> GND    <-> GND
+//! 3.3V <-> VCC <-> VCC    <-> VDD
+//! PA0          <-> 32K
+//! PA1  <-> PPS
+//! PA3  <-> TX
+//! PB8          <-> SCL    <-> SCL
+//! PB9          <-> SDA    <-> SDA
+//! ```
+//!
+//! The 32K output is open drain. The internal pull-up of PA0 is enabled.
+//!
+//! Run with:
+//! `cargo embed --example gps-pps-ds3231-drift-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_info, log_warn,
+    panic_display::{self, Bus},
+    uart::{LineBuffer, RxBuffer, RxReader, UartRx},
+};
+use ds323x::Ds323x;
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtic::app;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    serial::{Config, Rx, Serial},
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const NOMINAL_HZ: u32 = 32768;
+const MEASUREMENT_S: u32 = 600;
+const APPLY_CORRECTION: bool = false;
+const SYSCLK_MHZ: u32 = 72;
+const GPS_BAUD_RATE: u32 = 9600;
+const RX_BUFFER: usize = 256;
+/// NMEA sentences are up to 82 characters long.
+const LINE_LEN: usize = 82;
+/// Only correct drifts larger than this
+const THRESHOLD_PPM: f32 = 0.1;
+/// Frequency change for one step of the aging offset
+const PPM_PER_AGING_STEP: f32 = 0.1;
+
+/// Result of a complete measurement
+struct Drift {
+    ppm: f32,
+    suggested_offset: i8,
+}
+
+/// Drift of `counts` periods of the 32kHz output in `seconds` GPS seconds.
+fn drift_ppm(counts: u32, seconds: u32) -> f32 {
+    let expected = NOMINAL_HZ * seconds;
+    let error = counts as i32 - expected as i32;
+    error as f32 * 1_000_000.0 / expected as f32
+}
+
+/// Aging offset that compensates `ppm` when `current` is set now.
+fn suggest_offset(current: i8, ppm: f32) -> i8 {
+    // A fast oscillator (positive drift) needs a larger offset.
+    let steps = libm::roundf(ppm / PPM_PER_AGING_STEP) as i32;
+    (i32::from(current) + steps).clamp(-128, 127) as i8
+}
+
+/// Receiver state from the NMEA sentences
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct GpsStatus {
+    fix: bool,
+    satellites: u8,
+}
+
+impl GpsStatus {
+    /// Update from an RMC (fix) or GGA (satellites) sentence of any
+    /// satellite system. Other sentences and bad checksums are ignored.
+    fn update(&mut self, line: &str) {
+        let mut fields = match nmea_fields(line) {
+            Some(fields) => fields,
+            None => return,
+        };
+        let kind = fields.next().unwrap_or("");
+        if kind.ends_with("RMC") {
+            self.fix = fields.nth(1) == Some("A");
+        } else if kind.ends_with("GGA") {
+            self.satellites = fields.nth(6).and_then(|n| n.parse().ok()).unwrap_or(0);
+        }
+    }
+}
+
+/// Fields of an NMEA sentence, starting with its type, if the checksum
+/// matches.
+fn nmea_fields(line: &str) -> Option<core::str::Split<'_, char>> {
+    let (body, checksum) = line.trim().strip_prefix('$')?.split_once('*')?;
+    let expected = u8::from_str_radix(checksum, 16).ok()?;
+    if body.bytes().fold(0, |sum, byte| sum ^ byte) != expected {
+        return None;
+    }
+    Some(body.split(','))
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        uart_rx: UartRx<Rx<pac::USART2>, RX_BUFFER>,
+        reader: RxReader<RX_BUFFER>,
+        tim2: pac::TIM2,
+        // Taken by the idle task, which creates the drivers.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("GPS PPS DS3231 drift example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the TIM2 clock before handing the RCC over to the HAL.
+        device.RCC.apb1enr.modify(|_, w| w.tim2en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // TIM2 channel 1 and 2 inputs
+        let _clock_input = gpioa.pa0.into_pull_up_input(&mut gpioa.crl);
+        let _pps_input = gpioa.pa1.into_floating_input(&mut gpioa.crl);
+
+        // Count the rising edges of TI1 and capture the counter on the rising
+        // edges of TI2.
+        let tim2 = device.TIM2;
+        tim2.ccmr1_input()
+            .modify(|_, w| w.cc1s().ti1().ic1f().fck_int_n2().cc2s().ti2());
+        tim2.ccer
+            .modify(|_, w| w.cc2p().clear_bit().cc2e().set_bit());
+        tim2.smcr
+            .modify(|_, w| w.ts().ti1fp1().sms().ext_clock_mode());
+        tim2.cr1.modify(|_, w| w.cen().enabled());
+
+        // Only the receiver is used, the GPS keeps its default settings.
+        let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+        let rx = gpioa.pa3;
+        let serial = Serial::usart2(
+            device.USART2,
+            (tx, rx),
+            &mut afio.mapr,
+            Config::default().baudrate(GPS_BAUD_RATE.bps()),
+            clocks,
+            &mut rcc.apb1,
+        );
+        let (_tx, mut rx) = serial.split();
+        rx.listen();
+        let (uart_rx, reader) = singleton!(: RxBuffer<RX_BUFFER> = RxBuffer::new())
+            .unwrap()
+            .split(rx);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // DS3231 RTC and display
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x68, 0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            uart_rx,
+            reader,
+            tim2,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    /// Move the received NMEA bytes into the buffer.
+    #[task(binds = USART2, priority = 2, resources = [uart_rx])]
+    fn usart2(cx: usart2::Context) {
+        cx.resources.uart_rx.on_interrupt();
+    }
+
+    #[idle(resources = [reader, tim2, i2c, led])]
+    fn idle(cx: idle::Context) -> ! {
+        let reader = cx.resources.reader;
+        let tim2 = cx.resources.tim2;
+        let led = cx.resources.led;
+        let cycles_per_second = SYSCLK_MHZ * 1_000_000;
+
+        let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(
+            cx.resources.i2c.take().unwrap(),
+        );
+        let interface = I2CDIBuilder::new().init(manager.acquire());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut rtc = Ds323x::new_ds3231(manager.acquire());
+        rtc.enable_32khz_output().unwrap();
+        let mut aging_offset = rtc.get_aging_offset().unwrap();
+        log_info!("Aging offset: {}", aging_offset);
+
+        let mut line_buffer: LineBuffer<LINE_LEN> = LineBuffer::new();
+        let mut gps = GpsStatus::default();
+        let mut redraw = true;
+
+        let mut last_capture: Option<(u16, u32)> = None;
+        let mut seconds = 0;
+        let mut counts = 0;
+        let mut last_drift: Option<Drift> = None;
+        let mut lines: [String<32>; 6] = Default::default();
+        loop {
+            for line in reader.lines(&mut line_buffer) {
+                let previous = gps;
+                gps.update(&line);
+                if gps != previous {
+                    log_info!("GPS fix: {}, {} satellites", gps.fix, gps.satellites);
+                    redraw = true;
+                }
+            }
+            if reader.dropped() > 0 || reader.errors() > 0 {
+                log_warn!(
+                    every = 100,
+                    "NMEA bytes dropped: {}, UART errors: {}",
+                    reader.dropped(),
+                    reader.errors()
+                );
+            }
+
+            if tim2.sr.read().cc2if().bit_is_set() {
+                // Reading the capture register clears the flag.
+                let capture = tim2.ccr2.read().bits() as u16;
+                let now = DWT::get_cycle_count();
+                led.set_low().unwrap();
+
+                // The 16-bit counter wraps around every 2 seconds, so check
+                // with the cycle counter that no PPS pulse was missed.
+                match last_capture {
+                    Some((last, time))
+                        if (90..=110)
+                            .contains(&(now.wrapping_sub(time) / (cycles_per_second / 100))) =>
+                    {
+                        counts += u32::from(capture.wrapping_sub(last));
+                        seconds += 1;
+                    }
+                    Some(_) => {
+                        log_warn!("PPS lost, restarting the measurement");
+                        counts = 0;
+                        seconds = 0;
+                    }
+                    None => (),
+                }
+                last_capture = Some((capture, now));
+
+                if seconds == MEASUREMENT_S {
+                    let ppm = drift_ppm(counts, seconds);
+                    let suggested_offset = suggest_offset(aging_offset, ppm);
+                    log_info!(
+                        "Drift: {:.3} ppm, aging offset {} -> {}",
+                        ppm,
+                        aging_offset,
+                        suggested_offset
+                    );
+                    if APPLY_CORRECTION && libm::fabsf(ppm) > THRESHOLD_PPM {
+                        rtc.set_aging_offset(suggested_offset).unwrap();
+                        rtc.convert_temperature().unwrap();
+                        aging_offset = suggested_offset;
+                        log_info!("Aging offset set to {}", aging_offset);
+                    }
+                    last_drift = Some(Drift {
+                        ppm,
+                        suggested_offset,
+                    });
+                    counts = 0;
+                    seconds = 0;
+                }
+                redraw = true;
+            }
+            if !redraw {
+                continue;
+            }
+            redraw = false;
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            if gps.fix {
+                write!(lines[0], "GPS fix, {} sats", gps.satellites).unwrap();
+            } else {
+                write!(lines[0], "No GPS fix, {} sats", gps.satellites).unwrap();
+            }
+            write!(lines[1], "Measuring {}/{}s", seconds, MEASUREMENT_S).unwrap();
+            if seconds > 0 {
+                write!(lines[2], "Now: {:.2} ppm", drift_ppm(counts, seconds)).unwrap();
+            }
+            write!(lines[3], "Aging offset: {}", aging_offset).unwrap();
+            if let Some(drift) = &last_drift {
+                write!(lines[4], "Drift: {:.3} ppm", drift.ppm).unwrap();
+                write!(lines[5], "Suggested: {}", drift.suggested_offset).unwrap();
+            }
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 10))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+            led.set_high().unwrap();
+        }
+    }
+};
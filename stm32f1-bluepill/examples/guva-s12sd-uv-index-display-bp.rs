@@ -0,0 +1,212 @@
+//! Measure the UV index with a GUVA-S12SD analog UV sensor and show it on an
+//! SSD1306 OLED display together with a bar and a sunburn warning.
+//!
+//! The output voltage of the sensor module is proportional to the UV
+//! radiation. It is read with the ADC, averaged and converted into the UV
+//! index with the `convert` module of this crate using the typical
+//! characteristic of the module (about 100mV per UV index point).
+//!
+//! The bar has marks at the thresholds of the WHO categories:
+//! - 0-2: low
+//! - 3-5: moderate, use sun protection
+//! - 6-7: high, use sun protection
+//! - 8-10: very high, avoid the sun
+//! - 11+: extreme, avoid the sun
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> GUVA-S12SD <-> Display
+//! GND  <-> GND        <-> GND
+//! 3.3V <-> VCC        <-> VDD
+//! PA0  <-> SIG
+//! PB8                 <-> SCL
+//! PB9                 <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example guva-s12sd-uv-index-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    convert::{adc_to_millivolts, average, interpolate},
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, Rectangle},
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{adc, delay::Delay, pac, prelude::*};
+
+/// Output voltage in mV and UV index * 10
+const UV_INDEX_CURVE: [(i32, i32); 12] = [
+    (50, 0),
+    (227, 10),
+    (318, 20),
+    (408, 30),
+    (503, 40),
+    (606, 50),
+    (696, 60),
+    (795, 70),
+    (881, 80),
+    (976, 90),
+    (1079, 100),
+    (1170, 110),
+];
+const MAX_INDEX: i32 = 11;
+/// Category thresholds as UV index
+const THRESHOLDS: [i32; 4] = [3, 6, 8, 11];
+const SAMPLES: usize = 32;
+
+const BAR_LEFT: i32 = 0;
+const BAR_RIGHT: i32 = 121;
+const BAR_TOP: i32 = 44;
+const BAR_BOTTOM: i32 = 56;
+
+fn category(index: i32) -> (&'static str, &'static str) {
+    match index {
+        i if i < 3 => ("Low", ""),
+        i if i < 6 => ("Moderate", "Use sunscreen"),
+        i if i < 8 => ("High", "Use sunscreen"),
+        i if i < 11 => ("Very high", "Avoid the sun"),
+        _ => ("Extreme", "Avoid the sun"),
+    }
+}
+
+fn bar_x(index_tenths: i32) -> i32 {
+    BAR_LEFT + (BAR_RIGHT - BAR_LEFT) * index_tenths / (MAX_INDEX * 10)
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("GUVA-S12SD UV index example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.adcclk(2.mhz()).freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let mut adc1 = adc::Adc::adc1(dp.ADC1, &mut rcc.apb2, clocks);
+    let mut uv_sensor = gpioa.pa0.into_analog(&mut gpioa.crl);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+    let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+    let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+
+    let mut lines: [String<32>; 3] = [String::new(), String::new(), String::new()];
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+
+        let raw = average((0..SAMPLES).map(|_| {
+            let value: u16 = adc1.read(&mut uv_sensor).unwrap();
+            value
+        }));
+        let millivolts = adc_to_millivolts(raw);
+        let index_tenths = interpolate(&UV_INDEX_CURVE, millivolts as i32);
+        let index = index_tenths / 10;
+        let (name, warning) = category(index);
+        log_info!(
+            "{} mV, UV index: {}.{} ({})",
+            millivolts,
+            index,
+            index_tenths % 10,
+            name
+        );
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        if index >= MAX_INDEX {
+            write!(lines[0], "UV index: {}+", MAX_INDEX).unwrap();
+        } else {
+            write!(lines[0], "UV index: {}.{}", index, index_tenths % 10).unwrap();
+        }
+        write!(lines[1], "{}", name).unwrap();
+        write!(lines[2], "{}", warning).unwrap();
+
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 14))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        Rectangle::new(
+            Point::new(BAR_LEFT, BAR_TOP),
+            Point::new(BAR_RIGHT, BAR_BOTTOM),
+        )
+        .into_styled(outline)
+        .draw(&mut disp)
+        .unwrap();
+        Rectangle::new(
+            Point::new(BAR_LEFT, BAR_TOP),
+            Point::new(bar_x(index_tenths), BAR_BOTTOM),
+        )
+        .into_styled(fill)
+        .draw(&mut disp)
+        .unwrap();
+        for threshold in THRESHOLDS.iter() {
+            let x = bar_x(threshold * 10);
+            Line::new(Point::new(x, BAR_TOP - 4), Point::new(x, BAR_TOP - 1))
+                .into_styled(outline)
+                .draw(&mut disp)
+                .unwrap();
+            Line::new(Point::new(x, BAR_BOTTOM + 1), Point::new(x, BAR_BOTTOM + 4))
+                .into_styled(outline)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        delay.delay_ms(500_u16);
+    }
+}
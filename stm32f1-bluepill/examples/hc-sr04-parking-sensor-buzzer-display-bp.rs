@@ -0,0 +1,287 @@
+//! Parking sensor: measure the distance to an obstacle with an HC-SR04
+//! ultrasonic sensor, beep faster the closer it gets and show the distance
+//! and a bar on an SSD1306 OLED display.
+//!
+//! The HC-SR04 sends an ultrasonic burst after a 10µs pulse on TRIG and
+//! then holds ECHO high until the echo comes back, 58µs per centimeter of
+//! distance. TIM4 measures the echo pulse in hardware: channel 1 captures the
+//! rising edge and channel 2 the falling edge of the same input, so the main
+//! loop only needs to trigger a measurement and collect the result later.
+//!
+//! Now and then the sensor catches a stray echo or none at all. The last
+//! `FILTER_LEN` distances go through the `MedianFilter` from this crate,
+//! which ignores these outliers.
+//!
+//! The buzzer is silent beyond `FAR_CM`, beeps faster and faster as the
+//! obstacle gets closer and beeps continuously below `STOP_CM`.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> HC-SR04 <-> Buzzer <-> Display
+//! GND  <-> GND     <-> GND    <-> GND
+//! 5V   <-> VCC
+//! 3.3V                        <-> VDD
+//! PB0  <-> TRIG
+//! PB6  <-> ECHO
+//! PA3              <-> +
+//! PB8                         <-> SCL
+//! PB9                         <-> SDA
+//! ```
+//!
+//! The HC-SR04 needs 5V and its ECHO output is 5V as well, so it must be
+//! connected to a 5V tolerant pin like PB6. The buzzer must be an active
+//! buzzer (it beeps when supplied with a constant voltage).
+//!
+//! Run with:
+//! `cargo embed --example hc-sr04-parking-sensor-buzzer-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    delay::DwtDelay,
+    i2c_speed, log_info,
+    median::MedianFilter,
+    monotonic,
+    panic_display::{self, Bus},
+    scheduler::Scheduler,
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::{blocking::delay::DelayUs, digital::v2::OutputPin};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{pac, prelude::*};
+
+const SYSCLK_MHZ: u32 = 72;
+const US_PER_CM: u32 = 58;
+/// Range of the HC-SR04. Longer echoes or no echo at all count as this.
+const MAX_CM: u32 = 400;
+const MIN_CM: u32 = 2;
+const FILTER_LEN: usize = 5;
+/// Beyond this distance the buzzer is silent.
+const FAR_CM: u32 = 100;
+/// Below this distance the buzzer beeps continuously.
+const STOP_CM: u32 = 10;
+const BEEP_MS: u32 = 50;
+/// Time between beeps at `FAR_CM`
+const SLOWEST_BEEP_PERIOD_MS: u32 = 1000;
+/// Time between beeps just above `STOP_CM`
+const FASTEST_BEEP_PERIOD_MS: u32 = 120;
+
+// Tasks in order of priority
+const BUZZER: usize = 0;
+// The HC-SR04 needs at least 60ms between measurements.
+const MEASURE: usize = 1;
+const DISPLAY: usize = 2;
+const BLINK: usize = 3;
+
+/// Time between beeps for the given distance, `None` if the buzzer is
+/// silent and `Some(0)` if it beeps continuously.
+fn beep_period_ms(distance_cm: u32) -> Option<u32> {
+    if distance_cm >= FAR_CM {
+        None
+    } else if distance_cm <= STOP_CM {
+        Some(0)
+    } else {
+        let span = SLOWEST_BEEP_PERIOD_MS - FASTEST_BEEP_PERIOD_MS;
+        Some(FASTEST_BEEP_PERIOD_MS + span * (distance_cm - STOP_CM) / (FAR_CM - STOP_CM))
+    }
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("HC-SR04 parking sensor example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    // Enable the TIM4 clock before handing the RCC over to the HAL.
+    dp.RCC.apb1enr.modify(|_, w| w.tim4en().set_bit());
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(SYSCLK_MHZ.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+    let mut delay = DwtDelay::new(clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let mut trigger = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+    let mut buzzer = gpioa.pa3.into_push_pull_output(&mut gpioa.crl);
+
+    // TIM4 channel 1 input. The TIM4 clock is twice PCLK1, so 72MHz. Count
+    // microseconds and capture the rising edge of TI1 on channel 1 and the
+    // falling edge on channel 2.
+    let _echo = gpiob.pb6.into_floating_input(&mut gpiob.crl);
+    let echo_timer = dp.TIM4;
+    echo_timer
+        .psc
+        .write(|w| w.psc().bits((SYSCLK_MHZ - 1) as u16));
+    echo_timer.egr.write(|w| w.ug().set_bit());
+    echo_timer
+        .ccmr1_input()
+        .modify(|_, w| w.cc1s().ti1().cc2s().ti1());
+    echo_timer
+        .ccer
+        .modify(|_, w| w.cc1e().set_bit().cc2p().set_bit().cc2e().set_bit());
+    echo_timer.cr1.modify(|_, w| w.cen().enabled());
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+    let bar_style = PrimitiveStyle::with_fill(BinaryColor::On);
+    let frame_style = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+
+    let mut filter: MedianFilter<u32, FILTER_LEN> = MedianFilter::new();
+    let mut distance_cm = None;
+    let mut raw_cm = MAX_CM;
+    let mut last_beep_ms = monotonic::millis();
+    let mut line: String<32> = String::new();
+    let mut led_on = false;
+    let mut scheduler = Scheduler::new([10, 60, 200, 500]);
+    loop {
+        match scheduler.poll() {
+            Some(BUZZER) => {
+                let now = monotonic::millis();
+                match distance_cm.and_then(beep_period_ms) {
+                    Some(0) => buzzer.set_high().unwrap(),
+                    Some(period) => {
+                        let elapsed = now.wrapping_sub(last_beep_ms);
+                        if elapsed >= period {
+                            last_beep_ms = now;
+                            buzzer.set_high().unwrap();
+                        } else if elapsed >= BEEP_MS {
+                            buzzer.set_low().unwrap();
+                        }
+                    }
+                    None => buzzer.set_low().unwrap(),
+                }
+            }
+            Some(MEASURE) => {
+                // Collect the echo of the previous measurement. Reading the
+                // captures clears the flags.
+                let status = echo_timer.sr.read();
+                raw_cm = if status.cc1if().bit_is_set() && status.cc2if().bit_is_set() {
+                    let rising = echo_timer.ccr1.read().bits() as u16;
+                    let falling = echo_timer.ccr2.read().bits() as u16;
+                    let width_us = u32::from(falling.wrapping_sub(rising));
+                    (width_us / US_PER_CM).clamp(MIN_CM, MAX_CM)
+                } else {
+                    // No echo
+                    MAX_CM
+                };
+                distance_cm = Some(filter.push(raw_cm));
+
+                // Start the next measurement.
+                echo_timer.sr.modify(|_, w| {
+                    w.cc1if()
+                        .clear_bit()
+                        .cc2if()
+                        .clear_bit()
+                        .cc1of()
+                        .clear_bit()
+                        .cc2of()
+                        .clear_bit()
+                });
+                trigger.set_high().unwrap();
+                delay.delay_us(10_u8);
+                trigger.set_low().unwrap();
+            }
+            Some(DISPLAY) => {
+                disp.clear();
+                line.clear();
+                match distance_cm {
+                    Some(d) if d >= MAX_CM => write!(line, "Distance: > {} cm", MAX_CM).unwrap(),
+                    Some(d) => write!(line, "Distance: {} cm", d).unwrap(),
+                    None => write!(line, "Distance: ...").unwrap(),
+                }
+                Text::new(&line, Point::new(0, 0))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+                line.clear();
+                write!(line, "Last echo: {} cm", raw_cm).unwrap();
+                Text::new(&line, Point::new(0, 16))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+
+                // The bar grows as the obstacle gets closer than `FAR_CM`.
+                let closeness = FAR_CM - distance_cm.unwrap_or(FAR_CM).clamp(STOP_CM, FAR_CM);
+                let width = (closeness * 127 / (FAR_CM - STOP_CM)) as i32;
+                Rectangle::new(Point::new(0, 40), Point::new(127, 63))
+                    .into_styled(frame_style)
+                    .draw(&mut disp)
+                    .unwrap();
+                if width > 0 {
+                    Rectangle::new(Point::new(0, 40), Point::new(width, 63))
+                        .into_styled(bar_style)
+                        .draw(&mut disp)
+                        .unwrap();
+                }
+                disp.flush().unwrap();
+            }
+            Some(BLINK) => {
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                led_on = !led_on;
+                if led_on {
+                    led.set_high().unwrap();
+                } else {
+                    led.set_low().unwrap();
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Log the temperature and humidity measured with an HDC2080 sensor, without
+//! a display, and report failures with LED blink codes.
+//!
+//! When the sensor does not answer, or stops answering for several reads in
+//! a row, the example records the fault, blinks its code on the LED a few
+//! times and resets itself, with the `diagnostics` module. After the reset
+//! it prints the fault and blinks its code once more, then tries again. A
+//! long blink followed by one short blink means an I2C timeout, by two a
+//! missing sensor and by four a hard fault.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> HDC2080
+//! GND  <-> GND
+//! 3.3V <-> VCC
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example hdc2080-fault-blink-codes-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::{entry, exception, ExceptionFrame};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    diagnostics::{self, Fault},
+    log_error, log_info, log_warn,
+    monotonic::{self, with_timeout, TimeoutError},
+    panic_display as _,
+};
+use embedded_hal::digital::v2::OutputPin;
+use hdc20xx::{Hdc20xx, SlaveAddr};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    i2c::{BlockingI2c, DutyCycle, Mode},
+    pac,
+    prelude::*,
+};
+
+const READ_TIMEOUT_MS: u32 = 100;
+/// Timeouts in a row before giving up
+const MAX_TIMEOUTS: u32 = 3;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[exception]
+fn HardFault(ef: &ExceptionFrame) -> ! {
+    diagnostics::hard_fault(ef)
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("HDC2080 fault blink codes example");
+    if let Some(last) = diagnostics::take_last_fault() {
+        log_warn!("Last fault: {:?} at {:#010x}", last.fault, last.pc);
+        diagnostics::blink_code(last.fault);
+    }
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let i2c = BlockingI2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        Mode::Fast {
+            frequency: 100_000.hz(),
+            duty_cycle: DutyCycle::Ratio2to1,
+        },
+        clocks,
+        &mut rcc.apb1,
+        1000,
+        10,
+        1000,
+        1000,
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut sensor = Hdc20xx::new(i2c, SlaveAddr::default());
+    let mut timeouts = 0;
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        monotonic::wait_ms(500);
+        led.set_low().unwrap();
+        monotonic::wait_ms(50);
+
+        match with_timeout(READ_TIMEOUT_MS, || sensor.read()) {
+            Ok(data) => {
+                timeouts = 0;
+                log_info!(
+                    "Temperature: {:.2}ºC, humidity: {:.2}%",
+                    data.temperature,
+                    data.humidity.unwrap()
+                );
+            }
+            Err(TimeoutError::Timeout) => {
+                timeouts += 1;
+                log_warn!("Sensor timeout");
+                if timeouts >= MAX_TIMEOUTS {
+                    log_error!("Sensor stopped answering");
+                    diagnostics::fail(Fault::I2cTimeout);
+                }
+            }
+            Err(TimeoutError::Other(e)) => {
+                log_error!("Sensor error: {:?}", e);
+                diagnostics::fail(Fault::SensorMissing);
+            }
+        }
+    }
+}
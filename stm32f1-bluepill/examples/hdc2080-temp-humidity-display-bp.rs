@@ -1,6 +1,10 @@
 //! Continuously measure the temperature and humidity with an
 //! HDC2080 sensor and print the values to an SSD1306 OLED display.
 //!
+//! The measurements are read with a timeout so that the error is shown
+//! instead of hanging when the sensor stops answering, e.g. when it is
+//! disconnected.
+//!
 //! This example is runs on the STM32F103 "Bluepill" board using I2C1.
 //!
 //! ```
@@ -19,7 +23,13 @@
 #![no_main]
 
 use core::fmt::Write;
-use cortex_m_rt::entry;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    clock, log_error, log_info,
+    monotonic::{self, with_timeout, TimeoutError},
+    panic_display::{self, Bus},
+};
 use embedded_graphics::{
     fonts::{Font6x8, Text},
     pixelcolor::BinaryColor,
@@ -28,27 +38,33 @@ use embedded_graphics::{
 };
 use embedded_hal::digital::v2::OutputPin;
 use hdc20xx::{Hdc20xx, SlaveAddr};
-use nb::block;
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use ssd1306::{prelude::*, Builder, I2CDIBuilder};
 use stm32f1xx_hal::{
-    delay::Delay,
     i2c::{BlockingI2c, DutyCycle, Mode},
     pac,
     prelude::*,
 };
 
+const READ_TIMEOUT_MS: u32 = 100;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("HDC2080 example");
-    let cp = cortex_m::Peripherals::take().unwrap();
+    log_info!("HDC2080 example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
 
     let mut flash = dp.FLASH.constrain();
     let mut rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
+    clock::log_clocks(&clocks);
     let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
     let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
 
@@ -73,12 +89,13 @@ fn main() -> ! {
 
     let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
     let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
-    let mut delay = Delay::new(cp.SYST, clocks);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
 
     let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
     let interface = I2CDIBuilder::new().init(manager.acquire());
     let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
     disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
     disp.flush().unwrap();
 
     let text_style = TextStyleBuilder::new(Font6x8)
@@ -91,16 +108,23 @@ fn main() -> ! {
         // Blink LED 0 to check that everything is actually running.
         // If the LED 0 is off, something went wrong.
         led.set_high().unwrap();
-        delay.delay_ms(50_u16);
+        monotonic::wait_ms(50);
         led.set_low().unwrap();
-        delay.delay_ms(50_u16);
-
-        let data = block!(sensor.read()).unwrap();
+        monotonic::wait_ms(50);
 
         lines[0].clear();
         lines[1].clear();
-        write!(lines[0], "Temperature: {:.2}ºC  ", data.temperature).unwrap();
-        write!(lines[1], "Humidity: {:.2}%  ", data.humidity.unwrap()).unwrap();
+        match with_timeout(READ_TIMEOUT_MS, || sensor.read()) {
+            Ok(data) => {
+                write!(lines[0], "Temperature: {:.2}ºC  ", data.temperature).unwrap();
+                write!(lines[1], "Humidity: {:.2}%  ", data.humidity.unwrap()).unwrap();
+            }
+            Err(TimeoutError::Timeout) => write!(lines[0], "Sensor timeout").unwrap(),
+            Err(TimeoutError::Other(e)) => {
+                log_error!(every = 10, "Sensor error: {:?}", e);
+                write!(lines[0], "Sensor error").unwrap();
+            }
+        }
         disp.clear();
         for (i, line) in lines.iter().enumerate() {
             Text::new(line, Point::new(0, i as i32 * 16))
@@ -0,0 +1,214 @@
+//! Scroll text and show the temperature measured with a TMP102 on an
+//! Adafruit-style quad alphanumeric 14-segment display with an HT16K33
+//! controller.
+//!
+//! The brightness is faded in and out while the text scrolls. The
+//! temperature is shown with one decimal and the degree character. The
+//! display blinks slowly when the temperature gets close to
+//! `ALARM_TEMPERATURE` and fast when it goes above it.
+//!
+//! A minimal HT16K33 driver with a font for 14-segment digits is included
+//! in this example.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> HT16K33 <-> TMP102
+//! GND  <-> GND     <-> GND
+//! 3.3V <-> VCC     <-> VCC
+//! 3.3V <-> Vi2c
+//! PB8  <-> SCL     <-> SCL
+//! PB9  <-> SDA     <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example ht16k33-alphanumeric-tmp102-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{i2c_speed, log_info, panic_display as _};
+use embedded_hal::blocking::i2c;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+const DIGITS: usize = 4;
+const MESSAGE: &str = "    HELLO FROM RUST    ";
+const ALARM_TEMPERATURE: f32 = 30.0;
+
+const DECIMAL_POINT: u16 = 0x4000;
+const DEGREE: u16 = 0x00E3;
+
+/// Segments of the digits 0-9
+const FONT_DIGITS: [u16; 10] = [
+    0x0C3F, 0x0006, 0x00DB, 0x008F, 0x00E6, 0x2069, 0x00FD, 0x0007, 0x00FF, 0x00EF,
+];
+
+/// Segments of the letters A-Z
+const FONT_LETTERS: [u16; 26] = [
+    0x00F7, 0x128F, 0x0039, 0x120F, 0x00F9, 0x0071, 0x00BD, 0x00F6, 0x1209, 0x001E, 0x2470, 0x0038,
+    0x0536, 0x2136, 0x003F, 0x00F3, 0x203F, 0x20F3, 0x018D, 0x1201, 0x003E, 0x0C30, 0x2836, 0x2D00,
+    0x1500, 0x0C09,
+];
+
+fn segments(c: char) -> u16 {
+    match c {
+        '0'..='9' => FONT_DIGITS[c as usize - '0' as usize],
+        'A'..='Z' => FONT_LETTERS[c as usize - 'A' as usize],
+        'a'..='z' => FONT_LETTERS[c as usize - 'a' as usize],
+        '-' => 0x00C0,
+        '_' => 0x0008,
+        '*' | '°' => DEGREE,
+        '/' => 0x0C00,
+        _ => 0,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BlinkRate {
+    Off = 0,
+    Hz2 = 1,
+    Hz1 = 2,
+}
+
+/// Minimal HT16K33 driver for a 4-digit 14-segment display
+struct Ht16k33<I2C> {
+    i2c: I2C,
+    address: u8,
+    buffer: [u16; DIGITS],
+}
+
+impl<I2C, E> Ht16k33<I2C>
+where
+    I2C: i2c::Write<Error = E>,
+{
+    fn new(i2c: I2C, address: u8) -> Self {
+        Ht16k33 {
+            i2c,
+            address,
+            buffer: [0; DIGITS],
+        }
+    }
+
+    fn init(&mut self) -> Result<(), E> {
+        // Turn on the oscillator
+        self.i2c.write(self.address, &[0x21])?;
+        self.set_blink_rate(BlinkRate::Off)?;
+        self.set_brightness(15)
+    }
+
+    /// Set the brightness: 0-15
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), E> {
+        self.i2c.write(self.address, &[0xE0 | (brightness & 0x0F)])
+    }
+
+    /// Turn the display on with the blink rate given
+    fn set_blink_rate(&mut self, rate: BlinkRate) -> Result<(), E> {
+        self.i2c.write(self.address, &[0x81 | (rate as u8) << 1])
+    }
+
+    /// Show a text. Decimal points are shown together with the previous
+    /// character. Characters that do not fit are ignored.
+    fn write_str(&mut self, text: &str) -> Result<(), E> {
+        self.buffer = [0; DIGITS];
+        let mut position = 0;
+        for c in text.chars() {
+            if c == '.' && position > 0 && self.buffer[position - 1] & DECIMAL_POINT == 0 {
+                self.buffer[position - 1] |= DECIMAL_POINT;
+            } else if position < DIGITS {
+                self.buffer[position] = if c == '.' { DECIMAL_POINT } else { segments(c) };
+                position += 1;
+            }
+        }
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<(), E> {
+        let mut data = [0; 1 + DIGITS * 2];
+        for (i, digit) in self.buffer.iter().enumerate() {
+            data[1 + i * 2..3 + i * 2].copy_from_slice(&digit.to_le_bytes());
+        }
+        self.i2c.write(self.address, &data)
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("HT16K33 alphanumeric display example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // HT16K33 display and TMP102 sensor
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x70, 0x48]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let mut display = Ht16k33::new(manager.acquire(), 0x70);
+    display.init().unwrap();
+    let mut tmp102 = Tmp1x2::new(manager.acquire(), SlaveAddr::default());
+
+    let mut buffer: heapless::String<16> = heapless::String::new();
+    loop {
+        // Scroll the message while fading the brightness in and out.
+        let steps = MESSAGE.len() - DIGITS + 1;
+        for start in 0..steps {
+            let fade = (start * 30 / steps) as u8;
+            let brightness = if fade < 15 { fade } else { 30 - fade };
+            display.set_brightness(brightness).unwrap();
+            display.write_str(&MESSAGE[start..start + DIGITS]).unwrap();
+            delay.delay_ms(250_u16);
+        }
+
+        display.set_brightness(15).unwrap();
+        for _ in 0..10 {
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            led.set_high().unwrap();
+            delay.delay_ms(50_u16);
+            led.set_low().unwrap();
+            delay.delay_ms(450_u16);
+
+            let temperature = tmp102.read_temperature().unwrap_or(-273.0);
+            log_info!("Temperature: {:.1}C", temperature);
+            buffer.clear();
+            write!(buffer, "{:4.1}*", temperature).unwrap();
+            display.write_str(&buffer).unwrap();
+            let rate = if temperature > ALARM_TEMPERATURE {
+                BlinkRate::Hz2
+            } else if temperature > ALARM_TEMPERATURE - 2.0 {
+                BlinkRate::Hz1
+            } else {
+                BlinkRate::Off
+            };
+            display.set_blink_rate(rate).unwrap();
+        }
+        display.set_blink_rate(BlinkRate::Off).unwrap();
+    }
+}
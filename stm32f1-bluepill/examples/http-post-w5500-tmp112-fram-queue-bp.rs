@@ -0,0 +1,338 @@
+//! Post the temperature measured with a TMP112 sensor as JSON to an HTTP
+//! server through a W5500 Ethernet module, keeping the samples in an FRAM
+//! while the network or the server is down.
+//!
+//! Every `SAMPLE_INTERVAL_S` seconds a sample is appended to the queue of
+//! the `record_queue` module, then all the queued samples are posted one by
+//! one to `PATH` on the server, e.g.:
+//! `{"uptime_s":120,"temperature":21.50}`
+//! A sample is only removed from the queue once the server answered with
+//! a 2xx status, so none are lost. After a failure, the example waits for
+//! 1, 2, 4... up to `MAX_BACKOFF` intervals before posting again, and keeps
+//! collecting samples in the meantime. When the queue is full, the oldest
+//! samples are dropped.
+//!
+//! The queue is in an MB85RC256V FRAM, so the samples survive resets and
+//! power loss. To keep them in RAM instead, create the queue with
+//! `RecordQueue::new(Ram::<4096>::new())`. The samples only carry the time
+//! since the start, so the ones kept over a reset have the uptime of the
+//! run before. Add a real-time clock like the DS3231 for actual
+//! timestamps.
+//!
+//! For a quick test, run a server which prints the requests, e.g. with
+//! `nc -lk 8080`, though it does not answer, so the samples stay queued.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and SPI1.
+//!
+//! ```
+//! BP   <-> TMP112 <-> MB85RC256V <-> W5500
+//! GND  <-> GND    <-> GND        <-> GND
+//! GND             <-> A0, A1, A2, WP
+//! 3.3V <-> VCC    <-> VCC        <-> 3.3V
+//! PB8  <-> SCL    <-> SCL
+//! PB9  <-> SDA    <-> SDA
+//! PA4                            <-> SCS
+//! PA5                            <-> SCLK
+//! PA6                            <-> MISO
+//! PA7                            <-> MOSI
+//! PB0                            <-> RST
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example http-post-w5500-tmp112-fram-queue-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    http, i2c_speed, log_info, log_warn, monotonic, panic_display as _,
+    record_queue::{Fram, RecordQueue, Storage},
+    w5500::{self, NetConfig, W5500},
+};
+use embedded_hal::{
+    blocking::spi,
+    digital::v2::OutputPin,
+    spi::{Mode, Phase, Polarity},
+};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{pac, prelude::*, spi::Spi};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+const SERVER_IP: [u8; 4] = [192, 168, 1, 10];
+const SERVER_PORT: u16 = 8080;
+const HOST: &str = "192.168.1.10";
+const PATH: &str = "/samples";
+const NET_CONFIG: NetConfig = NetConfig {
+    // Locally administered address, change it if there are several boards.
+    mac: [0x02, 0x00, 0x00, 0x12, 0x34, 0x58],
+    ip: [192, 168, 1, 52],
+    subnet_mask: [255, 255, 255, 0],
+    router: [192, 168, 1, 1],
+};
+/// First of the local ports, a new one is used for every connection.
+const LOCAL_PORT_MIN: u16 = 49152;
+
+const SAMPLE_INTERVAL_S: u32 = 60;
+/// Longest wait after failures, in sample intervals
+const MAX_BACKOFF: u32 = 32;
+const ANSWER_TIMEOUT_MS: u32 = 5000;
+const FRAM_ADDRESS: u8 = 0x50;
+const FRAM_SIZE: u32 = 32 * 1024;
+const SAMPLE_LEN: usize = 8;
+
+#[derive(Debug)]
+enum PostError {
+    Net(w5500::Error),
+    /// The FRAM could not be read or written
+    Storage,
+    /// The request does not fit into the buffer
+    TooLong,
+    /// No status line arrived in time
+    Timeout,
+    /// The answer has no status line
+    BadAnswer,
+    /// The server did not accept the sample
+    Status(u16),
+}
+
+/// Temperature measurement as stored in the queue
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sample {
+    /// Seconds since the start
+    uptime_s: u32,
+    temperature: f32,
+}
+
+impl Sample {
+    fn to_bytes(&self) -> [u8; SAMPLE_LEN] {
+        let mut bytes = [0; SAMPLE_LEN];
+        bytes[0..4].copy_from_slice(&self.uptime_s.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.temperature.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; SAMPLE_LEN]) -> Self {
+        Sample {
+            uptime_s: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            temperature: f32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+/// Post `body` and wait for the status of the answer.
+fn post<SPI, CS, E>(
+    w5500: &mut W5500<SPI, CS>,
+    local_port: u16,
+    body: &str,
+) -> Result<(), PostError>
+where
+    SPI: spi::Write<u8, Error = E> + spi::Transfer<u8, Error = E>,
+    CS: OutputPin,
+{
+    let mut request: String<256> = String::new();
+    http::write_post(&mut request, HOST, PATH, http::JSON, body).map_err(|_| PostError::TooLong)?;
+    w5500
+        .connect_tcp(local_port, SERVER_IP, SERVER_PORT)
+        .map_err(PostError::Net)?;
+    let result = send_request(w5500, request.as_bytes());
+    let closed = w5500.close().map_err(PostError::Net);
+    result.and(closed)
+}
+
+fn send_request<SPI, CS, E>(w5500: &mut W5500<SPI, CS>, request: &[u8]) -> Result<(), PostError>
+where
+    SPI: spi::Write<u8, Error = E> + spi::Transfer<u8, Error = E>,
+    CS: OutputPin,
+{
+    w5500.send(request).map_err(PostError::Net)?;
+    let mut answer = [0; 64];
+    let mut len = 0;
+    let sent = monotonic::millis();
+    loop {
+        if let Some(code) = http::status(&answer[..len]) {
+            return if http::is_success(code) {
+                Ok(())
+            } else {
+                Err(PostError::Status(code))
+            };
+        }
+        if len == answer.len() {
+            return Err(PostError::BadAnswer);
+        }
+        if monotonic::millis().wrapping_sub(sent) >= ANSWER_TIMEOUT_MS {
+            return Err(PostError::Timeout);
+        }
+        match w5500.receive(&mut answer[len..]).map_err(PostError::Net)? {
+            Some(received) => len += received,
+            // The server closed the connection without a status line.
+            None if !w5500.connected().map_err(PostError::Net)? => {
+                return Err(PostError::BadAnswer);
+            }
+            None => (),
+        }
+    }
+}
+
+/// Post the queued samples, oldest first, and return how many were sent.
+fn flush<S, SPI, CS, E>(
+    queue: &mut RecordQueue<S, SAMPLE_LEN>,
+    w5500: &mut W5500<SPI, CS>,
+    connections: &mut u16,
+) -> Result<u32, PostError>
+where
+    S: Storage,
+    SPI: spi::Write<u8, Error = E> + spi::Transfer<u8, Error = E>,
+    CS: OutputPin,
+{
+    let mut sent = 0;
+    while let Some(record) = queue.peek().map_err(|_| PostError::Storage)? {
+        let sample = Sample::from_bytes(&record);
+        let mut body: String<64> = String::new();
+        write!(
+            body,
+            "{{\"uptime_s\":{},\"temperature\":{:.2}}}",
+            sample.uptime_s, sample.temperature
+        )
+        .map_err(|_| PostError::TooLong)?;
+        // The server may still hold the last connection from a port, so
+        // use the next one each time.
+        *connections = connections.wrapping_add(1);
+        post(w5500, LOCAL_PORT_MIN + *connections % 16384, &body)?;
+        queue.pop().map_err(|_| PostError::Storage)?;
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("HTTP post W5500 FRAM queue example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // TMP112 sensor and FRAM
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x48, FRAM_ADDRESS]),
+    );
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let mut sensor = Tmp1x2::new(manager.acquire(), SlaveAddr::default());
+    let fram = Fram::new(manager.acquire(), FRAM_ADDRESS, FRAM_SIZE);
+    let mut queue: RecordQueue<_, SAMPLE_LEN> = RecordQueue::restore(fram).unwrap();
+    log_info!(
+        "{} of {} samples queued from before",
+        queue.len(),
+        queue.capacity()
+    );
+
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+    let cs = gpioa.pa4.into_push_pull_output(&mut gpioa.crl);
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        Mode {
+            polarity: Polarity::IdleLow,
+            phase: Phase::CaptureOnFirstTransition,
+        },
+        9.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    // Hardware reset: at least 500us low, then the PLL of the W5500 needs 1ms.
+    let mut reset = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+    reset.set_low().unwrap();
+    monotonic::wait_ms(1);
+    reset.set_high().unwrap();
+    monotonic::wait_ms(2);
+    let mut w5500 = W5500::new(spi, cs, &NET_CONFIG).unwrap();
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut connections = 0;
+    let mut backoff = 1;
+    let mut skip = 0;
+    loop {
+        // LED 0 is on while the example is busy.
+        // If it stays on, something went wrong.
+        led.set_low().unwrap();
+        let start = monotonic::millis();
+        match sensor.read_temperature() {
+            Ok(temperature) => {
+                let sample = Sample {
+                    uptime_s: start / 1000,
+                    temperature,
+                };
+                match queue.push(&sample.to_bytes()) {
+                    Ok(true) => log_warn!("Queue full, dropped the oldest sample"),
+                    Ok(false) => (),
+                    Err(e) => log_warn!("FRAM error: {:?}", e),
+                }
+            }
+            Err(e) => log_warn!("Sensor error: {:?}", e),
+        }
+
+        if skip > 0 {
+            skip -= 1;
+        } else if !w5500.link_up().unwrap_or(false) {
+            log_warn!("No Ethernet link, {} samples queued", queue.len());
+        } else {
+            match flush(&mut queue, &mut w5500, &mut connections) {
+                Ok(sent) => {
+                    log_info!("Posted {} samples", sent);
+                    backoff = 1;
+                }
+                Err(e) => {
+                    log_warn!(
+                        "Posting failed: {:?}, {} samples queued, retrying in {} intervals",
+                        e,
+                        queue.len(),
+                        backoff
+                    );
+                    skip = backoff - 1;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+        led.set_high().unwrap();
+
+        let interval_ms = SAMPLE_INTERVAL_S * 1000;
+        while monotonic::millis().wrapping_sub(start) < interval_ms {
+            cortex_m::asm::wfi();
+        }
+    }
+}
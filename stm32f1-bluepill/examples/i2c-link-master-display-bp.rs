@@ -0,0 +1,179 @@
+//! Read the measurements of another board through I2C with the `I2cLink`
+//! driver, like any other sensor, and show them on an SSD1306 OLED display
+//! which shares the bus. The other board runs the i2c-link-slave-sensor-bp
+//! example.
+//!
+//! At startup the WHO_AM_I register is checked and the slave is told to
+//! measure every 250ms. Then the measurements are read twice per second.
+//! The master turns the LED of the slave on while the voltage on its PA0 is
+//! above 1.65V, to show writing to the register map. A measurement with the
+//! same sample number as the previous one is marked as old.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP  <-> Slave       <-> Display
+//! GND <-> GND         <-> GND
+//! 3.3V                <-> VDD
+//! PB8 <-> SCL (PB10)  <-> SCL
+//! PB9 <-> SDA (PB11)  <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example i2c-link-master-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_link::{self, I2cLink, WHO_AM_I_VALUE},
+    i2c_speed, log_error, log_info, monotonic,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{pac, prelude::*};
+
+const READ_INTERVAL_MS: u32 = 500;
+const SLAVE_INTERVAL_MS: u32 = 250;
+const LED_THRESHOLD_MV: u16 = 1650;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("I2C link master example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[i2c_link::ADDRESS, 0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut link = I2cLink::new(manager.acquire());
+    let mut lines: [String<32>; 5] = Default::default();
+    match link.who_am_i() {
+        Ok(WHO_AM_I_VALUE) => write!(lines[0], "Slave found").unwrap(),
+        Ok(value) => write!(lines[0], "Wrong WHO_AM_I: {:02x}", value).unwrap(),
+        Err(e) => {
+            log_error!("Slave not found: {:?}", e);
+            write!(lines[0], "Slave not found").unwrap();
+        }
+    }
+    log_info!("{}", lines[0]);
+    if let Err(e) = link.set_interval_ms(SLAVE_INTERVAL_MS) {
+        log_error!("Setting the interval failed: {:?}", e);
+    }
+
+    let mut previous_sample = None;
+    let mut remote_led = None;
+    let mut led_on = false;
+    loop {
+        monotonic::wait_ms(READ_INTERVAL_MS);
+
+        for line in lines[1..].iter_mut() {
+            line.clear();
+        }
+        match link.read_measurements() {
+            Ok(m) => {
+                let old = if previous_sample == Some(m.sample) {
+                    " (old)"
+                } else {
+                    ""
+                };
+                previous_sample = Some(m.sample);
+                write!(lines[1], "Sample: {}{}", m.sample, old).unwrap();
+                write!(
+                    lines[2],
+                    "Temperature: {:.1}C",
+                    f32::from(m.temperature) / 10.0
+                )
+                .unwrap();
+                write!(lines[3], "PA0: {}mV", m.analog_mv).unwrap();
+                write!(lines[4], "Uptime: {}s", m.uptime_s).unwrap();
+
+                let on = m.analog_mv > LED_THRESHOLD_MV;
+                if remote_led != Some(on) && link.set_led(on).is_ok() {
+                    remote_led = Some(on);
+                }
+            }
+            Err(e) => {
+                log_error!("Read failed: {:?}", e);
+                write!(lines[1], "Read failed").unwrap();
+                previous_sample = None;
+                remote_led = None;
+            }
+        }
+
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 12))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led_on = !led_on;
+        if led_on {
+            led.set_high().unwrap();
+        } else {
+            led.set_low().unwrap();
+        }
+    }
+}
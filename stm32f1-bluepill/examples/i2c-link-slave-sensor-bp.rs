@@ -0,0 +1,184 @@
+//! Expose the measurements of this board through an I2C slave register map,
+//! so that another board can read them like any other sensor. The
+//! i2c-link-master-display-bp example is the other side of the link.
+//!
+//! The register map is described in the `i2c_link` module: a WHO_AM_I
+//! register, the measurements (internal temperature sensor, the voltage on
+//! PA0 and the uptime), and two registers the master can write: the LED and
+//! the time between measurements.
+//!
+//! I2C2 is set up as a slave with the `i2c_slave` module. Every address
+//! match, byte and stop condition is handled in the I2C2 event interrupt and
+//! passed to the `RegisterMap`. Clock stretching holds the master until the
+//! interrupt has answered.
+//!
+//! The LED is controlled by the master. The measurements and the number of
+//! transactions are printed through RTT.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C2.
+//!
+//! ```
+//! BP   <-> Master      <-> Potentiometer
+//! GND  <-> GND         <-> GND
+//! 3.3V                 <-> VCC
+//! PB10 <-> SCL (PB8)
+//! PB11 <-> SDA (PB9)
+//! PA0                  <-> Wiper
+//! ```
+//!
+//! Connect a 4.7K pull-up resistor from each of SCL and SDA to 3.3V.
+//!
+//! Run with:
+//! `cargo embed --example i2c-link-slave-sensor-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    convert::{adc_to_millivolts, average},
+    i2c_link::{self, Measurements, RegisterMap},
+    i2c_slave::I2c2Slave,
+    log_info, panic_display as _,
+};
+use embedded_hal::digital::v2::OutputPin;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    adc::Adc,
+    gpio::{gpioa::PA0, gpioc::PC13, Analog, Output, PushPull, State},
+    pac,
+    prelude::*,
+};
+
+const SYSCLK_MHZ: u32 = 72;
+const PCLK1_MHZ: u32 = 36;
+const POLL_MS: u32 = 10;
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        slave: I2c2Slave,
+        map: RegisterMap,
+        transactions: u32,
+        bus_errors: u32,
+        adc: Adc<pac::ADC1>,
+        analog: PA0<Analog>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("I2C link slave example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the I2C2 clock before handing the RCC over to the HAL.
+        device.RCC.apb1enr.modify(|_, w| w.i2c2en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(PCLK1_MHZ.mhz())
+            .adcclk(12.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let _slave_scl = gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh);
+        let _slave_sda = gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh);
+        let slave = I2c2Slave::new(device.I2C2, i2c_link::ADDRESS, clocks);
+
+        let adc = Adc::adc1(device.ADC1, &mut rcc.apb2, clocks);
+        let analog = gpioa.pa0.into_analog(&mut gpioa.crl);
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            slave,
+            map: RegisterMap::new(),
+            transactions: 0,
+            bus_errors: 0,
+            adc,
+            analog,
+            led,
+        }
+    }
+
+    /// Pass the address match, the received and requested bytes and the
+    /// stop condition to the register map.
+    #[task(binds = I2C2_EV, priority = 2, resources = [slave, map, transactions])]
+    fn i2c2_ev(cx: i2c2_ev::Context) {
+        if cx.resources.slave.on_event(cx.resources.map) {
+            *cx.resources.transactions += 1;
+        }
+    }
+
+    #[task(binds = I2C2_ER, priority = 2, resources = [slave, map, bus_errors])]
+    fn i2c2_er(cx: i2c2_er::Context) {
+        if cx.resources.slave.on_error(cx.resources.map) {
+            *cx.resources.bus_errors += 1;
+        }
+    }
+
+    #[idle(resources = [map, transactions, bus_errors, adc, analog, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let adc = cx.resources.adc;
+        let analog = cx.resources.analog;
+        let led = cx.resources.led;
+
+        let mut measurements = Measurements::default();
+        let mut last_poll = DWT::get_cycle_count();
+        let mut elapsed_ms = 0;
+        let mut since_measurement_ms = u32::MAX;
+        loop {
+            if DWT::get_cycle_count().wrapping_sub(last_poll) < POLL_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_poll = last_poll.wrapping_add(POLL_MS * 1000 * SYSCLK_MHZ);
+            elapsed_ms += POLL_MS;
+            since_measurement_ms = since_measurement_ms.saturating_add(POLL_MS);
+
+            let (led_on, interval_ms) = cx.resources.map.lock(|map| (map.led(), map.interval_ms()));
+            if led_on {
+                led.set_low().unwrap();
+            } else {
+                led.set_high().unwrap();
+            }
+            if since_measurement_ms < interval_ms {
+                continue;
+            }
+            since_measurement_ms = 0;
+
+            let raw = average((0..16).map(|_| adc.read(analog).unwrap_or(0)));
+            measurements.sample = measurements.sample.wrapping_add(1);
+            measurements.temperature = (adc.read_temp() * 10) as i16;
+            measurements.analog_mv = adc_to_millivolts(raw) as u16;
+            measurements.uptime_s = elapsed_ms / 1000;
+            cx.resources.map.lock(|map| map.update(&measurements));
+
+            let transactions = cx.resources.transactions.lock(|t| *t);
+            let errors = cx.resources.bus_errors.lock(|e| *e);
+            log_info!(
+                "{:?}, {} transactions, {} bus errors",
+                measurements,
+                transactions,
+                errors
+            );
+        }
+    }
+};
@@ -0,0 +1,155 @@
+//! Listen to the traffic on an I2C bus between another master and its
+//! devices and print every transaction through RTT, to find out why a
+//! driver does not work: a wrong address, a missing ACK, the wrong register
+//! or a stop where a repeated start was expected.
+//!
+//! The I2C peripheral of the STM32F103 only answers to its own address, so
+//! it can not watch other traffic. Instead PB12 and PB13 are connected to
+//! SCL and SDA of the bus as floating inputs, which do not disturb it. The
+//! main loop reads both lines at once from the input data register as fast
+//! as it can and feeds them to the `I2cDecoder`. This is fast enough for
+//! 100kHz and 400kHz buses.
+//!
+//! The events of a transaction are collected and printed after its stop
+//! condition, one line per transaction, e.g.:
+//! ```text
+//! #12 S 0x48 W A | 00 A | Sr 0x48 R A | 19 A | 40 N | P
+//! ```
+//! `S` is a start, `Sr` a repeated start, `P` a stop, `W`/`R` the direction,
+//! `A` an ACK and `N` a NACK. A NACK after the address means that no device
+//! has that address. A NACK after the last byte read is how the master ends
+//! a read.
+//!
+//! Printing takes some time: a transaction which starts while the previous
+//! one is printed is missed. With the usual gaps between the transactions
+//! of a driver this is rare.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board.
+//!
+//! ```
+//! BP   <-> Monitored bus
+//! GND  <-> GND
+//! PB12 <-> SCL
+//! PB13 <-> SDA
+//! ```
+//!
+//! The monitored bus needs its own pull-up resistors and must use 3.3V or
+//! 5V (PB12 and PB13 are 5V tolerant).
+//!
+//! Run with:
+//! `cargo embed --example i2c-sniffer-rtt-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    i2c_sniffer::{I2cDecoder, I2cEvent},
+    log_info, log_warn, panic_display as _,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::{String, Vec};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{pac, prelude::*};
+
+const MAX_EVENTS: usize = 64;
+const SCL_MASK: u32 = 1 << 12;
+const SDA_MASK: u32 = 1 << 13;
+
+fn ack(ack: bool) -> char {
+    if ack {
+        'A'
+    } else {
+        'N'
+    }
+}
+
+fn write_event<W: Write>(w: &mut W, event: &I2cEvent) -> core::fmt::Result {
+    match event {
+        I2cEvent::Start => write!(w, "S "),
+        I2cEvent::RepeatedStart => write!(w, "| Sr "),
+        I2cEvent::Address {
+            address,
+            read,
+            ack: a,
+        } => write!(
+            w,
+            "0x{:02x} {} {} ",
+            address,
+            if *read { 'R' } else { 'W' },
+            ack(*a)
+        ),
+        I2cEvent::Data { value, ack: a } => write!(w, "| {:02x} {} ", value, ack(*a)),
+        I2cEvent::Stop => write!(w, "| P"),
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("I2C sniffer example");
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Enable the GPIOB clock before handing the RCC over to the HAL. GPIOB
+    // is not split: PB12 and PB13 stay floating inputs as after reset and
+    // are read together from the input data register.
+    dp.RCC.apb2enr.modify(|_, w| w.iopben().set_bit());
+    let gpiob = dp.GPIOB;
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let _clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .freeze(&mut flash.acr);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let mut decoder = I2cDecoder::new();
+    let mut events: Vec<I2cEvent, MAX_EVENTS> = Vec::new();
+    let mut dropped = 0;
+    let mut transactions = 0_u32;
+    let mut led_on = false;
+    loop {
+        let idr = gpiob.idr.read().bits();
+        let event = match decoder.sample(idr & SCL_MASK != 0, idr & SDA_MASK != 0) {
+            Some(event) => event,
+            None => continue,
+        };
+        if event == I2cEvent::Start {
+            events.clear();
+            dropped = 0;
+        }
+        if events.push(event).is_err() {
+            dropped += 1;
+        }
+        if event != I2cEvent::Stop {
+            continue;
+        }
+
+        transactions += 1;
+        // At most 9 characters per event
+        let mut line: String<{ MAX_EVENTS * 9 + 16 }> = String::new();
+        write!(line, "#{} ", transactions).unwrap();
+        for event in events.iter() {
+            write_event(&mut line, event).unwrap();
+        }
+        log_info!("{}", line);
+        if dropped > 0 {
+            log_warn!("{} more events not shown", dropped);
+        }
+
+        // Toggle LED 0 on every transaction to see that there is traffic.
+        led_on = !led_on;
+        if led_on {
+            led.set_high().unwrap();
+        } else {
+            led.set_low().unwrap();
+        }
+    }
+}
@@ -15,6 +15,10 @@
 //! PB6                 <-> RX
 //! ```
 //!
+//! Each line has the index, CO2, TVOC and resistance, separated by commas.
+//! With the `telemetry-json` feature each line is a JSON object with these
+//! fields instead, and the "start" line is left out.
+//!
 //! Run with:
 //! `cargo embed --example iaq-core-c-gas-voc-usart-logger-bp`,
 
@@ -22,14 +26,17 @@
 #![no_std]
 #![no_main]
 
+#[cfg(not(feature = "telemetry-json"))]
 use core::fmt::Write;
+#[cfg(feature = "telemetry-json")]
+use driver_examples_bluepill::telemetry;
+use driver_examples_bluepill::{bootloader::relocate_vector_table, log_info, panic_display as _};
 use embedded_hal::digital::v2::OutputPin;
 use iaq_core::{IaqCore, Measurement};
 use nb::block;
-use panic_rtt_target as _;
 use rtic::app;
 use rtic::cyccnt::U32Ext;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use stm32f1xx_hal::{
     gpio::{
         gpiob::{PB8, PB9},
@@ -45,6 +52,15 @@ use stm32f1xx_hal::{
 const PERIOD: u32 = 1_000_000_000; // 10 seconds
 type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
 
+/// One line sent through the serial interface
+#[cfg_attr(feature = "telemetry-json", derive(serde::Serialize))]
+pub struct Sample {
+    index: usize,
+    co2: u16,
+    tvoc: u16,
+    resistance: u32,
+}
+
 #[app(device = stm32f1xx_hal::pac, peripherals = true, monotonic = rtic::cyccnt::CYCCNT)]
 const APP: () = {
     struct Resources {
@@ -56,9 +72,10 @@ const APP: () = {
     #[init(schedule = [measure])]
     fn init(cx: init::Context) -> init::LateResources {
         rtt_init_print!();
-        rprintln!("iAQ-Core-C example");
+        log_info!("iAQ-Core-C example");
 
         let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
         core.DWT.enable_cycle_counter();
 
         let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
@@ -114,6 +131,7 @@ const APP: () = {
         cx.schedule.measure(cx.start + PERIOD.cycles()).unwrap();
 
         let (mut tx, _rx) = serial.split();
+        #[cfg(not(feature = "telemetry-json"))]
         writeln!(tx, "start\r",).unwrap();
         init::LateResources { led, sensor, tx }
     }
@@ -149,10 +167,19 @@ const APP: () = {
         }
         for i in 0..*INDEX {
             let data = MEASUREMENTS[i];
+            let sample = Sample {
+                index: i,
+                co2: data.co2,
+                tvoc: data.tvoc,
+                resistance: data.resistance,
+            };
+            #[cfg(feature = "telemetry-json")]
+            telemetry::write_line::<_, _, 96>(cx.resources.tx, &sample).unwrap();
+            #[cfg(not(feature = "telemetry-json"))]
             writeln!(
                 cx.resources.tx,
                 "{},{},{},{}\r",
-                i, data.co2, data.tvoc, data.resistance
+                sample.index, sample.co2, sample.tvoc, sample.resistance
             )
             .unwrap();
         }
@@ -0,0 +1,472 @@
+//! Combination lock with a 4x4 keypad read through an MCP23017 I/O expander,
+//! a 16x2 HD44780 character LCD, PIN codes stored in an AT24C256 EEPROM and
+//! a relay driving an electric door strike.
+//!
+//! Enter a code and press `#` to open the door for `STRIKE_MS`. `*` clears
+//! the entry. The first code slot holds the admin code, which is set to
+//! `DEFAULT_ADMIN_CODE` if the EEPROM contains no valid admin code.
+//! - `A`, admin code, `#`, new code, `#`: add a user code.
+//! - `D`, admin code, `#`: delete all user codes.
+//!
+//! After `MAX_ATTEMPTS` wrong codes in a row the keypad is locked for
+//! `LOCKOUT_MS`, doubling with every further wrong code. The number of wrong
+//! codes is stored in the EEPROM too, so that restarting the board does not
+//! end the lockout.
+//!
+//! Each code is stored in a 16-byte slot with a CRC-32 checksum calculated
+//! with the STM32 hardware CRC unit. Slots with a wrong checksum are treated
+//! as empty.
+//!
+//! The keypad rows are connected to GPA0-GPA3 and the columns to GPB0-GPB3
+//! of the MCP23017, which pulls the columns up. To scan a row, only that row
+//! is driven low and the columns of the keys pressed in that row read low.
+//! A minimal MCP23017 keypad driver is included in this example.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> MCP23017  <-> LCD (PCF8574) <-> AT24C256 <-> Relay
+//! GND  <-> GND       <-> GND           <-> GND      <-> GND
+//! 3.3V <-> VDD                         <-> VCC
+//! 5V                 <-> VCC                        <-> VCC
+//! PB8  <-> SCL       <-> SCL           <-> SCL
+//! PB9  <-> SDA       <-> SDA           <-> SDA
+//! 3.3V <-> RESET
+//! GND  <-> A0, A1, A2                  <-> A0, A1, A2, WP
+//! PB12                                              <-> IN
+//!          GPA0-GPA3 <-> Keypad rows
+//!          GPB0-GPB3 <-> Keypad columns
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example keypad-lcd-combination-lock-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{crc::Crc32, log_info, log_warn, panic_display as _};
+use eeprom24x::{Eeprom24x, SlaveAddr as EepromAddr};
+use embedded_hal::{blocking::i2c, digital::v2::OutputPin};
+use hd44780_driver::{Cursor, CursorBlink, Display, DisplayMode, HD44780};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    crc::CrcExt,
+    delay::Delay,
+    i2c::{BlockingI2c, DutyCycle, Mode},
+    pac,
+    prelude::*,
+};
+
+const DEFAULT_ADMIN_CODE: &str = "1234";
+const MIN_CODE_LEN: usize = 4;
+const MAX_CODE_LEN: usize = 8;
+const MAX_ATTEMPTS: u8 = 3;
+const LOCKOUT_MS: u32 = 30_000;
+const STRIKE_MS: u32 = 3000;
+const MESSAGE_MS: u32 = 2000;
+/// Number of scans a key must be pressed to count
+const DEBOUNCE_SCANS: u8 = 3;
+const LCD_ADDRESS: u8 = 0x27;
+const KEYPAD_ADDRESS: u8 = 0x20;
+
+const SLOT_SIZE: u32 = 16;
+const SLOTS: usize = 4;
+const ADMIN_SLOT: usize = 0;
+const FAILURES_ADDRESS: u32 = SLOTS as u32 * SLOT_SIZE;
+
+type Code = String<MAX_CODE_LEN>;
+
+const KEYS: [[u8; 4]; 4] = [*b"123A", *b"456B", *b"789C", *b"*0#D"];
+
+// MCP23017 registers (IOCON.BANK = 0)
+const IODIRA: u8 = 0x00;
+const IODIRB: u8 = 0x01;
+const GPPUB: u8 = 0x0D;
+const GPIOB: u8 = 0x13;
+const OLATA: u8 = 0x14;
+
+/// Minimal driver for a 4x4 keypad matrix on an MCP23017
+struct Keypad<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C, E> Keypad<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    fn new(i2c: I2C, address: u8) -> Self {
+        Keypad { i2c, address }
+    }
+
+    fn init(&mut self) -> Result<(), E> {
+        // The rows are inputs except while they are scanned. Then they
+        // output the low level latched here.
+        self.write_register(OLATA, 0)?;
+        self.write_register(IODIRA, 0xFF)?;
+        self.write_register(IODIRB, 0xFF)?;
+        self.write_register(GPPUB, 0x0F)
+    }
+
+    /// Return the first key pressed, if any.
+    fn scan(&mut self) -> Result<Option<u8>, E> {
+        let mut pressed = None;
+        for (row, keys) in KEYS.iter().enumerate() {
+            // Only one row drives at a time, so that pressing several keys
+            // cannot short two outputs.
+            self.write_register(IODIRA, !(1 << row))?;
+            let columns = self.read_register(GPIOB)?;
+            pressed = keys
+                .iter()
+                .enumerate()
+                .find(|(column, _)| columns & (1 << column) == 0)
+                .map(|(_, key)| *key);
+            if pressed.is_some() {
+                break;
+            }
+        }
+        self.write_register(IODIRA, 0xFF)?;
+        Ok(pressed)
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), E> {
+        self.i2c.write(self.address, &[register, value])
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, E> {
+        let mut data = [0];
+        self.i2c
+            .write_read(self.address, &[register], &mut data)
+            .and(Ok(data[0]))
+    }
+}
+
+/// Code slot as stored in the EEPROM: length, digits and checksum
+fn encode_slot<C: Crc32>(code: &str, crc: &mut C) -> [u8; SLOT_SIZE as usize] {
+    let mut bytes = [0; SLOT_SIZE as usize];
+    bytes[0] = code.len() as u8;
+    bytes[1..=code.len()].copy_from_slice(code.as_bytes());
+    let checksum = crc.checksum(&bytes[..12]);
+    bytes[12..16].copy_from_slice(&checksum.to_le_bytes());
+    bytes
+}
+
+/// The code of a slot or `None` if it is empty or corrupted
+fn decode_slot<C: Crc32>(bytes: &[u8; SLOT_SIZE as usize], crc: &mut C) -> Option<Code> {
+    let checksum = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+    let len = usize::from(bytes[0]);
+    if crc.checksum(&bytes[..12]) != checksum || len == 0 || len > MAX_CODE_LEN {
+        return None;
+    }
+    let mut code = Code::new();
+    for digit in &bytes[1..=len] {
+        code.push(char::from(*digit)).ok()?;
+    }
+    Some(code)
+}
+
+/// Lockout time after `failures` wrong codes in a row
+fn lockout_ms(failures: u8) -> u32 {
+    if failures < MAX_ATTEMPTS {
+        0
+    } else {
+        LOCKOUT_MS << (failures - MAX_ATTEMPTS).min(4)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Code,
+    AddAuth,
+    AddNew,
+    DeleteAuth,
+}
+
+/// Millisecond clock from the DWT cycle counter, which does not wrap around
+/// as long as it is read at least every few minutes.
+struct Clock {
+    last_cycles: u32,
+    cycles_per_ms: u32,
+    ms: u32,
+}
+
+impl Clock {
+    fn now(&mut self) -> u32 {
+        let elapsed_ms = DWT::get_cycle_count().wrapping_sub(self.last_cycles) / self.cycles_per_ms;
+        self.last_cycles = self
+            .last_cycles
+            .wrapping_add(elapsed_ms * self.cycles_per_ms);
+        self.ms += elapsed_ms;
+        self.ms
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Keypad combination lock example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let mut strike = gpiob.pb12.into_push_pull_output(&mut gpiob.crh);
+    strike.set_low().unwrap();
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let i2c = BlockingI2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        Mode::Fast {
+            frequency: 100_000.hz(),
+            duty_cycle: DutyCycle::Ratio2to1,
+        },
+        clocks,
+        &mut rcc.apb1,
+        1000,
+        10,
+        1000,
+        1000,
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+    let mut clock = Clock {
+        last_cycles: DWT::get_cycle_count(),
+        cycles_per_ms: clocks.sysclk().0 / 1000,
+        ms: 0,
+    };
+
+    let mut crc = dp.CRC.new(&mut rcc.ahb);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let mut lcd = HD44780::new_i2c(manager.acquire(), LCD_ADDRESS, &mut delay).unwrap();
+    lcd.reset(&mut delay).unwrap();
+    lcd.set_display_mode(
+        DisplayMode {
+            display: Display::On,
+            cursor_visibility: Cursor::Invisible,
+            cursor_blink: CursorBlink::Off,
+        },
+        &mut delay,
+    )
+    .unwrap();
+
+    let mut keypad = Keypad::new(manager.acquire(), KEYPAD_ADDRESS);
+    keypad.init().unwrap();
+
+    let mut eeprom = Eeprom24x::new_24x256(manager.acquire(), EepromAddr::default());
+    let mut codes: [Option<Code>; SLOTS] = Default::default();
+    for (i, code) in codes.iter_mut().enumerate() {
+        let mut bytes = [0; SLOT_SIZE as usize];
+        if eeprom.read_data(i as u32 * SLOT_SIZE, &mut bytes).is_ok() {
+            *code = decode_slot(&bytes, &mut crc);
+        }
+    }
+    if codes[ADMIN_SLOT].is_none() {
+        log_warn!("No admin code found. Setting the default code.");
+        eeprom
+            .write_page(0, &encode_slot(DEFAULT_ADMIN_CODE, &mut crc))
+            .unwrap();
+        delay.delay_ms(5_u16);
+        codes[ADMIN_SLOT] = Some(Code::from(DEFAULT_ADMIN_CODE));
+    }
+    let mut failures = match eeprom.read_byte(FAILURES_ADDRESS) {
+        Ok(0xFF) | Err(_) => 0,
+        Ok(failures) => failures,
+    };
+    let mut lockout_until = match lockout_ms(failures) {
+        0 => None,
+        ms => Some(clock.now() + ms),
+    };
+
+    let mut step = Step::Code;
+    let mut entry = Code::new();
+    let mut open_until = None;
+    let mut message: Option<(&'static str, u32)> = None;
+    let mut last_key = None;
+    let mut stable_scans = 0;
+    let mut shown: [String<16>; 2] = Default::default();
+    let mut lines: [String<16>; 2] = Default::default();
+    loop {
+        let now = clock.now();
+
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        if now % 1000 < 500 {
+            led.set_high().unwrap();
+        } else {
+            led.set_low().unwrap();
+        }
+
+        if open_until.map_or(false, |until| now >= until) {
+            open_until = None;
+            strike.set_low().unwrap();
+        }
+        if lockout_until.map_or(false, |until| now >= until) {
+            lockout_until = None;
+        }
+        if message.map_or(false, |(_, until)| now >= until) {
+            message = None;
+        }
+
+        // Debounce the keypad: a key counts once it has been read the same
+        // for a few scans in a row.
+        let key = keypad.scan().unwrap_or(None);
+        let pressed = if key != last_key {
+            last_key = key;
+            stable_scans = 1;
+            None
+        } else if stable_scans < DEBOUNCE_SCANS {
+            stable_scans += 1;
+            key.filter(|_| stable_scans == DEBOUNCE_SCANS && lockout_until.is_none())
+        } else {
+            None
+        };
+
+        let mut wrong_code = false;
+        let mut correct_code = false;
+        match pressed {
+            Some(digit @ b'0'..=b'9') => {
+                entry.push(char::from(digit)).ok();
+            }
+            Some(b'*') => {
+                entry.clear();
+                step = Step::Code;
+            }
+            Some(b'A') => {
+                entry.clear();
+                step = Step::AddAuth;
+            }
+            Some(b'D') => {
+                entry.clear();
+                step = Step::DeleteAuth;
+            }
+            Some(b'#') => {
+                let is_admin = codes[ADMIN_SLOT].as_ref() == Some(&entry);
+                match step {
+                    Step::Code => {
+                        if codes.iter().any(|code| code.as_ref() == Some(&entry)) {
+                            log_info!("Open");
+                            correct_code = true;
+                            strike.set_high().unwrap();
+                            open_until = Some(now + STRIKE_MS);
+                        } else {
+                            wrong_code = true;
+                        }
+                    }
+                    Step::AddAuth if is_admin => {
+                        correct_code = true;
+                        step = Step::AddNew;
+                    }
+                    Step::DeleteAuth if is_admin => {
+                        correct_code = true;
+                        let empty = encode_slot("", &mut crc);
+                        for (i, code) in codes.iter_mut().enumerate().skip(1) {
+                            eeprom.write_page(i as u32 * SLOT_SIZE, &empty).unwrap();
+                            delay.delay_ms(5_u16);
+                            *code = None;
+                        }
+                        message = Some(("Codes deleted", now + MESSAGE_MS));
+                        step = Step::Code;
+                    }
+                    Step::AddNew => {
+                        let free = codes.iter().position(|code| code.is_none());
+                        message = Some(match free {
+                            _ if entry.len() < MIN_CODE_LEN => ("Code too short", now + MESSAGE_MS),
+                            Some(i) => {
+                                eeprom
+                                    .write_page(
+                                        i as u32 * SLOT_SIZE,
+                                        &encode_slot(&entry, &mut crc),
+                                    )
+                                    .unwrap();
+                                delay.delay_ms(5_u16);
+                                codes[i] = Some(entry.clone());
+                                ("Code saved", now + MESSAGE_MS)
+                            }
+                            None => ("Memory full", now + MESSAGE_MS),
+                        });
+                        step = Step::Code;
+                    }
+                    Step::AddAuth | Step::DeleteAuth => {
+                        wrong_code = true;
+                        step = Step::Code;
+                    }
+                }
+                entry.clear();
+                // A correct code ends the series of wrong codes.
+                let new_failures = if wrong_code {
+                    failures.saturating_add(1)
+                } else if correct_code {
+                    0
+                } else {
+                    failures
+                };
+                if new_failures != failures {
+                    failures = new_failures;
+                    eeprom.write_byte(FAILURES_ADDRESS, failures).unwrap();
+                    delay.delay_ms(5_u16);
+                }
+            }
+            _ => (),
+        }
+        if wrong_code {
+            log_warn!("Wrong code ({} in a row)", failures);
+            message = Some(("Wrong code", now + MESSAGE_MS));
+            let ms = lockout_ms(failures);
+            if ms > 0 {
+                lockout_until = Some(now + ms);
+            }
+        }
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        if let Some(until) = lockout_until {
+            write!(lines[0], "Locked").unwrap();
+            write!(lines[1], "Wait {}s", (until - now + 999) / 1000).unwrap();
+        } else if open_until.is_some() {
+            write!(lines[0], "Open").unwrap();
+        } else if let Some((text, _)) = message {
+            write!(lines[0], "{}", text).unwrap();
+        } else {
+            let prompt = match step {
+                Step::Code => "Enter code:",
+                Step::AddAuth | Step::DeleteAuth => "Admin code:",
+                Step::AddNew => "New code:",
+            };
+            write!(lines[0], "{}", prompt).unwrap();
+            for _ in 0..entry.len() {
+                lines[1].push('*').unwrap();
+            }
+        }
+        // Only update the LCD when something changed to avoid flickering.
+        if lines != shown {
+            lcd.clear(&mut delay).unwrap();
+            lcd.write_str(&lines[0], &mut delay).unwrap();
+            lcd.set_cursor_pos(40, &mut delay).unwrap();
+            lcd.write_str(&lines[1], &mut delay).unwrap();
+            shown = lines.clone();
+        }
+        delay.delay_ms(10_u16);
+    }
+}
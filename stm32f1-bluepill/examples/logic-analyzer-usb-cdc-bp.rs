@@ -0,0 +1,308 @@
+//! A logic analyzer for up to 8 lines: sample PA0-PA7 at up to 1MHz and send
+//! the transitions to the computer through USB as a virtual serial port
+//! (CDC ACM). The `tools/la2vcd.py` script starts a capture and saves it as
+//! a VCD file, which can be opened in PulseView or GTKWave.
+//!
+//! The samples are taken without the CPU: every update event of TIM2
+//! requests a transfer on DMA1 channel 2, which copies the input data
+//! register of GPIOA into the capture buffer. Only the lower byte (PA0-PA7)
+//! is kept. When the buffer is full, only the changes are sent, so a slow
+//! signal gives little data even with many samples.
+//!
+//! Commands (one per line):
+//! - `CAPTURE <rate_hz> <samples> [channels]`: capture `samples` samples
+//!   (at most `MAX_SAMPLES`) at `rate_hz` (`MIN_RATE_HZ` to `MAX_RATE_HZ`)
+//!   of the first `channels` lines (default 8), then send them.
+//!
+//! Errors are answered with `ERR <error>`. A capture is sent as:
+//! - A header line: `LA1 <timer_hz> <ticks_per_sample> <samples> <channels>`.
+//!   The exact sample rate is `timer_hz / ticks_per_sample`.
+//! - One 5 byte record per change: the sample number as a 32-bit little
+//!   endian number and the state of the lines as a byte (bit 0 is PA0). The
+//!   first record is sample 0 with the initial state.
+//! - An end record with the sample number `samples` and the last state.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using USB.
+//!
+//! ```
+//! BP   <-> Signals
+//! GND  <-> GND
+//! PA0  <-> Line 0
+//! PA1  <-> Line 1
+//! ...
+//! PA7  <-> Line 7
+//! ```
+//!
+//! The inputs are floating and must be between 0V and 3.3V. The LED is on
+//! while capturing. Example with the script:
+//! `python3 tools/la2vcd.py /dev/ttyACM0 --rate 1000000 --samples 8192 capture.vcd`
+//!
+//! Run with:
+//! `cargo embed --example logic-analyzer-usb-cdc-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::{
+    fmt::Write,
+    sync::atomic::{compiler_fence, Ordering},
+};
+use cortex_m::{asm::delay, singleton};
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{log_info, panic_display as _};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::{String, Vec};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    pac,
+    prelude::*,
+    usb::{Peripheral, UsbBus},
+};
+use usb_device::{bus::UsbBus as Bus, prelude::*};
+use usbd_serial::{SerialPort, USB_CLASS_CDC};
+
+const TIMER_HZ: u32 = 72_000_000;
+const MAX_SAMPLES: usize = 8192;
+const MIN_RATE_HZ: u32 = 10;
+const MAX_RATE_HZ: u32 = 1_000_000;
+const LINE_LEN: usize = 40;
+const RECORD_LEN: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Capture {
+        rate_hz: u32,
+        samples: usize,
+        channels: u8,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CommandError {
+    UnknownCommand,
+    MissingArgument,
+    InvalidNumber,
+    OutOfRange,
+}
+
+fn parse_number<T: core::str::FromStr>(text: Option<&str>) -> Result<T, CommandError> {
+    text.ok_or(CommandError::MissingArgument)?
+        .parse()
+        .map_err(|_| CommandError::InvalidNumber)
+}
+
+fn parse(line: &str) -> Result<Command, CommandError> {
+    let mut words = line.split_whitespace();
+    let command = words.next().ok_or(CommandError::UnknownCommand)?;
+    if command.eq_ignore_ascii_case("CAPTURE") {
+        let rate_hz = parse_number(words.next())?;
+        let samples = parse_number(words.next())?;
+        let channels = match words.next() {
+            Some(text) => parse_number(Some(text))?,
+            None => 8,
+        };
+        if !(MIN_RATE_HZ..=MAX_RATE_HZ).contains(&rate_hz)
+            || !(1..=MAX_SAMPLES).contains(&samples)
+            || !(1..=8).contains(&channels)
+        {
+            return Err(CommandError::OutOfRange);
+        }
+        return Ok(Command::Capture {
+            rate_hz,
+            samples,
+            channels,
+        });
+    }
+    Err(CommandError::UnknownCommand)
+}
+
+/// Set TIM2 to overflow at `rate_hz` and return the timer ticks per sample.
+fn set_rate(timer: &pac::TIM2, rate_hz: u32) -> u32 {
+    let ticks = TIMER_HZ / rate_hz;
+    let prescaler = ticks / 65536 + 1;
+    let period = ticks / prescaler;
+    timer.psc.write(|w| w.psc().bits((prescaler - 1) as u16));
+    timer.arr.write(|w| w.arr().bits((period - 1) as u16));
+    // Load the new values now. The DMA request of this update is not
+    // enabled yet.
+    timer.egr.write(|w| w.ug().set_bit());
+    prescaler * period
+}
+
+/// Send all of `data`, polling the USB device while the serial port is busy.
+/// Gives up if the device is no longer configured.
+fn write_all<B: Bus>(usb_dev: &mut UsbDevice<B>, serial: &mut SerialPort<B>, data: &[u8]) {
+    let mut data = data;
+    while !data.is_empty() {
+        if usb_dev.state() != UsbDeviceState::Configured {
+            return;
+        }
+        usb_dev.poll(&mut [&mut *serial]);
+        match serial.write(data) {
+            Ok(written) => data = &data[written..],
+            Err(UsbError::WouldBlock) => (),
+            Err(_) => return,
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Logic analyzer example");
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Enable the TIM2 clock before handing the RCC over to the HAL.
+    dp.RCC.apb1enr.modify(|_, w| w.tim2en().set_bit());
+    // The DMA reads the input register of GPIOA directly.
+    let idr_address = &dp.GPIOA.idr as *const _ as u32;
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    // USB needs a 48MHz clock: 72MHz / 1.5
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    assert!(clocks.usbclk_valid());
+
+    // PA0-PA7 stay floating inputs as after reset.
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    led.set_high().unwrap();
+
+    // The Bluepill has a pull-up resistor on D+. Pull D+ low for a moment
+    // so that the computer notices the device after a reset.
+    let mut usb_dp = gpioa.pa12.into_push_pull_output(&mut gpioa.crh);
+    usb_dp.set_low().unwrap();
+    delay(clocks.sysclk().0 / 100);
+    let usb = Peripheral {
+        usb: dp.USB,
+        pin_dm: gpioa.pa11,
+        pin_dp: usb_dp.into_floating_input(&mut gpioa.crh),
+    };
+    let usb_bus = UsbBus::new(usb);
+    let mut serial = SerialPort::new(&usb_bus);
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .manufacturer("driver-examples")
+        .product("Logic analyzer")
+        .serial_number("LA1")
+        .device_class(USB_CLASS_CDC)
+        .build();
+
+    let timer = dp.TIM2;
+    let mut dma_ch2 = dp.DMA1.split(&mut rcc.ahb).2;
+    dma_ch2.set_peripheral_address(idr_address, false);
+    // Read 32 bits from the peripheral, write the lower 8 bits to memory.
+    dma_ch2.ch().cr.modify(|_, w| {
+        w.dir()
+            .clear_bit()
+            .psize()
+            .bits32()
+            .msize()
+            .bits8()
+            .circ()
+            .clear_bit()
+            .pl()
+            .very_high()
+    });
+    let buffer = singleton!(: [u8; MAX_SAMPLES] = [0; MAX_SAMPLES]).unwrap();
+
+    let mut line: String<LINE_LEN> = String::new();
+    loop {
+        if !usb_dev.poll(&mut [&mut serial]) {
+            continue;
+        }
+        let mut received = [0_u8; 64];
+        let count = match serial.read(&mut received) {
+            Ok(count) => count,
+            Err(_) => continue,
+        };
+        for byte in received[..count].iter() {
+            if *byte != b'\r' && *byte != b'\n' {
+                // Too long lines are cut.
+                line.push(*byte as char).ok();
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let command = parse(&line);
+            line.clear();
+            let (rate_hz, samples, channels) = match command {
+                Ok(Command::Capture {
+                    rate_hz,
+                    samples,
+                    channels,
+                }) => (rate_hz, samples, channels),
+                Err(e) => {
+                    let mut reply: String<32> = String::new();
+                    writeln!(reply, "ERR {:?}", e).unwrap();
+                    write_all(&mut usb_dev, &mut serial, reply.as_bytes());
+                    continue;
+                }
+            };
+
+            log_info!("Capturing {} samples at {}Hz", samples, rate_hz);
+            let ticks = set_rate(&timer, rate_hz);
+            dma_ch2.stop();
+            dma_ch2.set_memory_address(buffer.as_ptr() as u32, true);
+            dma_ch2.set_transfer_length(samples);
+            dma_ch2.start();
+            led.set_low().unwrap();
+            timer.dier.modify(|_, w| w.ude().set_bit());
+            timer.cr1.modify(|_, w| w.cen().enabled());
+            // Keep answering the computer during long captures.
+            while dma_ch2.in_progress() {
+                usb_dev.poll(&mut [&mut serial]);
+            }
+            timer.cr1.modify(|_, w| w.cen().disabled());
+            timer.dier.modify(|_, w| w.ude().clear_bit());
+            dma_ch2.stop();
+            led.set_high().unwrap();
+            // The buffer was written by the DMA.
+            compiler_fence(Ordering::SeqCst);
+
+            let mut header: String<64> = String::new();
+            writeln!(
+                header,
+                "LA1 {} {} {} {}",
+                TIMER_HZ, ticks, samples, channels
+            )
+            .unwrap();
+            write_all(&mut usb_dev, &mut serial, header.as_bytes());
+
+            // Send the changes in packets of whole records.
+            let mask = ((1_u16 << channels) - 1) as u8;
+            let mut packet: Vec<u8, 60> = Vec::new();
+            let mut previous = None;
+            let mut changes = 0;
+            for (i, sample) in buffer[..samples].iter().enumerate() {
+                let state = *sample & mask;
+                if previous == Some(state) {
+                    continue;
+                }
+                previous = Some(state);
+                changes += 1;
+                packet.extend_from_slice(&(i as u32).to_le_bytes()).unwrap();
+                packet.push(state).unwrap();
+                if packet.len() + RECORD_LEN > packet.capacity() {
+                    write_all(&mut usb_dev, &mut serial, &packet);
+                    packet.clear();
+                }
+            }
+            packet
+                .extend_from_slice(&(samples as u32).to_le_bytes())
+                .unwrap();
+            packet.push(previous.unwrap_or(0)).unwrap();
+            write_all(&mut usb_dev, &mut serial, &packet);
+            log_info!("Sent {} changes", changes);
+        }
+    }
+}
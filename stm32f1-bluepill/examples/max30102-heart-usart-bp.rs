@@ -23,21 +23,15 @@
 
 use core::fmt::Write;
 use cortex_m_rt::entry;
+use driver_examples_bluepill::{i2c_speed, log_info, panic_display as _};
 use max3010x::{Led, LedPulseWidth, Max3010x, SampleAveraging, SamplingRate};
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
-use stm32f1xx_hal::{
-    delay::Delay,
-    i2c::{BlockingI2c, DutyCycle, Mode},
-    pac,
-    prelude::*,
-    serial,
-};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*, serial};
 
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("MAX30102 example");
+    log_info!("MAX30102 example");
 
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
@@ -57,20 +51,14 @@ fn main() -> ! {
     let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
     let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
 
-    let i2c = BlockingI2c::i2c1(
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
         dp.I2C1,
         (scl, sda),
         &mut afio.mapr,
-        Mode::Fast {
-            frequency: 400_000.hz(),
-            duty_cycle: DutyCycle::Ratio2to1,
-        },
         clocks,
         &mut rcc.apb1,
-        1000,
-        10,
-        1000,
-        1000,
+        // MAX30102 sensor
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x57]),
     );
 
     let tx = gpiob.pb6.into_alternate_push_pull(&mut gpiob.crl);
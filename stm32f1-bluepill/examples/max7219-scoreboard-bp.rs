@@ -0,0 +1,318 @@
+//! Two-player scoreboard on an 8-digit 7-segment display module with a
+//! MAX7219 controller.
+//!
+//! Each player has a button to score a point. The first player to reach
+//! `WINNING_SCORE` with a lead of two points wins and their score blinks.
+//! The leading player is marked with the decimal point. Another button
+//! starts a new game and the last one changes the brightness, which ramps
+//! smoothly to the new level. It also ramps up at startup.
+//!
+//! The display shows the scores like ` 11--  9`. Leading zeros are blanked
+//! using the "code B" font of the MAX7219, which includes a blank
+//! character. A minimal MAX7219 driver is included in this example.
+//!
+//! The buttons are sampled every 5ms and only count as pressed once they
+//! read the same for 8 samples in a row, which filters out the contact
+//! bounce. Sampling, ramping and blinking run at their own rates using the
+//! cooperative `scheduler` of this crate.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1.
+//!
+//! ```
+//! BP   <-> MAX7219 <-> Buttons
+//! GND  <-> GND     <-> GND
+//! 5V   <-> VCC
+//! PA4  <-> CS
+//! PA5  <-> CLK
+//! PA7  <-> DIN
+//! PB12             <-> Player 1
+//! PB13             <-> Player 2
+//! PB14             <-> New game
+//! PB15             <-> Brightness
+//! ```
+//!
+//! The MAX7219 needs 5V. Most modules accept the 3.3V signals of the board
+//! although the datasheet asks for 3.5V. Otherwise use a level shifter.
+//!
+//! Run with:
+//! `cargo embed --example max7219-scoreboard-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table, log_info, monotonic, panic_display as _,
+    scheduler::Scheduler,
+};
+use embedded_hal::{
+    blocking::spi,
+    digital::v2::{InputPin, OutputPin},
+    spi::MODE_0,
+};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{pac, prelude::*, spi::Spi};
+
+const WINNING_SCORE: u16 = 11;
+const MAX_SCORE: u16 = 999;
+const BRIGHTNESS_LEVELS: [u8; 4] = [1, 4, 8, 15];
+
+// Tasks in order of priority
+const BUTTONS: usize = 0;
+const RAMP: usize = 1;
+const BLINK: usize = 2;
+
+// MAX7219 registers
+const DECODE_MODE: u8 = 0x09;
+const INTENSITY: u8 = 0x0A;
+const SCAN_LIMIT: u8 = 0x0B;
+const SHUTDOWN: u8 = 0x0C;
+const DISPLAY_TEST: u8 = 0x0F;
+
+// Code B characters
+const DASH: u8 = 0x0A;
+const BLANK: u8 = 0x0F;
+const DECIMAL_POINT: u8 = 0x80;
+
+/// Minimal MAX7219 driver for an 8-digit 7-segment display
+struct Max7219<SPI, CS> {
+    spi: SPI,
+    cs: CS,
+}
+
+impl<SPI, CS, E> Max7219<SPI, CS>
+where
+    SPI: spi::Write<u8, Error = E>,
+    CS: OutputPin,
+{
+    fn new(spi: SPI, cs: CS) -> Self {
+        Max7219 { spi, cs }
+    }
+
+    fn init(&mut self) -> Result<(), E> {
+        self.write_register(DISPLAY_TEST, 0)?;
+        self.write_register(SCAN_LIMIT, 7)?;
+        // Code B font for all digits
+        self.write_register(DECODE_MODE, 0xFF)?;
+        self.set_intensity(0)?;
+        self.show(&[BLANK; 8])?;
+        self.write_register(SHUTDOWN, 1)
+    }
+
+    /// Set the brightness: 0-15
+    fn set_intensity(&mut self, intensity: u8) -> Result<(), E> {
+        self.write_register(INTENSITY, intensity & 0x0F)
+    }
+
+    /// Show code B characters, starting with the leftmost digit.
+    fn show(&mut self, digits: &[u8; 8]) -> Result<(), E> {
+        for (i, digit) in digits.iter().enumerate() {
+            // Digit 0 (register 1) is the rightmost one.
+            self.write_register(8 - i as u8, *digit)?;
+        }
+        Ok(())
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), E> {
+        self.cs.set_low().ok();
+        let result = self.spi.write(&[register, value]);
+        self.cs.set_high().ok();
+        result
+    }
+}
+
+/// Debounced button
+#[derive(Default)]
+struct Button {
+    history: u8,
+    pressed: bool,
+}
+
+impl Button {
+    /// Feed a new sample. Returns `true` when the button has just been
+    /// pressed.
+    fn update(&mut self, down: bool) -> bool {
+        self.history = (self.history << 1) | u8::from(down);
+        match self.history {
+            0xFF if !self.pressed => {
+                self.pressed = true;
+                true
+            }
+            0x00 => {
+                self.pressed = false;
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Code B digits of `score` right-aligned without leading zeros
+fn score_digits(score: u16) -> [u8; 3] {
+    let mut digits = [BLANK; 3];
+    let mut value = score;
+    for digit in digits.iter_mut().rev() {
+        *digit = (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    digits
+}
+
+fn winner(scores: &[u16; 2]) -> Option<usize> {
+    let [first, second] = *scores;
+    if first >= WINNING_SCORE && first >= second + 2 {
+        Some(0)
+    } else if second >= WINNING_SCORE && second >= first + 2 {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// Compose the display: scores of both players separated by dashes
+fn compose(scores: &[u16; 2], winner: Option<usize>, blink_on: bool) -> [u8; 8] {
+    let mut digits = [BLANK; 8];
+    digits[3] = DASH;
+    digits[4] = DASH;
+    for (player, score) in scores.iter().enumerate() {
+        if winner == Some(player) && !blink_on {
+            continue;
+        }
+        let start = if player == 0 { 0 } else { 5 };
+        let mut score_digits = score_digits(*score);
+        if *score > scores[1 - player] {
+            score_digits[2] |= DECIMAL_POINT;
+        }
+        digits[start..start + 3].copy_from_slice(&score_digits);
+    }
+    digits
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("MAX7219 scoreboard example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    // SPI1
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+    let mut cs = gpioa.pa4.into_push_pull_output(&mut gpioa.crl);
+    cs.set_high().unwrap();
+
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        MODE_0,
+        1_u32.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    let player1_pin = gpiob.pb12.into_pull_up_input(&mut gpiob.crh);
+    let player2_pin = gpiob.pb13.into_pull_up_input(&mut gpiob.crh);
+    let new_game_pin = gpiob.pb14.into_pull_up_input(&mut gpiob.crh);
+    let brightness_pin = gpiob.pb15.into_pull_up_input(&mut gpiob.crh);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let mut display = Max7219::new(spi, cs);
+    display.init().unwrap();
+
+    let mut buttons: [Button; 4] = Default::default();
+    let mut scores = [0_u16; 2];
+    let mut brightness_level = BRIGHTNESS_LEVELS.len() - 1;
+    let mut intensity = 0;
+    let mut blink_on = true;
+    let mut scheduler = Scheduler::new([5, 40, 250]);
+    loop {
+        match scheduler.poll() {
+            Some(BUTTONS) => {
+                // The buttons pull the inputs low when pressed.
+                let pins = [
+                    player1_pin.is_low().unwrap(),
+                    player2_pin.is_low().unwrap(),
+                    new_game_pin.is_low().unwrap(),
+                    brightness_pin.is_low().unwrap(),
+                ];
+                let mut pressed = [false; 4];
+                for ((button, down), pressed) in
+                    buttons.iter_mut().zip(pins.iter()).zip(pressed.iter_mut())
+                {
+                    *pressed = button.update(*down);
+                }
+                let game_over = winner(&scores).is_some();
+                for (player, score) in scores.iter_mut().enumerate() {
+                    if pressed[player] && !game_over && *score < MAX_SCORE {
+                        *score += 1;
+                    }
+                }
+                if pressed[2] {
+                    scores = [0, 0];
+                }
+                if pressed[3] {
+                    brightness_level = (brightness_level + 1) % BRIGHTNESS_LEVELS.len();
+                }
+                if pressed.iter().any(|p| *p) {
+                    log_info!("Score: {} - {}", scores[0], scores[1]);
+                    if let Some(player) = winner(&scores) {
+                        log_info!("Player {} wins", player + 1);
+                    }
+                    display
+                        .show(&compose(&scores, winner(&scores), blink_on))
+                        .unwrap();
+                }
+            }
+            Some(RAMP) => {
+                let target = BRIGHTNESS_LEVELS[brightness_level];
+                if intensity != target {
+                    if intensity < target {
+                        intensity += 1;
+                    } else {
+                        intensity -= 1;
+                    }
+                    display.set_intensity(intensity).unwrap();
+                }
+            }
+            Some(BLINK) => {
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                blink_on = !blink_on;
+                if blink_on {
+                    led.set_high().unwrap();
+                } else {
+                    led.set_low().unwrap();
+                }
+                display
+                    .show(&compose(&scores, winner(&scores), blink_on))
+                    .unwrap();
+            }
+            _ => (),
+        }
+    }
+}
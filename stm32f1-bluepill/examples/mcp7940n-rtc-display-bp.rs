@@ -1,6 +1,9 @@
 //! Stores the date and time on a MCP7940N real-time clock (RTC).
 //! Then continuously print the date and time.
 //!
+//! The date and time are fixed in the code. To set an RTC to the actual
+//! time, have a look at the sntp-w5500-ds3231-time-sync-bp example.
+//!
 //! Introductory blog post here:
 //! https://blog.eldruin.com/mcp794xx-real-time-clock-rtc-driver-in-rust/
 //!
@@ -23,6 +26,10 @@
 
 use core::fmt::Write;
 use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    clock, log_info,
+    panic_display::{self, Bus},
+};
 use embedded_graphics::{
     fonts::{Font6x8, Text},
     pixelcolor::BinaryColor,
@@ -31,8 +38,7 @@ use embedded_graphics::{
 };
 use embedded_hal::digital::v2::OutputPin;
 use mcp794xx::{Datelike, Mcp794xx, NaiveDate, Rtcc, Timelike};
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use ssd1306::{prelude::*, Builder, I2CDIBuilder};
 use stm32f1xx_hal::{
     delay::Delay,
@@ -44,14 +50,15 @@ use stm32f1xx_hal::{
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("MCP7940N example");
+    log_info!("MCP7940N example");
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
 
     let mut flash = dp.FLASH.constrain();
     let mut rcc = dp.RCC.constrain();
 
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
+    clock::log_clocks(&clocks);
 
     let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
 
@@ -84,6 +91,7 @@ fn main() -> ! {
     let interface = I2CDIBuilder::new().init(manager.acquire());
     let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
     disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
     disp.flush().unwrap();
 
     let text_style = TextStyleBuilder::new(Font6x8)
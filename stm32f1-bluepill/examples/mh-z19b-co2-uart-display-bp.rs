@@ -0,0 +1,376 @@
+//! Measure the CO2 concentration with an MH-Z19B NDIR sensor over UART and
+//! print it on an SSD1306 OLED display.
+//!
+//! Every command and answer is a 9-byte frame starting with 0xFF and ending
+//! with a checksum: the negated sum of bytes 1 to 7 plus one. Answers with a
+//! wrong checksum are discarded. This example uses these commands:
+//! - Read CO2 concentration (0x86)
+//! - Zero-point calibration (0x87): the sensor takes the current
+//!   concentration as 400ppm.
+//! - Automatic baseline correction (ABC) on/off (0x79): the sensor takes the
+//!   lowest concentration of every 24 hours as 400ppm. This works well
+//!   indoors if the room gets fresh air every day.
+//!
+//! A zero-point calibration in bad air spoils the measurements until the
+//! next calibration so it is guarded: the sensor must have been running for
+//! `CALIBRATION_WARM_UP_S` (it should be in fresh air during that time), the
+//! calibration button must be held for `CALIBRATION_HOLD_S` and it can only
+//! be done once every `CALIBRATION_LOCKOUT_S`.
+//!
+//! Press the ABC button to switch the automatic baseline correction on and
+//! off. It is on by default.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using USART2 for
+//! the sensor and I2C1 for the display.
+//!
+//! ```
+//! BP   <-> MH-Z19B <-> Display <-> Buttons
+//! GND  <-> GND     <-> GND
+//! 5V   <-> Vin
+//! 3.3V             <-> VDD     <-> +
+//! PA2  <-> RX
+//! PA3  <-> TX
+//! PB8              <-> SCL
+//! PB9              <-> SDA
+//! PB12                         <-> Zero calibration button -
+//! PB13                         <-> ABC button -
+//! ```
+//!
+//! The sensor needs 5V but its UART works with 3.3V levels.
+//!
+//! Run with:
+//! `cargo embed --example mh-z19b-co2-uart-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    i2c_speed, log_error, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::{
+    digital::v2::{InputPin, OutputPin},
+    serial,
+};
+use heapless::String;
+use nb::block;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    delay::Delay,
+    pac,
+    prelude::*,
+    serial::{Config, Serial},
+};
+
+/// The measurements are not valid during the first 3 minutes.
+const PREHEAT_S: u32 = 3 * 60;
+const CALIBRATION_WARM_UP_S: u32 = 20 * 60;
+const CALIBRATION_HOLD_S: u32 = 5;
+const CALIBRATION_LOCKOUT_S: u32 = 60 * 60;
+
+const CMD_READ_CO2: u8 = 0x86;
+const CMD_ZERO_POINT_CALIBRATION: u8 = 0x87;
+const CMD_ABC: u8 = 0x79;
+
+#[derive(Debug)]
+enum Error {
+    /// The sensor did not answer
+    Timeout,
+    /// The checksum of the answer does not match
+    Checksum,
+    /// The answer does not belong to the command
+    UnexpectedAnswer,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Measurement {
+    co2: u16,
+    /// Internal temperature. Not documented and not precise.
+    temperature: i16,
+}
+
+fn checksum(frame: &[u8; 9]) -> u8 {
+    let sum = frame[1..8].iter().fold(0_u8, |sum, b| sum.wrapping_add(*b));
+    (!sum).wrapping_add(1)
+}
+
+/// Minimal MH-Z19B driver
+struct MhZ19b<TX, RX> {
+    tx: TX,
+    rx: RX,
+    timeout_cycles: u32,
+}
+
+impl<TX, RX> MhZ19b<TX, RX>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+{
+    fn new(tx: TX, rx: RX, timeout_cycles: u32) -> Self {
+        MhZ19b {
+            tx,
+            rx,
+            timeout_cycles,
+        }
+    }
+
+    fn read_co2(&mut self) -> Result<Measurement, Error> {
+        self.send(CMD_READ_CO2, 0)?;
+        let answer = self.receive()?;
+        if answer[1] != CMD_READ_CO2 {
+            return Err(Error::UnexpectedAnswer);
+        }
+        Ok(Measurement {
+            co2: u16::from_be_bytes([answer[2], answer[3]]),
+            temperature: i16::from(answer[4]) - 40,
+        })
+    }
+
+    /// Take the current concentration as 400ppm. There is no answer.
+    fn calibrate_zero_point(&mut self) -> Result<(), Error> {
+        self.send(CMD_ZERO_POINT_CALIBRATION, 0)
+    }
+
+    /// Switch the automatic baseline correction on or off. There is no answer.
+    fn set_abc(&mut self, enabled: bool) -> Result<(), Error> {
+        self.send(CMD_ABC, if enabled { 0xA0 } else { 0x00 })
+    }
+
+    fn send(&mut self, command: u8, argument: u8) -> Result<(), Error> {
+        // Discard anything left over from a previous answer.
+        while self.rx.read().is_ok() {}
+        let mut frame = [0xFF, 0x01, command, argument, 0, 0, 0, 0, 0];
+        frame[8] = checksum(&frame);
+        for byte in frame.iter() {
+            block!(self.tx.write(*byte)).ok();
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<[u8; 9], Error> {
+        let mut frame = [0; 9];
+        // Synchronize on the start byte.
+        loop {
+            frame[0] = self.read_byte()?;
+            if frame[0] == 0xFF {
+                break;
+            }
+        }
+        for byte in frame[1..].iter_mut() {
+            *byte = self.read_byte()?;
+        }
+        if checksum(&frame) != frame[8] {
+            return Err(Error::Checksum);
+        }
+        Ok(frame)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        let start = DWT::get_cycle_count();
+        loop {
+            if let Ok(byte) = self.rx.read() {
+                return Ok(byte);
+            }
+            if DWT::get_cycle_count().wrapping_sub(start) > self.timeout_cycles {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+}
+
+/// Decides when a zero-point calibration is allowed.
+struct CalibrationGuard {
+    held_s: u32,
+    last_calibration_s: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CalibrationState {
+    /// Still warming up for the given number of seconds
+    WarmingUp(u32),
+    /// Locked after the last calibration for the given number of seconds
+    Locked(u32),
+    /// The button has been held for the given number of seconds
+    Holding(u32),
+    Ready,
+    /// Calibrate now
+    Calibrate,
+}
+
+impl CalibrationGuard {
+    fn new() -> Self {
+        CalibrationGuard {
+            held_s: 0,
+            last_calibration_s: None,
+        }
+    }
+
+    /// Call once per second with the time since startup.
+    fn update(&mut self, uptime_s: u32, pressed: bool) -> CalibrationState {
+        self.held_s = if pressed { self.held_s + 1 } else { 0 };
+        if uptime_s < CALIBRATION_WARM_UP_S {
+            return CalibrationState::WarmingUp(CALIBRATION_WARM_UP_S - uptime_s);
+        }
+        if let Some(last) = self.last_calibration_s {
+            let elapsed = uptime_s - last;
+            if elapsed < CALIBRATION_LOCKOUT_S {
+                return CalibrationState::Locked(CALIBRATION_LOCKOUT_S - elapsed);
+            }
+        }
+        if self.held_s >= CALIBRATION_HOLD_S {
+            self.held_s = 0;
+            self.last_calibration_s = Some(uptime_s);
+            CalibrationState::Calibrate
+        } else if self.held_s > 0 {
+            CalibrationState::Holding(self.held_s)
+        } else {
+            CalibrationState::Ready
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("MH-Z19B example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+    // The answer arrives within a few milliseconds.
+    let timeout_cycles = clocks.sysclk().0 / 10;
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::usart2(
+        dp.USART2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(9600.bps()),
+        clocks,
+        &mut rcc.apb1,
+    );
+    let (tx, rx) = serial.split();
+
+    let calibration_button = gpiob.pb12.into_pull_down_input(&mut gpiob.crh);
+    let abc_button = gpiob.pb13.into_pull_down_input(&mut gpiob.crh);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut sensor = MhZ19b::new(tx, rx, timeout_cycles);
+    let mut abc = true;
+    sensor.set_abc(abc).unwrap();
+
+    let mut guard = CalibrationGuard::new();
+    let mut abc_was_pressed = false;
+    let mut uptime_s = 0;
+    let mut lines: [String<32>; 4] = [String::new(), String::new(), String::new(), String::new()];
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+
+        let abc_pressed = abc_button.is_high().unwrap();
+        if abc_pressed && !abc_was_pressed {
+            abc = !abc;
+            log_info!("ABC {}", if abc { "on" } else { "off" });
+            sensor.set_abc(abc).unwrap();
+        }
+        abc_was_pressed = abc_pressed;
+
+        let calibration = guard.update(uptime_s, calibration_button.is_high().unwrap());
+        if calibration == CalibrationState::Calibrate {
+            log_info!("Zero-point calibration");
+            sensor.calibrate_zero_point().unwrap();
+        }
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        match sensor.read_co2() {
+            Ok(m) if uptime_s < PREHEAT_S => {
+                write!(lines[0], "Preheating... {}s", PREHEAT_S - uptime_s).unwrap();
+                write!(lines[1], "Temperature: {}C", m.temperature).unwrap();
+            }
+            Ok(m) => {
+                log_info!("CO2: {} ppm, temperature: {}C", m.co2, m.temperature);
+                write!(lines[0], "CO2: {} ppm", m.co2).unwrap();
+                write!(lines[1], "Temperature: {}C", m.temperature).unwrap();
+            }
+            Err(e) => {
+                log_error!("Error: {:?}", e);
+                write!(lines[0], "Error: {:?}", e).unwrap();
+            }
+        }
+        write!(lines[2], "ABC: {}", if abc { "on" } else { "off" }).unwrap();
+        match calibration {
+            CalibrationState::WarmingUp(s) => write!(lines[3], "Cal. in {} min", s / 60 + 1),
+            CalibrationState::Locked(s) => write!(lines[3], "Cal. locked {} min", s / 60 + 1),
+            CalibrationState::Holding(s) => {
+                write!(lines[3], "Hold {}s to cal.", CALIBRATION_HOLD_S - s)
+            }
+            CalibrationState::Ready => write!(lines[3], "Cal. ready"),
+            CalibrationState::Calibrate => write!(lines[3], "Calibrated"),
+        }
+        .unwrap();
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        // Roughly one second per loop including the communication.
+        delay.delay_ms(900_u16);
+        uptime_s += 1;
+    }
+}
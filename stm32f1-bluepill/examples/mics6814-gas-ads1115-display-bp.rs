@@ -0,0 +1,320 @@
+//! Estimate the CO, NO2 and NH3 concentrations with a MICS-6814 analog gas
+//! sensor read through an ADS1115 analog/digital converter and print them on
+//! an SSD1306 OLED display.
+//!
+//! The MICS-6814 has three metal-oxide sensing elements whose resistance
+//! changes with the gas concentration: RED (reducing gases, CO), OX
+//! (oxidizing gases, NO2) and NH3. Each one forms a voltage divider with a
+//! load resistor so that its resistance Rs can be calculated from the
+//! measured voltage. The supply voltage is measured on channel A3.
+//!
+//! The heaters need some time until the readings are stable so the example
+//! goes through these states:
+//! - Warming up for `WARM_UP_S` seconds. Nothing is calculated.
+//! - Calibrating: the resistances are averaged over `CALIBRATION_SAMPLES`
+//!   seconds to get the resistance in clean air R0. Start the example in
+//!   fresh air.
+//! - Measuring: the ratio Rs/R0 is converted into a concentration.
+//!
+//! The conversion uses the typical sensitivity curves of the datasheet. The
+//! curves are straight lines in a log-log plot. Here they are stored as a few
+//! points and interpolated linearly in fixed point so no floating point is
+//! needed. The values are only rough estimates: every sensor is different and
+//! each element reacts to several gases.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP  <-> ADS1115 <-> MICS-6814 <-> Display
+//! GND <-> GND     <-> GND       <-> GND
+//! +5V <-> +5V     <-> 5V        <-> +5V
+//! PB9 <-> SDA                   <-> SDA
+//! PB8 <-> SCL                   <-> SCL
+//!         A0      <-> CO
+//!         A1      <-> NO2
+//!         A2      <-> NH3
+//!         A3      <-> +5V
+//! ```
+//!
+//! Connect a load resistor from each of the CO, NO2 and NH3 outputs to GND
+//! and set `LOAD_RESISTANCE` to their values.
+//!
+//! Run with:
+//! `cargo embed --example mics6814-gas-ads1115-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use ads1x1x::{channel as AdcChannel, Ads1x1x, FullScaleRange, SlaveAddr};
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use nb::block;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    delay::Delay,
+    i2c::{BlockingI2c, DutyCycle, Mode},
+    pac,
+    prelude::*,
+};
+
+const WARM_UP_S: u32 = 10 * 60;
+const CALIBRATION_SAMPLES: u32 = 60;
+/// Load resistors of the CO, NO2 and NH3 channels in ohm
+const LOAD_RESISTANCE: [u32; 3] = [47_000, 10_000, 47_000];
+const NAMES: [&str; 3] = ["CO", "NO2", "NH3"];
+
+// Sensitivity curves as (Rs/R0 * 1000, ppm * 100) points
+const CO_CURVE: [(u32, u32); 10] = [
+    (3504, 100),
+    (1946, 200),
+    (895, 500),
+    (497, 1000),
+    (276, 2000),
+    (127, 5000),
+    (70, 10000),
+    (39, 20000),
+    (18, 50000),
+    (10, 100_000),
+];
+const NO2_CURVE: [(u32, u32); 8] = [
+    (345, 5),
+    (687, 10),
+    (1368, 20),
+    (3398, 50),
+    (6764, 100),
+    (13463, 200),
+    (33443, 500),
+    (66565, 1000),
+];
+const NH3_CURVE: [(u32, u32); 9] = [
+    (794, 100),
+    (524, 200),
+    (303, 500),
+    (200, 1000),
+    (132, 2000),
+    (76, 5000),
+    (50, 10000),
+    (33, 20000),
+    (19, 50000),
+];
+const CURVES: [&[(u32, u32)]; 3] = [&CO_CURVE, &NO2_CURVE, &NH3_CURVE];
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    WarmingUp { elapsed_s: u32 },
+    Calibrating { samples: u32, sums: [u64; 3] },
+    Measuring { r0: [u32; 3] },
+}
+
+impl State {
+    /// Advance the state machine with the resistances measured every second.
+    fn next(self, rs: [u32; 3]) -> Self {
+        match self {
+            State::WarmingUp { elapsed_s } if elapsed_s + 1 >= WARM_UP_S => State::Calibrating {
+                samples: 0,
+                sums: [0; 3],
+            },
+            State::WarmingUp { elapsed_s } => State::WarmingUp {
+                elapsed_s: elapsed_s + 1,
+            },
+            State::Calibrating { samples, mut sums } => {
+                for (sum, r) in sums.iter_mut().zip(rs.iter()) {
+                    *sum += u64::from(*r);
+                }
+                let samples = samples + 1;
+                if samples < CALIBRATION_SAMPLES {
+                    State::Calibrating { samples, sums }
+                } else {
+                    let mut r0 = [0; 3];
+                    for (r0, sum) in r0.iter_mut().zip(sums.iter()) {
+                        *r0 = (*sum / u64::from(samples)).max(1) as u32;
+                    }
+                    log_info!("R0: {:?}", r0);
+                    State::Measuring { r0 }
+                }
+            }
+            State::Measuring { r0 } => State::Measuring { r0 },
+        }
+    }
+}
+
+/// Sensor resistance in ohm from the output and supply voltages in millivolts.
+fn resistance(output_mv: u32, supply_mv: u32, load: u32) -> u32 {
+    let output_mv = output_mv.max(1).min(supply_mv);
+    (u64::from(load) * u64::from(supply_mv - output_mv) / u64::from(output_mv)) as u32
+}
+
+/// Interpolate the concentration (ppm * 100) for a ratio (Rs/R0 * 1000).
+/// Ratios beyond the curve give the concentration of the closest end.
+fn concentration(curve: &[(u32, u32)], ratio: u32) -> u32 {
+    let first = curve[0];
+    let last = curve[curve.len() - 1];
+    let (low_end, high_end) = if first.0 < last.0 {
+        (first, last)
+    } else {
+        (last, first)
+    };
+    if ratio <= low_end.0 {
+        return low_end.1;
+    }
+    if ratio >= high_end.0 {
+        return high_end.1;
+    }
+    for pair in curve.windows(2) {
+        let (r0, c0) = (i64::from(pair[0].0), i64::from(pair[0].1));
+        let (r1, c1) = (i64::from(pair[1].0), i64::from(pair[1].1));
+        let r = i64::from(ratio);
+        if (r0 <= r && r <= r1) || (r1 <= r && r <= r0) {
+            return (c0 + (c1 - c0) * (r - r0) / (r1 - r0)) as u32;
+        }
+    }
+    last.1
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("MICS-6814 example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let i2c = BlockingI2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        Mode::Fast {
+            frequency: 100_000.hz(),
+            duty_cycle: DutyCycle::Ratio2to1,
+        },
+        clocks,
+        &mut rcc.apb1,
+        1000,
+        10,
+        1000,
+        1000,
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut adc = Ads1x1x::new_ads1115(manager.acquire(), SlaveAddr::default());
+    // need to be able to measure [0-5V]
+    adc.set_full_scale_range(FullScaleRange::Within6_144V)
+        .unwrap();
+
+    let mut state = State::WarmingUp { elapsed_s: 0 };
+    let mut lines: [String<32>; 4] = [String::new(), String::new(), String::new(), String::new()];
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+
+        let values = [
+            block!(adc.read(&mut AdcChannel::SingleA0)).unwrap_or(0),
+            block!(adc.read(&mut AdcChannel::SingleA1)).unwrap_or(0),
+            block!(adc.read(&mut AdcChannel::SingleA2)).unwrap_or(0),
+            block!(adc.read(&mut AdcChannel::SingleA3)).unwrap_or(0),
+        ];
+        // One LSB is 187.5uV in the 6.144V range.
+        let mut millivolts = [0_u32; 4];
+        for (mv, value) in millivolts.iter_mut().zip(values.iter()) {
+            *mv = (*value).max(0) as u32 * 3 / 16;
+        }
+        let mut rs = [0; 3];
+        for ((r, mv), load) in rs
+            .iter_mut()
+            .zip(millivolts.iter())
+            .zip(LOAD_RESISTANCE.iter())
+        {
+            *r = resistance(*mv, millivolts[3], *load);
+        }
+        state = state.next(rs);
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        match state {
+            State::WarmingUp { elapsed_s } => {
+                let remaining = WARM_UP_S - elapsed_s;
+                write!(
+                    lines[0],
+                    "Warming up {}:{:02}",
+                    remaining / 60,
+                    remaining % 60
+                )
+                .unwrap();
+                for (i, line) in lines[1..].iter_mut().enumerate() {
+                    write!(line, "{}: {} ohm", NAMES[i], rs[i]).unwrap();
+                }
+            }
+            State::Calibrating { samples, .. } => {
+                write!(lines[0], "Calibrating {}s", CALIBRATION_SAMPLES - samples).unwrap();
+                write!(lines[1], "Keep in fresh air").unwrap();
+            }
+            State::Measuring { r0 } => {
+                write!(lines[0], "Supply: {}mV", millivolts[3]).unwrap();
+                for (i, line) in lines[1..].iter_mut().enumerate() {
+                    let ratio = (u64::from(rs[i]) * 1000 / u64::from(r0[i])) as u32;
+                    let ppm = concentration(CURVES[i], ratio);
+                    log_info!(
+                        "{}: Rs/R0 {}/1000, {}.{:02} ppm",
+                        NAMES[i],
+                        ratio,
+                        ppm / 100,
+                        ppm % 100
+                    );
+                    write!(line, "{}: {}.{:02} ppm", NAMES[i], ppm / 100, ppm % 100).unwrap();
+                }
+            }
+        }
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        delay.delay_ms(900_u16);
+    }
+}
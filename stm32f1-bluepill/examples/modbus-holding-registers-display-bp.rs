@@ -0,0 +1,233 @@
+//! Read holding registers from any Modbus RTU slave and print them on an
+//! SSD1306 OLED display.
+//!
+//! The slave address, the first register and the number of registers are
+//! set with the constants below, so this works with many industrial devices
+//! like temperature controllers, energy meters or soil sensors. Have a look
+//! at the register map in the manual of your device.
+//!
+//! The `modbus` module of this crate builds the requests, checks the CRC of
+//! the answers, retries failed requests and reports the exceptions sent by
+//! the slave (e.g. `IllegalDataAddress` if the registers do not exist).
+//! The number of successful reads and errors is shown too.
+//!
+//! The answers are received in the USART2 interrupt into a ring buffer (see
+//! the `uart` module), so no byte is lost while the display is updated.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using USART2 for
+//! the Modbus and I2C1 for the display.
+//!
+//! ```
+//! BP   <-> RS-485 module <-> Display
+//! GND  <-> GND           <-> GND
+//! 3.3V <-> VCC           <-> VDD
+//! PA2  <-> TXD
+//! PA3  <-> RXD
+//! PB8                    <-> SCL
+//! PB9                    <-> SDA
+//! ```
+//!
+//! Use an RS-485 module with automatic direction control and connect its A
+//! and B terminals to the device. Devices with a TTL UART can be connected
+//! directly.
+//!
+//! Run with:
+//! `cargo embed --example modbus-holding-registers-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::singleton;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_error, log_info,
+    modbus::Master,
+    panic_display::{self, Bus},
+    uart::{RxBuffer, RxReader, UartRx},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtic::app;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    delay::Delay,
+    gpio::{
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    serial::{Config, Rx, Serial, Tx},
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SLAVE_ADDRESS: u8 = 0x01;
+const START_REGISTER: u16 = 0x0000;
+const REGISTER_COUNT: usize = 4;
+const BAUD_RATE: u32 = 9600;
+const RETRIES: u8 = 2;
+const POLL_PERIOD_MS: u16 = 1000;
+const RX_BUFFER: usize = 512;
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        uart_rx: UartRx<Rx<pac::USART2>, RX_BUFFER>,
+        master: Master<Tx<pac::USART2>, RxReader<RX_BUFFER>>,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        delay: Delay,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Modbus RTU holding registers example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+
+        let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+        let rx = gpioa.pa3;
+        let serial = Serial::usart2(
+            device.USART2,
+            (tx, rx),
+            &mut afio.mapr,
+            Config::default().baudrate(BAUD_RATE.bps()),
+            clocks,
+            &mut rcc.apb1,
+        );
+        let (tx, mut rx) = serial.split();
+        rx.listen();
+        let (uart_rx, reader) = singleton!(: RxBuffer<RX_BUFFER> = RxBuffer::new())
+            .unwrap()
+            .split(rx);
+        // Wait up to 100ms for each byte of the answer.
+        let master = Master::new(tx, reader, clocks.sysclk().0 / 10).retries(RETRIES);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+        let delay = Delay::new(core.SYST, clocks);
+
+        init::LateResources {
+            uart_rx,
+            master,
+            i2c: Some(i2c),
+            delay,
+            led,
+        }
+    }
+
+    /// Move the received bytes into the buffer.
+    #[task(binds = USART2, priority = 2, resources = [uart_rx])]
+    fn usart2(cx: usart2::Context) {
+        cx.resources.uart_rx.on_interrupt();
+    }
+
+    #[idle(resources = [master, i2c, delay, led])]
+    fn idle(cx: idle::Context) -> ! {
+        let master = cx.resources.master;
+        let delay = cx.resources.delay;
+        let led = cx.resources.led;
+
+        let interface = I2CDIBuilder::new().init(cx.resources.i2c.take().unwrap());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut registers = [0_u16; REGISTER_COUNT];
+        let mut reads: u32 = 0;
+        let mut errors: u32 = 0;
+        let mut lines: [String<32>; REGISTER_COUNT + 2] = Default::default();
+        loop {
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            led.set_high().unwrap();
+            delay.delay_ms(50_u16);
+            led.set_low().unwrap();
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            write!(lines[0], "Slave 0x{:02X}", SLAVE_ADDRESS).unwrap();
+            match master.read_holding_registers(SLAVE_ADDRESS, START_REGISTER, &mut registers) {
+                Ok(()) => {
+                    reads += 1;
+                    for (i, (line, value)) in
+                        lines[1..].iter_mut().zip(registers.iter()).enumerate()
+                    {
+                        let address = START_REGISTER as usize + i;
+                        log_info!("Register {}: {} (0x{:04X})", address, value, value);
+                        write!(line, "{:5}: {:5} {:04X}", address, value, value).unwrap();
+                    }
+                }
+                Err(e) => {
+                    errors += 1;
+                    log_error!("Error: {:?}", e);
+                    write!(lines[1], "{:?}", e).unwrap();
+                }
+            }
+            write!(
+                lines[REGISTER_COUNT + 1],
+                "OK: {} Errors: {}",
+                reads,
+                errors
+            )
+            .unwrap();
+
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 10))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+
+            delay.delay_ms(POLL_PERIOD_MS);
+        }
+    }
+};
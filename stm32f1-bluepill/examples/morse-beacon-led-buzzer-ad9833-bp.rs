@@ -0,0 +1,398 @@
+//! Send Morse code with the LED, a buzzer and an AD9833 carrier at the same
+//! time, controlled over a serial console.
+//!
+//! The text is turned into keying events by the `MorseEncoder` from this
+//! crate. TIM2 interrupts every millisecond, counts down the duration of
+//! the current event and then switches the LED, the buzzer and the AD9833
+//! output for the next one. The AD9833 is held in reset while the key is
+//! up, so it only outputs the carrier while the key is down.
+//!
+//! Connect with a serial terminal at 115200 baud and send one command per
+//! line:
+//! - `SEND CQ DE N0CALL`: send a text once.
+//! - `BEACON VVV DE N0CALL`: send a text every `BEACON_PAUSE_S` seconds.
+//! - `STOP`: stop sending.
+//! - `WPM 20`: set the speed in words per minute (5 - 40).
+//! - `FREQ 700`: set the AD9833 frequency in Hz (1 - 10000000), e.g. an
+//!   audio tone or a low power carrier for a receiver next to it.
+//! - `STATUS`: print the settings.
+//!
+//! Every command is answered with a line starting with `OK` or `ERR`.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1 and USART1.
+//!
+//! ```
+//! BP   <-> AD9833 <-> Buzzer <-> USB-serial adapter
+//! GND  <-> VSS    <-> -      <-> GND
+//! 3.3V <-> VDD
+//! PA4  <-> FSYNC
+//! PA5  <-> CLK
+//! PA7  <-> DAT
+//! PB0             <-> +
+//! PA9                        <-> RX
+//! PA10                       <-> TX
+//! ```
+//!
+//! The buzzer must be an active buzzer (it beeps when supplied with a
+//! constant voltage). Only connect the AD9833 output to an antenna if you
+//! are allowed to transmit on its frequency.
+//!
+//! Run with:
+//! `cargo embed --example morse-beacon-led-buzzer-ad9833-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use ad983x::{ic::Ad9833, Ad983x, FrequencyRegister, MODE as SPI_MODE};
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    log_info,
+    morse::{self, Keying, MorseEncoder},
+    panic_display as _,
+    uart::{LineBuffer, RxBuffer, RxReader, TxBuffer, TxWriter, UartRx, UartTx},
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::{PA4, PA5, PA6, PA7},
+        gpiob::PB0,
+        gpioc::PC13,
+        Alternate, Floating, Input, Output, PushPull, State,
+    },
+    pac,
+    prelude::*,
+    serial::{Config, Rx, Serial, Tx},
+    spi::{Spi, Spi1NoRemap},
+    timer::{CountDownTimer, Event, Timer},
+};
+
+type Synth = Ad983x<
+    Spi<
+        pac::SPI1,
+        Spi1NoRemap,
+        (
+            PA5<Alternate<PushPull>>,
+            PA6<Input<Floating>>,
+            PA7<Alternate<PushPull>>,
+        ),
+    >,
+    PA4<Output<PushPull>>,
+    Ad9833,
+>;
+
+const SYSCLK_MHZ: u32 = 72;
+const BAUD_RATE: u32 = 115_200;
+const RX_BUFFER: usize = 64;
+const TX_BUFFER: usize = 256;
+const LINE_LEN: usize = 80;
+const MESSAGE_LEN: usize = 64;
+const BEACON_PAUSE_S: u32 = 10;
+const START_WPM: u8 = 15;
+const START_HZ: u32 = 700;
+const MAX_HZ: u32 = 10_000_000;
+const MCLK_HZ: f32 = 25_000_000.0;
+
+/// Value of a frequency register
+fn frequency_word(frequency_hz: u32) -> u32 {
+    (frequency_hz as f32 * (1 << 28) as f32 / MCLK_HZ + 0.5) as u32
+}
+
+/// Outputs keyed by the timer interrupt
+pub struct Keyer {
+    led: PC13<Output<PushPull>>,
+    buzzer: PB0<Output<PushPull>>,
+    key_down: bool,
+    remaining_ms: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command<'a> {
+    Send(&'a str),
+    Beacon(&'a str),
+    Stop,
+    Wpm(u8),
+    Frequency(u32),
+    Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CommandError {
+    UnknownCommand,
+    MissingArgument,
+    InvalidNumber,
+    OutOfRange,
+    Text(morse::Error),
+}
+
+fn parse(line: &str) -> Result<Command, CommandError> {
+    let mut parts = line.trim().splitn(2, char::is_whitespace);
+    let command = parts.next().ok_or(CommandError::UnknownCommand)?;
+    let argument = parts.next().map(str::trim).filter(|a| !a.is_empty());
+    if command.eq_ignore_ascii_case("STOP") {
+        return Ok(Command::Stop);
+    }
+    if command.eq_ignore_ascii_case("STATUS") {
+        return Ok(Command::Status);
+    }
+    if command.eq_ignore_ascii_case("SEND") {
+        return Ok(Command::Send(
+            argument.ok_or(CommandError::MissingArgument)?,
+        ));
+    }
+    if command.eq_ignore_ascii_case("BEACON") {
+        return Ok(Command::Beacon(
+            argument.ok_or(CommandError::MissingArgument)?,
+        ));
+    }
+    if command.eq_ignore_ascii_case("WPM") {
+        let argument = argument.ok_or(CommandError::MissingArgument)?;
+        return match argument.parse() {
+            Ok(wpm) if (morse::MIN_WPM..=morse::MAX_WPM).contains(&wpm) => Ok(Command::Wpm(wpm)),
+            Ok(_) => Err(CommandError::OutOfRange),
+            Err(_) => Err(CommandError::InvalidNumber),
+        };
+    }
+    if command.eq_ignore_ascii_case("FREQ") {
+        let argument = argument.ok_or(CommandError::MissingArgument)?;
+        return match argument.parse() {
+            Ok(hz) if (1..=MAX_HZ).contains(&hz) => Ok(Command::Frequency(hz)),
+            Ok(_) => Err(CommandError::OutOfRange),
+            Err(_) => Err(CommandError::InvalidNumber),
+        };
+    }
+    Err(CommandError::UnknownCommand)
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        synth: Synth,
+        timer: CountDownTimer<pac::TIM2>,
+        encoder: MorseEncoder<MESSAGE_LEN>,
+        keyer: Keyer,
+        uart_rx: UartRx<Rx<pac::USART1>, RX_BUFFER>,
+        uart_tx: UartTx<Tx<pac::USART1>, TX_BUFFER>,
+        reader: RxReader<RX_BUFFER>,
+        writer: TxWriter<TX_BUFFER>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Morse beacon example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // SPI1
+        let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+        let miso = gpioa.pa6;
+        let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+        let cs = gpioa
+            .pa4
+            .into_push_pull_output_with_state(&mut gpioa.crl, State::High);
+
+        let spi = Spi::spi1(
+            device.SPI1,
+            (sck, miso, mosi),
+            &mut afio.mapr,
+            SPI_MODE,
+            1_u32.mhz(),
+            clocks,
+            &mut rcc.apb2,
+        );
+
+        // Held in reset until the key is down
+        let mut synth = Ad983x::new_ad9833(spi, cs);
+        synth.reset().unwrap();
+        synth
+            .set_frequency(FrequencyRegister::F0, frequency_word(START_HZ))
+            .unwrap();
+        synth.select_frequency(FrequencyRegister::F0).unwrap();
+
+        let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+        let rx = gpioa.pa10;
+        let serial = Serial::usart1(
+            device.USART1,
+            (tx, rx),
+            &mut afio.mapr,
+            Config::default().baudrate(BAUD_RATE.bps()),
+            clocks,
+            &mut rcc.apb2,
+        );
+        let (tx, mut rx) = serial.split();
+        rx.listen();
+        let (uart_rx, reader) = singleton!(: RxBuffer<RX_BUFFER> = RxBuffer::new())
+            .unwrap()
+            .split(rx);
+        let (uart_tx, writer) = singleton!(: TxBuffer<TX_BUFFER> = TxBuffer::new())
+            .unwrap()
+            .split(tx, || rtic::pend(pac::Interrupt::USART1));
+
+        let mut timer = Timer::tim2(device.TIM2, &clocks, &mut rcc.apb1).start_count_down(1.khz());
+        timer.listen(Event::Update);
+
+        let buzzer = gpiob
+            .pb0
+            .into_push_pull_output_with_state(&mut gpiob.crl, State::Low);
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            synth,
+            timer,
+            encoder: MorseEncoder::new(START_WPM),
+            keyer: Keyer {
+                led,
+                buzzer,
+                key_down: false,
+                remaining_ms: 0,
+            },
+            uart_rx,
+            uart_tx,
+            reader,
+            writer,
+        }
+    }
+
+    #[task(binds = TIM2, priority = 2, resources = [synth, timer, encoder, keyer])]
+    fn tick(cx: tick::Context) {
+        cx.resources.timer.clear_update_interrupt_flag();
+        let keyer = cx.resources.keyer;
+        if keyer.remaining_ms > 0 {
+            keyer.remaining_ms -= 1;
+            return;
+        }
+        // Key up while there is nothing to send
+        let keying = encoder.next().unwrap_or(Keying {
+            key_down: false,
+            duration_ms: 0,
+        });
+        if keying.key_down != keyer.key_down {
+            keyer.key_down = keying.key_down;
+            let synth = cx.resources.synth;
+            if keying.key_down {
+                keyer.led.set_low().unwrap();
+                keyer.buzzer.set_high().unwrap();
+                synth.enable().unwrap();
+            } else {
+                keyer.led.set_high().unwrap();
+                keyer.buzzer.set_low().unwrap();
+                synth.reset().unwrap();
+            }
+        }
+        keyer.remaining_ms = keying.duration_ms.saturating_sub(1);
+    }
+
+    /// Move the received bytes into the buffer and send the waiting ones.
+    #[task(binds = USART1, priority = 2, resources = [uart_rx, uart_tx])]
+    fn usart1(cx: usart1::Context) {
+        cx.resources.uart_rx.on_interrupt();
+        let uart_tx = cx.resources.uart_tx;
+        if uart_tx.on_interrupt() {
+            uart_tx.tx().listen();
+        } else {
+            uart_tx.tx().unlisten();
+        }
+    }
+
+    #[idle(resources = [synth, encoder, reader, writer])]
+    fn idle(cx: idle::Context) -> ! {
+        let reader = cx.resources.reader;
+        let tx = cx.resources.writer;
+        let mut encoder = cx.resources.encoder;
+        let mut synth = cx.resources.synth;
+        let mut line_buffer: LineBuffer<LINE_LEN> = LineBuffer::new();
+        let mut beacon: Option<String<MESSAGE_LEN>> = None;
+        let mut frequency_hz = START_HZ;
+        let mut done_since = None;
+        writeln!(tx, "OK ready").unwrap();
+        loop {
+            for line in reader.lines(&mut line_buffer) {
+                log_info!("Command: {}", line);
+                let result = parse(&line).and_then(|command| {
+                    match command {
+                        Command::Send(text) | Command::Beacon(text) => {
+                            encoder
+                                .lock(|encoder| encoder.load(text))
+                                .map_err(CommandError::Text)?;
+                            beacon = match command {
+                                Command::Beacon(_) => Some(String::from(text)),
+                                _ => None,
+                            };
+                        }
+                        Command::Stop => {
+                            encoder.lock(|encoder| encoder.load("")).ok();
+                            beacon = None;
+                        }
+                        Command::Wpm(wpm) => encoder.lock(|encoder| encoder.set_wpm(wpm)),
+                        Command::Frequency(hz) => {
+                            frequency_hz = hz;
+                            synth.lock(|synth| {
+                                synth
+                                    .set_frequency(FrequencyRegister::F0, frequency_word(hz))
+                                    .unwrap()
+                            });
+                        }
+                        Command::Status => (),
+                    }
+                    Ok(())
+                });
+                match result {
+                    Ok(()) => {
+                        let (wpm, sending) =
+                            encoder.lock(|encoder| (encoder.wpm(), !encoder.is_done()));
+                        write!(tx, "OK {} WPM {} Hz", wpm, frequency_hz).unwrap();
+                        if let Some(text) = &beacon {
+                            write!(tx, " beacon: {}", text).unwrap();
+                        } else if sending {
+                            write!(tx, " sending").unwrap();
+                        }
+                        writeln!(tx).unwrap();
+                    }
+                    Err(e) => writeln!(tx, "ERR {:?}", e).unwrap(),
+                }
+            }
+
+            // Repeat the beacon after a pause.
+            let text = match &beacon {
+                Some(text) => text,
+                None => continue,
+            };
+            if !encoder.lock(|encoder| encoder.is_done()) {
+                done_since = None;
+                continue;
+            }
+            let now = DWT::get_cycle_count();
+            let since = *done_since.get_or_insert(now);
+            if now.wrapping_sub(since) >= BEACON_PAUSE_S * SYSCLK_MHZ * 1_000_000 {
+                encoder.lock(|encoder| encoder.load(text)).ok();
+                done_since = None;
+            }
+        }
+    }
+};
@@ -0,0 +1,281 @@
+//! Smoke and fire alarm with an MQ-2 gas sensor and an IR flame sensor.
+//! The alarm latches like in real alarm panels: it sounds a buzzer and
+//! switches a relay (e.g. for a fan or a gas valve) until someone presses
+//! the acknowledge button and the smoke and flame are gone. The time of the
+//! alarm is taken from a DS3231 real-time clock and shown on an SSD1306
+//! OLED display together with the readings.
+//!
+//! The latching follows the `alarm` module of this crate:
+//! - The alarm trips when smoke or a flame is detected for `TRIP_SAMPLES`
+//!   readings in a row, so that a single disturbance does not set it off.
+//! - The button silences the buzzer. The relay stays on until the smoke and
+//!   the flame are gone as well.
+//! - If the smoke and the flame disappear before someone acknowledges the
+//!   alarm, the buzzer keeps sounding so that the alarm is not missed.
+//!
+//! The heater of the MQ-2 needs `WARMUP_S` seconds before its readings make
+//! sense, so smoke is ignored until then. The flame sensor works at once.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> MQ-2 <-> Flame sensor <-> DS3231 <-> Display <-> Buzzer <-> Relay <-> Button
+//! GND  <-> GND  <-> GND          <-> GND    <-> GND     <-> GND    <-> GND
+//! 5V   <-> VCC                                                     <-> VCC
+//! 3.3V          <-> VCC          <-> VCC    <-> VDD                          <-> +
+//! PA0  <-> AO (through divider)
+//! PB12          <-> DO
+//! PB8                            <-> SCL    <-> SCL
+//! PB9                            <-> SDA    <-> SDA
+//! PA3                                                   <-> +
+//! PB0                                                              <-> IN
+//! PB13                                                                       <-> -
+//! ```
+//!
+//! The MQ-2 heater needs 5V and its analog output goes up to 5V as well,
+//! which is too much for PA0. Connect it through a voltage divider of 10K
+//! (to AO) and 20K (to GND). `SMOKE_THRESHOLD_MV` is the voltage at PA0.
+//! Adjust it for your sensor: light smoke should trip the alarm, clean air
+//! should not.
+//!
+//! The flame sensor output goes low when it sees a flame. Adjust its
+//! sensitivity with the potentiometer on the module. The buzzer must be an
+//! active buzzer and the relay module must switch on with a high input.
+//!
+//! Run with:
+//! `cargo embed --example mq-2-flame-latched-alarm-ds3231-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    alarm::{LatchedAlarm, State},
+    bootloader::relocate_vector_table,
+    convert::{adc_to_millivolts, average},
+    i2c_speed, log_info, log_warn, monotonic,
+    panic_display::{self, Bus},
+    scheduler::Scheduler,
+};
+use ds323x::{Ds323x, NaiveDateTime, Rtcc};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{adc, pac, prelude::*};
+
+/// Voltage at PA0 above which there is smoke
+const SMOKE_THRESHOLD_MV: u32 = 1200;
+/// Heating time of the MQ-2
+const WARMUP_S: u32 = 60;
+/// Number of readings in a row with smoke or flame which trip the alarm
+const TRIP_SAMPLES: u8 = 5;
+const SAMPLES: usize = 16;
+
+// Tasks in order of priority
+const BUTTON: usize = 0;
+const SENSORS: usize = 1;
+const BUZZER: usize = 2;
+const DISPLAY: usize = 3;
+
+/// What tripped the alarm and when
+#[derive(Debug, Clone, Copy)]
+struct Trip {
+    smoke: bool,
+    flame: bool,
+    /// `None` if the RTC could not be read
+    time: Option<NaiveDateTime>,
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("MQ-2 and flame sensor latched alarm example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.adcclk(2.mhz()).freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let mut adc1 = adc::Adc::adc1(dp.ADC1, &mut rcc.apb2, clocks);
+    let mut smoke_sensor = gpioa.pa0.into_analog(&mut gpioa.crl);
+    let flame_sensor = gpiob.pb12.into_pull_up_input(&mut gpiob.crh);
+    let button = gpiob.pb13.into_pull_down_input(&mut gpiob.crh);
+    let mut buzzer = gpioa.pa3.into_push_pull_output(&mut gpioa.crl);
+    let mut relay = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // DS3231 RTC and display
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x68, 0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut rtc = Ds323x::new_ds3231(manager.acquire());
+
+    let start_ms = monotonic::millis();
+    let mut alarm = LatchedAlarm::new();
+    let mut last_trip: Option<Trip> = None;
+    let mut smoke_mv = 0;
+    let mut flame = false;
+    let mut smoke_samples = 0_u8;
+    let mut flame_samples = 0_u8;
+    let mut button_history = 0_u8;
+    let mut beep_on = false;
+    let mut led_on = false;
+    let mut lines: [String<32>; 5] = Default::default();
+    let mut scheduler = Scheduler::new([10, 100, 250, 500]);
+    loop {
+        match scheduler.poll() {
+            Some(BUTTON) => {
+                // Acknowledge once the button has been pressed for 8 samples.
+                let previous = button_history;
+                button_history = (button_history << 1) | u8::from(button.is_high().unwrap());
+                if button_history == 0xFF && previous != 0xFF && alarm.is_latched() {
+                    log_info!("Alarm acknowledged");
+                    alarm.acknowledge();
+                }
+            }
+            Some(SENSORS) => {
+                let raw = average((0..SAMPLES).map(|_| {
+                    let value: u16 = adc1.read(&mut smoke_sensor).unwrap();
+                    value
+                }));
+                smoke_mv = adc_to_millivolts(raw);
+                flame = flame_sensor.is_low().unwrap();
+                let warm = monotonic::millis().wrapping_sub(start_ms) >= WARMUP_S * 1000;
+
+                // Count the readings in a row above the thresholds.
+                smoke_samples = if warm && smoke_mv > SMOKE_THRESHOLD_MV {
+                    smoke_samples.saturating_add(1)
+                } else {
+                    0
+                };
+                flame_samples = if flame {
+                    flame_samples.saturating_add(1)
+                } else {
+                    0
+                };
+                let smoke_detected = smoke_samples >= TRIP_SAMPLES;
+                let flame_detected = flame_samples >= TRIP_SAMPLES;
+
+                if alarm.update(smoke_detected || flame_detected) {
+                    let trip = Trip {
+                        smoke: smoke_detected,
+                        flame: flame_detected,
+                        time: rtc.get_datetime().ok(),
+                    };
+                    log_warn!("ALARM: {:?}", trip);
+                    last_trip = Some(trip);
+                }
+                // The relay stays on until the alarm is back to normal.
+                if alarm.is_latched() {
+                    relay.set_high().unwrap();
+                } else {
+                    relay.set_low().unwrap();
+                }
+            }
+            Some(BUZZER) => {
+                beep_on = alarm.is_unacknowledged() && !beep_on;
+                if beep_on {
+                    buzzer.set_high().unwrap();
+                } else {
+                    buzzer.set_low().unwrap();
+                }
+            }
+            Some(DISPLAY) => {
+                for line in lines.iter_mut() {
+                    line.clear();
+                }
+                let status = match alarm.state() {
+                    State::Normal => "Normal",
+                    State::Active => "ALARM! Press ack",
+                    State::Acknowledged => "ALARM acknowledged",
+                    State::Cleared => "Cleared. Press ack",
+                };
+                write!(lines[0], "{}", status).unwrap();
+                let warmup_ms = monotonic::millis().wrapping_sub(start_ms);
+                if warmup_ms < WARMUP_S * 1000 {
+                    let remaining_s = WARMUP_S - warmup_ms / 1000;
+                    write!(lines[1], "Smoke: warm-up {}s", remaining_s).unwrap();
+                } else {
+                    write!(lines[1], "Smoke: {} mV", smoke_mv).unwrap();
+                }
+                let flame_text = if flame { "yes" } else { "no" };
+                write!(lines[2], "Flame: {}", flame_text).unwrap();
+                if let Some(trip) = last_trip {
+                    let cause = match (trip.smoke, trip.flame) {
+                        (true, true) => "smoke+flame",
+                        (true, false) => "smoke",
+                        _ => "flame",
+                    };
+                    write!(lines[3], "Last alarm: {}", cause).unwrap();
+                    match trip.time {
+                        Some(time) => write!(lines[4], "{}", time).unwrap(),
+                        None => write!(lines[4], "Time unknown").unwrap(),
+                    }
+                }
+                disp.clear();
+                for (i, line) in lines.iter().enumerate() {
+                    Text::new(line, Point::new(0, i as i32 * 12))
+                        .into_styled(text_style)
+                        .draw(&mut disp)
+                        .unwrap();
+                }
+                disp.flush().unwrap();
+
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                led_on = !led_on;
+                if led_on {
+                    led.set_high().unwrap();
+                } else {
+                    led.set_low().unwrap();
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Publish the temperature measured with a TMP112 sensor over MQTT-SN
+//! through a W5500 Ethernet module.
+//!
+//! MQTT-SN is a lighter variant of MQTT which runs over UDP, see the
+//! `mqtt_sn` module of this crate. The example sends the packets to an
+//! MQTT-SN gateway, e.g. the Eclipse Paho MQTT-SN gateway, which forwards the
+//! messages to an MQTT broker. Set `GATEWAY_IP`, `GATEWAY_PORT` and the
+//! network settings to match your network. Then watch the messages with:
+//! `mosquitto_sub -h <broker> -t 'bluepill/#' -v`
+//!
+//! Every `PUBLISH_INTERVAL_S` seconds the example connects, registers the
+//! topic and publishes the temperature in degrees Celsius to
+//! `bluepill/temperature`. With `SLEEP`, it then tells the gateway that it
+//! sleeps until the next time, which saves the keep-alive pings, and waits
+//! with `wfi`. Otherwise it stays connected and the client sends a ping when
+//! nothing was sent for `KEEP_ALIVE_S`. When the gateway does not answer,
+//! the example connects again.
+//!
+//! The W5500 is driven with the `w5500` module of this crate. The SysTick
+//! interrupt wakes the core up every millisecond, and the W5500 keeps
+//! running, so for a battery powered sensor switch the module off while
+//! sleeping and reduce the wake-ups.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and SPI1.
+//!
+//! ```
+//! BP   <-> TMP112 <-> W5500
+//! GND  <-> GND    <-> GND
+//! 3.3V <-> VCC    <-> 3.3V
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! PA4             <-> SCS
+//! PA5             <-> SCLK
+//! PA6             <-> MISO
+//! PA7             <-> MOSI
+//! PB0             <-> RST
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example mqtt-sn-w5500-tmp112-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_error, log_info, log_warn, monotonic,
+    mqtt_sn::{Client, State},
+    panic_display as _,
+    w5500::{NetConfig, W5500},
+};
+use embedded_hal::{
+    digital::v2::OutputPin,
+    spi::{Mode, Phase, Polarity},
+};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{pac, prelude::*, spi::Spi};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+const CLIENT_ID: &str = "bluepill";
+const TOPIC: &str = "bluepill/temperature";
+const GATEWAY_IP: [u8; 4] = [192, 168, 1, 10];
+const GATEWAY_PORT: u16 = 10000;
+const LOCAL_PORT: u16 = 10000;
+const NET_CONFIG: NetConfig = NetConfig {
+    // Locally administered address, change it if there are several boards.
+    mac: [0x02, 0x00, 0x00, 0x12, 0x34, 0x56],
+    ip: [192, 168, 1, 50],
+    subnet_mask: [255, 255, 255, 0],
+    router: [192, 168, 1, 1],
+};
+
+const PUBLISH_INTERVAL_S: u16 = 60;
+/// Sleep between the publications instead of staying connected
+const SLEEP: bool = true;
+/// Time the gateway waits beyond the sleep time before dropping the session
+const SLEEP_MARGIN_S: u16 = 30;
+const KEEP_ALIVE_S: u16 = 120;
+const RETRY_MS: u32 = 5000;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("MQTT-SN W5500 example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // TMP112 sensor
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x48]),
+    );
+    let mut sensor = Tmp1x2::new(i2c, SlaveAddr::default());
+
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+    let cs = gpioa.pa4.into_push_pull_output(&mut gpioa.crl);
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        Mode {
+            polarity: Polarity::IdleLow,
+            phase: Phase::CaptureOnFirstTransition,
+        },
+        9.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    // Hardware reset: at least 500us low, then the PLL of the W5500 needs 1ms.
+    let mut reset = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+    reset.set_low().unwrap();
+    monotonic::wait_ms(1);
+    reset.set_high().unwrap();
+    monotonic::wait_ms(2);
+
+    let mut w5500 = W5500::new(spi, cs, &NET_CONFIG).unwrap();
+    while !w5500.link_up().unwrap() {
+        log_info!("Waiting for the Ethernet link");
+        monotonic::wait_ms(1000);
+    }
+    log_info!("Link up");
+    w5500
+        .open_udp(LOCAL_PORT, GATEWAY_IP, GATEWAY_PORT)
+        .unwrap();
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut client = Client::new(w5500, monotonic::millis).keep_alive(KEEP_ALIVE_S);
+    let mut topic = None;
+    loop {
+        // LED 0 is on while the example is awake.
+        // If it stays on, something went wrong.
+        led.set_low().unwrap();
+        if client.state() != State::Active {
+            // After sleeping, the session and the topic id are kept.
+            let clean_session = client.state() == State::Disconnected;
+            if let Err(e) = client.connect(CLIENT_ID) {
+                log_warn!("Connection failed: {:?}", e);
+                monotonic::wait_ms(RETRY_MS);
+                continue;
+            }
+            if clean_session {
+                topic = None;
+            }
+        }
+        if topic.is_none() {
+            match client.register(TOPIC) {
+                Ok(id) => topic = Some(id),
+                Err(e) => {
+                    log_warn!("Registration failed: {:?}", e);
+                    monotonic::wait_ms(RETRY_MS);
+                    continue;
+                }
+            }
+        }
+
+        let mut payload: String<16> = String::new();
+        match sensor.read_temperature() {
+            Ok(temperature) => {
+                write!(payload, "{:.2}", temperature).unwrap();
+                log_info!("Publishing {}ºC", payload);
+                if let Err(e) = client.publish(topic.unwrap(), payload.as_bytes()) {
+                    log_error!("Publishing failed: {:?}", e);
+                }
+            }
+            Err(e) => log_error!("Sensor error: {:?}", e),
+        }
+
+        let start = monotonic::millis();
+        let interval_ms = u32::from(PUBLISH_INTERVAL_S) * 1000;
+        if SLEEP {
+            if let Err(e) = client.sleep(PUBLISH_INTERVAL_S + SLEEP_MARGIN_S) {
+                log_warn!("Sleep request failed: {:?}", e);
+            }
+        }
+        led.set_high().unwrap();
+        while monotonic::millis().wrapping_sub(start) < interval_ms {
+            if let Err(e) = client.poll() {
+                log_warn!("Connection lost: {:?}", e);
+            }
+            cortex_m::asm::wfi();
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Receive the buttons pressed on an infrared remote control using the NEC
+//! protocol with a TSOP38238 IR receiver. Use them to change the page shown
+//! on an SSD1306 OLED display and to move a servo to preset positions.
+//!
+//! The time between the edges of the receiver output is measured with the
+//! cycle counter in an interrupt triggered on both edges. The pulse durations
+//! are fed into the `NecDecoder` from this crate, which works the same with
+//! any other way of measuring them, like timer input capture.
+//!
+//! The button codes are for the common 21-key "Car MP3" remote controls.
+//! The codes received are printed through RTT and shown on the first page so
+//! that you can adapt them to your remote control.
+//! - `PREV`/`NEXT`: previous/next page
+//! - `1`, `2`, `3`: move the servo to 0, 90 and 180 degrees
+//!
+//! The servo is driven with a PCA9685 on the same I2C bus as the display.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> TSOP38238 <-> Display <-> PCA9685
+//! GND  <-> GND       <-> GND     <-> GND
+//! 3.3V <-> VS        <-> VDD     <-> VCC
+//! PA1  <-> OUT
+//! PB8                <-> SCL     <-> SCL
+//! PB9                <-> SDA     <-> SDA
+//! GND                            <-> OE
+//!                                    V+ <-> +5V
+//!                                    Channel 0 <-> Servo
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example nec-ir-remote-display-servo-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    exti::setup_exti_pin,
+    i2c_speed, log_info,
+    nec::{NecDecoder, NecEvent},
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::{
+    spsc::{Consumer, Producer, Queue},
+    String,
+};
+use pwm_pca9685::{Address, Channel, Pca9685};
+use rtic::app;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::PA1,
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Floating, Input, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const PAGES: u8 = 3;
+
+// Buttons of the "Car MP3" remote control
+const BUTTON_PREV: u8 = 0x44;
+const BUTTON_NEXT: u8 = 0x40;
+const BUTTON_1: u8 = 0x0C;
+const BUTTON_2: u8 = 0x18;
+const BUTTON_3: u8 = 0x5E;
+
+// You need to tweak these min/max values for your servos as these may vary.
+// Be careful when doing this. Incorrect values can permanently damage your servos.
+const SERVO_MIN: u16 = 132; // pulse length for 0 degrees
+const SERVO_MAX: u16 = 608; // pulse length for 180 degrees
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        ir: PA1<Input<Floating>>,
+        decoder: NecDecoder,
+        last_edge: u32,
+        producer: Producer<'static, NecEvent, 8>,
+        consumer: Consumer<'static, NecEvent, 8>,
+        // Taken by the idle task, which creates the drivers.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        static mut QUEUE: Queue<NecEvent, 8> = Queue::new();
+
+        rtt_init_print!();
+        log_info!("NEC IR remote example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let mut ir = gpioa.pa1.into_floating_input(&mut gpioa.crl);
+        setup_exti_pin(&mut ir, &mut afio, &device.EXTI, Edge::RISING_FALLING);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // PCA9685 and display
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x40, 0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        let (producer, consumer) = QUEUE.split();
+        init::LateResources {
+            ir,
+            decoder: NecDecoder::new(),
+            last_edge: DWT::get_cycle_count(),
+            producer,
+            consumer,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = EXTI1, priority = 2, resources = [ir, decoder, last_edge, producer])]
+    fn ir_edge(cx: ir_edge::Context) {
+        let now = DWT::get_cycle_count();
+        let ir = cx.resources.ir;
+        ir.clear_interrupt_pending_bit();
+        let duration_us = now.wrapping_sub(*cx.resources.last_edge) / SYSCLK_MHZ;
+        *cx.resources.last_edge = now;
+        // The receiver output is low while the carrier is present so if it
+        // is high now, the pulse that just ended was a burst.
+        let burst = ir.is_high().unwrap();
+        if let Some(event) = cx.resources.decoder.pulse(burst, duration_us) {
+            cx.resources.producer.enqueue(event).ok();
+        }
+    }
+
+    #[idle(resources = [consumer, i2c, led])]
+    fn idle(cx: idle::Context) -> ! {
+        let i2c = cx.resources.i2c.take().unwrap();
+        let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+        let interface = I2CDIBuilder::new().init(manager.acquire());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut pwm = Pca9685::new(manager.acquire(), Address::default()).unwrap();
+        pwm.enable().unwrap();
+        // About 60Hz
+        pwm.set_prescale(100).unwrap();
+
+        let mut page = 0;
+        let mut angle = 90;
+        let mut last_event = None;
+        let mut repeats = 0;
+        let mut lines: [String<32>; 4] =
+            [String::new(), String::new(), String::new(), String::new()];
+        let mut redraw = true;
+        loop {
+            if let Some(event) = cx.resources.consumer.dequeue() {
+                log_info!("{:?}", event);
+                let (command, repeated) = match event {
+                    NecEvent::Command { command, .. } => (command, false),
+                    NecEvent::Repeat { command, .. } => (command, true),
+                };
+                repeats = if repeated { repeats + 1 } else { 0 };
+                // Holding a button only repeats the page changes.
+                match command {
+                    BUTTON_PREV => page = (page + PAGES - 1) % PAGES,
+                    BUTTON_NEXT => page = (page + 1) % PAGES,
+                    BUTTON_1 if !repeated => angle = 0,
+                    BUTTON_2 if !repeated => angle = 90,
+                    BUTTON_3 if !repeated => angle = 180,
+                    _ => (),
+                }
+                last_event = Some(event);
+                redraw = true;
+                cx.resources.led.set_low().unwrap();
+            }
+            if !redraw {
+                continue;
+            }
+            redraw = false;
+
+            let pulse = SERVO_MIN + ((SERVO_MAX - SERVO_MIN) as u32 * angle / 180) as u16;
+            pwm.set_channel_on_off(Channel::C0, 0, pulse).unwrap();
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            match page {
+                0 => {
+                    write!(lines[0], "Last received:").unwrap();
+                    match last_event {
+                        Some(NecEvent::Command { address, command })
+                        | Some(NecEvent::Repeat { address, command }) => {
+                            write!(lines[1], "Address: 0x{:X}", address).unwrap();
+                            write!(lines[2], "Command: 0x{:02X}", command).unwrap();
+                            write!(lines[3], "Repeats: {}", repeats).unwrap();
+                        }
+                        None => write!(lines[1], "Nothing").unwrap(),
+                    }
+                }
+                1 => {
+                    write!(lines[0], "Servo").unwrap();
+                    write!(lines[1], "Angle: {} deg", angle).unwrap();
+                    write!(lines[2], "Pulse: {}", pulse).unwrap();
+                }
+                _ => {
+                    write!(lines[0], "PREV/NEXT: page").unwrap();
+                    write!(lines[1], "1: servo 0 deg").unwrap();
+                    write!(lines[2], "2: servo 90 deg").unwrap();
+                    write!(lines[3], "3: servo 180 deg").unwrap();
+                }
+            }
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 16))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+            cx.resources.led.set_high().unwrap();
+        }
+    }
+};
@@ -0,0 +1,228 @@
+//! Send NEC infrared remote control codes with an IR LED when pressing
+//! buttons. This is the counterpart of the `nec-ir-remote-display-servo-bp`
+//! example: the buttons send the `NEXT` and `PREV` codes of the "Car MP3"
+//! remote control so that one board can control the other. It works with
+//! other receivers (like a TV) too if you change the address and commands.
+//!
+//! The 38kHz carrier is generated with PWM on TIM2. The bursts and spaces of
+//! the frames calculated with `nec::encode()` are timed with TIM3: on every
+//! TIM3 interrupt, the carrier is switched on or off and the timer is
+//! started again with the duration of the next burst or space.
+//! While a button is held, repeat frames are sent every 108ms.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board.
+//!
+//! ```
+//! BP   <-> IR LED circuit <-> Buttons
+//! GND  <-> GND
+//! 3.3V                    <-> +
+//! PA0  <-> 1K resistor <-> NPN transistor base
+//! PB12                    <-> NEXT button -
+//! PB13                    <-> PREV button -
+//! ```
+//!
+//! The IR LED (e.g. TSAL6200) goes with a series resistor (e.g. 47 ohm) from
+//! 5V to the collector of the transistor and its emitter goes to GND.
+//!
+//! Run with:
+//! `cargo embed --example nec-ir-transmitter-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    log_info,
+    nec::{self, FRAME_PULSES},
+    panic_display as _,
+};
+use embedded_hal::{
+    digital::v2::{InputPin, OutputPin},
+    Pwm,
+};
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::PA0,
+        gpiob::{PB12, PB13},
+        gpioc::PC13,
+        Alternate, Input, Output, PullDown, PushPull, State,
+    },
+    pac,
+    prelude::*,
+    pwm::{Channel, Pwm as TimerPwm, C1},
+    timer::{CountDownTimer, Event, Tim2NoRemap, Timer},
+};
+
+const SYSCLK_MHZ: u32 = 72;
+const ADDRESS: u16 = 0x00;
+const COMMAND_NEXT: u8 = 0x40;
+const COMMAND_PREV: u8 = 0x44;
+
+/// Frame being transmitted
+pub struct Transmission {
+    pulses: [u32; FRAME_PULSES],
+    len: usize,
+    index: usize,
+}
+
+impl Transmission {
+    fn is_done(&self) -> bool {
+        self.index >= self.len
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        carrier: TimerPwm<pac::TIM2, Tim2NoRemap, C1, PA0<Alternate<PushPull>>>,
+        timer: CountDownTimer<pac::TIM3>,
+        transmission: Transmission,
+        next_button: PB12<Input<PullDown>>,
+        prev_button: PB13<Input<PullDown>>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("NEC IR transmitter example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let carrier_pin = gpioa.pa0.into_alternate_push_pull(&mut gpioa.crl);
+        let mut carrier = Timer::tim2(device.TIM2, &clocks, &mut rcc.apb1)
+            .pwm::<Tim2NoRemap, _, _, _>(carrier_pin, &mut afio.mapr, 38.khz());
+        // A duty cycle of 1/3 is usual for IR transmitters.
+        carrier.set_duty(Channel::C1, carrier.get_max_duty() / 3);
+        carrier.disable(Channel::C1);
+
+        let timer = Timer::tim3(device.TIM3, &clocks, &mut rcc.apb1).start_count_down(1.hz());
+
+        let next_button = gpiob.pb12.into_pull_down_input(&mut gpiob.crh);
+        let prev_button = gpiob.pb13.into_pull_down_input(&mut gpiob.crh);
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            carrier,
+            timer,
+            transmission: Transmission {
+                pulses: [0; FRAME_PULSES],
+                len: 0,
+                index: 0,
+            },
+            next_button,
+            prev_button,
+            led,
+        }
+    }
+
+    #[task(binds = TIM3, priority = 2, resources = [carrier, timer, transmission])]
+    fn pulse(cx: pulse::Context) {
+        let timer = cx.resources.timer;
+        let carrier = cx.resources.carrier;
+        let transmission = cx.resources.transmission;
+        timer.clear_update_interrupt_flag();
+        transmission.index += 1;
+        if transmission.is_done() {
+            carrier.disable(Channel::C1);
+            timer.unlisten(Event::Update);
+        } else {
+            start_pulse(carrier, timer, transmission);
+        }
+    }
+
+    #[idle(resources = [carrier, timer, transmission, next_button, prev_button, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let mut last_command = None;
+        let mut last_frame = DWT::get_cycle_count();
+        loop {
+            let command = if cx.resources.next_button.is_high().unwrap() {
+                Some(COMMAND_NEXT)
+            } else if cx.resources.prev_button.is_high().unwrap() {
+                Some(COMMAND_PREV)
+            } else {
+                None
+            };
+            let elapsed_us = DWT::get_cycle_count().wrapping_sub(last_frame) / SYSCLK_MHZ;
+            if command.is_none() {
+                last_command = None;
+                cx.resources.led.set_high().unwrap();
+                continue;
+            }
+            if elapsed_us < nec::FRAME_PERIOD_US {
+                continue;
+            }
+            last_frame = DWT::get_cycle_count();
+            cx.resources.led.set_low().unwrap();
+
+            let repeat = command == last_command;
+            if repeat {
+                log_info!("Repeat");
+            } else {
+                log_info!("Sending command 0x{:02X}", command.unwrap());
+            }
+            last_command = command;
+
+            let resources = &mut cx.resources;
+            let timer = &mut resources.timer;
+            let transmission = &mut resources.transmission;
+            resources.carrier.lock(|carrier| {
+                timer.lock(|timer| {
+                    transmission.lock(|transmission| {
+                        if repeat {
+                            transmission.pulses[..3].copy_from_slice(&nec::REPEAT_PULSES);
+                            transmission.len = nec::REPEAT_PULSES.len();
+                        } else {
+                            transmission.pulses = nec::encode(ADDRESS, command.unwrap());
+                            transmission.len = FRAME_PULSES;
+                        }
+                        transmission.index = 0;
+                        start_pulse(carrier, timer, transmission);
+                        timer.listen(Event::Update);
+                    })
+                })
+            });
+        }
+    }
+};
+
+/// Switch the carrier on for bursts (even indices) and off for spaces and
+/// time the current pulse.
+fn start_pulse(
+    carrier: &mut TimerPwm<pac::TIM2, Tim2NoRemap, C1, PA0<Alternate<PushPull>>>,
+    timer: &mut CountDownTimer<pac::TIM3>,
+    transmission: &Transmission,
+) {
+    if transmission.index % 2 == 0 {
+        carrier.enable(Channel::C1);
+    } else {
+        carrier.disable(Channel::C1);
+    }
+    let duration_us = transmission.pulses[transmission.index];
+    timer.start((1_000_000 / duration_us).hz());
+}
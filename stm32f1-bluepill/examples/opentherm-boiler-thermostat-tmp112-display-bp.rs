@@ -0,0 +1,373 @@
+//! Room thermostat talking OpenTherm to a boiler: measure the room
+//! temperature with a TMP112, calculate the water temperature needed with
+//! a PI controller and send it to the boiler, which answers with its water
+//! temperature, modulation level and flame status. The values and the last
+//! exchange are shown on an SSD1306 OLED display.
+//!
+//! The thermostat is the OpenTherm master and sends one request every
+//! `REQUEST_INTERVAL_MS`, going through the status, the control setpoint,
+//! the water temperature and the modulation level. The frames are encoded
+//! and decoded with the `opentherm` module of this crate:
+//! - TIM3 interrupts every 500µs and sets the output to the level of the
+//!   next half bit of the request.
+//! - The time between the edges of the input is measured with the cycle
+//!   counter in an interrupt triggered on both edges and fed into the
+//!   `Decoder`, like in the nec-ir-remote-display-servo-bp example.
+//!
+//! The boiler line needs an interface circuit, like the OpenTherm adapter
+//! of Ihor Melnyk: its output is active low and its input active high. If
+//! the boiler does not answer within 800ms the request is skipped.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> OpenTherm adapter <-> TMP112 <-> Display
+//! GND  <-> GND               <-> GND    <-> GND
+//! 3.3V <-> VCC               <-> VCC    <-> VDD
+//! PA0  <-> OUT
+//! PA1  <-> IN
+//! PB8                        <-> SCL    <-> SCL
+//! PB9                        <-> SDA    <-> SDA
+//!          OT1, OT2 <-> boiler
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example opentherm-boiler-thermostat-tmp112-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    exti::setup_exti_pin,
+    i2c_speed, log_info, log_warn,
+    opentherm::{
+        self, Decoder, Frame, MessageType, BOILER_TEMPERATURE, CONTROL_SETPOINT, FRAME_HALF_BITS,
+        MASTER_CH_ENABLE, RELATIVE_MODULATION, SLAVE_FAULT, SLAVE_FLAME, STATUS,
+    },
+    panic_display::{self, Bus},
+    pi::PiController,
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::{
+    spsc::{Consumer, Producer, Queue},
+    String,
+};
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::{PA0, PA1},
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Floating, Input, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    timer::{CountDownTimer, Event, Timer},
+};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const REQUEST_INTERVAL_MS: u32 = 250;
+const ANSWER_TIMEOUT_MS: u32 = 800;
+const ROOM_SETPOINT: f32 = 21.0;
+/// Water temperature around which the controller works
+const WATER_BASE: f32 = 45.0;
+const WATER_MIN: f32 = 20.0;
+const WATER_MAX: f32 = 70.0;
+
+/// Request being transmitted
+pub struct Transmission {
+    levels: [bool; FRAME_HALF_BITS],
+    index: usize,
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        ot_out: PA0<Output<PushPull>>,
+        ot_in: PA1<Input<Floating>>,
+        timer: CountDownTimer<pac::TIM3>,
+        transmission: Transmission,
+        decoder: Decoder,
+        last_edge: u32,
+        producer: Producer<'static, Frame, 4>,
+        consumer: Consumer<'static, Frame, 4>,
+        // Taken by the idle task, which creates the drivers.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        static mut QUEUE: Queue<Frame, 4> = Queue::new();
+
+        rtt_init_print!();
+        log_info!("OpenTherm boiler thermostat example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // The output of the adapter is active low, so high is idle.
+        let ot_out = gpioa
+            .pa0
+            .into_push_pull_output_with_state(&mut gpioa.crl, State::High);
+        let mut ot_in = gpioa.pa1.into_floating_input(&mut gpioa.crl);
+        setup_exti_pin(&mut ot_in, &mut afio, &device.EXTI, Edge::RISING_FALLING);
+
+        let timer = Timer::tim3(device.TIM3, &clocks, &mut rcc.apb1)
+            .start_count_down((1_000_000 / opentherm::HALF_BIT_US).hz());
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // TMP112 sensor and display
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x48, 0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        let (producer, consumer) = QUEUE.split();
+        init::LateResources {
+            ot_out,
+            ot_in,
+            timer,
+            transmission: Transmission {
+                levels: [false; FRAME_HALF_BITS],
+                index: FRAME_HALF_BITS,
+            },
+            decoder: Decoder::new(),
+            last_edge: DWT::get_cycle_count(),
+            producer,
+            consumer,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = TIM3, priority = 2, resources = [ot_out, timer, transmission])]
+    fn half_bit(cx: half_bit::Context) {
+        let transmission = cx.resources.transmission;
+        cx.resources.timer.clear_update_interrupt_flag();
+        transmission.index += 1;
+        if transmission.index >= FRAME_HALF_BITS {
+            cx.resources.timer.unlisten(Event::Update);
+        } else {
+            set_level(cx.resources.ot_out, transmission.levels[transmission.index]);
+        }
+    }
+
+    #[task(binds = EXTI1, priority = 2, resources = [ot_in, decoder, last_edge, producer])]
+    fn ot_edge(cx: ot_edge::Context) {
+        let now = DWT::get_cycle_count();
+        let ot_in = cx.resources.ot_in;
+        ot_in.clear_interrupt_pending_bit();
+        let duration_us = now.wrapping_sub(*cx.resources.last_edge) / SYSCLK_MHZ;
+        *cx.resources.last_edge = now;
+        // The input is active high, so if it is low now, the period that
+        // just ended was active.
+        let active = ot_in.is_low().unwrap();
+        if let Some(frame) = cx.resources.decoder.pulse(active, duration_us) {
+            cx.resources.producer.enqueue(frame).ok();
+        }
+    }
+
+    #[idle(resources = [ot_out, timer, transmission, consumer, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let i2c = cx.resources.i2c.take().unwrap();
+        let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+        let interface = I2CDIBuilder::new().init(manager.acquire());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut sensor = Tmp1x2::new(manager.acquire(), SlaveAddr::default());
+        let mut pi =
+            PiController::new(10.0, 0.02).limits(WATER_MIN - WATER_BASE, WATER_MAX - WATER_BASE);
+
+        let requests = [
+            STATUS,
+            CONTROL_SETPOINT,
+            BOILER_TEMPERATURE,
+            RELATIVE_MODULATION,
+        ];
+        let mut next_request = 0;
+        let mut room = None;
+        let mut water_setpoint = WATER_MIN;
+        let mut water = None;
+        let mut modulation = None;
+        let mut boiler_status = 0;
+        let mut lines: [String<32>; 4] =
+            [String::new(), String::new(), String::new(), String::new()];
+        let cycles_per_ms = SYSCLK_MHZ * 1000;
+        let mut last_control = DWT::get_cycle_count();
+        loop {
+            let start = DWT::get_cycle_count();
+            let data_id = requests[next_request];
+            next_request = (next_request + 1) % requests.len();
+
+            if data_id == STATUS {
+                // Once per round: update the room temperature and the
+                // water temperature needed.
+                match sensor.read_temperature() {
+                    Ok(temperature) => {
+                        let dt_s = start.wrapping_sub(last_control) as f32
+                            / (cycles_per_ms as f32 * 1000.0);
+                        last_control = start;
+                        water_setpoint = WATER_BASE + pi.update(ROOM_SETPOINT, temperature, dt_s);
+                        room = Some(temperature);
+                    }
+                    Err(e) => log_warn!("Sensor error: {:?}", e),
+                }
+            }
+            let request = match data_id {
+                STATUS => Frame::read(STATUS, MASTER_CH_ENABLE),
+                CONTROL_SETPOINT => Frame::write(CONTROL_SETPOINT, opentherm::f88(water_setpoint)),
+                _ => Frame::read(data_id, 0),
+            };
+
+            // Start the transmission with the first half bit.
+            let resources = &mut cx.resources;
+            let timer = &mut resources.timer;
+            let transmission = &mut resources.transmission;
+            resources.ot_out.lock(|ot_out| {
+                timer.lock(|timer| {
+                    transmission.lock(|transmission| {
+                        transmission.levels = opentherm::encode(&request);
+                        transmission.index = 0;
+                        set_level(ot_out, transmission.levels[0]);
+                        timer.start((1_000_000 / opentherm::HALF_BIT_US).hz());
+                        timer.listen(Event::Update);
+                    })
+                })
+            });
+            cx.resources.led.set_low().unwrap();
+
+            let answer = loop {
+                if let Some(frame) = cx.resources.consumer.dequeue() {
+                    break Some(frame);
+                }
+                if DWT::get_cycle_count().wrapping_sub(start) >= ANSWER_TIMEOUT_MS * cycles_per_ms {
+                    break None;
+                }
+            };
+            cx.resources.led.set_high().unwrap();
+
+            lines[3].clear();
+            match answer {
+                Some(frame) => {
+                    log_info!("{:?} -> {:?}", request, frame);
+                    write!(
+                        lines[3],
+                        "ID{} {:04X}>{:04X}",
+                        request.data_id, request.value, frame.value
+                    )
+                    .unwrap();
+                    if frame.message_type == MessageType::ReadAck {
+                        match frame.data_id {
+                            STATUS => boiler_status = frame.value,
+                            BOILER_TEMPERATURE => water = Some(frame.value_f88()),
+                            RELATIVE_MODULATION => modulation = Some(frame.value_f88()),
+                            _ => (),
+                        }
+                    }
+                }
+                None => {
+                    log_warn!("No answer to {:?}", request);
+                    write!(lines[3], "ID{} no answer", request.data_id).unwrap();
+                }
+            }
+
+            for line in lines[..3].iter_mut() {
+                line.clear();
+            }
+            match room {
+                Some(room) => write!(lines[0], "Room {:.1}/{:.1}C", room, ROOM_SETPOINT),
+                None => write!(lines[0], "Room -/{:.1}C", ROOM_SETPOINT),
+            }
+            .unwrap();
+            match water {
+                Some(water) => write!(lines[1], "Water {:.1}/{:.1}C", water, water_setpoint),
+                None => write!(lines[1], "Water -/{:.1}C", water_setpoint),
+            }
+            .unwrap();
+            let flame = if boiler_status & SLAVE_FAULT != 0 {
+                "FAULT"
+            } else if boiler_status & SLAVE_FLAME != 0 {
+                "flame"
+            } else {
+                "off"
+            };
+            match modulation {
+                Some(modulation) => write!(lines[2], "Mod {:.0}% {}", modulation, flame),
+                None => write!(lines[2], "Mod - {}", flame),
+            }
+            .unwrap();
+
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 16))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+
+            while DWT::get_cycle_count().wrapping_sub(start) < REQUEST_INTERVAL_MS * cycles_per_ms {
+            }
+        }
+    }
+};
+
+/// Set the output of the adapter, which is active low.
+fn set_level(ot_out: &mut PA0<Output<PushPull>>, active: bool) {
+    if active {
+        ot_out.set_low().unwrap();
+    } else {
+        ot_out.set_high().unwrap();
+    }
+}
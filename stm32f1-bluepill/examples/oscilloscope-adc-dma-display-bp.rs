@@ -0,0 +1,316 @@
+//! A simple oscilloscope: sample a signal with the internal ADC at up to
+//! 850kHz and show the waveform on an SSD1306 OLED display, with the time
+//! and voltage per division, the frequency and the peak to peak voltage.
+//!
+//! ADC1 runs in continuous mode and DMA1 channel 1 streams every conversion
+//! into the two halves of a buffer with the `adc_stream` module, without the
+//! CPU. Each time a half of `BUFFER` samples is complete it is copied out
+//! and displayed; the halves completed meanwhile are skipped. The sampling
+//! rate is set by the ADC clock (12MHz) and the sample time: a conversion
+//! takes the sample time plus 12.5 ADC cycles. The slowest time base also
+//! averages groups of samples.
+//!
+//! When the buffer is full it is searched for the trigger: a rising edge
+//! through the trigger level, set with a potentiometer read by ADC2. To
+//! ignore noise the signal must first be `HYSTERESIS` below the level. The
+//! trace starts one division before the trigger, which is marked at the
+//! top. If there is no trigger the start of the buffer is shown and the
+//! display shows "AUTO". The trace is centered on the trigger level. The
+//! frequency is measured between the trigger and the next rising edge.
+//!
+//! Buttons select the time per division and the voltage per division.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> Display <-> Potentiometer <-> Buttons
+//! GND  <-> GND     <-> GND           <-> Button 1, Button 2
+//! 3.3V <-> VDD     <-> VCC
+//! PA0: Input signal (0-3.3V)
+//! PA1              <-> Wiper
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! PB12                               <-> Button 1 (time/div)
+//! PB13                               <-> Button 2 (volt/div)
+//! ```
+//!
+//! The input must stay between 0V and 3.3V. The fastest time base has a
+//! very short sample time: the source impedance should be below 1K there.
+//!
+//! Run with:
+//! `cargo embed --example oscilloscope-adc-dma-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::singleton;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    adc_stream::{conversion_cycles, AdcStream},
+    convert::{adc_to_millivolts, average},
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Line,
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    adc::{self, SampleTime},
+    pac,
+    prelude::*,
+};
+
+const ADC_MHZ: u32 = 12;
+const BUFFER: usize = 1024;
+/// Raw ADC steps the signal must fall below the level to arm the trigger
+const HYSTERESIS: u16 = 40;
+
+// Screen layout: two lines of text above the trace
+const WIDTH: usize = 128;
+const TRACE_TOP: i32 = 16;
+const TRACE_BOTTOM: i32 = 63;
+const TRACE_CENTER: i32 = 40;
+const PX_PER_DIV: i32 = 16;
+/// Samples shown before the trigger, in pixels
+const PRE_TRIGGER: usize = 16;
+
+/// ADC sample time and the number of samples averaged per pixel
+const TIME_BASES: [(SampleTime, usize); 5] = [
+    (SampleTime::T_1, 1),
+    (SampleTime::T_28, 1),
+    (SampleTime::T_71, 1),
+    (SampleTime::T_239, 1),
+    (SampleTime::T_239, 4),
+];
+const VOLTS_PER_DIV_MV: [i32; 5] = [2000, 1000, 500, 200, 100];
+
+/// Index of the first rising edge through `level` at or after `from`
+fn rising_edge(samples: &[u16], from: usize, level: u16) -> Option<usize> {
+    let mut armed = false;
+    for (i, sample) in samples.iter().enumerate().skip(from) {
+        if *sample + HYSTERESIS < level {
+            armed = true;
+        } else if armed && *sample >= level {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Write a time given in nanoseconds.
+fn write_time<W: Write>(w: &mut W, ns: u32) -> core::fmt::Result {
+    if ns < 1_000_000 {
+        write!(w, "{:.1}us", ns as f32 / 1e3)
+    } else {
+        write!(w, "{:.2}ms", ns as f32 / 1e6)
+    }
+}
+
+fn write_frequency<W: Write>(w: &mut W, hz: f32) -> core::fmt::Result {
+    if hz < 1e3 {
+        write!(w, "{:.1}Hz", hz)
+    } else {
+        write!(w, "{:.2}kHz", hz / 1e3)
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Oscilloscope example");
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Enable the ADC1 clock before handing the RCC over to the HAL.
+    dp.RCC.apb2enr.modify(|_, w| w.adc1en().set_bit());
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .adcclk(ADC_MHZ.mhz())
+        .freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let dma_ch1 = dp.DMA1.split(&mut rcc.ahb).1;
+    let _input = gpioa.pa0.into_analog(&mut gpioa.crl);
+    let buffer = singleton!(: [[u16; BUFFER]; 2] = [[0; BUFFER]; 2]).unwrap();
+    let mut stream = AdcStream::start(dp.ADC1, dma_ch1, &[0], TIME_BASES[0].0, buffer);
+
+    let mut adc2 = adc::Adc::adc2(dp.ADC2, &mut rcc.apb2, clocks);
+    let mut potentiometer = gpioa.pa1.into_analog(&mut gpioa.crl);
+
+    let time_button = gpiob.pb12.into_pull_up_input(&mut gpiob.crh);
+    let volts_button = gpiob.pb13.into_pull_up_input(&mut gpiob.crh);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+    let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+
+    let mut time_base = 0;
+    let mut volts_per_div = 1;
+    let mut buttons_pressed = (false, false);
+    let mut lines: [String<32>; 2] = Default::default();
+    let mut buffer = [0; BUFFER];
+    let mut led_on = false;
+    loop {
+        while stream
+            .poll(|samples| buffer.copy_from_slice(samples))
+            .is_none()
+        {}
+
+        // A frame takes at least 25ms, which debounces the buttons.
+        let pressed = (
+            time_button.is_low().unwrap(),
+            volts_button.is_low().unwrap(),
+        );
+        if pressed.0 && !buttons_pressed.0 {
+            time_base = (time_base + 1) % TIME_BASES.len();
+            // The sample time can only be changed while the DMA is stopped.
+            let (adc1, dma_ch1, samples) = stream.stop();
+            stream = AdcStream::start(adc1, dma_ch1, &[0], TIME_BASES[time_base].0, samples);
+        }
+        if pressed.1 && !buttons_pressed.1 {
+            volts_per_div = (volts_per_div + 1) % VOLTS_PER_DIV_MV.len();
+        }
+        buttons_pressed = pressed;
+
+        let level: u16 = adc2.read(&mut potentiometer).unwrap();
+        let (sample_time, averaged) = TIME_BASES[time_base];
+        let sample_ns = (conversion_cycles(sample_time) * 1000.0) as u32 / ADC_MHZ;
+        let span = WIDTH * averaged;
+        let pre_trigger = PRE_TRIGGER * averaged;
+
+        // Only triggers with a full screen after them count.
+        let trigger = rising_edge(&buffer[..], pre_trigger, level)
+            .filter(|t| t + span - pre_trigger <= BUFFER);
+        let start = trigger.map_or(0, |t| t - pre_trigger);
+        let shown = &buffer[start..start + span];
+
+        let min = *shown.iter().min().unwrap();
+        let max = *shown.iter().max().unwrap();
+        let frequency = trigger
+            .and_then(|t| rising_edge(&buffer[..], t, level).map(|next| (t, next)))
+            .map(|(t, next)| 1e9 / ((next - t) as f32 * sample_ns as f32));
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        write_time(
+            &mut lines[0],
+            sample_ns * averaged as u32 * PX_PER_DIV as u32,
+        )
+        .unwrap();
+        write!(
+            lines[0],
+            " {}mV {}",
+            VOLTS_PER_DIV_MV[volts_per_div],
+            if trigger.is_some() { "TRIG" } else { "AUTO" }
+        )
+        .unwrap();
+        match frequency {
+            Some(hz) => write_frequency(&mut lines[1], hz).unwrap(),
+            None => write!(lines[1], "-").unwrap(),
+        }
+        write!(
+            lines[1],
+            " {:.2}Vpp",
+            (adc_to_millivolts(max) - adc_to_millivolts(min)) as f32 / 1000.0
+        )
+        .unwrap();
+
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 8))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+
+        // Graticule: a dot at every crossing of the divisions
+        for x in (0..WIDTH as i32).step_by(PX_PER_DIV as usize) {
+            for y in
+                (TRACE_CENTER - PX_PER_DIV..=TRACE_CENTER + PX_PER_DIV).step_by(PX_PER_DIV as usize)
+            {
+                Pixel(Point::new(x, y), BinaryColor::On)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+        }
+        // Trigger mark at the top
+        if trigger.is_some() {
+            Line::new(
+                Point::new(PRE_TRIGGER as i32, TRACE_TOP),
+                Point::new(PRE_TRIGGER as i32, TRACE_TOP + 3),
+            )
+            .into_styled(outline)
+            .draw(&mut disp)
+            .unwrap();
+        }
+
+        // The trace, centered on the trigger level
+        let level_mv = adc_to_millivolts(level) as i32;
+        let scale = VOLTS_PER_DIV_MV[volts_per_div];
+        let mut previous = None;
+        for (x, pixel) in shown.chunks(averaged).enumerate() {
+            let mv = adc_to_millivolts(average(pixel.iter().copied())) as i32;
+            let y = (TRACE_CENTER - (mv - level_mv) * PX_PER_DIV / scale)
+                .clamp(TRACE_TOP, TRACE_BOTTOM);
+            let point = Point::new(x as i32, y);
+            Line::new(previous.unwrap_or(point), point)
+                .into_styled(outline)
+                .draw(&mut disp)
+                .unwrap();
+            previous = Some(point);
+        }
+        disp.flush().unwrap();
+
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led_on = !led_on;
+        if led_on {
+            led.set_high().unwrap();
+        } else {
+            led.set_low().unwrap();
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Control the speed of a 4-pin PC fan depending on the temperature measured
+//! with a TMP112 sensor and show the target and actual speed on an SSD1306
+//! OLED display.
+//!
+//! - The fan speed is set with a 25kHz PWM signal from TIM2.
+//! - The fan tachometer output gives two pulses per revolution. TIM3
+//!   captures the time of each pulse, so that the speed is measured
+//!   precisely even if the main loop only checks the captures every
+//!   millisecond.
+//! - The target speed comes from a temperature curve (`CURVE`), linearly
+//!   interpolated between its points.
+//! - Each fan reacts differently to the PWM duty cycle, so the duty cycle
+//!   expected for the target speed is trimmed with the `PiController` from
+//!   this crate until the measured speed matches.
+//!
+//! If the temperature cannot be read, the fan runs at full speed. If no
+//! tachometer pulses arrive, the display shows a stall warning.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> Fan      <-> TMP112 <-> Display
+//! GND  <-> GND (1)  <-> GND    <-> GND
+//!          +12V (2)
+//! 3.3V              <-> VCC    <-> VDD
+//! PA6  <-> TACH (3)
+//! PA0  <-> PWM (4)
+//! PB8               <-> SCL    <-> SCL
+//! PB9               <-> SDA    <-> SDA
+//! ```
+//!
+//! The tachometer output is open-collector and is pulled up by the board.
+//! Do not connect it to a pull-up resistor to 12V.
+//!
+//! Run with:
+//! `cargo embed --example pc-fan-tach-tmp112-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_info, monotonic,
+    panic_display::{self, Bus},
+    pi::PiController,
+    scheduler::Scheduler,
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::{digital::v2::OutputPin, Pwm};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    pac,
+    prelude::*,
+    pwm::Channel,
+    timer::{Tim2NoRemap, Timer},
+};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+const SYSCLK_MHZ: u32 = 72;
+/// Frequency of the tachometer capture timer
+const TACH_TIMER_HZ: u32 = 100_000;
+const PULSES_PER_REVOLUTION: u32 = 2;
+/// Without pulses for this long the fan is considered stalled.
+const STALL_TIMEOUT_MS: u32 = 1000;
+const MAX_RPM: f32 = 2000.0;
+/// Lowest duty cycle. Many fans stop or ignore lower values.
+const MIN_DUTY: f32 = 0.2;
+/// Target speed in RPM at some temperatures in Celsius
+const CURVE: [(f32, f32); 4] = [
+    (25.0, 600.0),
+    (35.0, 1000.0),
+    (45.0, 1600.0),
+    (55.0, MAX_RPM),
+];
+const CONTROL_PERIOD_MS: u32 = 500;
+
+// Tasks in order of priority
+const TACH: usize = 0;
+const CONTROL: usize = 1;
+const TEMPERATURE: usize = 2;
+const BLINK: usize = 3;
+
+/// Target speed for the given temperature
+fn target_rpm(temperature: f32) -> f32 {
+    let (first_temperature, first_rpm) = CURVE[0];
+    if temperature <= first_temperature {
+        return first_rpm;
+    }
+    for pair in CURVE.windows(2) {
+        let ((t0, rpm0), (t1, rpm1)) = (pair[0], pair[1]);
+        if temperature <= t1 {
+            return rpm0 + (rpm1 - rpm0) * (temperature - t0) / (t1 - t0);
+        }
+    }
+    MAX_RPM
+}
+
+/// Accumulates the time between tachometer pulses
+#[derive(Debug, Default)]
+struct Tachometer {
+    last_capture: Option<u16>,
+    ticks: u32,
+    pulses: u32,
+    last_pulse_ms: u32,
+}
+
+impl Tachometer {
+    fn capture(&mut self, capture: u16, now_ms: u32) {
+        if let Some(last) = self.last_capture {
+            self.ticks += u32::from(capture.wrapping_sub(last));
+            self.pulses += 1;
+        }
+        self.last_capture = Some(capture);
+        self.last_pulse_ms = now_ms;
+    }
+
+    /// A pulse was missed, so the time to the next one is not valid.
+    fn restart(&mut self) {
+        self.last_capture = None;
+    }
+
+    /// Speed since the last call, `None` if there were no pulses to measure
+    /// it from.
+    fn rpm(&mut self) -> Option<f32> {
+        let result = if self.pulses > 0 {
+            let seconds = self.ticks as f32 / TACH_TIMER_HZ as f32;
+            Some(self.pulses as f32 / PULSES_PER_REVOLUTION as f32 * 60.0 / seconds)
+        } else {
+            None
+        };
+        self.ticks = 0;
+        self.pulses = 0;
+        result
+    }
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("PC fan controller example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    // Enable the TIM3 clock before handing the RCC over to the HAL.
+    dp.RCC.apb1enr.modify(|_, w| w.tim3en().set_bit());
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(SYSCLK_MHZ.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let fan_pin = gpioa.pa0.into_alternate_push_pull(&mut gpioa.crl);
+    let mut fan = Timer::tim2(dp.TIM2, &clocks, &mut rcc.apb1).pwm::<Tim2NoRemap, _, _, _>(
+        fan_pin,
+        &mut afio.mapr,
+        25.khz(),
+    );
+    let max_duty = fan.get_max_duty();
+    fan.set_duty(Channel::C1, max_duty);
+    fan.enable(Channel::C1);
+
+    // TIM3 channel 1 input. The TIM3 clock is twice PCLK1, so 72MHz.
+    let _tach_input = gpioa.pa6.into_pull_up_input(&mut gpioa.crl);
+    let tach_timer = dp.TIM3;
+    tach_timer.psc.write(|w| {
+        w.psc()
+            .bits((SYSCLK_MHZ * 1_000_000 / TACH_TIMER_HZ - 1) as u16)
+    });
+    tach_timer.egr.write(|w| w.ug().set_bit());
+    // Capture the counter on the falling edges of TI1 (the fan pulls the
+    // output low twice per revolution), filtering short glitches.
+    tach_timer
+        .ccmr1_input()
+        .modify(|_, w| w.cc1s().ti1().ic1f().fck_int_n8());
+    tach_timer
+        .ccer
+        .modify(|_, w| w.cc1p().set_bit().cc1e().set_bit());
+    tach_timer.cr1.modify(|_, w| w.cen().enabled());
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // TMP112 sensor and display
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x48, 0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut sensor = Tmp1x2::new(manager.acquire(), SlaveAddr::default());
+
+    let mut tachometer = Tachometer::default();
+    let mut pi = PiController::new(0.0002, 0.0004).limits(-0.5, 0.5);
+    let mut temperature = None;
+    let mut actual_rpm = 0.0;
+    let mut duty = 1.0;
+    let mut lines: [String<32>; 4] = Default::default();
+    let mut scheduler = Scheduler::new([1, CONTROL_PERIOD_MS, 2000, 500]);
+    let mut led_on = false;
+    loop {
+        match scheduler.poll() {
+            Some(TACH) => {
+                let status = tach_timer.sr.read();
+                if status.cc1of().bit_is_set() {
+                    tach_timer.sr.modify(|_, w| w.cc1of().clear_bit());
+                    tachometer.restart();
+                }
+                if status.cc1if().bit_is_set() {
+                    // Reading the capture clears the flag.
+                    let capture = tach_timer.ccr1.read().bits() as u16;
+                    tachometer.capture(capture, monotonic::millis());
+                }
+            }
+            Some(CONTROL) => {
+                let stalled =
+                    monotonic::millis().wrapping_sub(tachometer.last_pulse_ms) > STALL_TIMEOUT_MS;
+                if stalled {
+                    actual_rpm = 0.0;
+                    tachometer.restart();
+                } else if let Some(rpm) = tachometer.rpm() {
+                    actual_rpm = rpm;
+                }
+
+                let target = temperature.map_or(MAX_RPM, target_rpm);
+                duty = if temperature.is_none() || stalled {
+                    // Run at full speed and start trimming again later.
+                    pi.reset();
+                    1.0
+                } else {
+                    let feedforward = target / MAX_RPM;
+                    let trim = pi.update(target, actual_rpm, CONTROL_PERIOD_MS as f32 / 1000.0);
+                    (feedforward + trim).clamp(MIN_DUTY, 1.0)
+                };
+                fan.set_duty(Channel::C1, (duty * f32::from(max_duty)) as u16);
+
+                for line in lines.iter_mut() {
+                    line.clear();
+                }
+                match temperature {
+                    Some(t) => write!(lines[0], "Temperature: {:.1}C", t).unwrap(),
+                    None => write!(lines[0], "Temperature: error").unwrap(),
+                }
+                write!(lines[1], "Target: {:.0} rpm", target).unwrap();
+                if stalled {
+                    write!(lines[2], "Actual: STALLED").unwrap();
+                } else {
+                    write!(lines[2], "Actual: {:.0} rpm", actual_rpm).unwrap();
+                }
+                write!(lines[3], "Duty: {:.0}%", duty * 100.0).unwrap();
+                disp.clear();
+                for (i, line) in lines.iter().enumerate() {
+                    Text::new(line, Point::new(0, i as i32 * 16))
+                        .into_styled(text_style)
+                        .draw(&mut disp)
+                        .unwrap();
+                }
+                disp.flush().unwrap();
+            }
+            Some(TEMPERATURE) => {
+                temperature = sensor.read_temperature().ok();
+                log_info!(
+                    "Temperature: {:?}, actual: {:.0} rpm, duty: {:.2}",
+                    temperature,
+                    actual_rpm,
+                    duty
+                );
+            }
+            Some(BLINK) => {
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                led_on = !led_on;
+                if led_on {
+                    led.set_high().unwrap();
+                } else {
+                    led.set_low().unwrap();
+                }
+            }
+            _ => (),
+        }
+    }
+}
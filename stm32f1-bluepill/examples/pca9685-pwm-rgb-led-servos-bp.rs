@@ -24,20 +24,15 @@
 #![no_main]
 
 use cortex_m_rt::entry;
-use panic_rtt_target as _;
+use driver_examples_bluepill::{color::Rainbow, i2c_speed, log_info, panic_display as _};
 use pwm_pca9685::{Address, Pca9685};
-use rtt_target::{rprintln, rtt_init_print};
-use stm32f1xx_hal::{
-    delay::Delay,
-    i2c::{BlockingI2c, DutyCycle, Mode},
-    pac,
-    prelude::*,
-};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*};
 
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("PCA9685 example");
+    log_info!("PCA9685 example");
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
 
@@ -52,20 +47,14 @@ fn main() -> ! {
     let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
     let mut delay = Delay::new(cp.SYST, clocks);
 
-    let i2c = BlockingI2c::i2c1(
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
         dp.I2C1,
         (scl, sda),
         &mut afio.mapr,
-        Mode::Fast {
-            frequency: 400_000.hz(),
-            duty_cycle: DutyCycle::Ratio2to1,
-        },
         clocks,
         &mut rcc.apb1,
-        1000,
-        10,
-        1000,
-        1000,
+        // PCA9685
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x40]),
     );
 
     let mut pwm = Pca9685::new(i2c, Address::default()).unwrap();
@@ -104,37 +93,6 @@ fn main() -> ! {
     }
 }
 
-/// RGB rainbow generator
-struct Rainbow {
-    hue: u16,
-}
-
-impl Rainbow {
-    fn new(hue: u16) -> Self {
-        Rainbow { hue }
-    }
-}
-
-impl Iterator for Rainbow {
-    type Item = (u16, u16, u16);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        // See HSV to RGB conversion: https://en.wikipedia.org/wiki/HSL_and_HSV
-        // To avoid floating point calculations and ensure smooth transitions
-        // the value range is limited to [0-4080] as 4080 = 60*68.
-        self.hue = (self.hue + 1) % 361;
-        match self.hue {
-            0..=59 => Some((4080, self.hue * 68, 0)),
-            60..=119 => Some(((120 - self.hue) * 68, 4080, 0)),
-            120..=179 => Some((0, 4080, (self.hue - 120) * 68)),
-            180..=239 => Some((0, (240 - self.hue) * 68, 4080)),
-            240..=299 => Some(((self.hue - 240) * 68, 0, 4080)),
-            300..=360 => Some((4080, 0, (360 - self.hue) * 68)),
-            _ => None,
-        }
-    }
-}
-
 struct Servo {
     current: u16,
     factor: i16,
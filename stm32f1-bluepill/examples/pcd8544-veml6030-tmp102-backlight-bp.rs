@@ -0,0 +1,286 @@
+//! Show the ambient light measured with a VEML6030 and the temperature
+//! measured with a TMP102 on a Nokia 5110 (PCD8544) 84x48 LCD and adjust
+//! the display backlight to the ambient light.
+//!
+//! The PCD8544 draws only a few hundred microamperes without backlight,
+//! which makes it a good low-power alternative to an OLED display when the
+//! device runs on batteries. Since it is a reflective LCD, it is readable in
+//! bright light without backlight, so the backlight brightness is reduced
+//! as the ambient light increases and switched off completely above
+//! `BACKLIGHT_OFF_LUX`.
+//!
+//! The contrast (operating voltage), bias and temperature coefficient are
+//! configured at the beginning. The best contrast value varies between
+//! modules. If the display looks all black or empty, try values between
+//! 0x30 and 0x50.
+//!
+//! A minimal PCD8544 driver with a frame buffer is included in this example.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1 for the
+//! display and I2C1 for the sensors.
+//!
+//! ```
+//! BP   <-> PCD8544 <-> VEML6030 <-> TMP102
+//! GND  <-> GND     <-> GND      <-> GND
+//! 3.3V <-> VCC     <-> VCC      <-> VCC
+//! PA5  <-> CLK
+//! PA7  <-> DIN
+//! PB0  <-> CE
+//! PB1  <-> DC
+//! PB10 <-> RST
+//! PA0  <-> LIGHT
+//! PB8              <-> SCL      <-> SCL
+//! PB9              <-> SDA      <-> SDA
+//! ```
+//!
+//! On some modules the backlight is on when the LIGHT pin is low. In that
+//! case set `BACKLIGHT_ACTIVE_LOW` to `true`.
+//!
+//! Run with:
+//! `cargo embed --example pcd8544-veml6030-tmp102-backlight-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{i2c_speed, log_info, panic_display as _};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::{blocking::spi, digital::v2::OutputPin, spi::MODE_0, Pwm};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    delay::Delay,
+    pac,
+    prelude::*,
+    pwm::Channel,
+    spi::Spi,
+    timer::{Tim2NoRemap, Timer},
+};
+use tmp1x2::{SlaveAddr as Tmp1x2Addr, Tmp1x2};
+use veml6030::{SlaveAddr as Veml6030Addr, Veml6030};
+
+const WIDTH: usize = 84;
+const HEIGHT: usize = 48;
+
+// Operating voltage (contrast): 0x00-0x7F
+const CONTRAST: u8 = 0x3F;
+// Bias system: 0-7. 4 (1:48) is recommended for this display size.
+const BIAS: u8 = 4;
+// Temperature coefficient: 0-3
+const TEMPERATURE_COEFFICIENT: u8 = 2;
+
+const BACKLIGHT_OFF_LUX: f32 = 200.0;
+const BACKLIGHT_ACTIVE_LOW: bool = false;
+
+/// Minimal PCD8544 driver with frame buffer
+struct Pcd8544<SPI, DC, CE> {
+    spi: SPI,
+    dc: DC,
+    ce: CE,
+    buffer: [u8; WIDTH * HEIGHT / 8],
+}
+
+impl<SPI, DC, CE> Pcd8544<SPI, DC, CE>
+where
+    SPI: spi::Write<u8>,
+    DC: OutputPin,
+    CE: OutputPin,
+{
+    fn new(spi: SPI, dc: DC, mut ce: CE) -> Self {
+        ce.set_high().ok();
+        Pcd8544 {
+            spi,
+            dc,
+            ce,
+            buffer: [0; WIDTH * HEIGHT / 8],
+        }
+    }
+
+    fn init(&mut self, contrast: u8, bias: u8, temperature_coefficient: u8) -> Result<(), ()> {
+        self.commands(&[
+            // Extended instruction set
+            0x21,
+            0x80 | (contrast & 0x7F),
+            0x04 | (temperature_coefficient & 0x03),
+            0x10 | (bias & 0x07),
+            // Basic instruction set, horizontal addressing
+            0x20,
+            // Normal display mode
+            0x0C,
+        ])
+    }
+
+    fn commands(&mut self, commands: &[u8]) -> Result<(), ()> {
+        self.dc.set_low().map_err(|_| ())?;
+        self.ce.set_low().map_err(|_| ())?;
+        let result = self.spi.write(commands).map_err(|_| ());
+        self.ce.set_high().map_err(|_| ())?;
+        result
+    }
+
+    fn clear_buffer(&mut self) {
+        self.buffer = [0; WIDTH * HEIGHT / 8];
+    }
+
+    /// Send the frame buffer to the display.
+    fn flush(&mut self) -> Result<(), ()> {
+        // Go to position 0, 0
+        self.commands(&[0x80, 0x40])?;
+        self.dc.set_high().map_err(|_| ())?;
+        self.ce.set_low().map_err(|_| ())?;
+        let result = self.spi.write(&self.buffer).map_err(|_| ());
+        self.ce.set_high().map_err(|_| ())?;
+        result
+    }
+}
+
+impl<SPI, DC, CE> DrawTarget<BinaryColor> for Pcd8544<SPI, DC, CE> {
+    type Error = core::convert::Infallible;
+
+    fn draw_pixel(&mut self, pixel: Pixel<BinaryColor>) -> Result<(), Self::Error> {
+        let Pixel(point, color) = pixel;
+        if point.x < 0 || point.y < 0 || point.x >= WIDTH as i32 || point.y >= HEIGHT as i32 {
+            return Ok(());
+        }
+        // Each byte contains a column of 8 pixels.
+        let (x, y) = (point.x as usize, point.y as usize);
+        let index = (y / 8) * WIDTH + x;
+        let mask = 1 << (y % 8);
+        match color {
+            BinaryColor::On => self.buffer[index] |= mask,
+            BinaryColor::Off => self.buffer[index] &= !mask,
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Size {
+        Size::new(WIDTH as u32, HEIGHT as u32)
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("PCD8544 example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    // SPI1
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+
+    let ce = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+    let dc = gpiob.pb1.into_push_pull_output(&mut gpiob.crl);
+    let mut rst = gpiob.pb10.into_push_pull_output(&mut gpiob.crh);
+
+    // The PCD8544 supports up to 4MHz.
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        MODE_0,
+        4_u32.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    let backlight_pin = gpioa.pa0.into_alternate_push_pull(&mut gpioa.crl);
+    let mut backlight = Timer::tim2(dp.TIM2, &clocks, &mut rcc.apb1).pwm::<Tim2NoRemap, _, _, _>(
+        backlight_pin,
+        &mut afio.mapr,
+        1.khz(),
+    );
+    backlight.enable(Channel::C1);
+    let max_duty = backlight.get_max_duty();
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // VEML6030 and TMP102 sensors
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x10, 0x48]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    rst.set_low().unwrap();
+    delay.delay_ms(10_u16);
+    rst.set_high().unwrap();
+    let mut lcd = Pcd8544::new(spi, dc, ce);
+    lcd.init(CONTRAST, BIAS, TEMPERATURE_COEFFICIENT).unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let mut als = Veml6030::new(manager.acquire(), Veml6030Addr::default());
+    als.enable().unwrap();
+    let mut tmp102 = Tmp1x2::new(manager.acquire(), Tmp1x2Addr::default());
+
+    let mut lines: [heapless::String<16>; 3] = [
+        heapless::String::new(),
+        heapless::String::new(),
+        heapless::String::new(),
+    ];
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+        delay.delay_ms(950_u16);
+
+        let lux = als.read_lux().unwrap_or(-1.0);
+        let temperature = tmp102.read_temperature().unwrap_or(-273.0);
+
+        // Full brightness in the dark, off in bright light
+        let brightness = 1.0 - (lux / BACKLIGHT_OFF_LUX).max(0.0).min(1.0);
+        let duty = (f32::from(max_duty) * brightness) as u16;
+        let duty = if BACKLIGHT_ACTIVE_LOW {
+            max_duty - duty
+        } else {
+            duty
+        };
+        backlight.set_duty(Channel::C1, duty);
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        write!(lines[0], "Lux: {:.1}", lux).unwrap();
+        write!(lines[1], "Temp: {:.1}C", temperature).unwrap();
+        write!(lines[2], "Light: {:3.0}%", brightness * 100.0).unwrap();
+
+        lcd.clear_buffer();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut lcd)
+                .unwrap();
+        }
+        lcd.flush().unwrap();
+    }
+}
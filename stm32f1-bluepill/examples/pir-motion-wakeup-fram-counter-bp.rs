@@ -0,0 +1,326 @@
+//! Wake up from STOP mode when a PIR motion sensor detects someone, show
+//! the temperature from a TMP102 sensor and the number of detected motions
+//! on an SSD1306 OLED display for `AWAKE_S` seconds and go back to sleep.
+//! The motion counter is kept in an I2C FRAM (e.g. MB85RC256V) so that it
+//! survives resets and power losses.
+//!
+//! In STOP mode all clocks are stopped and the voltage regulator runs in
+//! low-power mode, but the RAM and the registers keep their contents. The
+//! PIR output on PA0 raises the EXTI0 interrupt, which wakes up the
+//! microcontroller. The HAL does not support the low-power modes so the
+//! PWR registers are configured directly.
+//!
+//! After STOP mode the microcontroller always runs from the 8MHz internal
+//! oscillator, so this example uses it all the time. Otherwise the clocks
+//! would have to be configured again after every wake-up.
+//!
+//! Every new motion while awake counts as well and starts the `AWAKE_S`
+//! seconds again. The display is switched off while sleeping.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> PIR <-> TMP102 <-> FRAM <-> Display
+//! GND  <-> GND <-> GND    <-> GND  <-> GND
+//! 5V   <-> VCC
+//! 3.3V         <-> VCC    <-> VCC  <-> VDD
+//! PA0  <-> OUT
+//! PB8          <-> SCL    <-> SCL  <-> SCL
+//! PB9          <-> SDA    <-> SDA  <-> SDA
+//! ```
+//!
+//! PIR modules like the HC-SR501 need 5V but have a 3.3V output. Set the
+//! jumper to repeat trigger mode so that the output stays high while the
+//! motion goes on.
+//!
+//! The debugger keeps working in STOP mode because the DBG_STOP bit is set,
+//! but that also keeps the clocks running. Measure the current without
+//! the debugger connected.
+//!
+//! Run with:
+//! `cargo embed --example pir-motion-wakeup-fram-counter-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    crc::{Crc32, SoftwareCrc32},
+    exti::setup_exti_pin,
+    i2c_speed, log_error, log_info, log_warn,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::{
+    blocking::i2c,
+    digital::v2::{InputPin, OutputPin},
+};
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::PA0,
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Input, OpenDrain, Output, PullDown, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+/// Frequency of the internal oscillator, which runs the core after STOP mode
+const SYSCLK_MHZ: u32 = 8;
+/// Time the display stays on after the last motion
+const AWAKE_S: u32 = 30;
+const FRAM_ADDRESS: u8 = 0x50;
+const COUNTER_OFFSET: u16 = 0;
+
+/// Minimal driver for an I2C FRAM with 16-bit memory addresses
+struct Fram<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C, E> Fram<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    fn read(&mut self, offset: u16, data: &mut [u8]) -> Result<(), E> {
+        self.i2c
+            .write_read(FRAM_ADDRESS, &offset.to_be_bytes(), data)
+    }
+
+    /// FRAM writes complete immediately. There are no pages and no delay.
+    fn write(&mut self, offset: u16, data: &[u8; 8]) -> Result<(), E> {
+        let [high, low] = offset.to_be_bytes();
+        let mut buffer = [0; 10];
+        buffer[0] = high;
+        buffer[1] = low;
+        buffer[2..].copy_from_slice(data);
+        self.i2c.write(FRAM_ADDRESS, &buffer)
+    }
+}
+
+/// Read the stored number of motions. Returns 0 if nothing valid is stored.
+fn load_counter<I2C, E>(fram: &mut Fram<I2C>, crc: &mut SoftwareCrc32) -> u32
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    let mut data = [0; 8];
+    if fram.read(COUNTER_OFFSET, &mut data).is_err() {
+        return 0;
+    }
+    let stored_crc = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    if crc.checksum(&data[..4]) == stored_crc {
+        u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+    } else {
+        log_warn!("No valid counter stored");
+        0
+    }
+}
+
+fn store_counter<I2C, E>(
+    fram: &mut Fram<I2C>,
+    crc: &mut SoftwareCrc32,
+    motions: u32,
+) -> Result<(), E>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    let mut data = [0; 8];
+    data[..4].copy_from_slice(&motions.to_le_bytes());
+    let checksum = crc.checksum(&data[..4]);
+    data[4..].copy_from_slice(&checksum.to_le_bytes());
+    fram.write(COUNTER_OFFSET, &data)
+}
+
+/// PIR sensor input
+pub struct Pir {
+    pin: PA0<Input<PullDown>>,
+    /// Motions detected since the idle task last looked
+    motions: u32,
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        pir: Pir,
+        // Taken by the idle task, which creates the drivers.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("PIR motion wake-up example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the PWR clock before handing the RCC over to the HAL.
+        device.RCC.apb1enr.modify(|_, w| w.pwren().set_bit());
+
+        // Enter STOP mode (not STANDBY) with the regulator in low-power mode
+        // on WFI.
+        device
+            .PWR
+            .cr
+            .modify(|_, w| w.pdds().clear_bit().lpds().set_bit());
+        core.SCB.set_sleepdeep();
+        device.DBGMCU.cr.modify(|_, w| w.dbg_stop().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc.cfgr.sysclk(SYSCLK_MHZ.mhz()).freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let mut pir = gpioa.pa0.into_pull_down_input(&mut gpioa.crl);
+        setup_exti_pin(&mut pir, &mut afio, &device.EXTI, Edge::RISING);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // TMP102 sensor, FRAM and display
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x48, FRAM_ADDRESS, 0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            pir: Pir {
+                pin: pir,
+                motions: 0,
+            },
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = EXTI0, priority = 2, resources = [pir])]
+    fn motion(cx: motion::Context) {
+        let pir = cx.resources.pir;
+        pir.pin.clear_interrupt_pending_bit();
+        pir.motions += 1;
+    }
+
+    #[idle(resources = [pir, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let i2c = cx.resources.i2c.take().unwrap();
+        let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+        let interface = I2CDIBuilder::new().init(manager.acquire());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut sensor = Tmp1x2::new(manager.acquire(), SlaveAddr::default());
+        let mut fram = Fram {
+            i2c: manager.acquire(),
+        };
+        let mut crc = SoftwareCrc32::new();
+        let mut total_motions = load_counter(&mut fram, &mut crc);
+        log_info!("Stored counter: {} motions", total_motions);
+
+        let mut lines: [String<32>; 3] = Default::default();
+        let mut last_motion = DWT::get_cycle_count();
+        let mut led_on = false;
+        loop {
+            let (motions, pir_high) = cx.resources.pir.lock(|pir| {
+                let motions = pir.motions;
+                pir.motions = 0;
+                (motions, pir.pin.is_high().unwrap())
+            });
+            if motions > 0 {
+                total_motions = total_motions.wrapping_add(motions);
+                if let Err(e) = store_counter(&mut fram, &mut crc, total_motions) {
+                    log_error!("FRAM error: {:?}", e);
+                }
+            }
+            // The cycle counter stops in STOP mode so the time only counts
+            // while awake.
+            if motions > 0 || pir_high {
+                last_motion = DWT::get_cycle_count();
+            }
+            let awake_s = DWT::get_cycle_count().wrapping_sub(last_motion) / 1_000_000 / SYSCLK_MHZ;
+
+            if awake_s >= AWAKE_S {
+                log_info!("Going to sleep");
+                disp.display_on(false).unwrap();
+                cx.resources.led.set_high().unwrap();
+                // Interrupts are disabled so that a motion between checking
+                // and WFI is not missed: a pending interrupt still wakes up
+                // the microcontroller, and it is handled after `free`.
+                let pir = &mut cx.resources.pir;
+                cortex_m::interrupt::free(|_| {
+                    if pir.lock(|pir| pir.motions) == 0 {
+                        cortex_m::asm::wfi();
+                    }
+                });
+                log_info!("Woken up");
+                disp.display_on(true).unwrap();
+                last_motion = DWT::get_cycle_count();
+                continue;
+            }
+
+            let temperature = sensor.read_temperature().unwrap_or(-273.0);
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            write!(lines[0], "Temperature: {:.1}C", temperature).unwrap();
+            write!(lines[1], "Motions: {}", total_motions).unwrap();
+            write!(lines[2], "Sleep in {}s", AWAKE_S - awake_s).unwrap();
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 16))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            led_on = !led_on;
+            if led_on {
+                cx.resources.led.set_low().unwrap();
+            } else {
+                cx.resources.led.set_high().unwrap();
+            }
+            cortex_m::asm::delay(500_000 * SYSCLK_MHZ);
+        }
+    }
+};
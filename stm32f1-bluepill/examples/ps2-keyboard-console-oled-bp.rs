@@ -0,0 +1,412 @@
+//! Edit parameters with a PS/2 keyboard and an SSD1306 OLED display as a
+//! terminal, or over a serial console: both enter the same commands.
+//!
+//! The keyboard clock triggers an interrupt on every falling edge, which
+//! reads the data line and feeds it into the `Ps2Decoder` of this crate.
+//! The scan codes are translated into ASCII with the `Keyboard`. The line
+//! typed is shown at the bottom of the display. Backspace deletes the last
+//! character, escape the whole line and enter runs it. The commands and
+//! their answers scroll up above and are sent to the serial console too.
+//!
+//! The serial console at 115200 baud is read with the `uart` module like in
+//! the pwm-pulse-generator-encoder-console-bp example. Send one command per
+//! line:
+//! - `LIST`: print all parameters.
+//! - `GET blink`: print a parameter.
+//! - `SET blink 200`: change a parameter.
+//!
+//! Every command is answered with a line starting with `OK` or `ERR`. The
+//! `blink` parameter is the blink period of LED 0 in milliseconds, the
+//! others are examples.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and USART1.
+//!
+//! ```
+//! BP   <-> PS/2 keyboard <-> Display <-> USB-serial adapter
+//! GND  <-> GND           <-> GND     <-> GND
+//! 5V   <-> VCC
+//! 3.3V                   <-> VDD
+//! PB12 <-> CLK
+//! PB13 <-> DATA
+//! PB8                    <-> SCL
+//! PB9                    <-> SDA
+//! PA9                                <-> RX
+//! PA10                               <-> TX
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example ps2-keyboard-console-oled-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    exti::setup_exti_pin,
+    i2c_speed, log_info, log_warn,
+    panic_display::{self, Bus},
+    ps2::{self, Keyboard, Ps2Decoder, BACKSPACE, ENTER, ESCAPE},
+    uart::{LineBuffer, RxBuffer, RxReader, TxBuffer, TxWriter, UartRx, UartTx},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::{
+    spsc::{Consumer, Producer, Queue},
+    String,
+};
+use rtic::app;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpiob::{PB12, PB13, PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Floating, Input, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    serial::{Config, Rx, Serial, Tx},
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const BAUD_RATE: u32 = 115_200;
+const RX_BUFFER: usize = 64;
+const TX_BUFFER: usize = 256;
+const LINE_LEN: usize = 32;
+/// Characters per line of the display with the 6x8 font
+const COLUMNS: usize = 21;
+/// Lines of the display with the 6x8 font
+const ROWS: usize = 8;
+
+/// Parameter which can be edited
+struct Parameter {
+    name: &'static str,
+    value: i32,
+    min: i32,
+    max: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CommandError {
+    UnknownCommand,
+    UnknownParameter,
+    MissingArgument,
+    InvalidNumber,
+    OutOfRange,
+}
+
+/// Run a command line and write the answer.
+fn run(
+    line: &str,
+    parameters: &mut [Parameter],
+    answer: &mut String<LINE_LEN>,
+) -> Result<(), CommandError> {
+    let mut words = line.split_whitespace();
+    let command = words.next().ok_or(CommandError::UnknownCommand)?;
+    if command.eq_ignore_ascii_case("LIST") {
+        write!(answer, "OK").unwrap();
+        for parameter in parameters.iter() {
+            write!(answer, " {}={}", parameter.name, parameter.value).ok();
+        }
+        return Ok(());
+    }
+    let name = words.next().ok_or(CommandError::MissingArgument)?;
+    let parameter = parameters
+        .iter_mut()
+        .find(|parameter| parameter.name.eq_ignore_ascii_case(name))
+        .ok_or(CommandError::UnknownParameter)?;
+    if command.eq_ignore_ascii_case("SET") {
+        let argument = words.next().ok_or(CommandError::MissingArgument)?;
+        match argument.parse() {
+            Ok(value) if (parameter.min..=parameter.max).contains(&value) => {
+                parameter.value = value
+            }
+            Ok(_) => return Err(CommandError::OutOfRange),
+            Err(_) => return Err(CommandError::InvalidNumber),
+        }
+    } else if !command.eq_ignore_ascii_case("GET") {
+        return Err(CommandError::UnknownCommand);
+    }
+    write!(answer, "OK {}={}", parameter.name, parameter.value).unwrap();
+    Ok(())
+}
+
+/// Scrolling text lines above the input line
+struct Terminal {
+    lines: [String<COLUMNS>; ROWS - 1],
+}
+
+impl Terminal {
+    /// Add text at the bottom, wrapped at the width of the display.
+    fn print(&mut self, text: &str) {
+        for chunk in text.as_bytes().chunks(COLUMNS) {
+            self.lines.rotate_left(1);
+            let last = &mut self.lines[ROWS - 2];
+            last.clear();
+            for byte in chunk {
+                last.push(char::from(*byte)).ok();
+            }
+        }
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        ps2_clock: PB12<Input<Floating>>,
+        ps2_data: PB13<Input<Floating>>,
+        decoder: Ps2Decoder,
+        last_edge: u32,
+        producer: Producer<'static, u8, 16>,
+        consumer: Consumer<'static, u8, 16>,
+        uart_rx: UartRx<Rx<pac::USART1>, RX_BUFFER>,
+        uart_tx: UartTx<Tx<pac::USART1>, TX_BUFFER>,
+        reader: RxReader<RX_BUFFER>,
+        writer: TxWriter<TX_BUFFER>,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        static mut QUEUE: Queue<u8, 16> = Queue::new();
+
+        rtt_init_print!();
+        log_info!("PS/2 keyboard console example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // The keyboard has pull-up resistors on its lines.
+        let mut ps2_clock = gpiob.pb12.into_floating_input(&mut gpiob.crh);
+        setup_exti_pin(&mut ps2_clock, &mut afio, &device.EXTI, Edge::FALLING);
+        let ps2_data = gpiob.pb13.into_floating_input(&mut gpiob.crh);
+
+        let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+        let rx = gpioa.pa10;
+        let serial = Serial::usart1(
+            device.USART1,
+            (tx, rx),
+            &mut afio.mapr,
+            Config::default().baudrate(BAUD_RATE.bps()),
+            clocks,
+            &mut rcc.apb2,
+        );
+        let (tx, mut rx) = serial.split();
+        rx.listen();
+        let (uart_rx, reader) = singleton!(: RxBuffer<RX_BUFFER> = RxBuffer::new())
+            .unwrap()
+            .split(rx);
+        let (uart_tx, writer) = singleton!(: TxBuffer<TX_BUFFER> = TxBuffer::new())
+            .unwrap()
+            .split(tx, || rtic::pend(pac::Interrupt::USART1));
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        let (producer, consumer) = QUEUE.split();
+        init::LateResources {
+            ps2_clock,
+            ps2_data,
+            decoder: Ps2Decoder::new(),
+            last_edge: DWT::get_cycle_count(),
+            producer,
+            consumer,
+            uart_rx,
+            uart_tx,
+            reader,
+            writer,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = EXTI15_10, priority = 2, resources = [ps2_clock, ps2_data, decoder, last_edge, producer])]
+    fn ps2_edge(cx: ps2_edge::Context) {
+        let now = DWT::get_cycle_count();
+        cx.resources.ps2_clock.clear_interrupt_pending_bit();
+        let data = cx.resources.ps2_data.is_high().unwrap();
+        let decoder = cx.resources.decoder;
+        // A pause in the middle of a frame: start again with the next one.
+        if now.wrapping_sub(*cx.resources.last_edge) > ps2::BIT_TIMEOUT_US * SYSCLK_MHZ {
+            decoder.reset();
+        }
+        *cx.resources.last_edge = now;
+        match decoder.bit(data) {
+            Some(Ok(code)) => {
+                cx.resources.producer.enqueue(code).ok();
+            }
+            Some(Err(e)) => log_warn!("PS/2 error: {:?}", e),
+            None => (),
+        }
+    }
+
+    /// Move the received bytes into the buffer and send the waiting ones.
+    #[task(binds = USART1, priority = 2, resources = [uart_rx, uart_tx])]
+    fn usart1(cx: usart1::Context) {
+        cx.resources.uart_rx.on_interrupt();
+        let uart_tx = cx.resources.uart_tx;
+        if uart_tx.on_interrupt() {
+            uart_tx.tx().listen();
+        } else {
+            uart_tx.tx().unlisten();
+        }
+    }
+
+    #[idle(resources = [consumer, reader, writer, i2c, led])]
+    fn idle(cx: idle::Context) -> ! {
+        let consumer = cx.resources.consumer;
+        let reader = cx.resources.reader;
+        let tx = cx.resources.writer;
+        let mut line_buffer: LineBuffer<LINE_LEN> = LineBuffer::new();
+        let i2c = cx.resources.i2c.take().unwrap();
+        let interface = I2CDIBuilder::new().init(i2c);
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut parameters = [
+            Parameter {
+                name: "blink",
+                value: 1000,
+                min: 100,
+                max: 5000,
+            },
+            Parameter {
+                name: "setpoint",
+                value: 21,
+                min: 5,
+                max: 30,
+            },
+            Parameter {
+                name: "hysteresis",
+                value: 1,
+                min: 0,
+                max: 5,
+            },
+        ];
+        let mut keyboard = Keyboard::new();
+        let mut terminal = Terminal {
+            lines: Default::default(),
+        };
+        terminal.print("Type a command:");
+        writeln!(tx, "OK ready").unwrap();
+        let mut input: String<LINE_LEN> = String::new();
+        let mut redraw = true;
+        let mut last_blink = DWT::get_cycle_count();
+        let mut led_on = false;
+        loop {
+            // Lines typed on the keyboard and received over the console
+            let mut typed = None;
+            while let Some(code) = consumer.dequeue() {
+                match keyboard.scan_code(code) {
+                    Some(ENTER) => {
+                        typed = Some(core::mem::take(&mut input));
+                        break;
+                    }
+                    Some(BACKSPACE) => {
+                        input.pop();
+                    }
+                    Some(ESCAPE) => input.clear(),
+                    Some(character) => {
+                        input.push(char::from(character)).ok();
+                    }
+                    None => continue,
+                }
+                redraw = true;
+            }
+            let line = typed.or_else(|| reader.lines(&mut line_buffer).next());
+            if let Some(line) = line {
+                log_info!("Command: {}", line);
+                let mut answer: String<LINE_LEN> = String::new();
+                if let Err(e) = run(&line, &mut parameters, &mut answer) {
+                    answer.clear();
+                    write!(answer, "ERR {:?}", e).ok();
+                }
+                writeln!(tx, "{}", answer).unwrap();
+                terminal.print(&line);
+                terminal.print(&answer);
+                redraw = true;
+            }
+
+            let blink_cycles = parameters[0].value as u32 / 2 * 1000 * SYSCLK_MHZ;
+            if DWT::get_cycle_count().wrapping_sub(last_blink) >= blink_cycles {
+                last_blink = DWT::get_cycle_count();
+                led_on = !led_on;
+                if led_on {
+                    cx.resources.led.set_low().unwrap();
+                } else {
+                    cx.resources.led.set_high().unwrap();
+                }
+            }
+
+            if !redraw {
+                continue;
+            }
+            redraw = false;
+            disp.clear();
+            for (i, line) in terminal.lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 8))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            // Show the end of the input line with a cursor.
+            let mut prompt: String<COLUMNS> = String::new();
+            let start = input.len().saturating_sub(COLUMNS - 2);
+            write!(prompt, ">{}_", &input[start..]).ok();
+            Text::new(&prompt, Point::new(0, (ROWS as i32 - 1) * 8))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+            disp.flush().unwrap();
+        }
+    }
+};
@@ -0,0 +1,530 @@
+//! Turn the Bluepill into a small bench pulse generator: two PWM outputs
+//! with a common frequency and separate duty cycles, adjustable with a
+//! rotary encoder or over a serial console. The settings are shown on an
+//! SSD1306 OLED display. For sine and triangle waves have a look at the
+//! ad9833-midi-player-bp example.
+//!
+//! The outputs are channels 1 and 2 of TIM2, clocked with 72MHz. For every
+//! frequency the prescaler is chosen as small as possible so that the duty
+//! cycle has the finest resolution. Up to 72kHz the duty cycle can be set in
+//! steps of 0.1%, above that the resolution is one timer tick, at the
+//! maximum of 1MHz only 1/72 of the period. The timer cannot generate every
+//! frequency exactly, so the display and the console show the frequency
+//! that is actually generated. The HAL does not support changing the PWM
+//! frequency so the timer registers are written directly.
+//!
+//! The rotary encoder is decoded by TIM4 in encoder mode, so no steps are
+//! lost. Press the encoder button to select the frequency or one of the duty
+//! cycles and turn it to change the value. The frequency changes by 1% per
+//! step, the duty cycles by 1%.
+//!
+//! Connect with a serial terminal at 115200 baud and send one command per
+//! line:
+//! - `FREQ 1000`: set the frequency in Hz (1 - 1000000).
+//! - `DUTY 1 25.5`: set the duty cycle of an output in percent.
+//! - `ON 2`, `OFF 2`: switch an output on or off. Off outputs are low.
+//! - `STATUS`: print the settings.
+//!
+//! Every command is answered with a line starting with `OK` or `ERR`.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and USART1.
+//!
+//! ```
+//! BP   <-> Encoder <-> Display <-> USB-serial adapter
+//! GND  <-> GND     <-> GND     <-> GND
+//! 3.3V <-> +       <-> VDD
+//! PB6  <-> CLK
+//! PB7  <-> DT
+//! PB5  <-> SW
+//! PB8              <-> SCL
+//! PB9              <-> SDA
+//! PA9                          <-> RX
+//! PA10                         <-> TX
+//! PA0: output 1
+//! PA1: output 2
+//! ```
+//!
+//! The outputs are 3.3V push-pull. Do not load them with less than 1K.
+//!
+//! Run with:
+//! `cargo embed --example pwm-pulse-generator-encoder-console-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+    uart::{LineBuffer, RxBuffer, RxReader, TxBuffer, TxWriter, UartRx, UartTx},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::String;
+use rtic::app;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpiob::{PB5, PB8, PB9},
+        gpioc::PC13,
+        Alternate, Input, OpenDrain, Output, PullUp, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    serial::{Config, Rx, Serial, Tx},
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const TIMER_HZ: u32 = SYSCLK_MHZ * 1_000_000;
+const BAUD_RATE: u32 = 115_200;
+const RX_BUFFER: usize = 64;
+const TX_BUFFER: usize = 256;
+const LINE_LEN: usize = 32;
+const UPDATE_MS: u32 = 10;
+const MIN_HZ: u32 = 1;
+const MAX_HZ: u32 = 1_000_000;
+/// Encoder counts per detent (TIM4 counts every edge of both signals)
+const COUNTS_PER_STEP: i16 = 4;
+
+/// Settings of the outputs
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Generator {
+    frequency_hz: u32,
+    /// Duty cycles of the outputs in 0.1%
+    duty_permille: [u32; 2],
+    enabled: [bool; 2],
+}
+
+impl Generator {
+    /// Configure the timer and return the frequency actually generated.
+    fn apply(&self, timer: &pac::TIM2) -> f32 {
+        // Smallest prescaler for which the period fits into 16 bits
+        let ticks = TIMER_HZ / self.frequency_hz;
+        let prescaler = (ticks - 1) / 0x1_0000 + 1;
+        let period = (TIMER_HZ / prescaler / self.frequency_hz).max(2);
+        timer.psc.write(|w| w.psc().bits((prescaler - 1) as u16));
+        timer.arr.write(|w| w.arr().bits((period - 1) as u16));
+        let compare = |i: usize| {
+            if self.enabled[i] {
+                (period * self.duty_permille[i] / 1000) as u16
+            } else {
+                0
+            }
+        };
+        timer.ccr1.write(|w| w.ccr().bits(compare(0)));
+        timer.ccr2.write(|w| w.ccr().bits(compare(1)));
+        TIMER_HZ as f32 / (prescaler * period) as f32
+    }
+}
+
+/// What the encoder changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Selection {
+    Frequency,
+    Duty(usize),
+}
+
+impl Selection {
+    fn next(self) -> Self {
+        match self {
+            Selection::Frequency => Selection::Duty(0),
+            Selection::Duty(0) => Selection::Duty(1),
+            Selection::Duty(_) => Selection::Frequency,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Frequency(u32),
+    /// Output (0 or 1) and duty cycle in 0.1%
+    Duty(usize, u32),
+    Enable(usize, bool),
+    Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CommandError {
+    UnknownCommand,
+    MissingArgument,
+    InvalidOutput,
+    InvalidNumber,
+    OutOfRange,
+}
+
+fn parse_output(text: Option<&str>) -> Result<usize, CommandError> {
+    match text.ok_or(CommandError::MissingArgument)?.parse::<usize>() {
+        Ok(output) if (1..=2).contains(&output) => Ok(output - 1),
+        _ => Err(CommandError::InvalidOutput),
+    }
+}
+
+fn parse(line: &str) -> Result<Command, CommandError> {
+    let mut words = line.split_whitespace();
+    let command = words.next().ok_or(CommandError::UnknownCommand)?;
+    if command.eq_ignore_ascii_case("STATUS") {
+        return Ok(Command::Status);
+    }
+    if command.eq_ignore_ascii_case("FREQ") {
+        let argument = words.next().ok_or(CommandError::MissingArgument)?;
+        return match argument.parse() {
+            Ok(hz) if (MIN_HZ..=MAX_HZ).contains(&hz) => Ok(Command::Frequency(hz)),
+            Ok(_) => Err(CommandError::OutOfRange),
+            Err(_) => Err(CommandError::InvalidNumber),
+        };
+    }
+    if command.eq_ignore_ascii_case("DUTY") {
+        let output = parse_output(words.next())?;
+        let argument = words.next().ok_or(CommandError::MissingArgument)?;
+        return match argument.parse::<f32>() {
+            Ok(percent) if (0.0..=100.0).contains(&percent) => {
+                Ok(Command::Duty(output, (percent * 10.0 + 0.5) as u32))
+            }
+            Ok(_) => Err(CommandError::OutOfRange),
+            Err(_) => Err(CommandError::InvalidNumber),
+        };
+    }
+    if command.eq_ignore_ascii_case("ON") {
+        return Ok(Command::Enable(parse_output(words.next())?, true));
+    }
+    if command.eq_ignore_ascii_case("OFF") {
+        return Ok(Command::Enable(parse_output(words.next())?, false));
+    }
+    Err(CommandError::UnknownCommand)
+}
+
+/// Change a frequency by `steps` of 1%, at least 1Hz per step.
+fn step_frequency(hz: u32, steps: i32) -> u32 {
+    let mut hz = hz;
+    for _ in 0..steps.abs() {
+        let change = (hz / 100).max(1);
+        hz = if steps > 0 {
+            hz + change
+        } else {
+            hz.saturating_sub(change)
+        };
+    }
+    hz.clamp(MIN_HZ, MAX_HZ)
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        uart_rx: UartRx<Rx<pac::USART1>, RX_BUFFER>,
+        uart_tx: UartTx<Tx<pac::USART1>, TX_BUFFER>,
+        reader: RxReader<RX_BUFFER>,
+        writer: TxWriter<TX_BUFFER>,
+        pwm_timer: pac::TIM2,
+        encoder_timer: pac::TIM4,
+        encoder_button: PB5<Input<PullUp>>,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("PWM pulse generator example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the TIM2 and TIM4 clocks before handing the RCC over to the
+        // HAL.
+        device
+            .RCC
+            .apb1enr
+            .modify(|_, w| w.tim2en().set_bit().tim4en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // TIM2 channel 1 and 2 outputs in PWM mode 1. The new period and
+        // compare values take effect at the next update, so that changing
+        // them does not produce glitches.
+        let _output1 = gpioa.pa0.into_alternate_push_pull(&mut gpioa.crl);
+        let _output2 = gpioa.pa1.into_alternate_push_pull(&mut gpioa.crl);
+        let pwm_timer = device.TIM2;
+        pwm_timer.ccmr1_output().modify(|_, w| {
+            w.oc1m()
+                .pwm_mode1()
+                .oc1pe()
+                .set_bit()
+                .oc2m()
+                .pwm_mode1()
+                .oc2pe()
+                .set_bit()
+        });
+        pwm_timer
+            .ccer
+            .modify(|_, w| w.cc1e().set_bit().cc2e().set_bit());
+        pwm_timer
+            .cr1
+            .modify(|_, w| w.arpe().set_bit().cen().enabled());
+
+        // TIM4 channel 1 and 2 inputs in encoder mode 3 (count on both edges
+        // of both inputs) with an input filter against contact bounce
+        let _encoder_a = gpiob.pb6.into_pull_up_input(&mut gpiob.crl);
+        let _encoder_b = gpiob.pb7.into_pull_up_input(&mut gpiob.crl);
+        let encoder_timer = device.TIM4;
+        encoder_timer.ccmr1_input().modify(|_, w| {
+            w.cc1s()
+                .ti1()
+                .ic1f()
+                .fdts_div32_n8()
+                .cc2s()
+                .ti2()
+                .ic2f()
+                .fdts_div32_n8()
+        });
+        encoder_timer.smcr.modify(|_, w| w.sms().encoder_mode_3());
+        encoder_timer.arr.write(|w| w.arr().bits(0xFFFF));
+        encoder_timer.cr1.modify(|_, w| w.cen().enabled());
+        let encoder_button = gpiob.pb5.into_pull_up_input(&mut gpiob.crl);
+
+        let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+        let rx = gpioa.pa10;
+        let serial = Serial::usart1(
+            device.USART1,
+            (tx, rx),
+            &mut afio.mapr,
+            Config::default().baudrate(BAUD_RATE.bps()),
+            clocks,
+            &mut rcc.apb2,
+        );
+        let (tx, mut rx) = serial.split();
+        rx.listen();
+        let (uart_rx, reader) = singleton!(: RxBuffer<RX_BUFFER> = RxBuffer::new())
+            .unwrap()
+            .split(rx);
+        let (uart_tx, writer) = singleton!(: TxBuffer<TX_BUFFER> = TxBuffer::new())
+            .unwrap()
+            .split(tx, || rtic::pend(pac::Interrupt::USART1));
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            uart_rx,
+            uart_tx,
+            reader,
+            writer,
+            pwm_timer,
+            encoder_timer,
+            encoder_button,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    /// Move the received bytes into the buffer and send the waiting ones.
+    #[task(binds = USART1, priority = 2, resources = [uart_rx, uart_tx])]
+    fn usart1(cx: usart1::Context) {
+        cx.resources.uart_rx.on_interrupt();
+        let uart_tx = cx.resources.uart_tx;
+        if uart_tx.on_interrupt() {
+            uart_tx.tx().listen();
+        } else {
+            uart_tx.tx().unlisten();
+        }
+    }
+
+    #[idle(resources = [reader, writer, pwm_timer, encoder_timer, encoder_button, i2c, led])]
+    fn idle(cx: idle::Context) -> ! {
+        let reader = cx.resources.reader;
+        let tx = cx.resources.writer;
+        let mut line_buffer: LineBuffer<LINE_LEN> = LineBuffer::new();
+        let pwm_timer = cx.resources.pwm_timer;
+        let encoder_timer = cx.resources.encoder_timer;
+        let i2c = cx.resources.i2c.take().unwrap();
+        let interface = I2CDIBuilder::new().init(i2c);
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut generator = Generator {
+            frequency_hz: 1000,
+            duty_permille: [500, 250],
+            enabled: [true, true],
+        };
+        let mut actual_hz = generator.apply(pwm_timer);
+        let mut selection = Selection::Frequency;
+        let mut last_count = encoder_timer.cnt.read().cnt().bits();
+        // Counts which do not make up a full step yet
+        let mut partial = 0_i16;
+        let mut button_history = 0_u8;
+        writeln!(tx, "OK ready").unwrap();
+
+        let mut lines: [String<32>; 4] = Default::default();
+        let mut redraw = true;
+        let mut last_update = DWT::get_cycle_count();
+        let mut updates = 0_u32;
+        loop {
+            if DWT::get_cycle_count().wrapping_sub(last_update) < UPDATE_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_update = last_update.wrapping_add(UPDATE_MS * 1000 * SYSCLK_MHZ);
+            updates += 1;
+            let previous = generator;
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            if updates % 100 < 50 {
+                cx.resources.led.set_low().unwrap();
+            } else {
+                cx.resources.led.set_high().unwrap();
+            }
+
+            for line in reader.lines(&mut line_buffer) {
+                log_info!("Command: {}", line);
+                match parse(&line) {
+                    Ok(command) => {
+                        match command {
+                            Command::Frequency(hz) => generator.frequency_hz = hz,
+                            Command::Duty(output, permille) => {
+                                generator.duty_permille[output] = permille
+                            }
+                            Command::Enable(output, on) => generator.enabled[output] = on,
+                            Command::Status => (),
+                        }
+                        actual_hz = generator.apply(pwm_timer);
+                        write!(tx, "OK {:.2}Hz", actual_hz).unwrap();
+                        for (i, (duty, on)) in generator
+                            .duty_permille
+                            .iter()
+                            .zip(generator.enabled.iter())
+                            .enumerate()
+                        {
+                            let state = if *on { "" } else { " off" };
+                            write!(tx, " {}: {}.{}%{}", i + 1, duty / 10, duty % 10, state)
+                                .unwrap();
+                        }
+                        writeln!(tx).unwrap();
+                    }
+                    Err(e) => writeln!(tx, "ERR {:?}", e).unwrap(),
+                }
+            }
+
+            // Select the next setting once the button has been pressed for
+            // 8 samples.
+            let previous_history = button_history;
+            button_history =
+                (button_history << 1) | u8::from(cx.resources.encoder_button.is_low().unwrap());
+            if button_history == 0xFF && previous_history != 0xFF {
+                selection = selection.next();
+                redraw = true;
+            }
+
+            let count = encoder_timer.cnt.read().cnt().bits();
+            partial += count.wrapping_sub(last_count) as i16;
+            last_count = count;
+            let steps = partial / COUNTS_PER_STEP;
+            partial %= COUNTS_PER_STEP;
+            if steps != 0 {
+                match selection {
+                    Selection::Frequency => {
+                        generator.frequency_hz =
+                            step_frequency(generator.frequency_hz, i32::from(steps))
+                    }
+                    Selection::Duty(i) => {
+                        let duty = generator.duty_permille[i] as i32 + i32::from(steps) * 10;
+                        generator.duty_permille[i] = duty.clamp(0, 1000) as u32;
+                    }
+                }
+            }
+
+            if generator != previous {
+                actual_hz = generator.apply(pwm_timer);
+                redraw = true;
+            }
+            if !redraw {
+                continue;
+            }
+            redraw = false;
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            let marker = |s: Selection| if s == selection { '>' } else { ' ' };
+            write!(
+                lines[0],
+                "{}Set: {} Hz",
+                marker(Selection::Frequency),
+                generator.frequency_hz
+            )
+            .unwrap();
+            write!(lines[1], " Out: {:.2} Hz", actual_hz).unwrap();
+            for (i, (duty, on)) in generator
+                .duty_permille
+                .iter()
+                .zip(generator.enabled.iter())
+                .enumerate()
+            {
+                let state = if *on { "" } else { " off" };
+                write!(
+                    lines[2 + i],
+                    "{}Duty {}: {}.{}%{}",
+                    marker(Selection::Duty(i)),
+                    i + 1,
+                    duty / 10,
+                    duty % 10,
+                    state
+                )
+                .unwrap();
+            }
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 16))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+        }
+    }
+};
@@ -0,0 +1,203 @@
+//! Read the voltage, current, power, energy, frequency and power factor of
+//! the mains with a PZEM-004T v3 energy meter over Modbus RTU and print them
+//! on an SSD1306 OLED display.
+//!
+//! The meter is read with the `modbus` module of this crate: its 10 input
+//! registers are read at once every second.
+//!
+//! | Register | Value                | Resolution |
+//! |----------|----------------------|------------|
+//! | 0        | Voltage              | 0.1V       |
+//! | 1, 2     | Current (low, high)  | 0.001A     |
+//! | 3, 4     | Power (low, high)    | 0.1W       |
+//! | 5, 6     | Energy (low, high)   | 1Wh        |
+//! | 7        | Frequency            | 0.1Hz      |
+//! | 8        | Power factor         | 0.01       |
+//! | 9        | Power alarm          |            |
+//!
+//! DANGER: The PZEM-004T is connected to the mains. Do not touch anything
+//! while it is connected and let someone qualified do the mains wiring.
+//! The UART side is isolated.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using USART1 for
+//! the meter and I2C1 for the display.
+//!
+//! ```
+//! BP   <-> PZEM-004T <-> Display
+//! GND  <-> GND       <-> GND
+//! 5V   <-> 5V
+//! 3.3V               <-> VDD
+//! PA9  <-> RX
+//! PA10 <-> TX
+//! PB8                <-> SCL
+//! PB9                <-> SDA
+//! ```
+//!
+//! The UART of the PZEM-004T uses 5V levels. PA9 and PA10 are 5V tolerant.
+//!
+//! Run with:
+//! `cargo embed --example pzem-004t-energy-meter-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    i2c_speed, log_error, log_info,
+    modbus::Master,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    delay::Delay,
+    pac,
+    prelude::*,
+    serial::{Config, Serial},
+};
+
+/// Default slave address of the PZEM-004T
+const SLAVE_ADDRESS: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy)]
+struct Reading {
+    voltage: f32,
+    current: f32,
+    power: f32,
+    energy_wh: u32,
+    frequency: f32,
+    power_factor: f32,
+    alarm: bool,
+}
+
+impl Reading {
+    fn from_registers(r: &[u16; 10]) -> Self {
+        let long = |low: u16, high: u16| (u32::from(high) << 16) | u32::from(low);
+        Reading {
+            voltage: f32::from(r[0]) / 10.0,
+            current: long(r[1], r[2]) as f32 / 1000.0,
+            power: long(r[3], r[4]) as f32 / 10.0,
+            energy_wh: long(r[5], r[6]),
+            frequency: f32::from(r[7]) / 10.0,
+            power_factor: f32::from(r[8]) / 100.0,
+            alarm: r[9] != 0,
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("PZEM-004T energy meter example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+    let rx = gpioa.pa10;
+    let serial = Serial::usart1(
+        dp.USART1,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(9600.bps()),
+        clocks,
+        &mut rcc.apb2,
+    );
+    let (tx, rx) = serial.split();
+    // The meter answers within a few tens of milliseconds.
+    let mut meter = Master::new(tx, rx, clocks.sysclk().0 / 10);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut registers = [0; 10];
+    let mut lines: [String<32>; 5] = [
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+    ];
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        match meter.read_input_registers(SLAVE_ADDRESS, 0, &mut registers) {
+            Ok(()) => {
+                let r = Reading::from_registers(&registers);
+                log_info!("{:?}", r);
+                write!(lines[0], "{:.1}V {:.1}Hz", r.voltage, r.frequency).unwrap();
+                write!(lines[1], "{:.3}A PF {:.2}", r.current, r.power_factor).unwrap();
+                write!(lines[2], "{:.1}W", r.power).unwrap();
+                write!(lines[3], "{:.3}kWh", r.energy_wh as f32 / 1000.0).unwrap();
+                if r.alarm {
+                    write!(lines[4], "Power alarm!").unwrap();
+                }
+            }
+            Err(e) => {
+                log_error!("Error: {:?}", e);
+                write!(lines[0], "Error: {:?}", e).unwrap();
+            }
+        }
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 12))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        delay.delay_ms(950_u16);
+    }
+}
@@ -0,0 +1,448 @@
+//! Line-following robot with a QTR-8A reflectance sensor array read by an
+//! MCP3008 analog/digital converter and two DC motors driven by a DRV8833.
+//!
+//! Every `CONTROL_MS` the 8 sensors are read and scaled to 0-1000 with
+//! their calibration, 1000 being the darkest. The line position is the
+//! average of the sensor positions (0, 1000, ..., 7000) weighted with these
+//! values, so that it is 3500 when the line is under the middle of the
+//! array. A PD controller (the `PidController` from this crate without
+//! integral) steers the robot back over the line by slowing down one motor
+//! and speeding up the other.
+//!
+//! When the line is lost, the robot keeps turning towards the side where it
+//! was last seen. After `LINE_LOST_MS` without the line it stops.
+//!
+//! Calibration: each sensor sees a different range of values, so the
+//! minimum and maximum of every sensor are recorded while sweeping the array
+//! over the line by hand for `CALIBRATION_MS` after startup (the LED blinks
+//! fast). The calibration is stored in the flash memory together with a
+//! CRC and used on the next start. Hold the button while starting up to
+//! calibrate again.
+//!
+//! Press the button to start and stop the robot.
+//!
+//! The MCP3008 is used rather than an ADS1115 because it reads all 8
+//! sensors, while the ADS1115 only has 4 inputs.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1.
+//!
+//! ```
+//! BP   <-> MCP3008          <-> QTR-8A  <-> DRV8833 <-> Button
+//! GND  <-> AGND, DGND       <-> GND     <-> GND
+//! 3.3V <-> VDD, VREF        <-> VCC                 <-> +
+//! PA5  <-> CLK
+//! PA6  <-> DOUT
+//! PA7  <-> DIN
+//! PB12 <-> CS/SHDN
+//!          CH0-CH7          <-> OUT1-8
+//! PA0                                   <-> AIN1
+//! PA1                                   <-> AIN2
+//! PA2                                   <-> BIN1
+//! PA3                                   <-> BIN2
+//! PB13                                              <-> -
+//!                                           VM <-> Battery +
+//!                                           AOUT1/2 <-> Left motor
+//!                                           BOUT1/2 <-> Right motor
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example qtr-8a-line-follower-mcp3008-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use adc_mcp3008::{Channels8, Mcp3008};
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table, crc::Crc32, log_info, log_warn, monotonic, motor,
+    panic_display as _, pid::PidController, scheduler::Scheduler,
+};
+use embedded_hal::{
+    digital::v2::{InputPin, OutputPin},
+    spi::MODE_0,
+    Pwm,
+};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    crc::CrcExt,
+    flash::{FlashSize, SectorSize},
+    gpio::{
+        gpioa::{PA0, PA1, PA2, PA3},
+        Alternate, PushPull,
+    },
+    pac,
+    prelude::*,
+    pwm::{Channel, Pwm as TimerPwm, C1, C2, C3, C4},
+    spi::Spi,
+    timer::{Tim2NoRemap, Timer},
+};
+
+type MotorPwm = TimerPwm<
+    pac::TIM2,
+    Tim2NoRemap,
+    (C1, C2, C3, C4),
+    (
+        PA0<Alternate<PushPull>>,
+        PA1<Alternate<PushPull>>,
+        PA2<Alternate<PushPull>>,
+        PA3<Alternate<PushPull>>,
+    ),
+>;
+
+const SENSORS: usize = 8;
+/// Line position with the line under the middle of the array
+const CENTER: i32 = 3500;
+/// Calibrated values below this do not see the line.
+const LINE_THRESHOLD: u16 = 200;
+/// A sensor must see at least this difference between the line and the
+/// floor (10-bit ADC values) to be calibrated.
+const MIN_CONTRAST: u16 = 100;
+const CALIBRATION_MS: u32 = 5000;
+const LINE_LOST_MS: u32 = 500;
+const CONTROL_MS: u32 = 5;
+const BASE_SPEED: f32 = 0.4;
+const KP: f32 = 0.6;
+const KD: f32 = 0.02;
+const REVERSE_LEFT: bool = false;
+const REVERSE_RIGHT: bool = false;
+
+// Second-to-last 1K page of the flash memory. The last one is reserved for
+// the xmodem-firmware-update-bp example.
+const CALIBRATION_OFFSET: u32 = 0xF800;
+const PAGE_SIZE: usize = 1024;
+const CALIBRATION_MAGIC: u32 = 0x3852_5451; // "QTR8"
+const CALIBRATION_SIZE: usize = 4 + SENSORS * 4 + 4;
+
+// Tasks in order of priority
+const CONTROL: usize = 0;
+const BUTTON: usize = 1;
+const BLINK: usize = 2;
+
+fn channel(i: usize) -> Channels8 {
+    match i {
+        0 => Channels8::CH0,
+        1 => Channels8::CH1,
+        2 => Channels8::CH2,
+        3 => Channels8::CH3,
+        4 => Channels8::CH4,
+        5 => Channels8::CH5,
+        6 => Channels8::CH6,
+        _ => Channels8::CH7,
+    }
+}
+
+/// Range of raw values seen by every sensor
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Calibration {
+    min: [u16; SENSORS],
+    max: [u16; SENSORS],
+}
+
+impl Calibration {
+    fn new() -> Self {
+        Calibration {
+            min: [u16::MAX; SENSORS],
+            max: [0; SENSORS],
+        }
+    }
+
+    fn update(&mut self, raw: &[u16; SENSORS]) {
+        for ((min, max), value) in self.min.iter_mut().zip(self.max.iter_mut()).zip(raw.iter()) {
+            *min = (*min).min(*value);
+            *max = (*max).max(*value);
+        }
+    }
+
+    /// Check that every sensor saw enough contrast. The error is the index of
+    /// the first one which did not.
+    fn check(&self) -> Result<(), usize> {
+        match self
+            .min
+            .iter()
+            .zip(self.max.iter())
+            .position(|(min, max)| max.saturating_sub(*min) < MIN_CONTRAST)
+        {
+            Some(sensor) => Err(sensor),
+            None => Ok(()),
+        }
+    }
+
+    /// Scale the raw values to 0 (floor) - 1000 (line).
+    fn scale(&self, raw: &[u16; SENSORS]) -> [u16; SENSORS] {
+        let mut values = [0; SENSORS];
+        for (((value, raw), min), max) in values
+            .iter_mut()
+            .zip(raw.iter())
+            .zip(self.min.iter())
+            .zip(self.max.iter())
+        {
+            let range = u32::from(max - min);
+            let offset = u32::from(raw.clamp(min, max) - min);
+            *value = (offset * 1000 / range) as u16;
+        }
+        values
+    }
+
+    fn to_bytes<C: Crc32>(&self, crc: &mut C) -> [u8; CALIBRATION_SIZE] {
+        let mut bytes = [0; CALIBRATION_SIZE];
+        bytes[..4].copy_from_slice(&CALIBRATION_MAGIC.to_le_bytes());
+        for (i, (min, max)) in self.min.iter().zip(self.max.iter()).enumerate() {
+            bytes[4 + i * 4..6 + i * 4].copy_from_slice(&min.to_le_bytes());
+            bytes[6 + i * 4..8 + i * 4].copy_from_slice(&max.to_le_bytes());
+        }
+        let checksum = crc.checksum(&bytes[..CALIBRATION_SIZE - 4]);
+        bytes[CALIBRATION_SIZE - 4..].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes<C: Crc32>(bytes: &[u8], crc: &mut C) -> Option<Self> {
+        let word =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        if bytes.len() < CALIBRATION_SIZE
+            || word(0) != CALIBRATION_MAGIC
+            || word(CALIBRATION_SIZE - 4) != crc.checksum(&bytes[..CALIBRATION_SIZE - 4])
+        {
+            return None;
+        }
+        let mut calibration = Calibration::new();
+        for (i, (min, max)) in calibration
+            .min
+            .iter_mut()
+            .zip(calibration.max.iter_mut())
+            .enumerate()
+        {
+            *min = u16::from_le_bytes([bytes[4 + i * 4], bytes[5 + i * 4]]);
+            *max = u16::from_le_bytes([bytes[6 + i * 4], bytes[7 + i * 4]]);
+        }
+        calibration.check().ok().map(|_| calibration)
+    }
+}
+
+/// Weighted line position: 0 under the first sensor, 7000 under the last
+/// one. `None` if no sensor sees the line.
+fn line_position(values: &[u16; SENSORS]) -> Option<i32> {
+    let mut sum = 0;
+    let mut weighted_sum = 0;
+    for (i, value) in values.iter().enumerate() {
+        if *value >= LINE_THRESHOLD {
+            sum += i32::from(*value);
+            weighted_sum += i32::from(*value) * i as i32 * 1000;
+        }
+    }
+    if sum > 0 {
+        Some(weighted_sum / sum)
+    } else {
+        None
+    }
+}
+
+/// Set the speed of the motors: -1 (full reverse) to 1 (full forward)
+fn drive(motors: &mut MotorPwm, left: f32, right: f32) {
+    let left = if REVERSE_LEFT { -left } else { left };
+    let right = if REVERSE_RIGHT { -right } else { right };
+    motor::set_speed(motors, Channel::C1, Channel::C2, left);
+    motor::set_speed(motors, Channel::C3, Channel::C4, right);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Calibrating { until_ms: u32 },
+    Stopped,
+    Running,
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("QTR-8A line follower example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut crc = dp.CRC.new(&mut rcc.ahb);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let motor_pins = (
+        gpioa.pa0.into_alternate_push_pull(&mut gpioa.crl),
+        gpioa.pa1.into_alternate_push_pull(&mut gpioa.crl),
+        gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl),
+        gpioa.pa3.into_alternate_push_pull(&mut gpioa.crl),
+    );
+    let mut motors = Timer::tim2(dp.TIM2, &clocks, &mut rcc.apb1).pwm::<Tim2NoRemap, _, _, _>(
+        motor_pins,
+        &mut afio.mapr,
+        20.khz(),
+    );
+    drive(&mut motors, 0.0, 0.0);
+    for channel in [Channel::C1, Channel::C2, Channel::C3, Channel::C4].iter() {
+        motors.enable(*channel);
+    }
+
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+    let mut adc_cs = gpiob.pb12.into_push_pull_output(&mut gpiob.crh);
+    adc_cs.set_high().unwrap();
+
+    // The MCP3008 supports up to 1.35MHz at 3.3V.
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        MODE_0,
+        1_u32.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+    let mut adc = Mcp3008::new(spi, adc_cs).unwrap();
+
+    let button = gpiob.pb13.into_pull_down_input(&mut gpiob.crh);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let stored = {
+        let writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+        writer
+            .read(CALIBRATION_OFFSET, CALIBRATION_SIZE)
+            .ok()
+            .and_then(|bytes| Calibration::from_bytes(bytes, &mut crc))
+    };
+    let (mut calibration, mut state) = match stored {
+        Some(calibration) if button.is_low().unwrap() => {
+            log_info!("Calibration: {:?}", calibration);
+            (calibration, State::Stopped)
+        }
+        _ => {
+            log_info!("Calibrating. Sweep the sensors over the line.");
+            let until_ms = monotonic::millis() + CALIBRATION_MS;
+            (Calibration::new(), State::Calibrating { until_ms })
+        }
+    };
+
+    let mut pid = PidController::new(KP, 0.0, KD).limits(-1.0, 1.0);
+    let mut last_position = CENTER;
+    let mut last_seen_ms = 0;
+    // Ignore the button until it has been released after startup.
+    let mut button_history = 0xFF_u8;
+    let mut led_on = false;
+    let mut blinks = 0_u32;
+    let mut scheduler = Scheduler::new([CONTROL_MS, 10, 125]);
+    loop {
+        match scheduler.poll() {
+            Some(CONTROL) => {
+                let mut raw = [0; SENSORS];
+                for (i, raw) in raw.iter_mut().enumerate() {
+                    *raw = adc.read_channel(channel(i)).unwrap_or(0);
+                }
+                let now = monotonic::millis();
+                match state {
+                    State::Calibrating { until_ms } => {
+                        calibration.update(&raw);
+                        if now < until_ms {
+                            continue;
+                        }
+                        match calibration.check() {
+                            Ok(()) => {
+                                let mut writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+                                writer.erase(CALIBRATION_OFFSET, PAGE_SIZE).unwrap();
+                                writer
+                                    .write(CALIBRATION_OFFSET, &calibration.to_bytes(&mut crc))
+                                    .unwrap();
+                                log_info!("Calibration stored: {:?}", calibration);
+                                state = State::Stopped;
+                            }
+                            Err(sensor) => {
+                                log_warn!("Sensor {} saw no line. Calibrating again.", sensor + 1);
+                                calibration = Calibration::new();
+                                state = State::Calibrating {
+                                    until_ms: now + CALIBRATION_MS,
+                                };
+                            }
+                        }
+                    }
+                    State::Stopped => drive(&mut motors, 0.0, 0.0),
+                    State::Running => {
+                        let values = calibration.scale(&raw);
+                        let position = match line_position(&values) {
+                            Some(position) => {
+                                last_seen_ms = now;
+                                position
+                            }
+                            // Keep turning to the side where the line was.
+                            None if last_position < CENTER => 0,
+                            None => 2 * CENTER,
+                        };
+                        last_position = position;
+                        if now.wrapping_sub(last_seen_ms) > LINE_LOST_MS {
+                            log_warn!("Line lost");
+                            drive(&mut motors, 0.0, 0.0);
+                            state = State::Stopped;
+                            continue;
+                        }
+                        // Positive when the line is on the right side.
+                        let error = (position - CENTER) as f32 / CENTER as f32;
+                        let steering = -pid.update(0.0, error, CONTROL_MS as f32 / 1000.0);
+                        drive(
+                            &mut motors,
+                            (BASE_SPEED + steering).clamp(-1.0, 1.0),
+                            (BASE_SPEED - steering).clamp(-1.0, 1.0),
+                        );
+                    }
+                }
+            }
+            Some(BUTTON) => {
+                let previous = button_history;
+                button_history = (button_history << 1) | u8::from(button.is_high().unwrap());
+                if button_history != 0xFF || previous == 0xFF {
+                    continue;
+                }
+                state = match state {
+                    State::Stopped => {
+                        log_info!("Running");
+                        pid.reset();
+                        last_position = CENTER;
+                        last_seen_ms = monotonic::millis();
+                        State::Running
+                    }
+                    State::Running => {
+                        log_info!("Stopped");
+                        State::Stopped
+                    }
+                    calibrating => calibrating,
+                };
+            }
+            Some(BLINK) => {
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                // It blinks fast while calibrating.
+                blinks = blinks.wrapping_add(1);
+                let calibrating = matches!(state, State::Calibrating { .. });
+                if calibrating || blinks % 4 == 0 {
+                    led_on = !led_on;
+                    if led_on {
+                        led.set_high().unwrap();
+                    } else {
+                        led.set_low().unwrap();
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
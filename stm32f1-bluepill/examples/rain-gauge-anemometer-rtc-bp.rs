@@ -0,0 +1,355 @@
+//! Measure the rain rate and the wind speed by counting the pulses of the
+//! reed switches in a tipping-bucket rain gauge and a cup anemometer and show
+//! them on an SSD1306 OLED display. Hourly totals are logged through RTT.
+//!
+//! Each switch triggers an EXTI interrupt on the falling edge. Reed switches
+//! bounce so edges closer than the debounce time to the last accepted pulse
+//! are ignored. The time between edges is measured with the cycle counter.
+//!
+//! The internal RTC generates an interrupt every second. The pulses counted
+//! during that second are put into sliding windows:
+//! - Rain rate in mm/h over the last `RAIN_WINDOW_S` seconds.
+//! - Wind speed in km/h averaged over the last `WIND_WINDOW_S` seconds and
+//!   the strongest gust (highest 1-second speed) of the current hour.
+//!
+//! At every full hour of the RTC time the totals of the hour are logged.
+//! The RTC keeps counting across resets if there is a battery on VBAT.
+//!
+//! The constants are for the common weather meter kits (e.g. SparkFun
+//! SEN-15901): 0.2794mm of rain per tip and 2.4km/h per pulse per second.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> Rain gauge <-> Anemometer <-> Display
+//! GND  <-> Switch     <-> Switch     <-> GND
+//! 3.3V                               <-> VDD
+//! PA1  <-> Switch
+//! PA2                 <-> Switch
+//! PB8                                <-> SCL
+//! PB9                                <-> SDA
+//! ```
+//!
+//! The switch inputs use the internal pull-up resistors.
+//!
+//! Run with:
+//! `cargo embed --example rain-gauge-anemometer-rtc-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    exti::setup_exti_pin,
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::{PA1, PA2},
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Input, OpenDrain, Output, PullUp, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    rtc::Rtc,
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const RAIN_DEBOUNCE_MS: u32 = 50;
+const WIND_DEBOUNCE_MS: u32 = 5;
+const RAIN_MM_PER_TIP: f32 = 0.2794;
+const WIND_KMH_PER_HZ: f32 = 2.4;
+const RAIN_WINDOW_S: usize = 10 * 60;
+const WIND_WINDOW_S: usize = 10;
+
+/// Totals of an hour
+#[derive(Debug, Clone, Copy)]
+pub struct HourTotals {
+    rain_mm: f32,
+    average_wind_kmh: f32,
+    max_gust_kmh: f32,
+}
+
+/// Pulses counted every second for the last seconds
+pub struct Windows {
+    rain: [u8; RAIN_WINDOW_S],
+    wind: [u8; WIND_WINDOW_S],
+    seconds: u32,
+    hour_rain_tips: u32,
+    hour_wind_pulses: u32,
+    hour_seconds: u32,
+    hour_max_gust: u8,
+    last_hour: Option<HourTotals>,
+}
+
+impl Windows {
+    fn new() -> Self {
+        Windows {
+            rain: [0; RAIN_WINDOW_S],
+            wind: [0; WIND_WINDOW_S],
+            seconds: 0,
+            hour_rain_tips: 0,
+            hour_wind_pulses: 0,
+            hour_seconds: 0,
+            hour_max_gust: 0,
+            last_hour: None,
+        }
+    }
+
+    /// Add the pulses counted during the last second.
+    fn push(&mut self, rain_tips: u8, wind_pulses: u8) {
+        self.rain[self.seconds as usize % RAIN_WINDOW_S] = rain_tips;
+        self.wind[self.seconds as usize % WIND_WINDOW_S] = wind_pulses;
+        self.seconds += 1;
+        self.hour_rain_tips += u32::from(rain_tips);
+        self.hour_wind_pulses += u32::from(wind_pulses);
+        self.hour_seconds += 1;
+        self.hour_max_gust = self.hour_max_gust.max(wind_pulses);
+    }
+
+    /// Close the current hour and return its totals.
+    fn end_hour(&mut self) -> HourTotals {
+        let totals = HourTotals {
+            rain_mm: self.hour_rain_tips as f32 * RAIN_MM_PER_TIP,
+            average_wind_kmh: self.hour_wind_pulses as f32 * WIND_KMH_PER_HZ
+                / self.hour_seconds.max(1) as f32,
+            max_gust_kmh: f32::from(self.hour_max_gust) * WIND_KMH_PER_HZ,
+        };
+        self.hour_rain_tips = 0;
+        self.hour_wind_pulses = 0;
+        self.hour_seconds = 0;
+        self.hour_max_gust = 0;
+        self.last_hour = Some(totals);
+        totals
+    }
+
+    fn rain_rate_mm_h(&self) -> f32 {
+        let tips: u32 = self.rain.iter().map(|t| u32::from(*t)).sum();
+        tips as f32 * RAIN_MM_PER_TIP * 3600.0 / RAIN_WINDOW_S as f32
+    }
+
+    fn wind_speed_kmh(&self) -> f32 {
+        let pulses: u32 = self.wind.iter().map(|p| u32::from(*p)).sum();
+        pulses as f32 * WIND_KMH_PER_HZ / WIND_WINDOW_S as f32
+    }
+
+    fn max_gust_kmh(&self) -> f32 {
+        f32::from(self.hour_max_gust) * WIND_KMH_PER_HZ
+    }
+}
+
+/// Count an edge if it is far enough from the last accepted one.
+fn debounce(now: u32, last_pulse: &mut u32, debounce_ms: u32, count: &mut u8) {
+    if now.wrapping_sub(*last_pulse) >= debounce_ms * 1000 * SYSCLK_MHZ {
+        *last_pulse = now;
+        *count = count.saturating_add(1);
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        rain_switch: PA1<Input<PullUp>>,
+        wind_switch: PA2<Input<PullUp>>,
+        #[init(0)]
+        last_rain_pulse: u32,
+        #[init(0)]
+        last_wind_pulse: u32,
+        #[init(0)]
+        rain_tips: u8,
+        #[init(0)]
+        wind_pulses: u8,
+        rtc: Rtc,
+        windows: Windows,
+        // Taken by the idle task, which creates the display driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Rain gauge and anemometer example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut pwr = device.PWR;
+        let mut backup_domain = rcc.bkp.constrain(device.BKP, &mut rcc.apb1, &mut pwr);
+        let mut rtc = Rtc::rtc(device.RTC, &mut backup_domain);
+        rtc.listen_seconds();
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let mut rain_switch = gpioa.pa1.into_pull_up_input(&mut gpioa.crl);
+        setup_exti_pin(&mut rain_switch, &mut afio, &device.EXTI, Edge::FALLING);
+
+        let mut wind_switch = gpioa.pa2.into_pull_up_input(&mut gpioa.crl);
+        setup_exti_pin(&mut wind_switch, &mut afio, &device.EXTI, Edge::FALLING);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            rain_switch,
+            wind_switch,
+            rtc,
+            windows: Windows::new(),
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = EXTI1, priority = 3, resources = [rain_switch, last_rain_pulse, rain_tips])]
+    fn rain_pulse(cx: rain_pulse::Context) {
+        let now = DWT::get_cycle_count();
+        cx.resources.rain_switch.clear_interrupt_pending_bit();
+        debounce(
+            now,
+            cx.resources.last_rain_pulse,
+            RAIN_DEBOUNCE_MS,
+            cx.resources.rain_tips,
+        );
+    }
+
+    #[task(binds = EXTI2, priority = 3, resources = [wind_switch, last_wind_pulse, wind_pulses])]
+    fn wind_pulse(cx: wind_pulse::Context) {
+        let now = DWT::get_cycle_count();
+        cx.resources.wind_switch.clear_interrupt_pending_bit();
+        debounce(
+            now,
+            cx.resources.last_wind_pulse,
+            WIND_DEBOUNCE_MS,
+            cx.resources.wind_pulses,
+        );
+    }
+
+    #[task(binds = RTC, priority = 2, resources = [rtc, windows, rain_tips, wind_pulses])]
+    fn second(mut cx: second::Context) {
+        let rtc = cx.resources.rtc;
+        rtc.clear_second_flag();
+        let rain_tips = cx
+            .resources
+            .rain_tips
+            .lock(|tips| core::mem::replace(tips, 0));
+        let wind_pulses = cx
+            .resources
+            .wind_pulses
+            .lock(|pulses| core::mem::replace(pulses, 0));
+        let windows = cx.resources.windows;
+        windows.push(rain_tips, wind_pulses);
+
+        let time = rtc.current_time();
+        if time % 3600 == 0 {
+            let totals = windows.end_hour();
+            log_info!(
+                "Hour {:02}:00 rain: {:.1}mm, wind: {:.1}km/h, max gust: {:.1}km/h",
+                time / 3600 % 24,
+                totals.rain_mm,
+                totals.average_wind_kmh,
+                totals.max_gust_kmh
+            );
+        }
+    }
+
+    #[idle(resources = [windows, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let i2c = cx.resources.i2c.take().unwrap();
+        let interface = I2CDIBuilder::new().init(i2c);
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut last_seconds = 0;
+        let mut lines: [String<32>; 4] =
+            [String::new(), String::new(), String::new(), String::new()];
+        loop {
+            let updated = cx.resources.windows.lock(|windows| {
+                if windows.seconds == last_seconds {
+                    return false;
+                }
+                last_seconds = windows.seconds;
+                for line in lines.iter_mut() {
+                    line.clear();
+                }
+                write!(lines[0], "Rain: {:.1} mm/h", windows.rain_rate_mm_h()).unwrap();
+                write!(lines[1], "Wind: {:.1} km/h", windows.wind_speed_kmh()).unwrap();
+                write!(lines[2], "Gust: {:.1} km/h", windows.max_gust_kmh()).unwrap();
+                match windows.last_hour {
+                    Some(totals) => write!(lines[3], "Last hour: {:.1} mm", totals.rain_mm),
+                    None => write!(lines[3], "Last hour: -"),
+                }
+                .unwrap();
+                true
+            });
+            if !updated {
+                continue;
+            }
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            cx.resources.led.set_low().unwrap();
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 16))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+            cx.resources.led.set_high().unwrap();
+        }
+    }
+};
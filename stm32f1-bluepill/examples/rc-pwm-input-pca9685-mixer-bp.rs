@@ -0,0 +1,373 @@
+//! Measure the servo pulses of 4 channels of an RC receiver and forward them
+//! to servos connected to a PCA9685, with trims, endpoints, reversing and
+//! mixing. This makes a servo signal repeater/mixer, for example to drive
+//! the elevons of a flying wing from a receiver without mixing.
+//!
+//! The receiver outputs a 1-2ms pulse every 20ms or so on each channel,
+//! 1.5ms being the center. The pulses are measured with input capture on
+//! the 4 channels of TIM2, counting microseconds. The STM32F1 timers can
+//! only capture one edge, so after each capture the interrupt switches the
+//! channel to the other edge, and the pulse width is the difference between
+//! the falling and rising edge captures.
+//!
+//! Every output is configured in `OUTPUTS`:
+//! - `mix`: how much of each input goes to the output in percent. For a
+//!   plain repeater use 100 for one input and 0 for the others.
+//! - `trim_us`: added to the center position
+//! - `reverse`: invert the direction
+//! - `min_us`/`max_us`: endpoints the servo never goes beyond
+//! - `failsafe_us`: position when one of its inputs is lost
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> RC receiver <-> PCA9685
+//! GND  <-> GND         <-> GND
+//! 3.3V                 <-> VCC
+//! PA0  <-> CH1
+//! PA1  <-> CH2
+//! PA2  <-> CH3
+//! PA3  <-> CH4
+//! PB8                  <-> SCL
+//! PB9                  <-> SDA
+//! GND                  <-> OE
+//!                          V+ <-> +5V
+//!                          Channels 0-3 <-> Servos
+//! ```
+//!
+//! PA0-PA3 are not 5V tolerant. Most receivers output 3.3V pulses even when
+//! powered with 5V, but check it first.
+//!
+//! Run with:
+//! `cargo embed --example rc-pwm-input-pca9685-mixer-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table, i2c_speed, log_info, panic_display as _,
+};
+use embedded_hal::digital::v2::OutputPin;
+use pwm_pca9685::{Address, Channel, Pca9685};
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    gpio::{
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const INPUTS: usize = 4;
+const CENTER_US: i32 = 1500;
+/// Pulses outside of this range are ignored.
+const MIN_PULSE_US: u16 = 800;
+const MAX_PULSE_US: u16 = 2200;
+/// An input without pulses for this long is lost.
+const FAILSAFE_MS: u32 = 100;
+const UPDATE_MS: u32 = 20;
+/// Period of the PCA9685 output at a prescale of 121 (50Hz)
+const PERIOD_US: u32 = 20_000;
+
+/// Configuration of a servo output
+struct ServoOutput {
+    channel: Channel,
+    mix: [i32; INPUTS],
+    trim_us: i32,
+    reverse: bool,
+    min_us: u16,
+    max_us: u16,
+    failsafe_us: u16,
+}
+
+const OUTPUTS: [ServoOutput; 4] = [
+    // Throttle (CH3) goes straight through but cuts on failsafe.
+    ServoOutput {
+        channel: Channel::C0,
+        mix: [0, 0, 100, 0],
+        trim_us: 0,
+        reverse: false,
+        min_us: 1000,
+        max_us: 2000,
+        failsafe_us: 1000,
+    },
+    // Rudder (CH4), reversed and with some trim.
+    ServoOutput {
+        channel: Channel::C1,
+        mix: [0, 0, 0, 100],
+        trim_us: 20,
+        reverse: true,
+        min_us: 1100,
+        max_us: 1900,
+        failsafe_us: 1500,
+    },
+    // Elevons: aileron (CH1) and elevator (CH2) mixed 50/50.
+    ServoOutput {
+        channel: Channel::C2,
+        mix: [50, 50, 0, 0],
+        trim_us: 0,
+        reverse: false,
+        min_us: 1000,
+        max_us: 2000,
+        failsafe_us: 1500,
+    },
+    ServoOutput {
+        channel: Channel::C3,
+        mix: [50, -50, 0, 0],
+        trim_us: 0,
+        reverse: false,
+        min_us: 1000,
+        max_us: 2000,
+        failsafe_us: 1500,
+    },
+];
+
+impl ServoOutput {
+    /// Pulse width for the given inputs
+    fn pulse_us(&self, inputs: &[Option<u16>; INPUTS]) -> u16 {
+        let mut offset = 0;
+        for (weight, input) in self.mix.iter().zip(inputs.iter()) {
+            match (weight, input) {
+                (0, _) => (),
+                (weight, Some(input)) => offset += weight * (i32::from(*input) - CENTER_US) / 100,
+                (_, None) => return self.failsafe_us,
+            }
+        }
+        if self.reverse {
+            offset = -offset;
+        }
+        let pulse = (CENTER_US + offset + self.trim_us)
+            .clamp(i32::from(self.min_us), i32::from(self.max_us));
+        pulse as u16
+    }
+}
+
+/// Pulse measurements of the receiver channels
+pub struct Pulses {
+    rising: [u16; INPUTS],
+    width_us: [u16; INPUTS],
+    /// Cycle count at the end of the last valid pulse
+    last_pulse: [u32; INPUTS],
+}
+
+/// Take the capture of `channel`, if any, and switch it to the other edge.
+/// Returns the captured value and whether it was a rising edge.
+fn take_capture(timer: &pac::TIM2, channel: usize) -> Option<(u16, bool)> {
+    let status = timer.sr.read();
+    let ccer = timer.ccer.read();
+    let (captured, rising) = match channel {
+        0 => (status.cc1if().bit_is_set(), ccer.cc1p().bit_is_clear()),
+        1 => (status.cc2if().bit_is_set(), ccer.cc2p().bit_is_clear()),
+        2 => (status.cc3if().bit_is_set(), ccer.cc3p().bit_is_clear()),
+        _ => (status.cc4if().bit_is_set(), ccer.cc4p().bit_is_clear()),
+    };
+    if !captured {
+        return None;
+    }
+    // Reading the capture clears the flag.
+    let value = match channel {
+        0 => timer.ccr1.read().bits(),
+        1 => timer.ccr2.read().bits(),
+        2 => timer.ccr3.read().bits(),
+        _ => timer.ccr4.read().bits(),
+    } as u16;
+    // Capture the falling edge after a rising edge and vice versa.
+    timer.ccer.modify(|_, w| match channel {
+        0 => w.cc1p().bit(rising),
+        1 => w.cc2p().bit(rising),
+        2 => w.cc3p().bit(rising),
+        _ => w.cc4p().bit(rising),
+    });
+    Some((value, rising))
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        timer: pac::TIM2,
+        pulses: Pulses,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("RC PWM input mixer example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the TIM2 clock before handing the RCC over to the HAL.
+        device.RCC.apb1enr.modify(|_, w| w.tim2en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // TIM2 channel 1-4 inputs
+        let _ch1 = gpioa.pa0.into_pull_down_input(&mut gpioa.crl);
+        let _ch2 = gpioa.pa1.into_pull_down_input(&mut gpioa.crl);
+        let _ch3 = gpioa.pa2.into_pull_down_input(&mut gpioa.crl);
+        let _ch4 = gpioa.pa3.into_pull_down_input(&mut gpioa.crl);
+
+        // The TIM2 clock is twice PCLK1, so 72MHz. Count microseconds and
+        // capture the rising edges first.
+        let timer = device.TIM2;
+        timer.psc.write(|w| w.psc().bits((SYSCLK_MHZ - 1) as u16));
+        timer.egr.write(|w| w.ug().set_bit());
+        timer
+            .ccmr1_input()
+            .modify(|_, w| w.cc1s().ti1().cc2s().ti2());
+        timer
+            .ccmr2_input()
+            .modify(|_, w| w.cc3s().ti3().cc4s().ti4());
+        timer.ccer.modify(|_, w| {
+            w.cc1e()
+                .set_bit()
+                .cc2e()
+                .set_bit()
+                .cc3e()
+                .set_bit()
+                .cc4e()
+                .set_bit()
+        });
+        timer.dier.modify(|_, w| {
+            w.cc1ie()
+                .set_bit()
+                .cc2ie()
+                .set_bit()
+                .cc3ie()
+                .set_bit()
+                .cc4ie()
+                .set_bit()
+        });
+        timer.cr1.modify(|_, w| w.cen().enabled());
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // PCA9685
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x40]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        let now = DWT::get_cycle_count();
+        init::LateResources {
+            timer,
+            pulses: Pulses {
+                rising: [0; INPUTS],
+                width_us: [0; INPUTS],
+                last_pulse: [now.wrapping_sub(FAILSAFE_MS * 1000 * SYSCLK_MHZ); INPUTS],
+            },
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = TIM2, priority = 2, resources = [timer, pulses])]
+    fn capture(cx: capture::Context) {
+        let timer = cx.resources.timer;
+        let pulses = cx.resources.pulses;
+        let channels = pulses
+            .rising
+            .iter_mut()
+            .zip(pulses.width_us.iter_mut())
+            .zip(pulses.last_pulse.iter_mut())
+            .enumerate();
+        for (channel, ((rising, width_us), last_pulse)) in channels {
+            match take_capture(timer, channel) {
+                Some((value, true)) => *rising = value,
+                Some((value, false)) => {
+                    let width = value.wrapping_sub(*rising);
+                    if (MIN_PULSE_US..=MAX_PULSE_US).contains(&width) {
+                        *width_us = width;
+                        *last_pulse = DWT::get_cycle_count();
+                    }
+                }
+                None => (),
+            }
+        }
+    }
+
+    #[idle(resources = [pulses, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let i2c = cx.resources.i2c.take().unwrap();
+        let mut pwm = Pca9685::new(i2c, Address::default()).unwrap();
+        pwm.enable().unwrap();
+        // 25MHz / (4096 * 50Hz) - 1
+        pwm.set_prescale(121).unwrap();
+
+        let mut last_update = DWT::get_cycle_count();
+        let mut updates = 0_u32;
+        loop {
+            if DWT::get_cycle_count().wrapping_sub(last_update) < UPDATE_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_update = last_update.wrapping_add(UPDATE_MS * 1000 * SYSCLK_MHZ);
+
+            let now = DWT::get_cycle_count();
+            let mut inputs = [None; INPUTS];
+            cx.resources.pulses.lock(|pulses| {
+                for ((input, width_us), last_pulse) in inputs
+                    .iter_mut()
+                    .zip(pulses.width_us.iter())
+                    .zip(pulses.last_pulse.iter())
+                {
+                    if now.wrapping_sub(*last_pulse) < FAILSAFE_MS * 1000 * SYSCLK_MHZ {
+                        *input = Some(*width_us);
+                    }
+                }
+            });
+
+            let mut outputs = [0; 4];
+            for (output, pulse_us) in OUTPUTS.iter().zip(outputs.iter_mut()) {
+                *pulse_us = output.pulse_us(&inputs);
+                let off = (u32::from(*pulse_us) * 4096 / PERIOD_US) as u16;
+                pwm.set_channel_on_off(output.channel, 0, off).unwrap();
+            }
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            updates += 1;
+            if updates % 50 == 0 {
+                cx.resources.led.set_low().unwrap();
+                log_info!("Inputs: {:?}, outputs: {:?}", inputs, outputs);
+            } else if updates % 50 == 5 {
+                cx.resources.led.set_high().unwrap();
+            }
+        }
+    }
+};
@@ -0,0 +1,435 @@
+//! Monitor doors and windows with reed switches, log every opening and
+//! closing with the time from a DS3231 real-time clock in an AT24C256
+//! EEPROM and page through the log on an SSD1306 OLED display.
+//!
+//! The reed switches trigger an EXTI interrupt on every edge. Since the
+//! contacts bounce, the idle task only reads the switches once they have
+//! been quiet for `DEBOUNCE_MS` and logs the ones which changed.
+//!
+//! The log is a ring buffer of the last `EVENT_COUNT` events in the EEPROM.
+//! Every event has a sequence number and a CRC-32 checksum calculated with
+//! the STM32 hardware CRC unit, like in the at24c256-crc-log-display-bp
+//! example, so the log continues after the newest valid event on startup.
+//!
+//! The display shows the time and the number of open doors in the first
+//! line and 3 events below, the newest first. Press the buttons to page
+//! through older and newer events.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> DS3231 <-> AT24C256 <-> Display <-> Reed switches <-> Buttons
+//! GND  <-> GND    <-> GND      <-> GND     <-> GND
+//! 3.3V <-> VCC    <-> VCC      <-> VDD                       <-> +
+//! 3.3V            <-> A0, A1, A2
+//! GND             <-> WP
+//! PB8  <-> SCL    <-> SCL      <-> SCL
+//! PB9  <-> SDA    <-> SDA      <-> SDA
+//! PB12                                     <-> Front door
+//! PB13                                     <-> Back door
+//! PB14                                     <-> Garage
+//! PB15                                     <-> Window
+//! PB0                                                        <-> Older -
+//! PB1                                                        <-> Newer -
+//! ```
+//!
+//! The reed switches connect their pin to GND while the magnet is close,
+//! so a high level (internal pull-up) means open. Set the time of the
+//! DS3231 first, for example with the ds3231-rtc-display-bp example.
+//!
+//! Run with:
+//! `cargo embed --example reed-switch-door-monitor-ds3231-eeprom-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    crc::Crc32,
+    exti::setup_exti_pin,
+    i2c_speed, log_error, log_info,
+    panic_display::{self, Bus},
+};
+use ds323x::{Ds323x, NaiveDateTime, Rtcc};
+use eeprom24x::{Eeprom24x, SlaveAddr as EepromAddr};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    crc::{Crc, CrcExt},
+    gpio::{
+        gpiob::{PB0, PB1, PB12, PB13, PB14, PB15, PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Input, OpenDrain, Output, PullDown, PullUp, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const DEBOUNCE_MS: u32 = 20;
+const POLL_MS: u32 = 10;
+const NAMES: [&str; SWITCHES] = ["Front", "Back", "Garage", "Window"];
+const SWITCHES: usize = 4;
+const EVENT_SIZE: u32 = 16;
+const EVENT_COUNT: usize = 100;
+const EVENTS_PER_PAGE: usize = 3;
+
+/// Opening or closing of a door as stored in the EEPROM
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Event {
+    sequence: u32,
+    /// Seconds since 1970
+    timestamp: u32,
+    switch: u8,
+    open: bool,
+}
+
+impl Event {
+    fn to_bytes<C: Crc32>(&self, crc: &mut C) -> [u8; EVENT_SIZE as usize] {
+        let mut bytes = [0; EVENT_SIZE as usize];
+        bytes[0..4].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[8] = self.switch;
+        bytes[9] = u8::from(self.open);
+        // bytes 10..12 are reserved
+        let checksum = crc.checksum(&bytes[..12]);
+        bytes[12..16].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// `None` for empty and corrupted slots
+    fn from_bytes<C: Crc32>(bytes: &[u8; EVENT_SIZE as usize], crc: &mut C) -> Option<Self> {
+        let checksum = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+        if bytes.iter().all(|b| *b == 0xFF) || crc.checksum(&bytes[..12]) != checksum {
+            return None;
+        }
+        Some(Event {
+            sequence: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            timestamp: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            switch: bytes[8],
+            open: bytes[9] != 0,
+        })
+    }
+}
+
+/// Reed switch inputs
+pub struct Switches {
+    pins: (
+        PB12<Input<PullUp>>,
+        PB13<Input<PullUp>>,
+        PB14<Input<PullUp>>,
+        PB15<Input<PullUp>>,
+    ),
+    /// An edge arrived since the switches were last read.
+    changed: bool,
+    last_edge: u32,
+}
+
+impl Switches {
+    /// Which doors are open
+    fn read(&self) -> [bool; SWITCHES] {
+        [
+            self.pins.0.is_high().unwrap(),
+            self.pins.1.is_high().unwrap(),
+            self.pins.2.is_high().unwrap(),
+            self.pins.3.is_high().unwrap(),
+        ]
+    }
+}
+
+/// Read the event in `slot`, `None` if it can't be read or isn't valid.
+fn read_event<F, C>(read: F, slot: usize, crc: &mut C) -> Option<Event>
+where
+    F: FnOnce(u32, &mut [u8; EVENT_SIZE as usize]) -> bool,
+    C: Crc32,
+{
+    let mut bytes = [0; EVENT_SIZE as usize];
+    if read(slot as u32 * EVENT_SIZE, &mut bytes) {
+        Event::from_bytes(&bytes, crc)
+    } else {
+        None
+    }
+}
+
+/// Position in the ring buffer of the next event
+struct Log {
+    next_slot: usize,
+    sequence: u32,
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        switches: Switches,
+        older_button: PB0<Input<PullDown>>,
+        newer_button: PB1<Input<PullDown>>,
+        crc: Crc,
+        // Taken by the idle task, which creates the drivers.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Reed switch door monitor example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let mut front = gpiob.pb12.into_pull_up_input(&mut gpiob.crh);
+        let mut back = gpiob.pb13.into_pull_up_input(&mut gpiob.crh);
+        let mut garage = gpiob.pb14.into_pull_up_input(&mut gpiob.crh);
+        let mut window = gpiob.pb15.into_pull_up_input(&mut gpiob.crh);
+        setup_exti_pin(&mut front, &mut afio, &device.EXTI, Edge::RISING_FALLING);
+        setup_exti_pin(&mut back, &mut afio, &device.EXTI, Edge::RISING_FALLING);
+        setup_exti_pin(&mut garage, &mut afio, &device.EXTI, Edge::RISING_FALLING);
+        setup_exti_pin(&mut window, &mut afio, &device.EXTI, Edge::RISING_FALLING);
+
+        let older_button = gpiob.pb0.into_pull_down_input(&mut gpiob.crl);
+        let newer_button = gpiob.pb1.into_pull_down_input(&mut gpiob.crl);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // DS3231 RTC, EEPROM and display
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x68, 0x57, 0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            switches: Switches {
+                pins: (front, back, garage, window),
+                changed: false,
+                last_edge: DWT::get_cycle_count(),
+            },
+            older_button,
+            newer_button,
+            crc: device.CRC.new(&mut rcc.ahb),
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    /// PB12-PB15 share the EXTI15_10 interrupt.
+    #[task(binds = EXTI15_10, priority = 2, resources = [switches])]
+    fn edge(cx: edge::Context) {
+        let switches = cx.resources.switches;
+        switches.pins.0.clear_interrupt_pending_bit();
+        switches.pins.1.clear_interrupt_pending_bit();
+        switches.pins.2.clear_interrupt_pending_bit();
+        switches.pins.3.clear_interrupt_pending_bit();
+        switches.changed = true;
+        switches.last_edge = DWT::get_cycle_count();
+    }
+
+    #[idle(resources = [switches, older_button, newer_button, crc, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let crc = cx.resources.crc;
+        let i2c = cx.resources.i2c.take().unwrap();
+        let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+        let interface = I2CDIBuilder::new().init(manager.acquire());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut rtc = Ds323x::new_ds3231(manager.acquire());
+        let mut eeprom =
+            Eeprom24x::new_24x256(manager.acquire(), EepromAddr::Alternative(true, true, true));
+
+        // Continue after the newest valid event.
+        let mut log = Log {
+            next_slot: 0,
+            sequence: 0,
+        };
+        for slot in 0..EVENT_COUNT {
+            if let Some(event) = read_event(
+                |address, bytes| eeprom.read_data(address, bytes).is_ok(),
+                slot,
+                crc,
+            ) {
+                if event.sequence >= log.sequence {
+                    log.next_slot = (slot + 1) % EVENT_COUNT;
+                    log.sequence = event.sequence + 1;
+                }
+            }
+        }
+        log_info!("{} events logged", log.sequence);
+
+        // Only changes after startup are logged.
+        let mut open = cx.resources.switches.lock(|switches| switches.read());
+        let mut page = 0;
+        let mut redraw = true;
+        let mut older_was_pressed = false;
+        let mut newer_was_pressed = false;
+        let mut lines: [String<32>; 7] = Default::default();
+        let mut last_poll = DWT::get_cycle_count();
+        let mut last_second = None;
+        loop {
+            if DWT::get_cycle_count().wrapping_sub(last_poll) < POLL_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_poll = last_poll.wrapping_add(POLL_MS * 1000 * SYSCLK_MHZ);
+
+            let now = DWT::get_cycle_count();
+            let levels = cx.resources.switches.lock(|switches| {
+                let quiet = now.wrapping_sub(switches.last_edge) > DEBOUNCE_MS * 1000 * SYSCLK_MHZ;
+                if switches.changed && quiet {
+                    switches.changed = false;
+                    Some(switches.read())
+                } else {
+                    None
+                }
+            });
+            if let Some(levels) = levels {
+                let datetime = rtc.get_datetime();
+                for (i, (state, level)) in open.iter_mut().zip(levels.iter()).enumerate() {
+                    if *state == *level {
+                        continue;
+                    }
+                    *state = *level;
+                    let event = Event {
+                        sequence: log.sequence,
+                        timestamp: datetime.map_or(0, |dt| dt.timestamp() as u32),
+                        switch: i as u8,
+                        open: *level,
+                    };
+                    log_info!("{:?}", event);
+                    let bytes = event.to_bytes(crc);
+                    match eeprom.write_page(log.next_slot as u32 * EVENT_SIZE, &bytes) {
+                        Ok(()) => {
+                            // Wait until the EEPROM has written the page.
+                            cortex_m::asm::delay(5000 * SYSCLK_MHZ);
+                            log.next_slot = (log.next_slot + 1) % EVENT_COUNT;
+                            log.sequence += 1;
+                        }
+                        Err(e) => log_error!("EEPROM error: {:?}", e),
+                    }
+                }
+                page = 0;
+                redraw = true;
+            }
+
+            let logged = (log.sequence as usize).min(EVENT_COUNT);
+            let pages = ((logged + EVENTS_PER_PAGE - 1) / EVENTS_PER_PAGE).max(1);
+            let older_pressed = cx.resources.older_button.is_high().unwrap();
+            if older_pressed && !older_was_pressed && page + 1 < pages {
+                page += 1;
+                redraw = true;
+            }
+            older_was_pressed = older_pressed;
+            let newer_pressed = cx.resources.newer_button.is_high().unwrap();
+            if newer_pressed && !newer_was_pressed && page > 0 {
+                page -= 1;
+                redraw = true;
+            }
+            newer_was_pressed = newer_pressed;
+
+            // Update the clock every second.
+            let datetime = rtc.get_datetime().ok();
+            let second = datetime.map(|dt| dt.timestamp());
+            if second != last_second {
+                last_second = second;
+                redraw = true;
+            }
+            if !redraw {
+                continue;
+            }
+            redraw = false;
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            let open_count = open.iter().filter(|o| **o).count();
+            match datetime {
+                Some(dt) => write!(lines[0], "{} Open: {}", dt.time(), open_count).unwrap(),
+                None => write!(lines[0], "RTC error Open: {}", open_count).unwrap(),
+            }
+            for i in 0..EVENTS_PER_PAGE {
+                let age = page * EVENTS_PER_PAGE + i;
+                if age >= logged {
+                    break;
+                }
+                let slot = (log.next_slot + EVENT_COUNT - 1 - age) % EVENT_COUNT;
+                let (name_line, time_line) = lines[1 + i * 2..].split_at_mut(1);
+                match read_event(
+                    |address, bytes| eeprom.read_data(address, bytes).is_ok(),
+                    slot,
+                    crc,
+                ) {
+                    Some(event) => {
+                        let name = NAMES.get(usize::from(event.switch)).unwrap_or(&"?");
+                        let action = if event.open { "opened" } else { "closed" };
+                        write!(name_line[0], "#{} {} {}", event.sequence, name, action).unwrap();
+                        let datetime = NaiveDateTime::from_timestamp(i64::from(event.timestamp), 0);
+                        write!(time_line[0], "  {}", datetime).unwrap();
+                    }
+                    None => write!(name_line[0], "(corrupted event)").unwrap(),
+                }
+            }
+            disp.clear();
+            // The first line at the top, the events 8 pixels apart below it
+            for (i, line) in lines.iter().enumerate() {
+                let y = if i == 0 { 0 } else { 8 + i as i32 * 8 };
+                Text::new(line, Point::new(0, y))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            if second.map_or(false, |s| s % 2 == 0) {
+                cx.resources.led.set_low().unwrap();
+            } else {
+                cx.resources.led.set_high().unwrap();
+            }
+        }
+    }
+};
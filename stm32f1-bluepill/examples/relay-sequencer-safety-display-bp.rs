@@ -0,0 +1,424 @@
+//! Switch the relays of a 4-channel relay board following a timed sequence,
+//! with interlocks, and show the state of each relay on an SSD1306 OLED
+//! display.
+//!
+//! The sequence only requests relay changes. The sequencer decides when they
+//! actually happen:
+//! - A relay stays on for at least its `min_on_ms` and off for at least its
+//!   `min_off_ms`, which protects loads like pumps or compressors from
+//!   short-cycling. Requests that come too early are delayed.
+//! - Relays in the same mutual exclusion group are never on at the same
+//!   time, e.g. the forward and reverse contactors of a motor. Switching one
+//!   on requests the others off and it is only switched on once they have
+//!   been off for `GROUP_DEAD_TIME_MS` (break before make).
+//! - While the safety input is active all relays are switched off at once,
+//!   ignoring the minimum on times, and all requests are ignored. The
+//!   sequence starts from the beginning once it is released.
+//!
+//! The safety input is meant for a normally-closed emergency stop switch to
+//! GND, so that a broken wire also stops everything.
+//!
+//! The display shows each relay as `on` or `off` together with a pending
+//! change, for example `>on 7s` if the relay will switch on in 7 seconds or
+//! `>on lock` if it waits for another relay of its group.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> Display <-> Relay board <-> E-stop
+//! GND  <-> GND     <-> GND         <-> NC contact
+//! 3.3V <-> VDD
+//! 5V               <-> VCC
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! PB12             <-> IN1
+//! PB13             <-> IN2
+//! PB14             <-> IN3
+//! PB15             <-> IN4
+//! PA0                              <-> NC contact
+//! ```
+//!
+//! Most relay boards switch a relay on when the input is low. Otherwise set
+//! `ACTIVE_LOW` to `false`.
+//!
+//! Run with:
+//! `cargo embed --example relay-sequencer-safety-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::{convert::Infallible, fmt::Write};
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_info, monotonic,
+    panic_display::{self, Bus},
+    scheduler::Scheduler,
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{pac, prelude::*};
+
+const ACTIVE_LOW: bool = true;
+const GROUP_DEAD_TIME_MS: u32 = 100;
+
+// Tasks in order of priority
+const CONTROL: usize = 0;
+const DISPLAY: usize = 1;
+const BLINK: usize = 2;
+
+/// Configuration of a relay
+struct Relay {
+    name: &'static str,
+    min_on_ms: u32,
+    min_off_ms: u32,
+    /// Mutual exclusion group
+    group: Option<u8>,
+}
+
+static RELAYS: [Relay; 4] = [
+    Relay {
+        name: "Pump",
+        min_on_ms: 5000,
+        min_off_ms: 10000,
+        group: None,
+    },
+    Relay {
+        name: "Valve",
+        min_on_ms: 1000,
+        min_off_ms: 1000,
+        group: None,
+    },
+    Relay {
+        name: "Mot fwd",
+        min_on_ms: 3000,
+        min_off_ms: 5000,
+        group: Some(0),
+    },
+    Relay {
+        name: "Mot rev",
+        min_on_ms: 3000,
+        min_off_ms: 5000,
+        group: Some(0),
+    },
+];
+
+/// Step of the sequence: request `relay` to be `on` at `at_ms`
+struct Step {
+    at_ms: u32,
+    relay: usize,
+    on: bool,
+}
+
+const SEQUENCE_MS: u32 = 60_000;
+const SEQUENCE: [Step; 10] = [
+    Step::new(0, 0, true),
+    Step::new(0, 2, true),
+    Step::new(10_000, 1, true),
+    // Reverse the motor. Forward goes off first.
+    Step::new(15_000, 3, true),
+    Step::new(20_000, 0, false),
+    Step::new(20_000, 1, false),
+    // Too early for the pump, it waits until its minimum off time is over.
+    Step::new(22_000, 0, true),
+    Step::new(30_000, 2, true),
+    Step::new(40_000, 0, false),
+    Step::new(40_000, 2, false),
+];
+
+impl Step {
+    const fn new(at_ms: u32, relay: usize, on: bool) -> Self {
+        Step { at_ms, relay, on }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RelayState {
+    on: bool,
+    requested: bool,
+    changed_ms: u32,
+}
+
+/// Applies the relay requests respecting the interlocks
+struct Sequencer<const N: usize> {
+    relays: &'static [Relay; N],
+    states: [RelayState; N],
+    safety_stop: bool,
+}
+
+impl<const N: usize> Sequencer<N> {
+    /// All relays start off and may be switched on right away.
+    fn new(relays: &'static [Relay; N], now_ms: u32) -> Self {
+        let mut states = [RelayState {
+            on: false,
+            requested: false,
+            changed_ms: now_ms,
+        }; N];
+        for (state, relay) in states.iter_mut().zip(relays.iter()) {
+            state.changed_ms = now_ms.wrapping_sub(relay.min_off_ms);
+        }
+        Sequencer {
+            relays,
+            states,
+            safety_stop: false,
+        }
+    }
+
+    /// Request a relay to be switched on or off. Switching on requests the
+    /// other relays of its group off. Ignored during a safety stop.
+    fn request(&mut self, index: usize, on: bool) {
+        if self.safety_stop {
+            return;
+        }
+        self.states[index].requested = on;
+        if let (true, Some(group)) = (on, self.relays[index].group) {
+            for (i, (state, relay)) in self.states.iter_mut().zip(self.relays.iter()).enumerate() {
+                if i != index && relay.group == Some(group) {
+                    state.requested = false;
+                }
+            }
+        }
+    }
+
+    /// Activate or release the safety stop. Activating it switches all
+    /// relays off immediately.
+    fn set_safety_stop(&mut self, stop: bool, now_ms: u32) {
+        if stop {
+            for state in self.states.iter_mut() {
+                state.requested = false;
+                if state.on {
+                    state.on = false;
+                    state.changed_ms = now_ms;
+                }
+            }
+        }
+        self.safety_stop = stop;
+    }
+
+    /// Whether another relay of the group of `index` is on or has just been
+    /// switched off.
+    fn group_locked(&self, index: usize, now_ms: u32) -> bool {
+        let group = match self.relays[index].group {
+            Some(group) => group,
+            None => return false,
+        };
+        self.states
+            .iter()
+            .zip(self.relays.iter())
+            .enumerate()
+            .any(|(i, (state, relay))| {
+                i != index
+                    && relay.group == Some(group)
+                    && (state.on || now_ms.wrapping_sub(state.changed_ms) < GROUP_DEAD_TIME_MS)
+            })
+    }
+
+    /// Apply the requests that are allowed now.
+    fn update(&mut self, now_ms: u32) {
+        let relays = self.relays;
+        for (index, relay) in relays.iter().enumerate() {
+            let locked = self.group_locked(index, now_ms);
+            let state = &mut self.states[index];
+            let elapsed = now_ms.wrapping_sub(state.changed_ms);
+            let allowed = match (state.on, state.requested) {
+                (true, false) => elapsed >= relay.min_on_ms,
+                (false, true) => elapsed >= relay.min_off_ms && !locked,
+                _ => false,
+            };
+            if allowed {
+                state.on = state.requested;
+                state.changed_ms = now_ms;
+                log_info!("{} {}", relay.name, if state.on { "on" } else { "off" });
+            }
+        }
+    }
+
+    fn is_on(&self, index: usize) -> bool {
+        self.states[index].on
+    }
+
+    /// Write the state of a relay and its pending change, if any.
+    fn describe<W: Write>(&self, index: usize, now_ms: u32, w: &mut W) -> core::fmt::Result {
+        let relay = &self.relays[index];
+        let state = &self.states[index];
+        write!(
+            w,
+            "{:<8}{:<4}",
+            relay.name,
+            if state.on { "on" } else { "off" }
+        )?;
+        if state.on == state.requested {
+            return Ok(());
+        }
+        let (target, min_ms) = if state.requested {
+            ("on", relay.min_off_ms)
+        } else {
+            ("off", relay.min_on_ms)
+        };
+        let elapsed = now_ms.wrapping_sub(state.changed_ms);
+        if elapsed < min_ms {
+            write!(w, ">{} {}s", target, (min_ms - elapsed + 999) / 1000)
+        } else {
+            write!(w, ">{} lock", target)
+        }
+    }
+}
+
+fn set_relay(relay: &mut dyn OutputPin<Error = Infallible>, on: bool) {
+    if on != ACTIVE_LOW {
+        relay.set_high().ok();
+    } else {
+        relay.set_low().ok();
+    }
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Relay sequencer example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    // Switch the relays off before anything else.
+    let mut relay1 = gpiob.pb12.into_push_pull_output(&mut gpiob.crh);
+    let mut relay2 = gpiob.pb13.into_push_pull_output(&mut gpiob.crh);
+    let mut relay3 = gpiob.pb14.into_push_pull_output(&mut gpiob.crh);
+    let mut relay4 = gpiob.pb15.into_push_pull_output(&mut gpiob.crh);
+    let mut relay_pins: [&mut dyn OutputPin<Error = Infallible>; 4] =
+        [&mut relay1, &mut relay2, &mut relay3, &mut relay4];
+    for pin in relay_pins.iter_mut() {
+        set_relay(&mut **pin, false);
+    }
+
+    // The normally-closed switch pulls the input low while everything is fine.
+    let safety_input = gpioa.pa0.into_pull_up_input(&mut gpioa.crl);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut sequencer = Sequencer::new(&RELAYS, monotonic::millis());
+    let mut sequence_start_ms = monotonic::millis();
+    let mut next_step = 0;
+    let mut lines: [String<32>; 5] = Default::default();
+    let mut scheduler = Scheduler::new([10, 250, 500]);
+    let mut led_on = false;
+    loop {
+        match scheduler.poll() {
+            Some(CONTROL) => {
+                let now_ms = monotonic::millis();
+                let stop = safety_input.is_high().unwrap();
+                if stop != sequencer.safety_stop {
+                    log_info!("Safety stop {}", if stop { "active" } else { "released" });
+                    sequencer.set_safety_stop(stop, now_ms);
+                    sequence_start_ms = now_ms;
+                    next_step = 0;
+                }
+                if !stop {
+                    let elapsed = now_ms.wrapping_sub(sequence_start_ms);
+                    if elapsed >= SEQUENCE_MS {
+                        sequence_start_ms = sequence_start_ms.wrapping_add(SEQUENCE_MS);
+                        next_step = 0;
+                    }
+                    let elapsed = now_ms.wrapping_sub(sequence_start_ms);
+                    while next_step < SEQUENCE.len() && SEQUENCE[next_step].at_ms <= elapsed {
+                        let step = &SEQUENCE[next_step];
+                        sequencer.request(step.relay, step.on);
+                        next_step += 1;
+                    }
+                }
+                sequencer.update(now_ms);
+                for (index, pin) in relay_pins.iter_mut().enumerate() {
+                    set_relay(&mut **pin, sequencer.is_on(index));
+                }
+            }
+            Some(DISPLAY) => {
+                let now_ms = monotonic::millis();
+                for line in lines.iter_mut() {
+                    line.clear();
+                }
+                if sequencer.safety_stop {
+                    write!(lines[0], "SAFETY STOP").unwrap();
+                } else {
+                    let elapsed = now_ms.wrapping_sub(sequence_start_ms);
+                    write!(
+                        lines[0],
+                        "Sequence {}/{}s",
+                        elapsed / 1000,
+                        SEQUENCE_MS / 1000
+                    )
+                    .unwrap();
+                }
+                for (index, line) in lines[1..].iter_mut().enumerate() {
+                    sequencer.describe(index, now_ms, line).unwrap();
+                }
+                disp.clear();
+                for (i, line) in lines.iter().enumerate() {
+                    Text::new(line, Point::new(0, i as i32 * 12))
+                        .into_styled(text_style)
+                        .draw(&mut disp)
+                        .unwrap();
+                }
+                disp.flush().unwrap();
+            }
+            Some(BLINK) => {
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                led_on = !led_on;
+                if led_on {
+                    led.set_high().unwrap();
+                } else {
+                    led.set_low().unwrap();
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Measure an unknown resistor against a reference resistor with an ADS1115
+//! analog/digital converter, and show its value, the closest value of the
+//! E24 series and the color code of that value on an SSD1306 OLED display.
+//!
+//! The unknown resistor Rx and the reference resistor form a voltage divider
+//! from 3.3V to GND. The same current flows through both, so
+//! `Rx = Rref * Vx / Vref`. The ADS1115 measures the voltage across each
+//! resistor in differential mode: across Rx on A0-A1 and across the
+//! reference on A2-A3. Only the ratio matters, so neither the supply voltage
+//! nor the voltage reference of the ADS1115 need to be exact.
+//!
+//! Each voltage is read with the largest gain at which it does not
+//! saturate: the reading starts in the 4.096V range and goes to the next
+//! smaller range as long as the value is below half of the full scale.
+//!
+//! The E24 series has 24 values per decade, the ones of the common 5%
+//! resistors. The closest one is found by the smallest ratio to the measured
+//! value. It is drawn as a resistor with its four color bands: two digits,
+//! the multiplier and gold for 5% tolerance. The names of the colors are
+//! written below the bands, since the display has only one color.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> ADS1115 <-> Resistors       <-> Display
+//! GND  <-> GND     <-> Rx end 2        <-> GND
+//! 3.3V <-> VDD     <-> Reference end 1 <-> VDD
+//!          A0      <-> Rx end 1, Reference end 2
+//!          A1      <-> Rx end 2
+//!          A2      <-> Reference end 1
+//!          A3      <-> Reference end 2
+//! PB8  <-> SCL                         <-> SCL
+//! PB9  <-> SDA                         <-> SDA
+//! ```
+//!
+//! Set `REFERENCE_OHMS` to the measured value of the reference resistor.
+//! With 10K the range is about 10 ohm to 1M. Above that the input impedance
+//! of the ADS1115 lowers the readings: use a larger reference resistor for
+//! large values.
+//!
+//! Run with:
+//! `cargo embed --example resistor-sorter-ads1115-e24-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use ads1x1x::{channel as AdcChannel, Ads1x1x, FullScaleRange, SlaveAddr};
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    i2c_speed, log_error, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, Rectangle},
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use nb::block;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*};
+
+const REFERENCE_OHMS: f32 = 10_000.0;
+/// Smaller values are shown as a short circuit.
+const MIN_OHMS: f32 = 1.0;
+/// Larger values are shown as an open circuit (no resistor).
+const MAX_OHMS: f32 = 10_000_000.0;
+/// Tolerance of the E24 series in percent
+const TOLERANCE_PERCENT: f32 = 5.0;
+
+/// Full scale ranges of the ADS1115 from the largest, with their full scale
+/// in microvolts
+const GAINS: [(FullScaleRange, f32); 5] = [
+    (FullScaleRange::Within4_096V, 4_096_000.0),
+    (FullScaleRange::Within2_048V, 2_048_000.0),
+    (FullScaleRange::Within1_024V, 1_024_000.0),
+    (FullScaleRange::Within0_512V, 512_000.0),
+    (FullScaleRange::Within0_256V, 256_000.0),
+];
+/// Readings below this use the next smaller range: half of the full scale.
+const GAIN_UP_BELOW: i16 = 16384;
+
+/// Two significant digits of the E24 series
+const E24: [u8; 24] = [
+    10, 11, 12, 13, 15, 16, 18, 20, 22, 24, 27, 30, 33, 36, 39, 43, 47, 51, 56, 62, 68, 75, 82, 91,
+];
+const COLORS: [&str; 10] = [
+    "BLK", "BRN", "RED", "ORG", "YEL", "GRN", "BLU", "VIO", "GRY", "WHT",
+];
+const GOLD: &str = "GLD";
+const SILVER: &str = "SLV";
+
+// Resistor drawing
+const BODY_LEFT: i32 = 16;
+const BODY_RIGHT: i32 = 112;
+const BODY_TOP: i32 = 26;
+const BODY_HEIGHT: i32 = 14;
+const BAND_WIDTH: i32 = 6;
+const BANDS_X: [i32; 4] = [28, 52, 76, 100];
+
+/// E24 value: `digits * 10^exponent` ohm
+#[derive(Debug, Clone, Copy)]
+struct Nominal {
+    digits: u8,
+    exponent: i8,
+}
+
+impl Nominal {
+    /// Closest E24 value to `ohms`
+    fn nearest(ohms: f32) -> Self {
+        // Scale to two digits [10-100).
+        let mut exponent = libm::floorf(libm::log10f(ohms)) as i8 - 1;
+        let scaled = ohms / libm::powf(10.0, f32::from(exponent));
+        let error = |digits: u8| libm::fabsf(libm::logf(scaled / f32::from(digits)));
+        // 100 is 10 of the next decade.
+        let mut digits = E24
+            .iter()
+            .copied()
+            .chain(core::iter::once(100))
+            .min_by(|a, b| error(*a).partial_cmp(&error(*b)).unwrap())
+            .unwrap();
+        if digits == 100 {
+            digits = 10;
+            exponent += 1;
+        }
+        Nominal { digits, exponent }
+    }
+
+    fn ohms(&self) -> f32 {
+        f32::from(self.digits) * libm::powf(10.0, f32::from(self.exponent))
+    }
+
+    /// Names of the four color bands, `None` if the multiplier has no color.
+    fn bands(&self) -> Option<[&'static str; 4]> {
+        let multiplier = match self.exponent {
+            -2 => SILVER,
+            -1 => GOLD,
+            exponent @ 0..=9 => COLORS[exponent as usize],
+            _ => return None,
+        };
+        Some([
+            COLORS[usize::from(self.digits / 10)],
+            COLORS[usize::from(self.digits % 10)],
+            multiplier,
+            GOLD,
+        ])
+    }
+}
+
+fn write_ohms<W: Write>(w: &mut W, ohms: f32) -> core::fmt::Result {
+    if ohms < 1e3 {
+        write!(w, "{:.1} Ohm", ohms)
+    } else if ohms < 1e6 {
+        write!(w, "{:.2} kOhm", ohms / 1e3)
+    } else {
+        write!(w, "{:.2} MOhm", ohms / 1e6)
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Resistor sorter example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // ADS1115 ADC and display
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x48, 0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+    let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+    let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+
+    let mut adc = Ads1x1x::new_ads1115(manager.acquire(), SlaveAddr::default());
+
+    let mut lines: [String<32>; 2] = Default::default();
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+
+        // Voltages across Rx and the reference in microvolts
+        let mut microvolts = [0.0; 2];
+        for (pair, uv) in microvolts.iter_mut().enumerate() {
+            for (i, (range, full_scale)) in GAINS.iter().enumerate() {
+                adc.set_full_scale_range(*range).unwrap();
+                let value = if pair == 0 {
+                    block!(adc.read(&mut AdcChannel::DifferentialA0A1))
+                } else {
+                    block!(adc.read(&mut AdcChannel::DifferentialA2A3))
+                };
+                let value = match value {
+                    Ok(value) => value,
+                    Err(e) => {
+                        log_error!(every = 20, "ADC error: {:?}", e);
+                        0
+                    }
+                };
+                *uv = f32::from(value) * full_scale / 32768.0;
+                if value.abs() >= GAIN_UP_BELOW || i + 1 == GAINS.len() {
+                    break;
+                }
+            }
+        }
+        let (rx_uv, reference_uv) = (microvolts[0], microvolts[1]);
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        disp.clear();
+        let ohms = if reference_uv > 0.0 {
+            REFERENCE_OHMS * rx_uv / reference_uv
+        } else {
+            MAX_OHMS
+        };
+        if ohms >= MAX_OHMS {
+            write!(lines[0], "Insert a resistor").unwrap();
+        } else if ohms < MIN_OHMS {
+            write!(lines[0], "Short circuit").unwrap();
+        } else {
+            let nominal = Nominal::nearest(ohms);
+            let deviation = (ohms / nominal.ohms() - 1.0) * 100.0;
+            log_info!(
+                "Rx: {} ohm, E24: {}e{}, {:.1}%",
+                ohms,
+                nominal.digits,
+                nominal.exponent,
+                deviation
+            );
+            write!(lines[0], "R = ").unwrap();
+            write_ohms(&mut lines[0], ohms).unwrap();
+            write!(lines[1], "E24 ").unwrap();
+            write_ohms(&mut lines[1], nominal.ohms()).unwrap();
+            // Mark values outside of the tolerance of the nominal value.
+            if libm::fabsf(deviation) > TOLERANCE_PERCENT {
+                write!(lines[1], " {:+.0}%!", deviation).unwrap();
+            } else {
+                write!(lines[1], " {:+.1}%", deviation).unwrap();
+            }
+
+            if let Some(bands) = nominal.bands() {
+                // Leads, body and the bands with their names below
+                let middle = BODY_TOP + BODY_HEIGHT / 2;
+                for (start, end) in [(0, BODY_LEFT), (BODY_RIGHT, 127)].iter() {
+                    Line::new(Point::new(*start, middle), Point::new(*end, middle))
+                        .into_styled(outline)
+                        .draw(&mut disp)
+                        .unwrap();
+                }
+                Rectangle::new(
+                    Point::new(BODY_LEFT, BODY_TOP),
+                    Point::new(BODY_RIGHT, BODY_TOP + BODY_HEIGHT),
+                )
+                .into_styled(outline)
+                .draw(&mut disp)
+                .unwrap();
+                for (x, name) in BANDS_X.iter().zip(bands.iter()) {
+                    Rectangle::new(
+                        Point::new(*x, BODY_TOP),
+                        Point::new(x + BAND_WIDTH - 1, BODY_TOP + BODY_HEIGHT),
+                    )
+                    .into_styled(fill)
+                    .draw(&mut disp)
+                    .unwrap();
+                    // Center the 18 pixel wide name below the band.
+                    Text::new(
+                        name,
+                        Point::new(x + BAND_WIDTH / 2 - 9, BODY_TOP + BODY_HEIGHT + 6),
+                    )
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+                }
+            }
+        }
+
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 12))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        delay.delay_ms(400_u16);
+    }
+}
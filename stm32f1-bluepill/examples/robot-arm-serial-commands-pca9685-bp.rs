@@ -0,0 +1,434 @@
+//! Control a 6-servo robotic arm (or a crane) connected to a PCA9685 with
+//! simple text commands over a serial port.
+//!
+//! Connect with a serial terminal at 115200 baud and send one command per
+//! line:
+//! - `J1 90`: move joint 1 to 90 degrees. The joints are numbered 1-6.
+//! - `POSE home`: move all joints to a pose from `POSES`.
+//! - `SPEED 50`: set the speed in percent of `MAX_DEG_PER_S`.
+//! - `STOP`: stop all joints where they are.
+//! - `STATUS`: print the position of all joints.
+//!
+//! Every command is answered with a line starting with `OK` or `ERR`.
+//!
+//! The joints move with the `Motion` from the `easing` module of this crate,
+//! so that they start and stop smoothly. When moving to a pose, all joints
+//! arrive at the same time.
+//!
+//! Each joint is configured in `JOINTS` with its PCA9685 channel, the range
+//! of angles it may move to without hitting the arm or the table, and the
+//! pulse widths for 0 and 180 degrees of its servo. Commands beyond the
+//! range are rejected.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and USART1.
+//!
+//! ```
+//! BP   <-> PCA9685 <-> USB-serial adapter
+//! GND  <-> GND     <-> GND
+//! 3.3V <-> VCC
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! GND  <-> OE
+//! PA9              <-> RX
+//! PA10             <-> TX
+//!          V+ <-> +5V
+//!          Channels 0-5 <-> Servos of joints 1-6
+//! ```
+//!
+//! Six servos can draw several amps when moving together. Power them from
+//! a separate 5V supply.
+//!
+//! Run with:
+//! `cargo embed --example robot-arm-serial-commands-pca9685-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    easing::Motion,
+    i2c_speed, log_info, panic_display as _,
+    uart::{LineBuffer, RxBuffer, RxReader, TxBuffer, TxWriter, UartRx, UartTx},
+};
+use embedded_hal::digital::v2::OutputPin;
+use pwm_pca9685::{Address, Channel, Pca9685};
+use rtic::app;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    gpio::{
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    serial::{Config, Rx, Serial, Tx},
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const BAUD_RATE: u32 = 115_200;
+const UPDATE_MS: u32 = 20;
+/// Servo period in microseconds (50Hz)
+const PERIOD_US: u32 = 20_000;
+/// Speed of the joints at `SPEED 100`
+const MAX_DEG_PER_S: f32 = 180.0;
+const DEFAULT_SPEED_PERCENT: u32 = 30;
+const RX_BUFFER: usize = 64;
+const TX_BUFFER: usize = 256;
+const LINE_LEN: usize = 32;
+
+/// Servo of a joint
+struct Joint {
+    name: &'static str,
+    channel: Channel,
+    /// Range of angles in degrees the joint may move to
+    min_deg: f32,
+    max_deg: f32,
+    /// Pulse width at 0 degrees
+    min_us: u16,
+    /// Pulse width at 180 degrees
+    max_us: u16,
+}
+
+impl Joint {
+    fn pulse_us(&self, deg: f32) -> u16 {
+        let span = f32::from(self.max_us - self.min_us);
+        self.min_us + (deg.clamp(self.min_deg, self.max_deg) / 180.0 * span) as u16
+    }
+}
+
+const JOINTS: [Joint; 6] = [
+    Joint {
+        name: "base",
+        channel: Channel::C0,
+        min_deg: 0.0,
+        max_deg: 180.0,
+        min_us: 500,
+        max_us: 2500,
+    },
+    Joint {
+        name: "shoulder",
+        channel: Channel::C1,
+        min_deg: 15.0,
+        max_deg: 165.0,
+        min_us: 500,
+        max_us: 2500,
+    },
+    Joint {
+        name: "elbow",
+        channel: Channel::C2,
+        min_deg: 0.0,
+        max_deg: 150.0,
+        min_us: 500,
+        max_us: 2500,
+    },
+    Joint {
+        name: "wrist pitch",
+        channel: Channel::C3,
+        min_deg: 0.0,
+        max_deg: 180.0,
+        min_us: 500,
+        max_us: 2500,
+    },
+    Joint {
+        name: "wrist roll",
+        channel: Channel::C4,
+        min_deg: 0.0,
+        max_deg: 180.0,
+        min_us: 500,
+        max_us: 2500,
+    },
+    Joint {
+        name: "gripper",
+        channel: Channel::C5,
+        min_deg: 30.0,
+        max_deg: 110.0,
+        min_us: 500,
+        max_us: 2500,
+    },
+];
+
+/// Named poses with the angle of every joint. The arm starts in the first
+/// one.
+const POSES: [(&str, [f32; 6]); 3] = [
+    ("home", [90.0, 90.0, 90.0, 90.0, 90.0, 70.0]),
+    ("rest", [90.0, 30.0, 140.0, 60.0, 90.0, 70.0]),
+    ("reach", [90.0, 120.0, 60.0, 120.0, 90.0, 40.0]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    /// Move a joint (index into `JOINTS`) to an angle
+    Joint(usize, f32),
+    /// Move to a pose (index into `POSES`)
+    Pose(usize),
+    Speed(u32),
+    Stop,
+    Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CommandError {
+    UnknownCommand,
+    InvalidJoint,
+    InvalidNumber,
+    /// The angle is beyond the range of the joint
+    OutOfRange {
+        min: f32,
+        max: f32,
+    },
+    UnknownPose,
+    MissingArgument,
+}
+
+fn parse(line: &str) -> Result<Command, CommandError> {
+    let mut words = line.split_whitespace();
+    let command = words.next().ok_or(CommandError::UnknownCommand)?;
+    let argument = words.next();
+    if command.eq_ignore_ascii_case("STOP") {
+        return Ok(Command::Stop);
+    }
+    if command.eq_ignore_ascii_case("STATUS") {
+        return Ok(Command::Status);
+    }
+    let argument = argument.ok_or(CommandError::MissingArgument)?;
+    if command.eq_ignore_ascii_case("POSE") {
+        return POSES
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(argument))
+            .map(Command::Pose)
+            .ok_or(CommandError::UnknownPose);
+    }
+    if command.eq_ignore_ascii_case("SPEED") {
+        return match argument.parse() {
+            Ok(speed) if (1..=100).contains(&speed) => Ok(Command::Speed(speed)),
+            _ => Err(CommandError::InvalidNumber),
+        };
+    }
+    if command.starts_with(&['J', 'j'][..]) {
+        let joint = match command[1..].parse::<usize>() {
+            Ok(number) if (1..=JOINTS.len()).contains(&number) => number - 1,
+            _ => return Err(CommandError::InvalidJoint),
+        };
+        let deg: f32 = argument.parse().map_err(|_| CommandError::InvalidNumber)?;
+        let Joint {
+            min_deg, max_deg, ..
+        } = JOINTS[joint];
+        if !(min_deg..=max_deg).contains(&deg) {
+            return Err(CommandError::OutOfRange {
+                min: min_deg,
+                max: max_deg,
+            });
+        }
+        return Ok(Command::Joint(joint, deg));
+    }
+    Err(CommandError::UnknownCommand)
+}
+
+/// Time to move `distance_deg` at the given speed
+fn duration_ms(distance_deg: f32, speed_percent: u32) -> u32 {
+    let deg_per_s = MAX_DEG_PER_S * speed_percent as f32 / 100.0;
+    (libm::fabsf(distance_deg) / deg_per_s * 1000.0) as u32
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        uart_rx: UartRx<Rx<pac::USART1>, RX_BUFFER>,
+        uart_tx: UartTx<Tx<pac::USART1>, TX_BUFFER>,
+        reader: RxReader<RX_BUFFER>,
+        writer: TxWriter<TX_BUFFER>,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Robot arm example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+        let rx = gpioa.pa10;
+        let serial = Serial::usart1(
+            device.USART1,
+            (tx, rx),
+            &mut afio.mapr,
+            Config::default().baudrate(BAUD_RATE.bps()),
+            clocks,
+            &mut rcc.apb2,
+        );
+        let (tx, mut rx) = serial.split();
+        rx.listen();
+        let (uart_rx, reader) = singleton!(: RxBuffer<RX_BUFFER> = RxBuffer::new())
+            .unwrap()
+            .split(rx);
+        let (uart_tx, writer) = singleton!(: TxBuffer<TX_BUFFER> = TxBuffer::new())
+            .unwrap()
+            .split(tx, || rtic::pend(pac::Interrupt::USART1));
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            // PCA9685
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x40]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            uart_rx,
+            uart_tx,
+            reader,
+            writer,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    /// Move the received bytes into the buffer and send the waiting ones.
+    #[task(binds = USART1, priority = 2, resources = [uart_rx, uart_tx])]
+    fn usart1(cx: usart1::Context) {
+        cx.resources.uart_rx.on_interrupt();
+        let uart_tx = cx.resources.uart_tx;
+        if uart_tx.on_interrupt() {
+            uart_tx.tx().listen();
+        } else {
+            uart_tx.tx().unlisten();
+        }
+    }
+
+    #[idle(resources = [reader, writer, i2c, led])]
+    fn idle(cx: idle::Context) -> ! {
+        let reader = cx.resources.reader;
+        let tx = cx.resources.writer;
+        let mut line_buffer: LineBuffer<LINE_LEN> = LineBuffer::new();
+        let i2c = cx.resources.i2c.take().unwrap();
+        let mut pwm = Pca9685::new(i2c, Address::default()).unwrap();
+        pwm.enable().unwrap();
+        // 25MHz / (4096 * 50Hz) - 1
+        pwm.set_prescale(121).unwrap();
+
+        let (_, home) = POSES[0];
+        let mut motions = [Motion::new(0.0); 6];
+        for (motion, deg) in motions.iter_mut().zip(home.iter()) {
+            *motion = Motion::new(*deg);
+        }
+        let mut speed_percent = DEFAULT_SPEED_PERCENT;
+        writeln!(tx, "OK ready").unwrap();
+
+        let mut last_update = DWT::get_cycle_count();
+        let mut updates = 0_u32;
+        loop {
+            for line in reader.lines(&mut line_buffer) {
+                log_info!("Command: {}", line);
+                match parse(&line) {
+                    Ok(Command::Joint(joint, deg)) => {
+                        let motion = &mut motions[joint];
+                        let duration = duration_ms(deg - motion.position(), speed_percent);
+                        motion.start(deg, duration);
+                        writeln!(tx, "OK J{} {:.1}", joint + 1, deg).unwrap();
+                    }
+                    Ok(Command::Pose(pose)) => {
+                        // Move all joints in the time the farthest one needs.
+                        let (name, angles) = POSES[pose];
+                        let duration = motions
+                            .iter()
+                            .zip(angles.iter())
+                            .map(|(motion, deg)| {
+                                duration_ms(deg - motion.position(), speed_percent)
+                            })
+                            .max()
+                            .unwrap_or(0);
+                        for (motion, deg) in motions.iter_mut().zip(angles.iter()) {
+                            motion.start(*deg, duration);
+                        }
+                        writeln!(tx, "OK POSE {} {}ms", name, duration).unwrap();
+                    }
+                    Ok(Command::Speed(speed)) => {
+                        speed_percent = speed;
+                        writeln!(tx, "OK SPEED {}", speed).unwrap();
+                    }
+                    Ok(Command::Stop) => {
+                        for motion in motions.iter_mut() {
+                            motion.stop();
+                        }
+                        writeln!(tx, "OK STOP").unwrap();
+                    }
+                    Ok(Command::Status) => {
+                        for (i, (joint, motion)) in JOINTS.iter().zip(motions.iter()).enumerate() {
+                            writeln!(
+                                tx,
+                                "J{} {:.1} -> {:.1} ({})",
+                                i + 1,
+                                motion.position(),
+                                motion.target(),
+                                joint.name
+                            )
+                            .unwrap();
+                        }
+                        writeln!(tx, "OK SPEED {}", speed_percent).unwrap();
+                    }
+                    Err(CommandError::OutOfRange { min, max }) => {
+                        writeln!(tx, "ERR out of range {:.0}-{:.0}", min, max).unwrap()
+                    }
+                    Err(e) => writeln!(tx, "ERR {:?}", e).unwrap(),
+                }
+            }
+
+            if DWT::get_cycle_count().wrapping_sub(last_update) < UPDATE_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_update = last_update.wrapping_add(UPDATE_MS * 1000 * SYSCLK_MHZ);
+
+            for (joint, motion) in JOINTS.iter().zip(motions.iter_mut()) {
+                let pulse_us = joint.pulse_us(motion.update(UPDATE_MS));
+                let off = (u32::from(pulse_us) * 4096 / PERIOD_US) as u16;
+                pwm.set_channel_on_off(joint.channel, 0, off).unwrap();
+            }
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            updates += 1;
+            if updates % 50 == 0 {
+                cx.resources.led.set_low().unwrap();
+            } else if updates % 50 == 5 {
+                cx.resources.led.set_high().unwrap();
+            }
+        }
+    }
+};
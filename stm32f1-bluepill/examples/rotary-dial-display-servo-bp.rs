@@ -0,0 +1,230 @@
+//! Dial numbers on an old rotary telephone dial to change the page shown
+//! on an SSD1306 OLED display and to move a servo to preset positions.
+//!
+//! The pulse contact of the dial is polled in the main loop and fed into
+//! the `RotaryDialDecoder` from this crate together with the milliseconds
+//! of the `monotonic` module. The decoder debounces the contact, counts the
+//! pulses and returns every digit dialed. A number is complete once no
+//! digit was dialed for `NUMBER_TIMEOUT_MS`:
+//! - `1`, `2`, `3`: show page 1, 2 or 3
+//! - `4` and a digit: move the servo to 20 degrees times the digit, e.g.
+//!   `40` to 0 and `49` to 180 degrees
+//!
+//! The servo is driven with a PCA9685 on the same I2C bus as the display.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> Rotary dial   <-> Display <-> PCA9685
+//! GND  <-> Pulse contact <-> GND     <-> GND
+//! PA1  <-> Pulse contact
+//! 3.3V                   <-> VDD     <-> VCC
+//! PB8                    <-> SCL     <-> SCL
+//! PB9                    <-> SDA     <-> SDA
+//! GND                                <-> OE
+//!                                        V+ <-> +5V
+//!                                        Channel 0 <-> Servo
+//! ```
+//!
+//! The pulse contact is usually between the two wires which are shorted
+//! while the dial is at rest. Use an ohmmeter to find them.
+//!
+//! Run with:
+//! `cargo embed --example rotary-dial-display-servo-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_info, log_warn, monotonic,
+    panic_display::{self, Bus},
+    rotary_dial::RotaryDialDecoder,
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::{String, Vec};
+use pwm_pca9685::{Address, Channel, Pca9685};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{pac, prelude::*};
+
+const PAGES: u8 = 3;
+const NUMBER_TIMEOUT_MS: u32 = 2000;
+const MAX_DIGITS: usize = 4;
+
+// You need to tweak these min/max values for your servos as these may vary.
+// Be careful when doing this. Incorrect values can permanently damage your servos.
+const SERVO_MIN: u16 = 132; // pulse length for 0 degrees
+const SERVO_MAX: u16 = 608; // pulse length for 180 degrees
+
+/// What a number dialed does
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Page(u8),
+    Servo(u32),
+    Unknown,
+}
+
+fn action(digits: &[u8]) -> Action {
+    match *digits {
+        [page @ 1..=PAGES] => Action::Page(page - 1),
+        [4, digit] => Action::Servo(u32::from(digit) * 20),
+        _ => Action::Unknown,
+    }
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Rotary dial example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let pulse_contact = gpioa.pa1.into_pull_up_input(&mut gpioa.crl);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // PCA9685 and display
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x40, 0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    led.set_high().unwrap();
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut pwm = Pca9685::new(manager.acquire(), Address::default()).unwrap();
+    pwm.enable().unwrap();
+    // About 60Hz
+    pwm.set_prescale(100).unwrap();
+
+    let mut decoder = RotaryDialDecoder::new();
+    let mut digits: Vec<u8, MAX_DIGITS> = Vec::new();
+    let mut last_digit_ms = 0;
+    let mut last_number: Vec<u8, MAX_DIGITS> = Vec::new();
+    let mut last_action = None;
+    let mut page = 0;
+    let mut angle = 90;
+    let mut lines: [String<32>; 4] = Default::default();
+    let mut redraw = true;
+    loop {
+        let now = monotonic::millis();
+        match decoder.update(pulse_contact.is_high().unwrap(), now) {
+            Some(Ok(digit)) => {
+                log_info!("Digit {}", digit);
+                if digits.push(digit).is_err() {
+                    log_warn!("Too many digits");
+                }
+                last_digit_ms = now;
+                led.set_low().unwrap();
+                redraw = true;
+            }
+            Some(Err(e)) => log_warn!("Dial error: {:?}", e),
+            None => (),
+        }
+        if !digits.is_empty() && now.wrapping_sub(last_digit_ms) >= NUMBER_TIMEOUT_MS {
+            let number_action = action(&digits);
+            log_info!("Number {:?}: {:?}", digits.as_slice(), number_action);
+            match number_action {
+                Action::Page(p) => page = p,
+                Action::Servo(a) => angle = a,
+                Action::Unknown => (),
+            }
+            last_number = core::mem::take(&mut digits);
+            last_action = Some(number_action);
+            led.set_high().unwrap();
+            redraw = true;
+        }
+        // Writing to the display takes long enough to miss pulses.
+        if !redraw || !decoder.is_idle() {
+            continue;
+        }
+        redraw = false;
+
+        let pulse = SERVO_MIN + ((SERVO_MAX - SERVO_MIN) as u32 * angle / 180) as u16;
+        pwm.set_channel_on_off(Channel::C0, 0, pulse).unwrap();
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        write!(lines[0], "Dialing: ").unwrap();
+        for digit in digits.iter() {
+            write!(lines[0], "{}", digit).unwrap();
+        }
+        match page {
+            0 => {
+                write!(lines[1], "Last number: ").unwrap();
+                for digit in last_number.iter() {
+                    write!(lines[1], "{}", digit).unwrap();
+                }
+                match last_action {
+                    Some(Action::Page(p)) => write!(lines[2], "Page {}", p + 1).unwrap(),
+                    Some(Action::Servo(a)) => write!(lines[2], "Servo {} deg", a).unwrap(),
+                    Some(Action::Unknown) => write!(lines[2], "Unknown number").unwrap(),
+                    None => write!(lines[2], "Nothing dialed").unwrap(),
+                }
+            }
+            1 => {
+                write!(lines[1], "Servo").unwrap();
+                write!(lines[2], "Angle: {} deg", angle).unwrap();
+                write!(lines[3], "Pulse: {}", pulse).unwrap();
+            }
+            _ => {
+                write!(lines[1], "1, 2, 3: page").unwrap();
+                write!(lines[2], "40 - 49: servo").unwrap();
+                write!(lines[3], "  0 - 180 deg").unwrap();
+            }
+        }
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+    }
+}
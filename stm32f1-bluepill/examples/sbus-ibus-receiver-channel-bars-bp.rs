@@ -0,0 +1,372 @@
+//! Decode the SBUS or IBUS output of an RC receiver and show the channels
+//! as bars on an SSD1306 OLED display.
+//!
+//! Select the protocol of your receiver in `PROTOCOL`:
+//! - SBUS (FrSky, Futaba, ...): 16 channels. The bottom line shows when the
+//!   receiver reports lost frames and when it is in failsafe.
+//! - IBUS (FlySky): 14 channels. There are no failsafe flags.
+//!
+//! With both protocols the display shows "NO SIGNAL" when no valid frame
+//! arrives for `SIGNAL_TIMEOUT_MS`.
+//!
+//! The bytes are received in the USART1 interrupt and fed to the decoders
+//! in the `sbus` and `ibus` modules of this crate. A gap between the bytes
+//! marks the start of a frame.
+//!
+//! SBUS uses inverted levels. The USART of the STM32F1 cannot invert its
+//! input (the RXINV option only exists on newer families like the STM32F3),
+//! so SBUS needs an inverter in front of PA10: for example an NPN
+//! transistor with a 10kΩ resistor from SBUS to its base, its emitter to
+//! GND and its collector to PA10 with a 10kΩ pull-up to 3.3V. Some
+//! receivers also have an uninverted SBUS output which can be connected
+//! directly. IBUS needs no inverter.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and USART1.
+//!
+//! ```
+//! BP   <-> Receiver          <-> Display
+//! GND  <-> GND               <-> GND
+//! 5V   <-> VCC
+//! 3.3V                       <-> VDD
+//! PA10 <-> SBUS (inverted) or IBUS servo output
+//! PB8                        <-> SCL
+//! PB9                        <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example sbus-ibus-receiver-channel-bars-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed,
+    ibus::{self, IbusDecoder},
+    log_info,
+    panic_display::{self, Bus},
+    sbus::{self, SbusDecoder},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::{digital::v2::OutputPin, serial::Read};
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    serial::{Config, Rx, Serial, StopBits},
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Protocol {
+    Sbus,
+    Ibus,
+}
+
+const PROTOCOL: Protocol = Protocol::Sbus;
+
+const SYSCLK_MHZ: u32 = 72;
+/// A new frame starts after a pause of this length.
+const GAP_US: u32 = 1000;
+const SIGNAL_TIMEOUT_MS: u32 = 100;
+const UPDATE_MS: u32 = 50;
+/// Height of the bars, the bottom line is for the status.
+const BAR_HEIGHT: i32 = 48;
+
+/// Decoder for the selected protocol
+pub enum Decoder {
+    Sbus(SbusDecoder),
+    Ibus(IbusDecoder),
+}
+
+/// Last valid frame of either protocol
+#[derive(Debug, Clone, Copy)]
+struct Channels {
+    /// Servo pulse widths in microseconds
+    us: [u16; sbus::CHANNELS],
+    count: usize,
+    frame_lost: bool,
+    failsafe: bool,
+}
+
+impl Decoder {
+    fn gap(&mut self) {
+        match self {
+            Decoder::Sbus(decoder) => decoder.gap(),
+            Decoder::Ibus(decoder) => decoder.gap(),
+        }
+    }
+
+    fn byte(&mut self, byte: u8) -> Option<Channels> {
+        let mut us = [0; sbus::CHANNELS];
+        match self {
+            Decoder::Sbus(decoder) => decoder.byte(byte).map(|frame| {
+                for (us, value) in us.iter_mut().zip(frame.channels.iter()) {
+                    *us = sbus::to_us(*value);
+                }
+                Channels {
+                    us,
+                    count: sbus::CHANNELS,
+                    frame_lost: frame.frame_lost,
+                    failsafe: frame.failsafe,
+                }
+            }),
+            Decoder::Ibus(decoder) => decoder.byte(byte).map(|channels| {
+                us[..ibus::CHANNELS].copy_from_slice(&channels);
+                Channels {
+                    us,
+                    count: ibus::CHANNELS,
+                    frame_lost: false,
+                    failsafe: false,
+                }
+            }),
+        }
+    }
+}
+
+/// Received frames shared with the idle task
+pub struct Reception {
+    last: Option<Channels>,
+    last_frame: u32,
+    frames: u32,
+    lost_frames: u32,
+    errors: u32,
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        rx: Rx<pac::USART1>,
+        decoder: Decoder,
+        last_byte: u32,
+        reception: Reception,
+        // Taken by the idle task, which creates the display driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("SBUS/IBUS receiver example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let (config, decoder) = match PROTOCOL {
+            // 8 data bits, even parity and 2 stop bits
+            Protocol::Sbus => (
+                Config::default()
+                    .baudrate(sbus::BAUD_RATE.bps())
+                    .parity_even()
+                    .stopbits(StopBits::STOP2),
+                Decoder::Sbus(SbusDecoder::new()),
+            ),
+            Protocol::Ibus => (
+                Config::default().baudrate(ibus::BAUD_RATE.bps()),
+                Decoder::Ibus(IbusDecoder::new()),
+            ),
+        };
+        // TX is not used.
+        let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+        let rx = gpioa.pa10;
+        let serial = Serial::usart1(
+            device.USART1,
+            (tx, rx),
+            &mut afio.mapr,
+            config,
+            clocks,
+            &mut rcc.apb2,
+        );
+        let (_tx, mut rx) = serial.split();
+        rx.listen();
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        let now = DWT::get_cycle_count();
+        init::LateResources {
+            rx,
+            decoder,
+            last_byte: now,
+            reception: Reception {
+                last: None,
+                last_frame: now.wrapping_sub(SIGNAL_TIMEOUT_MS * 1000 * SYSCLK_MHZ),
+                frames: 0,
+                lost_frames: 0,
+                errors: 0,
+            },
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = USART1, priority = 2, resources = [rx, decoder, last_byte, reception])]
+    fn receive(cx: receive::Context) {
+        let decoder = cx.resources.decoder;
+        let reception = cx.resources.reception;
+        let now = DWT::get_cycle_count();
+        if now.wrapping_sub(*cx.resources.last_byte) > GAP_US * SYSCLK_MHZ {
+            decoder.gap();
+        }
+        *cx.resources.last_byte = now;
+        match cx.resources.rx.read() {
+            Ok(byte) => {
+                if let Some(channels) = decoder.byte(byte) {
+                    reception.frames = reception.frames.wrapping_add(1);
+                    if channels.frame_lost {
+                        reception.lost_frames = reception.lost_frames.wrapping_add(1);
+                    }
+                    reception.last = Some(channels);
+                    reception.last_frame = now;
+                }
+            }
+            // Parity, framing or overrun error: wait for the next frame.
+            Err(nb::Error::Other(_)) => {
+                reception.errors = reception.errors.wrapping_add(1);
+                decoder.gap();
+            }
+            Err(nb::Error::WouldBlock) => (),
+        }
+    }
+
+    #[idle(resources = [reception, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let i2c = cx.resources.i2c.take().unwrap();
+        let interface = I2CDIBuilder::new().init(i2c);
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+        let bar_style = PrimitiveStyle::with_fill(BinaryColor::On);
+        let center_style = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+
+        let mut status: String<32> = String::new();
+        let mut last_update = DWT::get_cycle_count();
+        let mut last_frames = 0;
+        let mut updates = 0_u32;
+        loop {
+            if DWT::get_cycle_count().wrapping_sub(last_update) < UPDATE_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_update = last_update.wrapping_add(UPDATE_MS * 1000 * SYSCLK_MHZ);
+
+            let now = DWT::get_cycle_count();
+            let (channels, frames, lost_frames, errors, signal) =
+                cx.resources.reception.lock(|r| {
+                    let signal =
+                        now.wrapping_sub(r.last_frame) < SIGNAL_TIMEOUT_MS * 1000 * SYSCLK_MHZ;
+                    (r.last, r.frames, r.lost_frames, r.errors, signal)
+                });
+            let frames_per_s = frames.wrapping_sub(last_frames) * 1000 / UPDATE_MS;
+            last_frames = frames;
+
+            disp.clear();
+            status.clear();
+            match channels {
+                Some(channels) if signal => {
+                    for (i, us) in channels.us[..channels.count].iter().enumerate() {
+                        // 1000-2000µs from the bottom to the top
+                        let us = i32::from(*us).clamp(1000, 2000);
+                        let height = (us - 1000) * (BAR_HEIGHT - 1) / 1000 + 1;
+                        let x = i as i32 * 8;
+                        Rectangle::new(
+                            Point::new(x, BAR_HEIGHT - height),
+                            Point::new(x + 5, BAR_HEIGHT - 1),
+                        )
+                        .into_styled(bar_style)
+                        .draw(&mut disp)
+                        .unwrap();
+                    }
+                    if channels.failsafe {
+                        write!(status, "FAILSAFE").unwrap();
+                    } else {
+                        write!(status, "{}/s lost: {}", frames_per_s, lost_frames).unwrap();
+                    }
+                }
+                _ => write!(status, "NO SIGNAL errors: {}", errors).unwrap(),
+            }
+            Rectangle::new(
+                Point::new(0, BAR_HEIGHT / 2),
+                Point::new(127, BAR_HEIGHT / 2),
+            )
+            .into_styled(center_style)
+            .draw(&mut disp)
+            .unwrap();
+            Text::new(&status, Point::new(0, 56))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+            disp.flush().unwrap();
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            updates += 1;
+            if updates % 20 == 0 {
+                cx.resources.led.set_low().unwrap();
+                if let Some(channels) = channels {
+                    log_info!(
+                        "Channels: {:?}, failsafe: {}",
+                        channels.us,
+                        channels.failsafe
+                    );
+                }
+            } else if updates % 20 == 2 {
+                cx.resources.led.set_high().unwrap();
+            }
+        }
+    }
+};
@@ -0,0 +1,321 @@
+//! Log the temperature measured with a TMP112 sensor to a CSV file on an
+//! SD card, and read the file on a computer over USB without taking the
+//! card out.
+//!
+//! In logger mode, a line with the seconds since the start and the
+//! temperature is appended to `TEMP.CSV` every `LOG_INTERVAL_S` seconds.
+//! The file is closed after every line, so the card can be pulled at any
+//! time. Press the button to switch to USB mass storage (MSC) mode: the
+//! logging stops and the card shows up as a USB drive on the computer.
+//! Eject the drive on the computer and press the button again to go back
+//! to logging, which resets the board.
+//!
+//! Only one side may use the card at a time, as neither knows about the
+//! changes of the other one. That is why the board only connects to USB in
+//! MSC mode, and starts afresh afterwards.
+//!
+//! When the card can't be written, the example blinks the SD error code
+//! with the `diagnostics` module and resets itself. There is no real-time
+//! clock, so the files get a fixed date.
+//!
+//! The card must be formatted with FAT16 or FAT32. The SPI runs at 400kHz
+//! as needed for the initialization of the card, so copying over USB is
+//! slow, but quick enough for log files.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1, SPI1 and USB.
+//!
+//! ```
+//! BP   <-> TMP112 <-> SD card module <-> Button
+//! GND  <-> GND    <-> GND            <-> GND
+//! 3.3V <-> VCC    <-> 3.3V
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! PA4             <-> CS
+//! PA5             <-> SCK
+//! PA6             <-> MISO
+//! PA7             <-> MOSI
+//! PB12                               <-> +
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example sd-card-logger-usb-msc-tmp112-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::{Debug, Write};
+use cortex_m::{asm::delay, peripheral::SCB};
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    diagnostics::{self, Fault},
+    i2c_speed, log_error, log_info, log_warn, monotonic, panic_display as _,
+};
+use embedded_hal::{
+    digital::v2::{InputPin, OutputPin},
+    spi::{FullDuplex, Mode, Phase, Polarity},
+};
+use embedded_sdmmc::{
+    Block, BlockDevice as _, BlockIdx, BlockSpi, Controller, Mode as FileMode, SdMmcSpi,
+    TimeSource, Timestamp, VolumeIdx,
+};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    pac,
+    prelude::*,
+    spi::Spi,
+    usb::{Peripheral, UsbBus},
+};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+use usb_device::prelude::*;
+use usbd_scsi::{BlockDevice, BlockDeviceError, Scsi};
+
+const FILE_NAME: &str = "TEMP.CSV";
+const LOG_INTERVAL_S: u32 = 10;
+const BUTTON_POLL_MS: u32 = 10;
+const BLOCK_LEN: usize = 512;
+const USB_PACKET_LEN: u16 = 64;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+/// Fixed date for the files, as there is no real-time clock
+struct FixedTime;
+
+impl TimeSource for FixedTime {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 51,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+/// The SD card as the disk of the USB mass storage class
+struct UsbDisk<'a, SPI, CS>
+where
+    SPI: FullDuplex<u8>,
+    SPI::Error: Debug,
+    CS: OutputPin,
+{
+    card: BlockSpi<'a, SPI, CS>,
+    blocks: u32,
+}
+
+impl<'a, SPI, CS> BlockDevice for UsbDisk<'a, SPI, CS>
+where
+    SPI: FullDuplex<u8>,
+    SPI::Error: Debug,
+    CS: OutputPin,
+{
+    const BLOCK_BYTES: usize = BLOCK_LEN;
+
+    fn read_block(&self, lba: u32, data: &mut [u8]) -> Result<(), BlockDeviceError> {
+        let mut blocks = [Block::new()];
+        self.card
+            .read(&mut blocks, BlockIdx(lba), "usb")
+            .map_err(|_| BlockDeviceError::HardwareError)?;
+        data.copy_from_slice(&blocks[0].contents);
+        Ok(())
+    }
+
+    fn write_block(&mut self, lba: u32, data: &[u8]) -> Result<(), BlockDeviceError> {
+        let mut block = Block::new();
+        block.contents.copy_from_slice(data);
+        self.card
+            .write(&[block], BlockIdx(lba))
+            .map_err(|_| BlockDeviceError::WriteError)
+    }
+
+    fn max_lba(&self) -> u32 {
+        self.blocks - 1
+    }
+}
+
+/// Debounced button, pressed once it reads low for 8 polls in a row
+struct Button<P> {
+    pin: P,
+    history: u8,
+}
+
+impl<P: InputPin> Button<P> {
+    fn pressed(&mut self) -> bool {
+        let previous = self.history;
+        self.history = (self.history << 1) | u8::from(self.pin.is_low().unwrap_or(false));
+        self.history == 0xFF && previous != 0xFF
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("SD card logger USB MSC example");
+    if let Some(last) = diagnostics::take_last_fault() {
+        log_warn!("Last fault: {:?}", last.fault);
+    }
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    // USB needs a 48MHz clock: 72MHz / 1.5
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    assert!(clocks.usbclk_valid());
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // TMP112 sensor
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x48]),
+    );
+    let mut sensor = Tmp1x2::new(i2c, SlaveAddr::default());
+
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+    let cs = gpioa.pa4.into_push_pull_output(&mut gpioa.crl);
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        Mode {
+            polarity: Polarity::IdleLow,
+            phase: Phase::CaptureOnFirstTransition,
+        },
+        400.khz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+    let mut sdmmc = SdMmcSpi::new(spi, cs);
+
+    let mut button = Button {
+        pin: gpiob.pb12.into_pull_up_input(&mut gpiob.crh),
+        history: 0,
+    };
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    // Logger mode
+    let card = sdmmc
+        .acquire()
+        .unwrap_or_else(|_| diagnostics::fail(Fault::SdError));
+    let mut controller = Controller::new(card, FixedTime);
+    let mut volume = controller
+        .get_volume(VolumeIdx(0))
+        .unwrap_or_else(|_| diagnostics::fail(Fault::SdError));
+    let root = controller
+        .open_root_dir(&volume)
+        .unwrap_or_else(|_| diagnostics::fail(Fault::SdError));
+    log_info!("Logging to {}", FILE_NAME);
+    let mut last_log = monotonic::millis().wrapping_sub(LOG_INTERVAL_S * 1000);
+    loop {
+        monotonic::wait_ms(BUTTON_POLL_MS);
+        if button.pressed() {
+            break;
+        }
+        if monotonic::millis().wrapping_sub(last_log) < LOG_INTERVAL_S * 1000 {
+            continue;
+        }
+        last_log = last_log.wrapping_add(LOG_INTERVAL_S * 1000);
+
+        let temperature = match sensor.read_temperature() {
+            Ok(temperature) => temperature,
+            Err(e) => {
+                log_error!("Sensor error: {:?}", e);
+                continue;
+            }
+        };
+        led.set_low().unwrap();
+        let mut file = controller
+            .open_file_in_dir(
+                &mut volume,
+                &root,
+                FILE_NAME,
+                FileMode::ReadWriteCreateOrAppend,
+            )
+            .unwrap_or_else(|_| diagnostics::fail(Fault::SdError));
+        let mut line: String<48> = String::new();
+        if file.length() == 0 {
+            line.push_str("uptime_s,temperature\n").unwrap();
+        }
+        writeln!(line, "{},{:.2}", last_log / 1000, temperature).unwrap();
+        let written = controller.write(&mut volume, &mut file, line.as_bytes());
+        let closed = controller.close_file(&volume, file);
+        if written.is_err() || closed.is_err() {
+            diagnostics::fail(Fault::SdError);
+        }
+        log_info!("Logged {:.2}ºC", temperature);
+        led.set_high().unwrap();
+    }
+    controller.close_dir(&volume, root);
+    let (card, _) = controller.free();
+
+    // USB mass storage mode
+    log_info!("USB mass storage mode");
+    led.set_low().unwrap();
+    let blocks = match card.num_blocks() {
+        Ok(count) => count.0,
+        Err(_) => diagnostics::fail(Fault::SdError),
+    };
+    let disk = UsbDisk { card, blocks };
+    // The Bluepill has a pull-up resistor on D+. Pull D+ low for a moment
+    // so that the computer notices the device.
+    let mut usb_dp = gpioa.pa12.into_push_pull_output(&mut gpioa.crh);
+    usb_dp.set_low().unwrap();
+    delay(clocks.sysclk().0 / 100);
+    let usb = Peripheral {
+        usb: dp.USB,
+        pin_dm: gpioa.pa11,
+        pin_dp: usb_dp.into_floating_input(&mut gpioa.crh),
+    };
+    let usb_bus = UsbBus::new(usb);
+    let mut scsi = Scsi::new(
+        &usb_bus,
+        USB_PACKET_LEN,
+        disk,
+        b"drv-exmp",
+        b"Bluepill logger",
+        b"0.1",
+    );
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .manufacturer("driver-examples")
+        .product("SD card logger")
+        .serial_number("SD1")
+        .build();
+    let mut last_poll = monotonic::millis();
+    loop {
+        usb_dev.poll(&mut [&mut scsi]);
+        if monotonic::millis().wrapping_sub(last_poll) >= BUTTON_POLL_MS {
+            last_poll = monotonic::millis();
+            if button.pressed() {
+                log_info!("Back to logger mode");
+                SCB::sys_reset();
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Query an SDI-12 sensor like a soil moisture probe and print the values of
+//! its measurement on an SSD1306 OLED display.
+//!
+//! The `sdi12` module of this crate implements the master. This example
+//! provides the data line on PA8, which is switched between a push-pull
+//! output while sending and an input with pull-down while the sensor
+//! answers. At startup, the identification of the sensor (`aI!`) is printed.
+//! Then measurements are started (`aM!`) and, after the time the sensor
+//! asks for, the values are read (`aD0!`, ...).
+//!
+//! The meaning of the values depends on the sensor. For example, a METER
+//! TEROS 12 sends the raw volumetric water content, the temperature and the
+//! electrical conductivity. Set `ADDRESS` to the address of your sensor.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> Sensor <-> Display
+//! GND  <-> GND    <-> GND
+//! 3.3V            <-> VDD
+//! PA8  <-> DATA
+//! PB8             <-> SCL
+//! PB9             <-> SDA
+//! ```
+//!
+//! Power the sensor as specified in its manual (often 12V). The SDI-12
+//! spacing level is above 3.5V. 3.3V works with many sensors, otherwise use
+//! a level shifter. PA8 is 5V tolerant.
+//!
+//! Run with:
+//! `cargo embed --example sdi12-soil-probe-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    i2c_speed, log_error, log_info,
+    panic_display::{self, Bus},
+    sdi12::{Line, Master, MAX_ANSWER_LEN},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    delay::Delay,
+    gpio::{gpioa, Input, Output, PullDown, PushPull},
+    pac,
+    prelude::*,
+};
+
+const ADDRESS: u8 = b'0';
+const MAX_VALUES: usize = 4;
+const PERIOD_MS: u32 = 10_000;
+
+enum Pin {
+    Output(gpioa::PA8<Output<PushPull>>),
+    Input(gpioa::PA8<Input<PullDown>>),
+}
+
+/// SDI-12 data line on PA8
+struct Pa8Line {
+    pin: Option<Pin>,
+    crh: gpioa::CRH,
+}
+
+impl Line for Pa8Line {
+    fn drive(&mut self, spacing: bool) {
+        let mut pin = match self.pin.take().unwrap() {
+            Pin::Output(pin) => pin,
+            Pin::Input(pin) => pin.into_push_pull_output(&mut self.crh),
+        };
+        if spacing {
+            pin.set_high().unwrap();
+        } else {
+            pin.set_low().unwrap();
+        }
+        self.pin = Some(Pin::Output(pin));
+    }
+
+    fn release(&mut self) {
+        let pin = match self.pin.take().unwrap() {
+            Pin::Output(pin) => pin.into_pull_down_input(&mut self.crh),
+            Pin::Input(pin) => pin,
+        };
+        self.pin = Some(Pin::Input(pin));
+    }
+
+    fn is_spacing(&self) -> bool {
+        match &self.pin {
+            Some(Pin::Input(pin)) => pin.is_high().unwrap(),
+            _ => false,
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("SDI-12 example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let data = gpioa.pa8.into_push_pull_output(&mut gpioa.crh);
+    let line = Pa8Line {
+        pin: Some(Pin::Output(data)),
+        crh: gpioa.crh,
+    };
+    let mut sdi12 = Master::new(line, clocks.sysclk().0);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut answer = [0; MAX_ANSWER_LEN];
+    match sdi12.command(&[ADDRESS, b'I', b'!'], &mut answer) {
+        Ok(len) => log_info!(
+            "Sensor: {}",
+            core::str::from_utf8(&answer[..len]).unwrap_or("?")
+        ),
+        Err(e) => log_error!("Identification failed: {:?}", e),
+    }
+
+    let mut values = [0.0; MAX_VALUES];
+    let mut lines: [String<32>; MAX_VALUES + 1] = Default::default();
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        let result = sdi12
+            .start_measurement(ADDRESS)
+            .and_then(|(seconds, count)| {
+                log_info!("{} values ready in {}s", count, seconds);
+                sdi12.wait_ms(u32::from(seconds) * 1000);
+                let count = count.min(MAX_VALUES);
+                sdi12.read_data(ADDRESS, &mut values[..count])
+            });
+        match result {
+            Ok(count) => {
+                write!(lines[0], "Sensor {}", ADDRESS as char).unwrap();
+                for (i, (line, value)) in lines[1..]
+                    .iter_mut()
+                    .zip(values[..count].iter())
+                    .enumerate()
+                {
+                    log_info!("Value {}: {}", i + 1, value);
+                    write!(line, "Value {}: {:.2}", i + 1, value).unwrap();
+                }
+            }
+            Err(e) => {
+                log_error!("Error: {:?}", e);
+                write!(lines[0], "Error: {:?}", e).unwrap();
+            }
+        }
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 12))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        sdi12.wait_ms(PERIOD_MS);
+    }
+}
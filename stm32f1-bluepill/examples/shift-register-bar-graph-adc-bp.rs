@@ -0,0 +1,127 @@
+//! Show the voltage of a potentiometer measured with the internal ADC on a
+//! 10-LED bar graph connected to two chained 74HC595 shift registers.
+//!
+//! The shift registers are clocked through SPI and each of their outputs is
+//! used as an `OutputPin` through the `ShiftRegisterPins` helper from this
+//! crate. This way the bar graph code works the same with MCU pins or with
+//! expander pins.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1.
+//!
+//! ```
+//! BP   <-> 74HC595 (1)    <-> 74HC595 (2)
+//! GND  <-> GND, OE        <-> GND, OE
+//! 3.3V <-> VCC, MR        <-> VCC, MR
+//! PA5  <-> SH_CP          <-> SH_CP
+//! PA7  <-> DS
+//!          Q7'            <-> DS
+//! PB0  <-> ST_CP          <-> ST_CP
+//!          Q0-Q7 <-> LED 1-8
+//!                             Q0-Q1 <-> LED 9-10
+//! PA0  <-> Potentiometer wiper (the ends to GND and 3.3V)
+//! ```
+//!
+//! Each LED needs a series resistor (e.g. 330 ohm) to GND.
+//!
+//! Run with:
+//! `cargo embed --example shift-register-bar-graph-adc-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    convert::{adc_to_millivolts, ADC_MAX},
+    log_info, panic_display as _,
+    shift_register::ShiftRegisterPins,
+};
+use embedded_hal::{digital::v2::OutputPin, spi::MODE_0};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{adc, delay::Delay, pac, prelude::*, spi::Spi};
+
+/// Turn on as many LEDs as correspond to `value` out of `max`.
+fn bar_graph<P: OutputPin>(leds: &mut [P], value: u32, max: u32) -> Result<(), P::Error> {
+    let count = (value * leds.len() as u32 + max / 2) / max;
+    for (i, led) in leds.iter_mut().enumerate() {
+        if (i as u32) < count {
+            led.set_high()?;
+        } else {
+            led.set_low()?;
+        }
+    }
+    Ok(())
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("74HC595 bar graph example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.adcclk(2.mhz()).freeze(&mut flash.acr);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    // SPI1
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+    let latch = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        MODE_0,
+        1_u32.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    let mut adc1 = adc::Adc::adc1(dp.ADC1, &mut rcc.apb2, clocks);
+    let mut potentiometer = gpioa.pa0.into_analog(&mut gpioa.crl);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let expander: ShiftRegisterPins<_, _, 2> = ShiftRegisterPins::new(spi, latch);
+    let mut bar = [
+        expander.pin(0),
+        expander.pin(1),
+        expander.pin(2),
+        expander.pin(3),
+        expander.pin(4),
+        expander.pin(5),
+        expander.pin(6),
+        expander.pin(7),
+        expander.pin(8),
+        expander.pin(9),
+    ];
+    expander.write_all([0; 2]).unwrap();
+
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+        delay.delay_ms(50_u16);
+
+        let value: u16 = adc1.read(&mut potentiometer).unwrap();
+        log_info!(
+            every = 10,
+            "ADC: {} ({} mV)",
+            value,
+            adc_to_millivolts(value)
+        );
+        bar_graph(&mut bar, u32::from(value), u32::from(ADC_MAX)).unwrap();
+    }
+}
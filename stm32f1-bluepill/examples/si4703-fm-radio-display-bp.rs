@@ -28,31 +28,34 @@
 
 use core::fmt::Write;
 use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+};
 use embedded_graphics::{
     fonts::{Font6x8, Text},
     pixelcolor::BinaryColor,
     prelude::*,
     style::TextStyleBuilder,
 };
-use embedded_hal::digital::v2::{InputPin, OutputPin};
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use embedded_hal::{
+    blocking::i2c::Read,
+    digital::v2::{InputPin, OutputPin},
+};
+use rtt_target::rtt_init_print;
 use si4703::{
     reset_and_select_i2c_method1 as reset_si4703, ChannelSpacing, DeEmphasis, ErrorWithPin,
     SeekDirection, SeekMode, Si4703, Volume,
 };
 use ssd1306::{prelude::*, Builder, I2CDIBuilder};
-use stm32f1xx_hal::{
-    delay::Delay,
-    i2c::{BlockingI2c, DutyCycle, Mode},
-    pac,
-    prelude::*,
-};
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*};
+
+const SI4703_ADDRESS: u8 = 0x10;
 
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("Si4703 example");
+    log_info!("Si4703 example");
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
 
@@ -75,25 +78,23 @@ fn main() -> ! {
 
     reset_si4703(&mut rst, &mut sda, &mut delay).unwrap();
     let sda = sda.into_alternate_open_drain(&mut gpiob.crh);
-    let i2c = BlockingI2c::i2c1(
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
         dp.I2C1,
         (scl, sda),
         &mut afio.mapr,
-        Mode::Fast {
-            frequency: 400_000.hz(),
-            duty_cycle: DutyCycle::Ratio2to1,
-        },
         clocks,
         &mut rcc.apb1,
-        1000,
-        10,
-        1000,
-        1000,
+        // The Si4703 takes written bytes as register values, probe it with a read.
+        |i2c| {
+            i2c.read(SI4703_ADDRESS, &mut [0; 2]).is_ok()
+                && i2c_speed::probe_addresses(i2c, &[0x3C])
+        },
     );
     let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
     let interface = I2CDIBuilder::new().init(manager.acquire());
     let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
     disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
     disp.flush().unwrap();
 
     let text_style = TextStyleBuilder::new(Font6x8)
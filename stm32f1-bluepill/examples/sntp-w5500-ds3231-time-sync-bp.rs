@@ -0,0 +1,213 @@
+//! Get the time from a time server over SNTP through a W5500 Ethernet
+//! module and set a DS3231 real-time clock to it, instead of a fixed date
+//! in the code.
+//!
+//! The request is sent with the `sntp` and `w5500` modules of this crate.
+//! Half of the round trip time is added to the time of the server, then
+//! `UTC_OFFSET_MIN` to get the local time. The RTC is set at the start of
+//! the next second, which also restarts the internal second of the DS3231.
+//! The sync is repeated every `SYNC_INTERVAL_S` and the offset of the RTC
+//! found each time is printed, so you can see how much it drifts.
+//!
+//! There is no DNS client, so `SERVER_IP` is an address of
+//! time.cloudflare.com. Many routers also answer SNTP requests. The offset
+//! does not change for summer time by itself.
+//!
+//! A MCP7940N RTC can be set the same way with `Mcp794xx::new_mcp7940n()`
+//! and `enable()`.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and SPI1.
+//!
+//! ```
+//! BP   <-> DS3231 <-> W5500
+//! GND  <-> GND    <-> GND
+//! 3.3V <-> VCC    <-> 3.3V
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! PA4             <-> SCS
+//! PA5             <-> SCLK
+//! PA6             <-> MISO
+//! PA7             <-> MOSI
+//! PB0             <-> RST
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example sntp-w5500-ds3231-time-sync-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_error, log_info, log_warn, monotonic, panic_display as _,
+    sntp::{self, Time},
+    w5500::{self, NetConfig, W5500},
+};
+use ds323x::{Ds323x, NaiveDateTime, Rtcc};
+use embedded_hal::{
+    blocking::spi,
+    digital::v2::OutputPin,
+    spi::{Mode, Phase, Polarity},
+};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{pac, prelude::*, spi::Spi};
+
+/// time.cloudflare.com
+const SERVER_IP: [u8; 4] = [162, 159, 200, 123];
+const LOCAL_PORT: u16 = 12300;
+const NET_CONFIG: NetConfig = NetConfig {
+    // Locally administered address, change it if there are several boards.
+    mac: [0x02, 0x00, 0x00, 0x12, 0x34, 0x57],
+    ip: [192, 168, 1, 51],
+    subnet_mask: [255, 255, 255, 0],
+    router: [192, 168, 1, 1],
+};
+/// Local time minus UTC, e.g. 60 for CET
+const UTC_OFFSET_MIN: i64 = 60;
+const SYNC_INTERVAL_S: u32 = 3600;
+const ANSWER_TIMEOUT_MS: u32 = 2000;
+const RETRIES: u32 = 3;
+
+#[derive(Debug)]
+enum SyncError {
+    Net(w5500::Error),
+    Sntp(sntp::Error),
+    /// The server did not answer
+    Timeout,
+    /// The time does not fit into a date
+    InvalidTime,
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+/// Ask the server for the time, trying again when it does not answer.
+fn query_time<SPI, CS, E>(w5500: &mut W5500<SPI, CS>) -> Result<Time, SyncError>
+where
+    SPI: spi::Write<u8, Error = E> + spi::Transfer<u8, Error = E>,
+    CS: OutputPin,
+{
+    let mut buffer = [0; sntp::PACKET_LEN];
+    for _ in 0..RETRIES {
+        w5500.send(&sntp::request()).map_err(SyncError::Net)?;
+        let sent = monotonic::millis();
+        while monotonic::millis().wrapping_sub(sent) < ANSWER_TIMEOUT_MS {
+            if let Some(len) = w5500.receive(&mut buffer).map_err(SyncError::Net)? {
+                let time = sntp::parse(&buffer[..len]).map_err(SyncError::Sntp)?;
+                // The answer took about half of the round trip to arrive.
+                return Ok(time.add_ms(monotonic::millis().wrapping_sub(sent) / 2));
+            }
+        }
+        log_warn!("No answer from the server");
+    }
+    Err(SyncError::Timeout)
+}
+
+/// Set the RTC to the local time at the start of the next second and return
+/// how far off it was.
+fn set_rtc<RTC: Rtcc>(rtc: &mut RTC, utc: Time) -> Result<(NaiveDateTime, i64), SyncError> {
+    let local = NaiveDateTime::from_timestamp_opt(utc.seconds + UTC_OFFSET_MIN * 60 + 1, 0)
+        .ok_or(SyncError::InvalidTime)?;
+    monotonic::wait_ms((1_000_000_000 - utc.nanos) / 1_000_000);
+    let offset = rtc
+        .get_datetime()
+        .map(|now| now.signed_duration_since(local).num_seconds())
+        .unwrap_or(0);
+    rtc.set_datetime(&local)
+        .map_err(|_| SyncError::InvalidTime)?;
+    Ok((local, offset))
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("SNTP DS3231 time sync example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // DS3231 RTC
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x68]),
+    );
+    let mut rtc = Ds323x::new_ds3231(i2c);
+
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+    let cs = gpioa.pa4.into_push_pull_output(&mut gpioa.crl);
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        Mode {
+            polarity: Polarity::IdleLow,
+            phase: Phase::CaptureOnFirstTransition,
+        },
+        9.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    // Hardware reset: at least 500us low, then the PLL of the W5500 needs 1ms.
+    let mut reset = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+    reset.set_low().unwrap();
+    monotonic::wait_ms(1);
+    reset.set_high().unwrap();
+    monotonic::wait_ms(2);
+
+    let mut w5500 = W5500::new(spi, cs, &NET_CONFIG).unwrap();
+    while !w5500.link_up().unwrap() {
+        log_info!("Waiting for the Ethernet link");
+        monotonic::wait_ms(1000);
+    }
+    log_info!("Link up");
+    w5500.open_udp(LOCAL_PORT, SERVER_IP, sntp::PORT).unwrap();
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    loop {
+        match query_time(&mut w5500).and_then(|utc| set_rtc(&mut rtc, utc)) {
+            Ok((local, offset)) => log_info!("RTC set to {}, it was off by {}s", local, offset),
+            Err(e) => log_error!("Sync failed: {:?}", e),
+        }
+
+        for seconds in 1..=SYNC_INTERVAL_S {
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            led.set_low().unwrap();
+            monotonic::wait_ms(50);
+            led.set_high().unwrap();
+            monotonic::wait_ms(950);
+            if seconds % 60 == 0 {
+                if let Ok(now) = rtc.get_datetime() {
+                    log_info!("{}", now);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Read the temperature from a TMP112 sensor on a bit-banged I2C bus and
+//! show it on an SSD1306 OLED display on the hardware I2C1 bus.
+//!
+//! The sensor is on PB14 and PB15, which have no I2C peripheral but are 5V
+//! tolerant, so the bus could also be pulled up to 5V. The `soft_i2c`
+//! module implements the I2C traits, so the `tmp1x2` driver is used as with
+//! the hardware peripheral. The display shows the time a reading takes and
+//! the number of errors, e.g. when the sensor is disconnected.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and
+//! two GPIO pins.
+//!
+//! ```
+//! BP   <-> TMP112 <-> Display
+//! GND  <-> GND    <-> GND
+//! 3.3V <-> VCC    <-> VDD
+//! PB14 <-> SCL
+//! PB15 <-> SDA
+//! PB8             <-> SCL
+//! PB9             <-> SDA
+//! ```
+//!
+//! The bit-banged bus needs pull-up resistors, e.g. 4.7K to VCC, unless the
+//! sensor board has them.
+//!
+//! Run with:
+//! `cargo embed --example soft-i2c-tmp112-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    delay::DwtDelay,
+    i2c_speed, log_info, log_warn,
+    panic_display::{self, Bus},
+    soft_i2c::SoftI2c,
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{pac, prelude::*};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+const SYSCLK_MHZ: u32 = 72;
+const SOFT_I2C_HZ: u32 = 100_000;
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Software I2C TMP112 example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(SYSCLK_MHZ.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+
+    let soft_scl = gpiob.pb14.into_open_drain_output(&mut gpiob.crh);
+    let soft_sda = gpiob.pb15.into_open_drain_output(&mut gpiob.crh);
+    let soft_i2c = SoftI2c::new(soft_scl, soft_sda, clocks.sysclk().0, SOFT_I2C_HZ);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = DwtDelay::new(clocks.sysclk().0);
+
+    let interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut tmp112 = Tmp1x2::new(soft_i2c, SlaveAddr::default());
+
+    let mut lines: [String<32>; 3] = Default::default();
+    let mut errors = 0_u32;
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+        delay.delay_ms(200_u16);
+
+        let start = DWT::get_cycle_count();
+        let result = tmp112.read_temperature();
+        let read_us = DWT::get_cycle_count().wrapping_sub(start) / SYSCLK_MHZ;
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        match result {
+            Ok(temp_c) => write!(lines[0], "Temperature: {:.1}C", temp_c).unwrap(),
+            Err(e) => {
+                errors += 1;
+                log_warn!("TMP112 error: {:?}", e);
+                write!(lines[0], "Error: {:?}", e).unwrap();
+            }
+        }
+        write!(lines[1], "Read: {}us", read_us).unwrap();
+        write!(lines[2], "Errors: {}", errors).unwrap();
+
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+    }
+}
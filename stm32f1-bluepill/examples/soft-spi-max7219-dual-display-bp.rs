@@ -0,0 +1,210 @@
+//! Drive two 8-digit 7-segment display modules with MAX7219 controllers on
+//! a bit-banged SPI bus on arbitrary pins.
+//!
+//! The `soft_spi` module implements the SPI traits on PB12 and PB13, so the
+//! pins of SPI1 and SPI2 stay free for other peripherals. Both modules share
+//! the clock and data lines and each has its own chip select pin, like
+//! devices on a hardware SPI bus. The first module shows the time since
+//! startup like `00-01-23`, the second one how long updating both modules
+//! took in microseconds, which is the price of bit-banging. A minimal
+//! MAX7219 driver is included in this example.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using 4 GPIO pins.
+//!
+//! ```
+//! BP   <-> MAX7219 1 <-> MAX7219 2
+//! GND  <-> GND       <-> GND
+//! 5V   <-> VCC       <-> VCC
+//! PB12 <-> CLK       <-> CLK
+//! PB13 <-> DIN       <-> DIN
+//! PB14 <-> CS
+//! PB15               <-> CS
+//! ```
+//!
+//! The MAX7219 needs 5V. Most modules accept the 3.3V signals of the board
+//! although the datasheet asks for 3.5V. Otherwise use a level shifter.
+//!
+//! Run with:
+//! `cargo embed --example soft-spi-max7219-dual-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::DWT;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    log_info, monotonic, panic_display as _,
+    soft_spi::{NoMiso, SoftSpi},
+};
+use embedded_hal::{blocking::spi, digital::v2::OutputPin, spi::MODE_0};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{pac, prelude::*};
+
+const SYSCLK_MHZ: u32 = 72;
+const SOFT_SPI_HZ: u32 = 1_000_000;
+const UPDATE_MS: u32 = 100;
+
+// MAX7219 registers
+const DECODE_MODE: u8 = 0x09;
+const INTENSITY: u8 = 0x0A;
+const SCAN_LIMIT: u8 = 0x0B;
+const SHUTDOWN: u8 = 0x0C;
+const DISPLAY_TEST: u8 = 0x0F;
+
+// Code B characters
+const DASH: u8 = 0x0A;
+const BLANK: u8 = 0x0F;
+
+/// Minimal MAX7219 driver for an 8-digit 7-segment display. The SPI bus is
+/// passed to every call, so several devices can share it.
+struct Max7219<CS> {
+    cs: CS,
+}
+
+impl<CS: OutputPin> Max7219<CS> {
+    fn new(mut cs: CS) -> Self {
+        cs.set_high().ok();
+        Max7219 { cs }
+    }
+
+    fn init<SPI, E>(&mut self, spi: &mut SPI) -> Result<(), E>
+    where
+        SPI: spi::Write<u8, Error = E>,
+    {
+        self.write_register(spi, DISPLAY_TEST, 0)?;
+        self.write_register(spi, SCAN_LIMIT, 7)?;
+        // Code B font for all digits
+        self.write_register(spi, DECODE_MODE, 0xFF)?;
+        self.write_register(spi, INTENSITY, 4)?;
+        self.show(spi, &[BLANK; 8])?;
+        self.write_register(spi, SHUTDOWN, 1)
+    }
+
+    /// Show code B characters, starting with the leftmost digit.
+    fn show<SPI, E>(&mut self, spi: &mut SPI, digits: &[u8; 8]) -> Result<(), E>
+    where
+        SPI: spi::Write<u8, Error = E>,
+    {
+        for (i, digit) in digits.iter().enumerate() {
+            // Digit 0 (register 1) is the rightmost one.
+            self.write_register(spi, 8 - i as u8, *digit)?;
+        }
+        Ok(())
+    }
+
+    fn write_register<SPI, E>(&mut self, spi: &mut SPI, register: u8, value: u8) -> Result<(), E>
+    where
+        SPI: spi::Write<u8, Error = E>,
+    {
+        self.cs.set_low().ok();
+        let result = spi.write(&[register, value]);
+        self.cs.set_high().ok();
+        result
+    }
+}
+
+/// Code B digits of the time like `00-01-23`
+fn time_digits(seconds: u32) -> [u8; 8] {
+    let hours = (seconds / 3600 % 100) as u8;
+    let minutes = (seconds / 60 % 60) as u8;
+    let seconds = (seconds % 60) as u8;
+    [
+        hours / 10,
+        hours % 10,
+        DASH,
+        minutes / 10,
+        minutes % 10,
+        DASH,
+        seconds / 10,
+        seconds % 10,
+    ]
+}
+
+/// Code B digits of `value` right-aligned without leading zeros
+fn number_digits(value: u32) -> [u8; 8] {
+    let mut digits = [BLANK; 8];
+    let mut value = value;
+    for digit in digits.iter_mut().rev() {
+        *digit = (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    digits
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Software SPI MAX7219 example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(SYSCLK_MHZ.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let sck = gpiob.pb12.into_push_pull_output(&mut gpiob.crh);
+    let mosi = gpiob.pb13.into_push_pull_output(&mut gpiob.crh);
+    let mut spi = SoftSpi::new(sck, mosi, NoMiso, MODE_0, clocks.sysclk().0, SOFT_SPI_HZ);
+    let cs1 = gpiob.pb14.into_push_pull_output(&mut gpiob.crh);
+    let cs2 = gpiob.pb15.into_push_pull_output(&mut gpiob.crh);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let mut clock_display = Max7219::new(cs1);
+    let mut timing_display = Max7219::new(cs2);
+    clock_display.init(&mut spi).unwrap();
+    timing_display.init(&mut spi).unwrap();
+
+    let mut last_update = monotonic::millis();
+    let mut update_us = 0;
+    loop {
+        let now = monotonic::millis();
+        if now.wrapping_sub(last_update) < UPDATE_MS {
+            continue;
+        }
+        last_update = now;
+
+        let start = DWT::get_cycle_count();
+        clock_display
+            .show(&mut spi, &time_digits(now / 1000))
+            .unwrap();
+        timing_display
+            .show(&mut spi, &number_digits(update_us))
+            .unwrap();
+        update_us = DWT::get_cycle_count().wrapping_sub(start) / SYSCLK_MHZ;
+
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 does not blink, something went wrong.
+        if now / 1000 % 2 == 0 {
+            led.set_high().unwrap();
+        } else {
+            led.set_low().unwrap();
+        }
+        if now % 10_000 < UPDATE_MS {
+            log_info!("Updating both displays took {}us", update_us);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Measure the sound level with a MAX4466 microphone amplifier and show it
+//! in decibels and as a bar graph with a peak hold mark on an SSD1306 OLED
+//! display.
+//!
+//! ADC1 samples the microphone continuously at about 47.6kHz with the
+//! `adc_stream` module. The DMA interrupt adds up the samples and their
+//! squares for each half of the buffer, so no samples are lost while the
+//! display is updated. Every 125ms, like the "fast" time weighting of sound
+//! level meters, the RMS value is computed as the standard deviation of the
+//! samples, which removes the DC bias of the amplifier output, and converted
+//! into decibels:
+//!
+//! level = 20 * log10(RMS / RMS of a full scale sine) + `CALIBRATION_DB`
+//!
+//! There is no frequency weighting (it is "Z-weighted"). `CALIBRATION_DB` is
+//! the level of a sound which gives a full scale sine; find it by comparing
+//! with a sound level meter, for the gain set with the trimmer of the
+//! MAX4466. "CLIP" is shown when the signal reaches the limits of the ADC.
+//! The peak hold mark stays for `PEAK_HOLD_MS` unless a louder level comes.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> MAX4466 <-> Display
+//! GND  <-> GND     <-> GND
+//! 3.3V <-> VCC     <-> VDD
+//! PA0  <-> OUT
+//! PB8              <-> SCL
+//! PB9              <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example sound-level-meter-adc-stream-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    adc_stream::{sample_rate_hz, AdcStream},
+    bootloader::relocate_vector_table,
+    convert::ADC_MAX,
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, Rectangle},
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    adc::SampleTime,
+    gpio::{
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const ADC_MHZ: u32 = 12;
+const BUFFER: usize = 1024;
+const UPDATE_MS: u32 = 125;
+const PEAK_HOLD_MS: u32 = 2000;
+
+/// Level of a sound which gives a full scale sine, depends on the gain
+const CALIBRATION_DB: f32 = 110.0;
+/// RMS of a sine from 0 to `ADC_MAX`, in ADC steps
+const FULL_SCALE_RMS: f32 = (ADC_MAX as f32 + 1.0) / 2.0 / core::f32::consts::SQRT_2;
+
+// Bar graph range and position
+const MIN_DB: f32 = 30.0;
+const MAX_DB: f32 = 110.0;
+const BAR_TOP: i32 = 40;
+const BAR_BOTTOM: i32 = 55;
+
+/// Sums of the samples since the last update
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sums {
+    count: u32,
+    sum: i64,
+    squares: i64,
+    clipped: bool,
+}
+
+impl Sums {
+    fn add(&mut self, samples: &[u16]) {
+        for sample in samples {
+            let s = i64::from(*sample);
+            self.count += 1;
+            self.sum += s;
+            self.squares += s * s;
+            self.clipped |= *sample == 0 || *sample >= ADC_MAX;
+        }
+    }
+
+    /// Sound level in decibels
+    fn level_db(&self) -> f32 {
+        let n = f64::from(self.count.max(1));
+        let mean = self.sum as f64 / n;
+        let rms = libm::sqrt((self.squares as f64 / n - mean * mean).max(1.0)) as f32;
+        20.0 * libm::log10f(rms / FULL_SCALE_RMS) + CALIBRATION_DB
+    }
+}
+
+/// Horizontal position of a level on the bar graph
+fn bar_x(db: f32) -> i32 {
+    ((db - MIN_DB) / (MAX_DB - MIN_DB) * 127.0).clamp(0.0, 127.0) as i32
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        stream: AdcStream<BUFFER>,
+        sums: Sums,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Sound level meter example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the ADC1 clock before handing the RCC over to the HAL.
+        device.RCC.apb2enr.modify(|_, w| w.adc1en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .adcclk(ADC_MHZ.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let _microphone = gpioa.pa0.into_analog(&mut gpioa.crl);
+        let dma_ch1 = device.DMA1.split(&mut rcc.ahb).1;
+        let buffer = singleton!(: [[u16; BUFFER]; 2] = [[0; BUFFER]; 2]).unwrap();
+        let mut stream = AdcStream::start(device.ADC1, dma_ch1, &[0], SampleTime::T_239, buffer);
+        stream.listen();
+        log_info!(
+            "{:.0} samples/s",
+            sample_rate_hz(clocks.adcclk().0, SampleTime::T_239, 1)
+        );
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            stream,
+            sums: Sums::default(),
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = DMA1_CHANNEL1, priority = 2, resources = [stream, sums])]
+    fn dma1_channel1(cx: dma1_channel1::Context) {
+        let sums = cx.resources.sums;
+        cx.resources.stream.poll(|samples| sums.add(samples));
+    }
+
+    #[idle(resources = [stream, sums, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let led = cx.resources.led;
+        let interface = I2CDIBuilder::new().init(cx.resources.i2c.take().unwrap());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+        let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+        let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+
+        let mut last_update = DWT::get_cycle_count();
+        let mut peak_db = MIN_DB;
+        let mut since_peak_ms = 0;
+        let mut updates = 0_u32;
+        let mut lines: [String<32>; 2] = Default::default();
+        let mut led_on = false;
+        loop {
+            if DWT::get_cycle_count().wrapping_sub(last_update) < UPDATE_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_update = last_update.wrapping_add(UPDATE_MS * 1000 * SYSCLK_MHZ);
+
+            let sums = cx.resources.sums.lock(core::mem::take);
+            let level_db = sums.level_db();
+            since_peak_ms += UPDATE_MS;
+            if level_db >= peak_db || since_peak_ms >= PEAK_HOLD_MS {
+                peak_db = level_db;
+                since_peak_ms = 0;
+            }
+            updates += 1;
+            if updates % (1000 / UPDATE_MS) == 0 {
+                let overruns = cx.resources.stream.lock(|stream| stream.overruns());
+                log_info!(
+                    "{:.1}dB, peak {:.1}dB, {} overruns",
+                    level_db,
+                    peak_db,
+                    overruns
+                );
+            }
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            write!(
+                lines[0],
+                "{:.1} dB {}",
+                level_db,
+                if sums.clipped { "CLIP" } else { "" }
+            )
+            .unwrap();
+            write!(lines[1], "Peak: {:.1} dB", peak_db).unwrap();
+
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 12))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            Rectangle::new(Point::new(0, BAR_TOP), Point::new(127, BAR_BOTTOM))
+                .into_styled(outline)
+                .draw(&mut disp)
+                .unwrap();
+            Rectangle::new(
+                Point::new(0, BAR_TOP),
+                Point::new(bar_x(level_db), BAR_BOTTOM),
+            )
+            .into_styled(fill)
+            .draw(&mut disp)
+            .unwrap();
+            // Peak hold mark, sticking out of the bar
+            let peak_x = bar_x(peak_db);
+            Line::new(
+                Point::new(peak_x, BAR_TOP - 3),
+                Point::new(peak_x, BAR_BOTTOM + 3),
+            )
+            .into_styled(outline)
+            .draw(&mut disp)
+            .unwrap();
+            disp.flush().unwrap();
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            if updates % 4 == 0 {
+                led_on = !led_on;
+                if led_on {
+                    led.set_low().unwrap();
+                } else {
+                    led.set_high().unwrap();
+                }
+            }
+        }
+    }
+};
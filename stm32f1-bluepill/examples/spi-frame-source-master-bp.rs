@@ -0,0 +1,113 @@
+//! Send numbered test frames as fast as possible as an SPI master. This is
+//! the counterpart of the spi-slave-dma-sink-display-bp example, which runs
+//! on a second board, receives the frames and shows the throughput and the
+//! errors.
+//!
+//! Every frame is built with `test_frame::build` and the hardware CRC unit
+//! and sent while NSS (PA4) is low. The number of frames and bytes sent per
+//! second is printed through RTT.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1.
+//!
+//! ```
+//! BP   <-> Slave
+//! GND  <-> GND
+//! PA4  <-> NSS (PB12)
+//! PA5  <-> SCK (PB13)
+//! PA7  <-> MOSI (PB15)
+//! ```
+//!
+//! Change `SPI_HZ` to find the fastest clock the link can carry. The slave
+//! can receive at up to 18MHz.
+//!
+//! Run with:
+//! `cargo embed --example spi-frame-source-master-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::DWT;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::test_frame::{self, FRAME_LEN};
+use driver_examples_bluepill::{log_info, panic_display as _};
+use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin, spi::MODE_0};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{crc::CrcExt, pac, prelude::*, spi::Spi};
+
+const SYSCLK_HZ: u32 = 72_000_000;
+const SPI_HZ: u32 = 4_500_000;
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("SPI frame source example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(SYSCLK_HZ.hz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+
+    // SPI1
+    let mut nss = gpioa.pa4.into_push_pull_output(&mut gpioa.crl);
+    nss.set_high().unwrap();
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+
+    let mut spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        MODE_0,
+        SPI_HZ.hz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    let mut crc = dp.CRC.new(&mut rcc.ahb);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let mut sequence = 0_u8;
+    let mut frames = 0_u32;
+    let mut last_report = DWT::get_cycle_count();
+    let mut led_on = false;
+    loop {
+        let frame = test_frame::build(sequence, &mut crc);
+        nss.set_low().unwrap();
+        spi.write(&frame).unwrap();
+        nss.set_high().unwrap();
+        sequence = sequence.wrapping_add(1);
+        frames += 1;
+
+        if DWT::get_cycle_count().wrapping_sub(last_report) < SYSCLK_HZ {
+            continue;
+        }
+        last_report = last_report.wrapping_add(SYSCLK_HZ);
+        log_info!("{} frames/s, {} B/s", frames, frames * FRAME_LEN as u32);
+        frames = 0;
+
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led_on = !led_on;
+        if led_on {
+            led.set_high().unwrap();
+        } else {
+            led.set_low().unwrap();
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Share a single SPI bus between an ST7735 color LCD, a W25Q64 flash memory
+//! and an MCP3008 analog/digital converter, each with its own chip select.
+//!
+//! The JEDEC ID of the flash memory and the voltages measured by the MCP3008
+//! on channels 0 and 1 are printed on the LCD.
+//!
+//! The bus is shared through `shared-bus` proxies, which make sure only one
+//! device uses the bus at a time. Each driver handles its own chip select
+//! pin except for the ST7735 driver so its chip select is toggled around the
+//! display operations here.
+//!
+//! Each device runs with its own SPI mode and clock speed: the chip select
+//! pins are wrapped in `DeviceCs`, which sets SPI1 up for the device before
+//! selecting it. The flash memory runs at 18MHz, the display at 9MHz and the
+//! MCP3008, which supports up to 1.35MHz at 3.3V, at 1.125MHz in SPI mode 3.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1.
+//!
+//! ```
+//! BP   <-> ST7735 <-> W25Q64 <-> MCP3008
+//! GND  <-> GND    <-> GND    <-> AGND, DGND
+//! 3.3V <-> VCC    <-> VCC    <-> VDD, VREF
+//! 3.3V <-> LED
+//! PA5  <-> SCK    <-> CLK    <-> CLK
+//! PA6             <-> DO     <-> DOUT
+//! PA7  <-> SDA    <-> DI     <-> DIN
+//! PB0  <-> CS
+//! PB1  <-> DC
+//! PB10 <-> RST
+//! PB11            <-> CS
+//! PB12                       <-> CS/SHDN
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example spi-shared-bus-st7735-w25q-mcp3008-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use adc_mcp3008::{Channels8, Mcp3008};
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{log_info, panic_display as _, spi_device::DeviceCs};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::Rgb565,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::{
+    digital::v2::OutputPin,
+    spi::{MODE_0, MODE_3},
+};
+use rtt_target::rtt_init_print;
+use st7735_lcd::{Orientation, ST7735};
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*, spi::Spi};
+use w25::W25;
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Shared SPI bus example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .pclk2(72.mhz())
+        .freeze(&mut flash.acr);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    // SPI1
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+
+    let lcd_cs = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+    let dc = gpiob.pb1.into_push_pull_output(&mut gpiob.crl);
+    let rst = gpiob.pb10.into_push_pull_output(&mut gpiob.crh);
+    let flash_cs = gpiob.pb11.into_push_pull_output(&mut gpiob.crh);
+    let adc_cs = gpiob.pb12.into_push_pull_output(&mut gpiob.crh);
+    let mut lcd_cs = DeviceCs::new(lcd_cs, MODE_0, 9.mhz(), clocks);
+    let mut flash_cs = DeviceCs::new(flash_cs, MODE_0, 18.mhz(), clocks);
+    let mut adc_cs = DeviceCs::new(adc_cs, MODE_3, 1.mhz(), clocks);
+    lcd_cs.set_high().unwrap();
+    flash_cs.set_high().unwrap();
+    adc_cs.set_high().unwrap();
+
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        MODE_0,
+        1_u32.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(spi);
+
+    let mut lcd = ST7735::new(manager.acquire(), dc, rst, true, false, 160, 128);
+    lcd_cs.set_low().unwrap();
+    lcd.init(&mut delay).unwrap();
+    lcd.set_orientation(&Orientation::Landscape).unwrap();
+    lcd.clear(Rgb565::BLACK).unwrap();
+    lcd_cs.set_high().unwrap();
+
+    let mut w25 = W25::new_w25q64(manager.acquire(), flash_cs);
+    let mut adc = Mcp3008::new(manager.acquire(), adc_cs).unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(Rgb565::GREEN)
+        .background_color(Rgb565::BLACK)
+        .build();
+
+    let mut lines: [heapless::String<32>; 3] = [
+        heapless::String::new(),
+        heapless::String::new(),
+        heapless::String::new(),
+    ];
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+        delay.delay_ms(50_u16);
+
+        let id = w25.get_jedec_id().unwrap_or([255; 3]);
+        let ch0 = adc.read_channel(Channels8::CH0).unwrap_or(0xFFFF);
+        let ch1 = adc.read_channel(Channels8::CH1).unwrap_or(0xFFFF);
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        write!(lines[0], "JEDEC ID: {:X} {:X} {:X}", id[0], id[1], id[2]).unwrap();
+        // The MCP3008 is a 10-bit ADC using 3.3V as reference.
+        write!(
+            lines[1],
+            "CH0: {:4} {:4}mV",
+            ch0,
+            u32::from(ch0) * 3300 / 1023
+        )
+        .unwrap();
+        write!(
+            lines[2],
+            "CH1: {:4} {:4}mV",
+            ch1,
+            u32::from(ch1) * 3300 / 1023
+        )
+        .unwrap();
+
+        lcd_cs.set_low().unwrap();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut lcd)
+                .unwrap();
+        }
+        lcd_cs.set_high().unwrap();
+    }
+}
@@ -0,0 +1,324 @@
+//! Receive data as an SPI slave with DMA and show the throughput and the
+//! errors on an SSD1306 OLED display. Use it to test an SPI driver or a
+//! link between two boards. The spi-frame-source-master-bp example running
+//! on a second board sends suitable frames.
+//!
+//! The HAL supports SPI only as master, so SPI2 is set up as a receive-only
+//! slave through its registers: mode 0, 8-bit, MSB first, with the NSS pin
+//! selecting it. DMA1 channel 4 copies every received byte into a ring
+//! buffer in circular mode, so nothing is lost while the CPU is busy, e.g.
+//! updating the display.
+//!
+//! Every millisecond the TIM2 interrupt checks how far the DMA has written
+//! and splits the new bytes into `test_frame` frames, which are checked
+//! with the hardware CRC unit. Bytes outside of frames are skipped until the
+//! next sync byte. Lost frames are counted from the gaps in the sequence
+//! numbers. They are also the sign that the ring buffer overflowed.
+//!
+//! The ring buffer holds 4K, more than one millisecond at the highest SPI
+//! clock of the slave (18MHz, half of the APB1 clock).
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and SPI2.
+//!
+//! ```
+//! BP   <-> Master           <-> Display
+//! GND  <-> GND              <-> GND
+//! 3.3V                      <-> VDD
+//! PB12 <-> NSS (e.g. PA4)
+//! PB13 <-> SCK (e.g. PA5)
+//! PB15 <-> MOSI (e.g. PA7)
+//! PB8                       <-> SCL
+//! PB9                       <-> SDA
+//! ```
+//!
+//! The master should pull NSS low during its transfers. Tie PB12 to GND if
+//! it has no NSS line. MISO is not used.
+//!
+//! Run with:
+//! `cargo embed --example spi-slave-dma-sink-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::{
+    fmt::Write,
+    sync::atomic::{compiler_fence, Ordering},
+};
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+    test_frame::{self, FRAME_LEN, SYNC},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    crc::{Crc, CrcExt},
+    dma::dma1,
+    gpio::{
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    timer::{CountDownTimer, Event, Timer},
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const BUFFER: usize = 4096;
+const POLL_HZ: u32 = 1000;
+const DISPLAY_MS: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    bytes: u32,
+    frames: u32,
+    crc_errors: u32,
+    lost_frames: u32,
+    skipped: u32,
+}
+
+/// Ring buffer written by the DMA and the frame decoder
+pub struct Sink {
+    dma: dma1::C4,
+    buffer: &'static mut [u8; BUFFER],
+    read_index: usize,
+    frame: [u8; FRAME_LEN],
+    filled: usize,
+    next_sequence: Option<u8>,
+    crc: Crc,
+    stats: Stats,
+}
+
+impl Sink {
+    /// Decode the bytes received since the last call.
+    fn poll(&mut self) {
+        let write_index = BUFFER - usize::from(self.dma.ch().ndtr.read().ndt().bits());
+        // The buffer is written by the DMA.
+        compiler_fence(Ordering::SeqCst);
+        while self.read_index != write_index % BUFFER {
+            let byte = self.buffer[self.read_index];
+            self.read_index = (self.read_index + 1) % BUFFER;
+            self.receive(byte);
+        }
+    }
+
+    fn receive(&mut self, byte: u8) {
+        self.stats.bytes += 1;
+        if self.filled == 0 && byte != SYNC {
+            self.stats.skipped += 1;
+            return;
+        }
+        self.frame[self.filled] = byte;
+        self.filled += 1;
+        if self.filled < FRAME_LEN {
+            return;
+        }
+        self.filled = 0;
+        match test_frame::check(&self.frame, &mut self.crc) {
+            Some(sequence) => {
+                self.stats.frames += 1;
+                if let Some(expected) = self.next_sequence {
+                    self.stats.lost_frames += u32::from(sequence.wrapping_sub(expected));
+                }
+                self.next_sequence = Some(sequence.wrapping_add(1));
+            }
+            None => self.stats.crc_errors += 1,
+        }
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        sink: Sink,
+        timer: CountDownTimer<pac::TIM2>,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("SPI slave DMA sink example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the SPI2 clock before handing the RCC over to the HAL.
+        device.RCC.apb1enr.modify(|_, w| w.spi2en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        // PB12 (NSS), PB13 (SCK) and PB15 (MOSI) stay floating inputs as
+        // after reset, which is what the SPI slave needs.
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // Receive-only slave, mode 0, 8-bit, MSB first, NSS from the pin
+        let spi = device.SPI2;
+        spi.cr1.write(|w| {
+            w.cpha()
+                .clear_bit()
+                .cpol()
+                .clear_bit()
+                .mstr()
+                .clear_bit()
+                .lsbfirst()
+                .clear_bit()
+                .ssm()
+                .clear_bit()
+                .rxonly()
+                .set_bit()
+                .dff()
+                .clear_bit()
+        });
+        spi.cr2.write(|w| w.rxdmaen().set_bit());
+
+        let buffer = singleton!(: [u8; BUFFER] = [0; BUFFER]).unwrap();
+        let mut dma = device.DMA1.split(&mut rcc.ahb).4;
+        dma.set_peripheral_address(&spi.dr as *const _ as u32, false);
+        dma.set_memory_address(buffer.as_ptr() as u32, true);
+        dma.set_transfer_length(BUFFER);
+        dma.ch().cr.modify(|_, w| {
+            w.dir()
+                .clear_bit()
+                .circ()
+                .set_bit()
+                .psize()
+                .bits8()
+                .msize()
+                .bits8()
+                .pl()
+                .high()
+        });
+        dma.start();
+        spi.cr1.modify(|_, w| w.spe().set_bit());
+
+        let mut timer =
+            Timer::tim2(device.TIM2, &clocks, &mut rcc.apb1).start_count_down(POLL_HZ.hz());
+        timer.listen(Event::Update);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            sink: Sink {
+                dma,
+                buffer,
+                read_index: 0,
+                frame: [0; FRAME_LEN],
+                filled: 0,
+                next_sequence: None,
+                crc: device.CRC.new(&mut rcc.ahb),
+                stats: Stats::default(),
+            },
+            timer,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = TIM2, priority = 2, resources = [sink, timer])]
+    fn poll(cx: poll::Context) {
+        cx.resources.timer.clear_update_interrupt_flag();
+        cx.resources.sink.poll();
+    }
+
+    #[idle(resources = [sink, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let led = cx.resources.led;
+        let interface = I2CDIBuilder::new().init(cx.resources.i2c.take().unwrap());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut last_update = DWT::get_cycle_count();
+        let mut last_bytes = 0;
+        let mut lines: [String<32>; 5] = Default::default();
+        let mut led_on = false;
+        loop {
+            if DWT::get_cycle_count().wrapping_sub(last_update) < DISPLAY_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_update = last_update.wrapping_add(DISPLAY_MS * 1000 * SYSCLK_MHZ);
+
+            let stats = cx.resources.sink.lock(|sink| sink.stats);
+            let bytes_per_s = stats.bytes.wrapping_sub(last_bytes) * 1000 / DISPLAY_MS;
+            last_bytes = stats.bytes;
+            log_info!("{} B/s {:?}", bytes_per_s, stats);
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            write!(lines[0], "{:.1} kB/s", bytes_per_s as f32 / 1000.0).unwrap();
+            write!(lines[1], "Frames: {}", stats.frames).unwrap();
+            write!(lines[2], "CRC errors: {}", stats.crc_errors).unwrap();
+            write!(lines[3], "Lost frames: {}", stats.lost_frames).unwrap();
+            write!(lines[4], "Skipped: {}", stats.skipped).unwrap();
+
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 12))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+
+            // Blink LED 0 to check that everything is actually running.
+            // If the LED 0 is off, something went wrong.
+            led_on = !led_on;
+            if led_on {
+                led.set_low().unwrap();
+            } else {
+                led.set_high().unwrap();
+            }
+        }
+    }
+};
@@ -0,0 +1,332 @@
+//! Air quality station measuring the particulate matter concentrations
+//! (PM1.0, PM2.5, PM4.0 and PM10) with a Sensirion SPS30 and the eCO2 and
+//! eTVOC values with a CCS811. The values and the air quality index (AQI)
+//! calculated with the `aqi` module of this crate are shown on an SSD1306
+//! OLED display.
+//!
+//! The SPS30 is operated with a few commands over I2C:
+//! - Start measurement: the fan starts and new values are ready every second.
+//! - Read data-ready flag and read measured values: the values are sent as
+//!   big-endian floats. Every two bytes are followed by a CRC-8 checksum.
+//! - Start fan cleaning: the fan runs at maximum speed for 10 seconds to blow
+//!   out the dust. The sensor does this every week on its own while measuring
+//!   continuously. Press the button to do it now.
+//!
+//! The overall AQI is the highest of the PM2.5 and PM10 indices.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> SPS30 <-> CCS811 <-> Display <-> Button
+//! GND  <-> GND   <-> GND    <-> GND
+//! 5V   <-> VDD
+//! 3.3V           <-> VCC    <-> VDD     <-> +
+//! GND  <-> SEL
+//! PB8  <-> SCL   <-> SCL    <-> SCL
+//! PB9  <-> SDA   <-> SDA    <-> SDA
+//! PB7            <-> nWAKE
+//! PB12                                  <-> -
+//! ```
+//!
+//! The SPS30 needs 5V but its I2C lines work with the 3.3V pull-up
+//! resistors of the other modules. The I2C bus must not run faster than
+//! 100kHz for the SPS30.
+//!
+//! Run with:
+//! `cargo embed --example sps30-ccs811-air-quality-station-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    aqi::{aqi, Category, Pollutant},
+    log_error, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_ccs811::{prelude::*, AlgorithmResult, Ccs811Awake, MeasurementMode, SlaveAddr};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::{
+    blocking::i2c,
+    digital::v2::{InputPin, OutputPin},
+};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    delay::Delay,
+    i2c::{BlockingI2c, Mode},
+    pac,
+    prelude::*,
+};
+
+const SPS30_ADDRESS: u8 = 0x69;
+
+#[derive(Debug)]
+enum Error<E> {
+    /// I²C bus error
+    I2c(E),
+    /// The checksum of the received data does not match
+    Checksum,
+}
+
+/// Mass concentrations in µg/m³
+#[derive(Debug, Clone, Copy)]
+struct Measurement {
+    pm1_0: f32,
+    pm2_5: f32,
+    pm4_0: f32,
+    pm10: f32,
+}
+
+/// Minimal SPS30 driver
+struct Sps30<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C, E> Sps30<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+{
+    const START_MEASUREMENT: u16 = 0x0010;
+    const READ_DATA_READY: u16 = 0x0202;
+    const READ_MEASURED_VALUES: u16 = 0x0300;
+    const START_FAN_CLEANING: u16 = 0x5607;
+    /// Measured values as big-endian IEEE754 floats
+    const FLOAT_FORMAT: u8 = 0x03;
+
+    fn new(i2c: I2C) -> Self {
+        Sps30 { i2c }
+    }
+
+    fn start_measurement(&mut self) -> Result<(), Error<E>> {
+        let [cmd_high, cmd_low] = Self::START_MEASUREMENT.to_be_bytes();
+        let argument = [Self::FLOAT_FORMAT, 0];
+        self.i2c
+            .write(
+                SPS30_ADDRESS,
+                &[cmd_high, cmd_low, argument[0], argument[1], crc8(&argument)],
+            )
+            .map_err(Error::I2c)
+    }
+
+    fn start_fan_cleaning(&mut self) -> Result<(), Error<E>> {
+        self.command(Self::START_FAN_CLEANING)
+    }
+
+    fn data_ready(&mut self) -> Result<bool, Error<E>> {
+        let mut data = [0; 3];
+        self.read(Self::READ_DATA_READY, &mut data)?;
+        Ok(data[1] == 1)
+    }
+
+    fn read_measured_values(&mut self) -> Result<Measurement, Error<E>> {
+        // Only the mass concentrations. The number concentrations and the
+        // typical particle size follow.
+        let mut data = [0; 24];
+        self.read(Self::READ_MEASURED_VALUES, &mut data)?;
+        let value = |i: usize| {
+            let word = &data[i * 6..];
+            f32::from_be_bytes([word[0], word[1], word[3], word[4]])
+        };
+        Ok(Measurement {
+            pm1_0: value(0),
+            pm2_5: value(1),
+            pm4_0: value(2),
+            pm10: value(3),
+        })
+    }
+
+    fn command(&mut self, command: u16) -> Result<(), Error<E>> {
+        self.i2c
+            .write(SPS30_ADDRESS, &command.to_be_bytes())
+            .map_err(Error::I2c)
+    }
+
+    /// Send a command and read the answer checking the CRC of every word.
+    fn read(&mut self, command: u16, data: &mut [u8]) -> Result<(), Error<E>> {
+        self.command(command)?;
+        self.i2c.read(SPS30_ADDRESS, data).map_err(Error::I2c)?;
+        if data.chunks(3).all(|word| crc8(&word[..2]) == word[2]) {
+            Ok(())
+        } else {
+            Err(Error::Checksum)
+        }
+    }
+}
+
+/// Sensirion CRC-8 (polynomial 0x31, initial value 0xFF)
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFF_u8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("SPS30 + CCS811 air quality station example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let mut nwake = gpiob.pb7.into_push_pull_output(&mut gpiob.crl);
+    nwake.set_high().unwrap();
+    let button = gpiob.pb12.into_pull_down_input(&mut gpiob.crh);
+
+    let i2c = BlockingI2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        Mode::Standard {
+            frequency: 100_000.hz(),
+        },
+        clocks,
+        &mut rcc.apb1,
+        1000,
+        10,
+        1000,
+        1000,
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut sps30 = Sps30::new(manager.acquire());
+    sps30.start_measurement().unwrap();
+
+    let mut ccs811 = Ccs811Awake::new(manager.acquire(), SlaveAddr::default());
+    ccs811.software_reset().unwrap();
+    delay.delay_ms(10_u16);
+    let mut ccs811 = ccs811.start_application().ok().unwrap();
+    ccs811.set_mode(MeasurementMode::ConstantPower1s).unwrap();
+
+    let mut particles = Measurement {
+        pm1_0: 0.0,
+        pm2_5: 0.0,
+        pm4_0: 0.0,
+        pm10: 0.0,
+    };
+    let mut gas = AlgorithmResult {
+        eco2: 0,
+        etvoc: 0,
+        raw_current: 0,
+        raw_voltage: 0,
+    };
+    let mut cleaning_seconds = 0;
+    let mut lines: [String<32>; 5] = [
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+    ];
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+
+        if cleaning_seconds == 0 && button.is_high().unwrap() {
+            log_info!("Starting fan cleaning");
+            sps30.start_fan_cleaning().unwrap();
+            cleaning_seconds = 10;
+        }
+
+        match sps30.data_ready() {
+            Ok(true) => match sps30.read_measured_values() {
+                Ok(m) => particles = m,
+                Err(e) => log_error!("SPS30 error: {:?}", e),
+            },
+            Ok(false) => (),
+            Err(e) => log_error!("SPS30 error: {:?}", e),
+        }
+        if let Ok(data) = ccs811.data() {
+            gas = data;
+        }
+
+        // Concentrations beyond the highest breakpoint are off the scale.
+        let index = aqi(Pollutant::Pm2_5, particles.pm2_5)
+            .unwrap_or(500)
+            .max(aqi(Pollutant::Pm10, particles.pm10).unwrap_or(500));
+        log_info!(
+            "PM1.0: {:.1}, PM2.5: {:.1}, PM4.0: {:.1}, PM10: {:.1}, AQI: {}, eCO2: {}, eTVOC: {}",
+            particles.pm1_0,
+            particles.pm2_5,
+            particles.pm4_0,
+            particles.pm10,
+            index,
+            gas.eco2,
+            gas.etvoc
+        );
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        write!(lines[0], "PM1.0: {:.1} ug/m3", particles.pm1_0).unwrap();
+        write!(lines[1], "PM2.5: {:.1} ug/m3", particles.pm2_5).unwrap();
+        write!(lines[2], "PM10: {:.1} ug/m3", particles.pm10).unwrap();
+        if cleaning_seconds > 0 {
+            write!(lines[3], "Fan cleaning... {}", cleaning_seconds).unwrap();
+            cleaning_seconds -= 1;
+        } else {
+            write!(
+                lines[3],
+                "AQI {} {}",
+                index,
+                Category::from_aqi(index).name()
+            )
+            .unwrap();
+        }
+        write!(lines[4], "eCO2 {} eTVOC {}", gas.eco2, gas.etvoc).unwrap();
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 12))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+
+        delay.delay_ms(950_u16);
+    }
+}
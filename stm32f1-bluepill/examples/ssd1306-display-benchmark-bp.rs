@@ -0,0 +1,270 @@
+//! Render the same animation (a bouncing ball) over and over on an SSD1306
+//! OLED display and report how many frames per second are achieved and
+//! where the frame time goes.
+//!
+//! The example alternates between two ways of sending the frame buffer to
+//! the display, `FRAMES_PER_REPORT` frames each:
+//! - blocking I2C: the frame is rendered, then sent. The CPU waits for the
+//!   bus during the whole transfer.
+//! - DMA I2C: while DMA1 channel 6 sends one frame, the next frame is
+//!   rendered into a second buffer. The time spent waiting for the end of
+//!   the transfer afterwards is measured as CPU idle time, which your
+//!   application could use instead.
+//!
+//! `stm32f1xx-hal` 0.6 has no asynchronous I2C, so the DMA mode shows what
+//! an async driver would gain, as it would use the same hardware.
+//!
+//! Change `I2C_FREQUENCY_HZ` to see the effect of the bus speed.
+//! The results are printed through RTT for each mode.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> Display
+//! GND  <-> GND
+//! 3.3V <-> VDD
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example ssd1306-display-benchmark-bp --release`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::DWT;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    i2c_dma::I2c1Dma,
+    log_error, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    pixelcolor::BinaryColor, prelude::*, primitives::Circle, style::PrimitiveStyle,
+};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    i2c::{BlockingI2c, DutyCycle, Mode},
+    pac,
+    prelude::*,
+};
+
+const I2C_FREQUENCY_HZ: u32 = 400_000;
+const FRAMES_PER_REPORT: u32 = 100;
+const RADIUS: u32 = 8;
+
+const DISPLAY_ADDRESS: u8 = 0x3C;
+const WIDTH: usize = 128;
+const HEIGHT: usize = 64;
+/// Data control byte followed by the pixels
+const BUFFER_SIZE: usize = 1 + WIDTH * HEIGHT / 8;
+
+/// Command control byte followed by the commands to set up a 128x64 display
+/// with horizontal addressing over the whole screen
+const INIT: [u8; 32] = [
+    0x00, 0xAE, 0xD5, 0x80, 0xA8, 0x3F, 0xD3, 0x00, 0x40, 0x8D, 0x14, 0x20, 0x00, 0xA1, 0xC8, 0xDA,
+    0x12, 0x81, 0xCF, 0xD9, 0xF1, 0xDB, 0x40, 0xA4, 0xA6, 0x21, 0x00, 0x7F, 0x22, 0x00, 0x07, 0xAF,
+];
+
+/// Frame buffer laid out as it is sent to the display
+struct Frame<'a>(&'a mut [u8]);
+
+impl Frame<'_> {
+    fn clear(&mut self) {
+        self.0[0] = 0x40;
+        self.0[1..].iter_mut().for_each(|byte| *byte = 0);
+    }
+}
+
+impl DrawTarget<BinaryColor> for Frame<'_> {
+    type Error = core::convert::Infallible;
+
+    fn draw_pixel(&mut self, pixel: Pixel<BinaryColor>) -> Result<(), Self::Error> {
+        let Pixel(point, color) = pixel;
+        if point.x < 0 || point.y < 0 || point.x >= WIDTH as i32 || point.y >= HEIGHT as i32 {
+            return Ok(());
+        }
+        // Each byte contains a column of 8 pixels.
+        let (x, y) = (point.x as usize, point.y as usize);
+        let index = 1 + (y / 8) * WIDTH + x;
+        let mask = 1 << (y % 8);
+        match color {
+            BinaryColor::On => self.0[index] |= mask,
+            BinaryColor::Off => self.0[index] &= !mask,
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Size {
+        Size::new(WIDTH as u32, HEIGHT as u32)
+    }
+}
+
+/// Bouncing ball
+struct Ball {
+    position: Point,
+    speed: Point,
+}
+
+impl Ball {
+    fn step(&mut self) {
+        let max = Point::new(
+            WIDTH as i32 - 2 * RADIUS as i32,
+            HEIGHT as i32 - 2 * RADIUS as i32,
+        );
+        self.position += self.speed;
+        if self.position.x <= 0 || self.position.x >= max.x {
+            self.speed.x = -self.speed.x;
+        }
+        if self.position.y <= 0 || self.position.y >= max.y {
+            self.speed.y = -self.speed.y;
+        }
+    }
+
+    fn render(&self, buffer: &mut [u8]) {
+        let mut frame = Frame(buffer);
+        frame.clear();
+        Circle::new(
+            self.position + Point::new(RADIUS as i32, RADIUS as i32),
+            RADIUS,
+        )
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(&mut frame)
+        .unwrap();
+    }
+}
+
+/// Cycles spent in each part of the frames since the last report
+#[derive(Default)]
+struct Cycles {
+    render: u64,
+    transfer: u64,
+    idle: u64,
+}
+
+impl Cycles {
+    fn report(&self, mode: &str, frames: u32, sysclk_hz: u32) {
+        let total = self.render + self.transfer + self.idle;
+        let fps_x100 = u64::from(frames) * u64::from(sysclk_hz) * 100 / total;
+        log_info!(
+            "{} Hz {} I2C: {}.{:02} FPS, render {}%, transfer {}%, CPU idle {}%",
+            I2C_FREQUENCY_HZ,
+            mode,
+            fps_x100 / 100,
+            fps_x100 % 100,
+            self.render * 100 / total,
+            self.transfer * 100 / total,
+            self.idle * 100 / total
+        );
+    }
+}
+
+fn elapsed(since: u32) -> u64 {
+    u64::from(DWT::get_cycle_count().wrapping_sub(since))
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("SSD1306 display benchmark example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let i2c = BlockingI2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        Mode::Fast {
+            frequency: I2C_FREQUENCY_HZ.hz(),
+            duty_cycle: DutyCycle::Ratio2to1,
+        },
+        clocks,
+        &mut rcc.apb1,
+        1000,
+        10,
+        1000,
+        1000,
+    );
+    let dma_ch6 = dp.DMA1.split(&mut rcc.ahb).6;
+    let mut i2c = I2c1Dma::new(i2c, dma_ch6);
+
+    i2c.blocking().write(DISPLAY_ADDRESS, &INIT).unwrap();
+    panic_display::register(Bus::I2c1);
+
+    // The frame being sent and the frame being rendered
+    let mut front: &'static mut [u8] =
+        cortex_m::singleton!(: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE]).unwrap();
+    let mut back: &'static mut [u8] =
+        cortex_m::singleton!(: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE]).unwrap();
+
+    let mut ball = Ball {
+        position: Point::new(0, 0),
+        speed: Point::new(3, 2),
+    };
+    ball.render(front);
+
+    let sysclk_hz = clocks.sysclk().0;
+    let mut use_dma = false;
+    let mut frames = 0;
+    let mut cycles = Cycles::default();
+    loop {
+        if use_dma {
+            let start = DWT::get_cycle_count();
+            i2c.start_write(DISPLAY_ADDRESS, front);
+            cycles.transfer += elapsed(start);
+
+            let start = DWT::get_cycle_count();
+            ball.step();
+            ball.render(back);
+            cycles.render += elapsed(start);
+
+            let start = DWT::get_cycle_count();
+            let (sent, result) = i2c.wait();
+            cycles.idle += elapsed(start);
+            if let Err(e) = result {
+                log_error!(every = 100, "Display transfer failed: {:?}", e);
+            }
+            front = back;
+            back = sent;
+        } else {
+            let start = DWT::get_cycle_count();
+            i2c.blocking().write(DISPLAY_ADDRESS, front).unwrap();
+            cycles.transfer += elapsed(start);
+
+            let start = DWT::get_cycle_count();
+            ball.step();
+            ball.render(front);
+            cycles.render += elapsed(start);
+        }
+        frames += 1;
+
+        if frames == FRAMES_PER_REPORT {
+            cycles.report(if use_dma { "DMA" } else { "blocking" }, frames, sysclk_hz);
+            use_dma = !use_dma;
+            frames = 0;
+            cycles = Cycles::default();
+        }
+    }
+}
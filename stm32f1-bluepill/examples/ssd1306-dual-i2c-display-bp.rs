@@ -0,0 +1,127 @@
+//! Drive two SSD1306 OLED displays which have the same address by connecting
+//! each of them to a different I2C bus.
+//!
+//! This is an alternative to using an I2C switch like the TCA9548A when you
+//! have two devices with the same address and a free I2C peripheral.
+//! Each display shows which bus it is connected to and a counter.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and I2C2.
+//!
+//! ```
+//! BP   <-> Display 1 <-> Display 2
+//! GND  <-> GND       <-> GND
+//! 3.3V <-> VDD       <-> VDD
+//! PB8  <-> SCL
+//! PB9  <-> SDA
+//! PB10               <-> SCL
+//! PB11               <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example ssd1306-dual-i2c-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    i2c_speed, log_info,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*};
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Dual I2C SSD1306 example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl1 = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda1 = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let scl2 = gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh);
+    let sda2 = gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c1, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl1, sda1),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+    );
+    let (i2c2, _) =
+        i2c_speed::i2c2_with_fallback(dp.I2C2, (scl2, sda2), clocks, &mut rcc.apb1, |i2c| {
+            i2c_speed::probe_addresses(i2c, &[0x3C])
+        });
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let interface1 = I2CDIBuilder::new().init(i2c1);
+    let mut disp1: GraphicsMode<_> = Builder::new().connect(interface1).into();
+    disp1.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp1.flush().unwrap();
+
+    let interface2 = I2CDIBuilder::new().init(i2c2);
+    let mut disp2: GraphicsMode<_> = Builder::new().connect(interface2).into();
+    disp2.init().unwrap();
+    disp2.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut counter: u32 = 0;
+    let mut buffer: heapless::String<32> = heapless::String::new();
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+        delay.delay_ms(50_u16);
+
+        buffer.clear();
+        write!(buffer, "I2C1: {}", counter).unwrap();
+        disp1.clear();
+        Text::new(&buffer, Point::zero())
+            .into_styled(text_style)
+            .draw(&mut disp1)
+            .unwrap();
+        disp1.flush().unwrap();
+
+        buffer.clear();
+        write!(buffer, "I2C2: {}", counter).unwrap();
+        disp2.clear();
+        Text::new(&buffer, Point::zero())
+            .into_styled(text_style)
+            .draw(&mut disp2)
+            .unwrap();
+        disp2.flush().unwrap();
+
+        counter = counter.wrapping_add(1);
+    }
+}
@@ -0,0 +1,201 @@
+//! Show the eCO2 and eTVOC values measured with a CCS811 on an SSD1331 color
+//! OLED display with a background color that goes from green through yellow
+//! to red as the air quality gets worse.
+//!
+//! The background hue is calculated from the eCO2 value: green up to
+//! `GOOD_ECO2`, yellow at `FAIR_ECO2` and red from `BAD_ECO2` on, with smooth
+//! transitions in between. The color is converted to RGB565, which has 5 bits
+//! for red, 6 bits for green and 5 bits for blue.
+//!
+//! The same works on an SSD1351 128x128 display with the corresponding driver.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1 for the
+//! display and I2C1 for the sensor.
+//!
+//! ```
+//! BP   <-> SSD1331 <-> CCS811
+//! GND  <-> GND     <-> GND
+//! 3.3V <-> VCC     <-> VCC
+//! GND  <-> CS
+//! PA5  <-> SCL
+//! PA7  <-> SDA
+//! PB1  <-> DC
+//! PB10 <-> RES
+//! PB8              <-> SCL
+//! PB9              <-> SDA
+//! PB7              <-> nWAKE
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example ssd1331-ccs811-color-air-quality-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{log_info, panic_display as _};
+use embedded_ccs811::{prelude::*, AlgorithmResult, Ccs811Awake, MeasurementMode, SlaveAddr};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::Rectangle,
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::{digital::v2::OutputPin, spi::MODE_0};
+use heapless::String;
+use nb::block;
+use rtt_target::rtt_init_print;
+use ssd1331::{DisplayRotation, Ssd1331};
+use stm32f1xx_hal::{
+    delay::Delay,
+    i2c::{BlockingI2c, DutyCycle, Mode},
+    pac,
+    prelude::*,
+    spi::Spi,
+};
+
+const GOOD_ECO2: u16 = 600;
+const FAIR_ECO2: u16 = 1000;
+const BAD_ECO2: u16 = 1500;
+
+/// Background color for an eCO2 value
+fn air_quality_color(eco2: u16) -> Rgb565 {
+    // Hue in degrees: 120 is green, 60 yellow and 0 red.
+    let hue = if eco2 <= GOOD_ECO2 {
+        120.0
+    } else if eco2 <= FAIR_ECO2 {
+        120.0 - 60.0 * f32::from(eco2 - GOOD_ECO2) / f32::from(FAIR_ECO2 - GOOD_ECO2)
+    } else if eco2 <= BAD_ECO2 {
+        60.0 - 60.0 * f32::from(eco2 - FAIR_ECO2) / f32::from(BAD_ECO2 - FAIR_ECO2)
+    } else {
+        0.0
+    };
+    // Full saturation and value: one of red and green is always at maximum.
+    let (red, green) = if hue < 60.0 {
+        (1.0, hue / 60.0)
+    } else {
+        ((120.0 - hue) / 60.0, 1.0)
+    };
+    Rgb565::new((red * 31.0) as u8, (green * 63.0) as u8, 0)
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("CCS811 color air quality example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let mut nwake = gpiob.pb7.into_push_pull_output(&mut gpiob.crl);
+    nwake.set_high().unwrap();
+
+    let i2c = BlockingI2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        Mode::Fast {
+            frequency: 100_000.hz(),
+            duty_cycle: DutyCycle::Ratio2to1,
+        },
+        clocks,
+        &mut rcc.apb1,
+        1000,
+        10,
+        1000,
+        1000,
+    );
+
+    // SPI1
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+    let dc = gpiob.pb1.into_push_pull_output(&mut gpiob.crl);
+    let mut rst = gpiob.pb10.into_push_pull_output(&mut gpiob.crh);
+
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        MODE_0,
+        8_u32.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut disp = Ssd1331::new(spi, dc, DisplayRotation::Rotate0);
+    disp.reset(&mut rst, &mut delay).unwrap();
+    disp.init().unwrap();
+    disp.flush().unwrap();
+
+    let mut ccs811 = Ccs811Awake::new(i2c, SlaveAddr::default());
+    ccs811.software_reset().unwrap();
+    delay.delay_ms(10_u16);
+    let mut lines: [String<32>; 2] = [String::new(), String::new()];
+
+    let mut ccs811 = ccs811.start_application().ok().unwrap();
+    let temperature_c = 25.0;
+    let humidity_perc = 60.0;
+    ccs811
+        .set_environment(temperature_c, humidity_perc)
+        .unwrap();
+    ccs811.set_mode(MeasurementMode::ConstantPower1s).unwrap();
+
+    let default = AlgorithmResult {
+        eco2: 9999,
+        etvoc: 9999,
+        raw_current: 255,
+        raw_voltage: 9999,
+    };
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+        delay.delay_ms(50_u16);
+
+        let data = block!(ccs811.data()).unwrap_or(default);
+        let background = air_quality_color(data.eco2);
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        write!(lines[0], "eCO2: {}", data.eco2).unwrap();
+        write!(lines[1], "eTVOC: {}", data.etvoc).unwrap();
+
+        Rectangle::new(Point::zero(), Point::new(95, 63))
+            .into_styled(PrimitiveStyle::with_fill(background))
+            .draw(&mut disp)
+            .unwrap();
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(Rgb565::BLACK)
+            .background_color(background)
+            .build();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(4, 16 + i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+    }
+}
@@ -20,6 +20,10 @@
 
 use core::fmt::Write;
 use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    clock, i2c_speed, log_info,
+    panic_display::{self, Bus},
+};
 use embedded_graphics::{
     fonts::{Font6x8, Text},
     pixelcolor::BinaryColor,
@@ -27,28 +31,23 @@ use embedded_graphics::{
     style::TextStyleBuilder,
 };
 use embedded_hal::digital::v2::OutputPin;
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use ssd1306::{prelude::*, Builder, I2CDIBuilder};
-use stm32f1xx_hal::{
-    delay::Delay,
-    i2c::{BlockingI2c, DutyCycle, Mode},
-    pac,
-    prelude::*,
-};
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*};
 use tcs3472::{AllChannelMeasurement, Tcs3472};
 
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("TCS34725 example");
+    log_info!("TCS34725 example");
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
 
     let mut flash = dp.FLASH.constrain();
     let mut rcc = dp.RCC.constrain();
 
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
+    clock::log_clocks(&clocks);
 
     let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
 
@@ -57,20 +56,14 @@ fn main() -> ! {
     let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
     let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
 
-    let i2c = BlockingI2c::i2c1(
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
         dp.I2C1,
         (scl, sda),
         &mut afio.mapr,
-        Mode::Fast {
-            frequency: 400_000.hz(),
-            duty_cycle: DutyCycle::Ratio2to1,
-        },
         clocks,
         &mut rcc.apb1,
-        1000,
-        10,
-        1000,
-        1000,
+        // TCS34725 sensor and display
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x29, 0x3C]),
     );
 
     let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
@@ -81,6 +74,7 @@ fn main() -> ! {
     let interface = I2CDIBuilder::new().init(manager.acquire());
     let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
     disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
     disp.flush().unwrap();
 
     let text_style = TextStyleBuilder::new(Font6x8)
@@ -0,0 +1,160 @@
+//! Show a rotating rainbow on 16 RGB LEDs driven by two daisy-chained
+//! TLC5947 24-channel constant-current LED drivers.
+//!
+//! Unlike the PCA9685, the TLC5947 has no addresses or registers. It is a
+//! 288-bit shift register (24 channels of 12 bits) written over SPI. The
+//! data of the first board comes out at its DOUT pin into the DIN of the next
+//! board, so a chain is written as one long shift register, starting with
+//! the last channel of the last board. Nothing changes until the data is
+//! transferred to the outputs with a pulse on the latch (XLAT) pin, so all
+//! LEDs of the chain change at the same time.
+//!
+//! The blank (OE on some boards) pin turns all outputs off while it is
+//! high. At power-up the shift registers contain random data, so the
+//! outputs are kept blank until the first frame has been latched.
+//!
+//! Each board drives 8 RGB LEDs (channel 0: red of the first LED, channel 1:
+//! green, channel 2: blue, channel 3: red of the second LED...). The colors
+//! come from the `color` module of this crate and are gamma corrected so
+//! that the brightness changes look smooth.
+//!
+//! A minimal TLC5947 driver is included in this example.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1.
+//!
+//! ```
+//! BP   <-> TLC5947 #1 <-> TLC5947 #2
+//! GND  <-> GND        <-> GND
+//! 5V   <-> V+         <-> V+
+//! PA5  <-> CLK        <-> CLK
+//! PA7  <-> DIN
+//!          DOUT       <-> DIN
+//! PA4  <-> LAT        <-> LAT
+//! PA3  <-> OE         <-> OE
+//! ```
+//!
+//! Connect the common anodes of the LEDs to V+ and the cathodes to the
+//! outputs. The current is set by the resistor on the boards.
+//!
+//! The TLC59711 uses a different protocol (16-bit channels with a command
+//! header and no latch pin) and is not covered here.
+//!
+//! Run with:
+//! `cargo embed --example tlc5947-rgb-leds-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    color::{gamma, hue_to_rgb},
+    log_info, panic_display as _,
+};
+use embedded_hal::{blocking::spi, digital::v2::OutputPin, spi::MODE_0};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*, spi::Spi};
+
+const BOARDS: usize = 2;
+const CHANNELS: usize = 24 * BOARDS;
+const LEDS: usize = CHANNELS / 3;
+/// Brightness of the LEDs in 1/16
+const BRIGHTNESS: u16 = 4;
+
+/// Minimal driver for a chain of TLC5947 boards
+struct Tlc5947<SPI, LAT, BLANK> {
+    spi: SPI,
+    latch: LAT,
+    blank: BLANK,
+    values: [u16; CHANNELS],
+}
+
+impl<SPI, LAT, BLANK, E> Tlc5947<SPI, LAT, BLANK>
+where
+    SPI: spi::Write<u8, Error = E>,
+    LAT: OutputPin,
+    BLANK: OutputPin,
+{
+    /// Create the driver. The outputs stay blank until `update()`.
+    fn new(spi: SPI, mut latch: LAT, mut blank: BLANK) -> Self {
+        blank.set_high().ok();
+        latch.set_low().ok();
+        Tlc5947 {
+            spi,
+            latch,
+            blank,
+            values: [0; CHANNELS],
+        }
+    }
+
+    /// Set a channel of the chain: 0-4095
+    fn set(&mut self, channel: usize, value: u16) {
+        self.values[channel] = value.min(4095);
+    }
+
+    /// Shift the values into the chain and latch them.
+    fn update(&mut self) -> Result<(), E> {
+        // The last channel goes first. Two 12-bit values fit in 3 bytes.
+        for pair in self.values.chunks(2).rev() {
+            let (high, low) = (pair[1], pair[0]);
+            self.spi.write(&[
+                (high >> 4) as u8,
+                (((high & 0x0F) << 4) | (low >> 8)) as u8,
+                low as u8,
+            ])?;
+        }
+        self.latch.set_high().ok();
+        self.latch.set_low().ok();
+        self.blank.set_low().ok();
+        Ok(())
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("TLC5947 example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+
+    // SPI1
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+    let latch = gpioa.pa4.into_push_pull_output(&mut gpioa.crl);
+    let blank = gpioa.pa3.into_push_pull_output(&mut gpioa.crl);
+
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        MODE_0,
+        1_u32.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    let mut delay = Delay::new(cp.SYST, clocks);
+    let mut tlc = Tlc5947::new(spi, latch, blank);
+
+    let mut hue = 0;
+    loop {
+        // Spread the rainbow over all LEDs and rotate it.
+        for led in 0..LEDS {
+            let (r, g, b) = hue_to_rgb(hue + (led * 360 / LEDS) as u16);
+            for (i, value) in [r, g, b].iter().enumerate() {
+                tlc.set(3 * led + i, gamma(*value) * BRIGHTNESS / 16);
+            }
+        }
+        tlc.update().unwrap();
+        hue = (hue + 1) % 360;
+        delay.delay_ms(20_u16);
+    }
+}
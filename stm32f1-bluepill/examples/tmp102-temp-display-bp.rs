@@ -1,6 +1,11 @@
 //! Continuously read the temperature with a TMP102 sensor and display it in
 //! an SSD1306 OLED display.
 //!
+//! The second line shows the core and bus frequencies of the clock preset,
+//! see the `clock` module. The I2C bus falls back to 100kHz or 10kHz if
+//! the devices do not answer reliably at 400kHz, the third line shows the
+//! speed in use, see the `i2c_speed` module.
+//!
 //! Introductory blog post with some pictures here:
 //! https://blog.eldruin.com/tmp1x2-temperature-sensor-driver-in-rust/
 //!
@@ -23,6 +28,10 @@
 
 use core::fmt::Write;
 use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    clock, i2c_speed, log_info,
+    panic_display::{self, Bus},
+};
 use embedded_graphics::{
     fonts::{Font6x8, Text},
     pixelcolor::BinaryColor,
@@ -30,28 +39,26 @@ use embedded_graphics::{
     style::TextStyleBuilder,
 };
 use embedded_hal::digital::v2::OutputPin;
-use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::rtt_init_print;
 use ssd1306::{prelude::*, Builder, I2CDIBuilder};
-use stm32f1xx_hal::{
-    delay::Delay,
-    i2c::{BlockingI2c, DutyCycle, Mode},
-    pac,
-    prelude::*,
-};
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*};
 use tmp1x2::{SlaveAddr, Tmp1x2};
 
+const TMP102_ADDRESS: u8 = 0x48;
+const DISPLAY_ADDRESS: u8 = 0x3C;
+
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
-    rprintln!("TMP102 example");
+    log_info!("TMP102 example");
     let cp = cortex_m::Peripherals::take().unwrap();
     let dp = pac::Peripherals::take().unwrap();
 
     let mut flash = dp.FLASH.constrain();
     let mut rcc = dp.RCC.constrain();
 
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
+    clock::log_clocks(&clocks);
 
     let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
 
@@ -60,20 +67,15 @@ fn main() -> ! {
     let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
     let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
 
-    let i2c = BlockingI2c::i2c1(
+    // Fall back to a slower bus speed if the sensor or the display keep
+    // failing, e.g. on long wires.
+    let (i2c, i2c_hz) = i2c_speed::i2c1_with_fallback(
         dp.I2C1,
         (scl, sda),
         &mut afio.mapr,
-        Mode::Fast {
-            frequency: 400_000.hz(),
-            duty_cycle: DutyCycle::Ratio2to1,
-        },
         clocks,
         &mut rcc.apb1,
-        1000,
-        10,
-        1000,
-        1000,
+        |i2c| i2c_speed::probe_addresses(i2c, &[TMP102_ADDRESS, DISPLAY_ADDRESS]),
     );
 
     let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
@@ -84,6 +86,7 @@ fn main() -> ! {
     let interface = I2CDIBuilder::new().init(manager.acquire());
     let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
     disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
     disp.flush().unwrap();
 
     let text_style = TextStyleBuilder::new(Font6x8)
@@ -93,6 +96,13 @@ fn main() -> ! {
     let mut tmp102 = Tmp1x2::new(manager.acquire(), SlaveAddr::default());
 
     let mut buffer: heapless::String<64> = heapless::String::new();
+    let mut clock_line: heapless::String<32> = heapless::String::new();
+    clock::write_summary(&mut clock_line, &clocks).unwrap();
+    let mut i2c_line: heapless::String<32> = heapless::String::new();
+    match i2c_hz {
+        Some(frequency_hz) => write!(i2c_line, "I2C {}kHz", frequency_hz / 1000).unwrap(),
+        None => write!(i2c_line, "I2C unreliable").unwrap(),
+    }
     loop {
         // Blink LED 0 to check that everything is actually running.
         // If the LED 0 is off, something went wrong.
@@ -111,6 +121,14 @@ fn main() -> ! {
             .into_styled(text_style)
             .draw(&mut disp)
             .unwrap();
+        Text::new(&clock_line, Point::new(0, 16))
+            .into_styled(text_style)
+            .draw(&mut disp)
+            .unwrap();
+        Text::new(&i2c_line, Point::new(0, 32))
+            .into_styled(text_style)
+            .draw(&mut disp)
+            .unwrap();
 
         disp.flush().unwrap();
     }
@@ -0,0 +1,472 @@
+//! Emulate a TMP112 temperature sensor with the I2C2 peripheral in slave
+//! mode, with a temperature set over a serial console. Use it to test a
+//! driver against a device which does exactly what you tell it, e.g. with
+//! negative temperatures, alerts or a device which does not answer.
+//!
+//! The emulated register map is that of the TMP112 (and TMP102) at address
+//! 0x48:
+//! - Pointer register: the first byte written selects the register.
+//! - 0x00 Temperature (read-only): 12-bit, or 13-bit in extended mode.
+//! - 0x01 Configuration: the shutdown, extended mode, polarity, one-shot,
+//!   fault queue and conversion rate bits are stored. The AL bit shows the
+//!   alert in comparator mode, without the fault queue.
+//! - 0x02 T_LOW and 0x03 T_HIGH: alert thresholds.
+//!
+//! Conversions are instant: the temperature register changes as soon as a
+//! new temperature is set, except in shutdown mode. There a one-shot
+//! conversion updates it.
+//!
+//! I2C2 is set up as a slave with the `i2c_slave` module. Every address
+//! match, byte and stop condition is handled in the I2C2 event interrupt.
+//! Clock stretching holds the master until the interrupt has answered.
+//!
+//! To test without a second board, connect I2C1 to I2C2: the idle task
+//! reads the emulated sensor every second through I2C1 with the tmp1x2
+//! driver and prints the result through RTT. To test your own driver,
+//! connect the I2C bus of your board to PB10 and PB11 instead.
+//!
+//! Connect with a serial terminal at 115200 baud and send one command per
+//! line:
+//! - `TEMP -12.5`: set the temperature in degrees Celsius.
+//! - `NACK ON`, `NACK OFF`: stop or start answering to the address.
+//! - `STATUS`: print the registers and the number of transactions.
+//!
+//! Every command is answered with a line starting with `OK` or `ERR`.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1, I2C2 and USART1.
+//!
+//! ```
+//! BP   <-> BP   <-> USB-serial adapter
+//! GND           <-> GND
+//! PB10 <-> PB8 (SCL)
+//! PB11 <-> PB9 (SDA)
+//! PA9           <-> RX
+//! PA10          <-> TX
+//! ```
+//!
+//! Connect a 4.7K pull-up resistor from each of SCL and SDA to 3.3V.
+//!
+//! Run with:
+//! `cargo embed --example tmp112-emulator-i2c-slave-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::{peripheral::DWT, singleton};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_slave::{Device, I2c2Slave},
+    log_error, log_info, panic_display as _,
+    uart::{LineBuffer, RxBuffer, RxReader, TxBuffer, TxWriter, UartRx, UartTx},
+};
+use embedded_hal::digital::v2::OutputPin;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    gpio::{
+        gpiob::{PB8, PB9},
+        gpioc::PC13,
+        Alternate, OpenDrain, Output, PushPull, State,
+    },
+    i2c::{BlockingI2c, Mode},
+    pac,
+    prelude::*,
+    serial::{Config, Rx, Serial, Tx},
+};
+use tmp1x2::{SlaveAddr, Tmp1x2};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+const PCLK1_MHZ: u32 = 36;
+const BAUD_RATE: u32 = 115_200;
+const LINE_LEN: usize = 40;
+const RX_BUFFER: usize = 64;
+const TX_BUFFER: usize = 256;
+const POLL_MS: u32 = 10;
+const ADDRESS: u8 = 0x48;
+
+// Registers
+const TEMPERATURE: usize = 0;
+const CONFIG: usize = 1;
+const T_LOW: usize = 2;
+const T_HIGH: usize = 3;
+const NAMES: [&str; 4] = ["Temperature", "Config", "T_LOW", "T_HIGH"];
+
+// Configuration register bits
+const CONFIG_OS: u16 = 1 << 15;
+const CONFIG_POL: u16 = 1 << 10;
+const CONFIG_SD: u16 = 1 << 8;
+const CONFIG_AL: u16 = 1 << 5;
+const CONFIG_EM: u16 = 1 << 4;
+/// OS, F1-F0, POL, TM, SD, CR1-CR0 and EM. R1-R0 and AL are read-only.
+const CONFIG_WRITABLE: u16 = 0x9FD0;
+
+/// Register map and bus state of the emulated TMP112
+pub struct Tmp112 {
+    /// Temperature in 1/16 degrees Celsius
+    temperature: i16,
+    registers: [u16; 4],
+    pointer: usize,
+    transmit: bool,
+    /// Bytes transferred since the address
+    count: usize,
+    received: [u8; 2],
+    alert: bool,
+    transactions: u32,
+}
+
+impl Tmp112 {
+    fn new() -> Self {
+        let mut tmp112 = Tmp112 {
+            temperature: 25 * 16,
+            // Power-on values: 80C and 75C thresholds
+            registers: [0, 0x60A0, 0x4B00, 0x5000],
+            pointer: TEMPERATURE,
+            transmit: false,
+            count: 0,
+            received: [0; 2],
+            alert: false,
+            transactions: 0,
+        };
+        tmp112.convert();
+        tmp112
+    }
+
+    fn set_temperature(&mut self, celsius: f32) {
+        self.temperature = libm::roundf(celsius * 16.0) as i16;
+        if self.registers[CONFIG] & CONFIG_SD == 0 {
+            self.convert();
+        }
+    }
+
+    /// Update the temperature register and the alert as after a conversion.
+    fn convert(&mut self) {
+        let extended = self.registers[CONFIG] & CONFIG_EM != 0;
+        // 12-bit values are left-aligned. 13-bit values leave bit 0 set.
+        let (shift, max) = if extended { (3, 4095) } else { (4, 2047) };
+        let value = self.temperature.clamp(-max - 1, max);
+        self.registers[TEMPERATURE] = ((value << shift) as u16) | u16::from(extended);
+
+        // Comparator mode: active at T_HIGH until below T_LOW
+        let high = self.registers[T_HIGH] as i16 >> shift;
+        let low = self.registers[T_LOW] as i16 >> shift;
+        if value >= high {
+            self.alert = true;
+        } else if value < low {
+            self.alert = false;
+        }
+        self.update_alert_bit();
+    }
+
+    /// AL is 1 while the alert is inactive unless POL is set.
+    fn update_alert_bit(&mut self) {
+        let polarity = self.registers[CONFIG] & CONFIG_POL != 0;
+        if self.alert == polarity {
+            self.registers[CONFIG] |= CONFIG_AL;
+        } else {
+            self.registers[CONFIG] &= !CONFIG_AL;
+        }
+    }
+
+    /// Store a register written by the master.
+    fn commit(&mut self) {
+        if self.transmit || self.count < 3 {
+            return;
+        }
+        self.count = 0;
+        let value = u16::from_be_bytes(self.received);
+        match self.pointer {
+            CONFIG => {
+                let config = self.registers[CONFIG];
+                self.registers[CONFIG] = (value & CONFIG_WRITABLE) | (config & !CONFIG_WRITABLE);
+                // Running, or a one-shot conversion in shutdown mode
+                if value & CONFIG_SD == 0 || value & CONFIG_OS != 0 {
+                    self.convert();
+                }
+                self.update_alert_bit();
+            }
+            T_LOW | T_HIGH => {
+                self.registers[self.pointer] = value;
+                self.convert();
+            }
+            // The temperature register is read-only.
+            _ => (),
+        }
+    }
+}
+
+impl Device for Tmp112 {
+    /// The address matched: a new transaction or a repeated start.
+    fn start(&mut self, transmit: bool) {
+        self.commit();
+        self.transmit = transmit;
+        self.count = 0;
+        self.transactions += 1;
+    }
+
+    /// Byte written by the master
+    fn write(&mut self, byte: u8) {
+        match self.count {
+            0 => self.pointer = usize::from(byte & 0x03),
+            1 | 2 => self.received[self.count - 1] = byte,
+            _ => (),
+        }
+        self.count += 1;
+    }
+
+    /// Byte to send to the master: the selected register, MSB first. Longer
+    /// reads repeat it.
+    fn read(&mut self) -> u8 {
+        let bytes = self.registers[self.pointer].to_be_bytes();
+        let byte = bytes[self.count % 2];
+        self.count += 1;
+        byte
+    }
+
+    fn stop(&mut self) {
+        self.commit();
+        self.transmit = false;
+        self.count = 0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Temperature(f32),
+    Nack(bool),
+    Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CommandError {
+    UnknownCommand,
+    MissingArgument,
+    InvalidNumber,
+    OutOfRange,
+}
+
+fn parse(line: &str) -> Result<Command, CommandError> {
+    let mut words = line.split_whitespace();
+    let command = words.next().ok_or(CommandError::UnknownCommand)?;
+    if command.eq_ignore_ascii_case("STATUS") {
+        return Ok(Command::Status);
+    }
+    let argument = words.next().ok_or(CommandError::MissingArgument)?;
+    if command.eq_ignore_ascii_case("TEMP") {
+        return match argument.parse::<f32>() {
+            Ok(celsius) if (-55.0..=150.0).contains(&celsius) => Ok(Command::Temperature(celsius)),
+            Ok(_) => Err(CommandError::OutOfRange),
+            Err(_) => Err(CommandError::InvalidNumber),
+        };
+    }
+    if command.eq_ignore_ascii_case("NACK") {
+        if argument.eq_ignore_ascii_case("ON") {
+            return Ok(Command::Nack(true));
+        }
+        if argument.eq_ignore_ascii_case("OFF") {
+            return Ok(Command::Nack(false));
+        }
+        return Err(CommandError::MissingArgument);
+    }
+    Err(CommandError::UnknownCommand)
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        uart_rx: UartRx<Rx<pac::USART1>, RX_BUFFER>,
+        uart_tx: UartTx<Tx<pac::USART1>, TX_BUFFER>,
+        reader: RxReader<RX_BUFFER>,
+        writer: TxWriter<TX_BUFFER>,
+        slave: I2c2Slave,
+        tmp112: Tmp112,
+        bus_errors: u32,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("TMP112 emulator example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        // Enable the I2C2 clock before handing the RCC over to the HAL.
+        device.RCC.apb1enr.modify(|_, w| w.i2c2en().set_bit());
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(PCLK1_MHZ.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+        let rx = gpioa.pa10;
+        let serial = Serial::usart1(
+            device.USART1,
+            (tx, rx),
+            &mut afio.mapr,
+            Config::default().baudrate(BAUD_RATE.bps()),
+            clocks,
+            &mut rcc.apb2,
+        );
+        let (tx, mut rx) = serial.split();
+        rx.listen();
+        let (uart_rx, reader) = singleton!(: RxBuffer<RX_BUFFER> = RxBuffer::new())
+            .unwrap()
+            .split(rx);
+        let (uart_tx, writer) = singleton!(: TxBuffer<TX_BUFFER> = TxBuffer::new())
+            .unwrap()
+            .split(tx, || rtic::pend(pac::Interrupt::USART1));
+
+        // I2C2 pins of the emulated sensor
+        let _slave_scl = gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh);
+        let _slave_sda = gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh);
+        let slave = I2c2Slave::new(device.I2C2, ADDRESS, clocks);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let i2c = BlockingI2c::i2c1(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            Mode::Standard {
+                frequency: 100_000.hz(),
+            },
+            clocks,
+            &mut rcc.apb1,
+            1000,
+            10,
+            1000,
+            1000,
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            uart_rx,
+            uart_tx,
+            reader,
+            writer,
+            slave,
+            tmp112: Tmp112::new(),
+            bus_errors: 0,
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    /// Answer the master: address match, received and requested bytes and
+    /// the stop condition.
+    #[task(binds = I2C2_EV, priority = 3, resources = [slave, tmp112])]
+    fn i2c2_ev(cx: i2c2_ev::Context) {
+        cx.resources.slave.on_event(cx.resources.tmp112);
+    }
+
+    #[task(binds = I2C2_ER, priority = 3, resources = [slave, tmp112, bus_errors])]
+    fn i2c2_er(cx: i2c2_er::Context) {
+        if cx.resources.slave.on_error(cx.resources.tmp112) {
+            *cx.resources.bus_errors += 1;
+        }
+    }
+
+    /// Move the received bytes into the buffer and send the waiting ones.
+    #[task(binds = USART1, priority = 2, resources = [uart_rx, uart_tx])]
+    fn usart1(cx: usart1::Context) {
+        cx.resources.uart_rx.on_interrupt();
+        let uart_tx = cx.resources.uart_tx;
+        if uart_tx.on_interrupt() {
+            uart_tx.tx().listen();
+        } else {
+            uart_tx.tx().unlisten();
+        }
+    }
+
+    #[idle(resources = [reader, writer, slave, tmp112, bus_errors, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let reader = cx.resources.reader;
+        let tx = cx.resources.writer;
+        let led = cx.resources.led;
+        let mut sensor = Tmp1x2::new(cx.resources.i2c.take().unwrap(), SlaveAddr::default());
+        writeln!(tx, "OK ready").unwrap();
+
+        let mut line_buffer: LineBuffer<LINE_LEN> = LineBuffer::new();
+        let mut last_poll = DWT::get_cycle_count();
+        let mut polls = 0_u32;
+        let mut led_on = false;
+        loop {
+            if DWT::get_cycle_count().wrapping_sub(last_poll) < POLL_MS * 1000 * SYSCLK_MHZ {
+                continue;
+            }
+            last_poll = last_poll.wrapping_add(POLL_MS * 1000 * SYSCLK_MHZ);
+            polls += 1;
+
+            for line in reader.lines(&mut line_buffer) {
+                log_info!("Command: {}", line);
+                match parse(&line) {
+                    Ok(Command::Temperature(celsius)) => {
+                        cx.resources
+                            .tmp112
+                            .lock(|tmp112| tmp112.set_temperature(celsius));
+                        writeln!(tx, "OK TEMP {:.4}", celsius).unwrap();
+                    }
+                    Ok(Command::Nack(nack)) => {
+                        cx.resources.slave.lock(|slave| slave.set_ack(!nack));
+                        writeln!(tx, "OK NACK {}", if nack { "ON" } else { "OFF" }).unwrap();
+                    }
+                    Ok(Command::Status) => {
+                        let (registers, transactions, alert) = cx
+                            .resources
+                            .tmp112
+                            .lock(|tmp112| (tmp112.registers, tmp112.transactions, tmp112.alert));
+                        for (name, value) in NAMES.iter().zip(registers.iter()) {
+                            writeln!(tx, "{}: 0x{:04x}", name, value).unwrap();
+                        }
+                        let errors = cx.resources.bus_errors.lock(|errors| *errors);
+                        writeln!(tx, "Alert: {}", alert).unwrap();
+                        writeln!(tx, "Bus errors: {}", errors).unwrap();
+                        writeln!(tx, "OK STATUS {} transactions", transactions).unwrap();
+                    }
+                    Err(e) => writeln!(tx, "ERR {:?}", e).unwrap(),
+                }
+            }
+
+            // Once per second read the emulated sensor through I2C1.
+            if polls % (1000 / POLL_MS) == 0 {
+                match sensor.read_temperature() {
+                    Ok(celsius) => log_info!("Read through I2C1: {:.4}C", celsius),
+                    Err(e) => log_error!("Read through I2C1 failed: {:?}", e),
+                }
+
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                led_on = !led_on;
+                if led_on {
+                    led.set_low().unwrap();
+                } else {
+                    led.set_high().unwrap();
+                }
+            }
+        }
+    }
+};
@@ -0,0 +1,249 @@
+//! Turn the Bluepill into a USB media controller: turn a rotary encoder to
+//! change the volume of the computer and swipe over an APDS-9960 gesture
+//! sensor to skip to the next or previous track.
+//!
+//! The board shows up as a USB HID consumer control device, like the media
+//! keys of a keyboard, so it works without a driver. Every key is sent as a
+//! press followed by a release:
+//! - Turning the encoder sends one volume up or down key per detent.
+//! - Pressing the encoder button mutes the sound.
+//! - Swiping left or right sends the previous or next track key, swiping
+//!   up or down play/pause.
+//!
+//! The rotary encoder is decoded by TIM4 in encoder mode, like in the
+//! pwm-pulse-generator-encoder-console-bp example. The gestures are
+//! recognized with the `gesture` module of this crate. Which way is left
+//! depends on how the sensor is mounted.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1 and USB.
+//!
+//! ```
+//! BP   <-> Encoder <-> APDS-9960
+//! GND  <-> GND     <-> GND
+//! 3.3V <-> +       <-> VCC
+//! PB6  <-> CLK
+//! PB7  <-> DT
+//! PB5  <-> SW
+//! PB8              <-> SCL
+//! PB9              <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example usb-hid-media-encoder-apds9960-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use apds9960::Apds9960;
+use cortex_m::asm::delay;
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    gesture::{Gesture, GestureDetector},
+    i2c_speed, log_info, log_warn, monotonic, panic_display as _,
+};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use heapless::spsc::Queue;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    pac,
+    prelude::*,
+    usb::{Peripheral, UsbBus},
+};
+use usb_device::prelude::*;
+use usbd_hid::{
+    descriptor::{MediaKeyboardReport, SerializedDescriptor},
+    hid_class::HIDClass,
+};
+
+// Usage ids of the consumer page
+const VOLUME_UP: u16 = 0xE9;
+const VOLUME_DOWN: u16 = 0xEA;
+const MUTE: u16 = 0xE2;
+const NEXT_TRACK: u16 = 0xB5;
+const PREVIOUS_TRACK: u16 = 0xB6;
+const PLAY_PAUSE: u16 = 0xCD;
+/// No key pressed
+const RELEASE: u16 = 0;
+
+const UPDATE_MS: u32 = 10;
+const HID_POLL_MS: u8 = 10;
+/// Time without gesture data after which the hand is gone
+const GESTURE_END_MS: u32 = 50;
+/// Encoder counts per detent (TIM4 counts every edge of both signals)
+const COUNTS_PER_STEP: i16 = 4;
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("USB HID media controller example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    // Enable the TIM4 clock before handing the RCC over to the HAL.
+    dp.RCC.apb1enr.modify(|_, w| w.tim4en().set_bit());
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    // USB needs a 48MHz clock: 72MHz / 1.5
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+    assert!(clocks.usbclk_valid());
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // APDS9960 sensor
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x39]),
+    );
+    let mut sensor = Apds9960::new(i2c);
+    sensor.enable().unwrap();
+    // The gesture engine starts when something comes close enough.
+    sensor.enable_proximity().unwrap();
+    sensor.set_gesture_proximity_entry_threshold(40).unwrap();
+    sensor.set_gesture_proximity_exit_threshold(30).unwrap();
+    sensor.enable_gesture().unwrap();
+
+    // TIM4 channel 1 and 2 inputs in encoder mode 3 (count on both edges
+    // of both inputs) with an input filter against contact bounce
+    let _encoder_a = gpiob.pb6.into_pull_up_input(&mut gpiob.crl);
+    let _encoder_b = gpiob.pb7.into_pull_up_input(&mut gpiob.crl);
+    let encoder_timer = dp.TIM4;
+    encoder_timer.ccmr1_input().modify(|_, w| {
+        w.cc1s()
+            .ti1()
+            .ic1f()
+            .fdts_div32_n8()
+            .cc2s()
+            .ti2()
+            .ic2f()
+            .fdts_div32_n8()
+    });
+    encoder_timer.smcr.modify(|_, w| w.sms().encoder_mode_3());
+    encoder_timer.arr.write(|w| w.arr().bits(0xFFFF));
+    encoder_timer.cr1.modify(|_, w| w.cen().enabled());
+    let encoder_button = gpiob.pb5.into_pull_up_input(&mut gpiob.crl);
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    led.set_high().unwrap();
+
+    // The Bluepill has a pull-up resistor on D+. Pull D+ low for a moment
+    // so that the computer notices the device after a reset.
+    let mut usb_dp = gpioa.pa12.into_push_pull_output(&mut gpioa.crh);
+    usb_dp.set_low().unwrap();
+    delay(clocks.sysclk().0 / 100);
+    let usb = Peripheral {
+        usb: dp.USB,
+        pin_dm: gpioa.pa11,
+        pin_dp: usb_dp.into_floating_input(&mut gpioa.crh),
+    };
+    let usb_bus = UsbBus::new(usb);
+    let mut hid = HIDClass::new(&usb_bus, MediaKeyboardReport::desc(), HID_POLL_MS);
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27db))
+        .manufacturer("driver-examples")
+        .product("Media controller")
+        .serial_number("MC1")
+        .build();
+
+    let mut keys: Queue<u16, 16> = Queue::new();
+    let mut pressed = false;
+    let mut detector = GestureDetector::new();
+    let mut last_gesture_data = monotonic::millis();
+    let mut last_count = encoder_timer.cnt.read().cnt().bits();
+    // Counts which do not make up a full step yet
+    let mut partial = 0_i16;
+    let mut button_history = 0_u8;
+    let mut last_update = monotonic::millis();
+    loop {
+        usb_dev.poll(&mut [&mut hid]);
+
+        // Send the press of the next key, then its release.
+        if usb_dev.state() == UsbDeviceState::Configured {
+            let usage_id = if pressed {
+                Some(RELEASE)
+            } else {
+                keys.peek().copied()
+            };
+            if let Some(usage_id) = usage_id {
+                if hid.push_input(&MediaKeyboardReport { usage_id }).is_ok() {
+                    if !pressed {
+                        keys.dequeue();
+                    }
+                    pressed = !pressed;
+                }
+            }
+        }
+
+        let now = monotonic::millis();
+        if now.wrapping_sub(last_update) < UPDATE_MS {
+            continue;
+        }
+        last_update = now;
+
+        let count = encoder_timer.cnt.read().cnt().bits();
+        partial += count.wrapping_sub(last_count) as i16;
+        last_count = count;
+        let steps = partial / COUNTS_PER_STEP;
+        partial %= COUNTS_PER_STEP;
+        let key = if steps > 0 { VOLUME_UP } else { VOLUME_DOWN };
+        for _ in 0..steps.abs() {
+            // Steps beyond the queue are dropped.
+            keys.enqueue(key).ok();
+        }
+
+        // Mute once the button has been pressed for 8 samples.
+        let previous_history = button_history;
+        button_history = (button_history << 1) | u8::from(encoder_button.is_low().unwrap());
+        if button_history == 0xFF && previous_history != 0xFF {
+            keys.enqueue(MUTE).ok();
+        }
+
+        let mut dataset = [0; 4];
+        while sensor.read_gesture_data(&mut dataset).is_ok() {
+            detector.update(dataset);
+            last_gesture_data = now;
+        }
+        if now.wrapping_sub(last_gesture_data) >= GESTURE_END_MS {
+            if let Some(gesture) = detector.finish() {
+                log_info!("Gesture: {:?}", gesture);
+                let key = match gesture {
+                    Gesture::Left => PREVIOUS_TRACK,
+                    Gesture::Right => NEXT_TRACK,
+                    Gesture::Up | Gesture::Down => PLAY_PAUSE,
+                };
+                if keys.enqueue(key).is_err() {
+                    log_warn!("Too many keys queued");
+                }
+            }
+        }
+        // LED 0 is on while keys are being sent.
+        if keys.is_empty() && !pressed {
+            led.set_high().unwrap();
+        } else {
+            led.set_low().unwrap();
+        }
+    }
+}
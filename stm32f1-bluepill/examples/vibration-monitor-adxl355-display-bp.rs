@@ -0,0 +1,462 @@
+//! Monitor the vibration of a machine like ISO 10816 and show the RMS
+//! velocity in frequency bands and the state on an SSD1306 OLED display.
+//!
+//! An ADXL355 low noise accelerometer, screwed to the bearing housing,
+//! samples 3 axes at 4000Hz into its FIFO. When the FIFO reaches the
+//! watermark the INT1 pin interrupts and the samples are read over SPI, so
+//! none are lost while the display is updated. Blocks of `N` samples of each
+//! axis go through a Hann window and a 512-point FFT with the `fft` module,
+//! the `vibration` module integrates the bins to the RMS velocity in each
+//! band, and the highest axis counts. The levels are averaged over about a
+//! second and the overall band rates the machine as "GOOD", "WARNING" or
+//! "ALARM" with the limits of ISO 10816-3 for medium machines (15-300kW) on
+//! rigid foundations. The LED is on for a warning and blinks for an alarm.
+//!
+//! There is no driver crate for the ADXL355 (or the ISM330) for this
+//! version of `embedded-hal`, so the example accesses its registers
+//! directly.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1 and
+//! I2C1.
+//!
+//! ```
+//! BP   <-> ADXL355 <-> Display
+//! GND  <-> GND     <-> GND
+//! 3.3V <-> VDD     <-> VDD
+//! 3.3V <-> VDDIO
+//! PA4  <-> CS
+//! PA5  <-> SCLK
+//! PA6  <-> MISO
+//! PA7  <-> MOSI
+//! PB0  <-> INT1
+//! PB8              <-> SCL
+//! PB9              <-> SDA
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example vibration-monitor-adxl355-display-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    exti::setup_exti_pin,
+    fft::Fft,
+    i2c_speed, log_error, log_info,
+    panic_display::{self, Bus},
+    vibration::{velocity_rms_mm_s, zone, Band, Zone},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::{
+    blocking::spi::{Transfer, Write as SpiWrite},
+    digital::v2::{InputPin, OutputPin},
+    spi::MODE_0,
+};
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::{PA4, PA5, PA6, PA7},
+        gpiob::{PB0, PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Floating, Input, OpenDrain, Output, PushPull, State,
+    },
+    i2c::BlockingI2c,
+    pac,
+    prelude::*,
+    spi::{self, Spi, Spi1NoRemap},
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+type Spi1 = Spi<
+    pac::SPI1,
+    Spi1NoRemap,
+    (
+        PA5<Alternate<PushPull>>,
+        PA6<Input<Floating>>,
+        PA7<Alternate<PushPull>>,
+    ),
+>;
+
+const SYSCLK_MHZ: u32 = 72;
+/// FFT points, 128ms at the sample rate
+const N: usize = 512;
+const SAMPLE_RATE_HZ: f32 = 4000.0;
+/// Acceleration of a sample shifted to 16 bits, at ±2g 256000 / 16 per g
+const M_S2_PER_COUNT: f32 = 9.80665 / 16000.0;
+/// Weight of a new block in the average of the levels
+const AVERAGE: f32 = 8.0;
+/// ISO 10816-3 group 2, rigid foundation: zone B/C and C/D boundaries
+const WARNING_MM_S: f32 = 2.8;
+const ALARM_MM_S: f32 = 4.5;
+/// The first band rates the machine.
+const BANDS: [(&str, Band); 3] = [
+    (
+        "10-1000Hz",
+        Band {
+            low_hz: 10.0,
+            high_hz: 1000.0,
+        },
+    ),
+    (
+        "10-100Hz",
+        Band {
+            low_hz: 10.0,
+            high_hz: 100.0,
+        },
+    ),
+    (
+        "100-1000Hz",
+        Band {
+            low_hz: 100.0,
+            high_hz: 1000.0,
+        },
+    ),
+];
+const AXES: [char; 3] = ['X', 'Y', 'Z'];
+
+// ADXL355 registers
+const DEVID_AD: u8 = 0x00;
+const FIFO_ENTRIES: u8 = 0x05;
+const FIFO_DATA: u8 = 0x11;
+const FILTER: u8 = 0x28;
+const FIFO_SAMPLES: u8 = 0x29;
+const INT_MAP: u8 = 0x2a;
+const RANGE: u8 = 0x2c;
+const POWER_CTL: u8 = 0x2d;
+const RESET: u8 = 0x2f;
+/// DEVID_AD, DEVID_MST and PARTID
+const ID: [u8; 3] = [0xad, 0x1d, 0xed];
+const RESET_CODE: u8 = 0x52;
+/// INT1 active high, ±2g
+const RANGE_2G: u8 = 0x41;
+/// No high pass filter, 4000Hz output data rate
+const FILTER_4000HZ: u8 = 0x00;
+/// FIFO_FULL on INT1
+const FULL_EN1: u8 = 0x02;
+/// Measurement mode without temperature
+const MEASURE: u8 = 0x02;
+/// FIFO entries (axis samples) for the interrupt, 5ms of samples
+const WATERMARK: u8 = 60;
+const FIFO_LEN: usize = 96;
+// Flags in the last byte of a FIFO entry
+const X_MARKER: u8 = 0x01;
+const EMPTY: u8 = 0x02;
+
+/// ADXL355 on SPI
+pub struct Adxl355 {
+    spi: Spi1,
+    cs: PA4<Output<PushPull>>,
+}
+
+impl Adxl355 {
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), spi::Error> {
+        self.cs.set_low().ok();
+        let result = self.spi.write(&[register << 1, value]);
+        self.cs.set_high().ok();
+        result
+    }
+
+    fn read(&mut self, register: u8, buffer: &mut [u8]) -> Result<(), spi::Error> {
+        for byte in buffer.iter_mut() {
+            *byte = 0;
+        }
+        self.cs.set_low().ok();
+        let result = self
+            .spi
+            .write(&[register << 1 | 1])
+            .and_then(|_| self.spi.transfer(buffer).map(|_| ()));
+        self.cs.set_high().ok();
+        result
+    }
+
+    /// Read the FIFO, 3 bytes per entry, and return the number of entries.
+    /// The address of FIFO_DATA does not increment, so one read gets them
+    /// all.
+    fn read_fifo(&mut self, buffer: &mut [u8; FIFO_LEN * 3]) -> Result<usize, spi::Error> {
+        let mut entries = [0];
+        self.read(FIFO_ENTRIES, &mut entries)?;
+        let entries = usize::from(entries[0] & 0x7f).min(FIFO_LEN);
+        self.read(FIFO_DATA, &mut buffer[..entries * 3])?;
+        Ok(entries)
+    }
+}
+
+/// Blocks of samples of the 3 axes for the FFT
+pub struct Block {
+    samples: [[i16; N]; 3],
+    len: usize,
+    /// Axis of the next entry, 3 until the next X axis entry
+    axis: usize,
+}
+
+impl Block {
+    fn add(&mut self, entry: &[u8]) {
+        if entry[2] & EMPTY != 0 {
+            return;
+        }
+        if entry[2] & X_MARKER != 0 {
+            self.axis = 0;
+        }
+        // Full until the idle task takes it, or waiting for the X axis
+        if self.len == N || self.axis == 3 {
+            return;
+        }
+        // 20 bits, left aligned, to 16 bits
+        let raw = u32::from(entry[0]) << 24 | u32::from(entry[1]) << 16 | u32::from(entry[2]) << 8;
+        self.samples[self.axis][self.len] = ((raw as i32) >> 16) as i16;
+        self.axis += 1;
+        if self.axis == 3 {
+            self.len += 1;
+        }
+    }
+}
+
+/// Remove the gravity and the offset of the samples of an axis.
+fn remove_mean(samples: &[i16; N], re: &mut [i16; N]) {
+    let mean = samples.iter().map(|s| i32::from(*s)).sum::<i32>() / N as i32;
+    for (value, sample) in re.iter_mut().zip(samples.iter()) {
+        *value = (i32::from(*sample) - mean).clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        accel: Adxl355,
+        int1: PB0<Input<Floating>>,
+        block: Block,
+        // Taken by the idle task, which creates the driver.
+        i2c: Option<I2cBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Vibration monitor example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // SPI1
+        let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+        let miso = gpioa.pa6;
+        let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+        let cs = gpioa
+            .pa4
+            .into_push_pull_output_with_state(&mut gpioa.crl, State::High);
+
+        let spi = Spi::spi1(
+            device.SPI1,
+            (sck, miso, mosi),
+            &mut afio.mapr,
+            MODE_0,
+            8_u32.mhz(),
+            clocks,
+            &mut rcc.apb2,
+        );
+
+        let mut accel = Adxl355 { spi, cs };
+        let mut id = [0; 3];
+        accel.read(DEVID_AD, &mut id).unwrap();
+        if id != ID {
+            log_error!("No ADXL355, ID {:x?}", id);
+        }
+        accel.write_register(RESET, RESET_CODE).unwrap();
+        cortex_m::asm::delay(10 * 1000 * SYSCLK_MHZ);
+        accel.write_register(RANGE, RANGE_2G).unwrap();
+        accel.write_register(FILTER, FILTER_4000HZ).unwrap();
+        accel.write_register(FIFO_SAMPLES, WATERMARK).unwrap();
+        accel.write_register(INT_MAP, FULL_EN1).unwrap();
+
+        let mut int1 = gpiob.pb0.into_floating_input(&mut gpiob.crl);
+        setup_exti_pin(&mut int1, &mut afio, &device.EXTI, Edge::RISING);
+        accel.write_register(POWER_CTL, MEASURE).unwrap();
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let (i2c, _) = i2c_speed::i2c1_with_fallback(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            accel,
+            int1,
+            block: Block {
+                samples: [[0; N]; 3],
+                len: 0,
+                axis: 3,
+            },
+            i2c: Some(i2c),
+            led,
+        }
+    }
+
+    #[task(binds = EXTI0, priority = 2, resources = [accel, int1, block])]
+    fn accel_fifo(cx: accel_fifo::Context) {
+        let int1 = cx.resources.int1;
+        int1.clear_interrupt_pending_bit();
+        let mut buffer = [0; FIFO_LEN * 3];
+        // INT1 stays high while the FIFO is at the watermark, read until it
+        // falls so that the next entries raise it again.
+        while int1.is_high().unwrap() {
+            match cx.resources.accel.read_fifo(&mut buffer) {
+                Ok(entries) => {
+                    for entry in buffer[..entries * 3].chunks(3) {
+                        cx.resources.block.add(entry);
+                    }
+                }
+                Err(e) => {
+                    log_error!("FIFO: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    #[idle(resources = [block, i2c, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let led = cx.resources.led;
+        let interface = I2CDIBuilder::new().init(cx.resources.i2c.take().unwrap());
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c1);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let fft: Fft<N> = Fft::new();
+        let mut axes = [[0_i16; N]; 3];
+        let mut re = [0_i16; N];
+        let mut im = [0_i16; N];
+        // Averaged squares of the velocity and the highest axis of each band
+        let mut levels = [0.0_f32; BANDS.len()];
+        let mut highest = [0; BANDS.len()];
+        let mut line: String<32> = String::new();
+        let mut blocks = 0_u32;
+        loop {
+            let captured = cx.resources.block.lock(|block| {
+                if block.len < N {
+                    return false;
+                }
+                axes = block.samples;
+                block.len = 0;
+                block.axis = 3;
+                true
+            });
+            if !captured {
+                continue;
+            }
+
+            let start = DWT::get_cycle_count();
+            let mut velocities = [0.0_f32; BANDS.len()];
+            for (axis, samples) in axes.iter().enumerate() {
+                remove_mean(samples, &mut re);
+                im = [0; N];
+                fft.window(&mut re);
+                fft.transform(&mut re, &mut im);
+                for (band, (_, range)) in BANDS.iter().enumerate() {
+                    let velocity =
+                        velocity_rms_mm_s(&re, &im, SAMPLE_RATE_HZ, M_S2_PER_COUNT, *range);
+                    if velocity > velocities[band] {
+                        velocities[band] = velocity;
+                        highest[band] = axis;
+                    }
+                }
+            }
+            for (level, velocity) in levels.iter_mut().zip(velocities.iter()) {
+                *level += (velocity * velocity - *level) / AVERAGE;
+            }
+            let fft_us = DWT::get_cycle_count().wrapping_sub(start) / SYSCLK_MHZ;
+
+            let overall = libm::sqrtf(levels[0]);
+            let state = zone(overall, WARNING_MM_S, ALARM_MM_S);
+            disp.clear();
+            Text::new("Velocity mm/s RMS", Point::zero())
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+            for (band, (name, _)) in BANDS.iter().enumerate() {
+                line.clear();
+                write!(
+                    line,
+                    "{:<10} {:5.2} {}",
+                    name,
+                    libm::sqrtf(levels[band]),
+                    AXES[highest[band]]
+                )
+                .unwrap();
+                Text::new(&line, Point::new(0, 12 + 10 * band as i32))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            let state_text = match state {
+                Zone::Good => "State: GOOD",
+                Zone::Warning => "State: WARNING",
+                Zone::Alarm => "State: ALARM",
+            };
+            Text::new(state_text, Point::new(0, 52))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+            disp.flush().unwrap();
+
+            blocks += 1;
+            if blocks % 8 == 0 {
+                log_info!("{:.2} mm/s {:?}, FFT: {}us", overall, state, fft_us);
+            }
+            let led_on = match state {
+                Zone::Good => false,
+                Zone::Warning => true,
+                Zone::Alarm => blocks % 2 == 0,
+            };
+            if led_on {
+                led.set_low().unwrap();
+            } else {
+                led.set_high().unwrap();
+            }
+        }
+    }
+};
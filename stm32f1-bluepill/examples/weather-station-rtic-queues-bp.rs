@@ -0,0 +1,515 @@
+//! Weather station logger structured as producer, consumer and UI tasks
+//! which communicate over `heapless` SPSC queues under RTIC. Copy it as a
+//! template for your own multi-sensor firmware.
+//!
+//! - Producers: `sample_climate` reads the temperature and humidity with an
+//!   HDC2080 every `CLIMATE_PERIOD_S` seconds and `sample_light` the
+//!   ambient light with a VEML6030 every `LIGHT_PERIOD_S` seconds. Each
+//!   reading goes into two queues: one to the logger and one to the UI.
+//! - Consumer: `logger` appends the readings to `WEATHER.CSV` on an SD
+//!   card. The producers spawn it, a reading never waits for the card.
+//! - UI: the idle task shows the latest readings on an SSD1306 OLED display
+//!   and blinks the LED for each one.
+//!
+//! A `Queue` has one producer and one consumer end. Both producer tasks
+//! use the same ends because they run at the same priority, so they never
+//! preempt each other. A task at another priority needs its own queue. When
+//! a queue is full, the reading is dropped and counted instead of blocking
+//! the producer, the display shows the count. To add a sensor, add a
+//! variant to `Measurement` and a task like `sample_light`.
+//!
+//! The sensors are on I2C1 and the display on I2C2, so the UI never holds a
+//! bus the producers need. Without an SD card the example keeps running and
+//! only shows the readings. The card must be formatted with FAT16 or FAT32.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1, I2C2
+//! and SPI1.
+//!
+//! ```
+//! BP   <-> HDC2080 <-> VEML6030 <-> Display <-> SD card module
+//! GND  <-> GND     <-> GND      <-> GND     <-> GND
+//! 3.3V <-> VCC     <-> VCC      <-> VDD     <-> 3.3V
+//! PB8  <-> SCL     <-> SCL
+//! PB9  <-> SDA     <-> SDA
+//! PB10                          <-> SCL
+//! PB11                          <-> SDA
+//! PA4                                       <-> CS
+//! PA5                                       <-> SCK
+//! PA6                                       <-> MISO
+//! PA7                                       <-> MOSI
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example weather-station-rtic-queues-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_error, log_info, log_warn,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::digital::v2::OutputPin;
+use embedded_sdmmc::{
+    BlockSpi, Controller, Directory, Mode as FileMode, SdMmcSpi, TimeSource, Timestamp, Volume,
+    VolumeIdx,
+};
+use hdc20xx::{mode as Hdc20xxMode, Hdc20xx, SlaveAddr as Hdc20xxSlaveAddr};
+use heapless::{
+    spsc::{Consumer, Producer, Queue},
+    String,
+};
+use nb::block;
+use rtic::app;
+use rtic::cyccnt::U32Ext;
+use rtt_target::rtt_init_print;
+use shared_bus_rtic::SharedBus;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{
+    gpio::{
+        gpioa::{PA4, PA5, PA6, PA7},
+        gpiob::{PB10, PB11, PB8, PB9},
+        gpioc::PC13,
+        Alternate, Floating, Input, OpenDrain, Output, PushPull, State,
+    },
+    i2c::{BlockingI2c, Mode},
+    pac,
+    prelude::*,
+    spi::{Mode as SpiMode, Phase, Polarity, Spi, Spi1NoRemap},
+};
+use veml6030::{SlaveAddr as Veml6030SlaveAddr, Veml6030};
+
+const SYSCLK_HZ: u32 = 72_000_000;
+const CLIMATE_PERIOD_S: u32 = 10;
+const LIGHT_PERIOD_S: u32 = 2;
+const FILE_NAME: &str = "WEATHER.CSV";
+// Holds QUEUE_LEN - 1 readings
+const QUEUE_LEN: usize = 8;
+
+type SensorBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+type DisplayBus = BlockingI2c<pac::I2C2, (PB10<Alternate<OpenDrain>>, PB11<Alternate<OpenDrain>>)>;
+type SdSpi = Spi<
+    pac::SPI1,
+    Spi1NoRemap,
+    (
+        PA5<Alternate<PushPull>>,
+        PA6<Input<Floating>>,
+        PA7<Alternate<PushPull>>,
+    ),
+>;
+type SdCard = SdMmcSpi<SdSpi, PA4<Output<PushPull>>>;
+
+// Readings lost because a queue was full, and lines written to the card
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+static LOGGED: AtomicU32 = AtomicU32::new(0);
+
+/// Value of a sensor
+#[derive(Debug, Clone, Copy)]
+pub enum Measurement {
+    Climate { temperature: f32, humidity: f32 },
+    Light { lux: f32 },
+    Error { sensor: &'static str },
+}
+
+/// What the producers send to the consumers
+#[derive(Debug, Clone, Copy)]
+pub struct Reading {
+    uptime_s: u32,
+    measurement: Measurement,
+}
+
+/// All drivers on the shared sensor bus must be in one resource, see the
+/// shared-bus-rtic documentation.
+pub struct Sensors {
+    hdc2080: Hdc20xx<SharedBus<SensorBus>, Hdc20xxMode::OneShot>,
+    veml6030: Veml6030<SharedBus<SensorBus>>,
+}
+
+/// Producer ends of the queues to the logger and to the UI
+pub struct Outputs {
+    log: Producer<'static, Reading, QUEUE_LEN>,
+    ui: Producer<'static, Reading, QUEUE_LEN>,
+}
+
+impl Outputs {
+    /// Send a reading to both consumers without waiting.
+    fn send(&mut self, reading: Reading) {
+        for producer in [&mut self.log, &mut self.ui].iter_mut() {
+            if producer.enqueue(reading).is_err() {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// CSV file on the SD card
+pub struct SdLog {
+    controller: Controller<BlockSpi<'static, SdSpi, PA4<Output<PushPull>>>, FixedTime>,
+    volume: Volume,
+    root: Directory,
+}
+
+impl SdLog {
+    /// Append a line. The file is closed again, so the card can be pulled
+    /// at any time.
+    fn append(&mut self, line: &str) -> Result<(), ()> {
+        let mut file = self
+            .controller
+            .open_file_in_dir(
+                &mut self.volume,
+                &self.root,
+                FILE_NAME,
+                FileMode::ReadWriteCreateOrAppend,
+            )
+            .map_err(|_| ())?;
+        let header = if file.length() == 0 {
+            self.controller
+                .write(
+                    &mut self.volume,
+                    &mut file,
+                    b"uptime_s,temperature,humidity,lux\n",
+                )
+                .map(|_| ())
+        } else {
+            Ok(())
+        };
+        let written = header.and_then(|_| {
+            self.controller
+                .write(&mut self.volume, &mut file, line.as_bytes())
+        });
+        let closed = self.controller.close_file(&self.volume, file);
+        match (written, closed) {
+            (Ok(_), Ok(_)) => Ok(()),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Fixed date for the file, as there is no real-time clock
+pub struct FixedTime;
+
+impl TimeSource for FixedTime {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 51,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true, monotonic = rtic::cyccnt::CYCCNT)]
+const APP: () = {
+    struct Resources {
+        sensors: Sensors,
+        outputs: Outputs,
+        log_consumer: Consumer<'static, Reading, QUEUE_LEN>,
+        sd_log: Option<SdLog>,
+        ui_consumer: Consumer<'static, Reading, QUEUE_LEN>,
+        // Taken by the idle task, which creates the display driver.
+        display_bus: Option<DisplayBus>,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init(schedule = [sample_climate, sample_light])]
+    fn init(cx: init::Context) -> init::LateResources {
+        static mut LOG_QUEUE: Queue<Reading, QUEUE_LEN> = Queue::new();
+        static mut UI_QUEUE: Queue<Reading, QUEUE_LEN> = Queue::new();
+        static mut CARD: Option<SdCard> = None;
+
+        rtt_init_print!();
+        log_info!("Weather station RTIC queues example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_HZ.hz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = device.GPIOA.split(&mut rcc.apb2);
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+        let sensor_bus = BlockingI2c::i2c1(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            Mode::Standard {
+                frequency: 100_000.hz(),
+            },
+            clocks,
+            &mut rcc.apb1,
+            1000,
+            10,
+            1000,
+            1000,
+        );
+
+        let scl2 = gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh);
+        let sda2 = gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh);
+        let (display_bus, _) = i2c_speed::i2c2_with_fallback(
+            device.I2C2,
+            (scl2, sda2),
+            clocks,
+            &mut rcc.apb1,
+            |i2c| i2c_speed::probe_addresses(i2c, &[0x3C]),
+        );
+
+        let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+        let miso = gpioa.pa6;
+        let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+        let cs = gpioa.pa4.into_push_pull_output(&mut gpioa.crl);
+        let spi = Spi::spi1(
+            device.SPI1,
+            (sck, miso, mosi),
+            &mut afio.mapr,
+            SpiMode {
+                polarity: Polarity::IdleLow,
+                phase: Phase::CaptureOnFirstTransition,
+            },
+            400.khz(),
+            clocks,
+            &mut rcc.apb2,
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        let manager = shared_bus_rtic::new!(sensor_bus, SensorBus);
+        let hdc2080 = Hdc20xx::new(manager.acquire(), Hdc20xxSlaveAddr::default());
+        let mut veml6030 = Veml6030::new(manager.acquire(), Veml6030SlaveAddr::default());
+        if veml6030.enable().is_err() {
+            log_warn!("VEML6030 not found");
+        }
+
+        // The card driver lives in a `static` so that the controller which
+        // borrows it can be a resource.
+        let card = CARD.get_or_insert(SdMmcSpi::new(spi, cs));
+        let sd_log = match card.acquire() {
+            Ok(block_device) => {
+                let mut controller = Controller::new(block_device, FixedTime);
+                match controller.get_volume(VolumeIdx(0)) {
+                    Ok(volume) => match controller.open_root_dir(&volume) {
+                        Ok(root) => Some(SdLog {
+                            controller,
+                            volume,
+                            root,
+                        }),
+                        Err(e) => {
+                            log_error!("Cannot open the root directory: {:?}", e);
+                            None
+                        }
+                    },
+                    Err(e) => {
+                        log_error!("Cannot open the volume: {:?}", e);
+                        None
+                    }
+                }
+            }
+            Err(e) => {
+                log_warn!("No SD card, not logging: {:?}", e);
+                None
+            }
+        };
+
+        cx.schedule.sample_climate(cx.start).unwrap();
+        cx.schedule.sample_light(cx.start).unwrap();
+
+        let (log_producer, log_consumer) = LOG_QUEUE.split();
+        let (ui_producer, ui_consumer) = UI_QUEUE.split();
+        init::LateResources {
+            sensors: Sensors { hdc2080, veml6030 },
+            outputs: Outputs {
+                log: log_producer,
+                ui: ui_producer,
+            },
+            log_consumer,
+            sd_log,
+            ui_consumer,
+            display_bus: Some(display_bus),
+            led,
+        }
+    }
+
+    // Producers: both run at the same priority, so they share `sensors`
+    // and `outputs` without locks.
+    #[task(priority = 2, resources = [sensors, outputs], schedule = [sample_climate], spawn = [logger])]
+    fn sample_climate(cx: sample_climate::Context) {
+        static mut SAMPLES: u32 = 0;
+
+        let measurement = match block!(cx.resources.sensors.hdc2080.read()) {
+            Ok(data) => Measurement::Climate {
+                temperature: data.temperature,
+                humidity: data.humidity.unwrap_or(0.0),
+            },
+            Err(e) => {
+                log_error!(every = 10, "HDC2080 error: {:?}", e);
+                Measurement::Error { sensor: "HDC2080" }
+            }
+        };
+        cx.resources.outputs.send(Reading {
+            uptime_s: *SAMPLES * CLIMATE_PERIOD_S,
+            measurement,
+        });
+        *SAMPLES += 1;
+        // Already pending if the logger has not caught up yet. It empties
+        // the whole queue when it runs, so nothing is lost.
+        cx.spawn.logger().ok();
+        cx.schedule
+            .sample_climate(cx.scheduled + (CLIMATE_PERIOD_S * SYSCLK_HZ).cycles())
+            .unwrap();
+    }
+
+    #[task(priority = 2, resources = [sensors, outputs], schedule = [sample_light], spawn = [logger])]
+    fn sample_light(cx: sample_light::Context) {
+        static mut SAMPLES: u32 = 0;
+
+        let measurement = match cx.resources.sensors.veml6030.read_lux() {
+            Ok(lux) => Measurement::Light { lux },
+            Err(e) => {
+                log_error!(every = 10, "VEML6030 error: {:?}", e);
+                Measurement::Error { sensor: "VEML6030" }
+            }
+        };
+        cx.resources.outputs.send(Reading {
+            uptime_s: *SAMPLES * LIGHT_PERIOD_S,
+            measurement,
+        });
+        *SAMPLES += 1;
+        cx.spawn.logger().ok();
+        cx.schedule
+            .sample_light(cx.scheduled + (LIGHT_PERIOD_S * SYSCLK_HZ).cycles())
+            .unwrap();
+    }
+
+    // Consumer: slow SD card writes run below the producers.
+    #[task(priority = 1, resources = [log_consumer, sd_log])]
+    fn logger(cx: logger::Context) {
+        while let Some(reading) = cx.resources.log_consumer.dequeue() {
+            let sd_log = match cx.resources.sd_log {
+                Some(sd_log) => sd_log,
+                None => continue,
+            };
+            let mut line: String<48> = String::new();
+            match reading.measurement {
+                Measurement::Climate {
+                    temperature,
+                    humidity,
+                } => writeln!(
+                    line,
+                    "{},{:.2},{:.2},",
+                    reading.uptime_s, temperature, humidity
+                ),
+                Measurement::Light { lux } => writeln!(line, "{},,,{:.1}", reading.uptime_s, lux),
+                Measurement::Error { .. } => continue,
+            }
+            .unwrap();
+            if sd_log.append(&line).is_ok() {
+                LOGGED.fetch_add(1, Ordering::Relaxed);
+            } else {
+                log_error!(every = 10, "Cannot write to the SD card");
+            }
+        }
+    }
+
+    // UI: draws whenever there is something new and sleeps otherwise.
+    #[idle(resources = [ui_consumer, display_bus, led])]
+    fn idle(cx: idle::Context) -> ! {
+        let display_bus = cx.resources.display_bus.take().unwrap();
+        let interface = I2CDIBuilder::new().init(display_bus);
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init().unwrap();
+        panic_display::register(Bus::I2c2);
+        disp.flush().unwrap();
+
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        let mut lines: [String<32>; 4] = Default::default();
+        write!(lines[0], "Waiting for data").unwrap();
+        let mut led_on = false;
+        loop {
+            let reading = match cx.resources.ui_consumer.dequeue() {
+                Some(reading) => reading,
+                None => {
+                    cortex_m::asm::wfi();
+                    continue;
+                }
+            };
+            match reading.measurement {
+                Measurement::Climate {
+                    temperature,
+                    humidity,
+                } => {
+                    lines[0].clear();
+                    lines[1].clear();
+                    write!(lines[0], "Temperature: {:.1}C", temperature).unwrap();
+                    write!(lines[1], "Humidity: {:.1}%", humidity).unwrap();
+                }
+                Measurement::Light { lux } => {
+                    lines[2].clear();
+                    write!(lines[2], "Light: {:.1} lux", lux).unwrap();
+                }
+                Measurement::Error { sensor } => log_warn!("{} failed", sensor),
+            }
+            lines[3].clear();
+            write!(
+                lines[3],
+                "Logged {} lost {}",
+                LOGGED.load(Ordering::Relaxed),
+                DROPPED.load(Ordering::Relaxed)
+            )
+            .unwrap();
+
+            disp.clear();
+            for (i, line) in lines.iter().enumerate() {
+                Text::new(line, Point::new(0, i as i32 * 16))
+                    .into_styled(text_style)
+                    .draw(&mut disp)
+                    .unwrap();
+            }
+            disp.flush().unwrap();
+
+            led_on = !led_on;
+            if led_on {
+                cx.resources.led.set_low().unwrap();
+            } else {
+                cx.resources.led.set_high().unwrap();
+            }
+        }
+    }
+
+    extern "C" {
+        fn EXTI0();
+        fn EXTI1();
+    }
+};
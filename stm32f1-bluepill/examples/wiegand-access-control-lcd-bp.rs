@@ -0,0 +1,334 @@
+//! Access control with a Wiegand card reader or keypad, a 16x2 HD44780
+//! character LCD and a relay driving an electric door strike.
+//!
+//! The D0 and D1 lines of the reader trigger an interrupt on every falling
+//! edge, which adds the bit to the `WiegandDecoder` of this crate and notes
+//! the time with the cycle counter. Once no bit came for
+//! `wiegand::FRAME_TIMEOUT_MS`, the frame is decoded and checked:
+//! - 26 and 34-bit cards open the door if their facility code and number
+//!   are in `ALLOWED_CARDS`. The last card is shown on the LCD, so you can
+//!   read the numbers of new cards.
+//! - 4 and 8-bit keys enter a PIN: `#` opens the door if it is `PIN`, `*`
+//!   clears the entry.
+//!
+//! Like in the keypad-lcd-combination-lock-bp example, the door opens for
+//! `STRIKE_MS` and after `MAX_ATTEMPTS` denied cards or wrong PINs in a row
+//! the reader is ignored for `LOCKOUT_MS`, doubling with every further
+//! attempt. Frames with a wrong parity count as denied too.
+//!
+//! The readers run at 12V and their data lines are pulled up to 5V, so they
+//! go to 5V tolerant pins.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> Reader <-> LCD (PCF8574) <-> Relay
+//! GND  <-> GND    <-> GND           <-> GND
+//! 5V              <-> VCC           <-> VCC
+//! PB8             <-> SCL
+//! PB9             <-> SDA
+//! PB12 <-> D0
+//! PB13 <-> D1
+//! PB14                              <-> IN
+//!          +12V <-> 12V power supply
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example wiegand-access-control-lcd-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m::peripheral::DWT;
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    exti::setup_exti_pin,
+    log_info, log_warn, panic_display as _,
+    wiegand::{self, Frame, WiegandDecoder},
+};
+use embedded_hal::digital::v2::OutputPin;
+use hd44780_driver::{Cursor, CursorBlink, Display, DisplayMode, HD44780};
+use heapless::String;
+use rtic::{app, Mutex};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    delay::Delay,
+    gpio::{
+        gpiob::{PB12, PB13, PB14, PB8, PB9},
+        gpioc::PC13,
+        Alternate, Edge, ExtiPin, Input, OpenDrain, Output, PullUp, PushPull, State,
+    },
+    i2c::{BlockingI2c, DutyCycle, Mode},
+    pac,
+    prelude::*,
+};
+
+type I2cBus = BlockingI2c<pac::I2C1, (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>)>;
+
+const SYSCLK_MHZ: u32 = 72;
+/// Facility codes and numbers of the cards which open the door
+const ALLOWED_CARDS: [(u16, u16); 2] = [(123, 45678), (1, 1)];
+const PIN: &str = "1234";
+const MAX_PIN_LEN: usize = 8;
+const MAX_ATTEMPTS: u8 = 3;
+const LOCKOUT_MS: u32 = 30_000;
+const STRIKE_MS: u32 = 3000;
+const MESSAGE_MS: u32 = 2000;
+const LCD_ADDRESS: u8 = 0x27;
+
+/// Lockout time after `failures` denied attempts in a row
+fn lockout_ms(failures: u8) -> u32 {
+    if failures < MAX_ATTEMPTS {
+        0
+    } else {
+        LOCKOUT_MS << (failures - MAX_ATTEMPTS).min(4)
+    }
+}
+
+/// Millisecond clock from the DWT cycle counter, which does not wrap around
+/// as long as it is read at least every few minutes.
+struct Clock {
+    last_cycles: u32,
+    ms: u32,
+}
+
+impl Clock {
+    fn now(&mut self) -> u32 {
+        let cycles_per_ms = SYSCLK_MHZ * 1000;
+        let elapsed_ms = DWT::get_cycle_count().wrapping_sub(self.last_cycles) / cycles_per_ms;
+        self.last_cycles = self.last_cycles.wrapping_add(elapsed_ms * cycles_per_ms);
+        self.ms += elapsed_ms;
+        self.ms
+    }
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+const APP: () = {
+    struct Resources {
+        d0: PB12<Input<PullUp>>,
+        d1: PB13<Input<PullUp>>,
+        decoder: WiegandDecoder,
+        last_bit: u32,
+        strike: PB14<Output<PushPull>>,
+        // Taken by the idle task, which creates the drivers.
+        i2c: Option<I2cBus>,
+        delay: Delay,
+        led: PC13<Output<PushPull>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        log_info!("Wiegand access control example");
+        let mut core = cx.core;
+        relocate_vector_table(&mut core.SCB);
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+
+        let device: stm32f1xx_hal::stm32::Peripherals = cx.device;
+
+        let mut flash = device.FLASH.constrain();
+        let mut rcc = device.RCC.constrain();
+        let mut afio = device.AFIO.constrain(&mut rcc.apb2);
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.mhz())
+            .sysclk(SYSCLK_MHZ.mhz())
+            .pclk1(36.mhz())
+            .freeze(&mut flash.acr);
+
+        let mut gpiob = device.GPIOB.split(&mut rcc.apb2);
+
+        // Both data lines share the EXTI15_10 interrupt.
+        let mut d0 = gpiob.pb12.into_pull_up_input(&mut gpiob.crh);
+        setup_exti_pin(&mut d0, &mut afio, &device.EXTI, Edge::FALLING);
+        let mut d1 = gpiob.pb13.into_pull_up_input(&mut gpiob.crh);
+        setup_exti_pin(&mut d1, &mut afio, &device.EXTI, Edge::FALLING);
+
+        let strike = gpiob
+            .pb14
+            .into_push_pull_output_with_state(&mut gpiob.crh, State::Low);
+
+        let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+        let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+        let i2c = BlockingI2c::i2c1(
+            device.I2C1,
+            (scl, sda),
+            &mut afio.mapr,
+            Mode::Fast {
+                frequency: 100_000.hz(),
+                duty_cycle: DutyCycle::Ratio2to1,
+            },
+            clocks,
+            &mut rcc.apb1,
+            1000,
+            10,
+            1000,
+            1000,
+        );
+
+        let mut gpioc = device.GPIOC.split(&mut rcc.apb2);
+        let led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+
+        init::LateResources {
+            d0,
+            d1,
+            decoder: WiegandDecoder::new(),
+            last_bit: DWT::get_cycle_count(),
+            strike,
+            i2c: Some(i2c),
+            delay: Delay::new(core.SYST, clocks),
+            led,
+        }
+    }
+
+    #[task(binds = EXTI15_10, priority = 2, resources = [d0, d1, decoder, last_bit])]
+    fn data_edge(cx: data_edge::Context) {
+        if cx.resources.d0.check_interrupt() {
+            cx.resources.d0.clear_interrupt_pending_bit();
+            cx.resources.decoder.push(false);
+        }
+        if cx.resources.d1.check_interrupt() {
+            cx.resources.d1.clear_interrupt_pending_bit();
+            cx.resources.decoder.push(true);
+        }
+        *cx.resources.last_bit = DWT::get_cycle_count();
+    }
+
+    #[idle(resources = [decoder, last_bit, strike, i2c, delay, led])]
+    fn idle(mut cx: idle::Context) -> ! {
+        let i2c = cx.resources.i2c.take().unwrap();
+        let delay = cx.resources.delay;
+        let mut lcd = HD44780::new_i2c(i2c, LCD_ADDRESS, delay).unwrap();
+        lcd.reset(delay).unwrap();
+        lcd.set_display_mode(
+            DisplayMode {
+                display: Display::On,
+                cursor_visibility: Cursor::Invisible,
+                cursor_blink: CursorBlink::Off,
+            },
+            delay,
+        )
+        .unwrap();
+
+        let mut clock = Clock {
+            last_cycles: DWT::get_cycle_count(),
+            ms: 0,
+        };
+        let timeout_cycles = wiegand::FRAME_TIMEOUT_MS * SYSCLK_MHZ * 1000;
+        let mut pin: String<MAX_PIN_LEN> = String::new();
+        let mut failures = 0;
+        let mut open_until = None;
+        let mut lockout_until = None;
+        let mut message: Option<(&'static str, u32)> = None;
+        let mut last_card: Option<(u16, u16)> = None;
+        let mut shown: [String<16>; 2] = Default::default();
+        let mut lines: [String<16>; 2] = Default::default();
+        loop {
+            let now = clock.now();
+            if open_until.map_or(false, |until| now >= until) {
+                open_until = None;
+                cx.resources.strike.set_low().unwrap();
+            }
+            if lockout_until.map_or(false, |until| now >= until) {
+                lockout_until = None;
+            }
+            if message.map_or(false, |(_, until)| now >= until) {
+                message = None;
+            }
+
+            // Take the frame once no bit came for a while.
+            let last_bit = &mut cx.resources.last_bit;
+            let frame = cx.resources.decoder.lock(|decoder| {
+                last_bit.lock(|last_bit| {
+                    let elapsed = DWT::get_cycle_count().wrapping_sub(*last_bit);
+                    if decoder.is_empty() || elapsed < timeout_cycles {
+                        None
+                    } else {
+                        Some(decoder.finish())
+                    }
+                })
+            });
+
+            let mut granted = None;
+            match frame {
+                // Ignored while locked out, like a pressed key
+                Some(_) if lockout_until.is_some() => (),
+                Some(Ok(Frame::Card { facility, number })) => {
+                    log_info!("Card {}:{}", facility, number);
+                    last_card = Some((facility, number));
+                    granted = Some(ALLOWED_CARDS.contains(&(facility, number)));
+                }
+                Some(Ok(Frame::Key(b'*'))) => pin.clear(),
+                Some(Ok(Frame::Key(b'#'))) => {
+                    granted = Some(pin.as_str() == PIN);
+                    pin.clear();
+                }
+                Some(Ok(Frame::Key(digit))) => {
+                    pin.push(char::from(digit)).ok();
+                }
+                Some(Err(e)) => {
+                    log_warn!("Invalid frame: {:?}", e);
+                    granted = Some(false);
+                }
+                None => (),
+            }
+            match granted {
+                Some(true) => {
+                    log_info!("Open");
+                    failures = 0;
+                    cx.resources.strike.set_high().unwrap();
+                    open_until = Some(now + STRIKE_MS);
+                }
+                Some(false) => {
+                    failures = failures.saturating_add(1);
+                    log_warn!("Denied ({} in a row)", failures);
+                    message = Some(("Denied", now + MESSAGE_MS));
+                    let ms = lockout_ms(failures);
+                    if ms > 0 {
+                        lockout_until = Some(now + ms);
+                    }
+                }
+                None => (),
+            }
+
+            for line in lines.iter_mut() {
+                line.clear();
+            }
+            if let Some(until) = lockout_until {
+                write!(lines[0], "Locked").unwrap();
+                write!(lines[1], "Wait {}s", (until - now + 999) / 1000).unwrap();
+            } else {
+                if open_until.is_some() {
+                    write!(lines[0], "Open").unwrap();
+                } else if let Some((text, _)) = message {
+                    write!(lines[0], "{}", text).unwrap();
+                } else {
+                    write!(lines[0], "Show card/PIN").unwrap();
+                }
+                if !pin.is_empty() {
+                    for _ in 0..pin.len() {
+                        lines[1].push('*').unwrap();
+                    }
+                } else if let Some((facility, number)) = last_card {
+                    write!(lines[1], "Card {}:{}", facility, number).unwrap();
+                }
+            }
+            // Only update the LCD when something changed to avoid flickering.
+            if lines != shown {
+                cx.resources.led.set_low().unwrap();
+                lcd.clear(delay).unwrap();
+                lcd.write_str(&lines[0], delay).unwrap();
+                lcd.set_cursor_pos(40, delay).unwrap();
+                lcd.write_str(&lines[1], delay).unwrap();
+                shown = lines.clone();
+                cx.resources.led.set_high().unwrap();
+            }
+        }
+    }
+};
@@ -0,0 +1,304 @@
+//! Measure the wind direction with a wind vane read through an ADS1115
+//! analog/digital converter and the wind speed with a cup anemometer, and
+//! show them on an SSD1306 OLED display with a compass rose.
+//!
+//! The wind vane of the common weather meter kits (e.g. SparkFun SEN-15901)
+//! has 8 reed switches with a different resistor each. The magnet closes one
+//! or two of them at a time, so the vane has 16 directions with 16 different
+//! resistances. Together with a 10K resistor to VCC this forms a voltage
+//! divider. The ADS1115 measures the divider output on A0 and VCC on A1, so
+//! the ratio does not depend on the supply voltage. The direction is the
+//! closest entry in the `VANE` lookup table. Readings far from all entries
+//! (e.g. a broken cable) give no direction.
+//!
+//! The anemometer closes a reed switch once per turn. The pulses are counted
+//! by TIM2 in hardware: the timer is clocked from its channel 1 input
+//! (external clock mode 1) with the strongest input filter, like in the
+//! yf-s201-flow-meter-fram-bp example. Every second the counter is read. The
+//! speed is averaged over the last `WIND_WINDOW_S` seconds and the gust is
+//! the fastest second of that window.
+//!
+//! The compass rose shows where the wind comes from, like the vane.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> ADS1115 <-> Wind vane <-> Anemometer <-> Display
+//! GND  <-> GND     <-> Switch 1  <-> Switch     <-> GND
+//! 3.3V <-> VDD                                  <-> VDD
+//! 3.3V <-> A1
+//!          A0      <-> Switch 2
+//! PA0                            <-> Switch 2
+//! PB8  <-> SCL                                  <-> SCL
+//! PB9  <-> SDA                                  <-> SDA
+//! ```
+//!
+//! Connect a 10K resistor from 3.3V to A0. The anemometer input uses the
+//! internal pull-up resistor. The constants are for the SparkFun kit: 2.4km/h
+//! per pulse per second.
+//!
+//! Run with:
+//! `cargo embed --example wind-vane-anemometer-ads1115-compass-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use ads1x1x::{channel as AdcChannel, Ads1x1x, FullScaleRange, SlaveAddr};
+use core::{f32::consts::PI, fmt::Write};
+use cortex_m_rt::{entry, exception};
+use driver_examples_bluepill::{
+    bootloader::relocate_vector_table,
+    i2c_speed, log_error, log_info, monotonic,
+    panic_display::{self, Bus},
+    scheduler::Scheduler,
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Circle, Line},
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::String;
+use nb::block;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{pac, prelude::*};
+
+/// Divider output / VCC * 1000 for the 16 directions, starting at north and
+/// going clockwise, with a 10K resistor to VCC
+const VANE: [i32; 16] = [
+    767, // 33K
+    397, // 6.57K
+    451, // 8.2K
+    82,  // 891
+    91,  // 1K
+    64,  // 688
+    180, // 2.2K
+    124, // 1.41K
+    281, // 3.9K
+    239, // 3.14K
+    615, // 16K
+    585, // 14.12K
+    923, // 120K
+    808, // 42.12K
+    866, // 64.9K
+    686, // 21.88K
+];
+/// Largest difference to a `VANE` entry which still counts as that direction
+const VANE_TOLERANCE: i32 = 15;
+const DIRECTIONS: [&str; 16] = [
+    "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW", "NW",
+    "NNW",
+];
+const WIND_KMH_PER_HZ: f32 = 2.4;
+const WIND_WINDOW_S: usize = 10;
+
+const ROSE_CENTER: Point = Point::new(96, 32);
+const ROSE_RADIUS: i32 = 22;
+
+// Tasks in order of priority
+const WIND: usize = 0;
+const VANE_TASK: usize = 1;
+const DISPLAY: usize = 2;
+
+/// Index of the closest `VANE` entry, `None` if none is close enough.
+fn direction(ratio: i32) -> Option<usize> {
+    VANE.iter()
+        .enumerate()
+        .min_by_key(|(_, value)| (**value - ratio).abs())
+        .filter(|(_, value)| (**value - ratio).abs() <= VANE_TOLERANCE)
+        .map(|(i, _)| i)
+}
+
+/// Point at `radius` from the center of the compass rose in the direction
+/// `index` (0 = north, clockwise)
+fn rose_point(index: usize, radius: i32) -> Point {
+    let angle = index as f32 * PI / 8.0;
+    ROSE_CENTER
+        + Point::new(
+            (libm::sinf(angle) * radius as f32) as i32,
+            -(libm::cosf(angle) * radius as f32) as i32,
+        )
+}
+
+#[exception]
+fn SysTick() {
+    monotonic::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("Wind vane and anemometer example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+    relocate_vector_table(&mut cp.SCB);
+
+    // Enable the TIM2 clock before handing the RCC over to the HAL.
+    dp.RCC.apb1enr.modify(|_, w| w.tim2en().set_bit());
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    // TIM2 channel 1 input
+    let _anemometer = gpioa.pa0.into_pull_up_input(&mut gpioa.crl);
+
+    // Count the rising edges of TI1 with the strongest input filter.
+    let tim2 = dp.TIM2;
+    tim2.ccmr1_input()
+        .modify(|_, w| w.cc1s().ti1().ic1f().fdts_div32_n8());
+    tim2.smcr
+        .modify(|_, w| w.ts().ti1fp1().sms().ext_clock_mode());
+    tim2.cr1.modify(|_, w| w.cen().enabled());
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // ADS1115 ADC and display
+        |i2c| i2c_speed::probe_addresses(i2c, &[0x48, 0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+    let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+    let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+
+    let mut adc = Ads1x1x::new_ads1115(manager.acquire(), SlaveAddr::default());
+    adc.set_full_scale_range(FullScaleRange::Within4_096V)
+        .unwrap();
+
+    let mut wind = [0_u16; WIND_WINDOW_S];
+    let mut seconds = 0;
+    let mut last_count = tim2.cnt.read().cnt().bits();
+    let mut vane = None;
+    let mut lines: [String<32>; 4] = Default::default();
+    let mut led_on = false;
+    let mut scheduler = Scheduler::new([1000, 250, 250]);
+    loop {
+        match scheduler.poll() {
+            Some(WIND) => {
+                // The 16-bit counter wraps around after 65536 pulses. That
+                // is far more than the anemometer gives in one second.
+                let count = tim2.cnt.read().cnt().bits();
+                wind[seconds % WIND_WINDOW_S] = count.wrapping_sub(last_count);
+                last_count = count;
+                seconds += 1;
+
+                // Blink LED 0 to check that everything is actually running.
+                // If the LED 0 is off, something went wrong.
+                led_on = !led_on;
+                if led_on {
+                    led.set_high().unwrap();
+                } else {
+                    led.set_low().unwrap();
+                }
+            }
+            Some(VANE_TASK) => {
+                let output = block!(adc.read(&mut AdcChannel::SingleA0));
+                let vcc = block!(adc.read(&mut AdcChannel::SingleA1));
+                vane = match (output, vcc) {
+                    (Ok(output), Ok(vcc)) if vcc > 0 => {
+                        direction(i32::from(output) * 1000 / i32::from(vcc))
+                    }
+                    (Err(e), _) | (_, Err(e)) => {
+                        log_error!(every = 4, "ADC error: {:?}", e);
+                        None
+                    }
+                    _ => None,
+                };
+            }
+            Some(DISPLAY) => {
+                let samples = seconds.min(WIND_WINDOW_S).max(1);
+                let pulses: u32 = wind.iter().map(|p| u32::from(*p)).sum();
+                let speed_kmh = pulses as f32 * WIND_KMH_PER_HZ / samples as f32;
+                let gust_kmh = f32::from(*wind.iter().max().unwrap()) * WIND_KMH_PER_HZ;
+
+                for line in lines.iter_mut() {
+                    line.clear();
+                }
+                write!(lines[0], "Wind from").unwrap();
+                match vane {
+                    Some(i) => write!(lines[1], "{} {:.1}", DIRECTIONS[i], i as f32 * 22.5),
+                    None => write!(lines[1], "-"),
+                }
+                .unwrap();
+                write!(lines[2], "{:.1} km/h", speed_kmh).unwrap();
+                write!(lines[3], "Gust {:.1}", gust_kmh).unwrap();
+
+                disp.clear();
+                for (i, line) in lines.iter().enumerate() {
+                    Text::new(line, Point::new(0, i as i32 * 16))
+                        .into_styled(text_style)
+                        .draw(&mut disp)
+                        .unwrap();
+                }
+
+                // Compass rose: a circle with a tick every 22.5 degrees and
+                // the main directions outside
+                Circle::new(ROSE_CENTER, ROSE_RADIUS as u32)
+                    .into_styled(outline)
+                    .draw(&mut disp)
+                    .unwrap();
+                for i in 0..DIRECTIONS.len() {
+                    let length = if i % 4 == 0 { 5 } else { 2 };
+                    Line::new(
+                        rose_point(i, ROSE_RADIUS - length),
+                        rose_point(i, ROSE_RADIUS),
+                    )
+                    .into_styled(outline)
+                    .draw(&mut disp)
+                    .unwrap();
+                }
+                for (i, name) in ["N", "E", "S", "W"].iter().enumerate() {
+                    // Center the letters outside of the circle.
+                    let position = rose_point(i * 4, ROSE_RADIUS + 5) - Point::new(2, 3);
+                    Text::new(name, position)
+                        .into_styled(text_style)
+                        .draw(&mut disp)
+                        .unwrap();
+                }
+                // The pointer goes from the center to where the wind comes from.
+                if let Some(i) = vane {
+                    Line::new(ROSE_CENTER, rose_point(i, ROSE_RADIUS - 7))
+                        .into_styled(outline)
+                        .draw(&mut disp)
+                        .unwrap();
+                    Circle::new(rose_point(i, ROSE_RADIUS - 7), 2)
+                        .into_styled(fill)
+                        .draw(&mut disp)
+                        .unwrap();
+                }
+                disp.flush().unwrap();
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Minimal firmware update loader: receive a new application image through
+//! the serial interface using the XMODEM-CRC protocol, store it in the upper
+//! half of the flash memory and start it on the next boot.
+//!
+//! This example lives in the lower half of the flash memory.
+//! On boot, it checks whether a valid application image is stored in the
+//! upper half (address 0x08008000). If so and the update button is not
+//! pressed, the application is started.
+//! Otherwise, the loader waits for a new image through XMODEM-CRC (send it
+//! with `sx --xmodem` or any terminal program like minicom or Tera Term),
+//! programs it into the flash, verifies it and resets the board, which then
+//! starts the new application.
+//!
+//! The last 1K page of the flash is used to store the size and CRC of the
+//! image. This information is only written after the whole image has been
+//! received correctly so an interrupted transfer never leaves an image
+//! behind that would be started.
+//!
+//! The application image must be linked to run from the upper half. Build any
+//! example with the `upper-half` feature and convert it to a binary file.
+//! For example:
+//! ```
+//! cargo objcopy --release --example veml6070-uv-display-bp --features upper-half -- -O binary app.bin
+//! sx --xmodem app.bin < /dev/ttyUSB0 > /dev/ttyUSB0
+//! ```
+//!
+//! Progress is printed through RTT since the serial interface is busy.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using USART1.
+//!
+//! ```
+//! BP   <-> Serial module <-> Update button
+//! GND  <-> GND
+//! 3.3V <-> VCC           <-> +
+//! PB6  <-> RX
+//! PB7  <-> TX
+//! PA0                    <-> -
+//! ```
+//!
+//! Keep the update button pressed during reset to stay in the loader even
+//! if there is a valid application image.
+//!
+//! Run with:
+//! `cargo embed --example xmodem-firmware-update-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::{DWT, SCB};
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    bootloader::jump_to_application, log_error, log_info, log_warn, panic_display as _,
+};
+use embedded_hal::{
+    digital::v2::InputPin,
+    serial::{Read, Write},
+};
+use nb::block;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    flash::{FlashSize, FlashWriter, SectorSize},
+    pac,
+    prelude::*,
+    serial,
+};
+
+const FLASH_BASE: u32 = 0x0800_0000;
+// Offsets from the beginning of the flash memory
+const IMAGE_OFFSET: u32 = 0x8000;
+const INFO_OFFSET: u32 = 0xFC00;
+const IMAGE_MAX_SIZE: u32 = INFO_OFFSET - IMAGE_OFFSET;
+const PAGE_SIZE: usize = 1024;
+const INFO_MAGIC: u32 = 0x5550_4454; // "UPDT"
+
+const SOH: u8 = 0x01;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC_MODE: u8 = b'C';
+const PACKET_SIZE: usize = 128;
+const MAX_ERRORS: u8 = 10;
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("XMODEM firmware update example");
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let update_button = gpioa.pa0.into_pull_down_input(&mut gpioa.crl);
+
+    // Decide whether to start the application before configuring the clocks
+    // so that the application finds the chip as after a reset.
+    {
+        let writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+        if update_button.is_low().unwrap() {
+            match read_info(&writer) {
+                Some(length) => {
+                    log_info!("Starting application ({} bytes)", length);
+                    start_application(&mut cp.SCB);
+                }
+                None => log_warn!("No valid application found"),
+            }
+        } else {
+            log_info!("Update button pressed");
+        }
+    }
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+    let one_second = clocks.sysclk().0;
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+    let tx = gpiob.pb6.into_alternate_push_pull(&mut gpiob.crl);
+    let rx = gpiob.pb7;
+    let serial = serial::Serial::usart1(
+        dp.USART1,
+        (tx, rx),
+        &mut afio.mapr,
+        serial::Config::default().baudrate(115200.bps()),
+        clocks,
+        &mut rcc.apb2,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    let mut writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+    loop {
+        log_info!("Erasing...");
+        // Erase the information first so that a partially received image is never started.
+        writer.erase(INFO_OFFSET, PAGE_SIZE).unwrap();
+        writer.erase(IMAGE_OFFSET, IMAGE_MAX_SIZE as usize).unwrap();
+
+        log_info!("Waiting for XMODEM transfer...");
+        match receive(&mut rx, &mut tx, &mut writer, one_second) {
+            Ok((length, crc)) => {
+                log_info!("Received {} bytes", length);
+                if image_crc(&writer, length) == Some(crc) {
+                    write_info(&mut writer, length, crc).unwrap();
+                    log_info!("Update done. Restarting...");
+                    SCB::sys_reset();
+                } else {
+                    log_error!("Verification failed");
+                }
+            }
+            Err(e) => log_error!("Transfer failed: {:?}", e),
+        }
+    }
+}
+
+#[allow(unsafe_code)]
+fn start_application(scb: &mut SCB) -> ! {
+    // The image has been verified by `read_info()`.
+    unsafe { jump_to_application(scb, FLASH_BASE + IMAGE_OFFSET) }
+}
+
+#[derive(Debug)]
+enum XmodemError {
+    Timeout,
+    Cancelled,
+    Sequence,
+    TooLarge,
+    Flash,
+}
+
+/// Receive an image with XMODEM-CRC and write it to the flash.
+///
+/// Returns the length and CRC of the received image.
+fn receive<RX, TX>(
+    rx: &mut RX,
+    tx: &mut TX,
+    writer: &mut FlashWriter,
+    one_second: u32,
+) -> Result<(u32, u16), XmodemError>
+where
+    RX: Read<u8>,
+    TX: Write<u8>,
+{
+    // Ask the sender to start in CRC mode until it answers.
+    let mut first = None;
+    for _ in 0..60 {
+        send(tx, CRC_MODE);
+        first = read_byte(rx, one_second);
+        if first.is_some() {
+            break;
+        }
+    }
+
+    let mut expected_block: u8 = 1;
+    let mut length = 0;
+    let mut crc = 0;
+    let mut errors = 0;
+    let mut data = [0; PACKET_SIZE];
+    loop {
+        let header = first.take().or_else(|| read_byte(rx, 10 * one_second));
+        match header {
+            Some(SOH) => {
+                let block = read_byte(rx, one_second);
+                let block_complement = read_byte(rx, one_second);
+                let mut complete = true;
+                for byte in data.iter_mut() {
+                    match read_byte(rx, one_second) {
+                        Some(b) => *byte = b,
+                        None => {
+                            complete = false;
+                            break;
+                        }
+                    }
+                }
+                let crc_high = read_byte(rx, one_second);
+                let crc_low = read_byte(rx, one_second);
+                let packet = match (block, block_complement, crc_high, crc_low) {
+                    (Some(b), Some(nb), Some(h), Some(l)) if complete && b ^ nb == 0xFF => {
+                        Some((b, u16::from(h) << 8 | u16::from(l)))
+                    }
+                    _ => None,
+                };
+                match packet {
+                    Some((block, packet_crc)) if crc16(0, &data) == packet_crc => {
+                        errors = 0;
+                        if block == expected_block.wrapping_sub(1) {
+                            // Our ACK got lost and the sender repeated the packet.
+                            send(tx, ACK);
+                        } else if block != expected_block {
+                            send(tx, CAN);
+                            return Err(XmodemError::Sequence);
+                        } else if length + PACKET_SIZE as u32 > IMAGE_MAX_SIZE {
+                            send(tx, CAN);
+                            return Err(XmodemError::TooLarge);
+                        } else if writer.write(IMAGE_OFFSET + length, &data).is_err() {
+                            send(tx, CAN);
+                            return Err(XmodemError::Flash);
+                        } else {
+                            crc = crc16(crc, &data);
+                            length += PACKET_SIZE as u32;
+                            expected_block = expected_block.wrapping_add(1);
+                            send(tx, ACK);
+                        }
+                    }
+                    _ => {
+                        errors += 1;
+                        flush(rx, one_second);
+                        send(tx, NAK);
+                    }
+                }
+            }
+            Some(EOT) => {
+                send(tx, ACK);
+                return Ok((length, crc));
+            }
+            Some(CAN) => return Err(XmodemError::Cancelled),
+            _ => {
+                errors += 1;
+                send(tx, NAK);
+            }
+        }
+        if errors >= MAX_ERRORS {
+            send(tx, CAN);
+            return Err(XmodemError::Timeout);
+        }
+    }
+}
+
+fn read_byte<RX: Read<u8>>(rx: &mut RX, timeout_cycles: u32) -> Option<u8> {
+    let start = DWT::get_cycle_count();
+    loop {
+        if let Ok(byte) = rx.read() {
+            return Some(byte);
+        }
+        if DWT::get_cycle_count().wrapping_sub(start) > timeout_cycles {
+            return None;
+        }
+    }
+}
+
+/// Discard incoming data until the line is silent.
+fn flush<RX: Read<u8>>(rx: &mut RX, one_second: u32) {
+    while read_byte(rx, one_second).is_some() {}
+}
+
+fn send<TX: Write<u8>>(tx: &mut TX, byte: u8) {
+    block!(tx.write(byte)).ok();
+}
+
+/// CRC-16/XMODEM (polynomial 0x1021)
+fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn image_crc(writer: &FlashWriter, length: u32) -> Option<u16> {
+    writer
+        .read(IMAGE_OFFSET, length as usize)
+        .ok()
+        .map(|image| crc16(0, image))
+}
+
+/// Returns the length of the stored image if it is valid.
+fn read_info(writer: &FlashWriter) -> Option<u32> {
+    let info = writer.read(INFO_OFFSET, 12).ok()?;
+    let magic = u32::from_le_bytes([info[0], info[1], info[2], info[3]]);
+    let length = u32::from_le_bytes([info[4], info[5], info[6], info[7]]);
+    let crc = u16::from_le_bytes([info[8], info[9]]);
+    if magic != INFO_MAGIC || length == 0 || length > IMAGE_MAX_SIZE {
+        return None;
+    }
+    let stack_pointer = writer.read(IMAGE_OFFSET, 4).ok()?;
+    let stack_pointer = u32::from_le_bytes([
+        stack_pointer[0],
+        stack_pointer[1],
+        stack_pointer[2],
+        stack_pointer[3],
+    ]);
+    if stack_pointer & 0xFFFF_0000 != 0x2000_0000 {
+        return None;
+    }
+    if image_crc(writer, length) == Some(crc) {
+        Some(length)
+    } else {
+        None
+    }
+}
+
+fn write_info(writer: &mut FlashWriter, length: u32, crc: u16) -> Result<(), ()> {
+    let mut info = [0; 12];
+    info[0..4].copy_from_slice(&INFO_MAGIC.to_le_bytes());
+    info[4..8].copy_from_slice(&length.to_le_bytes());
+    info[8..10].copy_from_slice(&crc.to_le_bytes());
+    writer.write(INFO_OFFSET, &info).map_err(|_| ())
+}
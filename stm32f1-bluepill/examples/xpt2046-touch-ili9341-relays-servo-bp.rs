@@ -0,0 +1,473 @@
+//! Control two relays and a servo through buttons drawn on an ILI9341 color
+//! LCD using an XPT2046 resistive touch controller.
+//!
+//! The XPT2046 returns raw 12-bit readings which do not match the display
+//! pixels directly, as the touch panel is never perfectly aligned with the
+//! display. A 3-point calibration is used to calculate the transformation
+//! from touch readings to display coordinates. It compensates for offset,
+//! scaling and rotation.
+//! The calibration is stored in the last page of the flash memory so it only
+//! needs to be done once. It runs when no calibration is stored or when the
+//! screen is touched while the program starts.
+//!
+//! The LCD and the touch controller share SPI1. The XPT2046 supports up to
+//! 2MHz so the bus runs at this speed for both devices.
+//! The servo is driven with a 50Hz PWM signal from TIM2.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using SPI1.
+//!
+//! ```
+//! BP   <-> ILI9341 <-> XPT2046 (touch) <-> Relays <-> Servo
+//! GND  <-> GND     <-> GND             <-> GND    <-> GND
+//! 3.3V <-> VCC     <-> VCC
+//! 3.3V <-> LED
+//! 5V                                   <-> VCC    <-> V+
+//! PA5  <-> SCK     <-> T_CLK
+//! PA6  <-> SDO     <-> T_DO
+//! PA7  <-> SDI     <-> T_DIN
+//! PB0  <-> CS
+//! PB1  <-> DC
+//! PB10 <-> RESET
+//! PB12             <-> T_CS
+//! PB13             <-> T_IRQ
+//! PB14                                 <-> IN1
+//! PB15                                 <-> IN2
+//! PA0                                            <-> Signal
+//! ```
+//!
+//! Run with:
+//! `cargo embed --example xpt2046-touch-ili9341-relays-servo-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use display_interface_spi::SPIInterface;
+use driver_examples_bluepill::{log_info, log_warn, panic_display as _};
+use embedded_graphics::{
+    fonts::{Font12x16, Text},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{Line, Rectangle},
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use embedded_hal::{
+    blocking::spi::Transfer,
+    digital::v2::{InputPin, OutputPin},
+    spi::MODE_0,
+    Pwm,
+};
+use ili9341::{DisplaySize240x320, Ili9341, Orientation};
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    delay::Delay,
+    flash::{FlashSize, SectorSize},
+    pac,
+    prelude::*,
+    pwm::Channel,
+    spi::Spi,
+    timer::{Tim2NoRemap, Timer},
+};
+
+// Last 1K page of the flash memory
+const CONFIG_OFFSET: u32 = 0xFC00;
+const PAGE_SIZE: usize = 1024;
+const CONFIG_MAGIC: u32 = 0x5450_4331; // "TPC1"
+const CONFIG_SIZE: usize = 28;
+
+const WIDTH: i32 = 320;
+const HEIGHT: i32 = 240;
+
+// XPT2046 commands: 12-bit differential conversion of the X and Y positions
+const READ_X: u8 = 0xD0;
+const READ_Y: u8 = 0x90;
+const SAMPLES: u32 = 8;
+
+/// Minimal XPT2046 touch controller driver
+struct Xpt2046<SPI, CS> {
+    spi: SPI,
+    cs: CS,
+}
+
+impl<SPI, CS, E> Xpt2046<SPI, CS>
+where
+    SPI: Transfer<u8, Error = E>,
+    CS: OutputPin,
+{
+    fn new(spi: SPI, mut cs: CS) -> Self {
+        cs.set_high().ok();
+        Xpt2046 { spi, cs }
+    }
+
+    fn read_channel(&mut self, command: u8) -> Result<u16, E> {
+        let mut buffer = [command, 0, 0];
+        self.cs.set_low().ok();
+        let result = self
+            .spi
+            .transfer(&mut buffer)
+            .map(|data| (u16::from(data[1]) << 8 | u16::from(data[2])) >> 3);
+        self.cs.set_high().ok();
+        result
+    }
+
+    /// Average raw X and Y readings
+    fn read(&mut self) -> Result<(u16, u16), E> {
+        let mut x = 0;
+        let mut y = 0;
+        for _ in 0..SAMPLES {
+            x += u32::from(self.read_channel(READ_X)?);
+            y += u32::from(self.read_channel(READ_Y)?);
+        }
+        Ok(((x / SAMPLES) as u16, (y / SAMPLES) as u16))
+    }
+}
+
+/// Transformation from raw touch readings to display coordinates:
+/// x = a * raw_x + b * raw_y + c
+/// y = d * raw_x + e * raw_y + f
+#[derive(Debug, Clone, Copy)]
+struct Calibration {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+    f: f32,
+}
+
+impl Calibration {
+    /// Calculate the calibration from three raw touch readings and the
+    /// display points where they were made.
+    fn from_points(raw: &[(f32, f32); 3], display: &[(f32, f32); 3]) -> Option<Self> {
+        let (x1, y1) = raw[0];
+        let (x2, y2) = raw[1];
+        let (x3, y3) = raw[2];
+        let det = (x1 - x3) * (y2 - y3) - (x2 - x3) * (y1 - y3);
+        if det == 0.0 {
+            return None;
+        }
+        let solve = |v1: f32, v2: f32, v3: f32| {
+            let p = ((v1 - v3) * (y2 - y3) - (v2 - v3) * (y1 - y3)) / det;
+            let q = ((x1 - x3) * (v2 - v3) - (x2 - x3) * (v1 - v3)) / det;
+            (p, q, v1 - p * x1 - q * y1)
+        };
+        let (a, b, c) = solve(display[0].0, display[1].0, display[2].0);
+        let (d, e, f) = solve(display[0].1, display[1].1, display[2].1);
+        Some(Calibration { a, b, c, d, e, f })
+    }
+
+    fn apply(&self, raw: (u16, u16)) -> Point {
+        let (x, y) = (f32::from(raw.0), f32::from(raw.1));
+        Point::new(
+            (self.a * x + self.b * y + self.c) as i32,
+            (self.d * x + self.e * y + self.f) as i32,
+        )
+    }
+
+    fn to_bytes(&self) -> [u8; CONFIG_SIZE] {
+        let mut bytes = [0; CONFIG_SIZE];
+        bytes[0..4].copy_from_slice(&CONFIG_MAGIC.to_le_bytes());
+        let values = [self.a, self.b, self.c, self.d, self.e, self.f];
+        for (chunk, value) in bytes[4..].chunks_mut(4).zip(values.iter()) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let word = |i: usize| [bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]];
+        if bytes.len() < CONFIG_SIZE || u32::from_le_bytes(word(0)) != CONFIG_MAGIC {
+            return None;
+        }
+        let value = |n: usize| f32::from_le_bytes(word(4 + n * 4));
+        Some(Calibration {
+            a: value(0),
+            b: value(1),
+            c: value(2),
+            d: value(3),
+            e: value(4),
+            f: value(5),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Relay1,
+    Relay2,
+    Servo(u32),
+}
+
+struct Button {
+    top_left: Point,
+    bottom_right: Point,
+    label: &'static str,
+    action: Action,
+}
+
+impl Button {
+    const fn new(x0: i32, y0: i32, x1: i32, y1: i32, label: &'static str, action: Action) -> Self {
+        Button {
+            top_left: Point::new(x0, y0),
+            bottom_right: Point::new(x1, y1),
+            label,
+            action,
+        }
+    }
+
+    fn contains(&self, point: Point) -> bool {
+        point.x >= self.top_left.x
+            && point.x <= self.bottom_right.x
+            && point.y >= self.top_left.y
+            && point.y <= self.bottom_right.y
+    }
+
+    fn draw<D: DrawTarget<Rgb565>>(&self, display: &mut D, active: bool) -> Result<(), D::Error> {
+        let color = if active { Rgb565::GREEN } else { Rgb565::BLUE };
+        Rectangle::new(self.top_left, self.bottom_right)
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(display)?;
+        let text_style = TextStyleBuilder::new(Font12x16)
+            .text_color(Rgb565::WHITE)
+            .background_color(color)
+            .build();
+        Text::new(self.label, self.top_left + Point::new(8, 8))
+            .into_styled(text_style)
+            .draw(display)
+    }
+}
+
+const BUTTONS: [Button; 5] = [
+    Button::new(10, 40, 150, 110, "Relay 1", Action::Relay1),
+    Button::new(170, 40, 310, 110, "Relay 2", Action::Relay2),
+    Button::new(10, 140, 100, 210, "0", Action::Servo(0)),
+    Button::new(115, 140, 205, 210, "90", Action::Servo(90)),
+    Button::new(220, 140, 310, 210, "180", Action::Servo(180)),
+];
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("XPT2046 touch example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    // SPI1
+    let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
+    let miso = gpioa.pa6;
+    let mosi = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl);
+
+    let lcd_cs = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+    let dc = gpiob.pb1.into_push_pull_output(&mut gpiob.crl);
+    let rst = gpiob.pb10.into_push_pull_output(&mut gpiob.crh);
+    let touch_cs = gpiob.pb12.into_push_pull_output(&mut gpiob.crh);
+    let touch_irq = gpiob.pb13.into_pull_up_input(&mut gpiob.crh);
+    let mut relay1 = gpiob.pb14.into_push_pull_output(&mut gpiob.crh);
+    let mut relay2 = gpiob.pb15.into_push_pull_output(&mut gpiob.crh);
+
+    let spi = Spi::spi1(
+        dp.SPI1,
+        (sck, miso, mosi),
+        &mut afio.mapr,
+        MODE_0,
+        2_u32.mhz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    let servo_pin = gpioa.pa0.into_alternate_push_pull(&mut gpioa.crl);
+    let mut servo = Timer::tim2(dp.TIM2, &clocks, &mut rcc.apb1).pwm::<Tim2NoRemap, _, _, _>(
+        servo_pin,
+        &mut afio.mapr,
+        50.hz(),
+    );
+    servo.enable(Channel::C1);
+    let max_duty = servo.get_max_duty();
+    // 1ms pulse for 0 degrees to 2ms pulse for 180 degrees out of 20ms
+    let servo_duty = |angle: u32| (u32::from(max_duty) * (180 + angle) / (20 * 180)) as u16;
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(spi);
+
+    let interface = SPIInterface::new(manager.acquire(), dc, lcd_cs);
+    let mut lcd = Ili9341::new(
+        interface,
+        rst,
+        &mut delay,
+        Orientation::Landscape,
+        DisplaySize240x320,
+    )
+    .unwrap();
+    lcd.clear(Rgb565::BLACK).unwrap();
+
+    let mut touch = Xpt2046::new(manager.acquire(), touch_cs);
+
+    let mut writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+    let stored = writer
+        .read(CONFIG_OFFSET, CONFIG_SIZE)
+        .ok()
+        .and_then(Calibration::from_bytes);
+    let calibration = match stored {
+        Some(calibration) if touch_irq.is_high().unwrap() => calibration,
+        _ => {
+            let calibration = calibrate(&mut lcd, &mut touch, &touch_irq, &mut delay);
+            writer.erase(CONFIG_OFFSET, PAGE_SIZE).unwrap();
+            writer
+                .write(CONFIG_OFFSET, &calibration.to_bytes())
+                .unwrap();
+            log_info!("Calibration stored");
+            calibration
+        }
+    };
+    log_info!("{:?}", calibration);
+
+    let mut relay1_on = false;
+    let mut relay2_on = false;
+    let mut servo_angle = 90;
+    servo.set_duty(Channel::C1, servo_duty(servo_angle));
+    relay1.set_low().unwrap();
+    relay2.set_low().unwrap();
+
+    lcd.clear(Rgb565::BLACK).unwrap();
+    let active = |action: Action, relay1_on: bool, relay2_on: bool, servo_angle: u32| match action {
+        Action::Relay1 => relay1_on,
+        Action::Relay2 => relay2_on,
+        Action::Servo(angle) => angle == servo_angle,
+    };
+    for button in BUTTONS.iter() {
+        let is_active = active(button.action, relay1_on, relay2_on, servo_angle);
+        button.draw(&mut lcd, is_active).unwrap();
+    }
+
+    loop {
+        if touch_irq.is_low().unwrap() {
+            // The LED is on while the screen is touched.
+            led.set_low().unwrap();
+            let point = match touch.read() {
+                Ok(raw) => calibration.apply(raw),
+                Err(_) => Point::new(-1, -1),
+            };
+            log_info!("Touch at {:?}", point);
+            if let Some(button) = BUTTONS.iter().find(|b| b.contains(point)) {
+                match button.action {
+                    Action::Relay1 => {
+                        relay1_on = !relay1_on;
+                        set_relay(&mut relay1, relay1_on);
+                    }
+                    Action::Relay2 => {
+                        relay2_on = !relay2_on;
+                        set_relay(&mut relay2, relay2_on);
+                    }
+                    Action::Servo(angle) => {
+                        servo_angle = angle;
+                        servo.set_duty(Channel::C1, servo_duty(angle));
+                    }
+                }
+                for button in BUTTONS.iter() {
+                    let is_active = active(button.action, relay1_on, relay2_on, servo_angle);
+                    button.draw(&mut lcd, is_active).unwrap();
+                }
+            }
+            wait_for_release(&touch_irq, &mut delay);
+            led.set_high().unwrap();
+        }
+        delay.delay_ms(20_u16);
+    }
+}
+
+fn set_relay<P: OutputPin>(relay: &mut P, on: bool) {
+    if on {
+        relay.set_high().ok();
+    } else {
+        relay.set_low().ok();
+    }
+}
+
+fn wait_for_release<IRQ: InputPin>(irq: &IRQ, delay: &mut Delay) {
+    while irq.is_low().unwrap_or(false) {
+        delay.delay_ms(10_u16);
+    }
+    // Debounce
+    delay.delay_ms(50_u16);
+}
+
+/// Ask the user to touch three crosses and calculate the calibration.
+fn calibrate<D, SPI, CS, E, IRQ>(
+    display: &mut D,
+    touch: &mut Xpt2046<SPI, CS>,
+    irq: &IRQ,
+    delay: &mut Delay,
+) -> Calibration
+where
+    D: DrawTarget<Rgb565>,
+    SPI: Transfer<u8, Error = E>,
+    CS: OutputPin,
+    IRQ: InputPin,
+{
+    // Points spread over the display which are not on a line
+    let targets = [
+        Point::new(WIDTH / 10, HEIGHT / 10),
+        Point::new(WIDTH / 2, HEIGHT * 9 / 10),
+        Point::new(WIDTH * 9 / 10, HEIGHT / 2),
+    ];
+    let text_style = TextStyleBuilder::new(Font12x16)
+        .text_color(Rgb565::WHITE)
+        .build();
+    loop {
+        wait_for_release(irq, delay);
+        let mut raw = [(0.0, 0.0); 3];
+        for (target, reading) in targets.iter().zip(raw.iter_mut()) {
+            display.clear(Rgb565::BLACK).ok();
+            Text::new("Touch the cross", Point::new(70, 100))
+                .into_styled(text_style)
+                .draw(display)
+                .ok();
+            draw_cross(display, *target).ok();
+            let value = loop {
+                while irq.is_high().unwrap_or(true) {
+                    delay.delay_ms(10_u16);
+                }
+                // Let the touch settle before reading.
+                delay.delay_ms(20_u16);
+                if let Ok(value) = touch.read() {
+                    break value;
+                }
+            };
+            log_info!("Raw reading for {:?}: {:?}", target, value);
+            *reading = (f32::from(value.0), f32::from(value.1));
+            wait_for_release(irq, delay);
+        }
+        let display_points = [
+            (targets[0].x as f32, targets[0].y as f32),
+            (targets[1].x as f32, targets[1].y as f32),
+            (targets[2].x as f32, targets[2].y as f32),
+        ];
+        match Calibration::from_points(&raw, &display_points) {
+            Some(calibration) => return calibration,
+            None => log_warn!("Invalid calibration. Please try again."),
+        }
+    }
+}
+
+fn draw_cross<D: DrawTarget<Rgb565>>(display: &mut D, center: Point) -> Result<(), D::Error> {
+    let style = PrimitiveStyle::with_stroke(Rgb565::RED, 1);
+    Line::new(center - Point::new(10, 0), center + Point::new(10, 0))
+        .into_styled(style)
+        .draw(display)?;
+    Line::new(center - Point::new(0, 10), center + Point::new(0, 10))
+        .into_styled(style)
+        .draw(display)
+}
@@ -0,0 +1,235 @@
+//! Measure the water flow with a YF-S201 hall-effect flow sensor and keep a
+//! running total of liters that survives resets and power losses in an I2C
+//! FRAM (e.g. MB85RC256V). The flow and the total are shown on an SSD1306
+//! OLED display.
+//!
+//! The sensor outputs 7.5 pulses per second for every L/min (450 pulses per
+//! liter). The pulses are counted by TIM2 in hardware: the timer is clocked
+//! from its channel 1 input (external clock mode 1) instead of the internal
+//! clock, so no pulse is lost no matter how busy the CPU is. The HAL does not
+//! support this mode so the timer registers are configured directly.
+//! Every second the counter is read and the difference to the last reading
+//! gives the flow.
+//!
+//! The total number of pulses is stored in the FRAM together with a CRC-32
+//! calculated with the `crc` module of this crate. FRAM does not wear out
+//! like flash or EEPROM so it is updated every second while water flows.
+//! Press the button to reset the total.
+//!
+//! This example is runs on the STM32F103 "Bluepill" board using I2C1.
+//!
+//! ```
+//! BP   <-> YF-S201 <-> FRAM <-> Display <-> Button
+//! GND  <-> GND     <-> GND  <-> GND
+//! 5V   <-> VCC
+//! 3.3V             <-> VCC  <-> VDD     <-> +
+//! PA0  <-> OUT
+//! PB8              <-> SCL  <-> SCL
+//! PB9              <-> SDA  <-> SDA
+//! PB12                                  <-> -
+//! ```
+//!
+//! PA0 is not 5V tolerant. If your sensor has a pull-up resistor to 5V on
+//! its output, use a voltage divider.
+//!
+//! Run with:
+//! `cargo embed --example yf-s201-flow-meter-fram-bp`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use driver_examples_bluepill::{
+    crc::{Crc32, SoftwareCrc32},
+    i2c_speed, log_info, log_warn,
+    panic_display::{self, Bus},
+};
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use embedded_hal::{
+    blocking::i2c,
+    digital::v2::{InputPin, OutputPin},
+};
+use heapless::String;
+use rtt_target::rtt_init_print;
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use stm32f1xx_hal::{delay::Delay, pac, prelude::*};
+
+const PULSES_PER_LITER: u32 = 450;
+const FRAM_ADDRESS: u8 = 0x50;
+const TOTAL_OFFSET: u16 = 0;
+
+/// Minimal driver for an I2C FRAM with 16-bit memory addresses
+struct Fram<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C, E> Fram<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    fn read(&mut self, offset: u16, data: &mut [u8]) -> Result<(), E> {
+        self.i2c
+            .write_read(FRAM_ADDRESS, &offset.to_be_bytes(), data)
+    }
+
+    /// FRAM writes complete immediately. There are no pages and no delay.
+    fn write(&mut self, offset: u16, data: &[u8; 8]) -> Result<(), E> {
+        let [high, low] = offset.to_be_bytes();
+        let mut buffer = [0; 10];
+        buffer[0] = high;
+        buffer[1] = low;
+        buffer[2..].copy_from_slice(data);
+        self.i2c.write(FRAM_ADDRESS, &buffer)
+    }
+}
+
+/// Read the stored total number of pulses. Returns 0 if nothing valid is
+/// stored.
+fn load_total<I2C, E>(fram: &mut Fram<I2C>, crc: &mut SoftwareCrc32) -> u32
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    let mut data = [0; 8];
+    if fram.read(TOTAL_OFFSET, &mut data).is_err() {
+        return 0;
+    }
+    let stored_crc = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    if crc.checksum(&data[..4]) == stored_crc {
+        u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+    } else {
+        log_warn!("No valid total stored");
+        0
+    }
+}
+
+fn store_total<I2C, E>(fram: &mut Fram<I2C>, crc: &mut SoftwareCrc32, pulses: u32) -> Result<(), E>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    let mut data = [0; 8];
+    data[..4].copy_from_slice(&pulses.to_le_bytes());
+    let checksum = crc.checksum(&data[..4]);
+    data[4..].copy_from_slice(&checksum.to_le_bytes());
+    fram.write(TOTAL_OFFSET, &data)
+}
+
+#[entry]
+fn main() -> ! {
+    rtt_init_print!();
+    log_info!("YF-S201 flow meter example");
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Enable the TIM2 clock before handing the RCC over to the HAL.
+    dp.RCC.apb1enr.modify(|_, w| w.tim2en().set_bit());
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    // TIM2 channel 1 input
+    let _flow_input = gpioa.pa0.into_pull_up_input(&mut gpioa.crl);
+    let button = gpiob.pb12.into_pull_down_input(&mut gpiob.crh);
+
+    // Count the rising edges of TI1 with the strongest input filter.
+    let tim2 = dp.TIM2;
+    tim2.ccmr1_input()
+        .modify(|_, w| w.cc1s().ti1().ic1f().fdts_div32_n8());
+    tim2.smcr
+        .modify(|_, w| w.ts().ti1fp1().sms().ext_clock_mode());
+    tim2.cr1.modify(|_, w| w.cen().enabled());
+
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+
+    let (i2c, _) = i2c_speed::i2c1_with_fallback(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        clocks,
+        &mut rcc.apb1,
+        // FRAM and display
+        |i2c| i2c_speed::probe_addresses(i2c, &[FRAM_ADDRESS, 0x3C]),
+    );
+
+    let mut gpioc = dp.GPIOC.split(&mut rcc.apb2);
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
+    let interface = I2CDIBuilder::new().init(manager.acquire());
+    let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+    disp.init().unwrap();
+    panic_display::register(Bus::I2c1);
+    disp.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut fram = Fram {
+        i2c: manager.acquire(),
+    };
+    let mut crc = SoftwareCrc32::new();
+    let mut total_pulses = load_total(&mut fram, &mut crc);
+    log_info!("Stored total: {} pulses", total_pulses);
+
+    let mut last_count = tim2.cnt.read().cnt().bits();
+    let mut lines: [String<32>; 3] = [String::new(), String::new(), String::new()];
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.set_high().unwrap();
+        delay.delay_ms(50_u16);
+        led.set_low().unwrap();
+        delay.delay_ms(950_u16);
+
+        // The 16-bit counter wraps around after 65536 pulses. That is far
+        // more than the sensor gives in one second.
+        let count = tim2.cnt.read().cnt().bits();
+        let pulses = u32::from(count.wrapping_sub(last_count));
+        last_count = count;
+
+        if button.is_high().unwrap() {
+            log_info!("Resetting total");
+            total_pulses = 0;
+            store_total(&mut fram, &mut crc, total_pulses).unwrap();
+        } else if pulses > 0 {
+            total_pulses = total_pulses.wrapping_add(pulses);
+            store_total(&mut fram, &mut crc, total_pulses).unwrap();
+        }
+
+        // 7.5 pulses per second per L/min
+        let flow = pulses as f32 / 7.5;
+        let liters = total_pulses as f32 / PULSES_PER_LITER as f32;
+        log_info!("Flow: {:.2} L/min, total: {:.3} L", flow, liters);
+
+        for line in lines.iter_mut() {
+            line.clear();
+        }
+        write!(lines[0], "Flow: {:.2} L/min", flow).unwrap();
+        write!(lines[1], "Total: {:.3} L", liters).unwrap();
+        write!(lines[2], "Pulses: {}", total_pulses).unwrap();
+        disp.clear();
+        for (i, line) in lines.iter().enumerate() {
+            Text::new(line, Point::new(0, i as i32 * 16))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .unwrap();
+        }
+        disp.flush().unwrap();
+    }
+}
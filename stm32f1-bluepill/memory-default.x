@@ -0,0 +1,6 @@
+/* STM32F103C8: 64K flash, 20K RAM */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K
+}
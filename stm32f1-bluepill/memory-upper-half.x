@@ -0,0 +1,8 @@
+/* STM32F103C8 application image loaded by the xmodem-firmware-update-bp
+   example: it starts in the upper half of the flash at 0x08008000.
+   The last 1K page is reserved for the update information. */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08008000, LENGTH = 31K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K
+}
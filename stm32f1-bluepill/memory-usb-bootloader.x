@@ -0,0 +1,7 @@
+/* STM32F103C8 with an 8K USB bootloader (STM32duino/HID) at the
+   beginning of the flash: the application starts at 0x08002000 */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08002000, LENGTH = 56K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K
+}
@@ -0,0 +1,258 @@
+//! Continuous sampling with ADC1 and circular DMA into a double buffer.
+//!
+//! ADC1 converts a sequence of up to 16 channels (scan mode) over and over
+//! (continuous mode) and DMA1 channel 1 writes every result into a buffer of
+//! two halves of `N` samples, without the CPU. While the DMA fills one half
+//! the program processes the other one: `poll()` calls a closure with each
+//! half as soon as it is complete. The samples of the channels are
+//! interleaved in the order of the sequence, so `N` must be a multiple of
+//! the number of channels.
+//!
+//! The closure must be done before the DMA has filled the other half. If it
+//! is not, or if `poll()` is not called often enough, samples are lost and
+//! counted by `overruns()`. For slow processing, copy the samples out first.
+//!
+//! The HAL only supports single conversions and one channel with DMA, so the
+//! ADC is set up through its registers. Enable the ADC1 clock and set the
+//! ADC clock (at most 14MHz) before, and configure the pins as analog
+//! inputs:
+//!
+//! ```ignore
+//! dp.RCC.apb2enr.modify(|_, w| w.adc1en().set_bit());
+//! let clocks = rcc.cfgr.adcclk(12.mhz()).freeze(&mut flash.acr);
+//! let _voltage = gpioa.pa0.into_analog(&mut gpioa.crl);
+//! let _current = gpioa.pa1.into_analog(&mut gpioa.crl);
+//! let dma_ch1 = dp.DMA1.split(&mut rcc.ahb).1;
+//! let buffer = singleton!(: [[u16; 512]; 2] = [[0; 512]; 2]).unwrap();
+//! let mut stream = AdcStream::start(dp.ADC1, dma_ch1, &[0, 1], SampleTime::T_239, buffer);
+//! loop {
+//!     stream.poll(|samples| {
+//!         for frame in samples.chunks_exact(2) { /* frame[0]: PA0, frame[1]: PA1 */ }
+//!     });
+//! }
+//! ```
+//!
+//! `poll()` can also run in the DMA1_CHANNEL1 interrupt handler, see
+//! `listen()`, so that the halves are processed in time while the program
+//! is busy with something slow, e.g. updating a display.
+//!
+//! Each conversion takes the sample time plus 12.5 ADC clock cycles, see
+//! `sample_rate_hz()`.
+
+use core::sync::atomic::{compiler_fence, Ordering};
+use stm32f1xx_hal::{adc::SampleTime, dma::dma1, pac};
+
+/// Longest sequence of channels
+pub const MAX_CHANNELS: usize = 16;
+
+/// Total ADC clock cycles of a conversion with the given sample time
+pub fn conversion_cycles(sample_time: SampleTime) -> f32 {
+    let sample_cycles = match sample_time {
+        SampleTime::T_1 => 1.5,
+        SampleTime::T_7 => 7.5,
+        SampleTime::T_13 => 13.5,
+        SampleTime::T_28 => 28.5,
+        SampleTime::T_41 => 41.5,
+        SampleTime::T_55 => 55.5,
+        SampleTime::T_71 => 71.5,
+        SampleTime::T_239 => 239.5,
+    };
+    sample_cycles + 12.5
+}
+
+/// Sampling rate of each channel in Hz
+pub fn sample_rate_hz(adc_hz: u32, sample_time: SampleTime, channels: usize) -> f32 {
+    adc_hz as f32 / conversion_cycles(sample_time) / channels as f32
+}
+
+/// SMPx register value of a sample time
+fn sample_time_bits(sample_time: SampleTime) -> u32 {
+    match sample_time {
+        SampleTime::T_1 => 0,
+        SampleTime::T_7 => 1,
+        SampleTime::T_13 => 2,
+        SampleTime::T_28 => 3,
+        SampleTime::T_41 => 4,
+        SampleTime::T_55 => 5,
+        SampleTime::T_71 => 6,
+        SampleTime::T_239 => 7,
+    }
+}
+
+/// ADC1 and DMA1 channel 1 streaming into two halves of `N` samples
+pub struct AdcStream<const N: usize> {
+    adc: pac::ADC1,
+    dma: dma1::C1,
+    buffer: &'static mut [[u16; N]; 2],
+    channels: usize,
+    overruns: u32,
+}
+
+impl<const N: usize> AdcStream<N> {
+    /// Calibrate the ADC and start converting `channels` (ADC channel
+    /// numbers, e.g. 0 for PA0) with the given sample time.
+    pub fn start(
+        adc: pac::ADC1,
+        mut dma: dma1::C1,
+        channels: &[u8],
+        sample_time: SampleTime,
+        buffer: &'static mut [[u16; N]; 2],
+    ) -> Self {
+        assert!(!channels.is_empty() && channels.len() <= MAX_CHANNELS);
+        assert!(N % channels.len() == 0 && 2 * N <= usize::from(u16::MAX));
+
+        // Power on, wait for the ADC to stabilize (1us) and calibrate.
+        adc.cr2.modify(|_, w| w.adon().set_bit());
+        cortex_m::asm::delay(1000);
+        adc.cr2.modify(|_, w| w.rstcal().set_bit());
+        while adc.cr2.read().rstcal().bit_is_set() {}
+        adc.cr2.modify(|_, w| w.cal().set_bit());
+        while adc.cr2.read().cal().bit_is_set() {}
+
+        // Sample times of the channels and the sequence: 3 bits per channel
+        // in SMPR2 (channels 0-9) and SMPR1 (10-17), 5 bits per position in
+        // SQR3 (1-6), SQR2 (7-12) and SQR1 (13-16) with the length in SQR1.
+        let bits = sample_time_bits(sample_time);
+        let (mut smpr1, mut smpr2) = (adc.smpr1.read().bits(), adc.smpr2.read().bits());
+        let mut sqr = [0_u32; 3];
+        for (i, channel) in channels.iter().map(|c| u32::from(*c)).enumerate() {
+            if channel < 10 {
+                smpr2 = (smpr2 & !(0b111 << (3 * channel))) | (bits << (3 * channel));
+            } else {
+                let shift = 3 * (channel - 10);
+                smpr1 = (smpr1 & !(0b111 << shift)) | (bits << shift);
+            }
+            sqr[i / 6] |= channel << (5 * (i % 6));
+        }
+        sqr[2] |= (channels.len() as u32 - 1) << 20;
+        #[allow(unsafe_code)]
+        unsafe {
+            adc.smpr1.write(|w| w.bits(smpr1));
+            adc.smpr2.write(|w| w.bits(smpr2));
+            adc.sqr3.write(|w| w.bits(sqr[0]));
+            adc.sqr2.write(|w| w.bits(sqr[1]));
+            adc.sqr1.write(|w| w.bits(sqr[2]));
+        }
+        adc.cr1.modify(|_, w| w.scan().set_bit());
+
+        dma.set_peripheral_address(&adc.dr as *const _ as u32, false);
+        dma.set_memory_address(buffer.as_ptr() as u32, true);
+        dma.set_transfer_length(2 * N);
+        // Flags left over from an earlier stream
+        dma.ifcr().write(|w| w.cgif1().set_bit());
+        dma.ch().cr.modify(|_, w| {
+            w.dir()
+                .clear_bit()
+                .circ()
+                .set_bit()
+                .psize()
+                .bits16()
+                .msize()
+                .bits16()
+                .pl()
+                .high()
+        });
+        dma.start();
+
+        // Continuous conversions with DMA, started by software
+        adc.cr2.modify(|_, w| {
+            w.cont()
+                .set_bit()
+                .dma()
+                .set_bit()
+                .align()
+                .clear_bit()
+                .exttrig()
+                .set_bit()
+                .extsel()
+                .swstart()
+        });
+        adc.cr2.modify(|_, w| w.swstart().set_bit());
+
+        AdcStream {
+            adc,
+            dma,
+            buffer,
+            channels: channels.len(),
+            overruns: 0,
+        }
+    }
+
+    /// Call `f` with the samples of the half which was completed since the
+    /// last call, if any, and return its result.
+    pub fn poll<R, F: FnOnce(&[u16; N]) -> R>(&mut self, f: F) -> Option<R> {
+        let isr = self.dma.isr();
+        let (first_done, second_done) = (isr.htif1().bit_is_set(), isr.tcif1().bit_is_set());
+        let half = match (first_done, second_done) {
+            (false, false) => return None,
+            (true, false) => 0,
+            (false, true) => 1,
+            // Both halves completed: the older one is being overwritten.
+            // The DMA writes the first half again after the second one.
+            (true, true) => {
+                self.overruns += 1;
+                if self.position() < N {
+                    1
+                } else {
+                    0
+                }
+            }
+        };
+        self.dma
+            .ifcr()
+            .write(|w| w.chtif1().set_bit().ctcif1().set_bit());
+        // The buffer is written by the DMA.
+        compiler_fence(Ordering::SeqCst);
+        let result = f(&self.buffer[half]);
+        compiler_fence(Ordering::SeqCst);
+
+        // The other half completed too: the DMA has started overwriting the
+        // samples while `f` was processing them.
+        let isr = self.dma.isr();
+        let overtaken = if half == 0 {
+            isr.tcif1().bit_is_set()
+        } else {
+            isr.htif1().bit_is_set()
+        };
+        if overtaken {
+            self.overruns += 1;
+        }
+        Some(result)
+    }
+
+    /// Enable the DMA1_CHANNEL1 interrupt when a half is complete, to call
+    /// `poll()` from its handler instead of a loop.
+    pub fn listen(&mut self) {
+        self.dma
+            .ch()
+            .cr
+            .modify(|_, w| w.htie().set_bit().tcie().set_bit());
+    }
+
+    /// Number of halves that were lost or overwritten while being processed
+    pub fn overruns(&self) -> u32 {
+        self.overruns
+    }
+
+    /// Number of channels in the sequence
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Stop the conversions and the DMA, e.g. to start again with another
+    /// sample time.
+    pub fn stop(mut self) -> (pac::ADC1, dma1::C1, &'static mut [[u16; N]; 2]) {
+        self.adc
+            .cr2
+            .modify(|_, w| w.cont().clear_bit().dma().clear_bit());
+        self.adc.cr2.modify(|_, w| w.adon().clear_bit());
+        self.dma.stop();
+        compiler_fence(Ordering::SeqCst);
+        (self.adc, self.dma, self.buffer)
+    }
+
+    /// Index of the next sample written by the DMA
+    fn position(&mut self) -> usize {
+        2 * N - usize::from(self.dma.ch().ndtr.read().ndt().bits())
+    }
+}
@@ -0,0 +1,102 @@
+//! Latched alarm which must be acknowledged, like in fire and gas alarm
+//! panels.
+//!
+//! An alarm does not go away by itself when its condition disappears. Once
+//! tripped it stays latched until someone has acknowledged it and the
+//! condition is gone, in any order:
+//!
+//! ```text
+//!            condition                 acknowledge
+//! Normal ─────────────────> Active ──────────────────> Acknowledged
+//!    ^                        │                             │
+//!    │                        │ condition gone              │ condition gone
+//!    │                        v                             │
+//!    │ acknowledge         Cleared                          │
+//!    └─────────────────────────┴────────────────────────────┘
+//! ```
+//!
+//! The buzzer should sound while the alarm is unacknowledged (`Active` and
+//! `Cleared`), the outputs which make the situation safe should stay on
+//! while it is latched (everything but `Normal`).
+//!
+//! ```ignore
+//! let mut alarm = LatchedAlarm::new();
+//! loop {
+//!     alarm.update(smoke_detected());
+//!     if button_pressed() {
+//!         alarm.acknowledge();
+//!     }
+//!     buzzer.set_state(alarm.is_unacknowledged().into());
+//!     relay.set_state(alarm.is_latched().into());
+//! }
+//! ```
+
+/// State of a latched alarm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// No alarm
+    Normal,
+    /// The condition is present and nobody has acknowledged the alarm.
+    Active,
+    /// The condition is still present but the alarm was acknowledged.
+    Acknowledged,
+    /// The condition is gone but nobody has acknowledged the alarm.
+    Cleared,
+}
+
+/// Alarm which latches until it is acknowledged and its condition is gone
+#[derive(Debug, Clone)]
+pub struct LatchedAlarm {
+    state: State,
+}
+
+impl Default for LatchedAlarm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatchedAlarm {
+    pub fn new() -> Self {
+        LatchedAlarm {
+            state: State::Normal,
+        }
+    }
+
+    /// Update with the current condition. Returns `true` if the alarm has
+    /// just tripped.
+    pub fn update(&mut self, condition: bool) -> bool {
+        let previous = self.state;
+        self.state = match (self.state, condition) {
+            (State::Normal, true) | (State::Cleared, true) => State::Active,
+            (State::Active, false) => State::Cleared,
+            (State::Acknowledged, false) => State::Normal,
+            (state, _) => state,
+        };
+        previous == State::Normal && self.state == State::Active
+    }
+
+    /// Acknowledge the alarm. It returns to normal if the condition is
+    /// already gone.
+    pub fn acknowledge(&mut self) {
+        self.state = match self.state {
+            State::Active => State::Acknowledged,
+            State::Cleared => State::Normal,
+            state => state,
+        };
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// The alarm has tripped and has not returned to normal yet.
+    pub fn is_latched(&self) -> bool {
+        self.state != State::Normal
+    }
+
+    /// The alarm has tripped and nobody has acknowledged it yet.
+    pub fn is_unacknowledged(&self) -> bool {
+        self.state == State::Active || self.state == State::Cleared
+    }
+}
@@ -0,0 +1,104 @@
+//! Air quality index (AQI) calculation from particulate matter concentrations.
+//!
+//! This uses the breakpoints of the US EPA for PM2.5 and PM10. The index is
+//! interpolated linearly between the breakpoints. The AQI is officially
+//! defined for 24-hour averages so values calculated from single
+//! measurements are only an indication.
+//!
+//! ```ignore
+//! let index = aqi(Pollutant::Pm2_5, 20.3).unwrap();
+//! rprintln!("AQI: {} ({})", index, Category::from_aqi(index).name());
+//! ```
+
+/// Particulate matter size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pollutant {
+    /// Particles smaller than 2.5µm
+    Pm2_5,
+    /// Particles smaller than 10µm
+    Pm10,
+}
+
+/// AQI category
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Category {
+    Good,
+    Moderate,
+    UnhealthyForSensitiveGroups,
+    Unhealthy,
+    VeryUnhealthy,
+    Hazardous,
+}
+
+impl Category {
+    /// Category of an AQI value
+    pub fn from_aqi(aqi: u16) -> Self {
+        match aqi {
+            0..=50 => Category::Good,
+            51..=100 => Category::Moderate,
+            101..=150 => Category::UnhealthyForSensitiveGroups,
+            151..=200 => Category::Unhealthy,
+            201..=300 => Category::VeryUnhealthy,
+            _ => Category::Hazardous,
+        }
+    }
+
+    /// Short name that fits on small displays
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Good => "Good",
+            Category::Moderate => "Moderate",
+            Category::UnhealthyForSensitiveGroups => "Unhealthy (SG)",
+            Category::Unhealthy => "Unhealthy",
+            Category::VeryUnhealthy => "Very unhealthy",
+            Category::Hazardous => "Hazardous",
+        }
+    }
+}
+
+/// Concentration breakpoints (low, high) in µg/m³ and corresponding AQI range
+const PM2_5_BREAKPOINTS: [(f32, f32, u16, u16); 7] = [
+    (0.0, 12.0, 0, 50),
+    (12.1, 35.4, 51, 100),
+    (35.5, 55.4, 101, 150),
+    (55.5, 150.4, 151, 200),
+    (150.5, 250.4, 201, 300),
+    (250.5, 350.4, 301, 400),
+    (350.5, 500.4, 401, 500),
+];
+
+const PM10_BREAKPOINTS: [(f32, f32, u16, u16); 7] = [
+    (0.0, 54.0, 0, 50),
+    (55.0, 154.0, 51, 100),
+    (155.0, 254.0, 101, 150),
+    (255.0, 354.0, 151, 200),
+    (355.0, 424.0, 201, 300),
+    (425.0, 504.0, 301, 400),
+    (505.0, 604.0, 401, 500),
+];
+
+/// Calculate the AQI for a concentration in µg/m³.
+///
+/// Returns `None` if the concentration is negative or above the highest
+/// breakpoint.
+pub fn aqi(pollutant: Pollutant, concentration: f32) -> Option<u16> {
+    // The concentrations are truncated to the precision of the breakpoints.
+    let (concentration, breakpoints) = match pollutant {
+        Pollutant::Pm2_5 => (
+            (concentration * 10.0) as u32 as f32 / 10.0,
+            &PM2_5_BREAKPOINTS,
+        ),
+        Pollutant::Pm10 => (concentration as u32 as f32, &PM10_BREAKPOINTS),
+    };
+    if concentration < 0.0 {
+        return None;
+    }
+    breakpoints
+        .iter()
+        .find(|(_, high, _, _)| concentration <= *high)
+        .map(|(c_low, c_high, i_low, i_high)| {
+            let index = f32::from(i_high - i_low) / (c_high - c_low) * (concentration - c_low)
+                + f32::from(*i_low);
+            (index + 0.5) as u16
+        })
+}
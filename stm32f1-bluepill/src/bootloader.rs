@@ -0,0 +1,42 @@
+//! Support for running the examples behind a USB bootloader.
+//!
+//! USB bootloaders like the STM32duino or the HID bootloader occupy the first
+//! 8K of the flash memory. Building with the `usb-bootloader` feature places
+//! the application after them. The interrupt vector table of the application
+//! must then be activated at runtime, otherwise interrupts are handled by
+//! the vector table of the bootloader. Examples which use interrupts call
+//! `relocate_vector_table()` at the beginning.
+
+use cortex_m::peripheral::SCB;
+
+/// Address of the application vector table when using a USB bootloader.
+pub const APPLICATION_ADDRESS: u32 = 0x0800_2000;
+
+/// Point the vector table offset register to the application vector table.
+///
+/// This does nothing unless the `usb-bootloader` feature is enabled.
+#[allow(unused_variables)]
+pub fn relocate_vector_table(scb: &mut SCB) {
+    #[cfg(feature = "usb-bootloader")]
+    unsafe {
+        scb.vtor.write(APPLICATION_ADDRESS);
+    }
+}
+
+/// Start the application whose vector table is at `address`.
+///
+/// The stack pointer and the vector table are set up for the application
+/// and then its reset handler is called.
+///
+/// # Safety
+///
+/// There must be a valid application at `address`. Any interrupts enabled
+/// by the caller will fire in the application.
+pub unsafe fn jump_to_application(scb: &mut SCB, address: u32) -> ! {
+    let stack_pointer = core::ptr::read_volatile(address as *const u32);
+    let reset_vector = core::ptr::read_volatile((address + 4) as *const u32);
+    scb.vtor.write(address);
+    cortex_m::register::msp::write(stack_pointer);
+    let reset: extern "C" fn() -> ! = core::mem::transmute(reset_vector as usize);
+    reset()
+}
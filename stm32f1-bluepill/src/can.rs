@@ -0,0 +1,275 @@
+//! Minimal driver for the bxCAN peripheral (CAN1) of the STM32F103.
+//!
+//! The HAL does not support CAN, so the registers are written directly.
+//! The driver sends and receives classic data frames with standard 11-bit
+//! or extended 29-bit ids through transmit mailbox 0 and receive FIFO 0.
+//! CAN FD is not supported by the bxCAN peripheral.
+//!
+//! ```ignore
+//! // Before handing the RCC over to the HAL:
+//! dp.RCC.apb1enr.modify(|_, w| w.canen().set_bit());
+//! // ...
+//! let timing = can::bit_timing(clocks.pclk1().0, 500_000).unwrap();
+//! let mut can = Can::new(dp.CAN1, timing)?;
+//! can.set_filter(0, 0x200, 0x7F0);
+//! can.transmit(&Frame::new(0x100, &[1, 2, 3]).unwrap())?;
+//! if let Some(frame) = can.receive() {
+//!     // ...
+//! }
+//! ```
+//!
+//! The peripheral only receives the frames which pass one of its filters,
+//! so set at least one. The bus needs a transceiver like the SN65HVD230 and
+//! termination resistors of 120R at both ends. CAN1 shares its RAM with the
+//! USB peripheral, so they can't be used at the same time.
+
+use stm32f1xx_hal::pac::CAN1;
+
+const MCR_INRQ: u32 = 1 << 0;
+const MCR_TXFP: u32 = 1 << 2;
+const MCR_ABOM: u32 = 1 << 6;
+const MSR_INAK: u32 = 1 << 0;
+const MSR_SLAK: u32 = 1 << 1;
+const ESR_BOFF: u32 = 1 << 2;
+const TSR_TME0: u32 = 1 << 26;
+const RF0R_FMP0: u32 = 0b11;
+const RF0R_RFOM0: u32 = 1 << 5;
+const TIR_TXRQ: u32 = 1 << 0;
+/// Reads of the status register while waiting for a mode change, about
+/// 10ms at 72MHz. Joining the bus takes 11 bits, 1.1ms at 10kbit/s.
+const MODE_POLLS: u32 = 100_000;
+/// Extended id bit of the id registers
+const IR_IDE: u32 = 1 << 2;
+/// Remote frame bit of the id registers
+const IR_RTR: u32 = 1 << 1;
+const FMR_FINIT: u32 = 1 << 0;
+const STD_ID_SHIFT: u32 = 21;
+const EXT_ID_SHIFT: u32 = 3;
+pub const MAX_STD_ID: u16 = 0x7FF;
+pub const MAX_EXT_ID: u32 = 0x1FFF_FFFF;
+
+/// Frame id
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Id {
+    /// 11-bit id
+    Standard(u16),
+    /// 29-bit id
+    Extended(u32),
+}
+
+impl Id {
+    /// Value of the id registers
+    fn register(self) -> u32 {
+        match self {
+            Id::Standard(id) => u32::from(id) << STD_ID_SHIFT,
+            Id::Extended(id) => id << EXT_ID_SHIFT | IR_IDE,
+        }
+    }
+}
+
+/// CAN data frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    id: Id,
+    data: [u8; 8],
+    len: u8,
+}
+
+impl Frame {
+    /// Frame with a standard id. `None` if the id has more than 11 bits or
+    /// the data more than 8 bytes.
+    pub fn new(id: u16, data: &[u8]) -> Option<Self> {
+        if id > MAX_STD_ID {
+            return None;
+        }
+        Self::with_id(Id::Standard(id), data)
+    }
+
+    /// Frame with an extended id. `None` if the id has more than 29 bits or
+    /// the data more than 8 bytes.
+    pub fn new_extended(id: u32, data: &[u8]) -> Option<Self> {
+        if id > MAX_EXT_ID {
+            return None;
+        }
+        Self::with_id(Id::Extended(id), data)
+    }
+
+    fn with_id(id: Id, data: &[u8]) -> Option<Self> {
+        if data.len() > 8 {
+            return None;
+        }
+        let mut frame = Frame {
+            id,
+            data: [0; 8],
+            len: data.len() as u8,
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        Some(frame)
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..usize::from(self.len)]
+    }
+}
+
+/// Bit timing register value for a bit rate, with the sample point at
+/// about 87.5% as recommended for CANopen. `None` if the bit rate can't be
+/// made from `pclk1_hz` exactly.
+pub fn bit_timing(pclk1_hz: u32, bitrate: u32) -> Option<u32> {
+    // Prefer more time quanta per bit for a finer sample point.
+    (8..=25).rev().find_map(|quanta: u32| {
+        let prescaler = pclk1_hz / (bitrate * quanta);
+        if prescaler == 0 || prescaler > 1024 || prescaler * bitrate * quanta != pclk1_hz {
+            return None;
+        }
+        // The sync segment is one quantum.
+        let before_sample = (quanta * 7 + 4) / 8 - 1;
+        let after_sample = quanta - 1 - before_sample;
+        if !(1..=16).contains(&before_sample) || !(1..=8).contains(&after_sample) {
+            return None;
+        }
+        Some((prescaler - 1) | (before_sample - 1) << 16 | (after_sample - 1) << 20)
+    })
+}
+
+/// CAN errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The peripheral did not enter or leave the initialization mode. It
+    /// leaves it only after 11 recessive bits on RX, which needs a
+    /// transceiver and the RX pin set up as input.
+    Timeout,
+}
+
+/// bxCAN driver
+pub struct Can {
+    can: CAN1,
+}
+
+impl Can {
+    /// Configure the peripheral with the value of `bit_timing()` and join
+    /// the bus. The peripheral clock must be enabled in the RCC. Returns
+    /// `Error::Timeout` instead of waiting forever if the bus stays silent.
+    ///
+    /// The peripheral recovers from the bus-off state by itself, and
+    /// frames are sent in the order they were queued.
+    pub fn new(can: CAN1, bit_timing: u32) -> Result<Self, Error> {
+        // Leave the sleep mode and enter the initialization mode.
+        can.mcr.write(|w| unsafe { w.bits(MCR_INRQ) });
+        wait_for_mode(&can, MSR_INAK | MSR_SLAK, MSR_INAK)?;
+        can.btr.write(|w| unsafe { w.bits(bit_timing) });
+        can.mcr.write(|w| unsafe { w.bits(MCR_ABOM | MCR_TXFP) });
+        // The peripheral leaves the initialization mode after 11 recessive
+        // bits on the bus.
+        wait_for_mode(&can, MSR_INAK, 0)?;
+        Ok(Can { can })
+    }
+
+    /// Let the frames with a standard id which matches `id` in the bits set
+    /// in `mask` into receive FIFO 0, with filter bank `bank` (0 to 13).
+    /// Extended and remote frames are not let through.
+    pub fn set_filter(&mut self, bank: usize, id: u16, mask: u16) {
+        self.set_filter_bank(
+            bank,
+            Id::Standard(id).register(),
+            u32::from(mask) << STD_ID_SHIFT | IR_IDE | IR_RTR,
+        );
+    }
+
+    /// Like `set_filter()`, for the frames with an extended id.
+    pub fn set_extended_filter(&mut self, bank: usize, id: u32, mask: u32) {
+        self.set_filter_bank(
+            bank,
+            Id::Extended(id).register(),
+            mask << EXT_ID_SHIFT | IR_IDE | IR_RTR,
+        );
+    }
+
+    fn set_filter_bank(&mut self, bank: usize, id: u32, mask: u32) {
+        let bit = 1 << bank;
+        let can = &self.can;
+        can.fmr
+            .modify(|r, w| unsafe { w.bits(r.bits() | FMR_FINIT) });
+        can.fa1r.modify(|r, w| unsafe { w.bits(r.bits() & !bit) });
+        // One 32-bit filter in mask mode, assigned to FIFO 0
+        can.fs1r.modify(|r, w| unsafe { w.bits(r.bits() | bit) });
+        can.fm1r.modify(|r, w| unsafe { w.bits(r.bits() & !bit) });
+        can.ffa1r.modify(|r, w| unsafe { w.bits(r.bits() & !bit) });
+        can.fb[bank].fr1.write(|w| unsafe { w.bits(id) });
+        can.fb[bank].fr2.write(|w| unsafe { w.bits(mask) });
+        can.fa1r.modify(|r, w| unsafe { w.bits(r.bits() | bit) });
+        can.fmr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !FMR_FINIT) });
+    }
+
+    /// Queue a frame for sending. `WouldBlock` while the previous frame is
+    /// still waiting for the bus.
+    pub fn transmit(&mut self, frame: &Frame) -> nb::Result<(), core::convert::Infallible> {
+        if self.can.tsr.read().bits() & TSR_TME0 == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        let mailbox = &self.can.tx[0];
+        let word = |i: usize| {
+            u32::from_le_bytes([
+                frame.data[i],
+                frame.data[i + 1],
+                frame.data[i + 2],
+                frame.data[i + 3],
+            ])
+        };
+        mailbox
+            .tdtr
+            .write(|w| unsafe { w.bits(u32::from(frame.len)) });
+        mailbox.tdlr.write(|w| unsafe { w.bits(word(0)) });
+        mailbox.tdhr.write(|w| unsafe { w.bits(word(4)) });
+        mailbox
+            .tir
+            .write(|w| unsafe { w.bits(frame.id.register() | TIR_TXRQ) });
+        Ok(())
+    }
+
+    /// Take the oldest frame out of receive FIFO 0, if there is one.
+    pub fn receive(&mut self) -> Option<Frame> {
+        if self.can.rf0r.read().bits() & RF0R_FMP0 == 0 {
+            return None;
+        }
+        let mailbox = &self.can.rx[0];
+        let rir = mailbox.rir.read().bits();
+        let id = if rir & IR_IDE == 0 {
+            Id::Standard((rir >> STD_ID_SHIFT) as u16)
+        } else {
+            Id::Extended(rir >> EXT_ID_SHIFT)
+        };
+        let len = (mailbox.rdtr.read().bits() & 0x0F).min(8) as u8;
+        let mut data = [0; 8];
+        data[..4].copy_from_slice(&mailbox.rdlr.read().bits().to_le_bytes());
+        data[4..].copy_from_slice(&mailbox.rdhr.read().bits().to_le_bytes());
+        // Release the FIFO entry.
+        self.can.rf0r.write(|w| unsafe { w.bits(RF0R_RFOM0) });
+        Some(Frame { id, data, len })
+    }
+
+    /// Whether the peripheral left the bus after too many errors.
+    pub fn bus_off(&self) -> bool {
+        self.can.esr.read().bits() & ESR_BOFF != 0
+    }
+
+    /// Destroy the driver and return the peripheral.
+    pub fn destroy(self) -> CAN1 {
+        self.can
+    }
+}
+
+/// Wait until the bits of `mask` in the status register read `value`.
+fn wait_for_mode(can: &CAN1, mask: u32, value: u32) -> Result<(), Error> {
+    for _ in 0..MODE_POLLS {
+        if can.msr.read().bits() & mask == value {
+            return Ok(());
+        }
+    }
+    Err(Error::Timeout)
+}
@@ -0,0 +1,102 @@
+//! Clock configuration presets, selected with a feature.
+//!
+//! The examples which use `configure()` instead of configuring the RCC
+//! themselves run at the clock of the selected preset:
+//!
+//! - `LowPower` (`clock-low-power` feature): 8MHz from the internal
+//!   oscillator without the PLL, which draws the least current.
+//! - `Default`: 48MHz from the 8MHz crystal, which also allows USB.
+//! - `MaxPerformance` (`clock-max-performance` feature): 72MHz from the
+//!   crystal, the highest frequency in the datasheet. The HAL does not
+//!   allow more.
+//!
+//! ```ignore
+//! let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
+//! clock::log_clocks(&clocks);
+//! ```
+//!
+//! Examples which depend on a certain frequency, e.g. for timers, USB or
+//! the ADC sample rate, keep their own configuration.
+
+use core::fmt;
+use stm32f1xx_hal::{
+    flash::ACR,
+    prelude::*,
+    rcc::{Clocks, CFGR},
+};
+
+/// Named clock configuration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preset {
+    LowPower,
+    Default,
+    MaxPerformance,
+}
+
+/// Preset selected with the features, `clock-low-power` wins if both are
+/// enabled.
+#[cfg(feature = "clock-low-power")]
+pub const PRESET: Preset = Preset::LowPower;
+#[cfg(all(feature = "clock-max-performance", not(feature = "clock-low-power")))]
+pub const PRESET: Preset = Preset::MaxPerformance;
+#[cfg(not(any(feature = "clock-low-power", feature = "clock-max-performance")))]
+pub const PRESET: Preset = Preset::Default;
+
+impl Preset {
+    /// Name for messages
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::LowPower => "low-power",
+            Preset::Default => "default",
+            Preset::MaxPerformance => "max-performance",
+        }
+    }
+}
+
+/// Configure and freeze the clocks with the selected preset.
+pub fn configure(cfgr: CFGR, acr: &mut ACR) -> Clocks {
+    configure_preset(PRESET, cfgr, acr)
+}
+
+/// Configure and freeze the clocks with the given preset.
+pub fn configure_preset(preset: Preset, cfgr: CFGR, acr: &mut ACR) -> Clocks {
+    match preset {
+        Preset::LowPower => cfgr.sysclk(8.mhz()).freeze(acr),
+        Preset::Default => cfgr
+            .use_hse(8.mhz())
+            .sysclk(48.mhz())
+            .pclk1(24.mhz())
+            .freeze(acr),
+        Preset::MaxPerformance => cfgr
+            .use_hse(8.mhz())
+            .sysclk(72.mhz())
+            .pclk1(36.mhz())
+            .pclk2(72.mhz())
+            .adcclk(12.mhz())
+            .freeze(acr),
+    }
+}
+
+/// Write the core and bus frequencies in MHz in one short line, e.g. for a
+/// display: `CPU 72 APB 36/72MHz`.
+pub fn write_summary<W: fmt::Write>(writer: &mut W, clocks: &Clocks) -> fmt::Result {
+    write!(
+        writer,
+        "CPU {} APB {}/{}MHz",
+        clocks.sysclk().0 / 1_000_000,
+        clocks.pclk1().0 / 1_000_000,
+        clocks.pclk2().0 / 1_000_000
+    )
+}
+
+/// Log all frequencies.
+pub fn log_clocks(clocks: &Clocks) {
+    crate::log_info!(
+        "Clocks: SYSCLK {}Hz, HCLK {}Hz, PCLK1 {}Hz, PCLK2 {}Hz, ADCCLK {}Hz",
+        clocks.sysclk().0,
+        clocks.hclk().0,
+        clocks.pclk1().0,
+        clocks.pclk2().0,
+        clocks.adcclk().0
+    );
+}
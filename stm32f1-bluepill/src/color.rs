@@ -0,0 +1,59 @@
+//! Color helpers for the LED driver examples.
+//!
+//! The colors are RGB with 12-bit channels, the resolution of PWM LED
+//! drivers like the PCA9685 or the TLC5947. To avoid floating point
+//! calculations in the hue to RGB conversion the channels are limited to
+//! [0-4080] as 4080 = 60*68.
+//!
+//! ```ignore
+//! for (r, g, b) in Rainbow::new(0) {
+//!     let (r, g, b) = (gamma(r), gamma(g), gamma(b));
+//!     // ...
+//! }
+//! ```
+
+/// Largest value of a channel
+pub const MAX: u16 = 4080;
+/// Perceived brightness is roughly the light output to the power 1/2.2.
+const GAMMA: f32 = 2.2;
+
+/// Fully saturated color of the given hue in degrees [0-360].
+pub fn hue_to_rgb(hue: u16) -> (u16, u16, u16) {
+    // See HSV to RGB conversion: https://en.wikipedia.org/wiki/HSL_and_HSV
+    match hue % 360 {
+        hue @ 0..=59 => (MAX, hue * 68, 0),
+        hue @ 60..=119 => ((120 - hue) * 68, MAX, 0),
+        hue @ 120..=179 => (0, MAX, (hue - 120) * 68),
+        hue @ 180..=239 => (0, (240 - hue) * 68, MAX),
+        hue @ 240..=299 => ((hue - 240) * 68, 0, MAX),
+        hue => (MAX, 0, (360 - hue) * 68),
+    }
+}
+
+/// Gamma correction of a channel, so that a linear change of the value
+/// looks like a linear change of brightness.
+pub fn gamma(value: u16) -> u16 {
+    let normalized = f32::from(value.min(MAX)) / f32::from(MAX);
+    (libm::powf(normalized, GAMMA) * f32::from(MAX) + 0.5) as u16
+}
+
+/// RGB rainbow generator
+pub struct Rainbow {
+    hue: u16,
+}
+
+impl Rainbow {
+    /// Start at the given hue in degrees.
+    pub fn new(hue: u16) -> Self {
+        Rainbow { hue }
+    }
+}
+
+impl Iterator for Rainbow {
+    type Item = (u16, u16, u16);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.hue = (self.hue + 1) % 360;
+        Some(hue_to_rgb(self.hue))
+    }
+}
@@ -0,0 +1,61 @@
+//! Complementary filter to estimate a tilt angle from a gyroscope and an
+//! accelerometer.
+//!
+//! The gyroscope is precise over short times but its integrated angle
+//! drifts. The angle calculated from the direction of gravity measured by
+//! the accelerometer does not drift but is disturbed by every movement. The
+//! filter integrates the gyroscope rate and slowly pulls the result towards
+//! the accelerometer angle:
+//!
+//! `angle = alpha * (angle + rate * dt) + (1 - alpha) * accel_angle`
+//!
+//! With `alpha` close to 1 the gyroscope dominates. The time constant of
+//! the filter is about `dt * alpha / (1 - alpha)`, for example 0.25s with
+//! `alpha = 0.98` at 200Hz.
+//!
+//! ```ignore
+//! let mut filter = ComplementaryFilter::new(0.98);
+//! loop {
+//!     let accel_angle = libm::atan2f(ax, az).to_degrees();
+//!     let angle = filter.update(gyro_y_dps, accel_angle, 0.005);
+//! }
+//! ```
+
+/// Complementary filter for one angle
+#[derive(Debug, Clone)]
+pub struct ComplementaryFilter {
+    alpha: f32,
+    angle: Option<f32>,
+}
+
+impl ComplementaryFilter {
+    /// Create a filter. `alpha` is the weight of the gyroscope: 0-1
+    pub fn new(alpha: f32) -> Self {
+        ComplementaryFilter {
+            alpha: alpha.clamp(0.0, 1.0),
+            angle: None,
+        }
+    }
+
+    /// Combine a new gyroscope rate (in units per second) and accelerometer
+    /// angle measured `dt_s` seconds after the previous ones and return the
+    /// angle. The first call returns the accelerometer angle.
+    pub fn update(&mut self, rate: f32, accel_angle: f32, dt_s: f32) -> f32 {
+        let angle = match self.angle {
+            Some(angle) => self.alpha * (angle + rate * dt_s) + (1.0 - self.alpha) * accel_angle,
+            None => accel_angle,
+        };
+        self.angle = Some(angle);
+        angle
+    }
+
+    /// Last estimated angle
+    pub fn angle(&self) -> Option<f32> {
+        self.angle
+    }
+
+    /// Start again from the next accelerometer angle.
+    pub fn reset(&mut self) {
+        self.angle = None;
+    }
+}
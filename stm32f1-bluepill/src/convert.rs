@@ -0,0 +1,48 @@
+//! Conversion of raw measurements into physical units.
+//!
+//! Helpers for analog sensors read with the 12-bit ADC of the STM32F1: the
+//! raw samples are averaged, converted into millivolts and then into the
+//! measured quantity using the characteristic curve of the sensor given as a
+//! table of points.
+//!
+//! ```ignore
+//! const CURVE: [(i32, i32); 3] = [(0, 0), (1000, 50), (2000, 150)];
+//! let raw = average((0..16).map(|_| adc1.read(&mut pin).unwrap()));
+//! let value = interpolate(&CURVE, adc_to_millivolts(raw) as i32);
+//! ```
+
+/// Highest value of the 12-bit ADC
+pub const ADC_MAX: u16 = 4095;
+/// ADC reference voltage in millivolts. VREF+ is VDDA (3.3V) on the Bluepill.
+pub const VREF_MV: u32 = 3300;
+
+/// Convert a raw ADC reading into millivolts.
+pub fn adc_to_millivolts(raw: u16) -> u32 {
+    u32::from(raw.min(ADC_MAX)) * VREF_MV / u32::from(ADC_MAX)
+}
+
+/// Average of the samples. Returns 0 if there are none.
+pub fn average<I: IntoIterator<Item = u16>>(samples: I) -> u16 {
+    let (sum, count) = samples.into_iter().fold((0_u32, 0_u32), |(sum, count), s| {
+        (sum + u32::from(s), count + 1)
+    });
+    sum.checked_div(count).unwrap_or(0) as u16
+}
+
+/// Linear interpolation in a table of (input, output) points sorted by input.
+///
+/// Inputs beyond the table give the output of the closest end.
+pub fn interpolate(table: &[(i32, i32)], input: i32) -> i32 {
+    let (first_input, first_output) = table[0];
+    if input <= first_input {
+        return first_output;
+    }
+    for pair in table.windows(2) {
+        let (x0, y0) = pair[0];
+        let (x1, y1) = pair[1];
+        if input <= x1 {
+            return y0 + (y1 - y0) * (input - x0) / (x1 - x0);
+        }
+    }
+    table[table.len() - 1].1
+}
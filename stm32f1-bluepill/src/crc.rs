@@ -0,0 +1,100 @@
+//! CRC-32 checksums using the hardware CRC unit or a software fallback.
+//!
+//! The STM32 CRC unit calculates the CRC-32/MPEG-2 checksum
+//! (polynomial 0x04C11DB7, initial value 0xFFFFFFFF, no reflection, no final
+//! XOR) over 32-bit words. `SoftwareCrc32` calculates exactly the same so that
+//! data written with one implementation can be validated with the other, for
+//! example on a device without CRC unit.
+//!
+//! ```ignore
+//! let mut crc = dp.CRC.new(&mut rcc.ahb);
+//! let checksum = crc.checksum(&data);
+//! assert_eq!(checksum, SoftwareCrc32::new().checksum(&data));
+//! ```
+
+use stm32f1xx_hal::crc::Crc;
+
+/// CRC-32/MPEG-2 calculation over 32-bit words.
+pub trait Crc32 {
+    /// Restart the calculation.
+    fn reset(&mut self);
+
+    /// Feed a word into the calculation.
+    fn feed(&mut self, word: u32);
+
+    /// Current checksum.
+    fn value(&self) -> u32;
+
+    /// Calculate the checksum of `data` from scratch.
+    ///
+    /// The data is fed as little-endian words. An incomplete last word is
+    /// padded with zeros.
+    fn checksum(&mut self, data: &[u8]) -> u32 {
+        self.reset();
+        for chunk in data.chunks(4) {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.feed(u32::from_le_bytes(word));
+        }
+        self.value()
+    }
+}
+
+impl Crc32 for Crc {
+    fn reset(&mut self) {
+        Crc::reset(self);
+    }
+
+    fn feed(&mut self, word: u32) {
+        self.write(word);
+    }
+
+    fn value(&self) -> u32 {
+        self.read()
+    }
+}
+
+/// Software implementation of the CRC unit calculation.
+#[derive(Debug, Clone)]
+pub struct SoftwareCrc32 {
+    value: u32,
+}
+
+impl SoftwareCrc32 {
+    const POLYNOMIAL: u32 = 0x04C1_1DB7;
+    const INITIAL: u32 = 0xFFFF_FFFF;
+
+    pub fn new() -> Self {
+        SoftwareCrc32 {
+            value: Self::INITIAL,
+        }
+    }
+}
+
+impl Default for SoftwareCrc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 for SoftwareCrc32 {
+    fn reset(&mut self) {
+        self.value = Self::INITIAL;
+    }
+
+    fn feed(&mut self, word: u32) {
+        let mut crc = self.value ^ word;
+        for _ in 0..32 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ Self::POLYNOMIAL
+            } else {
+                crc << 1
+            };
+        }
+        self.value = crc;
+    }
+
+    fn value(&self) -> u32 {
+        self.value
+    }
+}
@@ -0,0 +1,186 @@
+//! DALI lighting bus frames and their Manchester encoding.
+//!
+//! DALI (IEC 62386) connects a controller with up to 64 LED drivers and
+//! ballasts (control gear) over two wires at 1200 bit/s. The controller
+//! sends forward frames: a start bit, an address byte and a command byte.
+//! The control gear answers queries with a backward frame: a start bit and
+//! one byte. Every bit is split into two halves of `HALF_BIT_US` with
+//! opposite levels, low and then high for a 1, high and then low for a 0.
+//! The bus is high while idle, and frames end with two idle stop bits.
+//!
+//! `forward()` returns the bus level of every half bit of a forward frame,
+//! so it can be sent by a timer interrupt at 2400Hz. The decoder of
+//! backward frames does not care how the levels are measured. Feed it with
+//! the duration of every period of constant level, like the `NecDecoder`:
+//!
+//! ```ignore
+//! let levels = dali::forward(BROADCAST_ARC_POWER, dali::arc_level(50.0));
+//! // ...
+//! let mut decoder = BackwardDecoder::new();
+//! // on every edge:
+//! if let Some(answer) = decoder.pulse(was_high, duration_us) {
+//!     rprintln!("{:08b}", answer);
+//! }
+//! ```
+//!
+//! The bus needs a power supply and an interface circuit, which usually
+//! inverts the levels.
+
+/// Duration of half a bit (Te) in microseconds
+pub const HALF_BIT_US: u32 = 417;
+/// Number of half bits of a forward frame with its start and stop bits
+pub const FORWARD_HALF_BITS: usize = 38;
+/// Time within which the control gear answers a query, in microseconds
+pub const ANSWER_TIMEOUT_US: u32 = 22 * HALF_BIT_US + 18 * HALF_BIT_US;
+
+/// Address byte sending the direct arc power to all control gear
+pub const BROADCAST_ARC_POWER: u8 = 0xFE;
+/// Address byte sending a command to all control gear
+pub const BROADCAST_COMMAND: u8 = 0xFF;
+
+/// Command: switch off without fading
+pub const OFF: u8 = 0x00;
+/// Query: status byte, see the `STATUS_` flags
+pub const QUERY_STATUS: u8 = 0x90;
+/// Query: whether the lamp failed, yes (0xFF) or no answer
+pub const QUERY_LAMP_FAILURE: u8 = 0x92;
+/// Query: arc power level of the lamp
+pub const QUERY_ACTUAL_LEVEL: u8 = 0xA0;
+
+/// Status flag: the control gear does not work
+pub const STATUS_GEAR_FAILURE: u8 = 1 << 0;
+/// Status flag: the lamp failed
+pub const STATUS_LAMP_FAILURE: u8 = 1 << 1;
+/// Status flag: the lamp is on
+pub const STATUS_LAMP_ON: u8 = 1 << 2;
+/// Status flag: a fade is running
+pub const STATUS_FADE_RUNNING: u8 = 1 << 4;
+/// Status flag: the control gear has no short address
+pub const STATUS_NO_SHORT_ADDRESS: u8 = 1 << 6;
+/// Status flag: the power was lost since the last arc power command
+pub const STATUS_POWER_FAILURE: u8 = 1 << 7;
+
+/// Number of bits of a backward frame without the start bit
+const BACKWARD_BITS: usize = 8;
+
+/// Address byte sending the direct arc power to the control gear with the
+/// short address `address` (0 to 63)
+pub fn arc_power_address(address: u8) -> u8 {
+    (address & 0x3F) << 1
+}
+
+/// Address byte sending a command to the control gear with the short
+/// address `address` (0 to 63)
+pub fn command_address(address: u8) -> u8 {
+    (address & 0x3F) << 1 | 1
+}
+
+/// Arc power level (1 to 254, 0 is off) for a light output in %, with the
+/// logarithmic dimming curve of DALI: level 1 is 0.1% and 254 is 100%.
+pub fn arc_level(percent: f32) -> u8 {
+    if percent < 0.1 {
+        return 0;
+    }
+    let level = 1.0 + (libm::log10f(percent) + 1.0) * 253.0 / 3.0;
+    (level + 0.5).min(254.0) as u8
+}
+
+/// Bus levels of the half bits of a forward frame with its start and stop
+/// bits. `true` is high.
+pub fn forward(address: u8, data: u8) -> [bool; FORWARD_HALF_BITS] {
+    let bits = 1 << 16 | u32::from(address) << 8 | u32::from(data);
+    // The stop bits are idle.
+    let mut levels = [true; FORWARD_HALF_BITS];
+    for (i, pair) in levels[..34].chunks_mut(2).enumerate() {
+        let one = bits & 1 << (16 - i) != 0;
+        pair[0] = !one;
+        pair[1] = one;
+    }
+    levels
+}
+
+/// Manchester decoder of backward frames
+#[derive(Debug, Clone)]
+pub struct BackwardDecoder {
+    bits: u16,
+    half_bits: usize,
+    first_half: bool,
+}
+
+impl Default for BackwardDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BackwardDecoder {
+    /// Create a new decoder
+    pub fn new() -> Self {
+        BackwardDecoder {
+            bits: 0,
+            half_bits: 0,
+            first_half: false,
+        }
+    }
+
+    /// Feed the duration of a period of constant level. `high` is the bus
+    /// level during the period.
+    ///
+    /// Returns the answer when a complete backward frame has been received.
+    pub fn pulse(&mut self, high: bool, duration_us: u32) -> Option<u8> {
+        // The start bit begins with the first low half bit.
+        if self.half_bits == 0 && high {
+            return None;
+        }
+        let half_bits = if near(duration_us, HALF_BIT_US) {
+            1
+        } else if near(duration_us, 2 * HALF_BIT_US) {
+            2
+        } else {
+            self.half_bits = 0;
+            return None;
+        };
+        for _ in 0..half_bits {
+            if let Some(answer) = self.half_bit(high) {
+                return Some(answer);
+            }
+            if self.half_bits == 0 {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn half_bit(&mut self, high: bool) -> Option<u8> {
+        if self.half_bits == 0 {
+            self.bits = 0;
+        }
+        if self.half_bits % 2 == 0 {
+            self.first_half = high;
+            // The second half of the last bit may be idle like the bus
+            // after the frame, so the frame ends with its first half.
+            if self.half_bits == 2 * BACKWARD_BITS {
+                self.half_bits = 0;
+                let answer = self.bits << 1 | u16::from(!high);
+                return if answer >> BACKWARD_BITS == 1 {
+                    Some(answer as u8)
+                } else {
+                    None
+                };
+            }
+        } else if high == self.first_half {
+            // No transition in the middle of the bit
+            self.half_bits = 0;
+            return None;
+        } else {
+            self.bits = self.bits << 1 | u16::from(high);
+        }
+        self.half_bits += 1;
+        None
+    }
+}
+
+/// Whether `duration` is within 25% of `expected`
+fn near(duration: u32, expected: u32) -> bool {
+    duration > expected * 3 / 4 && duration < expected * 5 / 4
+}
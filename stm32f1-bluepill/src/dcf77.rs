@@ -0,0 +1,180 @@
+//! DCF77 radio time signal decoder.
+//!
+//! The DCF77 transmitter reduces its carrier at the start of every second
+//! for 100ms (a 0 bit) or 200ms (a 1 bit). The reduction of the 59th second
+//! is missing, so the next reduction marks the start of the minute. During
+//! the minute, the time of the next minute is sent in BCD with even parity
+//! bits for the minutes, the hours and the date.
+//!
+//! The decoder does not care how the pulses are measured. Feed it with the
+//! time between the start of the carrier reductions and with their duration,
+//! for example measured between the edges of the output of a receiver module.
+//!
+//! ```ignore
+//! let mut decoder = Dcf77Decoder::new();
+//! // at the start of every reduction:
+//! if let Some(Ok(time)) = decoder.pulse_start(ms_since_last_start) {
+//!     rprintln!("{:?}", time); // the current time at second 0
+//! }
+//! // at the end of every reduction:
+//! decoder.pulse_end(duration_ms);
+//! ```
+
+/// Decoded date and time at the start of the minute
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dcf77Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    /// 1 is Monday, 7 is Sunday
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Central European Summer Time (UTC+2) instead of UTC+1
+    pub summer_time: bool,
+}
+
+/// Decoding errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// A pulse or the time between pulses did not have a valid duration
+    Timing,
+    /// The number of bits received during the minute was not 59
+    Incomplete(u8),
+    /// A parity bit does not match
+    Parity,
+    /// A decoded value is out of range
+    InvalidValue,
+}
+
+/// DCF77 decoder
+#[derive(Debug, Clone)]
+pub struct Dcf77Decoder {
+    bits: u64,
+    count: u8,
+    valid: bool,
+}
+
+impl Default for Dcf77Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dcf77Decoder {
+    /// Create a new decoder
+    pub fn new() -> Self {
+        Dcf77Decoder {
+            bits: 0,
+            count: 0,
+            valid: true,
+        }
+    }
+
+    /// Number of bits received in the current minute
+    pub fn bits_received(&self) -> u8 {
+        self.count
+    }
+
+    /// Feed the start of a carrier reduction. `period_ms` is the time since
+    /// the start of the previous reduction.
+    ///
+    /// At the start of a minute, returns the result of decoding the minute
+    /// that just ended, which is the time now.
+    pub fn pulse_start(&mut self, period_ms: u32) -> Option<Result<Dcf77Time, Error>> {
+        if (1800..=2200).contains(&period_ms) {
+            let result = if !self.valid {
+                Err(Error::Timing)
+            } else if self.count != 59 {
+                Err(Error::Incomplete(self.count))
+            } else {
+                decode(self.bits)
+            };
+            self.bits = 0;
+            self.count = 0;
+            self.valid = true;
+            Some(result)
+        } else {
+            if !(800..=1200).contains(&period_ms) {
+                self.valid = false;
+            }
+            None
+        }
+    }
+
+    /// Feed the duration of a carrier reduction.
+    pub fn pulse_end(&mut self, duration_ms: u32) {
+        let bit = match duration_ms {
+            60..=140 => false,
+            160..=260 => true,
+            _ => {
+                self.valid = false;
+                return;
+            }
+        };
+        if self.count < 59 {
+            if bit {
+                self.bits |= 1 << self.count;
+            }
+            self.count += 1;
+        } else {
+            self.valid = false;
+        }
+    }
+}
+
+fn decode(bits: u64) -> Result<Dcf77Time, Error> {
+    // Bit 0 is always 0 and bit 20 always 1.
+    if bits & 1 != 0 || bits & (1 << 20) == 0 {
+        return Err(Error::InvalidValue);
+    }
+    if !even_parity(bits, 21, 28) || !even_parity(bits, 29, 35) || !even_parity(bits, 36, 58) {
+        return Err(Error::Parity);
+    }
+    let time = Dcf77Time {
+        minute: bcd(bits, 21, 7)?,
+        hour: bcd(bits, 29, 6)?,
+        day: bcd(bits, 36, 6)?,
+        weekday: bcd(bits, 42, 3)?,
+        month: bcd(bits, 45, 5)?,
+        year: 2000 + u16::from(bcd(bits, 50, 8)?),
+        summer_time: bits & (1 << 17) != 0,
+    };
+    let valid = time.minute < 60
+        && time.hour < 24
+        && (1..=31).contains(&time.day)
+        && (1..=7).contains(&time.weekday)
+        && (1..=12).contains(&time.month);
+    if valid {
+        Ok(time)
+    } else {
+        Err(Error::InvalidValue)
+    }
+}
+
+/// Whether the number of ones in bits `first..=last` (including the parity
+/// bit `last`) is even
+fn even_parity(bits: u64, first: u8, last: u8) -> bool {
+    let mask = ((1_u64 << (last - first + 1)) - 1) << first;
+    (bits & mask).count_ones() % 2 == 0
+}
+
+/// Decode `len` BCD bits starting at `first`.
+fn bcd(bits: u64, first: u8, len: u8) -> Result<u8, Error> {
+    const WEIGHTS: [u8; 8] = [1, 2, 4, 8, 10, 20, 40, 80];
+    let mut units = 0;
+    let mut tens = 0;
+    for (i, weight) in WEIGHTS.iter().take(usize::from(len)).enumerate() {
+        if bits & (1 << (first as usize + i)) != 0 {
+            if i < 4 {
+                units += weight;
+            } else {
+                tens += weight;
+            }
+        }
+    }
+    if units > 9 {
+        return Err(Error::InvalidValue);
+    }
+    Ok(tens + units)
+}
@@ -0,0 +1,95 @@
+//! Microsecond and millisecond delays with the DWT cycle counter.
+//!
+//! The `Delay` of the HAL takes SysTick over, which then can not be used for
+//! the `monotonic` timebase or by RTIC, and `asm::delay()` only guarantees a
+//! minimum. `DwtDelay` counts the cycles of the core instead, which is
+//! accurate to a few cycles and leaves SysTick alone. It implements the
+//! `DelayUs` and `DelayMs` traits, so it can be passed to any driver, and it
+//! can be copied, so several drivers can have one.
+//!
+//! The DWT cycle counter must be enabled:
+//!
+//! ```ignore
+//! cp.DCB.enable_trace();
+//! cp.DWT.enable_cycle_counter();
+//! let mut delay = DwtDelay::new(clocks.sysclk().0);
+//! trigger.set_high().unwrap();
+//! delay.delay_us(10_u8);
+//! trigger.set_low().unwrap();
+//! ```
+//!
+//! Interrupts which run in between make a delay longer, so disable them
+//! around the parts of a protocol where that matters.
+
+use cortex_m::peripheral::DWT;
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+/// Busy waiting delay timed with the DWT cycle counter
+#[derive(Debug, Clone, Copy)]
+pub struct DwtDelay {
+    cycles_per_us: u32,
+}
+
+impl DwtDelay {
+    /// Create a new delay for the given core clock.
+    pub fn new(sysclk_hz: u32) -> Self {
+        DwtDelay {
+            cycles_per_us: (sysclk_hz / 1_000_000).max(1),
+        }
+    }
+
+    /// Number of core cycles in the given number of microseconds
+    pub fn us_to_cycles(&self, us: u32) -> u32 {
+        us * self.cycles_per_us
+    }
+
+    /// Busy wait for the given number of core cycles.
+    pub fn delay_cycles(&self, cycles: u32) {
+        let start = DWT::get_cycle_count();
+        while DWT::get_cycle_count().wrapping_sub(start) < cycles {}
+    }
+}
+
+impl DelayUs<u32> for DwtDelay {
+    fn delay_us(&mut self, us: u32) {
+        // Wait in steps of one second, so that the cycles fit into a u32.
+        let mut remaining = us;
+        while remaining > 0 {
+            let step = remaining.min(1_000_000);
+            self.delay_cycles(self.us_to_cycles(step));
+            remaining -= step;
+        }
+    }
+}
+
+impl DelayUs<u16> for DwtDelay {
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(u32::from(us));
+    }
+}
+
+impl DelayUs<u8> for DwtDelay {
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(u32::from(us));
+    }
+}
+
+impl DelayMs<u32> for DwtDelay {
+    fn delay_ms(&mut self, ms: u32) {
+        for _ in 0..ms {
+            self.delay_us(1000_u32);
+        }
+    }
+}
+
+impl DelayMs<u16> for DwtDelay {
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(u32::from(ms));
+    }
+}
+
+impl DelayMs<u8> for DwtDelay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(u32::from(ms));
+    }
+}
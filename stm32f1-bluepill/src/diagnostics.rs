@@ -0,0 +1,186 @@
+//! Fault codes shown as LED blink patterns and kept over a reset.
+//!
+//! An example running without a debug probe gives no hint of what went
+//! wrong when it stops. `fail()` records the fault in RAM which is not
+//! initialized at startup, blinks its code on the LED on PC13 a few times
+//! and resets the board. After the reset, the example picks the record up
+//! to print or show it:
+//!
+//! ```ignore
+//! if let Some(last) = diagnostics::take_last_fault() {
+//!     log_warn!("Last fault: {:?} at {:#010x}", last.fault, last.pc);
+//!     diagnostics::blink_code(last.fault);
+//! }
+//! // ...
+//! if sensor.read().is_err() {
+//!     diagnostics::fail(Fault::SensorMissing);
+//! }
+//! ```
+//!
+//! A code is a long blink followed by as many short blinks as its value,
+//! then a pause: a long and two short blinks mean a missing sensor.
+//!
+//! Hard faults are recorded with the address of the faulting instruction
+//! with the handler of this module:
+//!
+//! ```ignore
+//! #[exception]
+//! fn HardFault(ef: &ExceptionFrame) -> ! {
+//!     diagnostics::hard_fault(ef)
+//! }
+//! ```
+//!
+//! The record survives a reset, but not a power cycle.
+
+use core::{mem::MaybeUninit, ptr};
+use cortex_m::{asm, interrupt, peripheral::SCB};
+use cortex_m_rt::ExceptionFrame;
+use stm32f1xx_hal::pac;
+
+/// Category of a fault. The value is the number of short blinks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// A device stopped answering in the middle of a transfer
+    I2cTimeout = 1,
+    /// A device does not answer at all
+    SensorMissing = 2,
+    /// An SD card can not be read or written
+    SdError = 3,
+    /// Recorded by `hard_fault()`
+    HardFault = 4,
+}
+
+impl Fault {
+    fn from_code(code: u32) -> Option<Self> {
+        match code {
+            1 => Some(Fault::I2cTimeout),
+            2 => Some(Fault::SensorMissing),
+            3 => Some(Fault::SdError),
+            4 => Some(Fault::HardFault),
+            _ => None,
+        }
+    }
+
+    /// Number of short blinks in the code
+    pub fn blinks(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Fault recorded before the last reset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LastFault {
+    pub fault: Fault,
+    /// Address of the faulting instruction for hard faults, 0 otherwise
+    pub pc: u32,
+}
+
+/// Tells a record from what is in the RAM after power up
+const MAGIC: u32 = 0xFA17_C0DE;
+/// Times `fail()` blinks the code before resetting
+const REPEATS: u32 = 5;
+const LONG_MS: u32 = 600;
+const SHORT_MS: u32 = 150;
+const GAP_MS: u32 = 300;
+const PAUSE_MS: u32 = 1500;
+/// Frequency of the HSI, and of the crystal of the Bluepill
+const OSC_HZ: u32 = 8_000_000;
+
+#[repr(C)]
+struct Record {
+    magic: u32,
+    code: u32,
+    pc: u32,
+}
+
+// The .uninit section of cortex-m-rt is not zeroed at startup.
+#[link_section = ".uninit.diagnostics"]
+static mut RECORD: MaybeUninit<Record> = MaybeUninit::uninit();
+
+fn record(fault: Fault, pc: u32) {
+    // Interrupts are disabled, nothing else accesses RECORD.
+    unsafe {
+        ptr::write_volatile(
+            RECORD.as_mut_ptr(),
+            Record {
+                magic: MAGIC,
+                code: fault as u32,
+                pc,
+            },
+        );
+    }
+}
+
+/// Return the fault recorded before the last reset, once.
+pub fn take_last_fault() -> Option<LastFault> {
+    interrupt::free(|_| unsafe {
+        let record = ptr::read_volatile(RECORD.as_ptr());
+        ptr::write_volatile(&mut (*RECORD.as_mut_ptr()).magic, 0);
+        if record.magic != MAGIC {
+            return None;
+        }
+        Fault::from_code(record.code).map(|fault| LastFault {
+            fault,
+            pc: record.pc,
+        })
+    })
+}
+
+/// Record `fault`, blink its code and reset.
+pub fn fail(fault: Fault) -> ! {
+    interrupt::disable();
+    record(fault, 0);
+    blink_and_reset(fault)
+}
+
+/// Record a hard fault, blink its code and reset. Call it from the
+/// `HardFault` handler.
+pub fn hard_fault(ef: &ExceptionFrame) -> ! {
+    record(Fault::HardFault, ef.pc);
+    blink_and_reset(Fault::HardFault)
+}
+
+fn blink_and_reset(fault: Fault) -> ! {
+    for _ in 0..REPEATS {
+        blink_code(fault);
+    }
+    SCB::sys_reset()
+}
+
+/// Blink the code of `fault` once on the LED on PC13. The pin is taken
+/// over, so it does not matter whether the example configured it already.
+pub fn blink_code(fault: Fault) {
+    let (rcc, gpioc) = unsafe { (&*pac::RCC::ptr(), &*pac::GPIOC::ptr()) };
+    rcc.apb2enr.modify(|_, w| w.iopcen().set_bit());
+    gpioc
+        .crh
+        .modify(|_, w| w.mode13().output2().cnf13().push_pull());
+    let cycles_per_ms = sysclk_hz() / 1000;
+    let flash = |on_ms: u32| {
+        // The LED is on when PC13 is low.
+        gpioc.bsrr.write(|w| w.br13().set_bit());
+        asm::delay(on_ms * cycles_per_ms);
+        gpioc.bsrr.write(|w| w.bs13().set_bit());
+        asm::delay(GAP_MS * cycles_per_ms);
+    };
+    flash(LONG_MS);
+    for _ in 0..fault.blinks() {
+        flash(SHORT_MS);
+    }
+    asm::delay(PAUSE_MS * cycles_per_ms);
+}
+
+/// Core clock read back from the RCC, so that the blinks have the same
+/// length before and after the example sets the clocks up
+fn sysclk_hz() -> u32 {
+    let cfgr = unsafe { &*pac::RCC::ptr() }.cfgr.read();
+    if cfgr.sws().bits() != 0b10 {
+        return OSC_HZ;
+    }
+    let input = if cfgr.pllsrc().bit_is_set() && cfgr.pllxtpre().bit_is_clear() {
+        OSC_HZ
+    } else {
+        OSC_HZ / 2
+    };
+    input * (u32::from(cfgr.pllmul().bits()) + 2).min(16)
+}
@@ -0,0 +1,192 @@
+//! Minimal DroneCAN (UAVCAN v0) node: broadcast messages over CAN.
+//!
+//! DroneCAN runs on classic CAN with extended 29-bit ids, which carry the
+//! priority, the data type id and the node id of the sender. Every frame
+//! ends with a tail byte holding the transfer id, so receivers can tell
+//! repeated and lost messages apart. This module only implements messages
+//! which fit into a single frame (7 bytes), which covers the node status
+//! and the air data messages. Frames are made with the `can` module:
+//!
+//! ```ignore
+//! let mut node = Node::new(42).unwrap();
+//! let status = NodeStatus {
+//!     uptime_s: monotonic::millis() / 1000,
+//!     health: Health::Ok,
+//!     mode: Mode::Operational,
+//!     vendor_status: 0,
+//! };
+//! let frame = node.broadcast(PRIORITY_LOW, NODE_STATUS_ID, &status.encode());
+//! can.transmit(&frame.unwrap())?;
+//! ```
+//!
+//! Every node on the bus needs a different node id, from 1 to 127, and must
+//! broadcast its status once a second. Services like `GetNodeInfo` and the
+//! dynamic node id allocation are not implemented, so tools like the
+//! DroneCAN GUI list the node without a name.
+
+use crate::can::Frame;
+use heapless::LinearMap;
+
+/// `uavcan.protocol.NodeStatus`
+pub const NODE_STATUS_ID: u16 = 341;
+/// `uavcan.equipment.air_data.StaticPressure`
+pub const STATIC_PRESSURE_ID: u16 = 1028;
+/// `uavcan.equipment.air_data.StaticTemperature`
+pub const STATIC_TEMPERATURE_ID: u16 = 1029;
+
+pub const PRIORITY_HIGH: u8 = 8;
+pub const PRIORITY_MEDIUM: u8 = 16;
+pub const PRIORITY_LOW: u8 = 24;
+
+pub const MAX_NODE_ID: u8 = 127;
+/// Payload of a single frame transfer
+pub const MAX_PAYLOAD_LEN: usize = 7;
+
+const START_OF_TRANSFER: u8 = 1 << 7;
+const END_OF_TRANSFER: u8 = 1 << 6;
+const TRANSFER_ID_MASK: u8 = 0x1F;
+
+/// Health of a node
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Health {
+    Ok = 0,
+    Warning = 1,
+    Error = 2,
+    Critical = 3,
+}
+
+/// Operating mode of a node
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Operational = 0,
+    Initialization = 1,
+    Maintenance = 2,
+    SoftwareUpdate = 3,
+    Offline = 7,
+}
+
+/// `uavcan.protocol.NodeStatus` message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeStatus {
+    pub uptime_s: u32,
+    pub health: Health,
+    pub mode: Mode,
+    /// Free to use by the application
+    pub vendor_status: u16,
+}
+
+impl NodeStatus {
+    pub fn encode(&self) -> [u8; 7] {
+        let [u0, u1, u2, u3] = self.uptime_s.to_le_bytes();
+        let [v0, v1] = self.vendor_status.to_le_bytes();
+        // The sub mode is always 0.
+        let state = (self.health as u8) << 6 | (self.mode as u8) << 3;
+        [u0, u1, u2, u3, state, v0, v1]
+    }
+}
+
+/// `uavcan.equipment.air_data.StaticPressure` message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StaticPressure {
+    /// Pa
+    pub pressure: f32,
+    /// Pa²
+    pub variance: f32,
+}
+
+impl StaticPressure {
+    pub fn encode(&self) -> [u8; 6] {
+        let [p0, p1, p2, p3] = self.pressure.to_le_bytes();
+        let [v0, v1] = f16_bits(self.variance).to_le_bytes();
+        [p0, p1, p2, p3, v0, v1]
+    }
+}
+
+/// `uavcan.equipment.air_data.StaticTemperature` message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StaticTemperature {
+    /// K
+    pub temperature: f32,
+    /// K²
+    pub variance: f32,
+}
+
+impl StaticTemperature {
+    pub fn encode(&self) -> [u8; 4] {
+        let [t0, t1] = f16_bits(self.temperature).to_le_bytes();
+        let [v0, v1] = f16_bits(self.variance).to_le_bytes();
+        [t0, t1, v0, v1]
+    }
+}
+
+/// Node which keeps the transfer ids of the messages it broadcasts
+#[derive(Debug)]
+pub struct Node {
+    id: u8,
+    transfer_ids: LinearMap<u16, u8, 8>,
+}
+
+impl Node {
+    /// `None` if the node id is not between 1 and 127.
+    pub fn new(id: u8) -> Option<Self> {
+        if id == 0 || id > MAX_NODE_ID {
+            return None;
+        }
+        Some(Node {
+            id,
+            transfer_ids: LinearMap::new(),
+        })
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Frame broadcasting an encoded message of the data type
+    /// `data_type_id`, with a priority from 0 (highest) to 31. `None` if
+    /// the payload does not fit into one frame or the node already sends 8
+    /// other data types.
+    pub fn broadcast(&mut self, priority: u8, data_type_id: u16, payload: &[u8]) -> Option<Frame> {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return None;
+        }
+        if !self.transfer_ids.contains_key(&data_type_id) {
+            self.transfer_ids.insert(data_type_id, 0).ok()?;
+        }
+        let transfer_id = self.transfer_ids.get_mut(&data_type_id)?;
+        let tail = START_OF_TRANSFER | END_OF_TRANSFER | *transfer_id;
+        *transfer_id = (*transfer_id + 1) & TRANSFER_ID_MASK;
+
+        let mut data = [0; MAX_PAYLOAD_LEN + 1];
+        data[..payload.len()].copy_from_slice(payload);
+        data[payload.len()] = tail;
+        // The message bit (7) is 0.
+        let id =
+            u32::from(priority & 0x1F) << 24 | u32::from(data_type_id) << 8 | u32::from(self.id);
+        Frame::new_extended(id, &data[..=payload.len()])
+    }
+}
+
+/// IEEE 754 half precision bits of a value, rounded towards 0
+fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    if value.is_nan() {
+        return sign | 0x7E00;
+    }
+    let exponent = ((bits >> 23) & 0xFF) as i32 - 127 + 15;
+    let mantissa = bits & 0x7F_FFFF;
+    if exponent >= 0x1F {
+        // Too large: infinity
+        sign | 0x7C00
+    } else if exponent <= 0 {
+        // Subnormal or too small: 0
+        if exponent < -10 {
+            sign
+        } else {
+            sign | ((mantissa | 0x80_0000) >> (14 - exponent)) as u16
+        }
+    } else {
+        sign | (exponent as u16) << 10 | (mantissa >> 13) as u16
+    }
+}
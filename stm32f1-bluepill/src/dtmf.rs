@@ -0,0 +1,149 @@
+//! DTMF (touch tone) keys: the tone pairs and a decoder.
+//!
+//! Every key is the sum of two sines, one of the row and one of the
+//! column of the keypad:
+//!
+//! ```text
+//!         1209Hz 1336Hz 1477Hz 1633Hz
+//! 697Hz     1      2      3      A
+//! 770Hz     4      5      6      B
+//! 852Hz     7      8      9      C
+//! 941Hz     *      0      #      D
+//! ```
+//!
+//! `tones()` returns the frequencies to generate for a key. The
+//! `DtmfDecoder` measures all 8 frequencies with `Goertzel` filters in
+//! blocks of `BLOCK_LEN` samples, about 25ms at the usual 8kHz sampling
+//! rate. A key is detected when both the strongest row and the strongest
+//! column carry most of the energy of the block and is returned once it
+//! was detected in two blocks in a row. Send the tones for at least 50ms
+//! with a pause of at least 50ms.
+
+use crate::goertzel::Goertzel;
+
+/// Samples per block
+pub const BLOCK_LEN: u32 = 205;
+/// Lowest amplitude of the tones, in sample steps
+pub const MIN_AMPLITUDE: i64 = 40;
+
+/// Row frequencies
+pub const ROWS_HZ: [f32; 4] = [697.0, 770.0, 852.0, 941.0];
+/// Column frequencies
+pub const COLUMNS_HZ: [f32; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+
+const KEYS: [[u8; 4]; 4] = [*b"123A", *b"456B", *b"789C", *b"*0#D"];
+
+/// Row and column frequency of a key: `b'0'` to `b'9'`, `b'A'` to `b'D'`,
+/// `b'*'` or `b'#'`
+pub fn tones(key: u8) -> Option<(f32, f32)> {
+    let key = key.to_ascii_uppercase();
+    KEYS.iter().enumerate().find_map(|(row, keys)| {
+        let column = keys.iter().position(|k| *k == key)?;
+        Some((ROWS_HZ[row], COLUMNS_HZ[column]))
+    })
+}
+
+/// DTMF decoder
+#[derive(Debug, Clone)]
+pub struct DtmfDecoder {
+    rows: [Goertzel; 4],
+    columns: [Goertzel; 4],
+    count: u32,
+    energy: i64,
+    /// Key detected in the last block
+    candidate: Option<u8>,
+    /// Key returned last, until it is released
+    key: Option<u8>,
+}
+
+impl DtmfDecoder {
+    /// Create a new decoder for samples at `sample_rate_hz`.
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let filter = |hz| Goertzel::new(hz, sample_rate_hz, BLOCK_LEN);
+        DtmfDecoder {
+            rows: [
+                filter(ROWS_HZ[0]),
+                filter(ROWS_HZ[1]),
+                filter(ROWS_HZ[2]),
+                filter(ROWS_HZ[3]),
+            ],
+            columns: [
+                filter(COLUMNS_HZ[0]),
+                filter(COLUMNS_HZ[1]),
+                filter(COLUMNS_HZ[2]),
+                filter(COLUMNS_HZ[3]),
+            ],
+            count: 0,
+            energy: 0,
+            candidate: None,
+            key: None,
+        }
+    }
+
+    /// Add the next sample without DC offset. Returns a key once when it
+    /// is pressed.
+    pub fn push(&mut self, sample: i16) -> Option<u8> {
+        for filter in self.rows.iter_mut().chain(self.columns.iter_mut()) {
+            filter.push(sample);
+        }
+        self.energy += i64::from(sample) * i64::from(sample);
+        self.count += 1;
+        if self.count < BLOCK_LEN {
+            return None;
+        }
+        let detected = self.detect();
+        for filter in self.rows.iter_mut().chain(self.columns.iter_mut()) {
+            filter.reset();
+        }
+        self.energy = 0;
+        self.count = 0;
+
+        let stable = detected == self.candidate;
+        self.candidate = detected;
+        if stable && detected != self.key {
+            self.key = detected;
+            return detected;
+        }
+        None
+    }
+
+    /// Key of the block that just ended
+    fn detect(&self) -> Option<u8> {
+        let n = i64::from(BLOCK_LEN);
+        // Two sines of amplitude A have an energy of n * A².
+        if self.energy < n * MIN_AMPLITUDE * MIN_AMPLITUDE {
+            return None;
+        }
+        // Each of the two tones has a power of about energy * n / 4. Accept
+        // half of that, but the other frequencies must be 4 times weaker.
+        let threshold = (self.energy * n / 8) as u64;
+        let row = strongest(&self.rows, threshold)?;
+        let column = strongest(&self.columns, threshold)?;
+        Some(KEYS[row][column])
+    }
+}
+
+/// Index of the filter with the highest power if it is above the threshold
+/// and clearly stronger than the others
+fn strongest(filters: &[Goertzel; 4], threshold: u64) -> Option<usize> {
+    let powers = [
+        filters[0].power(),
+        filters[1].power(),
+        filters[2].power(),
+        filters[3].power(),
+    ];
+    let (index, max) = powers
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, power)| **power)
+        .map(|(index, power)| (index, *power))?;
+    let others_weak = powers
+        .iter()
+        .enumerate()
+        .all(|(i, power)| i == index || *power < max / 4);
+    if max >= threshold && others_weak {
+        Some(index)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,85 @@
+//! Smooth movements for servos and other actuators.
+//!
+//! A `Motion` goes from its current position to a target in a given time.
+//! It starts and stops gently (cubic ease-in-out), which avoids jerks in
+//! mechanical systems like robotic arms. Call `update()` periodically with
+//! the elapsed time and send the returned position to the actuator:
+//!
+//! ```ignore
+//! let mut motion = Motion::new(90.0);
+//! motion.start(45.0, 1000);
+//! loop {
+//!     let angle = motion.update(20);
+//!     // set the servo to `angle`, wait 20ms
+//! }
+//! ```
+//!
+//! Several motions can be coordinated by giving them the same duration, so
+//! that they arrive at the same time.
+
+/// Cubic ease-in-out: goes from 0 to 1 as `t` goes from 0 to 1, slowly at
+/// the start and the end.
+pub fn ease_in_out(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Movement from one position to another
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Motion {
+    from: f32,
+    to: f32,
+    duration_ms: u32,
+    elapsed_ms: u32,
+}
+
+impl Motion {
+    /// Create a motion standing still at `position`.
+    pub fn new(position: f32) -> Self {
+        Motion {
+            from: position,
+            to: position,
+            duration_ms: 0,
+            elapsed_ms: 0,
+        }
+    }
+
+    /// Start moving from the current position to `to`, arriving in
+    /// `duration_ms`. A running movement continues from where it is.
+    pub fn start(&mut self, to: f32, duration_ms: u32) {
+        self.from = self.position();
+        self.to = to;
+        self.duration_ms = duration_ms;
+        self.elapsed_ms = 0;
+    }
+
+    /// Stop where the motion is now.
+    pub fn stop(&mut self) {
+        let position = self.position();
+        *self = Motion::new(position);
+    }
+
+    /// Advance the motion by `dt_ms` and return the new position.
+    pub fn update(&mut self, dt_ms: u32) -> f32 {
+        self.elapsed_ms = (self.elapsed_ms + dt_ms).min(self.duration_ms);
+        self.position()
+    }
+
+    /// Current position
+    pub fn position(&self) -> f32 {
+        if self.elapsed_ms >= self.duration_ms {
+            return self.to;
+        }
+        let t = self.elapsed_ms as f32 / self.duration_ms as f32;
+        self.from + (self.to - self.from) * ease_in_out(t)
+    }
+
+    /// Position at the end of the movement
+    pub fn target(&self) -> f32 {
+        self.to
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed_ms >= self.duration_ms
+    }
+}
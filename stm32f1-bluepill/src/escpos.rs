@@ -0,0 +1,139 @@
+//! Minimal ESC/POS command builder for serial thermal printers like the
+//! CSN-A2 (the common "mini thermal receipt printer").
+//!
+//! The printer prints text as it is received and a line is printed when a
+//! newline arrives. The commands change the formatting of the following
+//! text. `Printer` implements `core::fmt::Write` so that text can be printed
+//! with `write!`:
+//!
+//! ```ignore
+//! let mut printer = Printer::new(tx);
+//! printer.init()?;
+//! printer.align(Align::Center)?;
+//! printer.size(2, 2)?;
+//! writeln!(printer, "Hello").unwrap();
+//! printer.size(1, 1)?;
+//! printer.bold(true)?;
+//! writeln!(printer, "Temperature: {:.1}C", 23.5).unwrap();
+//! printer.feed(3)?;
+//! ```
+//!
+//! These printers have no flow control over the serial line. The buffer of
+//! the printer is large enough for text, but bitmaps are only printed
+//! reliably at 19200 baud or less.
+
+use embedded_hal::serial;
+use nb::block;
+
+const ESC: u8 = 0x1B;
+const GS: u8 = 0x1D;
+/// Number of dots in a line of the CSN-A2
+pub const DOTS_PER_LINE: u16 = 384;
+
+/// Alignment of the text and bitmaps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left = 0,
+    Center = 1,
+    Right = 2,
+}
+
+/// Serial thermal printer
+pub struct Printer<TX> {
+    tx: TX,
+}
+
+impl<TX, E> Printer<TX>
+where
+    TX: serial::Write<u8, Error = E>,
+{
+    pub fn new(tx: TX) -> Self {
+        Printer { tx }
+    }
+
+    /// Destroy the printer and return the serial transmitter.
+    pub fn destroy(self) -> TX {
+        self.tx
+    }
+
+    /// Reset the formatting to the defaults.
+    pub fn init(&mut self) -> Result<(), E> {
+        self.write(&[ESC, b'@'])
+    }
+
+    /// Set the heating parameters. More heating dots print faster but need
+    /// more current. A longer heating time prints darker but slower. See the
+    /// printer manual for the values.
+    pub fn heating(&mut self, max_dots: u8, time: u8, interval: u8) -> Result<(), E> {
+        self.write(&[ESC, b'7', max_dots, time, interval])
+    }
+
+    pub fn bold(&mut self, enable: bool) -> Result<(), E> {
+        self.write(&[ESC, b'E', u8::from(enable)])
+    }
+
+    pub fn underline(&mut self, enable: bool) -> Result<(), E> {
+        self.write(&[ESC, b'-', u8::from(enable)])
+    }
+
+    /// Print white on black.
+    pub fn inverse(&mut self, enable: bool) -> Result<(), E> {
+        self.write(&[GS, b'B', u8::from(enable)])
+    }
+
+    pub fn align(&mut self, align: Align) -> Result<(), E> {
+        self.write(&[ESC, b'a', align as u8])
+    }
+
+    /// Character size as multiples of the normal width and height: 1-8
+    pub fn size(&mut self, width: u8, height: u8) -> Result<(), E> {
+        let width = width.clamp(1, 8) - 1;
+        let height = height.clamp(1, 8) - 1;
+        self.write(&[GS, b'!', (width << 4) | height])
+    }
+
+    /// Print the buffer and feed the paper by `lines` lines.
+    pub fn feed(&mut self, lines: u8) -> Result<(), E> {
+        self.write(&[ESC, b'd', lines])
+    }
+
+    /// Print a bitmap. Each row is `width_bytes` bytes long, the most
+    /// significant bit of each byte is the leftmost dot and a set bit is
+    /// printed black.
+    ///
+    /// Panics if `data` is shorter than `width_bytes * height`.
+    pub fn bitmap(&mut self, width_bytes: u16, height: u16, data: &[u8]) -> Result<(), E> {
+        let len = usize::from(width_bytes) * usize::from(height);
+        assert!(data.len() >= len);
+        let [width_low, width_high] = width_bytes.to_le_bytes();
+        let [height_low, height_high] = height.to_le_bytes();
+        self.write(&[
+            GS,
+            b'v',
+            b'0',
+            0,
+            width_low,
+            width_high,
+            height_low,
+            height_high,
+        ])?;
+        self.write(&data[..len])
+    }
+
+    /// Send raw bytes, for commands not covered here.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), E> {
+        for byte in data {
+            block!(self.tx.write(*byte))?;
+        }
+        Ok(())
+    }
+}
+
+impl<TX, E> core::fmt::Write for Printer<TX>
+where
+    TX: serial::Write<u8, Error = E>,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
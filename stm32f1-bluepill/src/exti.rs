@@ -0,0 +1,68 @@
+//! Pins as external interrupt (EXTI) inputs, e.g. for the data-ready or
+//! alert output of a sensor.
+//!
+//! `setup_exti_pin()` routes the pin to its EXTI line and enables the
+//! interrupt on the given edge. The interrupt handler, an RTIC task bound to
+//! `EXTI0`-`EXTI4`, `EXTI9_5` or `EXTI15_10` depending on the pin number,
+//! clears the pending bit and handles the event or sets an `ExtiFlag`, which
+//! the program polls without locking a resource:
+//!
+//! ```ignore
+//! static DATA_READY: ExtiFlag = ExtiFlag::new();
+//!
+//! // In init
+//! let mut ready = gpiob.pb0.into_pull_up_input(&mut gpiob.crl);
+//! setup_exti_pin(&mut ready, &mut afio, &device.EXTI, Edge::FALLING);
+//!
+//! // In the EXTI0 task, which owns the pin
+//! ready.clear_interrupt_pending_bit();
+//! DATA_READY.set();
+//!
+//! // In the program
+//! if DATA_READY.take() {
+//!     let value = sensor.read().unwrap();
+//! }
+//! ```
+//!
+//! Pins with the same number on different ports share one EXTI line, so
+//! only one of them can be an interrupt input.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use stm32f1xx_hal::{
+    afio,
+    gpio::{Edge, ExtiPin},
+    pac::EXTI,
+};
+
+/// Enable the interrupt of a pin on the given edge.
+pub fn setup_exti_pin<P: ExtiPin>(pin: &mut P, afio: &mut afio::Parts, exti: &EXTI, edge: Edge) {
+    pin.make_interrupt_source(afio);
+    pin.trigger_on_edge(exti, edge);
+    pin.enable_interrupt(exti);
+}
+
+/// Event flag set by an interrupt handler
+#[derive(Debug, Default)]
+pub struct ExtiFlag(AtomicBool);
+
+impl ExtiFlag {
+    /// Create a flag which is not set.
+    pub const fn new() -> Self {
+        ExtiFlag(AtomicBool::new(false))
+    }
+
+    /// Set the flag. Call this from the interrupt handler.
+    pub fn set(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Whether the flag is set, without clearing it
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Clear the flag and return whether it was set.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+}
@@ -0,0 +1,136 @@
+//! Fixed point FFT for spectrum analysis.
+//!
+//! A radix-2 FFT of `N` points (a power of 2) on `i16` samples in Q15
+//! format, so it runs on the Cortex-M3 without an FPU: a 256-point FFT
+//! takes a few milliseconds at 72MHz. Every stage halves the values so that
+//! they cannot overflow, so the result is scaled by `1 / N`: a full scale
+//! sine gives a magnitude of about `16384` in its bin, times the gain of
+//! the window, 0.5 for the Hann window.
+//!
+//! ```ignore
+//! let fft: Fft<256> = Fft::new();
+//! let mut re = samples; // without DC offset, shifted up to use the range
+//! let mut im = [0; 256];
+//! fft.window(&mut re);
+//! fft.transform(&mut re, &mut im);
+//! for bin in 1..128 {
+//!     let hz = bin as f32 * sample_rate_hz / 256.0;
+//!     let level = magnitude(re[bin], im[bin]);
+//! }
+//! ```
+//!
+//! For real samples only the first `N / 2` bins are useful, the others are
+//! their mirror image.
+
+use core::f32::consts::PI;
+
+/// FFT of `N` points
+#[derive(Debug, Clone)]
+pub struct Fft<const N: usize> {
+    /// One period of a sine in Q15
+    sine: [i16; N],
+    /// Hann window in Q15
+    hann: [i16; N],
+}
+
+impl<const N: usize> Default for Fft<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Fft<N> {
+    /// Calculate the tables. `N` must be a power of 2.
+    pub fn new() -> Self {
+        assert!(N.is_power_of_two() && N >= 4);
+        let mut sine = [0; N];
+        let mut hann = [0; N];
+        for (i, (sine, hann)) in sine.iter_mut().zip(hann.iter_mut()).enumerate() {
+            let angle = 2.0 * PI * i as f32 / N as f32;
+            *sine = q15(libm::sinf(angle));
+            *hann = q15(0.5 - 0.5 * libm::cosf(angle));
+        }
+        Fft { sine, hann }
+    }
+
+    /// Multiply the samples with a Hann window, so that a frequency between
+    /// two bins does not leak into all the others.
+    pub fn window(&self, samples: &mut [i16; N]) {
+        for (sample, factor) in samples.iter_mut().zip(self.hann.iter()) {
+            *sample = mul(*sample, *factor);
+        }
+    }
+
+    /// Transform in place: the real and imaginary parts of the samples in,
+    /// those of the bins out.
+    pub fn transform(&self, re: &mut [i16; N], im: &mut [i16; N]) {
+        // Reorder the samples by bit-reversed index.
+        let bits = N.trailing_zeros();
+        for i in 0..N {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if j > i {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= N {
+            let half = len / 2;
+            let step = N / len;
+            for start in (0..N).step_by(len) {
+                for k in 0..half {
+                    // e^(-2πik/len) = cos - i sin
+                    let index = k * step;
+                    let cos = self.sine[(index + N / 4) % N];
+                    let sin = self.sine[index];
+                    let a = start + k;
+                    let b = a + half;
+                    let (re_b, im_b) = (i32::from(re[b]), i32::from(im[b]));
+                    let t_re = (re_b * i32::from(cos) + im_b * i32::from(sin)) >> 15;
+                    let t_im = (im_b * i32::from(cos) - re_b * i32::from(sin)) >> 15;
+                    let (re_a, im_a) = (i32::from(re[a]), i32::from(im[a]));
+                    re[a] = ((re_a + t_re) >> 1) as i16;
+                    im[a] = ((im_a + t_im) >> 1) as i16;
+                    re[b] = ((re_a - t_re) >> 1) as i16;
+                    im[b] = ((im_a - t_im) >> 1) as i16;
+                }
+            }
+            len *= 2;
+        }
+    }
+}
+
+/// Magnitude of a bin
+pub fn magnitude(re: i16, im: i16) -> u16 {
+    let square = |x: i16| (i32::from(x) * i32::from(x)) as u32;
+    isqrt(square(re) + square(im)) as u16
+}
+
+/// Integer square root, rounded down
+fn isqrt(value: u32) -> u32 {
+    let mut root = 0;
+    let mut bit = 1 << 30;
+    let mut rest = value;
+    while bit > value {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rest >= root + bit {
+            rest -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+fn q15(value: f32) -> i16 {
+    (value * 32767.0) as i16
+}
+
+fn mul(a: i16, b: i16) -> i16 {
+    ((i32::from(a) * i32::from(b)) >> 15) as i16
+}
@@ -0,0 +1,240 @@
+//! Frequency shift keying of text as RTTY or Bell 202 AFSK.
+//!
+//! The text is sent like with a UART: every character is a frame of a start
+//! bit (space), the data bits (least significant first) and the stop bits
+//! (mark). Mark and space are sent as two audio tones, e.g. by switching
+//! between the two frequency registers of an AD9833.
+//! - `RTTY`: amateur radio teleprinter, 45.45 baud, 170Hz shift, 5-bit
+//!   Baudot (ITA2) code with 1.5 stop bits. There are only upper case
+//!   letters, the digits and a few signs, so the text is converted to upper
+//!   case and `LTRS`/`FIGS` shift codes are added when needed. Decode it
+//!   with fldigi in RTTY mode with its default settings.
+//! - `BELL_202`: 1200 baud, 1200Hz mark and 2200Hz space, 8-bit ASCII with
+//!   one stop bit. Decode it with `minimodem --rx 1200`.
+//!
+//! The `FskEncoder` returns the level of every half bit, so that the 1.5
+//! stop bits can be sent too. Call `half_bit()` from a timer interrupt
+//! running at `Mode::half_bit_hz()`:
+//!
+//! ```ignore
+//! encoder.load("CQ CQ DE N0CALL\r\n").unwrap();
+//! // in the timer interrupt:
+//! match encoder.half_bit() {
+//!     Some(true) => synth.select_frequency(FrequencyRegister::F0).unwrap(),
+//!     Some(false) => synth.select_frequency(FrequencyRegister::F1).unwrap(),
+//!     None => timer.unlisten(Event::Update),
+//! }
+//! ```
+//!
+//! Every transmission starts with half a second of mark, so that the
+//! receiver can find the tones.
+
+use heapless::Vec;
+
+/// Baudot code to shift to letters
+const LTRS: u8 = 0x1F;
+/// Baudot code to shift to figures
+const FIGS: u8 = 0x1B;
+
+/// Baudot (ITA2) letters, 0 for the codes which are not characters
+const LETTERS: [u8; 32] = [
+    0, b'E', b'\n', b'A', b' ', b'S', b'I', b'U', b'\r', b'D', b'R', b'J', b'N', b'F', b'C', b'K',
+    b'T', b'Z', b'L', b'W', b'H', b'Y', b'P', b'Q', b'O', b'B', b'G', 0, b'M', b'X', b'V', 0,
+];
+
+/// Baudot (ITA2) figures, 0 for the codes which differ between the variants
+const FIGURES: [u8; 32] = [
+    0, b'3', b'\n', b'-', b' ', 0, b'8', b'7', b'\r', 0, b'4', 0, b',', 0, b':', b'(', b'5', 0,
+    b')', b'2', 0, b'6', b'0', b'1', b'9', b'?', 0, 0, b'.', b'/', 0, 0,
+];
+
+/// Modulation and character format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mode {
+    pub mark_hz: f32,
+    pub space_hz: f32,
+    pub baud: f32,
+    /// 5-bit Baudot code with 1.5 stop bits instead of 8-bit ASCII with 1
+    pub baudot: bool,
+}
+
+/// Amateur radio RTTY
+pub const RTTY: Mode = Mode {
+    mark_hz: 2125.0,
+    space_hz: 2295.0,
+    baud: 45.45,
+    baudot: true,
+};
+
+/// Bell 202 AFSK
+pub const BELL_202: Mode = Mode {
+    mark_hz: 1200.0,
+    space_hz: 2200.0,
+    baud: 1200.0,
+    baudot: false,
+};
+
+impl Mode {
+    /// Rate at which `FskEncoder::half_bit()` has to be called
+    pub fn half_bit_hz(&self) -> u32 {
+        (self.baud * 2.0 + 0.5) as u32
+    }
+
+    fn data_bits(&self) -> u8 {
+        if self.baudot {
+            5
+        } else {
+            8
+        }
+    }
+
+    fn stop_half_bits(&self) -> u8 {
+        if self.baudot {
+            3
+        } else {
+            2
+        }
+    }
+}
+
+/// FSK error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The character cannot be sent in this mode
+    Character(char),
+    /// The text does not fit into the encoder
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Shift {
+    Letters,
+    Figures,
+}
+
+/// Encoder of up to `N` characters, including Baudot shift codes
+#[derive(Debug, Clone)]
+pub struct FskEncoder<const N: usize> {
+    mode: Mode,
+    codes: Vec<u8, N>,
+    index: usize,
+    half_bit: u8,
+    preamble: u32,
+}
+
+impl<const N: usize> FskEncoder<N> {
+    /// Create a new encoder with nothing to send
+    pub fn new(mode: Mode) -> Self {
+        FskEncoder {
+            mode,
+            codes: Vec::new(),
+            index: 0,
+            half_bit: 0,
+            preamble: 0,
+        }
+    }
+
+    /// Modulation and character format
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Start sending a text, replacing what was not sent yet. Nothing is
+    /// sent if it returns an error.
+    pub fn load(&mut self, text: &str) -> Result<(), Error> {
+        self.codes.clear();
+        self.index = 0;
+        self.half_bit = 0;
+        self.preamble = 0;
+        let result = if self.mode.baudot {
+            self.load_baudot(text)
+        } else {
+            self.load_ascii(text)
+        };
+        match result {
+            Ok(()) => self.preamble = self.mode.half_bit_hz() / 2,
+            Err(_) => self.codes.clear(),
+        }
+        result
+    }
+
+    fn load_ascii(&mut self, text: &str) -> Result<(), Error> {
+        for c in text.chars() {
+            if !c.is_ascii() {
+                return Err(Error::Character(c));
+            }
+            self.codes.push(c as u8).map_err(|_| Error::Full)?;
+        }
+        Ok(())
+    }
+
+    fn load_baudot(&mut self, text: &str) -> Result<(), Error> {
+        // Receivers usually shift back to letters after a space, so the
+        // shift is unknown then.
+        let mut shift = None;
+        for c in text.chars() {
+            if !c.is_ascii() {
+                return Err(Error::Character(c));
+            }
+            let ascii = c.to_ascii_uppercase() as u8;
+            let find = |table: &[u8; 32]| {
+                table
+                    .iter()
+                    .position(|b| *b != 0 && *b == ascii)
+                    .map(|code| code as u8)
+            };
+            let (needed, code) = match (find(&LETTERS), find(&FIGURES)) {
+                // Space, CR and LF are the same in both
+                (Some(code), Some(_)) => (None, code),
+                (Some(code), None) => (Some(Shift::Letters), code),
+                (None, Some(code)) => (Some(Shift::Figures), code),
+                (None, None) => return Err(Error::Character(c)),
+            };
+            if needed.is_some() && needed != shift {
+                let shift_code = match needed {
+                    Some(Shift::Figures) => FIGS,
+                    _ => LTRS,
+                };
+                self.codes.push(shift_code).map_err(|_| Error::Full)?;
+                shift = needed;
+            }
+            self.codes.push(code).map_err(|_| Error::Full)?;
+            if ascii == b' ' {
+                shift = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether everything has been sent
+    pub fn is_done(&self) -> bool {
+        self.preamble == 0 && self.index >= self.codes.len()
+    }
+
+    /// The level of the next half bit: `true` for mark and `false` for
+    /// space. Returns `None` once everything has been sent.
+    pub fn half_bit(&mut self) -> Option<bool> {
+        if self.preamble > 0 {
+            self.preamble -= 1;
+            return Some(true);
+        }
+        let code = *self.codes.get(self.index)?;
+        let data_bits = self.mode.data_bits();
+        let bit = self.half_bit / 2;
+        let mark = if bit == 0 {
+            // Start bit
+            false
+        } else if bit <= data_bits {
+            code >> (bit - 1) & 1 != 0
+        } else {
+            // Stop bits
+            true
+        };
+        self.half_bit += 1;
+        if self.half_bit == 2 + 2 * data_bits + self.mode.stop_half_bits() {
+            self.half_bit = 0;
+            self.index += 1;
+        }
+        Some(mark)
+    }
+}
@@ -0,0 +1,171 @@
+//! Analog-style gauge widget for embedded-graphics displays.
+//!
+//! The gauge is a 270° dial with tick marks, a needle and a numeric readout
+//! below its center. It works with any display implementing `DrawTarget`.
+//!
+//! Drawing the whole gauge is slow on displays without frame buffer (like
+//! most SPI color LCDs) so after drawing it once, use `update()` which only
+//! redraws the needle and the readout.
+//!
+//! ```ignore
+//! let mut gauge = Gauge::new(Point::new(120, 120), 110, -10.0, 40.0)
+//!     .colors(Rgb565::WHITE, Rgb565::RED, Rgb565::BLACK);
+//! gauge.draw(&mut display)?;
+//! loop {
+//!     gauge.update(&mut display, read_temperature())?;
+//! }
+//! ```
+
+use core::fmt::Write;
+use embedded_graphics::{
+    fonts::{Font12x16, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Circle, Line},
+    style::{PrimitiveStyle, TextStyleBuilder},
+};
+use libm::{cosf, sinf};
+
+const START_ANGLE: f32 = 135.0;
+const SWEEP_ANGLE: f32 = 270.0;
+
+/// Gauge widget
+#[derive(Debug, Clone)]
+pub struct Gauge<C> {
+    center: Point,
+    radius: u32,
+    min: f32,
+    max: f32,
+    ticks: u32,
+    value: f32,
+    dial_color: C,
+    needle_color: C,
+    background: C,
+}
+
+impl<C> Gauge<C>
+where
+    C: PixelColor + From<BinaryColor>,
+{
+    /// Create a gauge showing values in the range `[min, max]`.
+    pub fn new(center: Point, radius: u32, min: f32, max: f32) -> Self {
+        Gauge {
+            center,
+            radius,
+            min,
+            max,
+            ticks: 10,
+            value: min,
+            dial_color: BinaryColor::On.into(),
+            needle_color: BinaryColor::On.into(),
+            background: BinaryColor::Off.into(),
+        }
+    }
+
+    /// Set the number of intervals between tick marks.
+    pub fn ticks(mut self, ticks: u32) -> Self {
+        self.ticks = ticks.max(1);
+        self
+    }
+
+    /// Set the colors used to draw the gauge.
+    pub fn colors(mut self, dial: C, needle: C, background: C) -> Self {
+        self.dial_color = dial;
+        self.needle_color = needle;
+        self.background = background;
+        self
+    }
+
+    /// Value currently shown
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Draw the whole gauge.
+    pub fn draw<D: DrawTarget<C>>(&self, display: &mut D) -> Result<(), D::Error> {
+        let outline = PrimitiveStyle::with_stroke(self.dial_color, 2);
+        Circle::new(self.center, self.radius)
+            .into_styled(outline)
+            .draw(display)?;
+
+        let tick_style = PrimitiveStyle::with_stroke(self.dial_color, 1);
+        let radius = self.radius as f32;
+        for i in 0..=self.ticks {
+            let fraction = i as f32 / self.ticks as f32;
+            // Every other tick mark is longer.
+            let inner = if i % 2 == 0 { 0.8 } else { 0.88 };
+            Line::new(
+                self.point_at(fraction, radius * inner),
+                self.point_at(fraction, radius * 0.95),
+            )
+            .into_styled(tick_style)
+            .draw(display)?;
+        }
+        self.draw_readout(display)?;
+        self.draw_needle(display, self.value, self.needle_color)
+    }
+
+    /// Show a new value redrawing only the needle and the readout.
+    pub fn update<D: DrawTarget<C>>(
+        &mut self,
+        display: &mut D,
+        value: f32,
+    ) -> Result<(), D::Error> {
+        self.draw_needle(display, self.value, self.background)?;
+        self.value = value;
+        // The needle can cross the readout so draw the readout first.
+        self.draw_readout(display)?;
+        self.draw_needle(display, self.value, self.needle_color)
+    }
+
+    fn draw_needle<D: DrawTarget<C>>(
+        &self,
+        display: &mut D,
+        value: f32,
+        color: C,
+    ) -> Result<(), D::Error> {
+        Line::new(
+            self.center,
+            self.point_at(self.fraction(value), self.radius as f32 * 0.75),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(color, 3))
+        .draw(display)?;
+        Circle::new(self.center, 4)
+            .into_styled(PrimitiveStyle::with_fill(self.needle_color))
+            .draw(display)
+    }
+
+    fn draw_readout<D: DrawTarget<C>>(&self, display: &mut D) -> Result<(), D::Error> {
+        let mut text: heapless::String<16> = heapless::String::new();
+        // A too long value is truncated, which is fine for a readout.
+        write!(text, "{:7.2}", self.value).ok();
+        let text_style = TextStyleBuilder::new(Font12x16)
+            .text_color(self.dial_color)
+            .background_color(self.background)
+            .build();
+        let width = 12 * text.len() as i32;
+        Text::new(
+            &text,
+            self.center + Point::new(-width / 2, self.radius as i32 / 2),
+        )
+        .into_styled(text_style)
+        .draw(display)
+    }
+
+    /// Position of the value in the range `[0, 1]`
+    fn fraction(&self, value: f32) -> f32 {
+        let fraction = (value - self.min) / (self.max - self.min);
+        fraction.max(0.0).min(1.0)
+    }
+
+    /// Point at `distance` from the center in the direction of `fraction`
+    fn point_at(&self, fraction: f32, distance: f32) -> Point {
+        // Screen coordinates: angles grow clockwise starting from the right.
+        let angle = (START_ANGLE + SWEEP_ANGLE * fraction).to_radians();
+        self.center
+            + Point::new(
+                (distance * cosf(angle)) as i32,
+                (distance * sinf(angle)) as i32,
+            )
+    }
+}
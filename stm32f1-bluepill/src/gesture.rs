@@ -0,0 +1,117 @@
+//! Swipe detection from the photodiode data of a gesture sensor like the
+//! APDS-9960.
+//!
+//! The APDS-9960 measures the light reflected by a hand with four
+//! directional photodiodes: up, down, left and right. While the hand is
+//! over the sensor, it adds a dataset of the four values to its FIFO every
+//! few milliseconds. The driver only reads the data, so this module turns
+//! it into a direction: the balance between opposite photodiodes changes
+//! while the hand passes, and the direction with the largest change wins.
+//!
+//! ```ignore
+//! let mut detector = GestureDetector::new();
+//! loop {
+//!     match sensor.read_gesture_data(&mut dataset) {
+//!         Ok(()) => detector.update(dataset),
+//!         // No new data for a while: the hand is gone.
+//!         Err(nb::Error::WouldBlock) if idle_ms > 50 => {
+//!             if let Some(gesture) = detector.finish() {
+//!                 // ...
+//!             }
+//!         }
+//!         _ => (),
+//!     }
+//! }
+//! ```
+//!
+//! Which direction is up depends on how the sensor is mounted. The default
+//! thresholds suit a hand some 5 to 10cm above the sensor.
+
+/// Direction of a swipe
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Balance of the up/down and left/right photodiodes in %
+type Ratios = (i32, i32);
+
+/// Swipe detector
+#[derive(Debug, Clone)]
+pub struct GestureDetector {
+    threshold: u8,
+    sensitivity: i32,
+    first: Option<Ratios>,
+    last: Ratios,
+}
+
+impl Default for GestureDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GestureDetector {
+    pub fn new() -> Self {
+        GestureDetector {
+            threshold: 10,
+            sensitivity: 50,
+            first: None,
+            last: (0, 0),
+        }
+    }
+
+    /// Set the value all photodiodes have to exceed for a dataset to count.
+    /// The default is 10.
+    pub fn threshold(mut self, threshold: u8) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the change of the balance in % needed for a swipe. The default
+    /// is 50.
+    pub fn sensitivity(mut self, percent: i32) -> Self {
+        self.sensitivity = percent;
+        self
+    }
+
+    /// Add a dataset of the up, down, left and right photodiodes.
+    pub fn update(&mut self, dataset: [u8; 4]) {
+        if dataset.iter().any(|value| *value <= self.threshold) {
+            return;
+        }
+        let [up, down, left, right] = dataset;
+        let balance =
+            |a: u8, b: u8| (i32::from(a) - i32::from(b)) * 100 / (i32::from(a) + i32::from(b));
+        let ratios = (balance(up, down), balance(left, right));
+        if self.first.is_none() {
+            self.first = Some(ratios);
+        }
+        self.last = ratios;
+    }
+
+    /// Finish the gesture once the hand is gone and return the direction of
+    /// the swipe, if there was one.
+    pub fn finish(&mut self) -> Option<Gesture> {
+        let (first_ud, first_lr) = self.first.take()?;
+        let delta_ud = self.last.0 - first_ud;
+        let delta_lr = self.last.1 - first_lr;
+        if delta_ud.abs().max(delta_lr.abs()) < self.sensitivity {
+            return None;
+        }
+        Some(if delta_ud.abs() > delta_lr.abs() {
+            if delta_ud < 0 {
+                Gesture::Up
+            } else {
+                Gesture::Down
+            }
+        } else if delta_lr < 0 {
+            Gesture::Left
+        } else {
+            Gesture::Right
+        })
+    }
+}
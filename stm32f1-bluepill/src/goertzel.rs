@@ -0,0 +1,71 @@
+//! Goertzel algorithm: the power of a single frequency in a block of samples.
+//!
+//! It is a single bin of a DFT, calculated sample by sample with one
+//! multiplication, so it is much cheaper than an FFT when only a few
+//! frequencies matter, like the tones of DTMF or CTCSS. The filter runs in
+//! fixed point, so it is fast on the Cortex-M3 without an FPU.
+//!
+//! Feed it `n` samples, read the `power()` and `reset()` it for the next
+//! block:
+//!
+//! ```ignore
+//! let mut filter = Goertzel::new(1000.0, 8000.0, 200);
+//! for sample in block.iter() {
+//!     filter.push(*sample);
+//! }
+//! let power = filter.power();
+//! filter.reset();
+//! ```
+//!
+//! The frequency is rounded to the nearest bin, a multiple of
+//! `sample_rate_hz / n`, so a longer block separates closer frequencies but
+//! takes longer. For a sine of amplitude `A` at the frequency of the bin the
+//! power is about `(A * n / 2)²`. Remove the DC offset of the samples first,
+//! e.g. the middle of the ADC range.
+
+use core::f32::consts::PI;
+
+/// Fraction bits of the coefficient
+const FRACTION_BITS: u32 = 14;
+
+/// Goertzel filter of one frequency
+#[derive(Debug, Clone)]
+pub struct Goertzel {
+    coeff: i64,
+    s1: i64,
+    s2: i64,
+}
+
+impl Goertzel {
+    /// Create a filter for `target_hz` for blocks of `n` samples.
+    pub fn new(target_hz: f32, sample_rate_hz: f32, n: u32) -> Self {
+        let k = libm::roundf(n as f32 * target_hz / sample_rate_hz);
+        let w = 2.0 * PI * k / n as f32;
+        let coeff = libm::roundf(2.0 * libm::cosf(w) * (1 << FRACTION_BITS) as f32);
+        Goertzel {
+            coeff: coeff as i64,
+            s1: 0,
+            s2: 0,
+        }
+    }
+
+    /// Add the next sample.
+    pub fn push(&mut self, sample: i16) {
+        let s = i64::from(sample) + ((self.coeff * self.s1) >> FRACTION_BITS) - self.s2;
+        self.s2 = self.s1;
+        self.s1 = s;
+    }
+
+    /// Power of the frequency in the samples since the last reset
+    pub fn power(&self) -> u64 {
+        let power = self.s1 * self.s1 + self.s2 * self.s2
+            - ((self.coeff * self.s1) >> FRACTION_BITS) * self.s2;
+        power.max(0) as u64
+    }
+
+    /// Start a new block.
+    pub fn reset(&mut self) {
+        self.s1 = 0;
+        self.s2 = 0;
+    }
+}
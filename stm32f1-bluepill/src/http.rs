@@ -0,0 +1,68 @@
+//! Minimal HTTP/1.1 client requests and answers.
+//!
+//! `write_post()` writes a POST request with a body into any
+//! `core::fmt::Write`, e.g. a `heapless::String`, and `status()` reads the
+//! status code from the start of the answer. Sending them is left to the
+//! network stack, e.g. a TCP connection of the `w5500` module:
+//!
+//! ```ignore
+//! let mut request: String<512> = String::new();
+//! http::write_post(&mut request, "192.168.1.10", "/samples", JSON, &body)?;
+//! w5500.connect_tcp(40000, [192, 168, 1, 10], 80)?;
+//! w5500.send(request.as_bytes())?;
+//! // Receive the beginning of the answer...
+//! let ok = http::status(&answer[..len]).map_or(false, http::is_success);
+//! ```
+//!
+//! The requests ask the server to close the connection after answering,
+//! so there is no need to read the rest of the answer.
+
+use core::fmt::{self, Write};
+
+pub const PORT: u16 = 80;
+/// Content type of a JSON body
+pub const JSON: &str = "application/json";
+
+/// Write a POST request of `body` to `path` on `host`.
+pub fn write_post<W: Write>(
+    writer: &mut W,
+    host: &str,
+    path: &str,
+    content_type: &str,
+    body: &str,
+) -> fmt::Result {
+    write!(
+        writer,
+        "POST {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        path,
+        host,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Status code of an answer, once its status line arrived, e.g. 200 for
+/// "HTTP/1.1 200 OK".
+pub fn status(answer: &[u8]) -> Option<u16> {
+    let line = answer.split(|b| *b == b'\r').next()?;
+    if line.len() == answer.len() || !line.starts_with(b"HTTP/1.") {
+        return None;
+    }
+    let code = line.split(|b| *b == b' ').nth(1)?;
+    if code.len() != 3 || !code.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(code.iter().fold(0, |n, d| n * 10 + u16::from(d - b'0')))
+}
+
+/// Whether the status code means that the request succeeded.
+pub fn is_success(code: u16) -> bool {
+    (200..300).contains(&code)
+}
@@ -0,0 +1,193 @@
+//! Writes on I2C1 with DMA, so the CPU is free during the transfer.
+//!
+//! The HAL only has blocking I2C, which keeps the CPU busy until the last
+//! byte is on the bus. `I2c1Dma` wraps a `BlockingI2c` set up as usual and
+//! adds writes through DMA1 channel 6: `start_write()` sends the start
+//! condition and the address and hands the bytes to the DMA, `wait()` waits
+//! for the end of the transfer, sends the stop condition and returns the
+//! buffer. In between, the program can do something else, e.g. render the
+//! next frame for a display:
+//!
+//! ```ignore
+//! let dma_ch6 = dp.DMA1.split(&mut rcc.ahb).6;
+//! let mut i2c = I2c1Dma::new(i2c, dma_ch6);
+//! i2c.blocking().write(ADDRESS, &[0x00, 0xAF]).unwrap();
+//! i2c.start_write(ADDRESS, frame);
+//! // ...
+//! let (frame, result) = i2c.wait();
+//! result.unwrap();
+//! ```
+//!
+//! The buffer is handed over for the transfer, so it can't be changed while
+//! the DMA reads it.
+
+use core::sync::atomic::{compiler_fence, Ordering};
+use stm32f1xx_hal::{
+    dma::dma1,
+    i2c::BlockingI2c,
+    pac::{self, i2c1},
+};
+
+/// Polls of a status flag before giving up on a step of the transfer
+const TIMEOUT: u32 = 100_000;
+
+/// DMA I2C errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// A device did not acknowledge the address or a byte.
+    Nack,
+    /// Misplaced start or stop condition
+    Bus,
+    /// Another master took the bus.
+    ArbitrationLost,
+    /// The start condition or the address took too long.
+    Timeout,
+}
+
+/// I2C1 with blocking and DMA writes
+pub struct I2c1Dma<PINS> {
+    i2c: BlockingI2c<pac::I2C1, PINS>,
+    dma: dma1::C6,
+    buffer: Option<&'static mut [u8]>,
+    error: Option<Error>,
+}
+
+impl<PINS> I2c1Dma<PINS> {
+    /// Add DMA writes to a bus set up with the HAL.
+    pub fn new(i2c: BlockingI2c<pac::I2C1, PINS>, dma: dma1::C6) -> Self {
+        I2c1Dma {
+            i2c,
+            dma,
+            buffer: None,
+            error: None,
+        }
+    }
+
+    /// The bus for blocking transfers. Wait for a DMA transfer to end first.
+    pub fn blocking(&mut self) -> &mut BlockingI2c<pac::I2C1, PINS> {
+        &mut self.i2c
+    }
+
+    fn regs(&self) -> &'static i2c1::RegisterBlock {
+        // The peripheral belongs to `self.i2c`, which is not used until the
+        // DMA transfer has ended.
+        unsafe { &*pac::I2C1::ptr() }
+    }
+
+    /// Check the error flags and wait until `done`.
+    fn wait_for(&self, done: impl Fn(&i2c1::sr1::R) -> bool) -> Result<(), Error> {
+        for _ in 0..TIMEOUT {
+            let status = self.regs().sr1.read();
+            check(&status)?;
+            if done(&status) {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Send the start condition and the address, then let the DMA write
+    /// `bytes`. Errors are returned by `wait()`, together with the buffer.
+    pub fn start_write(&mut self, address: u8, bytes: &'static mut [u8]) {
+        let regs = self.regs();
+        self.dma
+            .set_peripheral_address(&regs.dr as *const _ as u32, false);
+        self.dma.set_memory_address(bytes.as_ptr() as u32, true);
+        self.dma.set_transfer_length(bytes.len());
+        self.dma.ifcr().write(|w| w.cgif6().set_bit());
+        self.dma.ch().cr.modify(|_, w| {
+            w.dir()
+                .set_bit()
+                .circ()
+                .clear_bit()
+                .psize()
+                .bits8()
+                .msize()
+                .bits8()
+                .pl()
+                .high()
+        });
+        self.buffer = Some(bytes);
+        // The buffer is read by the DMA from now on.
+        compiler_fence(Ordering::SeqCst);
+        self.dma.start();
+        regs.cr2.modify(|_, w| w.dmaen().set_bit());
+
+        regs.cr1.modify(|_, w| w.start().set_bit());
+        let result = self.wait_for(|s| s.sb().bit_is_set()).and_then(|_| {
+            regs.dr.write(|w| w.dr().bits(address << 1));
+            self.wait_for(|s| s.addr().bit_is_set())
+        });
+        match result {
+            // Reading SR2 after SR1 clears ADDR and the DMA starts.
+            Ok(()) => {
+                regs.sr2.read();
+            }
+            Err(e) => {
+                self.error = Some(e);
+                self.finish();
+            }
+        }
+    }
+
+    /// Whether the DMA has handed all bytes to the peripheral or the
+    /// transfer failed. `wait()` does not block then.
+    pub fn is_done(&self) -> bool {
+        self.buffer.is_none()
+            || self.error.is_some()
+            || self.dma.isr().tcif6().bit_is_set()
+            || check(&self.regs().sr1.read()).is_err()
+    }
+
+    /// Wait for the end of the transfer started with `start_write()`, send
+    /// the stop condition and return the buffer.
+    ///
+    /// Panics if no transfer was started.
+    pub fn wait(&mut self) -> (&'static mut [u8], Result<(), Error>) {
+        let buffer = self.buffer.take().unwrap();
+        if let Some(e) = self.error.take() {
+            return (buffer, Err(e));
+        }
+        let regs = self.regs();
+        let result = loop {
+            if let Err(e) = check(&regs.sr1.read()) {
+                break Err(e);
+            }
+            if self.dma.isr().tcif6().bit_is_set() {
+                // The last byte is still being sent.
+                break self.wait_for(|s| s.btf().bit_is_set());
+            }
+        };
+        self.finish();
+        (buffer, result)
+    }
+
+    /// Stop the DMA and release the bus.
+    fn finish(&mut self) {
+        let regs = self.regs();
+        self.dma.stop();
+        self.dma.ifcr().write(|w| w.cgif6().set_bit());
+        regs.cr2.modify(|_, w| w.dmaen().clear_bit());
+        regs.cr1.modify(|_, w| w.stop().set_bit());
+        regs.sr1
+            .modify(|_, w| w.af().clear_bit().berr().clear_bit().arlo().clear_bit());
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// Return the bus and the DMA channel.
+    pub fn free(self) -> (BlockingI2c<pac::I2C1, PINS>, dma1::C6) {
+        (self.i2c, self.dma)
+    }
+}
+
+fn check(status: &i2c1::sr1::R) -> Result<(), Error> {
+    if status.af().bit_is_set() {
+        Err(Error::Nack)
+    } else if status.berr().bit_is_set() {
+        Err(Error::Bus)
+    } else if status.arlo().bit_is_set() {
+        Err(Error::ArbitrationLost)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Register map protocol for a board-to-board I2C link.
+//!
+//! One board is an I2C slave which exposes its measurements like a sensor
+//! does: the master writes a register address (the pointer) and then reads
+//! or writes bytes from there on. The pointer moves on by itself after each
+//! byte, so several registers can be read in one transaction. Multi-byte
+//! values are big-endian.
+//!
+//! | Address   | Register     | Access | Contents                               |
+//! |-----------|--------------|--------|----------------------------------------|
+//! | 0x00      | WHO_AM_I     | R      | Always `WHO_AM_I_VALUE`                |
+//! | 0x01      | SAMPLE       | R      | Counts the measurements, wraps around  |
+//! | 0x02-0x03 | TEMPERATURE  | R      | i16, 1/10 degrees Celsius              |
+//! | 0x04-0x05 | ANALOG       | R      | u16, millivolts                        |
+//! | 0x06-0x09 | UPTIME       | R      | u32, seconds                           |
+//! | 0x0A      | CONTROL      | R/W    | Bit 0: LED on                          |
+//! | 0x0B      | INTERVAL     | R/W    | Time between measurements in 10ms, >0  |
+//!
+//! Reading beyond the last register gives 0xFF. Writes to read-only
+//! registers are ignored.
+//!
+//! The slave side is `RegisterMap`, an `i2c_slave::Device` which is fed with
+//! the bus events. A read returns a snapshot taken when the master addressed
+//! the slave, so a value never changes halfway through a transaction. The
+//! master side is `I2cLink`, a driver like the one of any other sensor:
+//!
+//! ```ignore
+//! let mut link = I2cLink::new(i2c);
+//! let measurements = link.read_measurements()?;
+//! link.set_led(true)?;
+//! ```
+
+use crate::i2c_slave::Device;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+/// Default 7-bit address of the slave
+pub const ADDRESS: u8 = 0x42;
+/// Contents of the WHO_AM_I register
+pub const WHO_AM_I_VALUE: u8 = 0xB1;
+
+/// Register addresses
+pub mod register {
+    pub const WHO_AM_I: u8 = 0x00;
+    pub const SAMPLE: u8 = 0x01;
+    pub const TEMPERATURE: u8 = 0x02;
+    pub const ANALOG: u8 = 0x04;
+    pub const UPTIME: u8 = 0x06;
+    pub const CONTROL: u8 = 0x0A;
+    pub const INTERVAL: u8 = 0x0B;
+}
+
+/// Number of registers
+pub const REGISTER_COUNT: usize = 0x0C;
+/// CONTROL register bit which turns the LED of the slave on
+pub const CONTROL_LED: u8 = 1 << 0;
+
+const MEASUREMENTS_LEN: usize = (register::CONTROL - register::SAMPLE) as usize;
+
+/// Measurements exposed by the slave
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Measurements {
+    /// Number of the measurement, to tell whether it is new
+    pub sample: u8,
+    /// Temperature in 1/10 degrees Celsius
+    pub temperature: i16,
+    pub analog_mv: u16,
+    pub uptime_s: u32,
+}
+
+impl Measurements {
+    fn to_bytes(self) -> [u8; MEASUREMENTS_LEN] {
+        let mut bytes = [0; MEASUREMENTS_LEN];
+        bytes[0] = self.sample;
+        bytes[1..3].copy_from_slice(&self.temperature.to_be_bytes());
+        bytes[3..5].copy_from_slice(&self.analog_mv.to_be_bytes());
+        bytes[5..9].copy_from_slice(&self.uptime_s.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; MEASUREMENTS_LEN]) -> Self {
+        Measurements {
+            sample: bytes[0],
+            temperature: i16::from_be_bytes([bytes[1], bytes[2]]),
+            analog_mv: u16::from_be_bytes([bytes[3], bytes[4]]),
+            uptime_s: u32::from_be_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]),
+        }
+    }
+}
+
+/// Registers of the slave and the state of the current transaction
+#[derive(Debug)]
+pub struct RegisterMap {
+    registers: [u8; REGISTER_COUNT],
+    snapshot: [u8; REGISTER_COUNT],
+    pointer: usize,
+    /// The next byte written is the pointer.
+    pointer_next: bool,
+}
+
+impl Default for RegisterMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegisterMap {
+    /// Create the registers with the LED off and measurements every second.
+    pub fn new() -> Self {
+        let mut registers = [0; REGISTER_COUNT];
+        registers[usize::from(register::WHO_AM_I)] = WHO_AM_I_VALUE;
+        registers[usize::from(register::INTERVAL)] = 100;
+        RegisterMap {
+            registers,
+            snapshot: registers,
+            pointer: 0,
+            pointer_next: false,
+        }
+    }
+
+    /// Store new measurements.
+    pub fn update(&mut self, measurements: &Measurements) {
+        let start = usize::from(register::SAMPLE);
+        self.registers[start..start + MEASUREMENTS_LEN].copy_from_slice(&measurements.to_bytes());
+    }
+
+    /// Whether the master turned the LED on.
+    pub fn led(&self) -> bool {
+        self.registers[usize::from(register::CONTROL)] & CONTROL_LED != 0
+    }
+
+    /// Time between measurements in milliseconds
+    pub fn interval_ms(&self) -> u32 {
+        u32::from(self.registers[usize::from(register::INTERVAL)]) * 10
+    }
+}
+
+impl Device for RegisterMap {
+    fn start(&mut self, read: bool) {
+        if read {
+            self.snapshot = self.registers;
+        }
+        self.pointer_next = !read;
+    }
+
+    fn write(&mut self, byte: u8) {
+        if self.pointer_next {
+            self.pointer = usize::from(byte);
+            self.pointer_next = false;
+            return;
+        }
+        if self.pointer == usize::from(register::CONTROL) {
+            self.registers[self.pointer] = byte & CONTROL_LED;
+        } else if self.pointer == usize::from(register::INTERVAL) && byte > 0 {
+            self.registers[self.pointer] = byte;
+        }
+        self.pointer = self.pointer.saturating_add(1);
+    }
+
+    fn read(&mut self) -> u8 {
+        let byte = self.snapshot.get(self.pointer).copied().unwrap_or(0xFF);
+        self.pointer = self.pointer.saturating_add(1);
+        byte
+    }
+}
+
+/// Master side driver for the slave
+#[derive(Debug)]
+pub struct I2cLink<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C, E> I2cLink<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Create a new driver for the slave at the default address.
+    pub fn new(i2c: I2C) -> Self {
+        I2cLink {
+            i2c,
+            address: ADDRESS,
+        }
+    }
+
+    /// Destroy the driver and return the I2C bus.
+    pub fn destroy(self) -> I2C {
+        self.i2c
+    }
+
+    /// Read the WHO_AM_I register, which should be `WHO_AM_I_VALUE`.
+    pub fn who_am_i(&mut self) -> Result<u8, E> {
+        let mut value = [0];
+        self.i2c
+            .write_read(self.address, &[register::WHO_AM_I], &mut value)?;
+        Ok(value[0])
+    }
+
+    /// Read all measurements in one transaction.
+    pub fn read_measurements(&mut self) -> Result<Measurements, E> {
+        let mut bytes = [0; MEASUREMENTS_LEN];
+        self.i2c
+            .write_read(self.address, &[register::SAMPLE], &mut bytes)?;
+        Ok(Measurements::from_bytes(&bytes))
+    }
+
+    /// Turn the LED of the slave on or off.
+    pub fn set_led(&mut self, on: bool) -> Result<(), E> {
+        let control = if on { CONTROL_LED } else { 0 };
+        self.i2c.write(self.address, &[register::CONTROL, control])
+    }
+
+    /// Set the time between measurements, from 10ms to 2550ms.
+    pub fn set_interval_ms(&mut self, interval_ms: u32) -> Result<(), E> {
+        let interval = (interval_ms / 10).clamp(1, 255) as u8;
+        self.i2c
+            .write(self.address, &[register::INTERVAL, interval])
+    }
+}
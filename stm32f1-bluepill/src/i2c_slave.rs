@@ -0,0 +1,125 @@
+//! I2C2 as a slave, to emulate a device.
+//!
+//! The HAL supports I2C only as master, so `I2c2Slave` sets I2C2 up as a
+//! slave through its registers. The register map of the emulated device
+//! implements `Device`; the I2C2 interrupts pass it every address match,
+//! byte and stop condition. Clock stretching holds the master until the
+//! interrupt has answered.
+//!
+//! The I2C2 clock must be enabled before the RCC is handed over to the HAL:
+//!
+//! ```ignore
+//! device.RCC.apb1enr.modify(|_, w| w.i2c2en().set_bit());
+//! // ...
+//! let _scl = gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh);
+//! let _sda = gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh);
+//! let slave = I2c2Slave::new(device.I2C2, 0x48, clocks);
+//!
+//! #[task(binds = I2C2_EV, resources = [slave, map])]
+//! fn i2c2_ev(cx: i2c2_ev::Context) {
+//!     cx.resources.slave.on_event(cx.resources.map);
+//! }
+//!
+//! #[task(binds = I2C2_ER, resources = [slave, map])]
+//! fn i2c2_er(cx: i2c2_er::Context) {
+//!     cx.resources.slave.on_error(cx.resources.map);
+//! }
+//! ```
+
+use stm32f1xx_hal::{pac, rcc::Clocks};
+
+/// Register map of an emulated device
+pub trait Device {
+    /// The slave was addressed, after a start or a repeated start.
+    /// `transmit` is set if the master reads from the slave.
+    fn start(&mut self, transmit: bool);
+
+    /// Byte written by the master
+    fn write(&mut self, byte: u8);
+
+    /// Byte to send to the master
+    fn read(&mut self) -> u8;
+
+    /// The transaction ended with a stop condition or with the NACK the
+    /// master sends after the last byte it reads.
+    fn stop(&mut self) {}
+}
+
+/// I2C2 in slave mode
+pub struct I2c2Slave {
+    i2c: pac::I2C2,
+}
+
+impl I2c2Slave {
+    /// Set up I2C2 as a slave with the given 7-bit address and enable its
+    /// event, buffer and error interrupts.
+    #[allow(unsafe_code)]
+    pub fn new(i2c: pac::I2C2, address: u8, clocks: Clocks) -> Self {
+        let pclk1_mhz = clocks.pclk1().0 / 1_000_000;
+        i2c.cr1.write(|w| w.pe().clear_bit());
+        // The address goes in bits 7:1. Bit 14 must be kept at 1.
+        i2c.oar1
+            .write(|w| unsafe { w.bits((1 << 14) | (u32::from(address) << 1)) });
+        // FREQ is the APB1 clock in MHz. ITEVTEN, ITBUFEN and ITERREN.
+        i2c.cr2
+            .write(|w| unsafe { w.bits(pclk1_mhz | (1 << 9) | (1 << 10) | (1 << 8)) });
+        i2c.cr1.write(|w| w.pe().set_bit());
+        // ACK can only be set once the peripheral is enabled.
+        i2c.cr1.modify(|_, w| w.ack().set_bit());
+        I2c2Slave { i2c }
+    }
+
+    /// Answer to the address, or not, as if no device was on the bus.
+    pub fn set_ack(&mut self, ack: bool) {
+        self.i2c.cr1.modify(|_, w| w.ack().bit(ack));
+    }
+
+    /// Handle the I2C2 event interrupt. Returns whether the slave was
+    /// addressed.
+    pub fn on_event<D: Device>(&mut self, device: &mut D) -> bool {
+        let i2c = &self.i2c;
+        let status = i2c.sr1.read();
+        if status.addr().bit_is_set() {
+            // Reading SR2 after SR1 clears ADDR.
+            device.start(i2c.sr2.read().tra().bit_is_set());
+        }
+        if status.rxne().bit_is_set() {
+            device.write(i2c.dr.read().dr().bits());
+        }
+        if status.txe().bit_is_set() && i2c.sr2.read().tra().bit_is_set() {
+            i2c.dr.write(|w| w.dr().bits(device.read()));
+        }
+        if status.stopf().bit_is_set() {
+            // Reading SR1 and then writing CR1 clears STOPF.
+            i2c.cr1.modify(|_, w| w.pe().set_bit());
+            device.stop();
+        }
+        status.addr().bit_is_set()
+    }
+
+    /// Handle the I2C2 error interrupt. The master ends every read with a
+    /// NACK. Returns whether there was anything else, i.e. a bus error.
+    pub fn on_error<D: Device>(&mut self, device: &mut D) -> bool {
+        let i2c = &self.i2c;
+        let status = i2c.sr1.read();
+        if status.af().bit_is_set() {
+            device.stop();
+        }
+        i2c.sr1.modify(|_, w| {
+            w.af()
+                .clear_bit()
+                .berr()
+                .clear_bit()
+                .arlo()
+                .clear_bit()
+                .ovr()
+                .clear_bit()
+        });
+        status.berr().bit_is_set() || status.arlo().bit_is_set() || status.ovr().bit_is_set()
+    }
+
+    /// Return the peripheral.
+    pub fn free(self) -> pac::I2C2 {
+        self.i2c
+    }
+}
@@ -0,0 +1,133 @@
+//! Passive I2C bus decoder for sniffing the traffic between other devices.
+//!
+//! On an I2C bus SDA only changes while SCL is low, except for the start
+//! and stop conditions: SDA falling while SCL is high is a start, SDA rising
+//! while SCL is high is a stop. The receiver reads every bit at the rising
+//! edge of SCL. Each byte has 8 bits, most significant bit first, followed
+//! by an acknowledge bit which is low (ACK) or high (NACK). The first byte
+//! after a start is the 7-bit address and the read/write bit.
+//!
+//! The decoder does not care how the lines are sampled. Feed it with the
+//! level of both lines at the same time, as often as possible: every level
+//! of SCL must be seen at least once.
+//!
+//! ```ignore
+//! let mut decoder = I2cDecoder::new();
+//! loop {
+//!     let idr = gpiob.idr.read();
+//!     if let Some(event) = decoder.sample(idr.idr12().bit(), idr.idr13().bit()) {
+//!         rprintln!("{:?}", event);
+//!     }
+//! }
+//! ```
+//!
+//! 10-bit addresses are shown as an address followed by data.
+
+/// Decoded bus condition or byte
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum I2cEvent {
+    Start,
+    /// A start without a stop before it
+    RepeatedStart,
+    /// First byte after a start: the 7-bit address and the direction
+    Address {
+        address: u8,
+        read: bool,
+        ack: bool,
+    },
+    Data {
+        value: u8,
+        ack: bool,
+    },
+    Stop,
+}
+
+/// I2C decoder
+#[derive(Debug)]
+pub struct I2cDecoder {
+    scl: bool,
+    sda: bool,
+    in_transaction: bool,
+    address_next: bool,
+    byte: u8,
+    bits: u8,
+}
+
+impl Default for I2cDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl I2cDecoder {
+    /// Create a new decoder. Until the first start condition is seen
+    /// nothing is decoded.
+    pub fn new() -> Self {
+        I2cDecoder {
+            scl: true,
+            sda: true,
+            in_transaction: false,
+            address_next: false,
+            byte: 0,
+            bits: 0,
+        }
+    }
+
+    /// Whether a start condition was seen and no stop after it yet.
+    pub fn in_transaction(&self) -> bool {
+        self.in_transaction
+    }
+
+    /// Decode the current level of both lines.
+    pub fn sample(&mut self, scl: bool, sda: bool) -> Option<I2cEvent> {
+        let (previous_scl, previous_sda) = (self.scl, self.sda);
+        self.scl = scl;
+        self.sda = sda;
+
+        if scl && previous_scl && sda != previous_sda {
+            self.byte = 0;
+            self.bits = 0;
+            if !sda {
+                let event = if self.in_transaction {
+                    I2cEvent::RepeatedStart
+                } else {
+                    I2cEvent::Start
+                };
+                self.in_transaction = true;
+                self.address_next = true;
+                return Some(event);
+            }
+            if self.in_transaction {
+                self.in_transaction = false;
+                return Some(I2cEvent::Stop);
+            }
+            return None;
+        }
+
+        if scl && !previous_scl && self.in_transaction {
+            if self.bits < 8 {
+                self.byte = (self.byte << 1) | u8::from(sda);
+                self.bits += 1;
+                return None;
+            }
+            let ack = !sda;
+            let event = if self.address_next {
+                I2cEvent::Address {
+                    address: self.byte >> 1,
+                    read: self.byte & 1 != 0,
+                    ack,
+                }
+            } else {
+                I2cEvent::Data {
+                    value: self.byte,
+                    ack,
+                }
+            };
+            self.address_next = false;
+            self.byte = 0;
+            self.bits = 0;
+            return Some(event);
+        }
+        None
+    }
+}
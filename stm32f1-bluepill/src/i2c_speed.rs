@@ -0,0 +1,172 @@
+//! I2C setup which falls back to a slower bus speed on errors.
+//!
+//! Breakouts on long wires or with weak pull-up resistors often work at
+//! 100kHz but not at 400kHz. `i2c1_with_fallback()` and
+//! `i2c2_with_fallback()` set the bus up at each of the `SPEEDS_HZ` in turn
+//! and probe the devices, until a speed passes. A speed passes if no more
+//! than one of `PROBES` probes fails, so a single glitch does not slow the
+//! bus down, but repeated errors do:
+//!
+//! ```ignore
+//! let (i2c, speed) = i2c_speed::i2c1_with_fallback(
+//!     dp.I2C1,
+//!     (scl, sda),
+//!     &mut afio.mapr,
+//!     clocks,
+//!     &mut rcc.apb1,
+//!     |i2c| i2c_speed::probe_addresses(i2c, &[0x48, 0x3C]),
+//! );
+//! ```
+//!
+//! The returned speed is `None` if no speed passed. The bus runs at the
+//! slowest speed then, so the example can carry on and report the errors.
+
+use embedded_hal::blocking::i2c::Write;
+use stm32f1xx_hal::{
+    afio::MAPR,
+    i2c::{BlockingI2c, DutyCycle, Mode, Pins},
+    pac::{I2C1, I2C2},
+    prelude::*,
+    rcc::{Clocks, APB1},
+};
+
+/// Bus speeds tried from first to last
+pub const SPEEDS_HZ: [u32; 3] = [400_000, 100_000, 10_000];
+/// Number of probes at each speed
+pub const PROBES: u8 = 4;
+
+/// Mode of the I2C peripheral for a bus speed
+pub fn mode(frequency_hz: u32) -> Mode {
+    if frequency_hz > 100_000 {
+        Mode::Fast {
+            frequency: frequency_hz.hz(),
+            duty_cycle: DutyCycle::Ratio2to1,
+        }
+    } else {
+        Mode::Standard {
+            frequency: frequency_hz.hz(),
+        }
+    }
+}
+
+/// Timeout for the start condition, the address and each data byte. A byte
+/// with its acknowledge takes 9 clock periods, give it twice that.
+fn timeout_us(frequency_hz: u32) -> u32 {
+    (18_000_000 / frequency_hz).max(1000)
+}
+
+/// Set I2C1 up at the fastest speed at which `probe` passes and return the
+/// bus with that speed.
+pub fn i2c1_with_fallback<PINS, F>(
+    i2c1: I2C1,
+    pins: PINS,
+    mapr: &mut MAPR,
+    clocks: Clocks,
+    apb1: &mut APB1,
+    probe: F,
+) -> (BlockingI2c<I2C1, PINS>, Option<u32>)
+where
+    PINS: Pins<I2C1>,
+    F: FnMut(&mut BlockingI2c<I2C1, PINS>) -> bool,
+{
+    with_fallback(
+        1,
+        (i2c1, pins),
+        |i2c1, pins, frequency_hz| {
+            let timeout = timeout_us(frequency_hz);
+            BlockingI2c::i2c1(
+                i2c1,
+                pins,
+                mapr,
+                mode(frequency_hz),
+                clocks,
+                apb1,
+                timeout,
+                10,
+                timeout,
+                timeout,
+            )
+        },
+        |i2c| i2c.free(),
+        probe,
+    )
+}
+
+/// Set I2C2 up at the fastest speed at which `probe` passes and return the
+/// bus with that speed.
+pub fn i2c2_with_fallback<PINS, F>(
+    i2c2: I2C2,
+    pins: PINS,
+    clocks: Clocks,
+    apb1: &mut APB1,
+    probe: F,
+) -> (BlockingI2c<I2C2, PINS>, Option<u32>)
+where
+    PINS: Pins<I2C2>,
+    F: FnMut(&mut BlockingI2c<I2C2, PINS>) -> bool,
+{
+    with_fallback(
+        2,
+        (i2c2, pins),
+        |i2c2, pins, frequency_hz| {
+            let timeout = timeout_us(frequency_hz);
+            BlockingI2c::i2c2(
+                i2c2,
+                pins,
+                mode(frequency_hz),
+                clocks,
+                apb1,
+                timeout,
+                10,
+                timeout,
+                timeout,
+            )
+        },
+        |i2c| i2c.free(),
+        probe,
+    )
+}
+
+/// Try the `SPEEDS_HZ` on bus number `bus`. `init` sets the bus up at a
+/// speed and `free` releases it for the next try.
+fn with_fallback<I2C, PINS, F>(
+    bus: u8,
+    mut parts: (I2C, PINS),
+    mut init: impl FnMut(I2C, PINS, u32) -> BlockingI2c<I2C, PINS>,
+    free: impl Fn(BlockingI2c<I2C, PINS>) -> (I2C, PINS),
+    mut probe: F,
+) -> (BlockingI2c<I2C, PINS>, Option<u32>)
+where
+    F: FnMut(&mut BlockingI2c<I2C, PINS>) -> bool,
+{
+    for (i, frequency_hz) in SPEEDS_HZ.iter().copied().enumerate() {
+        let mut i2c = init(parts.0, parts.1, frequency_hz);
+        let failures = (0..PROBES).filter(|_| !probe(&mut i2c)).count();
+        if failures <= 1 {
+            crate::log_info!("I2C{} runs at {}Hz", bus, frequency_hz);
+            return (i2c, Some(frequency_hz));
+        }
+        crate::log_warn!(
+            "I2C{} at {}Hz: {} of {} probes failed",
+            bus,
+            frequency_hz,
+            failures,
+            PROBES
+        );
+        if i == SPEEDS_HZ.len() - 1 {
+            return (i2c, None);
+        }
+        parts = free(i2c);
+    }
+    unreachable!()
+}
+
+/// Probe which writes a 0x00 byte to each of the devices. Writes work for
+/// devices which do not answer reads, like SSD1306 displays. The byte is an
+/// empty command list for an SSD1306 and selects register 0 of most
+/// sensors, so it changes nothing.
+pub fn probe_addresses<I2C: Write>(i2c: &mut I2C, addresses: &[u8]) -> bool {
+    addresses
+        .iter()
+        .all(|address| i2c.write(*address, &[0x00]).is_ok())
+}
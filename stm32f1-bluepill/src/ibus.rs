@@ -0,0 +1,100 @@
+//! FlySky IBUS RC receiver protocol decoder.
+//!
+//! IBUS is a serial protocol at 115200 baud, 8 data bits, no parity and
+//! 1 stop bit with normal levels, so it works with any UART. A frame is sent
+//! every 7ms and is 32 bytes long:
+//! - the length 0x20 and the command 0x40
+//! - 14 channels as 16-bit little-endian servo pulse widths in microseconds
+//! - a 16-bit little-endian checksum: 0xFFFF minus the sum of all the other
+//!   bytes
+//!
+//! There are no failsafe flags. Depending on its configuration, the receiver
+//! either stops sending frames or sends the failsafe positions when it loses
+//! the connection to the transmitter.
+//!
+//! ```ignore
+//! let mut decoder = IbusDecoder::new();
+//! // for every received byte:
+//! if let Some(channels) = decoder.byte(byte) {
+//!     rprintln!("{:?}", channels);
+//! }
+//! // when the line has been idle for a while or on a reception error:
+//! decoder.gap();
+//! ```
+
+/// Baud rate of IBUS
+pub const BAUD_RATE: u32 = 115_200;
+/// Number of channels
+pub const CHANNELS: usize = 14;
+/// Length of a frame in bytes
+pub const FRAME_LEN: usize = 32;
+const HEADER: [u8; 2] = [0x20, 0x40];
+
+/// Parse a complete frame and return the channels in microseconds.
+pub fn parse(data: &[u8; FRAME_LEN]) -> Option<[u16; CHANNELS]> {
+    if data[..2] != HEADER {
+        return None;
+    }
+    let sum = data[..FRAME_LEN - 2]
+        .iter()
+        .fold(0_u16, |sum, b| sum.wrapping_add(u16::from(*b)));
+    let checksum = u16::from_le_bytes([data[FRAME_LEN - 2], data[FRAME_LEN - 1]]);
+    if 0xFFFF - sum != checksum {
+        return None;
+    }
+    let mut channels = [0; CHANNELS];
+    for (channel, bytes) in channels.iter_mut().zip(data[2..].chunks(2)) {
+        *channel = u16::from_le_bytes([bytes[0], bytes[1]]);
+    }
+    Some(channels)
+}
+
+/// IBUS stream decoder
+#[derive(Debug, Clone)]
+pub struct IbusDecoder {
+    buffer: [u8; FRAME_LEN],
+    len: usize,
+}
+
+impl Default for IbusDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IbusDecoder {
+    /// Create a new decoder
+    pub fn new() -> Self {
+        IbusDecoder {
+            buffer: [0; FRAME_LEN],
+            len: 0,
+        }
+    }
+
+    /// The line was idle or there was a reception error. The next byte
+    /// starts a new frame.
+    pub fn gap(&mut self) {
+        self.len = 0;
+    }
+
+    /// Feed a received byte.
+    ///
+    /// Returns the channels in microseconds when a frame is complete and
+    /// valid.
+    pub fn byte(&mut self, byte: u8) -> Option<[u16; CHANNELS]> {
+        // Synchronize on the header.
+        if self.len < HEADER.len() && byte != HEADER[self.len] {
+            self.len = 0;
+            if byte != HEADER[0] {
+                return None;
+            }
+        }
+        self.buffer[self.len] = byte;
+        self.len += 1;
+        if self.len < FRAME_LEN {
+            return None;
+        }
+        self.len = 0;
+        parse(&self.buffer)
+    }
+}
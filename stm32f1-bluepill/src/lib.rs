@@ -1,3 +1,68 @@
-//! Empty. Please have a look at the examples.
+//! Helpers shared by some of the examples. Please have a look at the examples.
 //!
 #![no_std]
+
+pub mod adc_stream;
+pub mod alarm;
+pub mod aqi;
+pub mod bootloader;
+pub mod can;
+pub mod clock;
+pub mod color;
+pub mod complementary;
+pub mod convert;
+pub mod crc;
+pub mod dali;
+pub mod dcf77;
+pub mod delay;
+pub mod diagnostics;
+pub mod dronecan;
+pub mod dtmf;
+pub mod easing;
+pub mod escpos;
+pub mod exti;
+pub mod fft;
+pub mod fsk;
+pub mod gauge;
+pub mod gesture;
+pub mod goertzel;
+pub mod http;
+pub mod i2c_dma;
+pub mod i2c_link;
+pub mod i2c_slave;
+pub mod i2c_sniffer;
+pub mod i2c_speed;
+pub mod ibus;
+pub mod logging;
+pub mod median;
+pub mod modbus;
+pub mod monotonic;
+pub mod morse;
+pub mod motor;
+pub mod mqtt_sn;
+pub mod nec;
+pub mod onewire;
+pub mod opentherm;
+pub mod panic_display;
+pub mod pi;
+pub mod pid;
+pub mod profile;
+pub mod ps2;
+pub mod record_queue;
+pub mod rng;
+pub mod rotary_dial;
+pub mod sbus;
+pub mod scheduler;
+pub mod sdi12;
+pub mod shift_register;
+pub mod sntp;
+pub mod soft_i2c;
+pub mod soft_spi;
+pub mod spi_device;
+#[cfg(feature = "telemetry-json")]
+pub mod telemetry;
+pub mod test_frame;
+pub mod uart;
+pub mod vibration;
+pub mod w5500;
+pub mod wiegand;
@@ -0,0 +1,219 @@
+//! Logging macros with levels, selectable output and rate limiting.
+//!
+//! `log_info!`, `log_warn!` and `log_error!` take the same arguments as
+//! `rprintln!` and print a line starting with the level:
+//!
+//! ```ignore
+//! use driver_examples_bluepill::{log_info, log_warn};
+//!
+//! log_info!("Temperature: {:.1}C", temperature);
+//! // In a loop: print the first message and then only every 100th.
+//! log_warn!(every = 100, "ADC error: {:?}", e);
+//! ```
+//!
+//! The arguments are formatted straight into the output, without a buffer.
+//!
+//! The messages below the level selected at compile time are removed
+//! completely: everything by default, only warnings and errors with the
+//! `log-warn` feature and only errors with `log-error`.
+//!
+//! The messages go through RTT by default, which the example initializes
+//! with `rtt_init_print!()`. With the `log-semihosting` feature they go
+//! through semihosting instead, and with `log-uart` to a UART, e.g. when
+//! there is no debug probe:
+//!
+//! ```ignore
+//! // `uart_tx` goes to the USART1 interrupt handler, see the `uart` module.
+//! let (uart_tx, writer) = singleton!(: TxBuffer<256> = TxBuffer::new())
+//!     .unwrap()
+//!     .split(tx, || NVIC::pend(Interrupt::USART1));
+//! logging::set_writer(singleton!(: TxWriter<256> = writer).unwrap());
+//! ```
+//!
+//! The UART writer may wait for room in its buffer, so do not log from
+//! interrupt handlers of the same or a higher priority than the UART. A
+//! message logged by an interrupt handler while another one is being
+//! written is dropped.
+//!
+//! defmt is not supported: it needs its own format strings and traits.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// Importance of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+}
+
+impl Level {
+    fn prefix(self) -> &'static str {
+        match self {
+            Level::Error => "[ERROR] ",
+            Level::Warn => "[WARN] ",
+            Level::Info => "[INFO] ",
+        }
+    }
+}
+
+/// Least important level printed, selected with the `log-warn` and
+/// `log-error` features
+pub const MAX_LEVEL: Level = if cfg!(feature = "log-error") {
+    Level::Error
+} else if cfg!(feature = "log-warn") {
+    Level::Warn
+} else {
+    Level::Info
+};
+
+/// Whether messages of the given level are printed.
+pub const fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL as u8
+}
+
+/// Print a message. Use the macros instead.
+pub fn log(level: Level, args: fmt::Arguments) {
+    if enabled(level) {
+        write(format_args!("{}{}", level.prefix(), args));
+    }
+}
+
+/// Print a message which replaces `suppressed` others. Use the macros
+/// instead.
+pub fn log_limited(level: Level, suppressed: u32, args: fmt::Arguments) {
+    if suppressed == 0 {
+        log(level, args);
+    } else if enabled(level) {
+        write(format_args!(
+            "{}{} ({} more not shown)",
+            level.prefix(),
+            args,
+            suppressed
+        ));
+    }
+}
+
+/// Lets one in `every` calls through. Each rate limited macro call has its
+/// own.
+#[derive(Debug)]
+pub struct RateLimit {
+    calls: AtomicU32,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimit {
+    pub const fn new() -> Self {
+        RateLimit {
+            calls: AtomicU32::new(0),
+        }
+    }
+
+    /// Count a call. Returns the number of calls held back since the last
+    /// one let through if this one may go through.
+    pub fn check(&self, every: u32) -> Option<u32> {
+        let every = every.max(1);
+        let calls = self.calls.fetch_add(1, Ordering::Relaxed);
+        match calls % every {
+            0 if calls == 0 => Some(0),
+            0 => Some(every - 1),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(any(feature = "log-semihosting", feature = "log-uart")))]
+fn write(line: fmt::Arguments) {
+    rtt_target::rprintln!("{}", line);
+}
+
+#[cfg(feature = "log-semihosting")]
+fn write(line: fmt::Arguments) {
+    cortex_m_semihosting::hprintln!("{}", line).ok();
+}
+
+#[cfg(all(feature = "log-uart", not(feature = "log-semihosting")))]
+pub use self::uart::set_writer;
+
+#[cfg(all(feature = "log-uart", not(feature = "log-semihosting")))]
+use self::uart::write;
+
+#[cfg(all(feature = "log-uart", not(feature = "log-semihosting")))]
+mod uart {
+    use core::{
+        fmt::{self, Write},
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    static mut WRITER: Option<&'static mut (dyn Write + Send)> = None;
+    /// Set while `WRITER` is in use
+    static BUSY: AtomicBool = AtomicBool::new(false);
+
+    /// Send the messages to `writer` from now on, e.g. a `uart::TxWriter`.
+    pub fn set_writer(writer: &'static mut (dyn Write + Send)) {
+        while BUSY.swap(true, Ordering::Acquire) {}
+        unsafe {
+            WRITER = Some(writer);
+        }
+        BUSY.store(false, Ordering::Release);
+    }
+
+    pub(super) fn write(line: fmt::Arguments) {
+        if BUSY.swap(true, Ordering::Acquire) {
+            return;
+        }
+        // BUSY gives exclusive access to WRITER.
+        if let Some(writer) = unsafe { WRITER.as_mut() } {
+            writer.write_fmt(format_args!("{}\r\n", line)).ok();
+        }
+        BUSY.store(false, Ordering::Release);
+    }
+}
+
+/// Print an informational message, see the `logging` module.
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => {
+        $crate::__log!($crate::logging::Level::Info, $($arg)+)
+    };
+}
+
+/// Print a warning, see the `logging` module.
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => {
+        $crate::__log!($crate::logging::Level::Warn, $($arg)+)
+    };
+}
+
+/// Print an error, see the `logging` module.
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => {
+        $crate::__log!($crate::logging::Level::Error, $($arg)+)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log {
+    ($level:expr, every = $every:expr, $($arg:tt)+) => {{
+        if $crate::logging::enabled($level) {
+            static LIMIT: $crate::logging::RateLimit = $crate::logging::RateLimit::new();
+            if let Some(suppressed) = LIMIT.check($every) {
+                $crate::logging::log_limited($level, suppressed, format_args!($($arg)+));
+            }
+        }
+    }};
+    ($level:expr, $($arg:tt)+) => {
+        $crate::logging::log($level, format_args!($($arg)+))
+    };
+}
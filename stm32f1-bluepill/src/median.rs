@@ -0,0 +1,65 @@
+//! Moving median filter.
+//!
+//! The median of the last `N` values ignores single outliers completely,
+//! unlike an average which is pulled towards them. This suits sensors which
+//! now and then return a wrong value, like an ultrasonic distance sensor
+//! catching a stray echo.
+//!
+//! ```ignore
+//! let mut filter: MedianFilter<u32, 5> = MedianFilter::new();
+//! loop {
+//!     let distance = filter.push(measure());
+//! }
+//! ```
+//!
+//! Use an odd `N` so that the median is one of the values.
+
+/// Median of the last `N` values
+#[derive(Debug, Clone)]
+pub struct MedianFilter<T, const N: usize> {
+    values: [T; N],
+    len: usize,
+    next: usize,
+}
+
+impl<T: Copy + Ord + Default, const N: usize> Default for MedianFilter<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy + Ord + Default, const N: usize> MedianFilter<T, N> {
+    pub fn new() -> Self {
+        MedianFilter {
+            values: [T::default(); N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// Add a value and return the median. Until `N` values have been added,
+    /// the median is taken over the values added so far.
+    pub fn push(&mut self, value: T) -> T {
+        self.values[self.next] = value;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        self.median().unwrap_or(value)
+    }
+
+    /// Median of the stored values, `None` if there are none.
+    pub fn median(&self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let mut sorted = self.values;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable();
+        Some(sorted[self.len / 2])
+    }
+
+    /// Forget all values.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.next = 0;
+    }
+}
@@ -0,0 +1,329 @@
+//! Minimal Modbus RTU master over a serial interface.
+//!
+//! A request is the slave address, the function code, the data and a
+//! CRC-16/MODBUS checksum (little-endian). The answer repeats the address and
+//! the function code. If the slave cannot process the request, it answers
+//! with the function code + 0x80 and an exception code.
+//!
+//! Requests are built with `Request` and sent with `Master::execute()`, or
+//! with the convenience methods of `Master`. Requests that time out or whose
+//! answer has a wrong checksum are sent again up to the configured number of
+//! retries. Exceptions are returned right away.
+//!
+//! The answers are received with a timeout measured with the DWT cycle
+//! counter, so it must be enabled. The receiver can be the one of the HAL or
+//! a `uart::RxReader`, which keeps the bytes received while the program was
+//! busy with something else.
+//!
+//! ```ignore
+//! let mut master = Master::new(tx, rx, clocks.sysclk().0 / 10).retries(2);
+//! let mut registers = [0; 10];
+//! master.read_holding_registers(0x01, 0, &mut registers)?;
+//! ```
+//!
+//! Most industrial devices use RS-485. Use a transceiver module with
+//! automatic direction control since the master does not switch the
+//! direction.
+
+use cortex_m::peripheral::DWT;
+use embedded_hal::serial;
+use nb::block;
+
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+const EXCEPTION_FLAG: u8 = 0x80;
+/// Largest number of registers that can be read at once
+pub const MAX_REGISTERS: usize = 125;
+/// Length of the longest answer
+pub const MAX_ANSWER_LEN: usize = 5 + 2 * MAX_REGISTERS;
+
+/// Exception codes sent by the slaves
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exception {
+    IllegalFunction,
+    IllegalDataAddress,
+    IllegalDataValue,
+    SlaveDeviceFailure,
+    Acknowledge,
+    SlaveDeviceBusy,
+    Other(u8),
+}
+
+impl From<u8> for Exception {
+    fn from(code: u8) -> Self {
+        match code {
+            1 => Exception::IllegalFunction,
+            2 => Exception::IllegalDataAddress,
+            3 => Exception::IllegalDataValue,
+            4 => Exception::SlaveDeviceFailure,
+            5 => Exception::Acknowledge,
+            6 => Exception::SlaveDeviceBusy,
+            _ => Exception::Other(code),
+        }
+    }
+}
+
+/// Modbus errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The slave did not answer (completely) in time
+    Timeout,
+    /// The checksum of the answer does not match
+    Crc,
+    /// The answer does not belong to the request
+    UnexpectedResponse,
+    /// The slave answered with an exception
+    Exception(Exception),
+    /// Too many registers requested or the buffer is too small
+    InvalidArgument,
+}
+
+/// CRC-16/MODBUS (reflected polynomial 0xA001, initial value 0xFFFF)
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+    for byte in data {
+        crc ^= u16::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// Request frame including the checksum
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Request {
+    frame: [u8; 8],
+}
+
+impl Request {
+    /// Read `count` holding registers (function 0x03) starting at `start`.
+    pub fn read_holding_registers(slave: u8, start: u16, count: u16) -> Self {
+        Self::new(slave, READ_HOLDING_REGISTERS, start, count)
+    }
+
+    /// Read `count` input registers (function 0x04) starting at `start`.
+    pub fn read_input_registers(slave: u8, start: u16, count: u16) -> Self {
+        Self::new(slave, READ_INPUT_REGISTERS, start, count)
+    }
+
+    /// Write a holding register (function 0x06).
+    pub fn write_single_register(slave: u8, address: u16, value: u16) -> Self {
+        Self::new(slave, WRITE_SINGLE_REGISTER, address, value)
+    }
+
+    fn new(slave: u8, function: u8, first: u16, second: u16) -> Self {
+        let [first_high, first_low] = first.to_be_bytes();
+        let [second_high, second_low] = second.to_be_bytes();
+        let mut frame = [
+            slave,
+            function,
+            first_high,
+            first_low,
+            second_high,
+            second_low,
+            0,
+            0,
+        ];
+        let [crc_low, crc_high] = crc16(&frame[..6]).to_le_bytes();
+        frame[6] = crc_low;
+        frame[7] = crc_high;
+        Request { frame }
+    }
+
+    pub fn slave(&self) -> u8 {
+        self.frame[0]
+    }
+
+    pub fn function(&self) -> u8 {
+        self.frame[1]
+    }
+
+    /// Complete frame to send
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Length of a successful answer including the checksum
+    pub fn answer_len(&self) -> usize {
+        match self.function() {
+            READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+                5 + 2 * usize::from(u16::from_be_bytes([self.frame[4], self.frame[5]]))
+            }
+            // The answer repeats the request.
+            _ => self.frame.len(),
+        }
+    }
+}
+
+/// Modbus RTU master
+pub struct Master<TX, RX> {
+    tx: TX,
+    rx: RX,
+    timeout_cycles: u32,
+    retries: u8,
+}
+
+impl<TX, RX> Master<TX, RX>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+{
+    /// Create a new master. `timeout_cycles` is the time to wait for each
+    /// byte of the answer in CPU cycles.
+    pub fn new(tx: TX, rx: RX, timeout_cycles: u32) -> Self {
+        Master {
+            tx,
+            rx,
+            timeout_cycles,
+            retries: 0,
+        }
+    }
+
+    /// Send failed requests again up to `retries` times.
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Send a request and receive the answer into `answer`.
+    ///
+    /// Returns the length of the answer including the checksum.
+    pub fn execute(&mut self, request: &Request, answer: &mut [u8]) -> Result<usize, Error> {
+        let len = request.answer_len();
+        if len > MAX_ANSWER_LEN || answer.len() < len {
+            return Err(Error::InvalidArgument);
+        }
+        let mut attempt = 0;
+        loop {
+            self.send(request.as_bytes());
+            match self.receive(request, &mut answer[..len]) {
+                Err(Error::Timeout) | Err(Error::Crc) if attempt < self.retries => {
+                    attempt += 1;
+                }
+                result => return result.map(|_| len),
+            }
+        }
+    }
+
+    /// Read consecutive holding registers (function 0x03) starting at
+    /// `start`. The number of registers is the length of `registers`.
+    pub fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        start: u16,
+        registers: &mut [u16],
+    ) -> Result<(), Error> {
+        self.read_registers(READ_HOLDING_REGISTERS, slave, start, registers)
+    }
+
+    /// Read consecutive input registers (function 0x04) starting at
+    /// `start`. The number of registers is the length of `registers`.
+    pub fn read_input_registers(
+        &mut self,
+        slave: u8,
+        start: u16,
+        registers: &mut [u16],
+    ) -> Result<(), Error> {
+        self.read_registers(READ_INPUT_REGISTERS, slave, start, registers)
+    }
+
+    /// Write a holding register (function 0x06).
+    pub fn write_single_register(
+        &mut self,
+        slave: u8,
+        address: u16,
+        value: u16,
+    ) -> Result<(), Error> {
+        let request = Request::write_single_register(slave, address, value);
+        let mut answer = [0; 8];
+        self.execute(&request, &mut answer)?;
+        if answer != request.frame {
+            return Err(Error::UnexpectedResponse);
+        }
+        Ok(())
+    }
+
+    /// Release the serial interface.
+    pub fn destroy(self) -> (TX, RX) {
+        (self.tx, self.rx)
+    }
+
+    fn read_registers(
+        &mut self,
+        function: u8,
+        slave: u8,
+        start: u16,
+        registers: &mut [u16],
+    ) -> Result<(), Error> {
+        if registers.is_empty() || registers.len() > MAX_REGISTERS {
+            return Err(Error::InvalidArgument);
+        }
+        let request = Request::new(slave, function, start, registers.len() as u16);
+        let mut answer = [0; MAX_ANSWER_LEN];
+        self.execute(&request, &mut answer)?;
+        if usize::from(answer[2]) != 2 * registers.len() {
+            return Err(Error::UnexpectedResponse);
+        }
+        for (register, bytes) in registers.iter_mut().zip(answer[3..].chunks(2)) {
+            *register = u16::from_be_bytes([bytes[0], bytes[1]]);
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, frame: &[u8]) {
+        // Discard anything left over from a previous answer.
+        while self.rx.read().is_ok() {}
+        for byte in frame {
+            block!(self.tx.write(*byte)).ok();
+        }
+    }
+
+    /// Receive an answer of the length of `answer` or an exception.
+    fn receive(&mut self, request: &Request, answer: &mut [u8]) -> Result<(), Error> {
+        let function = request.function();
+        // An exception answer is 5 bytes long so read those first.
+        for byte in answer[..5].iter_mut() {
+            *byte = self.read_byte()?;
+        }
+        let len = if answer[1] == function | EXCEPTION_FLAG {
+            5
+        } else {
+            for byte in answer[5..].iter_mut() {
+                *byte = self.read_byte()?;
+            }
+            answer.len()
+        };
+        let (data, crc) = answer[..len].split_at(len - 2);
+        if crc16(data).to_le_bytes() != [crc[0], crc[1]] {
+            return Err(Error::Crc);
+        }
+        if data[0] != request.slave() {
+            return Err(Error::UnexpectedResponse);
+        }
+        if data[1] == function | EXCEPTION_FLAG {
+            return Err(Error::Exception(Exception::from(data[2])));
+        }
+        if data[1] != function {
+            return Err(Error::UnexpectedResponse);
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        let start = DWT::get_cycle_count();
+        loop {
+            if let Ok(byte) = self.rx.read() {
+                return Ok(byte);
+            }
+            if DWT::get_cycle_count().wrapping_sub(start) > self.timeout_cycles {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Monotonic millisecond and microsecond timebase using SysTick.
+//!
+//! Examples can timestamp events and implement timeouts with it instead of
+//! blocking in `Delay`. SysTick interrupts every millisecond and the
+//! exception handler, which each example defines itself so that examples
+//! using SysTick for something else are not affected, counts the
+//! milliseconds. The microseconds are calculated from the current value of
+//! the SysTick counter.
+//!
+//! ```ignore
+//! #[exception]
+//! fn SysTick() {
+//!     monotonic::tick();
+//! }
+//!
+//! monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+//! let start = monotonic::micros();
+//! disp.flush().unwrap();
+//! rprintln!("Flush took {}us", monotonic::micros().wrapping_sub(start));
+//! ```
+//!
+//! `with_timeout()` is a replacement for `block!` that gives up after some
+//! time, so that a device that stops answering does not hang the example:
+//!
+//! ```ignore
+//! match with_timeout(100, || sensor.read()) {
+//!     Ok(data) => rprintln!("{:?}", data),
+//!     Err(TimeoutError::Timeout) => rprintln!("Sensor timeout"),
+//!     Err(TimeoutError::Other(e)) => rprintln!("Sensor error: {:?}", e),
+//! }
+//! ```
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::peripheral::{syst::SystClkSource, SYST};
+
+static MILLIS: AtomicU32 = AtomicU32::new(0);
+static CYCLES_PER_US: AtomicU32 = AtomicU32::new(1);
+
+/// Start the SysTick exception every millisecond.
+pub fn setup_monotonic(mut syst: SYST, sysclk_hz: u32) {
+    CYCLES_PER_US.store(sysclk_hz / 1_000_000, Ordering::Relaxed);
+    syst.set_clock_source(SystClkSource::Core);
+    syst.set_reload(sysclk_hz / 1000 - 1);
+    syst.clear_current();
+    syst.enable_interrupt();
+    syst.enable_counter();
+}
+
+/// Advance the timebase. Call this from the SysTick exception handler.
+pub fn tick() {
+    MILLIS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Milliseconds since `setup_monotonic()`. Wraps around after about 49
+/// days, so compare with `wrapping_sub()`.
+pub fn millis() -> u32 {
+    MILLIS.load(Ordering::Relaxed)
+}
+
+/// Microseconds since `setup_monotonic()`. Wraps around after about 71
+/// minutes, so compare with `wrapping_sub()`.
+///
+/// This is only correct while the SysTick exception can run, i.e. not with
+/// interrupts disabled.
+pub fn micros() -> u32 {
+    loop {
+        let ms = millis();
+        // SysTick counts down from the reload value.
+        let cycles = SYST::get_reload() - SYST::get_current();
+        // Read again if the millisecond changed in between.
+        if millis() == ms {
+            let us = cycles / CYCLES_PER_US.load(Ordering::Relaxed);
+            return ms.wrapping_mul(1000).wrapping_add(us);
+        }
+    }
+}
+
+/// Busy wait for the given number of milliseconds.
+pub fn wait_ms(ms: u32) {
+    let start = millis();
+    while millis().wrapping_sub(start) < ms {}
+}
+
+/// Error returned by `with_timeout()`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeoutError<E> {
+    /// The operation did not complete in time
+    Timeout,
+    /// The operation failed
+    Other(E),
+}
+
+/// Poll the non-blocking operation `f` until it completes, but at most for
+/// `timeout_ms` milliseconds.
+pub fn with_timeout<T, E, F>(timeout_ms: u32, mut f: F) -> Result<T, TimeoutError<E>>
+where
+    F: FnMut() -> nb::Result<T, E>,
+{
+    let start = millis();
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(nb::Error::Other(e)) => return Err(TimeoutError::Other(e)),
+            Err(nb::Error::WouldBlock) => {
+                if millis().wrapping_sub(start) >= timeout_ms {
+                    return Err(TimeoutError::Timeout);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Morse code keying of text.
+//!
+//! The `MorseEncoder` turns a text into a sequence of `Keying` events: key
+//! down for a dot or a dash and key up for the gaps, each with its
+//! duration. It does not care what is keyed, so the same events can switch
+//! an LED, a buzzer and the carrier of an AD9833 together.
+//!
+//! The timing is the standard one: a dot is one unit, a dash three, the gap
+//! within a character one unit, between characters three and between words
+//! seven. The unit is `1200 / wpm` milliseconds ("PARIS" timing).
+//!
+//! ```ignore
+//! let mut encoder: MorseEncoder<32> = MorseEncoder::new(20);
+//! encoder.load("CQ DE N0CALL").unwrap();
+//! for keying in &mut encoder {
+//!     if keying.key_down {
+//!         led.set_low().unwrap();
+//!     } else {
+//!         led.set_high().unwrap();
+//!     }
+//!     delay.delay_ms(keying.duration_ms);
+//! }
+//! ```
+
+use heapless::Vec;
+
+/// Slowest speed in words per minute
+pub const MIN_WPM: u8 = 5;
+/// Fastest speed in words per minute
+pub const MAX_WPM: u8 = 40;
+
+/// Morse error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The character has no Morse code
+    Character(char),
+    /// The text does not fit into the encoder
+    Full,
+}
+
+/// Key down or up for some time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keying {
+    pub key_down: bool,
+    pub duration_ms: u32,
+}
+
+/// Encoder of texts up to `N` characters
+#[derive(Debug, Clone)]
+pub struct MorseEncoder<const N: usize> {
+    text: Vec<u8, N>,
+    wpm: u8,
+    index: usize,
+    element: usize,
+    /// The next event is the gap after the element
+    gap: bool,
+}
+
+impl<const N: usize> MorseEncoder<N> {
+    /// Create a new encoder with nothing to send. The speed is limited to
+    /// `MIN_WPM` to `MAX_WPM`.
+    pub fn new(wpm: u8) -> Self {
+        let mut encoder = MorseEncoder {
+            text: Vec::new(),
+            wpm: MIN_WPM,
+            index: 0,
+            element: 0,
+            gap: false,
+        };
+        encoder.set_wpm(wpm);
+        encoder
+    }
+
+    /// Speed in words per minute
+    pub fn wpm(&self) -> u8 {
+        self.wpm
+    }
+
+    /// Change the speed, also of the text being sent. It is limited to
+    /// `MIN_WPM` to `MAX_WPM`.
+    pub fn set_wpm(&mut self, wpm: u8) {
+        self.wpm = wpm.max(MIN_WPM).min(MAX_WPM);
+    }
+
+    /// Duration of a dot
+    pub fn unit_ms(&self) -> u32 {
+        1200 / u32::from(self.wpm)
+    }
+
+    /// Start sending a text, replacing what was not sent yet. Lower case
+    /// letters are sent as upper case. Nothing is sent if it returns an
+    /// error.
+    pub fn load(&mut self, text: &str) -> Result<(), Error> {
+        self.text.clear();
+        self.index = 0;
+        self.element = 0;
+        self.gap = false;
+        for c in text.chars() {
+            let upper = c.to_ascii_uppercase();
+            if upper != ' ' && code(upper).is_none() {
+                self.text.clear();
+                return Err(Error::Character(c));
+            }
+            if self.text.push(upper as u8).is_err() {
+                self.text.clear();
+                return Err(Error::Full);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether everything has been sent
+    pub fn is_done(&self) -> bool {
+        self.index >= self.text.len()
+    }
+}
+
+impl<const N: usize> Iterator for MorseEncoder<N> {
+    type Item = Keying;
+
+    fn next(&mut self) -> Option<Keying> {
+        let unit = self.unit_ms();
+        let c = char::from(*self.text.get(self.index)?);
+        let up = |units| Keying {
+            key_down: false,
+            duration_ms: units * unit,
+        };
+        let pattern = match code(c) {
+            Some(pattern) => pattern.as_bytes(),
+            None => {
+                // The gap between characters is already sent, so 4 units
+                // make the 7 between words.
+                self.index += 1;
+                return Some(up(4));
+            }
+        };
+        if !self.gap {
+            self.gap = true;
+            let units = if pattern[self.element] == b'.' { 1 } else { 3 };
+            return Some(Keying {
+                key_down: true,
+                duration_ms: units * unit,
+            });
+        }
+        self.gap = false;
+        self.element += 1;
+        if self.element < pattern.len() {
+            return Some(up(1));
+        }
+        self.element = 0;
+        self.index += 1;
+        Some(up(3))
+    }
+}
+
+/// Dots and dashes of an upper case character
+fn code(c: char) -> Option<&'static str> {
+    Some(match c {
+        'A' => ".-",
+        'B' => "-...",
+        'C' => "-.-.",
+        'D' => "-..",
+        'E' => ".",
+        'F' => "..-.",
+        'G' => "--.",
+        'H' => "....",
+        'I' => "..",
+        'J' => ".---",
+        'K' => "-.-",
+        'L' => ".-..",
+        'M' => "--",
+        'N' => "-.",
+        'O' => "---",
+        'P' => ".--.",
+        'Q' => "--.-",
+        'R' => ".-.",
+        'S' => "...",
+        'T' => "-",
+        'U' => "..-",
+        'V' => "...-",
+        'W' => ".--",
+        'X' => "-..-",
+        'Y' => "-.--",
+        'Z' => "--..",
+        '0' => "-----",
+        '1' => ".----",
+        '2' => "..---",
+        '3' => "...--",
+        '4' => "....-",
+        '5' => ".....",
+        '6' => "-....",
+        '7' => "--...",
+        '8' => "---..",
+        '9' => "----.",
+        '.' => ".-.-.-",
+        ',' => "--..--",
+        '?' => "..--..",
+        '\'' => ".----.",
+        '/' => "-..-.",
+        '(' => "-.--.",
+        ')' => "-.--.-",
+        ':' => "---...",
+        '=' => "-...-",
+        '+' => ".-.-.",
+        '-' => "-....-",
+        '"' => ".-..-.",
+        '@' => ".--.-.",
+        _ => return None,
+    })
+}
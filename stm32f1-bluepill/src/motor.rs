@@ -0,0 +1,34 @@
+//! Speed control of brushed DC motors with H-bridge drivers which have two
+//! PWM inputs per motor, like the DRV8833 or the MX1508.
+//!
+//! The bridge is driven in slow decay mode: one input is held high and the
+//! other one is low for the given fraction of the PWM period. The speed is
+//! then close to proportional to the duty cycle. With both inputs high the
+//! motor brakes.
+//!
+//! ```ignore
+//! let mut pwm = Timer::tim2(dp.TIM2, &clocks, &mut rcc.apb1)
+//!     .pwm::<Tim2NoRemap, _, _, _>(pins, &mut afio.mapr, 20.khz());
+//! pwm.enable(Channel::C1);
+//! pwm.enable(Channel::C2);
+//! motor::set_speed(&mut pwm, Channel::C1, Channel::C2, 0.5);
+//! ```
+
+use embedded_hal::Pwm;
+
+/// Set the speed of a motor: -1 (full reverse) to 1 (full forward).
+/// Positive speeds drive current from OUT1 to OUT2.
+pub fn set_speed<P>(pwm: &mut P, in1: P::Channel, in2: P::Channel, speed: f32)
+where
+    P: Pwm<Duty = u16>,
+{
+    let max_duty = pwm.get_max_duty();
+    let duty = ((1.0 - libm::fabsf(speed).min(1.0)) * f32::from(max_duty)) as u16;
+    if speed >= 0.0 {
+        pwm.set_duty(in1, max_duty);
+        pwm.set_duty(in2, duty);
+    } else {
+        pwm.set_duty(in1, duty);
+        pwm.set_duty(in2, max_duty);
+    }
+}
@@ -0,0 +1,353 @@
+//! Minimal MQTT-SN client for publishing over UDP.
+//!
+//! MQTT-SN is the variant of MQTT for sensor networks. It needs no TCP
+//! connection: each packet is a UDP datagram to a gateway, e.g. the Eclipse
+//! Paho MQTT-SN gateway, which forwards the messages to an MQTT broker. The
+//! packets are small: a one byte length and type, and the topics are
+//! registered once and then published to with a two byte id.
+//!
+//! The client supports what a publishing sensor needs: connecting,
+//! registering topics, publishing with QoS 0, keep-alive pings and the
+//! sleep mode. The datagrams go through the `Transport` trait, e.g. a UDP
+//! socket of the `w5500` module. Requests are sent again when the gateway
+//! does not answer in time, up to the configured number of retries.
+//!
+//! ```ignore
+//! let mut client = Client::new(socket, monotonic::millis).keep_alive(60);
+//! client.connect("bluepill")?;
+//! let topic = client.register("bluepill/temperature")?;
+//! client.publish(topic, b"21.5")?;
+//! loop {
+//!     // Sends a ping when nothing was sent for the keep-alive time.
+//!     client.poll()?;
+//! }
+//! ```
+//!
+//! With `sleep()` the client tells the gateway that it is going to sleep
+//! for some time, instead of sending pings. The gateway keeps the session
+//! and the registered topics. Calling `connect()` again within that time
+//! wakes the client up, and the topic ids stay valid. If the client does
+//! not come back in time, the gateway drops the session.
+//!
+//! The keep-alive and sleep times are far beyond the range of the DWT cycle
+//! counter, so the client takes a millisecond clock like
+//! `monotonic::millis()`.
+
+const CONNECT: u8 = 0x04;
+const CONNACK: u8 = 0x05;
+const REGISTER: u8 = 0x0A;
+const REGACK: u8 = 0x0B;
+const PUBLISH: u8 = 0x0C;
+const PINGREQ: u8 = 0x16;
+const PINGRESP: u8 = 0x17;
+const DISCONNECT: u8 = 0x18;
+const FLAG_CLEAN_SESSION: u8 = 0x04;
+const PROTOCOL_ID: u8 = 0x01;
+/// Longest packet sent or received
+pub const MAX_PACKET_LEN: usize = 128;
+
+/// Datagrams to and from the gateway
+pub trait Transport {
+    type Error;
+    /// Send a datagram to the gateway.
+    fn send(&mut self, packet: &[u8]) -> Result<(), Self::Error>;
+    /// Receive a datagram from the gateway into `buffer`, if one arrived,
+    /// and return its length.
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error>;
+}
+
+/// Reasons of the gateway to reject a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+    Congestion,
+    InvalidTopicId,
+    NotSupported,
+    Other(u8),
+}
+
+impl From<u8> for Rejection {
+    fn from(code: u8) -> Self {
+        match code {
+            1 => Rejection::Congestion,
+            2 => Rejection::InvalidTopicId,
+            3 => Rejection::NotSupported,
+            _ => Rejection::Other(code),
+        }
+    }
+}
+
+/// MQTT-SN errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error<E> {
+    /// Error sending or receiving a datagram
+    Transport(E),
+    /// The gateway did not answer in time
+    Timeout,
+    /// The gateway rejected the request
+    Rejected(Rejection),
+    /// The client is not connected
+    NotConnected,
+    /// The packet does not fit into `MAX_PACKET_LEN`
+    TooLong,
+}
+
+/// State of the client
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Disconnected,
+    Active,
+    Asleep,
+}
+
+/// Packet being built
+struct Packet {
+    bytes: [u8; MAX_PACKET_LEN],
+    len: usize,
+}
+
+impl Packet {
+    /// Build a packet from its type and fields.
+    fn new(msg_type: u8, fields: &[&[u8]]) -> Option<Self> {
+        let mut packet = Packet {
+            bytes: [0; MAX_PACKET_LEN],
+            len: 2,
+        };
+        packet.bytes[1] = msg_type;
+        for field in fields {
+            let end = packet.len + field.len();
+            if end > MAX_PACKET_LEN {
+                return None;
+            }
+            packet.bytes[packet.len..end].copy_from_slice(field);
+            packet.len = end;
+        }
+        Some(packet)
+    }
+
+    fn as_bytes(&mut self) -> &[u8] {
+        self.bytes[0] = self.len as u8;
+        &self.bytes[..self.len]
+    }
+}
+
+/// MQTT-SN client
+pub struct Client<T> {
+    transport: T,
+    millis: fn() -> u32,
+    timeout_ms: u32,
+    retries: u8,
+    keep_alive_s: u16,
+    state: State,
+    msg_id: u16,
+    last_sent_ms: u32,
+    buffer: [u8; MAX_PACKET_LEN],
+}
+
+impl<T: Transport> Client<T> {
+    /// Create a new client. `millis` returns the milliseconds since some
+    /// point in time, e.g. `monotonic::millis`.
+    pub fn new(transport: T, millis: fn() -> u32) -> Self {
+        Client {
+            transport,
+            millis,
+            timeout_ms: 3000,
+            retries: 2,
+            keep_alive_s: 60,
+            state: State::Disconnected,
+            msg_id: 0,
+            last_sent_ms: 0,
+            buffer: [0; MAX_PACKET_LEN],
+        }
+    }
+
+    /// Set the time to wait for an answer. The default is 3 seconds.
+    pub fn timeout_ms(mut self, timeout_ms: u32) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Set how many times a request is sent again. The default is 2.
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Set the keep-alive time in seconds. The default is 60 seconds.
+    pub fn keep_alive(mut self, seconds: u16) -> Self {
+        self.keep_alive_s = seconds;
+        self
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Connect to the gateway. When asleep, wake up and keep the session.
+    pub fn connect(&mut self, client_id: &str) -> Result<(), Error<T::Error>> {
+        let flags = if self.state == State::Asleep {
+            0
+        } else {
+            FLAG_CLEAN_SESSION
+        };
+        let packet = Self::packet(
+            CONNECT,
+            &[
+                &[flags, PROTOCOL_ID],
+                &self.keep_alive_s.to_be_bytes(),
+                client_id.as_bytes(),
+            ],
+        )?;
+        let len = self.request(packet, |answer| answer[1] == CONNACK && answer.len() == 3)?;
+        check(self.buffer[len - 1])?;
+        self.state = State::Active;
+        Ok(())
+    }
+
+    /// Register a topic name and return its id for publishing.
+    pub fn register(&mut self, topic: &str) -> Result<u16, Error<T::Error>> {
+        self.check_active()?;
+        let msg_id = self.next_msg_id();
+        let packet = Self::packet(
+            REGISTER,
+            &[&[0, 0], &msg_id.to_be_bytes(), topic.as_bytes()],
+        )?;
+        self.request(packet, |answer| {
+            answer[1] == REGACK && answer.len() == 7 && answer[4..6] == msg_id.to_be_bytes()
+        })?;
+        check(self.buffer[6])?;
+        Ok(u16::from_be_bytes([self.buffer[2], self.buffer[3]]))
+    }
+
+    /// Publish `payload` to a registered topic with QoS 0, which is not
+    /// acknowledged.
+    pub fn publish(&mut self, topic_id: u16, payload: &[u8]) -> Result<(), Error<T::Error>> {
+        self.check_active()?;
+        // QoS 0 and a normal topic id, and no message id
+        let mut packet = Self::packet(PUBLISH, &[&[0], &topic_id.to_be_bytes(), &[0, 0], payload])?;
+        self.send(packet.as_bytes())
+    }
+
+    /// Answer the pings of the gateway and send a ping when nothing was
+    /// sent for the keep-alive time. Call this regularly while connected.
+    pub fn poll(&mut self) -> Result<(), Error<T::Error>> {
+        if self.state != State::Active {
+            return Ok(());
+        }
+        while let Some(len) = self.receive()? {
+            self.handle_unexpected(len)?;
+        }
+        let elapsed_ms = (self.millis)().wrapping_sub(self.last_sent_ms);
+        if elapsed_ms >= u32::from(self.keep_alive_s) * 1000 {
+            self.ping()?;
+        }
+        Ok(())
+    }
+
+    /// Check that the gateway is still there.
+    pub fn ping(&mut self) -> Result<(), Error<T::Error>> {
+        self.check_active()?;
+        let result = self.request(Self::packet(PINGREQ, &[])?, |answer| answer[1] == PINGRESP);
+        if let Err(Error::Timeout) = result {
+            // The gateway is gone or dropped the session.
+            self.state = State::Disconnected;
+        }
+        result.map(|_| ())
+    }
+
+    /// Tell the gateway that the client sleeps for `seconds`. Connect again
+    /// within that time to wake up.
+    pub fn sleep(&mut self, seconds: u16) -> Result<(), Error<T::Error>> {
+        self.check_active()?;
+        let packet = Self::packet(DISCONNECT, &[&seconds.to_be_bytes()])?;
+        self.request(packet, |answer| answer[1] == DISCONNECT)?;
+        self.state = State::Asleep;
+        Ok(())
+    }
+
+    /// Disconnect from the gateway, which drops the session.
+    pub fn disconnect(&mut self) -> Result<(), Error<T::Error>> {
+        if self.state == State::Disconnected {
+            return Ok(());
+        }
+        let result = self.request(Self::packet(DISCONNECT, &[])?, |answer| {
+            answer[1] == DISCONNECT
+        });
+        self.state = State::Disconnected;
+        result.map(|_| ())
+    }
+
+    /// Destroy the client and return the transport.
+    pub fn destroy(self) -> T {
+        self.transport
+    }
+
+    fn packet(msg_type: u8, fields: &[&[u8]]) -> Result<Packet, Error<T::Error>> {
+        Packet::new(msg_type, fields).ok_or(Error::TooLong)
+    }
+
+    fn check_active(&self) -> Result<(), Error<T::Error>> {
+        match self.state {
+            State::Active => Ok(()),
+            _ => Err(Error::NotConnected),
+        }
+    }
+
+    fn next_msg_id(&mut self) -> u16 {
+        // 0 is not a valid message id.
+        self.msg_id = self.msg_id.wrapping_add(1).max(1);
+        self.msg_id
+    }
+
+    fn send(&mut self, packet: &[u8]) -> Result<(), Error<T::Error>> {
+        self.transport.send(packet).map_err(Error::Transport)?;
+        self.last_sent_ms = (self.millis)();
+        Ok(())
+    }
+
+    /// Receive a packet into the buffer. Datagrams whose length does not
+    /// match their header are dropped.
+    fn receive(&mut self) -> Result<Option<usize>, Error<T::Error>> {
+        match self.transport.receive(&mut self.buffer) {
+            Ok(Some(len)) if len >= 2 && usize::from(self.buffer[0]) == len => Ok(Some(len)),
+            Ok(_) => Ok(None),
+            Err(e) => Err(Error::Transport(e)),
+        }
+    }
+
+    /// Answer a ping of the gateway, ignore anything else.
+    fn handle_unexpected(&mut self, len: usize) -> Result<(), Error<T::Error>> {
+        if self.buffer[1] == PINGREQ && len == 2 {
+            self.send(&[2, PINGRESP])?;
+        }
+        Ok(())
+    }
+
+    /// Send `packet` until an answer for which `is_answer` is true arrives,
+    /// and return the length of the answer in the buffer.
+    fn request<F>(&mut self, mut packet: Packet, is_answer: F) -> Result<usize, Error<T::Error>>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        for _ in 0..=self.retries {
+            self.send(packet.as_bytes())?;
+            let start = (self.millis)();
+            while (self.millis)().wrapping_sub(start) < self.timeout_ms {
+                if let Some(len) = self.receive()? {
+                    if is_answer(&self.buffer[..len]) {
+                        return Ok(len);
+                    }
+                    self.handle_unexpected(len)?;
+                }
+            }
+        }
+        Err(Error::Timeout)
+    }
+}
+
+/// Turn a return code into an error.
+fn check<E>(code: u8) -> Result<(), Error<E>> {
+    match code {
+        0 => Ok(()),
+        _ => Err(Error::Rejected(Rejection::from(code))),
+    }
+}
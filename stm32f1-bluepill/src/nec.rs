@@ -0,0 +1,177 @@
+//! NEC infrared remote control protocol decoder and encoder.
+//!
+//! A NEC frame starts with a 9ms carrier burst followed by a 4.5ms space.
+//! Then 32 bits follow (address, inverted address, command, inverted
+//! command), least significant bit first. Each bit is a 562.5µs burst
+//! followed by a 562.5µs space for a 0 or a 1687.5µs space for a 1. A final
+//! 562.5µs burst ends the frame.
+//! While a button is held, repeat frames are sent every 108ms: a 9ms burst,
+//! a 2.25ms space and a 562.5µs burst.
+//!
+//! The decoder does not care how the pulses are measured. Feed it with the
+//! duration of every burst and space, for example measured between the edges
+//! of the output of an IR receiver module like the TSOP38238. Note that the
+//! output of these modules is low while a burst is received.
+//!
+//! ```ignore
+//! let mut decoder = NecDecoder::new();
+//! // on every edge:
+//! if let Some(event) = decoder.pulse(was_burst, duration_us) {
+//!     rprintln!("{:?}", event);
+//! }
+//! ```
+//!
+//! To transmit, `encode()` returns the duration of every burst and space of
+//! a frame. Send them with a 38kHz carrier during the bursts.
+
+/// Duration of a burst or a space unit in microseconds
+pub const UNIT_US: u32 = 562;
+/// Duration of the leading burst in microseconds
+pub const LEADER_BURST_US: u32 = 9000;
+/// Duration of the space after the leading burst in microseconds
+pub const LEADER_SPACE_US: u32 = 4500;
+/// Duration of the space after the leading burst of a repeat frame
+pub const REPEAT_SPACE_US: u32 = 2250;
+/// Duration of the space for a 1 bit in microseconds
+pub const ONE_SPACE_US: u32 = 1687;
+/// Time between the start of two frames while a button is held
+pub const FRAME_PERIOD_US: u32 = 108_000;
+/// Number of bursts and spaces in a frame
+pub const FRAME_PULSES: usize = 67;
+/// Bursts and spaces of a repeat frame
+pub const REPEAT_PULSES: [u32; 3] = [LEADER_BURST_US, REPEAT_SPACE_US, UNIT_US];
+
+/// Duration of the bursts and spaces of a frame in microseconds, starting
+/// with a burst. Addresses above 0xFF are sent as extended NEC frames.
+pub fn encode(address: u16, command: u8) -> [u32; FRAME_PULSES] {
+    let [address_low, address_high] = if address > 0xFF {
+        address.to_le_bytes()
+    } else {
+        [address as u8, !(address as u8)]
+    };
+    let data = u32::from_le_bytes([address_low, address_high, command, !command]);
+    let mut pulses = [UNIT_US; FRAME_PULSES];
+    pulses[0] = LEADER_BURST_US;
+    pulses[1] = LEADER_SPACE_US;
+    for bit in 0..32 {
+        if data & (1 << bit) != 0 {
+            pulses[3 + bit * 2] = ONE_SPACE_US;
+        }
+    }
+    pulses
+}
+
+/// Decoded frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NecEvent {
+    /// A button was pressed. `address` is 8-bit for standard frames or
+    /// 16-bit for extended NEC frames.
+    Command { address: u16, command: u8 },
+    /// The last button is still held
+    Repeat { address: u16, command: u8 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Leader,
+    Burst,
+    Space,
+    Trailer,
+    RepeatTrailer,
+}
+
+/// NEC protocol decoder
+#[derive(Debug, Clone)]
+pub struct NecDecoder {
+    state: State,
+    data: u32,
+    bits: u8,
+    last: Option<(u16, u8)>,
+}
+
+impl Default for NecDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NecDecoder {
+    /// Create a new decoder
+    pub fn new() -> Self {
+        NecDecoder {
+            state: State::Idle,
+            data: 0,
+            bits: 0,
+            last: None,
+        }
+    }
+
+    /// Feed the duration of a pulse. `burst` is true if the carrier was
+    /// present during the pulse.
+    ///
+    /// Returns an event when a complete frame has been received.
+    pub fn pulse(&mut self, burst: bool, duration_us: u32) -> Option<NecEvent> {
+        let (next, event) = match (self.state, burst) {
+            (_, true) if near(duration_us, LEADER_BURST_US) => (State::Leader, None),
+            (State::Leader, false) if near(duration_us, LEADER_SPACE_US) => {
+                self.data = 0;
+                self.bits = 0;
+                (State::Burst, None)
+            }
+            (State::Leader, false) if near(duration_us, REPEAT_SPACE_US) => {
+                (State::RepeatTrailer, None)
+            }
+            (State::Burst, true) if near(duration_us, UNIT_US) => (State::Space, None),
+            (State::Space, false) if near(duration_us, UNIT_US) => self.bit(false),
+            (State::Space, false) if near(duration_us, ONE_SPACE_US) => self.bit(true),
+            (State::Trailer, true) if near(duration_us, UNIT_US) => {
+                let event = self.frame();
+                self.last = event;
+                (
+                    State::Idle,
+                    event.map(|(address, command)| NecEvent::Command { address, command }),
+                )
+            }
+            (State::RepeatTrailer, true) if near(duration_us, UNIT_US) => (
+                State::Idle,
+                self.last
+                    .map(|(address, command)| NecEvent::Repeat { address, command }),
+            ),
+            _ => (State::Idle, None),
+        };
+        self.state = next;
+        event
+    }
+
+    fn bit(&mut self, one: bool) -> (State, Option<NecEvent>) {
+        if one {
+            self.data |= 1 << self.bits;
+        }
+        self.bits += 1;
+        if self.bits == 32 {
+            (State::Trailer, None)
+        } else {
+            (State::Burst, None)
+        }
+    }
+
+    /// Validate the received data and return the address and command.
+    fn frame(&self) -> Option<(u16, u8)> {
+        let [address_low, address_high, command, command_inverted] = self.data.to_le_bytes();
+        if command != !command_inverted {
+            return None;
+        }
+        let address = if address_high == !address_low {
+            u16::from(address_low)
+        } else {
+            u16::from_le_bytes([address_low, address_high])
+        };
+        Some((address, command))
+    }
+}
+
+/// Whether `duration` is within 25% of `expected`
+fn near(duration: u32, expected: u32) -> bool {
+    duration > expected * 3 / 4 && duration < expected * 5 / 4
+}
@@ -0,0 +1,155 @@
+//! 1-Wire master for sensors like the DS18B20 temperature sensor.
+//!
+//! 1-Wire uses a single open-drain data line with a pull-up resistor
+//! (typically 4.7K). The master starts every transaction with a reset pulse,
+//! to which the devices answer with a presence pulse. Every bit is a time
+//! slot started by the master pulling the line low: for a 1 it releases the
+//! line after a few microseconds, for a 0 it holds it low for the whole slot.
+//! To read a bit the master starts a slot and samples the line shortly
+//! after: a device sending a 0 holds it low.
+//!
+//! The bits are timed with `DwtDelay`, so the DWT cycle counter must be
+//! enabled.
+//! Interrupts are disabled during the reset pulse and every time slot.
+//!
+//! The pin must be an open-drain output which can also be read, like
+//! `into_open_drain_output()` of the HAL.
+//!
+//! ```ignore
+//! let mut bus = OneWire::new(pin, clocks.sysclk().0);
+//! bus.reset()?;
+//! bus.write_byte(SKIP_ROM);
+//! bus.write_byte(0x44);
+//! ```
+//!
+//! Only one device on the bus is supported: it is addressed with `SKIP_ROM`.
+
+use crate::delay::DwtDelay;
+use embedded_hal::{
+    blocking::delay::DelayUs,
+    digital::v2::{InputPin, OutputPin},
+};
+
+/// Address all devices on the bus at once
+pub const SKIP_ROM: u8 = 0xCC;
+
+/// 1-Wire errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// No device answered the reset pulse.
+    NoPresence,
+    /// The line is held low, e.g. by a short circuit.
+    BusLow,
+    /// The CRC of the received data does not match.
+    Crc,
+}
+
+/// 1-Wire master
+pub struct OneWire<P> {
+    pin: P,
+    delay: DwtDelay,
+}
+
+impl<P: OutputPin + InputPin> OneWire<P> {
+    /// Create a new master. `sysclk_hz` is used to time the bits.
+    pub fn new(mut pin: P, sysclk_hz: u32) -> Self {
+        pin.set_high().ok();
+        OneWire {
+            pin,
+            delay: DwtDelay::new(sysclk_hz),
+        }
+    }
+
+    /// Send a reset pulse and wait for the presence pulse.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        if self.pin.is_low().unwrap_or(true) {
+            return Err(Error::BusLow);
+        }
+        let present = cortex_m::interrupt::free(|_| {
+            self.pin.set_low().ok();
+            self.wait_us(480);
+            self.pin.set_high().ok();
+            self.wait_us(70);
+            self.pin.is_low().unwrap_or(false)
+        });
+        self.wait_us(410);
+        if present {
+            Ok(())
+        } else {
+            Err(Error::NoPresence)
+        }
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        for i in 0..8 {
+            self.write_bit(byte & (1 << i) != 0);
+        }
+    }
+
+    pub fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |byte, i| byte | (u8::from(self.read_bit()) << i))
+    }
+
+    /// Read `data` and check the CRC in its last byte.
+    pub fn read_checked(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        for byte in data.iter_mut() {
+            *byte = self.read_byte();
+        }
+        // The CRC over the data including its CRC is 0.
+        if crc8(data) == 0 {
+            Ok(())
+        } else {
+            Err(Error::Crc)
+        }
+    }
+
+    /// Release the pin.
+    pub fn destroy(self) -> P {
+        self.pin
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        cortex_m::interrupt::free(|_| {
+            self.pin.set_low().ok();
+            if bit {
+                self.wait_us(6);
+                self.pin.set_high().ok();
+                self.wait_us(64);
+            } else {
+                self.wait_us(60);
+                self.pin.set_high().ok();
+                self.wait_us(10);
+            }
+        });
+    }
+
+    fn read_bit(&mut self) -> bool {
+        cortex_m::interrupt::free(|_| {
+            self.pin.set_low().ok();
+            self.wait_us(6);
+            self.pin.set_high().ok();
+            self.wait_us(9);
+            let bit = self.pin.is_high().unwrap_or(false);
+            self.wait_us(55);
+            bit
+        })
+    }
+
+    fn wait_us(&mut self, us: u16) {
+        self.delay.delay_us(us);
+    }
+}
+
+/// Dallas/Maxim CRC-8 (polynomial x^8 + x^5 + x^4 + 1, reflected) as used
+/// for the ROM codes and the scratchpad of 1-Wire devices.
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8C
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
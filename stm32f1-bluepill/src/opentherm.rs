@@ -0,0 +1,235 @@
+//! OpenTherm frames and their Manchester encoding.
+//!
+//! OpenTherm connects a room thermostat (the master) with a boiler (the
+//! slave) over two wires. The master sends a request at least once a second
+//! and the boiler answers within 800ms. Both are 32-bit frames: an even
+//! parity bit, the message type, the data id and a 16-bit value. On the
+//! wire, a frame is framed by a start and a stop bit (both 1) and sent at
+//! 1000 bit/s in Manchester code: every bit is split into two halves of
+//! 500µs with opposite levels, active and then idle for a 1, idle and then
+//! active for a 0. The line is idle between frames.
+//!
+//! The levels need an interface circuit, as the master signals with the
+//! voltage and the boiler with the current on the line. `encode()` returns
+//! the level of every half bit of a request, so it can be sent by a timer
+//! interrupt every `HALF_BIT_US`. The decoder does not care how the levels
+//! are measured. Feed it with the duration of every period of constant
+//! level, like the `NecDecoder`:
+//!
+//! ```ignore
+//! let mut decoder = Decoder::new();
+//! // on every edge:
+//! if let Some(frame) = decoder.pulse(was_active, duration_us) {
+//!     rprintln!("{:?}", frame);
+//! }
+//! ```
+
+/// Duration of half a bit in microseconds
+pub const HALF_BIT_US: u32 = 500;
+/// Number of half bits of a frame with its start and stop bits
+pub const FRAME_HALF_BITS: usize = 68;
+
+/// Status flags (read): master flags in the high byte, boiler flags in the
+/// low byte
+pub const STATUS: u8 = 0;
+/// Control setpoint (write): water temperature in °C, f8.8
+pub const CONTROL_SETPOINT: u8 = 1;
+/// Relative modulation level (read) in %, f8.8
+pub const RELATIVE_MODULATION: u8 = 17;
+/// Boiler water temperature (read) in °C, f8.8
+pub const BOILER_TEMPERATURE: u8 = 25;
+/// Return water temperature (read) in °C, f8.8
+pub const RETURN_TEMPERATURE: u8 = 28;
+
+/// Central heating enable flag of the master status
+pub const MASTER_CH_ENABLE: u16 = 1 << 8;
+/// Fault flag of the boiler status
+pub const SLAVE_FAULT: u16 = 1 << 0;
+/// Central heating active flag of the boiler status
+pub const SLAVE_CH_ACTIVE: u16 = 1 << 1;
+/// Flame on flag of the boiler status
+pub const SLAVE_FLAME: u16 = 1 << 3;
+
+/// Message type of a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageType {
+    /// Request of the master to read a value
+    ReadData = 0,
+    /// Request of the master to write a value
+    WriteData = 1,
+    /// Request of the master with a value it could not determine
+    InvalidData = 2,
+    /// Answer of the boiler with the value read
+    ReadAck = 4,
+    /// Answer of the boiler to a write request
+    WriteAck = 5,
+    /// The boiler has no valid value for the data id
+    DataInvalid = 6,
+    /// The boiler does not know the data id
+    UnknownDataId = 7,
+}
+
+/// OpenTherm frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub message_type: MessageType,
+    pub data_id: u8,
+    pub value: u16,
+}
+
+impl Frame {
+    /// Request to read the value of `data_id`
+    pub fn read(data_id: u8, value: u16) -> Self {
+        Frame {
+            message_type: MessageType::ReadData,
+            data_id,
+            value,
+        }
+    }
+
+    /// Request to write `value` to `data_id`
+    pub fn write(data_id: u8, value: u16) -> Self {
+        Frame {
+            message_type: MessageType::WriteData,
+            data_id,
+            value,
+        }
+    }
+
+    /// The 32 bits of the frame, with the parity bit.
+    pub fn to_bits(&self) -> u32 {
+        let bits = (self.message_type as u32) << 28
+            | u32::from(self.data_id) << 16
+            | u32::from(self.value);
+        bits | (bits.count_ones() & 1) << 31
+    }
+
+    /// `None` if the parity or the message type is invalid.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        if bits.count_ones() & 1 != 0 {
+            return None;
+        }
+        let message_type = match (bits >> 28) & 0b111 {
+            0 => MessageType::ReadData,
+            1 => MessageType::WriteData,
+            2 => MessageType::InvalidData,
+            4 => MessageType::ReadAck,
+            5 => MessageType::WriteAck,
+            6 => MessageType::DataInvalid,
+            7 => MessageType::UnknownDataId,
+            _ => return None,
+        };
+        Some(Frame {
+            message_type,
+            data_id: (bits >> 16) as u8,
+            value: bits as u16,
+        })
+    }
+
+    /// The value as a f8.8 fixed point number, e.g. a temperature.
+    pub fn value_f88(&self) -> f32 {
+        f32::from(self.value as i16) / 256.0
+    }
+}
+
+/// f8.8 fixed point value of a number, e.g. of a setpoint
+pub fn f88(value: f32) -> u16 {
+    (value * 256.0) as i16 as u16
+}
+
+/// Levels of the half bits of a frame with its start and stop bits. `true`
+/// is active.
+pub fn encode(frame: &Frame) -> [bool; FRAME_HALF_BITS] {
+    let bits = 1 << 33 | u64::from(frame.to_bits()) << 1 | 1;
+    let mut levels = [false; FRAME_HALF_BITS];
+    for (i, pair) in levels.chunks_mut(2).enumerate() {
+        let one = bits & 1 << (33 - i) != 0;
+        pair[0] = one;
+        pair[1] = !one;
+    }
+    levels
+}
+
+/// Manchester decoder of OpenTherm frames
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    bits: u64,
+    half_bits: usize,
+    first_half: bool,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    /// Create a new decoder
+    pub fn new() -> Self {
+        Decoder {
+            bits: 0,
+            half_bits: 0,
+            first_half: false,
+        }
+    }
+
+    /// Feed the duration of a period of constant level. `active` is the
+    /// level during the period.
+    ///
+    /// Returns a frame when a complete and valid frame has been received.
+    pub fn pulse(&mut self, active: bool, duration_us: u32) -> Option<Frame> {
+        // The start bit begins with the first active half bit.
+        if self.half_bits == 0 && !active {
+            return None;
+        }
+        let half_bits = if near(duration_us, HALF_BIT_US) {
+            1
+        } else if near(duration_us, 2 * HALF_BIT_US) {
+            2
+        } else {
+            self.half_bits = 0;
+            return None;
+        };
+        for _ in 0..half_bits {
+            if let Some(frame) = self.half_bit(active) {
+                return Some(frame);
+            }
+            if self.half_bits == 0 {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn half_bit(&mut self, active: bool) -> Option<Frame> {
+        if self.half_bits == 0 {
+            self.bits = 0;
+        }
+        if self.half_bits % 2 == 0 {
+            self.first_half = active;
+        } else if active == self.first_half {
+            // No transition in the middle of the bit
+            self.half_bits = 0;
+            return None;
+        } else {
+            self.bits = self.bits << 1 | u64::from(self.first_half);
+        }
+        self.half_bits += 1;
+        // The second half of the stop bit is idle like the line after the
+        // frame, so the frame ends with the first half.
+        if self.half_bits < FRAME_HALF_BITS - 1 {
+            return None;
+        }
+        self.half_bits = 0;
+        if !active || self.bits >> 32 != 1 {
+            return None;
+        }
+        Frame::from_bits(self.bits as u32)
+    }
+}
+
+/// Whether `duration` is within 25% of `expected`
+fn near(duration: u32, expected: u32) -> bool {
+    duration > expected * 3 / 4 && duration < expected * 5 / 4
+}
@@ -0,0 +1,228 @@
+//! Panic handler which shows the panic message on the SSD1306 display.
+//!
+//! Without a debugger attached a panic just stops the example, with the
+//! message going to an RTT channel nobody reads. With the `panic-display`
+//! feature the panic handler of this module also shows the message and its
+//! location on the display and then blinks the LED on PC13 rapidly, so that
+//! failures in the field can be seen.
+//!
+//! The examples register the display once it is initialized:
+//!
+//! ```ignore
+//! use driver_examples_bluepill::panic_display::{self, Bus};
+//!
+//! disp.init().unwrap();
+//! panic_display::register(Bus::I2c1);
+//! ```
+//!
+//! The display driver belongs to the example, so the handler does not use
+//! it. It takes the I2C peripheral over as it is, resets it keeping the
+//! clock settings of the example, and talks to the display through a driver
+//! of its own, at the default address. This also works when the panic
+//! happened in the middle of a transfer. Only 128x64 displays are
+//! supported.
+//!
+//! Without the feature this module links the handler of panic-rtt-target,
+//! so the examples use it in both cases instead of that crate:
+//!
+//! ```ignore
+//! use driver_examples_bluepill::panic_display as _;
+//! ```
+
+use core::sync::atomic::{AtomicU8, Ordering};
+#[cfg(not(feature = "panic-display"))]
+use panic_rtt_target as _;
+
+/// I2C peripheral the display is connected to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bus {
+    I2c1 = 1,
+    I2c2 = 2,
+}
+
+/// The registered bus, 0 for none
+static BUS: AtomicU8 = AtomicU8::new(0);
+
+/// Show the panic message on the display connected to `bus`. Call it after
+/// initializing the display.
+pub fn register(bus: Bus) {
+    BUS.store(bus as u8, Ordering::Relaxed);
+}
+
+#[cfg(feature = "panic-display")]
+mod handler {
+    use super::BUS;
+    use core::{
+        fmt::{self, Write},
+        panic::PanicInfo,
+        sync::atomic::Ordering,
+    };
+    use embedded_graphics::{
+        fonts::{Font6x8, Text},
+        pixelcolor::BinaryColor,
+        prelude::*,
+        style::TextStyleBuilder,
+    };
+    use embedded_hal::blocking::i2c;
+    use heapless::String;
+    use rtt_target::{ChannelMode, UpChannel};
+    use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+    use stm32f1xx_hal::pac::{self, i2c1};
+
+    /// Characters per line and lines of the 6x8 font on the display
+    const COLUMNS: usize = 21;
+    const ROWS: usize = 8;
+    /// Status register polls before a transfer is given up
+    const TIMEOUT: u32 = 100_000;
+    /// About 10 blinks per second at 72MHz
+    const BLINK_CYCLES: u32 = 3_600_000;
+
+    #[inline(never)]
+    #[panic_handler]
+    fn panic(info: &PanicInfo) -> ! {
+        cortex_m::interrupt::disable();
+
+        // Like panic-rtt-target: take the channel over, wherever the panic
+        // happened.
+        if let Some(mut channel) = unsafe { UpChannel::conjure(0) } {
+            channel.set_mode(ChannelMode::BlockIfFull);
+            writeln!(channel, "{}", info).ok();
+        }
+
+        let mut message = Truncated(String::new());
+        write!(message, "{}", info).ok();
+        let regs: Option<&'static i2c1::RegisterBlock> = match BUS.load(Ordering::Relaxed) {
+            1 => Some(unsafe { &*pac::I2C1::ptr() }),
+            2 => Some(unsafe { &*pac::I2C2::ptr() }),
+            _ => None,
+        };
+        if let Some(regs) = regs {
+            show(RawI2c::take_over(regs), &message.0).ok();
+        }
+        blink()
+    }
+
+    /// Draw the message, wrapped into lines, below a title.
+    fn show(i2c: RawI2c, message: &str) -> Result<(), DisplayError> {
+        let interface = I2CDIBuilder::new().init(i2c);
+        let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
+        disp.init()?;
+        let text_style = TextStyleBuilder::new(Font6x8)
+            .text_color(BinaryColor::On)
+            .build();
+        Text::new("PANIC", Point::zero())
+            .into_styled(text_style)
+            .draw(&mut disp)
+            .ok();
+
+        let mut rest = message;
+        for row in 1..ROWS {
+            if rest.is_empty() {
+                break;
+            }
+            let line_end = rest.find('\n').unwrap_or_else(|| rest.len());
+            let end = rest[..line_end]
+                .char_indices()
+                .nth(COLUMNS)
+                .map_or(line_end, |(i, _)| i);
+            Text::new(&rest[..end], Point::new(0, row as i32 * 8))
+                .into_styled(text_style)
+                .draw(&mut disp)
+                .ok();
+            rest = rest[end..].strip_prefix('\n').unwrap_or(&rest[end..]);
+        }
+        disp.flush()
+    }
+
+    /// Blink the LED on PC13 forever.
+    fn blink() -> ! {
+        let (rcc, gpioc) = unsafe { (&*pac::RCC::ptr(), &*pac::GPIOC::ptr()) };
+        rcc.apb2enr.modify(|_, w| w.iopcen().set_bit());
+        gpioc
+            .crh
+            .modify(|_, w| w.mode13().output2().cnf13().push_pull());
+        loop {
+            gpioc.bsrr.write(|w| w.br13().set_bit());
+            cortex_m::asm::delay(BLINK_CYCLES);
+            gpioc.bsrr.write(|w| w.bs13().set_bit());
+            cortex_m::asm::delay(BLINK_CYCLES);
+        }
+    }
+
+    /// Keeps what fits into the buffer.
+    struct Truncated(String<{ COLUMNS * (ROWS - 1) }>);
+
+    impl Write for Truncated {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            for c in s.chars() {
+                if self.0.push(c).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Minimal I2C master on the registers of a peripheral set up before
+    struct RawI2c {
+        regs: &'static i2c1::RegisterBlock,
+    }
+
+    impl RawI2c {
+        /// Reset the peripheral, keeping its clock settings, and enable it
+        /// without interrupts or DMA.
+        fn take_over(regs: &'static i2c1::RegisterBlock) -> Self {
+            let freq = regs.cr2.read().freq().bits();
+            let ccr = regs.ccr.read().bits();
+            let trise = regs.trise.read().bits();
+            regs.cr1.write(|w| w.swrst().set_bit());
+            regs.cr1.write(|w| w.swrst().clear_bit());
+            unsafe {
+                regs.cr2.write(|w| w.freq().bits(freq));
+                regs.ccr.write(|w| w.bits(ccr));
+                regs.trise.write(|w| w.bits(trise));
+            }
+            regs.cr1.write(|w| w.pe().set_bit());
+            RawI2c { regs }
+        }
+
+        /// Wait until `done` or an error.
+        fn wait(&self, done: impl Fn(&i2c1::sr1::R) -> bool) -> Result<(), ()> {
+            for _ in 0..TIMEOUT {
+                let status = self.regs.sr1.read();
+                if status.af().bit_is_set() || status.berr().bit_is_set() {
+                    return Err(());
+                }
+                if done(&status) {
+                    return Ok(());
+                }
+            }
+            Err(())
+        }
+
+        fn transfer(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+            self.regs.cr1.modify(|_, w| w.start().set_bit());
+            self.wait(|s| s.sb().bit_is_set())?;
+            self.regs.dr.write(|w| w.dr().bits(address << 1));
+            self.wait(|s| s.addr().bit_is_set())?;
+            // Reading SR2 after SR1 clears ADDR.
+            self.regs.sr2.read();
+            for byte in bytes {
+                self.wait(|s| s.tx_e().bit_is_set())?;
+                self.regs.dr.write(|w| w.dr().bits(*byte));
+            }
+            self.wait(|s| s.btf().bit_is_set())
+        }
+    }
+
+    impl i2c::Write for RawI2c {
+        type Error = ();
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+            let result = self.transfer(address, bytes);
+            self.regs.cr1.modify(|_, w| w.stop().set_bit());
+            self.regs.sr1.modify(|_, w| w.af().clear_bit());
+            result
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Proportional-integral (PI) controller.
+//!
+//! The output is limited to a range. While the output is saturated the
+//! integral does not grow any further (anti-windup), so that the controller
+//! reacts right away when the error changes sign.
+//!
+//! ```ignore
+//! let mut pi = PiController::new(0.0005, 0.0002).limits(-0.3, 0.3);
+//! loop {
+//!     let correction = pi.update(target_rpm, measured_rpm, 0.5);
+//!     // ...
+//! }
+//! ```
+
+/// PI controller
+#[derive(Debug, Clone)]
+pub struct PiController {
+    kp: f32,
+    ki: f32,
+    min: f32,
+    max: f32,
+    integral: f32,
+}
+
+impl PiController {
+    /// Create a controller with the given gains. The output is not limited.
+    pub fn new(kp: f32, ki: f32) -> Self {
+        PiController {
+            kp,
+            ki,
+            min: f32::MIN,
+            max: f32::MAX,
+            integral: 0.0,
+        }
+    }
+
+    /// Limit the output to [`min`, `max`].
+    pub fn limits(mut self, min: f32, max: f32) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    /// Calculate the output for a new measurement taken `dt_s` seconds after
+    /// the previous one.
+    pub fn update(&mut self, setpoint: f32, measurement: f32, dt_s: f32) -> f32 {
+        let error = setpoint - measurement;
+        let proportional = self.kp * error;
+        let integral = self.integral + self.ki * error * dt_s;
+        let output = proportional + integral;
+        if output > self.max {
+            // Only let the integral move back into the range.
+            self.integral = self.integral.min(integral);
+            self.max
+        } else if output < self.min {
+            self.integral = self.integral.max(integral);
+            self.min
+        } else {
+            self.integral = integral;
+            output
+        }
+    }
+
+    /// Forget the accumulated error.
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+    }
+}
@@ -0,0 +1,94 @@
+//! Proportional-integral-derivative (PID) controller.
+//!
+//! Like the `PiController`, the output is limited to a range and the
+//! integral does not grow any further while the output is saturated
+//! (anti-windup). The derivative is taken from the measurement rather than
+//! from the error, so that changing the setpoint does not cause a kick.
+//!
+//! ```ignore
+//! let mut pid = PidController::new(0.08, 0.5, 0.002).limits(-1.0, 1.0);
+//! loop {
+//!     let output = pid.update(0.0, angle, 0.005);
+//!     // ...
+//! }
+//! ```
+//!
+//! The gains can be changed while running with `set_gains()`, for example
+//! to tune a controller from a console.
+
+/// PID controller
+#[derive(Debug, Clone)]
+pub struct PidController {
+    kp: f32,
+    ki: f32,
+    kd: f32,
+    min: f32,
+    max: f32,
+    integral: f32,
+    last_measurement: Option<f32>,
+}
+
+impl PidController {
+    /// Create a controller with the given gains. The output is not limited.
+    pub fn new(kp: f32, ki: f32, kd: f32) -> Self {
+        PidController {
+            kp,
+            ki,
+            kd,
+            min: f32::MIN,
+            max: f32::MAX,
+            integral: 0.0,
+            last_measurement: None,
+        }
+    }
+
+    /// Limit the output to [`min`, `max`].
+    pub fn limits(mut self, min: f32, max: f32) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    /// Current gains: (kp, ki, kd)
+    pub fn gains(&self) -> (f32, f32, f32) {
+        (self.kp, self.ki, self.kd)
+    }
+
+    /// Change the gains. The accumulated integral is kept.
+    pub fn set_gains(&mut self, kp: f32, ki: f32, kd: f32) {
+        self.kp = kp;
+        self.ki = ki;
+        self.kd = kd;
+    }
+
+    /// Calculate the output for a new measurement taken `dt_s` seconds after
+    /// the previous one.
+    pub fn update(&mut self, setpoint: f32, measurement: f32, dt_s: f32) -> f32 {
+        let error = setpoint - measurement;
+        let proportional = self.kp * error;
+        let derivative = match self.last_measurement {
+            Some(last) => -self.kd * (measurement - last) / dt_s,
+            None => 0.0,
+        };
+        self.last_measurement = Some(measurement);
+        let integral = self.integral + self.ki * error * dt_s;
+        let output = proportional + integral + derivative;
+        if output > self.max {
+            // Only let the integral move back into the range.
+            self.integral = self.integral.min(integral);
+            self.max
+        } else if output < self.min {
+            self.integral = self.integral.max(integral);
+            self.min
+        } else {
+            self.integral = integral;
+            output
+        }
+    }
+
+    /// Forget the accumulated error and the last measurement.
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last_measurement = None;
+    }
+}
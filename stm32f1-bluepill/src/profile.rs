@@ -0,0 +1,115 @@
+//! Simple run-time profiling using the DWT cycle counter.
+//!
+//! Wrap the code to be measured in a `Probe` and print a report every now
+//! and then to see where the loop time goes:
+//!
+//! ```ignore
+//! let mut cp = cortex_m::Peripherals::take().unwrap();
+//! profile::enable(&mut cp.DCB, &mut cp.DWT);
+//! let mut flush = Probe::new("flush");
+//! loop {
+//!     flush.measure(|| disp.flush()).unwrap();
+//!     profile::report(&mut [&mut flush], clocks.sysclk().0);
+//! }
+//! ```
+//!
+//! Everything here compiles to nothing unless the `profile` feature is
+//! enabled, so the probes can stay in the examples. Run with for example:
+//! `cargo embed --example ccs811-gas-voc-display-bp --features profile`
+
+use cortex_m::peripheral::{DCB, DWT};
+
+/// Enable the DWT cycle counter.
+pub fn enable(dcb: &mut DCB, dwt: &mut DWT) {
+    if cfg!(feature = "profile") {
+        dcb.enable_trace();
+        dwt.enable_cycle_counter();
+    }
+}
+
+/// Accumulates the duration of a repeatedly measured operation.
+#[derive(Debug)]
+pub struct Probe {
+    name: &'static str,
+    count: u32,
+    total: u64,
+    min: u32,
+    max: u32,
+}
+
+impl Probe {
+    /// Create a new probe. The name is printed in the report.
+    pub const fn new(name: &'static str) -> Self {
+        Probe {
+            name,
+            count: 0,
+            total: 0,
+            min: u32::MAX,
+            max: 0,
+        }
+    }
+
+    /// Run `f` and record how many cycles it took.
+    pub fn measure<T, F: FnOnce() -> T>(&mut self, f: F) -> T {
+        if cfg!(feature = "profile") {
+            let start = DWT::get_cycle_count();
+            let result = f();
+            self.record(DWT::get_cycle_count().wrapping_sub(start));
+            result
+        } else {
+            f()
+        }
+    }
+
+    /// Record a measurement of `cycles` cycles.
+    pub fn record(&mut self, cycles: u32) {
+        self.count = self.count.saturating_add(1);
+        self.total = self.total.saturating_add(u64::from(cycles));
+        self.min = self.min.min(cycles);
+        self.max = self.max.max(cycles);
+    }
+
+    /// Forget all measurements taken so far.
+    pub fn reset(&mut self) {
+        *self = Probe::new(self.name);
+    }
+
+    /// Number of measurements taken since the last reset.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Minimum, average and maximum cycles since the last reset.
+    pub fn stats(&self) -> Option<(u32, u32, u32)> {
+        if self.count == 0 {
+            None
+        } else {
+            let avg = (self.total / u64::from(self.count)) as u32;
+            Some((self.min, avg, self.max))
+        }
+    }
+}
+
+/// Print the statistics of all probes through RTT and reset them.
+///
+/// `sysclk_hz` is used to convert the cycles into microseconds.
+pub fn report(probes: &mut [&mut Probe], sysclk_hz: u32) {
+    if !cfg!(feature = "profile") {
+        return;
+    }
+    let cycles_per_us = (sysclk_hz / 1_000_000).max(1);
+    crate::log_info!("profile (min/avg/max us):");
+    for probe in probes.iter_mut() {
+        if let Some((min, avg, max)) = probe.stats() {
+            crate::log_info!(
+                "  {:<12} {:>8} {:>8} {:>8} ({} runs)",
+                probe.name,
+                min / cycles_per_us,
+                avg / cycles_per_us,
+                max / cycles_per_us,
+                probe.count
+            );
+        }
+        probe.reset();
+    }
+}
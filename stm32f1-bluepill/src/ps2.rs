@@ -0,0 +1,229 @@
+//! PS/2 keyboard frames and scan code translation.
+//!
+//! A PS/2 keyboard sends every byte as a frame of 11 bits on its DATA line:
+//! a start bit (0), 8 data bits (least significant first), an odd parity
+//! bit and a stop bit (1). It drives the CLK line too, at 10 to 16.7kHz,
+//! and the data is valid on the falling edges. Read the DATA line on every
+//! falling edge of CLK, e.g. in an EXTI interrupt, and feed it into the
+//! `Ps2Decoder`. If the clock stops for longer than `BIT_TIMEOUT_US` in the
+//! middle of a frame, `reset()` the decoder, so that it catches up with the
+//! next frame.
+//!
+//! The bytes are scan codes (set 2), one or more per key press and
+//! release. `Keyboard` keeps track of the shift and caps lock keys and
+//! turns them into ASCII for a US layout:
+//!
+//! ```ignore
+//! // on every falling edge of CLK:
+//! if let Some(Ok(code)) = decoder.bit(data.is_high().unwrap()) {
+//!     if let Some(ascii) = keyboard.scan_code(code) {
+//!         // ...
+//!     }
+//! }
+//! ```
+//!
+//! The keyboard needs 5V, but the lines are open collector, so they can be
+//! connected to 5V tolerant pins. Sending commands to the keyboard, e.g.
+//! to switch its LEDs, is not supported.
+
+/// Longest time between two clock edges within a frame
+pub const BIT_TIMEOUT_US: u32 = 1000;
+
+/// Enter key
+pub const ENTER: u8 = b'\n';
+/// Backspace key
+pub const BACKSPACE: u8 = 0x08;
+/// Escape key
+pub const ESCAPE: u8 = 0x1B;
+
+const RELEASE_PREFIX: u8 = 0xF0;
+const EXTENDED_PREFIX: u8 = 0xE0;
+const LEFT_SHIFT: u8 = 0x12;
+const RIGHT_SHIFT: u8 = 0x59;
+const CAPS_LOCK: u8 = 0x58;
+
+/// PS/2 frame error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The parity bit is wrong
+    Parity,
+    /// The stop bit is not 1
+    Framing,
+}
+
+/// PS/2 frame decoder
+#[derive(Debug, Clone, Default)]
+pub struct Ps2Decoder {
+    frame: u16,
+    bits: u8,
+}
+
+impl Ps2Decoder {
+    /// Create a new decoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the level of the DATA line at a falling edge of CLK. Returns the
+    /// byte received once the frame is complete.
+    pub fn bit(&mut self, data: bool) -> Option<Result<u8, Error>> {
+        // Wait for a start bit.
+        if self.bits == 0 && data {
+            return None;
+        }
+        self.frame |= u16::from(data) << self.bits;
+        self.bits += 1;
+        if self.bits < 11 {
+            return None;
+        }
+        let frame = self.frame;
+        self.reset();
+        let byte = (frame >> 1) as u8;
+        if frame & (1 << 10) == 0 {
+            Some(Err(Error::Framing))
+        } else if (byte.count_ones() + u32::from(frame >> 9 & 1)) % 2 != 1 {
+            Some(Err(Error::Parity))
+        } else {
+            Some(Ok(byte))
+        }
+    }
+
+    /// Drop the bits of an incomplete frame.
+    pub fn reset(&mut self) {
+        self.frame = 0;
+        self.bits = 0;
+    }
+}
+
+/// Scan code set 2 to ASCII translation for a US layout
+#[derive(Debug, Clone, Default)]
+pub struct Keyboard {
+    release: bool,
+    extended: bool,
+    shift: [bool; 2],
+    caps_lock: bool,
+}
+
+impl Keyboard {
+    /// Create a new keyboard with the caps lock off
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the caps lock is on
+    pub fn caps_lock(&self) -> bool {
+        self.caps_lock
+    }
+
+    /// Add a scan code. Returns the character of a key pressed, including
+    /// `ENTER`, `BACKSPACE` and `ESCAPE`. Other keys are ignored.
+    pub fn scan_code(&mut self, code: u8) -> Option<u8> {
+        match code {
+            RELEASE_PREFIX => {
+                self.release = true;
+                return None;
+            }
+            EXTENDED_PREFIX => {
+                self.extended = true;
+                return None;
+            }
+            _ => (),
+        }
+        let release = core::mem::replace(&mut self.release, false);
+        let extended = core::mem::replace(&mut self.extended, false);
+        match (code, extended) {
+            (LEFT_SHIFT, false) => self.shift[0] = !release,
+            (RIGHT_SHIFT, false) => self.shift[1] = !release,
+            (CAPS_LOCK, false) if !release => self.caps_lock = !self.caps_lock,
+            // Keypad enter and slash
+            (0x5A, true) if !release => return Some(ENTER),
+            (0x4A, true) if !release => return Some(b'/'),
+            (_, false) if !release => {
+                let (normal, shifted) = characters(code)?;
+                let shift = self.shift[0] || self.shift[1];
+                let shift = if normal.is_ascii_lowercase() {
+                    shift != self.caps_lock
+                } else {
+                    shift
+                };
+                return Some(if shift { shifted } else { normal });
+            }
+            _ => (),
+        }
+        None
+    }
+}
+
+/// Characters of a key without and with shift
+fn characters(code: u8) -> Option<(u8, u8)> {
+    Some(match code {
+        0x0D => (b'\t', b'\t'),
+        0x0E => (b'`', b'~'),
+        0x15 => (b'q', b'Q'),
+        0x16 => (b'1', b'!'),
+        0x1A => (b'z', b'Z'),
+        0x1B => (b's', b'S'),
+        0x1C => (b'a', b'A'),
+        0x1D => (b'w', b'W'),
+        0x1E => (b'2', b'@'),
+        0x21 => (b'c', b'C'),
+        0x22 => (b'x', b'X'),
+        0x23 => (b'd', b'D'),
+        0x24 => (b'e', b'E'),
+        0x25 => (b'4', b'$'),
+        0x26 => (b'3', b'#'),
+        0x29 => (b' ', b' '),
+        0x2A => (b'v', b'V'),
+        0x2B => (b'f', b'F'),
+        0x2C => (b't', b'T'),
+        0x2D => (b'r', b'R'),
+        0x2E => (b'5', b'%'),
+        0x31 => (b'n', b'N'),
+        0x32 => (b'b', b'B'),
+        0x33 => (b'h', b'H'),
+        0x34 => (b'g', b'G'),
+        0x35 => (b'y', b'Y'),
+        0x36 => (b'6', b'^'),
+        0x3A => (b'm', b'M'),
+        0x3B => (b'j', b'J'),
+        0x3C => (b'u', b'U'),
+        0x3D => (b'7', b'&'),
+        0x3E => (b'8', b'*'),
+        0x41 => (b',', b'<'),
+        0x42 => (b'k', b'K'),
+        0x43 => (b'i', b'I'),
+        0x44 => (b'o', b'O'),
+        0x45 => (b'0', b')'),
+        0x46 => (b'9', b'('),
+        0x49 => (b'.', b'>'),
+        0x4A => (b'/', b'?'),
+        0x4B => (b'l', b'L'),
+        0x4C => (b';', b':'),
+        0x4D => (b'p', b'P'),
+        0x4E => (b'-', b'_'),
+        0x52 => (b'\'', b'"'),
+        0x54 => (b'[', b'{'),
+        0x55 => (b'=', b'+'),
+        0x5A => (ENTER, ENTER),
+        0x5B => (b']', b'}'),
+        0x5D => (b'\\', b'|'),
+        0x66 => (BACKSPACE, BACKSPACE),
+        0x76 => (ESCAPE, ESCAPE),
+        // Keypad with num lock on
+        0x69 => (b'1', b'1'),
+        0x6B => (b'4', b'4'),
+        0x6C => (b'7', b'7'),
+        0x70 => (b'0', b'0'),
+        0x71 => (b'.', b'.'),
+        0x72 => (b'2', b'2'),
+        0x73 => (b'5', b'5'),
+        0x74 => (b'6', b'6'),
+        0x75 => (b'8', b'8'),
+        0x79 => (b'+', b'+'),
+        0x7A => (b'3', b'3'),
+        0x7B => (b'-', b'-'),
+        0x7C => (b'*', b'*'),
+        0x7D => (b'9', b'9'),
+        _ => return None,
+    })
+}
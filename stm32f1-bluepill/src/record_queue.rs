@@ -0,0 +1,264 @@
+//! Queue of fixed size records for store-and-forward, in RAM or in an FRAM.
+//!
+//! Records which can't be sent right away, e.g. while the network is down,
+//! are appended with `push()`. Once the connection is back, the oldest one
+//! is read with `peek()` and removed with `pop()` after it was sent, so
+//! nothing is lost when sending fails halfway. When the queue is full, the
+//! oldest record is dropped to make room for the new one.
+//!
+//! ```ignore
+//! let mut queue: RecordQueue<_, 8> = RecordQueue::restore(Fram::new(i2c, 0x50, 32 * 1024))?;
+//! queue.push(&sample.to_bytes())?;
+//! while let Some(record) = queue.peek()? {
+//!     if send(&record).is_err() {
+//!         break;
+//!     }
+//!     queue.pop()?;
+//! }
+//! ```
+//!
+//! The records go to a `Storage`. `Ram` keeps them in an array, which is
+//! lost on reset. `Fram` keeps them in an MB85RC ferroelectric RAM on the
+//! I2C bus, which keeps its content without power and can be written as
+//! often as RAM, unlike an EEPROM. The position of the queue is stored in a
+//! header at the start of the storage, so `restore()` continues with the
+//! records which were not sent before the reset.
+
+use core::convert::Infallible;
+use embedded_hal::blocking::i2c;
+
+const MAGIC: u32 = 0x5155_4555;
+/// Magic number, record length, index of the oldest record and length
+const HEADER_LEN: u32 = 16;
+/// Bytes written to the FRAM in one I2C transaction
+const FRAM_CHUNK_LEN: usize = 32;
+
+/// Memory holding the queue
+pub trait Storage {
+    type Error;
+    /// Size in bytes
+    fn capacity(&self) -> u32;
+    fn read(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Self::Error>;
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Storage in RAM, lost on reset
+pub struct Ram<const N: usize> {
+    bytes: [u8; N],
+}
+
+impl<const N: usize> Ram<N> {
+    pub fn new() -> Self {
+        Ram { bytes: [0; N] }
+    }
+}
+
+impl<const N: usize> Default for Ram<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Storage for Ram<N> {
+    type Error = Infallible;
+
+    fn capacity(&self) -> u32 {
+        N as u32
+    }
+
+    fn read(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Infallible> {
+        let start = address as usize;
+        buffer.copy_from_slice(&self.bytes[start..start + buffer.len()]);
+        Ok(())
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Infallible> {
+        let start = address as usize;
+        self.bytes[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+/// MB85RC FRAM with 16-bit addresses, e.g. the MB85RC256V with 32KB
+pub struct Fram<I2C> {
+    i2c: I2C,
+    address: u8,
+    capacity: u32,
+}
+
+impl<I2C, E> Fram<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    /// `address` is 0x50 plus the A2, A1 and A0 pins, `capacity` the size
+    /// in bytes.
+    pub fn new(i2c: I2C, address: u8, capacity: u32) -> Self {
+        Fram {
+            i2c,
+            address,
+            capacity,
+        }
+    }
+
+    /// Destroy the driver and return the I2C bus.
+    pub fn destroy(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C, E> Storage for Fram<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    type Error = E;
+
+    fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    fn read(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), E> {
+        let address = (address as u16).to_be_bytes();
+        self.i2c.write_read(self.address, &address, buffer)
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), E> {
+        // The FRAM needs no write time, but the memory address and the data
+        // have to go in the same transaction.
+        let mut packet = [0; FRAM_CHUNK_LEN + 2];
+        for (i, chunk) in data.chunks(FRAM_CHUNK_LEN).enumerate() {
+            let start = address as u16 + (i * FRAM_CHUNK_LEN) as u16;
+            packet[..2].copy_from_slice(&start.to_be_bytes());
+            packet[2..2 + chunk.len()].copy_from_slice(chunk);
+            self.i2c.write(self.address, &packet[..2 + chunk.len()])?;
+        }
+        Ok(())
+    }
+}
+
+/// Queue of records of `R` bytes
+pub struct RecordQueue<S, const R: usize> {
+    storage: S,
+    slots: u32,
+    head: u32,
+    len: u32,
+}
+
+impl<S: Storage, const R: usize> RecordQueue<S, R> {
+    /// Number of records which fit into `storage` after the header.
+    ///
+    /// Panics if not even one record fits.
+    fn slots(storage: &S) -> u32 {
+        let capacity = storage.capacity();
+        assert!(
+            R > 0 && capacity >= HEADER_LEN + R as u32,
+            "RecordQueue: storage of {} bytes can't hold the {} byte header and one record of {} bytes",
+            capacity,
+            HEADER_LEN,
+            R
+        );
+        (capacity - HEADER_LEN) / R as u32
+    }
+
+    /// Create an empty queue, dropping whatever the storage holds.
+    ///
+    /// Panics if the storage can't hold the header and one record.
+    pub fn new(storage: S) -> Result<Self, S::Error> {
+        let slots = Self::slots(&storage);
+        let mut queue = RecordQueue {
+            storage,
+            slots,
+            head: 0,
+            len: 0,
+        };
+        queue.write_header()?;
+        Ok(queue)
+    }
+
+    /// Continue the queue kept in the storage, or create an empty one if
+    /// it holds none for records of this length.
+    ///
+    /// Panics if the storage can't hold the header and one record.
+    pub fn restore(mut storage: S) -> Result<Self, S::Error> {
+        let slots = Self::slots(&storage);
+        let mut header = [0; HEADER_LEN as usize];
+        storage.read(0, &mut header)?;
+        let word =
+            |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let (head, len) = (word(8), word(12));
+        if word(0) != MAGIC || word(4) != R as u32 || head >= slots || len > slots {
+            return Self::new(storage);
+        }
+        Ok(RecordQueue {
+            storage,
+            slots,
+            head,
+            len,
+        })
+    }
+
+    /// Number of records in the queue
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of records which fit into the queue
+    pub fn capacity(&self) -> u32 {
+        self.slots
+    }
+
+    /// Append a record. When the queue is full, the oldest record is
+    /// dropped to make room and `true` is returned.
+    pub fn push(&mut self, record: &[u8; R]) -> Result<bool, S::Error> {
+        let tail = (self.head + self.len) % self.slots;
+        self.storage.write(self.address(tail), record)?;
+        let dropped = self.len == self.slots;
+        if dropped {
+            self.head = (self.head + 1) % self.slots;
+        } else {
+            self.len += 1;
+        }
+        self.write_header()?;
+        Ok(dropped)
+    }
+
+    /// Read the oldest record without removing it.
+    pub fn peek(&mut self) -> Result<Option<[u8; R]>, S::Error> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        let mut record = [0; R];
+        self.storage.read(self.address(self.head), &mut record)?;
+        Ok(Some(record))
+    }
+
+    /// Remove the oldest record, once it was sent.
+    pub fn pop(&mut self) -> Result<(), S::Error> {
+        if self.len > 0 {
+            self.head = (self.head + 1) % self.slots;
+            self.len -= 1;
+            self.write_header()?;
+        }
+        Ok(())
+    }
+
+    /// Destroy the queue and return the storage.
+    pub fn destroy(self) -> S {
+        self.storage
+    }
+
+    fn address(&self, slot: u32) -> u32 {
+        HEADER_LEN + slot * R as u32
+    }
+
+    fn write_header(&mut self) -> Result<(), S::Error> {
+        let mut header = [0; HEADER_LEN as usize];
+        for (i, word) in [MAGIC, R as u32, self.head, self.len].iter().enumerate() {
+            header[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        self.storage.write(0, &header)
+    }
+}
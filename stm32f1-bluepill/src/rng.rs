@@ -0,0 +1,51 @@
+//! Software random number generation for devices without a hardware RNG.
+//!
+//! The STM32F1 does not have a true random number generator (TRNG) like the
+//! STM32F4, F7, H7 or L4 families. `XorShift32` is a small pseudo-random
+//! number generator which is good enough for games and for adding jitter to
+//! timings but not for cryptography.
+//! In order to get a different sequence after each reset, seed it with some
+//! physical noise like the least significant bits of ADC readings of a
+//! floating pin. On families with a TRNG, seed it with a value read from it.
+
+/// Xorshift pseudo-random number generator with 32-bit state
+#[derive(Debug, Clone)]
+pub struct XorShift32 {
+    state: u32,
+}
+
+impl XorShift32 {
+    /// Create a generator from a seed. A seed of zero is replaced as the
+    /// generator would be stuck at zero otherwise.
+    pub fn new(seed: u32) -> Self {
+        XorShift32 {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    /// Create a generator from the least significant bit of each of
+    /// the noise samples given (e.g. ADC readings).
+    pub fn from_noise<I: IntoIterator<Item = u16>>(samples: I) -> Self {
+        let seed = samples
+            .into_iter()
+            .take(32)
+            .fold(0, |seed, sample| (seed << 1) | u32::from(sample & 1));
+        Self::new(seed)
+    }
+
+    /// Next random number.
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Random number in the range `[0, max)`. `max` must not be zero.
+    pub fn below(&mut self, max: u32) -> u32 {
+        // The bias is negligible for the small ranges used in the examples.
+        self.next_u32() % max
+    }
+}
@@ -0,0 +1,103 @@
+//! Pulse dialing of rotary telephone dials.
+//!
+//! A rotary dial has a pulse contact which is closed at rest. While the
+//! dial returns, the contact opens (breaks) once for every step: about 60ms
+//! open and 40ms closed, so 10 pulses per second. 1 to 9 send as many
+//! pulses and 0 sends 10. The pause until the next digit is much longer,
+//! since the dial has to be turned again first, so a digit is complete once
+//! the contact stayed closed for `DIGIT_TIMEOUT_MS`.
+//!
+//! Connect the pulse contact between an input with pull-up and GND, so the
+//! input is high while it is open. The contact bounces, so the level is
+//! only taken once it stayed the same for `DEBOUNCE_MS`. The decoder is fed
+//! with the input level and a millisecond timestamp, e.g. from the
+//! `monotonic` module, as often as possible, at least every few
+//! milliseconds:
+//!
+//! ```ignore
+//! loop {
+//!     let open = pulse_contact.is_high().unwrap();
+//!     match decoder.update(open, monotonic::millis()) {
+//!         Some(Ok(digit)) => rprintln!("Dialed {}", digit),
+//!         Some(Err(e)) => rprintln!("Error: {:?}", e),
+//!         None => (),
+//!     }
+//! }
+//! ```
+//!
+//! Slow code like flushing a display can make the decoder miss pulses while
+//! the dial returns. Only run it while `is_idle()`.
+
+/// Time the contact has to keep its level
+pub const DEBOUNCE_MS: u32 = 10;
+/// Time without a pulse after which a digit is complete
+pub const DIGIT_TIMEOUT_MS: u32 = 200;
+/// Longest time the contact is open during a pulse
+pub const MAX_BREAK_MS: u32 = 150;
+
+/// Rotary dial error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// More than 10 pulses
+    Pulses(u8),
+    /// The contact stayed open for longer than `MAX_BREAK_MS`, e.g. because
+    /// it is disconnected
+    LongBreak,
+}
+
+/// Rotary dial pulse decoder
+#[derive(Debug, Clone, Default)]
+pub struct RotaryDialDecoder {
+    /// Last level read
+    level: bool,
+    level_since: u32,
+    /// Debounced level
+    open: bool,
+    open_since: u32,
+    pulses: u8,
+}
+
+impl RotaryDialDecoder {
+    /// Create a new decoder with the contact closed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether no digit is being dialed
+    pub fn is_idle(&self) -> bool {
+        self.pulses == 0 && self.level == self.open
+    }
+
+    /// Add the level of the pulse contact, `true` for open, at the time
+    /// `now_ms`. Returns the digit dialed once it is complete.
+    pub fn update(&mut self, open: bool, now_ms: u32) -> Option<Result<u8, Error>> {
+        if open != self.level {
+            self.level = open;
+            self.level_since = now_ms;
+        }
+        if self.level != self.open && now_ms.wrapping_sub(self.level_since) >= DEBOUNCE_MS {
+            self.open = self.level;
+            self.open_since = self.level_since;
+            if self.open {
+                self.pulses = self.pulses.saturating_add(1);
+            }
+        }
+        if self.pulses == 0 {
+            return None;
+        }
+        let elapsed = now_ms.wrapping_sub(self.open_since);
+        if self.open && elapsed > MAX_BREAK_MS {
+            self.pulses = 0;
+            Some(Err(Error::LongBreak))
+        } else if !self.open && elapsed >= DIGIT_TIMEOUT_MS {
+            let pulses = core::mem::replace(&mut self.pulses, 0);
+            match pulses {
+                1..=9 => Some(Ok(pulses)),
+                10 => Some(Ok(0)),
+                _ => Some(Err(Error::Pulses(pulses))),
+            }
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! SBUS RC receiver protocol decoder.
+//!
+//! SBUS is a serial protocol at 100000 baud, 8 data bits, even parity and
+//! 2 stop bits, with inverted levels (the line is low when idle). A frame is
+//! sent every 7ms (or 14ms in slow mode) and is 25 bytes long:
+//! - the start byte 0x0F
+//! - 22 bytes with 16 channels of 11 bits, least significant bit first
+//! - a flags byte: digital channels 17 and 18, frame lost and failsafe
+//! - the end byte 0x00 (SBUS2 receivers send 0x04, 0x14, 0x24 or 0x34)
+//!
+//! The channels go from about 172 to 1811 with 992 in the center, which
+//! `to_us()` converts to the usual 1000-2000µs servo pulse.
+//!
+//! The start byte can also appear inside of a frame, so the decoder only
+//! synchronizes on it after a gap. Call `gap()` when no byte was received
+//! for a while (more than one byte time, a millisecond is fine):
+//!
+//! ```ignore
+//! let mut decoder = SbusDecoder::new();
+//! // for every received byte:
+//! if let Some(frame) = decoder.byte(byte) {
+//!     rprintln!("{:?}", frame);
+//! }
+//! // when the line has been idle for a while or on a reception error:
+//! decoder.gap();
+//! ```
+
+/// Baud rate of SBUS
+pub const BAUD_RATE: u32 = 100_000;
+/// Number of proportional channels
+pub const CHANNELS: usize = 16;
+/// Length of a frame in bytes
+pub const FRAME_LEN: usize = 25;
+const START: u8 = 0x0F;
+
+/// Channel value at the lower end of the stick travel
+pub const MIN: u16 = 172;
+/// Channel value with the stick in the center
+pub const CENTER: u16 = 992;
+/// Channel value at the upper end of the stick travel
+pub const MAX: u16 = 1811;
+
+/// Decoded frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    /// Raw channel values, see `MIN`, `CENTER` and `MAX`
+    pub channels: [u16; CHANNELS],
+    /// Digital channel 17
+    pub ch17: bool,
+    /// Digital channel 18
+    pub ch18: bool,
+    /// The receiver missed a frame from the transmitter. It repeats the
+    /// last channel values.
+    pub frame_lost: bool,
+    /// The receiver lost the connection to the transmitter. The channels
+    /// hold the failsafe positions configured in the receiver.
+    pub failsafe: bool,
+}
+
+impl Frame {
+    /// Parse a complete frame including the start and end bytes.
+    pub fn parse(data: &[u8; FRAME_LEN]) -> Option<Self> {
+        let end = data[FRAME_LEN - 1];
+        if data[0] != START || (end != 0x00 && end & 0x0F != 0x04) {
+            return None;
+        }
+        let mut channels = [0; CHANNELS];
+        let mut bits = 0_u32;
+        let mut bit_count = 0;
+        let mut payload = data[1..23].iter();
+        for channel in channels.iter_mut() {
+            while bit_count < 11 {
+                bits |= u32::from(*payload.next()?) << bit_count;
+                bit_count += 8;
+            }
+            *channel = (bits & 0x7FF) as u16;
+            bits >>= 11;
+            bit_count -= 11;
+        }
+        let flags = data[23];
+        Some(Frame {
+            channels,
+            ch17: flags & 0x01 != 0,
+            ch18: flags & 0x02 != 0,
+            frame_lost: flags & 0x04 != 0,
+            failsafe: flags & 0x08 != 0,
+        })
+    }
+}
+
+/// Convert a channel value to a servo pulse width in microseconds.
+pub fn to_us(value: u16) -> u16 {
+    // 172 -> 988µs, 992 -> 1500µs, 1811 -> 2012µs
+    (u32::from(value) * 5 / 8 + 880) as u16
+}
+
+/// SBUS stream decoder
+#[derive(Debug, Clone)]
+pub struct SbusDecoder {
+    buffer: [u8; FRAME_LEN],
+    len: usize,
+    synchronized: bool,
+}
+
+impl Default for SbusDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SbusDecoder {
+    /// Create a new decoder. It waits for a gap before the first frame.
+    pub fn new() -> Self {
+        SbusDecoder {
+            buffer: [0; FRAME_LEN],
+            len: 0,
+            synchronized: false,
+        }
+    }
+
+    /// The line was idle or there was a reception error. The next byte
+    /// starts a new frame.
+    pub fn gap(&mut self) {
+        self.len = 0;
+        self.synchronized = true;
+    }
+
+    /// Feed a received byte.
+    ///
+    /// Returns the frame when it is complete and valid.
+    pub fn byte(&mut self, byte: u8) -> Option<Frame> {
+        if !self.synchronized {
+            return None;
+        }
+        if self.len == 0 && byte != START {
+            self.synchronized = false;
+            return None;
+        }
+        self.buffer[self.len] = byte;
+        self.len += 1;
+        if self.len < FRAME_LEN {
+            return None;
+        }
+        // Frames usually follow each other with a gap, but continue with
+        // the next one if the start byte follows without a gap.
+        self.len = 0;
+        let frame = Frame::parse(&self.buffer);
+        self.synchronized = frame.is_some();
+        frame
+    }
+}
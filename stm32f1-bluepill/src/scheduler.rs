@@ -0,0 +1,78 @@
+//! Tiny cooperative scheduler for running things at different rates without
+//! an RTOS.
+//!
+//! The timebase is the millisecond counter of the `monotonic` module, so
+//! `setup_monotonic()` must be called and SysTick handled as described
+//! there.
+//!
+//! The scheduler holds a table with the period and the last run of every
+//! task. `poll()` returns the index of the first task that is due, so the
+//! order of the table is the priority. The tasks themselves are plain code in
+//! the main loop, so they can use all the local variables:
+//!
+//! ```ignore
+//! const BLINK: usize = 0;
+//! const MEASURE: usize = 1;
+//! monotonic::setup_monotonic(cp.SYST, clocks.sysclk().0);
+//! let mut scheduler = Scheduler::new([100, 1000]);
+//! loop {
+//!     match scheduler.poll() {
+//!         Some(BLINK) => { /* every 100ms */ }
+//!         Some(MEASURE) => { /* every second */ }
+//!         _ => (),
+//!     }
+//! }
+//! ```
+//!
+//! Tasks run to completion. A task that takes long delays the others, but
+//! no run is lost as long as a task does not fall behind by more than its
+//! period.
+
+use crate::monotonic::millis;
+
+#[derive(Debug, Clone, Copy)]
+struct Task {
+    period_ms: u32,
+    last_run_ms: u32,
+}
+
+/// Table of periodic tasks
+#[derive(Debug)]
+pub struct Scheduler<const N: usize> {
+    tasks: [Task; N],
+}
+
+impl<const N: usize> Scheduler<N> {
+    /// Create a scheduler with the periods of the tasks in milliseconds.
+    /// All tasks are due right away.
+    pub fn new(periods_ms: [u32; N]) -> Self {
+        let now = millis();
+        let mut tasks = [Task {
+            period_ms: 0,
+            last_run_ms: now,
+        }; N];
+        for (task, period_ms) in tasks.iter_mut().zip(periods_ms.iter()) {
+            task.period_ms = *period_ms;
+            task.last_run_ms = now.wrapping_sub(*period_ms);
+        }
+        Scheduler { tasks }
+    }
+
+    /// Return the index of the first task that is due and mark it as run.
+    pub fn poll(&mut self) -> Option<usize> {
+        let now = millis();
+        for (index, task) in self.tasks.iter_mut().enumerate() {
+            let elapsed = now.wrapping_sub(task.last_run_ms);
+            if elapsed >= task.period_ms {
+                // Keep the rate unless the task fell behind too much.
+                task.last_run_ms = if elapsed < 2 * task.period_ms {
+                    task.last_run_ms.wrapping_add(task.period_ms)
+                } else {
+                    now
+                };
+                return Some(index);
+            }
+        }
+        None
+    }
+}
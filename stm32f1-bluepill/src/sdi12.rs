@@ -0,0 +1,238 @@
+//! SDI-12 master for environmental sensors like soil probes.
+//!
+//! SDI-12 uses a single data line at 1200 baud with 7 data bits, even
+//! parity and one stop bit. The logic is inverted: the line is low
+//! (marking) for a 1 and when idle, and high (spacing) for a 0 and the start
+//! bit. A command is preceded by a break (spacing for at least 12ms) and
+//! marking for at least 8.33ms. Then the master releases the line and the
+//! sensor answers with a line ending in `"\r\n"`.
+//!
+//! The bits are timed with the DWT cycle counter, so it must be enabled.
+//! Interrupts are disabled while a character is sent or received.
+//!
+//! The line is accessed through the `Line` trait since it must switch
+//! between output and input.
+//!
+//! ```ignore
+//! let mut sdi12 = Master::new(line, clocks.sysclk().0);
+//! let (seconds, count) = sdi12.start_measurement(b'0')?;
+//! sdi12.wait_ms(u32::from(seconds) * 1000);
+//! let mut values = [0.0; 9];
+//! let count = sdi12.read_data(b'0', &mut values[..count])?;
+//! ```
+
+use cortex_m::peripheral::DWT;
+
+const BAUD_RATE: u32 = 1200;
+const BREAK_MS: u32 = 13;
+const MARKING_MS: u32 = 9;
+/// The sensor starts answering within 15ms
+const ANSWER_TIMEOUT_MS: u32 = 20;
+/// Longest answer is 75 characters plus the line ending
+pub const MAX_ANSWER_LEN: usize = 77;
+
+/// Data line of the bus
+pub trait Line {
+    /// Drive the line high (spacing) or low (marking).
+    fn drive(&mut self, spacing: bool);
+    /// Stop driving the line so that the sensor can answer.
+    fn release(&mut self);
+    /// Whether the released line is high (spacing).
+    fn is_spacing(&self) -> bool;
+}
+
+/// SDI-12 errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The sensor did not answer (completely) in time
+    Timeout,
+    /// The parity of a received character does not match
+    Parity,
+    /// The answer could not be understood
+    InvalidAnswer,
+    /// The answer does not fit in the buffer
+    BufferFull,
+}
+
+/// SDI-12 master
+pub struct Master<L> {
+    line: L,
+    cycles_per_bit: u32,
+    cycles_per_ms: u32,
+}
+
+impl<L: Line> Master<L> {
+    /// Create a new master. `sysclk_hz` is used to time the bits.
+    pub fn new(mut line: L, sysclk_hz: u32) -> Self {
+        line.drive(false);
+        Master {
+            line,
+            cycles_per_bit: sysclk_hz / BAUD_RATE,
+            cycles_per_ms: sysclk_hz / 1000,
+        }
+    }
+
+    /// Send a command like `b"0I!"` and receive the answer without the line
+    /// ending. Returns the length of the answer.
+    pub fn command(&mut self, command: &[u8], answer: &mut [u8]) -> Result<usize, Error> {
+        self.line.drive(true);
+        self.wait_ms(BREAK_MS);
+        self.line.drive(false);
+        self.wait_ms(MARKING_MS);
+        for c in command {
+            self.send_char(*c);
+        }
+        self.line.release();
+
+        let mut len = 0;
+        let mut timeout = ANSWER_TIMEOUT_MS * self.cycles_per_ms;
+        let result = loop {
+            let c = match self.receive_char(timeout) {
+                Ok(c) => c,
+                Err(e) => break Err(e),
+            };
+            // The characters follow each other closely.
+            timeout = 2 * 10 * self.cycles_per_bit;
+            if c == b'\n' && len > 0 && answer[len - 1] == b'\r' {
+                break Ok(len - 1);
+            }
+            if len == answer.len() {
+                break Err(Error::BufferFull);
+            }
+            answer[len] = c;
+            len += 1;
+        };
+        self.line.drive(false);
+        result
+    }
+
+    /// Start a measurement (`aM!`). Returns the number of seconds until the
+    /// data is ready and the number of values.
+    pub fn start_measurement(&mut self, address: u8) -> Result<(u16, usize), Error> {
+        let mut answer = [0; MAX_ANSWER_LEN];
+        let len = self.command(&[address, b'M', b'!'], &mut answer)?;
+        // atttn
+        if len != 5 || answer[0] != address {
+            return Err(Error::InvalidAnswer);
+        }
+        let seconds = parse_digits(&answer[1..4])?;
+        let count = parse_digits(&answer[4..5])?;
+        Ok((seconds, usize::from(count)))
+    }
+
+    /// Read the values of the last measurement with `aD0!`, `aD1!`... until
+    /// `values` is full. Returns the number of values read.
+    pub fn read_data(&mut self, address: u8, values: &mut [f32]) -> Result<usize, Error> {
+        let mut count = 0;
+        for index in b'0'..=b'9' {
+            if count == values.len() {
+                break;
+            }
+            let mut answer = [0; MAX_ANSWER_LEN];
+            let len = self.command(&[address, b'D', index, b'!'], &mut answer)?;
+            if len == 0 || answer[0] != address {
+                return Err(Error::InvalidAnswer);
+            }
+            let read = parse_values(&answer[1..len], &mut values[count..])?;
+            if read == 0 {
+                break;
+            }
+            count += read;
+        }
+        Ok(count)
+    }
+
+    /// Busy wait for the given number of milliseconds.
+    pub fn wait_ms(&self, ms: u32) {
+        for _ in 0..ms {
+            self.wait_cycles(self.cycles_per_ms);
+        }
+    }
+
+    /// Release the line.
+    pub fn destroy(self) -> L {
+        self.line
+    }
+
+    fn send_char(&mut self, c: u8) {
+        let data = c & 0x7F;
+        let parity = data.count_ones() % 2 == 1;
+        // Start bit, 7 data bits, parity bit, stop bit. A 0 is spacing.
+        let bits = (u16::from(data) << 1) | (u16::from(parity) << 8) | (1 << 9);
+        cortex_m::interrupt::free(|_| {
+            let start = DWT::get_cycle_count();
+            for i in 0..10 {
+                self.line.drive(bits & (1 << i) == 0);
+                let end = self.cycles_per_bit * (i + 1);
+                while DWT::get_cycle_count().wrapping_sub(start) < end {}
+            }
+        });
+    }
+
+    fn receive_char(&mut self, timeout_cycles: u32) -> Result<u8, Error> {
+        let wait_start = DWT::get_cycle_count();
+        while !self.line.is_spacing() {
+            if DWT::get_cycle_count().wrapping_sub(wait_start) > timeout_cycles {
+                return Err(Error::Timeout);
+            }
+        }
+        let bits = cortex_m::interrupt::free(|_| {
+            let start = DWT::get_cycle_count();
+            let mut bits = 0_u16;
+            // Sample the data and parity bits in their middle.
+            for i in 0..8 {
+                let middle = self.cycles_per_bit * (2 * i + 3) / 2;
+                while DWT::get_cycle_count().wrapping_sub(start) < middle {}
+                if !self.line.is_spacing() {
+                    bits |= 1 << i;
+                }
+            }
+            // Wait for the stop bit.
+            let stop = self.cycles_per_bit * 19 / 2;
+            while DWT::get_cycle_count().wrapping_sub(start) < stop {}
+            bits
+        });
+        if bits.count_ones() % 2 != 0 {
+            return Err(Error::Parity);
+        }
+        Ok((bits & 0x7F) as u8)
+    }
+
+    fn wait_cycles(&self, cycles: u32) {
+        let start = DWT::get_cycle_count();
+        while DWT::get_cycle_count().wrapping_sub(start) < cycles {}
+    }
+}
+
+fn parse_digits(digits: &[u8]) -> Result<u16, Error> {
+    digits.iter().try_fold(0_u16, |value, d| {
+        if d.is_ascii_digit() {
+            Ok(value * 10 + u16::from(d - b'0'))
+        } else {
+            Err(Error::InvalidAnswer)
+        }
+    })
+}
+
+/// Parse values like `+22.5-3.1+13` into `values`. Returns how many.
+fn parse_values(data: &[u8], values: &mut [f32]) -> Result<usize, Error> {
+    let text = core::str::from_utf8(data).map_err(|_| Error::InvalidAnswer)?;
+    let mut count = 0;
+    let mut start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(core::iter::once((text.len(), '+')))
+    {
+        if c == '+' || c == '-' {
+            if let Some(s) = start {
+                if count == values.len() {
+                    break;
+                }
+                values[count] = text[s..i].parse().map_err(|_| Error::InvalidAnswer)?;
+                count += 1;
+            }
+            start = Some(i);
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,134 @@
+//! Output pins through a chain of 74HC595 shift registers.
+//!
+//! The shift registers are clocked with SPI (SCK to SH_CP, MOSI to DS) and
+//! their outputs are latched with an additional pin (ST_CP). `N` is the
+//! number of shift registers in the chain. Each of the `8 * N` outputs can be
+//! used as an `OutputPin` so that drivers can use expander pins
+//! transparently. For example, an HD44780 display could be driven with:
+//!
+//! ```ignore
+//! let expander: ShiftRegisterPins<_, _, 1> = ShiftRegisterPins::new(spi, latch);
+//! let lcd = HD44780::new_4bit(
+//!     expander.pin(0), expander.pin(1),
+//!     expander.pin(2), expander.pin(3), expander.pin(4), expander.pin(5),
+//!     &mut delay,
+//! );
+//! ```
+//!
+//! Every change on a pin shifts out the state of the whole chain. To change
+//! many outputs at once, use `write_all()` instead.
+
+use core::cell::RefCell;
+use embedded_hal::{blocking::spi, digital::v2::OutputPin};
+
+/// All possible errors
+#[derive(Debug)]
+pub enum Error<SpiE, PinE> {
+    /// SPI communication error
+    Spi(SpiE),
+    /// Latch pin error
+    Pin(PinE),
+}
+
+struct Inner<SPI, LATCH, const N: usize> {
+    spi: SPI,
+    latch: LATCH,
+    state: [u8; N],
+}
+
+/// Chain of `N` 74HC595 shift registers
+pub struct ShiftRegisterPins<SPI, LATCH, const N: usize> {
+    inner: RefCell<Inner<SPI, LATCH, N>>,
+}
+
+impl<SPI, LATCH, SpiE, PinE, const N: usize> ShiftRegisterPins<SPI, LATCH, N>
+where
+    SPI: spi::Write<u8, Error = SpiE>,
+    LATCH: OutputPin<Error = PinE>,
+{
+    /// Create a new instance. All outputs start low once the first change is
+    /// written.
+    pub fn new(spi: SPI, latch: LATCH) -> Self {
+        ShiftRegisterPins {
+            inner: RefCell::new(Inner {
+                spi,
+                latch,
+                state: [0; N],
+            }),
+        }
+    }
+
+    /// Get the output pin with the given index. Index 0 is output Q0 of the
+    /// first shift register in the chain, index 8 is Q0 of the second one and
+    /// so on.
+    ///
+    /// Panics if the index is not lower than `8 * N`.
+    pub fn pin(&self, index: usize) -> ShiftRegisterPin<'_, SPI, LATCH, N> {
+        assert!(index < 8 * N);
+        ShiftRegisterPin {
+            register: self,
+            index,
+        }
+    }
+
+    /// Set the state of all outputs at once. `state[0]` is the first shift
+    /// register in the chain and bit 0 is its output Q0.
+    pub fn write_all(&self, state: [u8; N]) -> Result<(), Error<SpiE, PinE>> {
+        self.inner.borrow_mut().state = state;
+        self.update()
+    }
+
+    /// Destroy the instance and return the SPI interface and latch pin.
+    pub fn destroy(self) -> (SPI, LATCH) {
+        let inner = self.inner.into_inner();
+        (inner.spi, inner.latch)
+    }
+
+    fn set(&self, index: usize, high: bool) -> Result<(), Error<SpiE, PinE>> {
+        {
+            let mut inner = self.inner.borrow_mut();
+            let mask = 1 << (index % 8);
+            if high {
+                inner.state[index / 8] |= mask;
+            } else {
+                inner.state[index / 8] &= !mask;
+            }
+        }
+        self.update()
+    }
+
+    fn update(&self) -> Result<(), Error<SpiE, PinE>> {
+        let mut inner = self.inner.borrow_mut();
+        let Inner { spi, latch, state } = &mut *inner;
+        latch.set_low().map_err(Error::Pin)?;
+        // The data for the last register in the chain goes out first.
+        for byte in state.iter().rev() {
+            // The most significant bit ends up in Q7.
+            spi.write(&[*byte]).map_err(Error::Spi)?;
+        }
+        // The outputs are updated on the rising edge.
+        latch.set_high().map_err(Error::Pin)
+    }
+}
+
+/// Single output of a `ShiftRegisterPins` chain
+pub struct ShiftRegisterPin<'a, SPI, LATCH, const N: usize> {
+    register: &'a ShiftRegisterPins<SPI, LATCH, N>,
+    index: usize,
+}
+
+impl<'a, SPI, LATCH, SpiE, PinE, const N: usize> OutputPin for ShiftRegisterPin<'a, SPI, LATCH, N>
+where
+    SPI: spi::Write<u8, Error = SpiE>,
+    LATCH: OutputPin<Error = PinE>,
+{
+    type Error = Error<SpiE, PinE>;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.register.set(self.index, false)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.register.set(self.index, true)
+    }
+}
@@ -0,0 +1,95 @@
+//! SNTP (Simple Network Time Protocol) request and answer.
+//!
+//! The client sends a 48 byte request to UDP port 123 of a time server and
+//! the answer carries the time at which the server sent it. `request()`
+//! builds the request and `parse()` checks the answer and returns the time
+//! as Unix time in UTC:
+//!
+//! ```ignore
+//! w5500.open_udp(LOCAL_PORT, SERVER_IP, sntp::PORT)?;
+//! w5500.send(&sntp::request())?;
+//! let sent = monotonic::millis();
+//! // Wait for the answer...
+//! let time = sntp::parse(&buffer[..len])?;
+//! // The answer took about half of the round trip to arrive.
+//! let time = time.add_ms(monotonic::millis().wrapping_sub(sent) / 2);
+//! ```
+//!
+//! The seconds of NTP wrap around in 2036. Times with the highest bit
+//! clear are taken to be after that, so this works until 2104.
+
+pub const PORT: u16 = 123;
+pub const PACKET_LEN: usize = 48;
+const VERSION: u8 = 4;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+/// Seconds from 1900 (NTP) to 1970 (Unix)
+const NTP_TO_UNIX_S: i64 = 2_208_988_800;
+const TRANSMIT_TIMESTAMP: usize = 40;
+
+/// SNTP errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The answer is shorter than `PACKET_LEN`
+    TooShort,
+    /// The answer does not come from a server
+    NotServer,
+    /// The server has no time yet
+    Unsynchronized,
+    /// The server asks the client to stop or to try later ("kiss-o'-death")
+    KissOfDeath,
+}
+
+/// Time of the server, since 1970-01-01 00:00:00 UTC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Time {
+    pub seconds: i64,
+    pub nanos: u32,
+}
+
+impl Time {
+    /// The time `ms` milliseconds later
+    pub fn add_ms(self, ms: u32) -> Self {
+        let nanos = u64::from(self.nanos) + u64::from(ms) * 1_000_000;
+        Time {
+            seconds: self.seconds + (nanos / 1_000_000_000) as i64,
+            nanos: (nanos % 1_000_000_000) as u32,
+        }
+    }
+}
+
+/// Request to send to the server
+pub fn request() -> [u8; PACKET_LEN] {
+    let mut packet = [0; PACKET_LEN];
+    packet[0] = VERSION << 3 | MODE_CLIENT;
+    packet
+}
+
+/// Check the answer of the server and return its transmit time.
+pub fn parse(packet: &[u8]) -> Result<Time, Error> {
+    if packet.len() < PACKET_LEN {
+        return Err(Error::TooShort);
+    }
+    if packet[0] & 0x07 != MODE_SERVER {
+        return Err(Error::NotServer);
+    }
+    if packet[0] >> 6 == LEAP_UNSYNCHRONIZED {
+        return Err(Error::Unsynchronized);
+    }
+    // Stratum 0
+    if packet[1] == 0 {
+        return Err(Error::KissOfDeath);
+    }
+    let timestamp = &packet[TRANSMIT_TIMESTAMP..TRANSMIT_TIMESTAMP + 8];
+    let seconds = u32::from_be_bytes([timestamp[0], timestamp[1], timestamp[2], timestamp[3]]);
+    let fraction = u32::from_be_bytes([timestamp[4], timestamp[5], timestamp[6], timestamp[7]]);
+    let mut seconds = i64::from(seconds);
+    if seconds < 0x8000_0000 {
+        seconds += 1 << 32;
+    }
+    Ok(Time {
+        seconds: seconds - NTP_TO_UNIX_S,
+        nanos: ((u64::from(fraction) * 1_000_000_000) >> 32) as u32,
+    })
+}
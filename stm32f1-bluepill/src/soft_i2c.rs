@@ -0,0 +1,265 @@
+//! Bit-banged I2C master on any two GPIO pins.
+//!
+//! Useful when the pins of the I2C peripherals are taken or broken, for a
+//! second bus with devices on the same address, or to use 5V tolerant pins
+//! for a 5V bus. It implements the blocking I2C traits of `embedded-hal`, so
+//! it works with any driver, but it keeps the core busy for the whole
+//! transaction.
+//!
+//! Both pins must be open-drain outputs which can also be read, like
+//! `into_open_drain_output()` of the HAL, with pull-up resistors on the bus.
+//! The bits are timed with `DwtDelay`, so the DWT cycle counter must be
+//! enabled.
+//!
+//! ```ignore
+//! let scl = gpiob.pb14.into_open_drain_output(&mut gpiob.crh);
+//! let sda = gpiob.pb15.into_open_drain_output(&mut gpiob.crh);
+//! let i2c = SoftI2c::new(scl, sda, clocks.sysclk().0, 100_000);
+//! let mut sensor = Tmp1x2::new(i2c, SlaveAddr::default());
+//! ```
+//!
+//! A slave may hold SCL low to make the master wait (clock stretching), for
+//! up to `STRETCH_TIMEOUT_US`. Interrupts only make the clock slower, which
+//! I2C allows, so they stay enabled. If a slave holds SDA low after a reset
+//! in the middle of a transaction, the master clocks it free before the
+//! next start condition.
+
+use crate::delay::DwtDelay;
+use cortex_m::peripheral::DWT;
+use embedded_hal::{
+    blocking::i2c::{Read, Write, WriteRead},
+    digital::v2::{InputPin, OutputPin},
+};
+
+/// Longest time a slave may stretch the clock
+pub const STRETCH_TIMEOUT_US: u32 = 25_000;
+
+/// Software I2C errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// No slave acknowledged the address.
+    AddressNack,
+    /// The slave did not acknowledge a data byte.
+    DataNack,
+    /// SDA is held low and clocking did not free it.
+    BusBusy,
+    /// Another master drove SDA low while this one sent a 1.
+    ArbitrationLost,
+    /// A slave held SCL low for longer than `STRETCH_TIMEOUT_US`.
+    StretchTimeout,
+}
+
+/// Bit-banged I2C master
+pub struct SoftI2c<SCL, SDA> {
+    scl: SCL,
+    sda: SDA,
+    delay: DwtDelay,
+    half_period: u32,
+    stretch_timeout: u32,
+}
+
+impl<SCL, SDA> SoftI2c<SCL, SDA>
+where
+    SCL: OutputPin + InputPin,
+    SDA: OutputPin + InputPin,
+{
+    /// Create a new master running at about `frequency_hz`, 400kHz at most.
+    pub fn new(mut scl: SCL, mut sda: SDA, sysclk_hz: u32, frequency_hz: u32) -> Self {
+        scl.set_high().ok();
+        sda.set_high().ok();
+        let delay = DwtDelay::new(sysclk_hz);
+        SoftI2c {
+            scl,
+            sda,
+            delay,
+            half_period: sysclk_hz / frequency_hz.min(400_000) / 2,
+            stretch_timeout: delay.us_to_cycles(STRETCH_TIMEOUT_US),
+        }
+    }
+
+    /// Destroy the master and return the pins.
+    pub fn free(self) -> (SCL, SDA) {
+        (self.scl, self.sda)
+    }
+
+    /// Free SDA if a slave holds it low: clock until the slave has sent the
+    /// rest of its byte and releases it, then send a stop condition.
+    pub fn recover(&mut self) -> Result<(), Error> {
+        for _ in 0..9 {
+            if self.sda.is_high().unwrap_or(false) {
+                break;
+            }
+            self.scl.set_low().ok();
+            self.wait();
+            self.release_scl()?;
+            self.wait();
+        }
+        self.stop()?;
+        if self.sda.is_high().unwrap_or(false) {
+            Ok(())
+        } else {
+            Err(Error::BusBusy)
+        }
+    }
+
+    fn wait(&self) {
+        self.delay.delay_cycles(self.half_period);
+    }
+
+    /// Release SCL and wait until a stretching slave releases it as well.
+    fn release_scl(&mut self) -> Result<(), Error> {
+        self.scl.set_high().ok();
+        let start = DWT::get_cycle_count();
+        while self.scl.is_low().unwrap_or(false) {
+            if DWT::get_cycle_count().wrapping_sub(start) > self.stretch_timeout {
+                return Err(Error::StretchTimeout);
+            }
+        }
+        Ok(())
+    }
+
+    /// Start condition, or repeated start in a transaction
+    fn start(&mut self) -> Result<(), Error> {
+        self.sda.set_high().ok();
+        self.wait();
+        self.release_scl()?;
+        if self.sda.is_low().unwrap_or(true) {
+            self.recover()?;
+        }
+        self.wait();
+        self.sda.set_low().ok();
+        self.wait();
+        self.scl.set_low().ok();
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        self.scl.set_low().ok();
+        self.sda.set_low().ok();
+        self.wait();
+        self.release_scl()?;
+        self.wait();
+        self.sda.set_high().ok();
+        self.wait();
+        Ok(())
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), Error> {
+        if bit {
+            self.sda.set_high().ok();
+        } else {
+            self.sda.set_low().ok();
+        }
+        self.wait();
+        self.release_scl()?;
+        let lost = bit && self.sda.is_low().unwrap_or(false);
+        self.wait();
+        self.scl.set_low().ok();
+        if lost {
+            Err(Error::ArbitrationLost)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read_bit(&mut self) -> Result<bool, Error> {
+        self.sda.set_high().ok();
+        self.wait();
+        self.release_scl()?;
+        self.wait();
+        let bit = self.sda.is_high().unwrap_or(true);
+        self.scl.set_low().ok();
+        Ok(bit)
+    }
+
+    /// Send a byte and return whether the slave acknowledged it.
+    fn write_byte(&mut self, byte: u8) -> Result<bool, Error> {
+        for i in (0..8).rev() {
+            self.write_bit(byte >> i & 1 != 0)?;
+        }
+        Ok(!self.read_bit()?)
+    }
+
+    /// Receive a byte and acknowledge it if more should follow.
+    fn read_byte(&mut self, ack: bool) -> Result<u8, Error> {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = byte << 1 | self.read_bit()? as u8;
+        }
+        self.write_bit(!ack)?;
+        Ok(byte)
+    }
+
+    fn transfer(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        if !bytes.is_empty() || buffer.is_empty() {
+            self.start()?;
+            if !self.write_byte(address << 1)? {
+                return Err(Error::AddressNack);
+            }
+            for byte in bytes {
+                if !self.write_byte(*byte)? {
+                    return Err(Error::DataNack);
+                }
+            }
+        }
+        if !buffer.is_empty() {
+            self.start()?;
+            if !self.write_byte(address << 1 | 1)? {
+                return Err(Error::AddressNack);
+            }
+            let last = buffer.len() - 1;
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte = self.read_byte(i < last)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write `bytes`, then read into `buffer` after a repeated start. Either
+    /// may be empty. The bus is released in any case.
+    fn transaction(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        let result = self.transfer(address, bytes, buffer);
+        if result == Err(Error::ArbitrationLost) {
+            // The other master owns the bus now.
+            return result;
+        }
+        let stop = self.stop();
+        result.and(stop)
+    }
+}
+
+impl<SCL, SDA> Write for SoftI2c<SCL, SDA>
+where
+    SCL: OutputPin + InputPin,
+    SDA: OutputPin + InputPin,
+{
+    type Error = Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.transaction(address, bytes, &mut [])
+    }
+}
+
+impl<SCL, SDA> Read for SoftI2c<SCL, SDA>
+where
+    SCL: OutputPin + InputPin,
+    SDA: OutputPin + InputPin,
+{
+    type Error = Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        self.transaction(address, &[], buffer)
+    }
+}
+
+impl<SCL, SDA> WriteRead for SoftI2c<SCL, SDA>
+where
+    SCL: OutputPin + InputPin,
+    SDA: OutputPin + InputPin,
+{
+    type Error = Error;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        self.transaction(address, bytes, buffer)
+    }
+}
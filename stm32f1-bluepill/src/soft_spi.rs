@@ -0,0 +1,187 @@
+//! Bit-banged SPI master on any GPIO pins.
+//!
+//! Useful when the pins of the SPI peripherals are taken by other
+//! peripherals or broken. It supports the 4 SPI modes, sends the most
+//! significant bit first and implements the `FullDuplex` trait and through
+//! it the blocking SPI traits of `embedded-hal`, so it works with any
+//! driver. The core is busy for the whole transfer.
+//!
+//! The bits are timed with `DwtDelay`, so the DWT cycle counter must be
+//! enabled. For devices which only receive, like display controllers, pass
+//! `NoMiso` instead of an input pin. Several devices share the bus with a
+//! chip select pin each, as with a hardware SPI:
+//!
+//! ```ignore
+//! let sck = gpiob.pb12.into_push_pull_output(&mut gpiob.crh);
+//! let mosi = gpiob.pb13.into_push_pull_output(&mut gpiob.crh);
+//! let mut spi = SoftSpi::new(sck, mosi, NoMiso, MODE_0, clocks.sysclk().0, 1_000_000);
+//! cs.set_low().unwrap();
+//! spi.write(&[register, value]).unwrap();
+//! cs.set_high().unwrap();
+//! ```
+//!
+//! Interrupts only make the clock slower, which SPI allows, so they stay
+//! enabled.
+
+use crate::delay::DwtDelay;
+use core::convert::Infallible;
+use embedded_hal::{
+    blocking::spi::{transfer, write},
+    digital::v2::{InputPin, OutputPin},
+    spi::{FullDuplex, Mode, Phase, Polarity},
+};
+
+/// Placeholder for the MISO pin of a bus without one, it reads low.
+#[derive(Debug, Clone, Copy)]
+pub struct NoMiso;
+
+impl InputPin for NoMiso {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        Ok(false)
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        Ok(true)
+    }
+}
+
+/// Bit-banged SPI master
+pub struct SoftSpi<SCK, MOSI, MISO> {
+    sck: SCK,
+    mosi: MOSI,
+    miso: MISO,
+    mode: Mode,
+    delay: DwtDelay,
+    half_period: u32,
+    received: u8,
+}
+
+impl<SCK, MOSI, MISO> SoftSpi<SCK, MOSI, MISO>
+where
+    SCK: OutputPin,
+    MOSI: OutputPin,
+    MISO: InputPin,
+{
+    /// Create a new master running at about `frequency_hz`.
+    pub fn new(
+        sck: SCK,
+        mosi: MOSI,
+        miso: MISO,
+        mode: Mode,
+        sysclk_hz: u32,
+        frequency_hz: u32,
+    ) -> Self {
+        let mut spi = SoftSpi {
+            sck,
+            mosi,
+            miso,
+            mode,
+            delay: DwtDelay::new(sysclk_hz),
+            half_period: sysclk_hz / frequency_hz / 2,
+            received: 0,
+        };
+        spi.set_clock(false);
+        spi
+    }
+
+    /// Change the mode, e.g. for the next device on the bus. Do it while no
+    /// device is selected.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.set_clock(false);
+    }
+
+    /// Destroy the master and return the pins.
+    pub fn free(self) -> (SCK, MOSI, MISO) {
+        (self.sck, self.mosi, self.miso)
+    }
+
+    fn wait(&self) {
+        self.delay.delay_cycles(self.half_period);
+    }
+
+    /// Set the clock to its active or idle level.
+    fn set_clock(&mut self, active: bool) {
+        if active == (self.mode.polarity == Polarity::IdleLow) {
+            self.sck.set_high().ok();
+        } else {
+            self.sck.set_low().ok();
+        }
+    }
+
+    fn set_mosi(&mut self, bit: bool) {
+        if bit {
+            self.mosi.set_high().ok();
+        } else {
+            self.mosi.set_low().ok();
+        }
+    }
+
+    /// Send a byte and return the byte received at the same time.
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let mut received = 0;
+        for i in (0..8).rev() {
+            let bit = byte >> i & 1 != 0;
+            // The data changes on one edge of the clock and is sampled on
+            // the other.
+            let sampled = match self.mode.phase {
+                Phase::CaptureOnFirstTransition => {
+                    self.set_mosi(bit);
+                    self.wait();
+                    self.set_clock(true);
+                    let sampled = self.miso.is_high().unwrap_or(false);
+                    self.wait();
+                    self.set_clock(false);
+                    sampled
+                }
+                Phase::CaptureOnSecondTransition => {
+                    self.set_clock(true);
+                    self.set_mosi(bit);
+                    self.wait();
+                    self.set_clock(false);
+                    let sampled = self.miso.is_high().unwrap_or(false);
+                    self.wait();
+                    sampled
+                }
+            };
+            received = received << 1 | sampled as u8;
+        }
+        received
+    }
+}
+
+impl<SCK, MOSI, MISO> FullDuplex<u8> for SoftSpi<SCK, MOSI, MISO>
+where
+    SCK: OutputPin,
+    MOSI: OutputPin,
+    MISO: InputPin,
+{
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        Ok(self.received)
+    }
+
+    fn send(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+        self.received = self.exchange(byte);
+        Ok(())
+    }
+}
+
+impl<SCK, MOSI, MISO> transfer::Default<u8> for SoftSpi<SCK, MOSI, MISO>
+where
+    SCK: OutputPin,
+    MOSI: OutputPin,
+    MISO: InputPin,
+{
+}
+
+impl<SCK, MOSI, MISO> write::Default<u8> for SoftSpi<SCK, MOSI, MISO>
+where
+    SCK: OutputPin,
+    MOSI: OutputPin,
+    MISO: InputPin,
+{
+}
@@ -0,0 +1,88 @@
+//! Chip select pins which set the SPI1 mode and clock up for their device.
+//!
+//! `shared-bus` hands one SPI peripheral out to several drivers, but the
+//! peripheral is set up once, with a single mode and clock speed. Devices
+//! often need different ones: an ADC may be limited to ~1MHz while a flash
+//! memory runs at tens of MHz. `DeviceCs` wraps the chip select pin of a
+//! device together with its mode and clock speed. Each time a driver asserts
+//! chip select, SPI1 is set up for the device first:
+//!
+//! ```ignore
+//! let adc_cs = DeviceCs::new(adc_cs, MODE_3, 1.mhz(), clocks);
+//! let mut adc = Mcp3008::new(manager.acquire(), adc_cs).unwrap();
+//! ```
+//!
+//! Every device on the bus needs a `DeviceCs`, otherwise it runs with the
+//! setup of the device used last. The bus must not be used from interrupts
+//! between asserting a chip select and the transfer.
+
+use embedded_hal::{
+    digital::v2::OutputPin,
+    spi::{Mode, Phase, Polarity},
+};
+use stm32f1xx_hal::{pac, rcc::Clocks, time::Hertz};
+
+/// Chip select pin of a device on SPI1
+pub struct DeviceCs<PIN> {
+    pin: PIN,
+    mode: Mode,
+    /// Value of the baud rate control bits
+    br: u8,
+}
+
+impl<PIN: OutputPin> DeviceCs<PIN> {
+    /// Wrap the chip select `pin` of a device which uses `mode` and runs at
+    /// up to `frequency`.
+    pub fn new<F: Into<Hertz>>(pin: PIN, mode: Mode, frequency: F, clocks: Clocks) -> Self {
+        // Same dividers as the HAL, the clock is never faster than asked.
+        let br = match clocks.pclk2().0 / frequency.into().0 {
+            0 => unreachable!(),
+            1..=2 => 0b000,
+            3..=5 => 0b001,
+            6..=11 => 0b010,
+            12..=23 => 0b011,
+            24..=47 => 0b100,
+            48..=95 => 0b101,
+            96..=191 => 0b110,
+            _ => 0b111,
+        };
+        DeviceCs { pin, mode, br }
+    }
+
+    /// Set SPI1 up for the device.
+    fn configure(&self) {
+        // The peripheral belongs to the bus manager, which does not touch
+        // CR1 after the setup. No transfer runs while no device is selected.
+        #[allow(unsafe_code)]
+        let spi = unsafe { &*pac::SPI1::ptr() };
+        while spi.sr.read().bsy().bit_is_set() {}
+        spi.cr1.modify(|_, w| w.spe().clear_bit());
+        spi.cr1.modify(|_, w| {
+            w.cpol()
+                .bit(self.mode.polarity == Polarity::IdleHigh)
+                .cpha()
+                .bit(self.mode.phase == Phase::CaptureOnSecondTransition)
+                .br()
+                .bits(self.br)
+        });
+        spi.cr1.modify(|_, w| w.spe().set_bit());
+    }
+
+    /// Return the pin.
+    pub fn free(self) -> PIN {
+        self.pin
+    }
+}
+
+impl<PIN: OutputPin> OutputPin for DeviceCs<PIN> {
+    type Error = PIN::Error;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.configure();
+        self.pin.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_high()
+    }
+}
@@ -0,0 +1,57 @@
+//! Samples sent as newline-delimited JSON, with the `telemetry-json` feature.
+//!
+//! Each sample is a JSON object on its own line, which existing tools read
+//! straight from the serial port, e.g. `jq` or `json.loads()` in Python:
+//!
+//! ```text
+//! {"index":0,"co2":450,"tvoc":125,"resistance":231000}
+//! ```
+//!
+//! The sample is any type deriving `serde::Serialize`. It is serialized
+//! with serde-json-core into a `heapless::String` of `N` bytes, so no heap
+//! is needed:
+//!
+//! ```ignore
+//! #[derive(Serialize)]
+//! struct Sample {
+//!     index: usize,
+//!     co2: u16,
+//! }
+//!
+//! telemetry::write_line::<_, _, 64>(&mut tx, &Sample { index, co2 }).unwrap();
+//! ```
+//!
+//! `write_line()` writes to anything implementing `core::fmt::Write`, like
+//! the serial `Tx` of the HAL or the `uart` module. For a USB serial port,
+//! send the bytes of `to_line()`.
+
+use core::fmt::Write;
+use heapless::String;
+use serde::Serialize;
+
+/// Error sending a sample
+#[derive(Debug)]
+pub enum Error {
+    /// The sample does not fit into the line
+    Serialize(serde_json_core::ser::Error),
+    /// Error of the writer
+    Write,
+}
+
+/// Serialize `sample` into a line of at most `N` bytes, ending with CR LF.
+pub fn to_line<T: Serialize, const N: usize>(sample: &T) -> Result<String<N>, Error> {
+    let mut line: String<N> = serde_json_core::to_string(sample).map_err(Error::Serialize)?;
+    // The CR is whitespace for JSON parsers and makes terminals happy.
+    line.push_str("\r\n")
+        .map_err(|_| Error::Serialize(serde_json_core::ser::Error::BufferFull))?;
+    Ok(line)
+}
+
+/// Write `sample` as a line of at most `N` bytes.
+pub fn write_line<T: Serialize, W: Write, const N: usize>(
+    writer: &mut W,
+    sample: &T,
+) -> Result<(), Error> {
+    let line: String<N> = to_line(sample)?;
+    writer.write_str(&line).map_err(|_| Error::Write)
+}
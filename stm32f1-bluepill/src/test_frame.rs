@@ -0,0 +1,47 @@
+//! Numbered test frames for checking a data link between two boards.
+//!
+//! Every frame has `FRAME_LEN` bytes: the `SYNC` byte, a sequence number
+//! which wraps around after 255, a payload derived from the sequence number
+//! and the CRC-32 of the rest as a little-endian number at the end. The
+//! receiver finds the start of a frame by the `SYNC` byte and notices lost
+//! frames by the gaps in the sequence numbers.
+//!
+//! ```ignore
+//! let frame = build(sequence, &mut crc);
+//! spi.write(&frame)?;
+//! // on the other board
+//! if let Some(sequence) = check(&frame, &mut crc) { ... }
+//! ```
+
+use crate::crc::Crc32;
+
+/// First byte of every frame
+pub const SYNC: u8 = 0xA5;
+/// Length of a frame including the CRC
+pub const FRAME_LEN: usize = 64;
+const CRC_OFFSET: usize = FRAME_LEN - 4;
+
+/// Build the frame with the given sequence number.
+pub fn build<C: Crc32>(sequence: u8, crc: &mut C) -> [u8; FRAME_LEN] {
+    let mut frame = [0; FRAME_LEN];
+    frame[0] = SYNC;
+    frame[1] = sequence;
+    for (i, byte) in frame[2..CRC_OFFSET].iter_mut().enumerate() {
+        *byte = sequence.wrapping_add(i as u8);
+    }
+    let checksum = crc.checksum(&frame[..CRC_OFFSET]);
+    frame[CRC_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
+    frame
+}
+
+/// Sequence number of a valid frame, `None` if the sync byte or the CRC is
+/// wrong.
+pub fn check<C: Crc32>(frame: &[u8; FRAME_LEN], crc: &mut C) -> Option<u8> {
+    let mut stored = [0; 4];
+    stored.copy_from_slice(&frame[CRC_OFFSET..]);
+    if frame[0] == SYNC && crc.checksum(&frame[..CRC_OFFSET]) == u32::from_le_bytes(stored) {
+        Some(frame[1])
+    } else {
+        None
+    }
+}
@@ -0,0 +1,363 @@
+//! Interrupt driven UART with ring buffers.
+//!
+//! The UART holds a single received byte. Reading it with `block!` or from a
+//! polling loop loses bytes as soon as the program is busy for longer than
+//! one character, e.g. for 1ms at 9600 baud while updating a display. Here
+//! the UART interrupt moves every received byte into a ring buffer and sends
+//! the bytes waiting in another one. The rest of the program empties and
+//! fills the buffers whenever it has time.
+//!
+//! Each buffer is split into the part used by the interrupt handler and the
+//! part used by the program. This needs a `'static` buffer, e.g. from
+//! `singleton!`:
+//!
+//! ```ignore
+//! let (mut uart_rx, mut reader) = singleton!(: RxBuffer<64> = RxBuffer::new())
+//!     .unwrap()
+//!     .split(rx);
+//! let (mut uart_tx, mut writer) = singleton!(: TxBuffer<128> = TxBuffer::new())
+//!     .unwrap()
+//!     .split(tx, || NVIC::pend(Interrupt::USART1));
+//!
+//! // In the USART1 interrupt handler
+//! uart_rx.on_interrupt();
+//! if uart_tx.on_interrupt() {
+//!     uart_tx.tx().listen();
+//! } else {
+//!     uart_tx.tx().unlisten();
+//! }
+//!
+//! // In the program
+//! let mut line_buffer: LineBuffer<32> = LineBuffer::new();
+//! for line in reader.lines(&mut line_buffer) {
+//!     writeln!(writer, "Received: {}", line).unwrap();
+//! }
+//! ```
+//!
+//! Without flow control lines the receiver can not slow the sender down: the
+//! bytes that do not fit into the buffer are dropped and counted, and so are
+//! the bytes lost to UART errors (overrun, framing, noise or parity). The
+//! transmitter applies backpressure instead: writing waits while its buffer
+//! is full. Do not write from the interrupt handler itself or from a higher
+//! priority, or it would wait forever.
+//!
+//! A buffer of `N` bytes holds `N - 1` bytes.
+
+use core::{
+    convert::Infallible,
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use embedded_hal::serial;
+use heapless::{
+    spsc::{Consumer, Producer, Queue},
+    String,
+};
+
+/// Error counters of the receiver
+#[derive(Debug)]
+struct Counters {
+    dropped: AtomicU32,
+    errors: AtomicU32,
+}
+
+/// Storage for the received bytes
+pub struct RxBuffer<const N: usize> {
+    queue: Queue<u8, N>,
+    counters: Counters,
+}
+
+impl<const N: usize> Default for RxBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> RxBuffer<N> {
+    /// Create an empty buffer.
+    pub const fn new() -> Self {
+        RxBuffer {
+            queue: Queue::new(),
+            counters: Counters {
+                dropped: AtomicU32::new(0),
+                errors: AtomicU32::new(0),
+            },
+        }
+    }
+
+    /// Split into the part for the interrupt handler, which owns the UART
+    /// receiver, and the part for the program.
+    pub fn split<RX>(&'static mut self, rx: RX) -> (UartRx<RX, N>, RxReader<N>) {
+        let (producer, consumer) = self.queue.split();
+        let counters = &self.counters;
+        (
+            UartRx {
+                rx,
+                producer,
+                counters,
+            },
+            RxReader { consumer, counters },
+        )
+    }
+}
+
+/// Receiving side for the interrupt handler
+pub struct UartRx<RX, const N: usize> {
+    rx: RX,
+    producer: Producer<'static, u8, N>,
+    counters: &'static Counters,
+}
+
+impl<RX, const N: usize> UartRx<RX, N>
+where
+    RX: serial::Read<u8>,
+{
+    /// Move the received bytes into the buffer. Call it from the UART
+    /// interrupt handler.
+    pub fn on_interrupt(&mut self) {
+        loop {
+            match self.rx.read() {
+                Ok(byte) => {
+                    if self.producer.enqueue(byte).is_err() {
+                        self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Err(nb::Error::WouldBlock) => return,
+                Err(nb::Error::Other(_)) => {
+                    self.counters.errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// The UART receiver, e.g. to enable its interrupt.
+    pub fn rx(&mut self) -> &mut RX {
+        &mut self.rx
+    }
+}
+
+/// Receiving side for the program
+pub struct RxReader<const N: usize> {
+    consumer: Consumer<'static, u8, N>,
+    counters: &'static Counters,
+}
+
+impl<const N: usize> RxReader<N> {
+    /// Next received byte, if any.
+    pub fn read_byte(&mut self) -> Option<u8> {
+        self.consumer.dequeue()
+    }
+
+    /// Number of bytes waiting in the buffer
+    pub fn len(&self) -> usize {
+        self.consumer.len()
+    }
+
+    /// Whether no bytes are waiting in the buffer.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of bytes dropped because the buffer was full
+    pub fn dropped(&self) -> u32 {
+        self.counters.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of UART errors (overrun, framing, noise or parity)
+    pub fn errors(&self) -> u32 {
+        self.counters.errors.load(Ordering::Relaxed)
+    }
+
+    /// Iterate over the lines completed by the bytes received so far.
+    /// Incomplete lines stay in `line_buffer` until the next call.
+    pub fn lines<'a, const L: usize>(
+        &'a mut self,
+        line_buffer: &'a mut LineBuffer<L>,
+    ) -> Lines<'a, N, L> {
+        Lines {
+            reader: self,
+            line_buffer,
+        }
+    }
+}
+
+impl<const N: usize> serial::Read<u8> for RxReader<N> {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        self.read_byte().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+/// Assembles received bytes into lines of up to `L` characters
+#[derive(Debug, Default)]
+pub struct LineBuffer<const L: usize> {
+    line: String<L>,
+    too_long: bool,
+    discarded: u32,
+}
+
+impl<const L: usize> LineBuffer<L> {
+    /// Create an empty line buffer.
+    pub fn new() -> Self {
+        LineBuffer {
+            line: String::new(),
+            too_long: false,
+            discarded: 0,
+        }
+    }
+
+    /// Add a byte. Returns the line when the byte ends it. Lines end with
+    /// `\r`, `\n` or both. Empty lines and lines which are too long are
+    /// discarded.
+    pub fn push(&mut self, byte: u8) -> Option<String<L>> {
+        if byte == b'\r' || byte == b'\n' {
+            let line = core::mem::take(&mut self.line);
+            let too_long = core::mem::replace(&mut self.too_long, false);
+            return if line.is_empty() || too_long {
+                None
+            } else {
+                Some(line)
+            };
+        }
+        if !self.too_long && self.line.push(char::from(byte)).is_err() {
+            self.too_long = true;
+            self.discarded += 1;
+        }
+        None
+    }
+
+    /// Number of lines discarded because they were too long
+    pub fn discarded(&self) -> u32 {
+        self.discarded
+    }
+}
+
+/// Iterator over the received lines, see `RxReader::lines()`
+pub struct Lines<'a, const N: usize, const L: usize> {
+    reader: &'a mut RxReader<N>,
+    line_buffer: &'a mut LineBuffer<L>,
+}
+
+impl<'a, const N: usize, const L: usize> Iterator for Lines<'a, N, L> {
+    type Item = String<L>;
+
+    fn next(&mut self) -> Option<String<L>> {
+        while let Some(byte) = self.reader.read_byte() {
+            if let Some(line) = self.line_buffer.push(byte) {
+                return Some(line);
+            }
+        }
+        None
+    }
+}
+
+/// Storage for the bytes to send
+pub struct TxBuffer<const N: usize> {
+    queue: Queue<u8, N>,
+}
+
+impl<const N: usize> Default for TxBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> TxBuffer<N> {
+    /// Create an empty buffer.
+    pub const fn new() -> Self {
+        TxBuffer {
+            queue: Queue::new(),
+        }
+    }
+
+    /// Split into the part for the interrupt handler, which owns the UART
+    /// transmitter, and the part for the program. `pend` must trigger the
+    /// UART interrupt, so that it starts sending new bytes.
+    pub fn split<TX>(&'static mut self, tx: TX, pend: fn()) -> (UartTx<TX, N>, TxWriter<N>) {
+        let (producer, consumer) = self.queue.split();
+        (UartTx { tx, consumer }, TxWriter { producer, pend })
+    }
+}
+
+/// Sending side for the interrupt handler
+pub struct UartTx<TX, const N: usize> {
+    tx: TX,
+    consumer: Consumer<'static, u8, N>,
+}
+
+impl<TX, const N: usize> UartTx<TX, N>
+where
+    TX: serial::Write<u8>,
+{
+    /// Hand the waiting bytes over to the UART. Call it from the UART
+    /// interrupt handler. Returns whether bytes are still waiting, in which
+    /// case the transmit interrupt must be enabled, otherwise disabled.
+    pub fn on_interrupt(&mut self) -> bool {
+        while let Some(&byte) = self.consumer.peek() {
+            if self.tx.write(byte).is_err() {
+                return true;
+            }
+            self.consumer.dequeue();
+        }
+        false
+    }
+
+    /// The UART transmitter, e.g. to enable its interrupt.
+    pub fn tx(&mut self) -> &mut TX {
+        &mut self.tx
+    }
+}
+
+/// Sending side for the program
+pub struct TxWriter<const N: usize> {
+    producer: Producer<'static, u8, N>,
+    pend: fn(),
+}
+
+impl<const N: usize> TxWriter<N> {
+    /// Queue a byte, waiting while the buffer is full.
+    pub fn write_byte(&mut self, byte: u8) {
+        while self.producer.enqueue(byte).is_err() {
+            (self.pend)();
+        }
+    }
+
+    /// Queue all bytes, waiting while the buffer is full.
+    pub fn write_all(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_byte(*byte);
+        }
+        (self.pend)();
+    }
+}
+
+impl<const N: usize> fmt::Write for TxWriter<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl<const N: usize> serial::Write<u8> for TxWriter<N> {
+    type Error = Infallible;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+        let result = self
+            .producer
+            .enqueue(byte)
+            .map_err(|_| nb::Error::WouldBlock);
+        (self.pend)();
+        result
+    }
+
+    /// Waits until all bytes were handed over to the UART.
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        if self.producer.len() == 0 {
+            Ok(())
+        } else {
+            (self.pend)();
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Machine vibration severity from the spectrum of an accelerometer.
+//!
+//! Vibration standards like ISO 10816 rate machines by the RMS vibration
+//! velocity in a frequency band, usually 10 to 1000Hz. The velocity is
+//! calculated from the FFT of the acceleration of the `fft` module (with the
+//! Hann window), by dividing each bin by 2πf, which also suppresses the low
+//! frequencies where accelerometers are noisy. The bin magnitudes of the
+//! fixed point FFT are converted back to the RMS of the signal, including
+//! the energy the window spreads to the neighbouring bins.
+//!
+//! ```ignore
+//! let band = Band { low_hz: 10.0, high_hz: 1000.0 };
+//! let mm_s = velocity_rms_mm_s(&re, &im, sample_rate_hz, M_S2_PER_COUNT, band);
+//! match zone(mm_s, 2.8, 4.5) { /* ... */ }
+//! ```
+
+use core::f32::consts::PI;
+
+/// Frequency band
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub low_hz: f32,
+    pub high_hz: f32,
+}
+
+/// Evaluation of the vibration velocity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zone {
+    /// Fine for long-term operation
+    Good,
+    /// Not fit for long-term operation, plan a repair
+    Warning,
+    /// Vibration which may damage the machine
+    Alarm,
+}
+
+/// Zone of a velocity with the limits of the machine
+pub fn zone(velocity_mm_s: f32, warning_mm_s: f32, alarm_mm_s: f32) -> Zone {
+    if velocity_mm_s >= alarm_mm_s {
+        Zone::Alarm
+    } else if velocity_mm_s >= warning_mm_s {
+        Zone::Warning
+    } else {
+        Zone::Good
+    }
+}
+
+/// RMS velocity in mm/s in a band, from the bins of an `N`-point FFT of the
+/// acceleration sampled at `sample_rate_hz`. `m_s2_per_count` is the
+/// acceleration of one step of the FFT input.
+pub fn velocity_rms_mm_s<const N: usize>(
+    re: &[i16; N],
+    im: &[i16; N],
+    sample_rate_hz: f32,
+    m_s2_per_count: f32,
+    band: Band,
+) -> f32 {
+    let bin_hz = sample_rate_hz / N as f32;
+    let mut sum = 0.0;
+    for bin in 1..N / 2 {
+        let hz = bin as f32 * bin_hz;
+        if hz < band.low_hz || hz > band.high_hz {
+            continue;
+        }
+        let square =
+            f32::from(re[bin]) * f32::from(re[bin]) + f32::from(im[bin]) * f32::from(im[bin]);
+        let omega = 2.0 * PI * hz;
+        sum += square / (omega * omega);
+    }
+    // A sine of amplitude A gives a bin of A / 4 (FFT scaled by 1 / N, Hann
+    // window gain 0.5), so its RMS is 2√2 times the bin. The Hann window
+    // spreads 1.5 times the energy of the bin over its neighbours.
+    libm::sqrtf(sum * 8.0 / 1.5) * m_s2_per_count * 1000.0
+}
@@ -0,0 +1,381 @@
+//! Minimal W5500 Ethernet driver with a single UDP or TCP client socket.
+//!
+//! The W5500 runs the TCP/IP stack itself: the driver only writes the
+//! network settings and moves the data in and out of the buffers of socket 0
+//! over SPI (mode 0, up to 80MHz).
+//!
+//! ```ignore
+//! let config = NetConfig {
+//!     mac: [0x02, 0x00, 0x00, 0x12, 0x34, 0x56],
+//!     ip: [192, 168, 1, 50],
+//!     subnet_mask: [255, 255, 255, 0],
+//!     router: [192, 168, 1, 1],
+//! };
+//! let mut w5500 = W5500::new(spi, cs, &config)?;
+//! w5500.open_udp(10000, [192, 168, 1, 10], 10000)?;
+//! w5500.send(b"hello")?;
+//! if let Some(len) = w5500.receive(&mut buffer)? {
+//!     // ...
+//! }
+//! ```
+//!
+//! The datagrams go to the remote address and port given to `open_udp()`,
+//! and only the ones coming from there are received, the others are
+//! dropped. The socket implements the `Transport` trait of the `mqtt_sn`
+//! module.
+//!
+//! With `connect_tcp()` the socket connects to a TCP server instead. Then
+//! `send()` and `receive()` move a stream of bytes and `close()` ends the
+//! connection:
+//!
+//! ```ignore
+//! w5500.connect_tcp(40000, [192, 168, 1, 10], 80)?;
+//! w5500.send(b"GET / HTTP/1.1\r\n\r\n")?;
+//! while w5500.connected()? {
+//!     if let Some(len) = w5500.receive(&mut buffer)? {
+//!         // ...
+//!     }
+//! }
+//! w5500.close()?;
+//! ```
+
+use crate::mqtt_sn::Transport;
+use embedded_hal::{blocking::spi, digital::v2::OutputPin};
+
+// Common registers
+const MR: u16 = 0x0000;
+const GAR: u16 = 0x0001;
+const SUBR: u16 = 0x0005;
+const SHAR: u16 = 0x0009;
+const SIPR: u16 = 0x000F;
+const PHYCFGR: u16 = 0x002E;
+const VERSIONR: u16 = 0x0039;
+// Socket registers
+const SN_MR: u16 = 0x0000;
+const SN_CR: u16 = 0x0001;
+const SN_IR: u16 = 0x0002;
+const SN_SR: u16 = 0x0003;
+const SN_PORT: u16 = 0x0004;
+const SN_DIPR: u16 = 0x000C;
+const SN_DPORT: u16 = 0x0010;
+const SN_TX_FSR: u16 = 0x0020;
+const SN_TX_WR: u16 = 0x0024;
+const SN_RX_RSR: u16 = 0x0026;
+const SN_RX_RD: u16 = 0x0028;
+// Blocks of the common registers and of socket 0
+const COMMON: u8 = 0;
+const SOCKET: u8 = 1;
+const TX_BUFFER: u8 = 2;
+const RX_BUFFER: u8 = 3;
+
+const MR_RESET: u8 = 0x80;
+const SN_MR_TCP: u8 = 0x01;
+const SN_MR_UDP: u8 = 0x02;
+const SN_CR_OPEN: u8 = 0x01;
+const SN_CR_CONNECT: u8 = 0x04;
+const SN_CR_DISCON: u8 = 0x08;
+const SN_CR_CLOSE: u8 = 0x10;
+const SN_CR_SEND: u8 = 0x20;
+const SN_CR_RECV: u8 = 0x40;
+const SN_IR_SEND_OK: u8 = 0x10;
+const SN_IR_TIMEOUT: u8 = 0x08;
+const SOCK_CLOSED: u8 = 0x00;
+const SOCK_INIT: u8 = 0x13;
+const SOCK_ESTABLISHED: u8 = 0x17;
+const SOCK_UDP: u8 = 0x22;
+const CHIP_VERSION: u8 = 0x04;
+/// Header of each received datagram: source IP, port and length
+const UDP_HEADER_LEN: usize = 8;
+/// Reads of the mode register while waiting for the reset to end. The reset
+/// takes less than 1ms, each read takes a few microseconds.
+const RESET_POLLS: u32 = 10_000;
+
+/// Size of the transmit buffer of the socket after a reset, the longest
+/// data `send()` accepts
+pub const TX_BUFFER_SIZE: usize = 2048;
+
+/// W5500 errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// SPI communication error
+    Spi,
+    /// No W5500 answers
+    NotFound,
+    /// The socket could not be opened
+    Socket,
+    /// The remote address could not be reached, e.g. no ARP answer
+    SendTimeout,
+    /// The TCP server refused the connection, did not answer or closed it
+    Connect,
+    /// The data is longer than `TX_BUFFER_SIZE`
+    TooLong,
+}
+
+/// Network settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetConfig {
+    /// Use a locally administered address, e.g. starting with 0x02, and a
+    /// different one for each board.
+    pub mac: [u8; 6],
+    pub ip: [u8; 4],
+    pub subnet_mask: [u8; 4],
+    pub router: [u8; 4],
+}
+
+/// W5500 driver
+pub struct W5500<SPI, CS> {
+    spi: SPI,
+    cs: CS,
+    remote_ip: [u8; 4],
+    remote_port: u16,
+    tcp: bool,
+}
+
+impl<SPI, CS, E> W5500<SPI, CS>
+where
+    SPI: spi::Write<u8, Error = E> + spi::Transfer<u8, Error = E>,
+    CS: OutputPin,
+{
+    /// Reset the chip and configure the network.
+    pub fn new(spi: SPI, mut cs: CS, config: &NetConfig) -> Result<Self, Error> {
+        cs.set_high().ok();
+        let mut w5500 = W5500 {
+            spi,
+            cs,
+            remote_ip: [0; 4],
+            remote_port: 0,
+            tcp: false,
+        };
+        w5500.write(COMMON, MR, &[MR_RESET])?;
+        // The reset bit clears itself when the reset is done. Without a chip,
+        // MISO may float high and the bit never clears.
+        let mut polls = 0;
+        while w5500.read_u8(COMMON, MR)? & MR_RESET != 0 {
+            polls += 1;
+            if polls == RESET_POLLS {
+                return Err(Error::NotFound);
+            }
+        }
+        if w5500.read_u8(COMMON, VERSIONR)? != CHIP_VERSION {
+            return Err(Error::NotFound);
+        }
+        w5500.write(COMMON, GAR, &config.router)?;
+        w5500.write(COMMON, SUBR, &config.subnet_mask)?;
+        w5500.write(COMMON, SHAR, &config.mac)?;
+        w5500.write(COMMON, SIPR, &config.ip)?;
+        Ok(w5500)
+    }
+
+    /// Whether the Ethernet link is up.
+    pub fn link_up(&mut self) -> Result<bool, Error> {
+        Ok(self.read_u8(COMMON, PHYCFGR)? & 0x01 != 0)
+    }
+
+    /// Open the UDP socket on `local_port`, exchanging datagrams with
+    /// `remote_port` of `remote_ip`. An open socket is closed first.
+    pub fn open_udp(
+        &mut self,
+        local_port: u16,
+        remote_ip: [u8; 4],
+        remote_port: u16,
+    ) -> Result<(), Error> {
+        self.command(SN_CR_CLOSE)?;
+        self.write(SOCKET, SN_MR, &[SN_MR_UDP])?;
+        self.write(SOCKET, SN_PORT, &local_port.to_be_bytes())?;
+        self.command(SN_CR_OPEN)?;
+        if self.read_u8(SOCKET, SN_SR)? != SOCK_UDP {
+            return Err(Error::Socket);
+        }
+        self.write(SOCKET, SN_DIPR, &remote_ip)?;
+        self.write(SOCKET, SN_DPORT, &remote_port.to_be_bytes())?;
+        self.remote_ip = remote_ip;
+        self.remote_port = remote_port;
+        self.tcp = false;
+        Ok(())
+    }
+
+    /// Connect the socket from `local_port` to a TCP server at `remote_port`
+    /// of `remote_ip`. An open socket is closed first. This blocks until the
+    /// server answers or the retransmissions of the W5500 time out.
+    pub fn connect_tcp(
+        &mut self,
+        local_port: u16,
+        remote_ip: [u8; 4],
+        remote_port: u16,
+    ) -> Result<(), Error> {
+        self.command(SN_CR_CLOSE)?;
+        self.write(SOCKET, SN_MR, &[SN_MR_TCP])?;
+        self.write(SOCKET, SN_PORT, &local_port.to_be_bytes())?;
+        self.command(SN_CR_OPEN)?;
+        if self.read_u8(SOCKET, SN_SR)? != SOCK_INIT {
+            return Err(Error::Socket);
+        }
+        self.write(SOCKET, SN_DIPR, &remote_ip)?;
+        self.write(SOCKET, SN_DPORT, &remote_port.to_be_bytes())?;
+        self.command(SN_CR_CONNECT)?;
+        self.remote_ip = remote_ip;
+        self.remote_port = remote_port;
+        self.tcp = true;
+        loop {
+            match self.read_u8(SOCKET, SN_SR)? {
+                SOCK_ESTABLISHED => return Ok(()),
+                SOCK_CLOSED => return Err(Error::Connect),
+                _ => (),
+            }
+        }
+    }
+
+    /// Whether the TCP connection is established. It is not anymore once
+    /// the server closed it, but the data received before can still be read.
+    pub fn connected(&mut self) -> Result<bool, Error> {
+        Ok(self.read_u8(SOCKET, SN_SR)? == SOCK_ESTABLISHED)
+    }
+
+    /// Close the TCP connection, waiting for the server to acknowledge it,
+    /// or close the UDP socket.
+    pub fn close(&mut self) -> Result<(), Error> {
+        if self.tcp && self.read_u8(SOCKET, SN_SR)? != SOCK_CLOSED {
+            self.command(SN_CR_DISCON)?;
+            // The W5500 closes the socket when it is done or timed out.
+            while self.read_u8(SOCKET, SN_SR)? != SOCK_CLOSED {}
+        }
+        self.command(SN_CR_CLOSE)
+    }
+
+    /// Send a datagram to the remote address, or data over the TCP
+    /// connection. The data must fit into the transmit buffer of
+    /// `TX_BUFFER_SIZE` bytes, send longer TCP data in several parts.
+    pub fn send(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.len() > TX_BUFFER_SIZE {
+            return Err(Error::TooLong);
+        }
+        if self.tcp && !self.connected()? {
+            return Err(Error::Connect);
+        }
+        while usize::from(self.read_u16(SOCKET, SN_TX_FSR)?) < data.len() {}
+        // The chip wraps the pointers around its buffer by itself.
+        let pointer = self.read_u16(SOCKET, SN_TX_WR)?;
+        self.write(TX_BUFFER, pointer, data)?;
+        let pointer = pointer.wrapping_add(data.len() as u16);
+        self.write(SOCKET, SN_TX_WR, &pointer.to_be_bytes())?;
+        self.command(SN_CR_SEND)?;
+        loop {
+            let interrupts = self.read_u8(SOCKET, SN_IR)?;
+            if interrupts & (SN_IR_SEND_OK | SN_IR_TIMEOUT) != 0 {
+                self.write(SOCKET, SN_IR, &[SN_IR_SEND_OK | SN_IR_TIMEOUT])?;
+                return match interrupts & SN_IR_SEND_OK {
+                    0 => Err(Error::SendTimeout),
+                    _ => Ok(()),
+                };
+            }
+        }
+    }
+
+    /// Receive a datagram from the remote address into `buffer`, if one
+    /// arrived, and return its length. Datagrams from elsewhere or longer
+    /// than the buffer are dropped. Over TCP, receive as much of the data
+    /// which arrived as fits into `buffer`.
+    pub fn receive(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Error> {
+        let available = usize::from(self.read_u16(SOCKET, SN_RX_RSR)?);
+        if available == 0 {
+            return Ok(None);
+        }
+        let pointer = self.read_u16(SOCKET, SN_RX_RD)?;
+        if self.tcp {
+            let len = available.min(buffer.len());
+            self.read(RX_BUFFER, pointer, &mut buffer[..len])?;
+            let pointer = pointer.wrapping_add(len as u16);
+            self.write(SOCKET, SN_RX_RD, &pointer.to_be_bytes())?;
+            self.command(SN_CR_RECV)?;
+            return Ok(Some(len));
+        }
+        let mut header = [0; UDP_HEADER_LEN];
+        self.read(RX_BUFFER, pointer, &mut header)?;
+        let len = usize::from(u16::from_be_bytes([header[6], header[7]]));
+        let wanted = header[..4] == self.remote_ip
+            && header[4..6] == self.remote_port.to_be_bytes()
+            && len <= buffer.len();
+        if wanted {
+            let data = pointer.wrapping_add(UDP_HEADER_LEN as u16);
+            self.read(RX_BUFFER, data, &mut buffer[..len])?;
+        }
+        // Drop the datagram from the buffer of the chip.
+        let pointer = pointer.wrapping_add((UDP_HEADER_LEN + len) as u16);
+        self.write(SOCKET, SN_RX_RD, &pointer.to_be_bytes())?;
+        self.command(SN_CR_RECV)?;
+        Ok(if wanted { Some(len) } else { None })
+    }
+
+    /// Destroy the driver and return the SPI and chip select pin.
+    pub fn destroy(self) -> (SPI, CS) {
+        (self.spi, self.cs)
+    }
+
+    fn write(&mut self, block: u8, address: u16, data: &[u8]) -> Result<(), Error> {
+        let [high, low] = address.to_be_bytes();
+        self.cs.set_low().ok();
+        let result = self
+            .spi
+            .write(&[high, low, block << 3 | 0x04])
+            .and_then(|_| self.spi.write(data));
+        self.cs.set_high().ok();
+        result.map_err(|_| Error::Spi)
+    }
+
+    fn read(&mut self, block: u8, address: u16, buffer: &mut [u8]) -> Result<(), Error> {
+        let [high, low] = address.to_be_bytes();
+        for byte in buffer.iter_mut() {
+            *byte = 0;
+        }
+        self.cs.set_low().ok();
+        let result = self
+            .spi
+            .write(&[high, low, block << 3])
+            .and_then(|_| self.spi.transfer(buffer).map(|_| ()));
+        self.cs.set_high().ok();
+        result.map_err(|_| Error::Spi)
+    }
+
+    fn read_u8(&mut self, block: u8, address: u16) -> Result<u8, Error> {
+        let mut value = [0];
+        self.read(block, address, &mut value)?;
+        Ok(value[0])
+    }
+
+    /// Read a 16-bit register which the chip may change while it is read,
+    /// until two reads agree.
+    fn read_u16(&mut self, block: u8, address: u16) -> Result<u16, Error> {
+        let mut value = [0; 2];
+        loop {
+            self.read(block, address, &mut value)?;
+            let first = u16::from_be_bytes(value);
+            self.read(block, address, &mut value)?;
+            if u16::from_be_bytes(value) == first {
+                return Ok(first);
+            }
+        }
+    }
+
+    /// Run a socket command and wait until the chip accepted it.
+    fn command(&mut self, command: u8) -> Result<(), Error> {
+        self.write(SOCKET, SN_CR, &[command])?;
+        while self.read_u8(SOCKET, SN_CR)? != 0 {}
+        Ok(())
+    }
+}
+
+impl<SPI, CS, E> Transport for W5500<SPI, CS>
+where
+    SPI: spi::Write<u8, Error = E> + spi::Transfer<u8, Error = E>,
+    CS: OutputPin,
+{
+    type Error = Error;
+
+    fn send(&mut self, packet: &[u8]) -> Result<(), Error> {
+        W5500::send(self, packet)
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Error> {
+        W5500::receive(self, buffer)
+    }
+}
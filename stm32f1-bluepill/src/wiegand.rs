@@ -0,0 +1,123 @@
+//! Wiegand frames of access control card readers and keypads.
+//!
+//! A Wiegand reader has two data lines, D0 and D1, which are pulled high.
+//! For every bit, the reader pulls one of them low for about 50µs: D0 for a
+//! 0 and D1 for a 1, with about 2ms between the bits, most significant bit
+//! first. There is no end marker, so a frame is complete once no bit came
+//! for `FRAME_TIMEOUT_MS`. The number of bits tells the format:
+//! - 26 bits: even parity, 8-bit facility code, 16-bit card number, odd
+//!   parity. The even parity covers the first 12 data bits and the odd
+//!   parity the last 12.
+//! - 34 bits: the same with a 16-bit facility code and 16 data bits per
+//!   parity.
+//! - 4 bits: a key of a keypad, 0 to 9, 10 for `*` and 11 for `#`.
+//! - 8 bits: a key in the low nibble and its complement in the high one.
+//!
+//! The decoder does not care how the bits are read:
+//!
+//! ```ignore
+//! // on a falling edge of D0 or D1:
+//! decoder.push(d1_fell);
+//! last_bit = now;
+//! // ...
+//! if !decoder.is_empty() && now - last_bit >= FRAME_TIMEOUT_MS {
+//!     match decoder.finish() {
+//!         Ok(Frame::Card { facility, number }) => { /* ... */ }
+//!         // ...
+//!     }
+//! }
+//! ```
+
+/// Time without a bit after which a frame is complete
+pub const FRAME_TIMEOUT_MS: u32 = 25;
+
+/// Decoded frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frame {
+    /// Card of a 26 or 34-bit reader
+    Card { facility: u16, number: u16 },
+    /// Key of a keypad: `b'0'` to `b'9'`, `b'*'` or `b'#'`
+    Key(u8),
+}
+
+/// Wiegand error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// A parity bit or the complement of a key is wrong
+    Parity,
+    /// The key code is not 0 to 11
+    UnknownKey(u8),
+    /// The frame has a number of bits of no known format
+    Length(u8),
+}
+
+/// Wiegand frame decoder
+#[derive(Debug, Clone, Default)]
+pub struct WiegandDecoder {
+    bits: u64,
+    len: u8,
+}
+
+impl WiegandDecoder {
+    /// Create a new decoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the next bit: `true` for a pulse on D1, `false` for D0.
+    pub fn push(&mut self, one: bool) {
+        self.bits = self.bits << 1 | u64::from(one);
+        self.len = self.len.saturating_add(1);
+    }
+
+    /// Whether no bit came since the last frame
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Decode the bits received once the frame is complete and start a new
+    /// frame.
+    pub fn finish(&mut self) -> Result<Frame, Error> {
+        let (bits, len) = (self.bits, self.len);
+        self.bits = 0;
+        self.len = 0;
+        match len {
+            4 => key(bits as u8),
+            8 => {
+                let code = bits as u8 & 0x0F;
+                if (bits as u8) >> 4 != !code & 0x0F {
+                    return Err(Error::Parity);
+                }
+                key(code)
+            }
+            26 | 34 => {
+                let half = u32::from(len - 2) / 2;
+                let mask = (1 << half) - 1;
+                let data = bits >> 1 & ((1 << (len - 2)) - 1);
+                let first = data >> half & mask;
+                let second = data & mask;
+                let even = (bits >> (len - 1)) & 1;
+                let odd = bits & 1;
+                if (first.count_ones() as u64 + even) % 2 != 0
+                    || (second.count_ones() as u64 + odd) % 2 != 1
+                {
+                    return Err(Error::Parity);
+                }
+                Ok(Frame::Card {
+                    facility: (data >> 16) as u16,
+                    number: data as u16,
+                })
+            }
+            _ => Err(Error::Length(len)),
+        }
+    }
+}
+
+fn key(code: u8) -> Result<Frame, Error> {
+    match code {
+        0..=9 => Ok(Frame::Key(b'0' + code)),
+        10 => Ok(Frame::Key(b'*')),
+        11 => Ok(Frame::Key(b'#')),
+        _ => Err(Error::UnknownKey(code)),
+    }
+}
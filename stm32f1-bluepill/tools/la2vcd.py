@@ -0,0 +1,105 @@
+#!/usr/bin/env python3
+"""Capture with the logic-analyzer-usb-cdc-bp example and save a VCD file.
+
+The capture can be opened in PulseView (File > Import > Value Change Dump) or
+GTKWave. Only the Python standard library is needed. The serial port is put
+into raw mode with termios, so this works on Linux and macOS.
+
+    python3 tools/la2vcd.py /dev/ttyACM0 --rate 1000000 --samples 8192 capture.vcd
+
+The format sent by the example is described at the top of
+examples/logic-analyzer-usb-cdc-bp.rs.
+"""
+
+import argparse
+import os
+import struct
+import sys
+import termios
+import tty
+
+RECORD = struct.Struct("<IB")
+
+
+def read_exactly(port, length):
+    data = b""
+    while len(data) < length:
+        chunk = port.read(length - len(data))
+        if not chunk:
+            raise EOFError("the serial port was closed")
+        data += chunk
+    return data
+
+
+def read_line(port):
+    line = b""
+    while not line.endswith(b"\n"):
+        line += read_exactly(port, 1)
+    return line.decode("ascii").strip()
+
+
+def capture(device, rate, samples, channels):
+    """Return the header values and the list of (sample, state) changes."""
+    fd = os.open(device, os.O_RDWR | os.O_NOCTTY)
+    try:
+        tty.setraw(fd)
+        termios.tcflush(fd, termios.TCIOFLUSH)
+        with os.fdopen(fd, "r+b", buffering=0) as port:
+            fd = None
+            port.write(f"CAPTURE {rate} {samples} {channels}\n".encode("ascii"))
+            header = read_line(port).split()
+            if header[0] != "LA1":
+                raise ValueError(" ".join(header))
+            timer_hz, ticks, samples, channels = (int(value) for value in header[1:])
+            changes = []
+            while True:
+                sample, state = RECORD.unpack(read_exactly(port, RECORD.size))
+                changes.append((sample, state))
+                if sample == samples:
+                    return timer_hz / ticks, samples, channels, changes
+    finally:
+        if fd is not None:
+            os.close(fd)
+
+
+def write_vcd(out, rate, channels, changes):
+    """Write the changes with a time scale of 1ns."""
+    # VCD identifiers are printable characters.
+    ids = [chr(ord("!") + channel) for channel in range(channels)]
+    out.write("$timescale 1ns $end\n")
+    out.write("$scope module logic $end\n")
+    for channel, identifier in enumerate(ids):
+        out.write(f"$var wire 1 {identifier} PA{channel} $end\n")
+    out.write("$upscope $end\n$enddefinitions $end\n")
+    previous = None
+    for sample, state in changes:
+        out.write(f"#{round(sample * 1e9 / rate)}\n")
+        for channel, identifier in enumerate(ids):
+            bit = (state >> channel) & 1
+            if previous is None or (previous >> channel) & 1 != bit:
+                out.write(f"{bit}{identifier}\n")
+        previous = state
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("device", help="serial port, e.g. /dev/ttyACM0")
+    parser.add_argument("output", help="VCD file to write")
+    parser.add_argument("--rate", type=int, default=1_000_000, help="samples per second")
+    parser.add_argument("--samples", type=int, default=8192, help="number of samples")
+    parser.add_argument("--channels", type=int, default=8, help="lines PA0.. to record")
+    args = parser.parse_args()
+
+    try:
+        rate, samples, channels, changes = capture(
+            args.device, args.rate, args.samples, args.channels
+        )
+    except ValueError as e:
+        sys.exit(f"capture failed: {e}")
+    with open(args.output, "w") as out:
+        write_vcd(out, rate, channels, changes)
+    print(f"{samples} samples at {rate:.1f}Hz, {len(changes) - 1} changes")
+
+
+if __name__ == "__main__":
+    main()
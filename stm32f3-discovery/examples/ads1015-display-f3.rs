@@ -6,6 +6,13 @@
 //!
 //! https://blog.eldruin.com/ads1x1x-analog-to-digital-converter-driver-in-rust/
 //!
+//! The ALERT/RDY pin of the ADS1015 signals the end of every conversion
+//! with a falling edge on PB1, which raises the EXTI1 interrupt. Instead of
+//! asking the device over I2C until the conversion is done, the program
+//! waits for the flag set by the interrupt and then reads the result once.
+//! If the pin is not connected, the reading falls back to asking the device
+//! after `READY_TIMEOUT_US`.
+//!
 //! This example is runs on the STM32F3 Discovery board using I2C1.
 //!
 //! ```
@@ -14,6 +21,7 @@
 //! +5V <-> +5V     <-> +5V
 //! PB7 <-> SDA     <-> SDA
 //! PB6 <-> SCL     <-> SCL
+//! PB1 <-> ALERT
 //! ```
 //!
 //! For example you can create a simple voltage divider with resistors.
@@ -67,15 +75,24 @@
 use ads1x1x::{channel as AdcChannel, Ads1x1x, FullScaleRange, SlaveAddr};
 use core::fmt::Write;
 use cortex_m_rt::entry;
+use driver_examples::exti::{self, setup_exti_pin, Edge, ExtiFlag, Port};
 use embedded_graphics::{
     fonts::{Font6x8, Text},
     pixelcolor::BinaryColor,
     prelude::*,
     style::TextStyleBuilder,
 };
-use embedded_hal::adc::OneShot;
+use embedded_hal::{
+    adc::{Channel, OneShot},
+    blocking::delay::DelayUs,
+};
 use f3::{
-    hal::{delay::Delay, i2c::I2c, prelude::*, stm32f30x},
+    hal::{
+        delay::Delay,
+        i2c::I2c,
+        prelude::*,
+        stm32f30x::{self, interrupt},
+    },
     led::Led,
 };
 use nb::block;
@@ -83,6 +100,40 @@ use nb::block;
 use panic_semihosting as _;
 use ssd1306::{prelude::*, Builder, I2CDIBuilder};
 
+/// EXTI line of the ALERT/RDY pin, PB1
+const ALERT_LINE: u8 = 1;
+/// A conversion takes less than 1ms at the default data rate.
+const READY_TIMEOUT_US: u32 = 2000;
+
+static CONVERSION_READY: ExtiFlag = ExtiFlag::new();
+
+#[interrupt]
+fn EXTI1() {
+    exti::clear_pending(ALERT_LINE);
+    CONVERSION_READY.set();
+}
+
+/// Start a conversion, wait for the ALERT/RDY pin and read the result.
+fn read_when_ready<ADC, A, CH>(adc: &mut A, channel: &mut CH, delay: &mut Delay) -> i16
+where
+    A: OneShot<ADC, i16, CH>,
+    CH: Channel<ADC>,
+{
+    CONVERSION_READY.take();
+    // The first read starts the conversion.
+    match adc.read(channel) {
+        Ok(value) => return value,
+        Err(nb::Error::WouldBlock) => (),
+        Err(nb::Error::Other(_)) => return 8091,
+    }
+    let mut waited_us = 0;
+    while !CONVERSION_READY.take() && waited_us < READY_TIMEOUT_US {
+        delay.delay_us(10_u32);
+        waited_us += 10;
+    }
+    block!(adc.read(channel)).unwrap_or(8091)
+}
+
 #[entry]
 fn main() -> ! {
     let cp = cortex_m::Peripherals::take().unwrap();
@@ -106,6 +157,12 @@ fn main() -> ! {
 
     let i2c = I2c::i2c1(dp.I2C1, (scl, sda), 100.khz(), clocks, &mut rcc.apb1);
 
+    // ALERT/RDY is an open drain output.
+    let _alert = gpiob
+        .pb1
+        .into_pull_up_input(&mut gpiob.moder, &mut gpiob.pupdr);
+    setup_exti_pin(Port::B, ALERT_LINE, Edge::Falling);
+
     let manager = shared_bus::BusManager::<cortex_m::interrupt::Mutex<_>, _>::new(i2c);
     let interface = I2CDIBuilder::new().init(manager.acquire());
     let mut disp: GraphicsMode<_> = Builder::new().connect(interface).into();
@@ -123,6 +180,7 @@ fn main() -> ! {
     // need to be able to measure [0-5V]
     adc.set_full_scale_range(FullScaleRange::Within6_144V)
         .unwrap();
+    adc.use_alert_rdy_pin_as_ready().unwrap();
 
     loop {
         // Blink LED 0 to check that everything is actually running.
@@ -133,10 +191,10 @@ fn main() -> ! {
 
         // Read voltage in all channels
         let values = [
-            block!(adc.read(&mut AdcChannel::SingleA0)).unwrap_or(8091),
-            block!(adc.read(&mut AdcChannel::SingleA1)).unwrap_or(8091),
-            block!(adc.read(&mut AdcChannel::SingleA2)).unwrap_or(8091),
-            block!(adc.read(&mut AdcChannel::SingleA3)).unwrap_or(8091),
+            read_when_ready(&mut adc, &mut AdcChannel::SingleA0, &mut delay),
+            read_when_ready(&mut adc, &mut AdcChannel::SingleA1, &mut delay),
+            read_when_ready(&mut adc, &mut AdcChannel::SingleA2, &mut delay),
+            read_when_ready(&mut adc, &mut AdcChannel::SingleA3, &mut delay),
         ];
 
         let mut lines: [heapless::String<32>; 4] = [
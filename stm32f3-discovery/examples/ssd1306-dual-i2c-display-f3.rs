@@ -0,0 +1,113 @@
+//! Drive two SSD1306 OLED displays which have the same address by connecting
+//! each of them to a different I2C bus.
+//!
+//! This is an alternative to using an I2C switch like the TCA9548A when you
+//! have two devices with the same address and a free I2C peripheral.
+//! Each display shows which bus it is connected to and a counter.
+//!
+//! This example is runs on the STM32F3 Discovery board using I2C1 and I2C2.
+//!
+//! ```
+//! F3   <-> Display 1 <-> Display 2
+//! GND  <-> GND       <-> GND
+//! +5V  <-> VDD       <-> VDD
+//! PB7  <-> SDA
+//! PB6  <-> SCL
+//! PA10               <-> SDA
+//! PA9                <-> SCL
+//! ```
+//!
+//! Run with:
+//! `cargo run --example ssd1306-dual-i2c-display-f3 --target thumbv7em-none-eabihf`,
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+use panic_semihosting as _;
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    style::TextStyleBuilder,
+};
+use f3::{
+    hal::{delay::Delay, i2c::I2c, prelude::*, stm32f30x},
+    led::Led,
+};
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+
+#[entry]
+fn main() -> ! {
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = stm32f30x::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+    let mut gpioe = dp.GPIOE.split(&mut rcc.ahb);
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut led: Led = gpioe
+        .pe9
+        .into_push_pull_output(&mut gpioe.moder, &mut gpioe.otyper)
+        .into();
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.ahb);
+    let mut gpiob = dp.GPIOB.split(&mut rcc.ahb);
+    let scl1 = gpiob.pb6.into_af4(&mut gpiob.moder, &mut gpiob.afrl);
+    let sda1 = gpiob.pb7.into_af4(&mut gpiob.moder, &mut gpiob.afrl);
+    let scl2 = gpioa.pa9.into_af4(&mut gpioa.moder, &mut gpioa.afrh);
+    let sda2 = gpioa.pa10.into_af4(&mut gpioa.moder, &mut gpioa.afrh);
+
+    let i2c1 = I2c::i2c1(dp.I2C1, (scl1, sda1), 400.khz(), clocks, &mut rcc.apb1);
+    let i2c2 = I2c::i2c2(dp.I2C2, (scl2, sda2), 400.khz(), clocks, &mut rcc.apb1);
+
+    let interface1 = I2CDIBuilder::new().init(i2c1);
+    let mut disp1: GraphicsMode<_> = Builder::new().connect(interface1).into();
+    disp1.init().unwrap();
+    disp1.flush().unwrap();
+
+    let interface2 = I2CDIBuilder::new().init(i2c2);
+    let mut disp2: GraphicsMode<_> = Builder::new().connect(interface2).into();
+    disp2.init().unwrap();
+    disp2.flush().unwrap();
+
+    let text_style = TextStyleBuilder::new(Font6x8)
+        .text_color(BinaryColor::On)
+        .build();
+
+    let mut counter: u32 = 0;
+    let mut buffer: heapless::String<32> = heapless::String::new();
+    loop {
+        // Blink LED 0 to check that everything is actually running.
+        // If the LED 0 is off, something went wrong.
+        led.on();
+        delay.delay_ms(50_u16);
+        led.off();
+        delay.delay_ms(50_u16);
+
+        buffer.clear();
+        write!(buffer, "I2C1: {}", counter).unwrap();
+        disp1.clear();
+        Text::new(&buffer, Point::zero())
+            .into_styled(text_style)
+            .draw(&mut disp1)
+            .unwrap();
+        disp1.flush().unwrap();
+
+        buffer.clear();
+        write!(buffer, "I2C2: {}", counter).unwrap();
+        disp2.clear();
+        Text::new(&buffer, Point::zero())
+            .into_styled(text_style)
+            .draw(&mut disp2)
+            .unwrap();
+        disp2.flush().unwrap();
+
+        counter = counter.wrapping_add(1);
+    }
+}
@@ -18,6 +18,9 @@
 //! Beware that the TMP102 runs on 3.3V but PB6 and PB7 run on 5V level
 //! so make sure to put a logic level shifter in between.
 //!
+//! The clocks are configured with the preset of the `clock` module, the
+//! second line of the display shows the resulting frequencies.
+//!
 //! Run with:
 //! `cargo run --example tmp102-display-f3 --target thumbv7em-none-eabihf`
 
@@ -27,6 +30,7 @@
 
 use core::fmt::Write;
 use cortex_m_rt::entry;
+use driver_examples::clock;
 use embedded_graphics::{
     fonts::{Font6x8, Text},
     pixelcolor::BinaryColor,
@@ -49,7 +53,7 @@ fn main() -> ! {
     let mut flash = dp.FLASH.constrain();
     let mut rcc = dp.RCC.constrain();
     let mut gpioe = dp.GPIOE.split(&mut rcc.ahb);
-    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
 
     let mut led: Led = gpioe
         .pe9
@@ -76,6 +80,8 @@ fn main() -> ! {
     let mut tmp102 = Tmp1x2::new(manager.acquire(), SlaveAddr::default());
 
     let mut buffer: heapless::String<64> = heapless::String::new();
+    let mut clock_line: heapless::String<32> = heapless::String::new();
+    clock::write_summary(&mut clock_line, &clocks).unwrap();
     loop {
         // Blink LED 0 to check that everything is actually running.
         // If the LED 0 is off, something went wrong.
@@ -94,6 +100,10 @@ fn main() -> ! {
             .into_styled(text_style)
             .draw(&mut disp)
             .unwrap();
+        Text::new(&clock_line, Point::new(0, 16))
+            .into_styled(text_style)
+            .draw(&mut disp)
+            .unwrap();
         disp.flush().unwrap();
     }
 }
@@ -0,0 +1,71 @@
+//! Clock configuration presets, selected with a feature.
+//!
+//! The examples which use `configure()` instead of configuring the RCC
+//! themselves run at the clock of the selected preset:
+//!
+//! - `LowPower` (`clock-low-power` feature): 8MHz from the internal
+//!   oscillator without the PLL, which draws the least current.
+//! - `Default`: 48MHz from the internal oscillator through the PLL.
+//! - `MaxPerformance` (`clock-max-performance` feature): 64MHz, the most
+//!   the PLL makes of the internal oscillator. The HAL of the `f3` crate
+//!   does not support the crystal, which would allow 72MHz.
+//!
+//! ```ignore
+//! let clocks = clock::configure(rcc.cfgr, &mut flash.acr);
+//! let mut line: String<32> = String::new();
+//! clock::write_summary(&mut line, &clocks).unwrap();
+//! ```
+
+use core::fmt;
+use f3::hal::{
+    flash::ACR,
+    prelude::*,
+    rcc::{Clocks, CFGR},
+};
+
+/// Named clock configuration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preset {
+    LowPower,
+    Default,
+    MaxPerformance,
+}
+
+/// Preset selected with the features, `clock-low-power` wins if both are
+/// enabled.
+#[cfg(feature = "clock-low-power")]
+pub const PRESET: Preset = Preset::LowPower;
+#[cfg(all(feature = "clock-max-performance", not(feature = "clock-low-power")))]
+pub const PRESET: Preset = Preset::MaxPerformance;
+#[cfg(not(any(feature = "clock-low-power", feature = "clock-max-performance")))]
+pub const PRESET: Preset = Preset::Default;
+
+/// Configure and freeze the clocks with the selected preset.
+pub fn configure(cfgr: CFGR, acr: &mut ACR) -> Clocks {
+    configure_preset(PRESET, cfgr, acr)
+}
+
+/// Configure and freeze the clocks with the given preset.
+pub fn configure_preset(preset: Preset, cfgr: CFGR, acr: &mut ACR) -> Clocks {
+    match preset {
+        Preset::LowPower => cfgr.sysclk(8.mhz()).freeze(acr),
+        Preset::Default => cfgr.sysclk(48.mhz()).pclk1(24.mhz()).freeze(acr),
+        Preset::MaxPerformance => cfgr
+            .sysclk(64.mhz())
+            .pclk1(32.mhz())
+            .pclk2(64.mhz())
+            .freeze(acr),
+    }
+}
+
+/// Write the core and bus frequencies in MHz in one short line, e.g. for a
+/// display: `CPU 64 APB 32/64MHz`.
+pub fn write_summary<W: fmt::Write>(writer: &mut W, clocks: &Clocks) -> fmt::Result {
+    write!(
+        writer,
+        "CPU {} APB {}/{}MHz",
+        clocks.sysclk().0 / 1_000_000,
+        clocks.pclk1().0 / 1_000_000,
+        clocks.pclk2().0 / 1_000_000
+    )
+}
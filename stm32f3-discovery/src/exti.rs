@@ -0,0 +1,137 @@
+//! Pins as external interrupt (EXTI) inputs, e.g. for the data-ready or
+//! alert output of a sensor.
+//!
+//! The HAL of the `f3` crate does not support external interrupts, so
+//! `setup_exti_pin()` configures the SYSCFG and EXTI registers directly and
+//! unmasks the interrupt in the NVIC. Configure the pin as an input first.
+//! The interrupt handler clears the pending bit of the line and sets an
+//! `ExtiFlag`, which the program polls:
+//!
+//! ```ignore
+//! static DATA_READY: ExtiFlag = ExtiFlag::new();
+//!
+//! #[interrupt]
+//! fn EXTI1() {
+//!     exti::clear_pending(1);
+//!     DATA_READY.set();
+//! }
+//!
+//! let _ready = gpiob.pb1.into_pull_up_input(&mut gpiob.moder, &mut gpiob.pupdr);
+//! setup_exti_pin(Port::B, 1, Edge::Falling);
+//! if DATA_READY.take() {
+//!     let value = sensor.read().unwrap();
+//! }
+//! ```
+//!
+//! Pins with the same number on different ports share one EXTI line, so
+//! only one of them can be an interrupt input.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::peripheral::NVIC;
+use f3::hal::stm32f30x::{Interrupt, EXTI, RCC, SYSCFG};
+
+/// GPIO port of a pin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Port {
+    A = 0,
+    B = 1,
+    C = 2,
+    D = 3,
+    E = 4,
+    F = 5,
+}
+
+/// Edge of the input which triggers the interrupt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    RisingFalling,
+}
+
+/// Enable the interrupt of pin `pin` (0-15) of `port` on the given edge.
+pub fn setup_exti_pin(port: Port, pin: u8, edge: Edge) {
+    assert!(pin < 16);
+    let mask = 1 << pin;
+    let shift = u32::from(pin % 4) * 4;
+    let port_bits = (port as u32) << shift;
+    // The peripherals are shared with the HAL, which does not use them.
+    unsafe {
+        let rcc = &*RCC::ptr();
+        rcc.apb2enr.modify(|_, w| w.syscfgen().set_bit());
+        let syscfg = &*SYSCFG::ptr();
+        let select = |r: u32| r & !(0xf << shift) | port_bits;
+        match pin / 4 {
+            0 => syscfg.exticr1.modify(|r, w| w.bits(select(r.bits()))),
+            1 => syscfg.exticr2.modify(|r, w| w.bits(select(r.bits()))),
+            2 => syscfg.exticr3.modify(|r, w| w.bits(select(r.bits()))),
+            _ => syscfg.exticr4.modify(|r, w| w.bits(select(r.bits()))),
+        }
+        let exti = &*EXTI::ptr();
+        let rising = edge != Edge::Falling;
+        let falling = edge != Edge::Rising;
+        exti.rtsr1.modify(|r, w| {
+            w.bits(if rising {
+                r.bits() | mask
+            } else {
+                r.bits() & !mask
+            })
+        });
+        exti.ftsr1.modify(|r, w| {
+            w.bits(if falling {
+                r.bits() | mask
+            } else {
+                r.bits() & !mask
+            })
+        });
+        exti.pr1.write(|w| w.bits(mask));
+        exti.imr1.modify(|r, w| w.bits(r.bits() | mask));
+        NVIC::unmask(interrupt(pin));
+    }
+}
+
+/// Clear the pending bit of EXTI line `line`. Call this from the interrupt
+/// handler, otherwise it runs again and again.
+pub fn clear_pending(line: u8) {
+    // Writing 1 only clears the bit of this line.
+    unsafe { (*EXTI::ptr()).pr1.write(|w| w.bits(1 << line)) };
+}
+
+/// Interrupt of an EXTI line
+fn interrupt(line: u8) -> Interrupt {
+    match line {
+        0 => Interrupt::EXTI0,
+        1 => Interrupt::EXTI1,
+        2 => Interrupt::EXTI2_TS,
+        3 => Interrupt::EXTI3,
+        4 => Interrupt::EXTI4,
+        5..=9 => Interrupt::EXTI9_5,
+        _ => Interrupt::EXTI15_10,
+    }
+}
+
+/// Event flag set by an interrupt handler
+#[derive(Debug, Default)]
+pub struct ExtiFlag(AtomicBool);
+
+impl ExtiFlag {
+    /// Create a flag which is not set.
+    pub const fn new() -> Self {
+        ExtiFlag(AtomicBool::new(false))
+    }
+
+    /// Set the flag. Call this from the interrupt handler.
+    pub fn set(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Whether the flag is set, without clearing it
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Clear the flag and return whether it was set.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+}
@@ -1,3 +1,6 @@
-//! Empty. Please have a look at the examples.
+//! Helpers shared by some of the examples. Please have a look at the examples.
 //!
 #![no_std]
+
+pub mod clock;
+pub mod exti;